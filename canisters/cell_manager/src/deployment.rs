@@ -0,0 +1,408 @@
+//! Canister provisioning for Data Cells and their registration with the aggregator
+
+use candid::{CandidType, Principal};
+use candid::Nat;
+use ic_cdk::api::management_canister::main::{
+    canister_status, create_canister, delete_canister, deposit_cycles, install_code, stop_canister, update_settings,
+    CanisterIdRecord, CanisterInstallMode, CanisterSettings, CreateCanisterArgument, InstallCodeArgument,
+    UpdateSettingsArgument,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::state::State;
+use crate::types::*;
+
+/// Cycles given to each new cell when `CellConfig.cycles_limit` is unset
+pub const DEFAULT_CELL_CYCLES: u128 = 2_000_000_000_000;
+
+pub struct Deployment;
+
+impl Deployment {
+    /// Create an empty canister controlled by this manager
+    pub async fn create_canister(cycles: u128) -> Result<Principal, CellError> {
+        let settings = CanisterSettings {
+            controllers: Some(vec![ic_cdk::id()]),
+            ..Default::default()
+        };
+
+        let (record,) = create_canister(CreateCanisterArgument { settings: Some(settings) }, cycles)
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("create_canister failed: {:?} - {}", code, msg)
+            ))?;
+
+        Ok(record.canister_id)
+    }
+
+    /// Install the Data Cell wasm into a freshly created canister
+    pub async fn install_cell(canister_id: Principal, init: &DataCellInitConfig) -> Result<(), CellError> {
+        let wasm_module = State::cell_wasm()
+            .ok_or_else(|| CellError::DeploymentFailed("No data cell wasm uploaded".to_string()))?;
+        let arg = candid::encode_one(init)
+            .map_err(|e| CellError::DeploymentFailed(format!("Failed to encode init config: {}", e)))?;
+
+        install_code(InstallCodeArgument {
+            mode: CanisterInstallMode::Install,
+            canister_id,
+            wasm_module,
+            arg,
+        })
+        .await
+        .map_err(|(code, msg)| CellError::DeploymentFailed(
+            format!("install_code on {} failed: {:?} - {}", canister_id, code, msg)
+        ))
+    }
+
+    /// Stop and delete canisters, best effort; used to roll back partial deployments
+    pub async fn delete_canisters(canister_ids: &[Principal]) {
+        for canister_id in canister_ids {
+            if let Err(error) = Self::delete_cell_canister(*canister_id).await {
+                ic_cdk::println!("Failed to clean up canister {}: {:?}", canister_id, error);
+            }
+        }
+    }
+
+    /// Stop and delete a single cell canister
+    pub async fn delete_cell_canister(canister_id: Principal) -> Result<(), CellError> {
+        stop_canister(CanisterIdRecord { canister_id })
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("stop_canister on {} failed: {:?} - {}", canister_id, code, msg)
+            ))?;
+
+        delete_canister(CanisterIdRecord { canister_id })
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("delete_canister on {} failed: {:?} - {}", canister_id, code, msg)
+            ))
+    }
+
+    /// Current memory footprint, allocations and cycle balance of a cell canister
+    pub async fn resource_usage(canister_id: Principal) -> Result<ResourceUsage, CellError> {
+        let (status,) = canister_status(CanisterIdRecord { canister_id })
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("canister_status on {} failed: {:?} - {}", canister_id, code, msg)
+            ))?;
+
+        Ok(ResourceUsage {
+            memory_size: nat_to_u64(&status.memory_size),
+            cycles: nat_to_u128(&status.cycles),
+            idle_cycles_burned_per_day: nat_to_u64(&status.idle_cycles_burned_per_day),
+            compute_allocation: nat_to_u64(&status.settings.compute_allocation),
+            memory_allocation: nat_to_u64(&status.settings.memory_allocation),
+        })
+    }
+
+    /// Reserve compute (percent) and memory (bytes) allocation for a cell canister
+    pub async fn set_allocation(canister_id: Principal, compute_allocation: u64, memory_allocation: u64) -> Result<(), CellError> {
+        let settings = CanisterSettings {
+            compute_allocation: Some(Nat::from(compute_allocation)),
+            memory_allocation: Some(Nat::from(memory_allocation)),
+            ..Default::default()
+        };
+
+        update_settings(UpdateSettingsArgument { canister_id, settings })
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("update_settings on {} failed: {:?} - {}", canister_id, code, msg)
+            ))
+    }
+
+    /// Send cycles from the manager's balance to a cell canister
+    pub async fn top_up(canister_id: Principal, cycles: u128) -> Result<(), CellError> {
+        if ic_cdk::api::canister_balance128() < cycles {
+            return Err(CellError::InsufficientCycles);
+        }
+
+        deposit_cycles(CanisterIdRecord { canister_id }, cycles)
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("deposit_cycles to {} failed: {:?} - {}", canister_id, code, msg)
+            ))
+    }
+
+    /// Register a cell with the configured query aggregator, if any
+    pub async fn register_with_aggregator(
+        cell_id: Principal,
+        name: &str,
+        schema_version: u32,
+        replica_of: Option<Principal>,
+    ) -> Result<(), CellError> {
+        let aggregator = match State::settings().aggregator {
+            Some(aggregator) => aggregator,
+            None => return Ok(()),
+        };
+
+        let registration = AggregatorCellRegistration {
+            cell_id,
+            name: name.to_string(),
+            schema_version,
+            capabilities: Vec::new(),
+            performance_hints: AggregatorPerformanceHints {
+                typical_response_time_ms: 0,
+                max_concurrent_queries: 0,
+                preferred_batch_size: 0,
+                subnet_location: None,
+            },
+            replica_of,
+        };
+
+        let (result,): (Result<(), candid::Reserved>,) = ic_cdk::call(aggregator, "register_cell", (registration,))
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("register_cell failed: {:?} - {}", code, msg)
+            ))?;
+
+        result.map_err(|_| CellError::DeploymentFailed(
+            format!("Aggregator rejected registration of {}", cell_id)
+        ))
+    }
+
+    /// Remove a cell, and its replicas, from the configured query aggregator, if any
+    pub async fn unregister_from_aggregator(cell_id: Principal) -> Result<(), CellError> {
+        let aggregator = match State::settings().aggregator {
+            Some(aggregator) => aggregator,
+            None => return Ok(()),
+        };
+
+        let (result,): (Result<(), candid::Reserved>,) = ic_cdk::call(aggregator, "unregister_cell", (cell_id,))
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("unregister_cell failed: {:?} - {}", code, msg)
+            ))?;
+
+        result.map_err(|_| CellError::DeploymentFailed(
+            format!("Aggregator rejected unregistration of {}", cell_id)
+        ))
+    }
+}
+
+/// Resource snapshot of a cell canister from `canister_status`
+#[derive(Clone, Debug)]
+pub struct ResourceUsage {
+    pub memory_size: u64,
+    pub cycles: u128,
+    pub idle_cycles_burned_per_day: u64,
+    /// Reserved compute, in percent
+    pub compute_allocation: u64,
+    /// Reserved memory in bytes; 0 means best effort
+    pub memory_allocation: u64,
+}
+
+fn nat_to_u64(n: &Nat) -> u64 {
+    u64::try_from(n.0.clone()).unwrap_or(u64::MAX)
+}
+
+fn nat_to_u128(n: &Nat) -> u128 {
+    u128::try_from(n.0.clone()).unwrap_or(u128::MAX)
+}
+
+impl DataCellInitConfig {
+    /// Translate a manager cell configuration into the Data Cell init argument
+    pub fn from_config(config: &CellConfig, permissions: PermissionConfig, replica_of: Option<Principal>) -> Self {
+        DataCellInitConfig {
+            name: config.name.clone(),
+            schema: DataCellSchema::from_schema(&config.name, &config.schema),
+            permissions,
+            replica_of,
+            role_authority: if config.delegate_roles.unwrap_or(false) { Some(ic_cdk::id()) } else { None },
+        }
+    }
+}
+
+impl DataCellSchema {
+    /// Translate the manager's schema description into a Data Cell schema
+    ///
+    /// Type bounds become validation rules, `Required` constraints mark fields
+    /// required, and `Index`/`Unique` constraints and index names become
+    /// single-field indexes.
+    pub fn from_schema(name: &str, schema: &SchemaDefinition) -> Self {
+        let required: Vec<&str> = schema.constraints.iter()
+            .filter_map(|constraint| match constraint {
+                SchemaConstraint::Required(field) => Some(field.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let fields = schema.fields.iter()
+            .map(|(field_name, field_type)| {
+                let mut definition = DataCellField::from_type(field_type);
+                definition.required = required.contains(&field_name.as_str());
+                (field_name.clone(), definition)
+            })
+            .collect();
+
+        let mut indexes: Vec<DataCellIndex> = schema.indexes.iter()
+            .map(|field| DataCellIndex { name: field.clone(), fields: vec![field.clone()], unique: false })
+            .collect();
+        let mut constraints = Vec::new();
+
+        for constraint in &schema.constraints {
+            match constraint {
+                SchemaConstraint::Index(field) | SchemaConstraint::Unique(field) => {
+                    let unique = matches!(constraint, SchemaConstraint::Unique(_));
+                    match indexes.iter_mut().find(|index| index.name == *field) {
+                        Some(index) => index.unique |= unique,
+                        None => indexes.push(DataCellIndex { name: field.clone(), fields: vec![field.clone()], unique }),
+                    }
+                    if unique {
+                        constraints.push(DataCellConstraint::Unique(vec![field.clone()]));
+                    }
+                },
+                SchemaConstraint::ForeignKey { field, references } => {
+                    constraints.push(DataCellConstraint::ForeignKey {
+                        fields: vec![field.clone()],
+                        references: references.clone(),
+                    });
+                },
+                SchemaConstraint::Required(_) => {},
+            }
+        }
+
+        DataCellSchema {
+            version: schema.version,
+            name: name.to_string(),
+            fields,
+            indexes,
+            constraints,
+        }
+    }
+}
+
+impl DataCellField {
+    fn from_type(field_type: &FieldType) -> Self {
+        let mut validation_rules = Vec::new();
+
+        let field_type = match field_type {
+            FieldType::Text { max_length } => {
+                if let Some(max_length) = max_length {
+                    validation_rules.push(DataCellValidationRule::MaxLength(*max_length));
+                }
+                DataCellFieldType::Text
+            },
+            FieldType::Number { min, max } => {
+                if min.is_some() || max.is_some() {
+                    validation_rules.push(DataCellValidationRule::Range(
+                        min.unwrap_or(i64::MIN),
+                        max.unwrap_or(i64::MAX),
+                    ));
+                }
+                DataCellFieldType::Number
+            },
+            FieldType::Boolean => DataCellFieldType::Boolean,
+            FieldType::Principal => DataCellFieldType::Principal,
+            FieldType::Timestamp => DataCellFieldType::Timestamp,
+            FieldType::Blob { .. } => DataCellFieldType::Blob,
+            FieldType::Array { element_type, .. } => {
+                DataCellFieldType::Array(Box::new(Self::from_type(element_type).field_type))
+            },
+            FieldType::Object { fields } => DataCellFieldType::Object(
+                fields.iter()
+                    .map(|(name, field_type)| (name.clone(), Self::from_type(field_type)))
+                    .collect()
+            ),
+        };
+
+        DataCellField {
+            field_type,
+            required: false,
+            default_value: None,
+            validation_rules,
+        }
+    }
+}
+
+/// Data Cell `CellInitConfig`; optional settings are left at their defaults
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DataCellInitConfig {
+    pub name: String,
+    pub schema: DataCellSchema,
+    pub permissions: PermissionConfig,
+    pub replica_of: Option<Principal>,
+    pub role_authority: Option<Principal>,
+}
+
+/// Data Cell `SchemaDefinition`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DataCellSchema {
+    pub version: u32,
+    pub name: String,
+    pub fields: HashMap<String, DataCellField>,
+    pub indexes: Vec<DataCellIndex>,
+    pub constraints: Vec<DataCellConstraint>,
+}
+
+/// Data Cell `FieldDefinition`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DataCellField {
+    pub field_type: DataCellFieldType,
+    pub required: bool,
+    pub default_value: Option<String>,
+    pub validation_rules: Vec<DataCellValidationRule>,
+}
+
+/// Data Cell `FieldType`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DataCellFieldType {
+    Text,
+    Number,
+    Boolean,
+    Timestamp,
+    Principal,
+    Blob,
+    Array(Box<DataCellFieldType>),
+    Object(HashMap<String, DataCellField>),
+}
+
+/// Data Cell `ValidationRule` (the subset the manager schema can express)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DataCellValidationRule {
+    MaxLength(u32),
+    Range(i64, i64),
+}
+
+/// Data Cell `IndexDefinition`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DataCellIndex {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub unique: bool,
+}
+
+/// Data Cell `ConstraintDefinition`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DataCellConstraint {
+    Unique(Vec<String>),
+    ForeignKey { fields: Vec<String>, references: String },
+}
+
+/// Query Aggregator `CellRegistration`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct AggregatorCellRegistration {
+    cell_id: Principal,
+    name: String,
+    schema_version: u32,
+    capabilities: Vec<AggregatorCellCapability>,
+    performance_hints: AggregatorPerformanceHints,
+    replica_of: Option<Principal>,
+}
+
+/// Query Aggregator `CellCapability`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+enum AggregatorCellCapability {
+    FullTextSearch,
+    GeospatialQueries,
+    AdvancedIndexing,
+    StreamingSupport,
+    BatchOperations,
+}
+
+/// Query Aggregator `PerformanceHints`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct AggregatorPerformanceHints {
+    typical_response_time_ms: u32,
+    max_concurrent_queries: u32,
+    preferred_batch_size: u32,
+    subnet_location: Option<String>,
+}
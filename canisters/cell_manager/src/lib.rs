@@ -11,66 +11,382 @@ use std::collections::HashMap;
 
 mod state;
 mod types;
+mod deployment;
+mod scaling;
+mod migration;
 
 use state::State;
 use types::*;
+use deployment::*;
+use scaling::Scaling;
+use migration::Migration;
 
-/// Initialize the Cell Manager with default configuration
+/// Initialize the Cell Manager; the installing principal is always an admin
 #[init]
-fn init() {
+fn init(config: Option<ManagerConfig>) {
     ic_cdk::println!("CellDB Cell Manager initializing...");
-    State::init();
+    State::init(caller(), config.unwrap_or_default());
+    Scaling::schedule_auto_scale();
+}
+
+/// Upload the Data Cell wasm module installed into new cells (controllers only)
+///
+/// The module runs in every cell created afterwards, so it can't be left to
+/// the configured admin list.
+#[update]
+fn set_cell_wasm(wasm: Vec<u8>) -> Result<(), CellError> {
+    if !api::is_controller(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    State::set_cell_wasm(wasm);
+    Ok(())
 }
 
 /// Create a new Data Cell with specified schema and configuration
+///
+/// With `replicas` set, that many read replicas are deployed alongside the
+/// primary, granted read access to it and subscribed to its change feed.
+/// Every cell is registered with the aggregator. If any canister fails to
+/// deploy, all canisters created by the call are deleted again.
 #[update]
 async fn create_cell(config: CellConfig) -> Result<CellInfo, CellError> {
     ic_cdk::println!("Creating new Data Cell: {}", config.name);
 
-    // TODO: Implement cell creation logic
-    // - Validate schema configuration
-    // - Deploy new canister instance
-    // - Register cell in manager state
-    // - Return cell information
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    provision_cell(config).await
+}
+
+/// Deploy, register and record the canisters of a new cell
+async fn provision_cell(config: CellConfig) -> Result<CellInfo, CellError> {
+    if config.name.trim().is_empty() {
+        return Err(CellError::InvalidSchema("Cell name must not be empty".to_string()));
+    }
+
+    if State::cell_wasm().is_none() {
+        return Err(CellError::DeploymentFailed("No data cell wasm uploaded".to_string()));
+    }
+
+    let cycles = config.cycles_limit.map_or(DEFAULT_CELL_CYCLES, u128::from);
+    let replica_count = config.replicas.unwrap_or(0) as usize;
+
+    let mut created = Vec::with_capacity(replica_count + 1);
+    for _ in 0..=replica_count {
+        match Deployment::create_canister(cycles).await {
+            Ok(canister_id) => created.push(canister_id),
+            Err(error) => {
+                Deployment::delete_canisters(&created).await;
+                return Err(error);
+            },
+        }
+    }
+
+    let primary = created[0];
+    let replicas = created[1..].to_vec();
+
+    if let Err(error) = deploy_cells(&config, primary, &replicas).await {
+        Deployment::delete_canisters(&created).await;
+        return Err(error);
+    }
+
+    let mut status = CellStatus::Active;
+    let registrations = std::iter::once((primary, None))
+        .chain(replicas.iter().map(|replica| (*replica, Some(primary))));
+    for (cell_id, replica_of) in registrations {
+        if let Err(error) = Deployment::register_with_aggregator(cell_id, &config.name, config.schema.version, replica_of).await {
+            ic_cdk::println!("Failed to register cell {} with aggregator: {:?}", cell_id, error);
+            status = CellStatus::Error(format!("Aggregator registration failed for {}", cell_id));
+        }
+    }
+
+    let now = api::time();
+    let cell_info = CellInfo {
+        id: primary,
+        name: config.name.clone(),
+        schema: config.schema.clone(),
+        status,
+        created_at: now,
+        updated_at: now,
+        metrics: CellMetrics {
+            memory_usage: 0,
+            cycle_consumption: 0,
+            operation_count: 0,
+            last_updated: now,
+        },
+        replicas,
+        memory_limit: config.memory_limit,
+        scaling_config: config.scaling_config.clone(),
+        permissions: Some(config.permissions.clone()),
+        delegate_roles: config.delegate_roles,
+    };
+
+    State::register_cell(primary, cell_info.clone());
+    Ok(cell_info)
+}
+
+/// Install the primary, with read access for its replicas, and then each replica
+///
+/// The manager is made an admin of every cell so it can move records on deletion.
+async fn deploy_cells(config: &CellConfig, primary: Principal, replicas: &[Principal]) -> Result<(), CellError> {
+    let mut permissions = config.permissions.clone();
+    if !permissions.admin.contains(&id()) {
+        permissions.admin.push(id());
+    }
+
+    let mut primary_permissions = permissions.clone();
+    primary_permissions.read.extend(replicas.iter().map(|replica| AccessLevel::Principal(*replica)));
+
+    Deployment::install_cell(primary, &DataCellInitConfig::from_config(config, primary_permissions, None)).await?;
+
+    for replica in replicas {
+        let init = DataCellInitConfig::from_config(config, permissions.clone(), Some(primary));
+        Deployment::install_cell(*replica, &init).await?;
+    }
+
+    Ok(())
+}
+
+/// Create a new cell with the schema and permissions of `source`
+///
+/// With `copy_data`, the source's records are copied into the clone in
+/// chunks. Writes made to the source during the copy may or may not be
+/// included. If a chunk fails the clone is left in the `Error` state and
+/// `resume_clone` continues from the last copied chunk.
+#[update]
+async fn clone_cell(source: Principal, new_name: String, copy_data: bool) -> Result<CellInfo, CellError> {
+    ic_cdk::println!("Cloning cell {} as {}", source, new_name);
+
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let source_info = State::get_cell(&source)
+        .ok_or_else(|| CellError::NotFound(source.to_string()))?;
+    let permissions = source_info.permissions.clone().ok_or_else(|| CellError::DeploymentFailed(
+        format!("Permissions of cell {} are unknown; it predates cloning support", source)
+    ))?;
+
+    let config = CellConfig {
+        name: new_name,
+        schema: source_info.schema.clone(),
+        memory_limit: source_info.memory_limit,
+        cycles_limit: None,
+        permissions,
+        scaling_config: source_info.scaling_config.clone(),
+        replicas: None,
+        delegate_roles: source_info.delegate_roles,
+    };
+    let clone_info = provision_cell(config).await?;
+
+    if !copy_data {
+        return Ok(clone_info);
+    }
+
+    let now = api::time();
+    let job = CloneJob {
+        source,
+        target: clone_info.id,
+        cursor: None,
+        records_copied: 0,
+        started_at: now,
+        updated_at: now,
+        completed: false,
+        last_error: None,
+    };
+    State::save_clone_job(job.clone());
+
+    copy_into_clone(job).await
+}
+
+/// Continue copying records into a clone whose copy failed part way
+#[update]
+async fn resume_clone(target: Principal) -> Result<CellInfo, CellError> {
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let job = State::clone_job(&target)
+        .ok_or_else(|| CellError::NotFound(format!("No clone job for {}", target)))?;
+
+    copy_into_clone(job).await
+}
+
+/// Run a clone job and record its outcome in the clone's status
+async fn copy_into_clone(job: CloneJob) -> Result<CellInfo, CellError> {
+    let target = job.target;
+    let result = Migration::run_clone_job(job).await;
+
+    let mut cell_info = State::get_cell(&target)
+        .ok_or_else(|| CellError::NotFound(target.to_string()))?;
+    cell_info.updated_at = api::time();
 
-    Err(CellError::NotImplemented("Cell creation pending implementation".to_string()))
+    match result {
+        Ok(job) => {
+            ic_cdk::println!("Copied {} records from {} into {}", job.records_copied, job.source, target);
+            if matches!(cell_info.status, CellStatus::Error(_)) {
+                cell_info.status = CellStatus::Active;
+            }
+            State::register_cell(target, cell_info.clone());
+            Ok(cell_info)
+        },
+        Err(error) => {
+            cell_info.status = CellStatus::Error(format!("Record copy incomplete: {:?}", error));
+            State::register_cell(target, cell_info);
+            Err(error)
+        },
+    }
+}
+
+/// Progress of the record copy into a cloned cell
+#[query]
+fn clone_job(target: Principal) -> Option<CloneJob> {
+    State::clone_job(&target)
 }
 
 /// List all managed Data Cells
 #[query]
 fn list_cells() -> Vec<CellInfo> {
-    ic_cdk::println!("Listing all managed cells");
-
-    // TODO: Implement cell listing
-    // - Retrieve from stable storage
-    // - Return cell metadata
-
-    Vec::new()
+    State::list_all_cells().into_iter()
+        .map(|(_, cell_info)| cell_info)
+        .collect()
 }
 
 /// Get detailed information about a specific Data Cell
 #[query]
 fn get_cell_info(cell_id: Principal) -> Option<CellInfo> {
-    ic_cdk::println!("Getting info for cell: {}", cell_id);
+    State::get_cell(&cell_id)
+}
+
+/// Roles granted to a principal, consulted by cells created with `delegate_roles`
+#[query]
+fn roles_of(principal: Principal) -> Vec<String> {
+    State::roles_of(&principal)
+}
+
+/// Grant a role across every cell delegating role resolution (admin only)
+#[update]
+fn grant_role(principal: Principal, role: String) -> Result<(), CellError> {
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    State::grant_role(principal, role);
+    Ok(())
+}
+
+/// Revoke a role granted with `grant_role` (admin only)
+///
+/// Cells may keep honouring it until their cached copy of the caller's roles expires.
+#[update]
+fn revoke_role(principal: Principal, role: String) -> Result<(), CellError> {
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if !State::revoke_role(principal, &role) {
+        return Err(CellError::NotFound(format!("{} does not have role {}", principal, role)));
+    }
+    Ok(())
+}
+
+/// Decommission a Data Cell, deleting its canister and those of its replicas
+///
+/// A cell holding records is only deleted when `migrate_to` names a cell to
+/// copy them into first, or when `force` is set. The cell is unregistered
+/// from the aggregator once its canisters are gone.
+#[update]
+async fn delete_cell(cell_id: Principal, migrate_to: Option<Principal>, force: Option<bool>) -> Result<(), CellError> {
+    ic_cdk::println!("Deleting cell: {}", cell_id);
+
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let mut cell_info = State::get_cell(&cell_id)
+        .ok_or_else(|| CellError::NotFound(cell_id.to_string()))?;
+
+    if migrate_to == Some(cell_id) || migrate_to.map_or(false, |target| cell_info.replicas.contains(&target)) {
+        return Err(CellError::DeploymentFailed("Cannot migrate records into the cell being deleted".to_string()));
+    }
+
+    match migrate_to {
+        Some(target) => {
+            let copied = Migration::copy_records(cell_id, target).await?;
+            ic_cdk::println!("Migrated {} records from {} to {}", copied, cell_id, target);
+        },
+        None if !force.unwrap_or(false) => {
+            if Migration::has_records(cell_id).await? {
+                return Err(CellError::CellNotEmpty);
+            }
+        },
+        None => {},
+    }
+
+    cell_info.status = CellStatus::Maintenance;
+    cell_info.updated_at = api::time();
+    State::register_cell(cell_id, cell_info.clone());
+
+    // Replicas go first so none is left following a deleted primary
+    let canister_ids: Vec<Principal> = cell_info.replicas.iter().copied().chain(std::iter::once(cell_id)).collect();
+    for canister_id in canister_ids {
+        if let Err(error) = Deployment::delete_cell_canister(canister_id).await {
+            cell_info.status = CellStatus::Error(format!("Deletion failed at {}: {:?}", canister_id, error));
+            cell_info.updated_at = api::time();
+            State::register_cell(cell_id, cell_info);
+            return Err(error);
+        }
+        cell_info.replicas.retain(|replica| *replica != canister_id);
+    }
 
-    // TODO: Implement cell info retrieval
-    // - Lookup cell by ID
-    // - Return detailed metadata
+    State::remove_cell(&cell_id);
 
-    None
+    if let Err(error) = Deployment::unregister_from_aggregator(cell_id).await {
+        ic_cdk::println!("Failed to unregister cell {} from aggregator: {:?}", cell_id, error);
+    }
+
+    Ok(())
 }
 
 /// Scale a Data Cell by splitting or replicating
+///
+/// `Vertical` scaling raises the cell's compute and memory allocation and
+/// tops up its cycles, returning its unchanged principal.
 #[update]
 async fn scale_cell(cell_id: Principal, scaling_config: ScalingConfig) -> Result<Vec<Principal>, CellError> {
     ic_cdk::println!("Scaling cell: {} with config: {:?}", cell_id, scaling_config);
 
-    // TODO: Implement cell scaling
-    // - Analyze current cell load
-    // - Create additional cell instances
-    // - Redistribute data if needed
+    if !State::is_admin(&caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    Scaling::scale(cell_id, &scaling_config).await
+}
 
-    Err(CellError::NotImplemented("Cell scaling pending implementation".to_string()))
+/// Installed wasm build, for deployment tooling
+#[query]
+fn version() -> CanisterVersion {
+    CanisterVersion {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        build_version: build_version(),
+        schema_version: None,
+    }
+}
+
+/// Crate version, with the commit baked in when built with `CELLDB_GIT_COMMIT` set
+fn build_version() -> String {
+    match option_env!("CELLDB_GIT_COMMIT") {
+        Some(commit) => format!("{}+{}", env!("CARGO_PKG_VERSION"), commit),
+        None => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// Automatic scaling attempts, oldest first
+#[query]
+fn auto_scale_events() -> Vec<AutoScaleEvent> {
+    State::auto_scale_events()
 }
 
 /// Pre-upgrade hook to preserve state
@@ -83,6 +399,7 @@ fn pre_upgrade() {
 #[post_upgrade]
 fn post_upgrade() {
     State::post_upgrade();
+    Scaling::schedule_auto_scale();
 }
 
 // Export Candid interface
@@ -0,0 +1,167 @@
+//! Copying records between Data Cells through their export/import endpoints
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use crate::state::State;
+use crate::types::{CellError, CloneJob};
+
+/// Records moved per export/import round trip
+pub const MIGRATION_CHUNK_SIZE: u32 = 500;
+
+thread_local! {
+    /// Clone targets with a copy currently running
+    static COPYING: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
+}
+
+pub struct Migration;
+
+impl Migration {
+    /// Check whether a cell holds any (unexpired) records
+    pub async fn has_records(cell_id: Principal) -> Result<bool, CellError> {
+        let chunk = Self::export_chunk(cell_id, None, 1).await?;
+        Ok(!chunk.records.is_empty() || chunk.next_cursor.is_some())
+    }
+
+    /// Copy every record of `source` into `target`, keeping record IDs
+    ///
+    /// Records are validated against the target's schema; any rejection stops
+    /// the copy with an error.
+    pub async fn copy_records(source: Principal, target: Principal) -> Result<u64, CellError> {
+        let mut cursor = None;
+        let mut copied = 0;
+
+        loop {
+            let (imported, next_cursor) = Self::copy_chunk(source, target, cursor).await?;
+            copied += imported;
+
+            match next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(copied),
+            }
+        }
+    }
+
+    /// Run a clone copy job from its saved cursor until done or a chunk fails
+    ///
+    /// The cursor is saved after every chunk, so a failed job resumes where it
+    /// stopped instead of copying everything again.
+    pub async fn run_clone_job(mut job: CloneJob) -> Result<CloneJob, CellError> {
+        let started = COPYING.with(|copying| copying.borrow_mut().insert(job.target));
+        if !started {
+            return Err(CellError::DeploymentFailed(format!("A copy into {} is already running", job.target)));
+        }
+
+        let result = loop {
+            if job.completed {
+                break Ok(());
+            }
+
+            match Self::copy_chunk(job.source, job.target, job.cursor.clone()).await {
+                Ok((imported, next_cursor)) => {
+                    job.records_copied += imported;
+                    job.completed = next_cursor.is_none();
+                    job.cursor = next_cursor;
+                    job.last_error = None;
+                },
+                Err(error) => {
+                    job.last_error = Some(format!("{:?}", error));
+                    break Err(error);
+                },
+            }
+
+            job.updated_at = ic_cdk::api::time();
+            State::save_clone_job(job.clone());
+        };
+
+        job.updated_at = ic_cdk::api::time();
+        State::save_clone_job(job.clone());
+        COPYING.with(|copying| copying.borrow_mut().remove(&job.target));
+        result.map(|_| job)
+    }
+
+    /// Copy one chunk of records after `cursor`, returning the number imported and the next cursor
+    async fn copy_chunk(source: Principal, target: Principal, cursor: Option<String>) -> Result<(u64, Option<String>), CellError> {
+        let chunk = Self::export_chunk(source, cursor, MIGRATION_CHUNK_SIZE).await?;
+        let imported = Self::import_chunk(target, chunk.records, chunk.expirations).await?;
+        Ok((imported, chunk.next_cursor))
+    }
+
+    async fn export_chunk(cell_id: Principal, cursor: Option<String>, chunk_size: u32) -> Result<CellExportChunk, CellError> {
+        let (chunk,): (CellExportChunk,) = ic_cdk::call(cell_id, "export_chunk", (cursor, chunk_size))
+            .await
+            .map_err(|(code, msg)| CellError::DeploymentFailed(
+                format!("export_chunk on {} failed: {:?} - {}", cell_id, code, msg)
+            ))?;
+        Ok(chunk)
+    }
+
+    /// Import records into a cell with their expiry times, returning how many were imported
+    async fn import_chunk(cell_id: Principal, records: Vec<(String, String)>, expirations: Vec<(String, u64)>) -> Result<u64, CellError> {
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let (result,): (Result<CellImportReport, DataCellError>,) =
+            ic_cdk::call(cell_id, "import_chunk", (records, true, Some(expirations)))
+                .await
+                .map_err(|(code, msg)| CellError::DeploymentFailed(
+                    format!("import_chunk on {} failed: {:?} - {}", cell_id, code, msg)
+                ))?;
+
+        let report = result.map_err(|error| error.into_cell_error(cell_id))?;
+        if let Some((record_id, reason)) = report.errors.first() {
+            return Err(CellError::DeploymentFailed(format!(
+                "{} records rejected by {}, first {}: {}", report.rejected, cell_id, record_id, reason
+            )));
+        }
+
+        Ok(report.imported)
+    }
+}
+
+/// Data Cell `ExportChunk`; records are JSON text
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct CellExportChunk {
+    schema: Option<candid::Reserved>,
+    records: Vec<(String, String)>,
+    expirations: Vec<(String, u64)>,
+    next_cursor: Option<String>,
+}
+
+/// Data Cell `ImportReport`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct CellImportReport {
+    imported: u64,
+    rejected: u64,
+    errors: Vec<(String, String)>,
+}
+
+/// Data Cell `CellError`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+enum DataCellError {
+    ValidationError(String),
+    PermissionDenied,
+    NotFound(String),
+    SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
+    NotImplemented(String),
+}
+
+impl DataCellError {
+    /// Manager error reporting why `cell_id` rejected a call
+    fn into_cell_error(self, cell_id: Principal) -> CellError {
+        match self {
+            DataCellError::PermissionDenied => CellError::PermissionDenied,
+            DataCellError::NotFound(what) => CellError::NotFound(what),
+            DataCellError::NotImplemented(what) => CellError::NotImplemented(what),
+            DataCellError::ValidationError(reason) | DataCellError::SchemaViolation(reason) => {
+                CellError::InvalidSchema(format!("{} rejected the records: {}", cell_id, reason))
+            },
+            other => CellError::DeploymentFailed(format!("{} rejected the records: {:?}", cell_id, other)),
+        }
+    }
+}
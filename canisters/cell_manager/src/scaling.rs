@@ -0,0 +1,177 @@
+//! Cell scaling strategies
+
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::time::Duration;
+use crate::deployment::{Deployment, ResourceUsage};
+use crate::state::State;
+use crate::types::*;
+
+/// Compute allocation added per vertical scaling step, in percent
+const VERTICAL_COMPUTE_STEP: u64 = 10;
+
+/// Highest compute allocation a cell can be given, in percent
+const MAX_COMPUTE_ALLOCATION: u64 = 100;
+
+/// Memory reserved by vertical scaling, as a multiple of current usage
+const MEMORY_HEADROOM_FACTOR: u64 = 2;
+
+/// Largest memory allocation vertical scaling will reserve
+const MAX_MEMORY_ALLOCATION: u64 = 8 * 1024 * 1024 * 1024;
+
+/// Cycles sent to a cell on each vertical scaling step
+const VERTICAL_TOP_UP_CYCLES: u128 = 1_000_000_000_000;
+
+/// Interval between auto-scale load checks
+const AUTO_SCALE_INTERVAL_SECONDS: u64 = 300;
+
+/// Minimum time between automatic scaling attempts on the same cell
+const AUTO_SCALE_COOLDOWN_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Memory budget assumed for cells created without `memory_limit`
+const DEFAULT_MEMORY_LIMIT: u64 = 4 * 1024 * 1024 * 1024;
+
+thread_local! {
+    /// Cells with an automatic scale currently running
+    static AUTO_SCALING: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
+}
+
+pub struct Scaling;
+
+impl Scaling {
+    /// Scale a managed cell according to `config.strategy`
+    pub async fn scale(cell_id: Principal, config: &ScalingConfig) -> Result<Vec<Principal>, CellError> {
+        let cell_info = State::get_cell(&cell_id)
+            .ok_or_else(|| CellError::NotFound(cell_id.to_string()))?;
+
+        match config.strategy {
+            ScalingStrategy::Vertical => Self::scale_vertically(cell_info).await,
+            ScalingStrategy::Horizontal | ScalingStrategy::Hybrid => Err(CellError::NotImplemented(
+                "Horizontal cell scaling pending implementation".to_string()
+            )),
+        }
+    }
+
+    /// Start the timer that checks auto-scale cells; called from init and post_upgrade
+    pub fn schedule_auto_scale() {
+        ic_cdk_timers::set_timer_interval(Duration::from_secs(AUTO_SCALE_INTERVAL_SECONDS), || {
+            ic_cdk::spawn(Self::check_auto_scale());
+        });
+    }
+
+    /// Scale every auto-scale cell whose memory load has crossed its `split_threshold`
+    ///
+    /// Cells already scaling, scaled within the cooldown, or at `max_cells`
+    /// are skipped, so a cell over threshold triggers one scale at a time.
+    async fn check_auto_scale() {
+        let now = ic_cdk::api::time();
+        let candidates: Vec<(CellInfo, ScalingConfig)> = State::list_all_cells().into_iter()
+            .filter_map(|(_, cell_info)| {
+                let config = cell_info.scaling_config.clone().filter(|config| config.auto_scale)?;
+                Some((cell_info, config))
+            })
+            .filter(|(cell_info, _)| !matches!(cell_info.status, CellStatus::Scaling | CellStatus::Creating))
+            .filter(|(cell_info, _)| {
+                State::last_auto_scale(&cell_info.id)
+                    .map_or(true, |last| now.saturating_sub(last) >= AUTO_SCALE_COOLDOWN_NANOS)
+            })
+            .collect();
+
+        for (cell_info, config) in candidates {
+            let cell_id = cell_info.id;
+            let started = AUTO_SCALING.with(|scaling| scaling.borrow_mut().insert(cell_id));
+            if !started {
+                continue;
+            }
+
+            Self::auto_scale_cell(cell_info, config).await;
+            AUTO_SCALING.with(|scaling| scaling.borrow_mut().remove(&cell_id));
+        }
+    }
+
+    async fn auto_scale_cell(cell_info: CellInfo, config: ScalingConfig) {
+        let cell_id = cell_info.id;
+
+        let usage = match Deployment::resource_usage(cell_id).await {
+            Ok(usage) => usage,
+            Err(error) => {
+                ic_cdk::println!("Auto-scale check of cell {} failed: {:?}", cell_id, error);
+                return;
+            },
+        };
+
+        let limit = cell_info.memory_limit.unwrap_or(DEFAULT_MEMORY_LIMIT).max(1);
+        let load = usage.memory_size as f64 / limit as f64;
+        if load < config.split_threshold {
+            return;
+        }
+
+        let cell_count = 1 + cell_info.replicas.len() as u32;
+        let adds_cells = !matches!(config.strategy, ScalingStrategy::Vertical);
+        if adds_cells && cell_count >= config.max_cells {
+            ic_cdk::println!("Cell {} is over its split threshold but already at max_cells", cell_id);
+            return;
+        }
+
+        ic_cdk::println!("Auto-scaling cell {} at load {:.2}", cell_id, load);
+        let result = Self::scale(cell_id, &config).await;
+
+        State::record_auto_scale(AutoScaleEvent {
+            cell_id,
+            timestamp: ic_cdk::api::time(),
+            load,
+            strategy: config.strategy.clone(),
+            result_cells: result.clone().unwrap_or_default(),
+            error: result.err().map(|error| format!("{:?}", error)),
+        });
+    }
+
+    /// Give a cell more compute and memory headroom and top up its cycles
+    ///
+    /// The cell keeps its principal; no new cells are created.
+    async fn scale_vertically(mut cell_info: CellInfo) -> Result<Vec<Principal>, CellError> {
+        let cell_id = cell_info.id;
+        cell_info.status = CellStatus::Scaling;
+        cell_info.updated_at = ic_cdk::api::time();
+        State::register_cell(cell_id, cell_info.clone());
+
+        let result = Self::grow_allocation(cell_id).await;
+
+        // Re-read so changes made while scaling aren't lost
+        let mut cell_info = State::get_cell(&cell_id).unwrap_or(cell_info);
+        let now = ic_cdk::api::time();
+        cell_info.updated_at = now;
+
+        match result {
+            Ok(usage) => {
+                cell_info.status = CellStatus::Active;
+                cell_info.metrics.memory_usage = usage.memory_size;
+                cell_info.metrics.cycle_consumption = usage.idle_cycles_burned_per_day;
+                cell_info.metrics.last_updated = now;
+                State::register_cell(cell_id, cell_info);
+                Ok(vec![cell_id])
+            },
+            Err(error) => {
+                cell_info.status = CellStatus::Error(format!("Vertical scaling failed: {:?}", error));
+                State::register_cell(cell_id, cell_info);
+                Err(error)
+            },
+        }
+    }
+
+    async fn grow_allocation(cell_id: Principal) -> Result<ResourceUsage, CellError> {
+        let usage = Deployment::resource_usage(cell_id).await?;
+
+        let compute_allocation = (usage.compute_allocation + VERTICAL_COMPUTE_STEP).min(MAX_COMPUTE_ALLOCATION);
+        let memory_allocation = usage.memory_size
+            .saturating_mul(MEMORY_HEADROOM_FACTOR)
+            .max(usage.memory_allocation)
+            .min(MAX_MEMORY_ALLOCATION);
+
+        Deployment::top_up(cell_id, VERTICAL_TOP_UP_CYCLES).await?;
+        Deployment::set_allocation(cell_id, compute_allocation, memory_allocation).await?;
+
+        Deployment::resource_usage(cell_id).await
+    }
+}
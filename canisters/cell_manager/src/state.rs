@@ -1,14 +1,53 @@
 //! State management for Cell Manager canister using stable memory
 
-use candid::Principal;
-use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, RestrictedMemory, memory_manager::{MemoryManager, MemoryId}};
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, StableCell, DefaultMemoryImpl, memory_manager::{MemoryManager, MemoryId, VirtualMemory}};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use crate::types::*;
 
-type Memory = RestrictedMemory<DefaultMemoryImpl>;
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// Implement `Storable` for types kept in stable structures, encoded as CBOR
+///
+/// CBOR is self-describing, so `#[serde(default)]` fields added later decode
+/// from values written before they existed.
+macro_rules! impl_storable {
+    ($($ty:ty),+ $(,)?) => {$(
+        impl ic_stable_structures::Storable for $ty {
+            fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(self, &mut bytes)
+                    .expect(concat!("Failed to encode ", stringify!($ty)));
+                std::borrow::Cow::Owned(bytes)
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                ciborium::de::from_reader(bytes.as_ref())
+                    .expect(concat!("Failed to decode ", stringify!($ty)))
+            }
+
+            const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+        }
+    )+};
+}
+
+impl_storable!(CellInfo, AutoScaleEvent, CloneJob, ManagerSettings, GrantedRoles);
 type CellStorage = StableBTreeMap<Principal, CellInfo, Memory>;
 
+/// Auto-scale events keyed by sequence number
+type AutoScaleLog = StableBTreeMap<u64, AutoScaleEvent, Memory>;
+
+/// Clone copy jobs keyed by the clone's principal
+type CloneJobs = StableBTreeMap<Principal, CloneJob, Memory>;
+
+/// Roles granted to each principal, for cells delegating role resolution
+type RoleRegistry = StableBTreeMap<Principal, GrantedRoles, Memory>;
+
+/// Auto-scale events kept before the oldest are dropped
+const MAX_AUTO_SCALE_EVENTS: u64 = 500;
+
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
@@ -18,14 +57,95 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
         )
     );
+
+    static SETTINGS: RefCell<StableCell<ManagerSettings, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))),
+            ManagerSettings::default(),
+        ).expect("Failed to initialize manager settings")
+    );
+
+    /// Data Cell wasm module installed into newly created cells
+    static CELL_WASM: RefCell<StableCell<Vec<u8>, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))),
+            Vec::new(),
+        ).expect("Failed to initialize cell wasm storage")
+    );
+
+    static AUTO_SCALE_EVENTS: RefCell<AutoScaleLog> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+        )
+    );
+
+    static CLONE_JOBS: RefCell<CloneJobs> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+        )
+    );
+
+    static ROLES: RefCell<RoleRegistry> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+        )
+    );
+}
+
+/// Persistent Cell Manager configuration
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ManagerSettings {
+    pub admins: Vec<Principal>,
+    pub aggregator: Option<Principal>,
 }
 
+/// Roles granted to one principal
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct GrantedRoles(Vec<String>);
+
 pub struct State;
 
 impl State {
-    /// Initialize the state
-    pub fn init() {
-        // State initialization is handled by thread_local initialization
+    /// Initialize the state, making `installer` an admin alongside any configured admins
+    pub fn init(installer: Principal, config: ManagerConfig) {
+        let mut admins = config.admins;
+        if !admins.contains(&installer) {
+            admins.push(installer);
+        }
+
+        SETTINGS.with(|settings| {
+            settings.borrow_mut().set(ManagerSettings {
+                admins,
+                aggregator: config.aggregator,
+            }).expect("Failed to persist manager settings");
+        });
+    }
+
+    /// Get the manager configuration
+    pub fn settings() -> ManagerSettings {
+        SETTINGS.with(|settings| settings.borrow().get().clone())
+    }
+
+    /// Check whether a principal may manage cells
+    pub fn is_admin(principal: &Principal) -> bool {
+        Self::settings().admins.contains(principal)
+    }
+
+    /// Replace the Data Cell wasm module used for new cells
+    pub fn set_cell_wasm(wasm: Vec<u8>) {
+        CELL_WASM.with(|cell| {
+            cell.borrow_mut().set(wasm).expect("Failed to persist cell wasm");
+        });
+    }
+
+    /// Get the Data Cell wasm module, `None` if none has been uploaded
+    pub fn cell_wasm() -> Option<Vec<u8>> {
+        let wasm = CELL_WASM.with(|cell| cell.borrow().get().clone());
+        if wasm.is_empty() {
+            None
+        } else {
+            Some(wasm)
+        }
     }
 
     /// Pre-upgrade hook
@@ -52,10 +172,108 @@ impl State {
         })
     }
 
+    /// Remove a cell from the registry
+    pub fn remove_cell(cell_id: &Principal) -> Option<CellInfo> {
+        CLONE_JOBS.with(|jobs| {
+            jobs.borrow_mut().remove(cell_id);
+        });
+        CELLS.with(|cells| {
+            cells.borrow_mut().remove(cell_id)
+        })
+    }
+
     /// List all cells
     pub fn list_all_cells() -> Vec<(Principal, CellInfo)> {
         CELLS.with(|cells| {
             cells.borrow().iter().collect()
         })
     }
-}
\ No newline at end of file
+
+    /// Append an auto-scale event, dropping the oldest beyond the retention limit
+    pub fn record_auto_scale(event: AutoScaleEvent) {
+        AUTO_SCALE_EVENTS.with(|events| {
+            let mut events = events.borrow_mut();
+            let next = events.last_key_value().map_or(0, |(sequence, _)| sequence + 1);
+            events.insert(next, event);
+
+            while events.len() > MAX_AUTO_SCALE_EVENTS {
+                match events.first_key_value() {
+                    Some((oldest, _)) => { events.remove(&oldest); },
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Auto-scale events, oldest first
+    pub fn auto_scale_events() -> Vec<AutoScaleEvent> {
+        AUTO_SCALE_EVENTS.with(|events| {
+            events.borrow().iter().map(|(_, event)| event).collect()
+        })
+    }
+
+    /// Time of the most recent auto-scale attempt for a cell
+    pub fn last_auto_scale(cell_id: &Principal) -> Option<u64> {
+        AUTO_SCALE_EVENTS.with(|events| {
+            events.borrow().iter()
+                .filter(|(_, event)| event.cell_id == *cell_id)
+                .last()
+                .map(|(_, event)| event.timestamp)
+        })
+    }
+
+    /// Store the progress of a clone copy
+    pub fn save_clone_job(job: CloneJob) {
+        CLONE_JOBS.with(|jobs| {
+            jobs.borrow_mut().insert(job.target, job);
+        });
+    }
+
+    /// Get the clone copy job filling `target`
+    pub fn clone_job(target: &Principal) -> Option<CloneJob> {
+        CLONE_JOBS.with(|jobs| {
+            jobs.borrow().get(target)
+        })
+    }
+
+    /// Roles granted to a principal
+    pub fn roles_of(principal: &Principal) -> Vec<String> {
+        ROLES.with(|roles| {
+            roles.borrow().get(principal)
+        }).map(|granted| granted.0).unwrap_or_default()
+    }
+
+    /// Grant a role; returns false if the principal already had it
+    pub fn grant_role(principal: Principal, role: String) -> bool {
+        let mut granted = Self::roles_of(&principal);
+        if granted.contains(&role) {
+            return false;
+        }
+
+        granted.push(role);
+        ROLES.with(|roles| {
+            roles.borrow_mut().insert(principal, GrantedRoles(granted));
+        });
+        true
+    }
+
+    /// Revoke a role; returns false if the principal didn't have it
+    pub fn revoke_role(principal: Principal, role: &str) -> bool {
+        let mut granted = Self::roles_of(&principal);
+        let before = granted.len();
+        granted.retain(|r| r != role);
+        if granted.len() == before {
+            return false;
+        }
+
+        ROLES.with(|roles| {
+            let mut roles = roles.borrow_mut();
+            if granted.is_empty() {
+                roles.remove(&principal);
+            } else {
+                roles.insert(principal, GrantedRoles(granted));
+            }
+        });
+        true
+    }
+}
@@ -13,6 +13,21 @@ pub struct CellConfig {
     pub cycles_limit: Option<u64>,
     pub permissions: PermissionConfig,
     pub scaling_config: Option<ScalingConfig>,
+    /// Read replicas to deploy alongside the primary cell
+    pub replicas: Option<u32>,
+    /// Resolve `Role` access levels through this manager's role registry
+    /// instead of the cell's own
+    #[serde(default)]
+    pub delegate_roles: Option<bool>,
+}
+
+/// Cell Manager initialization configuration
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ManagerConfig {
+    /// Principals allowed to create and manage cells, in addition to the installer
+    pub admins: Vec<Principal>,
+    /// Query aggregator new cells are registered with
+    pub aggregator: Option<Principal>,
 }
 
 /// Schema definition for a Data Cell
@@ -90,6 +105,56 @@ pub struct CellInfo {
     pub created_at: u64,
     pub updated_at: u64,
     pub metrics: CellMetrics,
+    /// Read replicas following this cell
+    #[serde(default)]
+    pub replicas: Vec<Principal>,
+    /// Memory budget auto-scaling measures load against
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    #[serde(default)]
+    pub scaling_config: Option<ScalingConfig>,
+    /// Permissions the cell was created with
+    #[serde(default)]
+    pub permissions: Option<PermissionConfig>,
+    /// Whether the cell resolves roles through the manager
+    #[serde(default)]
+    pub delegate_roles: Option<bool>,
+}
+
+/// Build and schema version reported by `version`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    /// Always `None`; the manager has no schema of its own
+    pub schema_version: Option<u32>,
+}
+
+/// Record of an automatic scaling attempt
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AutoScaleEvent {
+    pub cell_id: Principal,
+    pub timestamp: u64,
+    /// Memory usage as a fraction of the cell's memory limit
+    pub load: f64,
+    pub strategy: ScalingStrategy,
+    /// Principals returned by the scale, empty if it failed
+    pub result_cells: Vec<Principal>,
+    pub error: Option<String>,
+}
+
+/// Progress of copying a source cell's records into its clone
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CloneJob {
+    pub source: Principal,
+    pub target: Principal,
+    /// Export cursor to resume from; `None` before the first chunk
+    pub cursor: Option<String>,
+    pub records_copied: u64,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub completed: bool,
+    pub last_error: Option<String>,
 }
 
 /// Cell status
@@ -118,5 +183,8 @@ pub enum CellError {
     InvalidSchema(String),
     InsufficientCycles,
     PermissionDenied,
+    DeploymentFailed(String),
     NotImplemented(String),
+    /// The cell still holds records; delete with `force` or `migrate_to`
+    CellNotEmpty,
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+
+/// Longer than the manager's auto-scale check interval
+const CHECK_SECS: u64 = 301;
+
+fn auto_scaled(name: &str, memory_limit: u64, strategy: ScalingStrategy, max_cells: u32) -> CellConfig {
+    CellConfig {
+        memory_limit: Some(memory_limit),
+        scaling_config: Some(ScalingConfig { auto_scale: true, max_cells, split_threshold: 0.5, strategy }),
+        ..cell_config(name)
+    }
+}
+
+#[test]
+fn a_cell_over_its_threshold_is_scaled_once_per_cooldown() {
+    let manager = Manager::new();
+    // Any installed cell uses more than one byte, so its load is far over the threshold
+    let info = manager.create_cell(auto_scaled("hot", 1, ScalingStrategy::Vertical, 1)).unwrap();
+
+    manager.advance_secs(CHECK_SECS);
+    let events = manager.auto_scale_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].cell_id, info.id);
+    assert_eq!(events[0].strategy, ScalingStrategy::Vertical);
+    assert!(events[0].load > 0.5);
+    assert_eq!(events[0].result_cells, vec![info.id]);
+    assert_eq!(events[0].error, None);
+    assert_eq!(manager.cell_info(info.id).unwrap().status, CellStatus::Active);
+
+    // Still over threshold, but within the cooldown
+    manager.advance_secs(CHECK_SECS);
+    assert_eq!(manager.auto_scale_events().len(), 1);
+}
+
+#[test]
+fn cells_under_threshold_or_at_max_cells_are_left_alone() {
+    let manager = Manager::new();
+    manager.create_cell(auto_scaled("cool", u64::MAX, ScalingStrategy::Vertical, 1)).unwrap();
+    manager.create_cell(auto_scaled("full", 1, ScalingStrategy::Horizontal, 1)).unwrap();
+    manager.create_cell(CellConfig {
+        memory_limit: Some(1),
+        scaling_config: Some(ScalingConfig { auto_scale: false, ..scaling(ScalingStrategy::Vertical) }),
+        ..cell_config("manual")
+    }).unwrap();
+
+    manager.advance_secs(CHECK_SECS);
+    assert!(manager.auto_scale_events().is_empty());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn clone_cell(manager: &Manager, source: candid::Principal, name: &str, copy_data: bool) -> Result<CellInfo, CellError> {
+    let (result,): (Result<CellInfo, CellError>,) =
+        manager.update(controller(), "clone_cell", (source, name.to_string(), copy_data));
+    result
+}
+
+fn clone_job(manager: &Manager, target: candid::Principal) -> Option<CloneJob> {
+    let (job,): (Option<CloneJob>,) = manager.query(user(), "clone_job", (target,));
+    job
+}
+
+/// More records than one migration chunk, so the copy takes several
+fn populated_source(manager: &Manager) -> CellInfo {
+    let source = manager.create_cell(cell_config("source")).unwrap();
+    let records = (0..600)
+        .map(|i| (format!("item_{:03}", i), json!({"name": format!("item_{:03}", i), "score": i})))
+        .collect();
+    assert_eq!(manager.import(source.id, records).imported, 600);
+    source
+}
+
+#[test]
+fn cloning_a_populated_cell_copies_every_record() {
+    let manager = Manager::new();
+    let source = populated_source(&manager);
+
+    let clone = clone_cell(&manager, source.id, "staging", true).unwrap();
+    assert_ne!(clone.id, source.id);
+    assert_eq!(clone.name, "staging");
+    assert_eq!(clone.status, CellStatus::Active);
+
+    assert_eq!(manager.record_count(clone.id), 600);
+    for record_id in ["item_000", "item_499", "item_500", "item_599"] {
+        assert_eq!(manager.get(clone.id, user(), record_id), manager.get(source.id, user(), record_id));
+    }
+
+    let job = clone_job(&manager, clone.id).unwrap();
+    assert!(job.completed);
+    assert_eq!(job.records_copied, 600);
+    assert_eq!(job.cursor, None);
+    assert_eq!(job.last_error, None);
+
+    // The clone keeps the source's schema and permissions
+    assert!(matches!(manager.try_insert(clone.id, user(), json!({"score": 1})), Err(DataCellError::ValidationError(_))));
+    manager.insert(clone.id, json!({"name": "new"}));
+    assert_eq!(manager.record_count(source.id), 600);
+    assert!(manager.registered_cells().contains(&clone.id));
+}
+
+#[test]
+fn cloning_without_data_gives_an_empty_cell() {
+    let manager = Manager::new();
+    let source = populated_source(&manager);
+
+    let clone = clone_cell(&manager, source.id, "empty", false).unwrap();
+    assert_eq!(manager.record_count(clone.id), 0);
+    assert!(clone_job(&manager, clone.id).is_none());
+}
+
+#[test]
+fn resuming_a_completed_clone_copies_nothing_more() {
+    let manager = Manager::new();
+    let source = populated_source(&manager);
+    let clone = clone_cell(&manager, source.id, "staging", true).unwrap();
+
+    let (result,): (Result<CellInfo, CellError>,) = manager.update(controller(), "resume_clone", (clone.id,));
+    assert_eq!(result.unwrap().id, clone.id);
+    assert_eq!(clone_job(&manager, clone.id).unwrap().records_copied, 600);
+
+    let (result,): (Result<CellInfo, CellError>,) = manager.update(controller(), "resume_clone", (source.id,));
+    assert!(matches!(result, Err(CellError::NotFound(_))));
+}
+
+#[test]
+fn only_admins_clone_known_cells() {
+    let manager = Manager::new();
+    let source = manager.create_cell(cell_config("source")).unwrap();
+
+    let (result,): (Result<CellInfo, CellError>,) =
+        manager.update(user(), "clone_cell", (source.id, "copy".to_string(), false));
+    assert!(matches!(result, Err(CellError::PermissionDenied)));
+    assert!(matches!(clone_cell(&manager, user(), "copy", false), Err(CellError::NotFound(_))));
+    assert_eq!(manager.list_cells().len(), 1);
+}
@@ -0,0 +1,489 @@
+//! PocketIC harness for Cell Manager integration tests
+//!
+//! The manager deploys real Data Cells and registers them with a real Query
+//! Aggregator, so all three wasm modules must be built for
+//! `wasm32-unknown-unknown` (or pointed at with `CELL_MANAGER_WASM`,
+//! `DATA_CELL_WASM` and `QUERY_AGGREGATOR_WASM`), and `POCKET_IC_BIN` must
+//! name a PocketIC server binary. Record JSON travels as `text`.
+
+#![allow(dead_code)]
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Deserialize, Principal};
+use pocket_ic::{query_candid_as, update_candid_as, PocketIc};
+use serde_json::Value;
+
+/// Enough for the manager to fund a primary and a few replicas
+pub const MANAGER_CYCLES: u128 = 100_000_000_000_000;
+
+pub const CYCLES: u128 = 2_000_000_000_000;
+
+pub fn controller() -> Principal {
+    Principal::self_authenticating(b"controller")
+}
+
+pub fn user() -> Principal {
+    Principal::self_authenticating(b"user")
+}
+
+pub fn other_user() -> Principal {
+    Principal::self_authenticating(b"other user")
+}
+
+fn wasm(env_var: &str, file: &str) -> Vec<u8> {
+    let path = std::env::var(env_var).unwrap_or_else(|_| {
+        format!("{}/../../target/wasm32-unknown-unknown/release/{}", env!("CARGO_MANIFEST_DIR"), file)
+    });
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Could not read {} at {}: {}", file, path, e))
+}
+
+pub fn manager_wasm() -> Vec<u8> {
+    wasm("CELL_MANAGER_WASM", "cell_manager.wasm")
+}
+
+pub fn cell_wasm() -> Vec<u8> {
+    wasm("DATA_CELL_WASM", "celldb.wasm")
+}
+
+pub fn aggregator_wasm() -> Vec<u8> {
+    wasm("QUERY_AGGREGATOR_WASM", "query_aggregator.wasm")
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ManagerConfig {
+    pub admins: Vec<Principal>,
+    pub aggregator: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellConfig {
+    pub name: String,
+    pub schema: SchemaDefinition,
+    pub memory_limit: Option<u64>,
+    pub cycles_limit: Option<u64>,
+    pub permissions: PermissionConfig,
+    pub scaling_config: Option<ScalingConfig>,
+    pub replicas: Option<u32>,
+    pub delegate_roles: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SchemaDefinition {
+    pub version: u32,
+    pub fields: Vec<(String, FieldType)>,
+    pub indexes: Vec<String>,
+    pub constraints: Vec<SchemaConstraint>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum FieldType {
+    Text { max_length: Option<u32> },
+    Number { min: Option<i64>, max: Option<i64> },
+    Boolean,
+    Principal,
+    Timestamp,
+    Blob { max_size: Option<u64> },
+    Array { element_type: Box<FieldType>, max_items: Option<u32> },
+    Object { fields: Vec<(String, FieldType)> },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum SchemaConstraint {
+    Required(String),
+    Unique(String),
+    Index(String),
+    ForeignKey { field: String, references: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PermissionConfig {
+    pub read: Vec<AccessLevel>,
+    pub write: Vec<AccessLevel>,
+    pub admin: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum AccessLevel {
+    Public,
+    Authenticated,
+    Principal(Principal),
+    Role(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ScalingConfig {
+    pub auto_scale: bool,
+    pub max_cells: u32,
+    pub split_threshold: f64,
+    pub strategy: ScalingStrategy,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ScalingStrategy {
+    Horizontal,
+    Vertical,
+    Hybrid,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellInfo {
+    pub id: Principal,
+    pub name: String,
+    pub status: CellStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub metrics: CellMetrics,
+    pub replicas: Vec<Principal>,
+    pub delegate_roles: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellStatus {
+    Creating,
+    Active,
+    Scaling,
+    Maintenance,
+    Error(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellMetrics {
+    pub memory_usage: u64,
+    pub cycle_consumption: u64,
+    pub operation_count: u64,
+    pub last_updated: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    pub schema_version: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AutoScaleEvent {
+    pub cell_id: Principal,
+    pub timestamp: u64,
+    pub load: f64,
+    pub strategy: ScalingStrategy,
+    pub result_cells: Vec<Principal>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellError {
+    NotFound(String),
+    InvalidSchema(String),
+    InsufficientCycles,
+    PermissionDenied,
+    DeploymentFailed(String),
+    NotImplemented(String),
+    CellNotEmpty,
+}
+
+/// Data Cell `CellError`
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum DataCellError {
+    ValidationError(String),
+    PermissionDenied,
+    NotFound(String),
+    SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
+    NotImplemented(String),
+}
+
+/// Data Cell `CellHealth`, only the fields the tests look at
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellHealth {
+    pub schema_version: u32,
+    pub record_count: u64,
+}
+
+/// Data Cell `ImportReport`
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: u64,
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CloneJob {
+    pub source: Principal,
+    pub target: Principal,
+    pub cursor: Option<String>,
+    pub records_copied: u64,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub completed: bool,
+    pub last_error: Option<String>,
+}
+
+/// Data Cell `Precondition`
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Precondition {
+    Absent,
+    FieldEquals { field: String, value: String },
+}
+
+/// Query Aggregator `AggregatorConfig`, with no cells registered up front
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorConfig {
+    name: String,
+    registered_cells: Vec<AggregatorCellRegistration>,
+    authorized_managers: Option<Vec<Principal>>,
+    retry_policy: Option<AggregatorRetryPolicy>,
+    streaming_config: AggregatorStreamingConfig,
+    optimization_config: AggregatorOptimizationConfig,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorCellRegistration {
+    cell_id: Principal,
+    name: String,
+    schema_version: u32,
+    capabilities: Vec<AggregatorCellCapability>,
+    performance_hints: AggregatorPerformanceHints,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum AggregatorCellCapability {
+    BatchOperations,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorPerformanceHints {
+    typical_response_time_ms: u32,
+    max_concurrent_queries: u32,
+    preferred_batch_size: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorRetryPolicy {
+    max_retries: u32,
+    base_delay_ms: u64,
+    max_delay_ms: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorStreamingConfig {
+    default_batch_size: u32,
+    max_concurrent_streams: u32,
+    stream_timeout_seconds: u64,
+    buffer_size: u32,
+    prefetch_enabled: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct AggregatorOptimizationConfig {
+    cache_enabled: bool,
+    cache_ttl_seconds: u64,
+    max_cache_entries: u64,
+    cost_optimization_enabled: bool,
+    adaptive_batching: bool,
+}
+
+fn aggregator_config(manager: Principal) -> AggregatorConfig {
+    AggregatorConfig {
+        name: "test_aggregator".to_string(),
+        registered_cells: Vec::new(),
+        authorized_managers: Some(vec![manager, controller()]),
+        retry_policy: None,
+        streaming_config: AggregatorStreamingConfig {
+            default_batch_size: 10,
+            max_concurrent_streams: 10,
+            stream_timeout_seconds: 3_600,
+            buffer_size: 100,
+            prefetch_enabled: false,
+        },
+        optimization_config: AggregatorOptimizationConfig {
+            cache_enabled: false,
+            cache_ttl_seconds: 60,
+            max_cache_entries: 100,
+            cost_optimization_enabled: false,
+            adaptive_batching: false,
+        },
+    }
+}
+
+/// Items with a required `name`, a `category` and a numeric `score`
+pub fn item_schema() -> SchemaDefinition {
+    SchemaDefinition {
+        version: 1,
+        fields: vec![
+            ("name".to_string(), FieldType::Text { max_length: None }),
+            ("category".to_string(), FieldType::Text { max_length: None }),
+            ("score".to_string(), FieldType::Number { min: None, max: None }),
+        ],
+        indexes: Vec::new(),
+        constraints: vec![SchemaConstraint::Required("name".to_string())],
+    }
+}
+
+/// Anyone may read, any authenticated caller may write, the controller administers
+pub fn cell_config(name: &str) -> CellConfig {
+    CellConfig {
+        name: name.to_string(),
+        schema: item_schema(),
+        memory_limit: None,
+        cycles_limit: None,
+        permissions: PermissionConfig {
+            read: vec![AccessLevel::Public],
+            write: vec![AccessLevel::Authenticated],
+            admin: vec![controller()],
+        },
+        scaling_config: None,
+        replicas: None,
+        delegate_roles: None,
+    }
+}
+
+pub fn scaling(strategy: ScalingStrategy) -> ScalingConfig {
+    ScalingConfig { auto_scale: false, max_cells: 4, split_threshold: 0.8, strategy }
+}
+
+pub fn parse(record: &str) -> Value {
+    serde_json::from_str(record).expect("record is not JSON")
+}
+
+/// A cell manager with the Data Cell wasm uploaded, and the aggregator it registers cells with
+pub struct Manager {
+    pub pic: PocketIc,
+    pub id: Principal,
+    pub aggregator: Principal,
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        let pic = PocketIc::new();
+
+        let id = pic.create_canister_with_settings(Some(controller()), None);
+        pic.add_cycles(id, MANAGER_CYCLES);
+
+        let aggregator = pic.create_canister_with_settings(Some(controller()), None);
+        pic.add_cycles(aggregator, CYCLES);
+        pic.install_canister(
+            aggregator, aggregator_wasm(), candid::encode_one(aggregator_config(id)).unwrap(), Some(controller()),
+        );
+
+        let config = ManagerConfig { admins: vec![controller()], aggregator: Some(aggregator) };
+        pic.install_canister(id, manager_wasm(), candid::encode_one(Some(config)).unwrap(), Some(controller()));
+
+        let manager = Self { pic, id, aggregator };
+        manager.set_cell_wasm(cell_wasm()).expect("set_cell_wasm failed");
+        manager
+    }
+
+    pub fn update<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        update_candid_as(&self.pic, self.id, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    pub fn query<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        query_candid_as(&self.pic, self.id, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    /// Let timers and spawned calls, such as replica syncs, run
+    pub fn settle(&self) {
+        for _ in 0..10 {
+            self.pic.tick();
+        }
+    }
+
+    pub fn set_cell_wasm(&self, wasm: Vec<u8>) -> Result<(), CellError> {
+        let (result,): (Result<(), CellError>,) = self.update(controller(), "set_cell_wasm", (wasm,));
+        result
+    }
+
+    /// Create a cell as the controller, an admin of the manager
+    pub fn create_cell(&self, config: CellConfig) -> Result<CellInfo, CellError> {
+        let (result,): (Result<CellInfo, CellError>,) = self.update(controller(), "create_cell", (config,));
+        result
+    }
+
+    pub fn cell_info(&self, cell_id: Principal) -> Option<CellInfo> {
+        let (info,): (Option<CellInfo>,) = self.query(user(), "get_cell_info", (cell_id,));
+        info
+    }
+
+    pub fn list_cells(&self) -> Vec<CellInfo> {
+        let (cells,): (Vec<CellInfo>,) = self.query(user(), "list_cells", ());
+        cells
+    }
+
+    pub fn scale(&self, cell_id: Principal, config: ScalingConfig) -> Result<Vec<Principal>, CellError> {
+        let (result,): (Result<Vec<Principal>, CellError>,) = self.update(controller(), "scale_cell", (cell_id, config));
+        result
+    }
+
+    pub fn delete_cell(&self, cell_id: Principal, migrate_to: Option<Principal>, force: Option<bool>) -> Result<(), CellError> {
+        let (result,): (Result<(), CellError>,) =
+            self.update(controller(), "delete_cell", (cell_id, migrate_to, force));
+        result
+    }
+
+    /// Whether a canister still answers calls
+    pub fn is_live(&self, canister_id: Principal) -> bool {
+        query_candid_as::<_, (Option<String>,)>(&self.pic, canister_id, user(), "get", ("probe".to_string(),)).is_ok()
+    }
+
+    pub fn auto_scale_events(&self) -> Vec<AutoScaleEvent> {
+        let (events,): (Vec<AutoScaleEvent>,) = self.query(user(), "auto_scale_events", ());
+        events
+    }
+
+    /// Move the clock forward and let due timers run
+    pub fn advance_secs(&self, secs: u64) {
+        self.pic.advance_time(std::time::Duration::from_secs(secs));
+        self.settle();
+    }
+
+    /// Cells registered with the aggregator
+    pub fn registered_cells(&self) -> Vec<Principal> {
+        let (versions,): (Vec<(Principal, u32)>,) =
+            query_candid_as(&self.pic, self.aggregator, user(), "schema_versions", ())
+                .expect("schema_versions failed");
+        versions.into_iter().map(|(cell_id, _)| cell_id).collect()
+    }
+
+    /// Insert a record into a cell directly
+    pub fn try_insert(&self, cell_id: Principal, sender: Principal, record: Value) -> Result<String, DataCellError> {
+        let (result,): (Result<String, DataCellError>,) = update_candid_as(
+            &self.pic, cell_id, sender, "insert",
+            (record.to_string(), None::<u64>, None::<Precondition>, None::<String>),
+        ).expect("insert call failed");
+        result
+    }
+
+    pub fn insert(&self, cell_id: Principal, record: Value) -> String {
+        self.try_insert(cell_id, user(), record).expect("insert failed")
+    }
+
+    pub fn record_count(&self, cell_id: Principal) -> u64 {
+        let (health,): (CellHealth,) = query_candid_as(&self.pic, cell_id, user(), "health", ())
+            .expect("health call failed");
+        health.record_count
+    }
+
+    /// Load records into a cell under fixed IDs, as the controller (a cell admin)
+    pub fn import(&self, cell_id: Principal, records: Vec<(String, Value)>) -> ImportReport {
+        let records: Vec<(String, String)> = records.into_iter()
+            .map(|(record_id, record)| (record_id, record.to_string()))
+            .collect();
+        let (result,): (Result<ImportReport, DataCellError>,) =
+            update_candid_as(&self.pic, cell_id, controller(), "import_chunk", (records, true, None::<Vec<(String, u64)>>))
+                .expect("import_chunk call failed");
+        result.expect("import_chunk failed")
+    }
+
+    pub fn get(&self, cell_id: Principal, sender: Principal, record_id: &str) -> Option<Value> {
+        let (record,): (Option<String>,) =
+            query_candid_as(&self.pic, cell_id, sender, "get", (record_id.to_string(),))
+                .expect("get call failed");
+        record.as_deref().map(parse)
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn empty_cells_are_deleted_and_unregistered() {
+    let manager = Manager::new();
+    let info = manager.create_cell(CellConfig { replicas: Some(1), ..cell_config("items") }).unwrap();
+    assert_eq!(manager.registered_cells().len(), 2);
+
+    manager.delete_cell(info.id, None, None).unwrap();
+
+    assert!(manager.list_cells().is_empty());
+    assert!(manager.registered_cells().is_empty());
+    assert!(!manager.is_live(info.id));
+    assert!(!manager.is_live(info.replicas[0]));
+}
+
+#[test]
+fn populated_cells_need_a_migration_target_or_force() {
+    let manager = Manager::new();
+    let source = manager.create_cell(cell_config("source")).unwrap();
+    let target = manager.create_cell(cell_config("target")).unwrap();
+    let ids: Vec<String> = (0..3)
+        .map(|i| manager.insert(source.id, json!({"name": format!("item_{}", i), "score": i})))
+        .collect();
+
+    assert_eq!(manager.delete_cell(source.id, None, None), Err(CellError::CellNotEmpty));
+    assert!(manager.is_live(source.id));
+
+    manager.delete_cell(source.id, Some(target.id), None).unwrap();
+    assert!(!manager.is_live(source.id));
+    for (i, record_id) in ids.iter().enumerate() {
+        assert_eq!(manager.get(target.id, user(), record_id).unwrap()["score"], json!(i));
+    }
+    assert_eq!(manager.list_cells().iter().map(|cell| cell.id).collect::<Vec<_>>(), vec![target.id]);
+    assert_eq!(manager.registered_cells(), vec![target.id]);
+
+    manager.delete_cell(target.id, None, Some(true)).unwrap();
+    assert!(!manager.is_live(target.id));
+    assert!(manager.list_cells().is_empty());
+}
+
+#[test]
+fn deletion_is_refused_for_non_admins_and_self_migration() {
+    let manager = Manager::new();
+    let info = manager.create_cell(CellConfig { replicas: Some(1), ..cell_config("items") }).unwrap();
+    manager.insert(info.id, json!({"name": "alpha"}));
+
+    let (result,): (Result<(), CellError>,) =
+        manager.update(user(), "delete_cell", (info.id, None::<candid::Principal>, Some(true)));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    for target in [info.id, info.replicas[0]] {
+        assert!(matches!(manager.delete_cell(info.id, Some(target), None), Err(CellError::DeploymentFailed(_))));
+    }
+    assert!(matches!(manager.delete_cell(user(), None, Some(true)), Err(CellError::NotFound(_))));
+    assert!(manager.is_live(info.id));
+    assert_eq!(manager.list_cells().len(), 1);
+}
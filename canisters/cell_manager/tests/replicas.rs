@@ -0,0 +1,44 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn replicas_are_created_registered_and_follow_the_primary() {
+    let manager = Manager::new();
+    let info = manager.create_cell(CellConfig { replicas: Some(2), ..cell_config("items") }).unwrap();
+
+    assert_eq!(info.status, CellStatus::Active);
+    assert_eq!(info.replicas.len(), 2);
+    assert!(!info.replicas.contains(&info.id));
+    assert_ne!(info.replicas[0], info.replicas[1]);
+    assert_eq!(manager.cell_info(info.id).unwrap().replicas, info.replicas);
+
+    let mut registered = manager.registered_cells();
+    registered.sort();
+    let mut expected = vec![info.id, info.replicas[0], info.replicas[1]];
+    expected.sort();
+    assert_eq!(registered, expected);
+
+    manager.settle();
+    let record_id = manager.insert(info.id, json!({"name": "alpha", "category": "a"}));
+    manager.settle();
+    for replica in &info.replicas {
+        assert_eq!(manager.get(*replica, user(), &record_id).unwrap()["name"], json!("alpha"));
+        assert_eq!(
+            manager.try_insert(*replica, user(), json!({"name": "beta"})),
+            Err(DataCellError::PermissionDenied),
+        );
+    }
+}
+
+#[test]
+fn failed_deployments_are_rolled_back() {
+    let manager = Manager::new();
+    manager.set_cell_wasm(b"not a wasm module".to_vec()).unwrap();
+
+    let result = manager.create_cell(CellConfig { replicas: Some(2), ..cell_config("items") });
+    assert!(matches!(result, Err(CellError::DeploymentFailed(_))));
+    assert!(manager.list_cells().is_empty());
+    assert!(manager.registered_cells().is_empty());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+use serde_json::json;
+
+/// Longer than a cell's cache of roles fetched from the manager
+const ROLE_CACHE_SECS: u64 = 5 * 60 + 1;
+
+/// A cell only `editor`s may write, optionally resolving roles through the manager
+fn editors_only(delegate_roles: Option<bool>) -> CellConfig {
+    CellConfig {
+        permissions: PermissionConfig {
+            read: vec![AccessLevel::Public],
+            write: vec![AccessLevel::Role("editor".to_string())],
+            admin: vec![controller()],
+        },
+        delegate_roles,
+        ..cell_config("items")
+    }
+}
+
+fn set_role(manager: &Manager, method: &str, principal: Principal, role: &str) {
+    let (result,): (Result<(), CellError>,) = manager.update(controller(), method, (principal, role.to_string()));
+    result.unwrap_or_else(|e| panic!("{} failed: {:?}", method, e));
+}
+
+#[test]
+fn a_manager_granted_role_grants_access_in_a_delegating_cell() {
+    let manager = Manager::new();
+    let cell = manager.create_cell(editors_only(Some(true))).unwrap();
+
+    set_role(&manager, "grant_role", user(), "editor");
+    let (roles,): (Vec<String>,) = manager.query(user(), "roles_of", (user(),));
+    assert_eq!(roles, ["editor"]);
+
+    let record_id = manager.try_insert(cell.id, user(), json!({"name": "alpha"})).unwrap();
+    assert_eq!(manager.get(cell.id, user(), &record_id).unwrap()["name"], json!("alpha"));
+    assert_eq!(manager.try_insert(cell.id, other_user(), json!({"name": "beta"})), Err(DataCellError::PermissionDenied));
+}
+
+#[test]
+fn cells_not_delegating_roles_ignore_the_manager() {
+    let manager = Manager::new();
+    let cell = manager.create_cell(editors_only(None)).unwrap();
+
+    set_role(&manager, "grant_role", user(), "editor");
+    assert_eq!(manager.try_insert(cell.id, user(), json!({"name": "alpha"})), Err(DataCellError::PermissionDenied));
+}
+
+#[test]
+fn role_changes_reach_cells_once_their_cached_roles_expire() {
+    let manager = Manager::new();
+    let cell = manager.create_cell(editors_only(Some(true))).unwrap();
+
+    // The denied attempt caches the user's empty role set
+    assert_eq!(manager.try_insert(cell.id, user(), json!({"name": "alpha"})), Err(DataCellError::PermissionDenied));
+    set_role(&manager, "grant_role", user(), "editor");
+    assert_eq!(manager.try_insert(cell.id, user(), json!({"name": "alpha"})), Err(DataCellError::PermissionDenied));
+
+    manager.advance_secs(ROLE_CACHE_SECS);
+    assert!(manager.try_insert(cell.id, user(), json!({"name": "alpha"})).is_ok());
+
+    set_role(&manager, "revoke_role", user(), "editor");
+    assert!(manager.try_insert(cell.id, user(), json!({"name": "beta"})).is_ok());
+    manager.advance_secs(ROLE_CACHE_SECS);
+    assert_eq!(manager.try_insert(cell.id, user(), json!({"name": "gamma"})), Err(DataCellError::PermissionDenied));
+}
+
+#[test]
+fn only_manager_admins_grant_roles() {
+    let manager = Manager::new();
+
+    let (result,): (Result<(), CellError>,) = manager.update(user(), "grant_role", (user(), "editor".to_string()));
+    assert!(matches!(result, Err(CellError::PermissionDenied)));
+    let (roles,): (Vec<String>,) = manager.query(user(), "roles_of", (user(),));
+    assert!(roles.is_empty());
+}
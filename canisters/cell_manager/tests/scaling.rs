@@ -0,0 +1,37 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn vertical_scaling_tops_up_the_cell_without_creating_new_ones() {
+    let manager = Manager::new();
+    let info = manager.create_cell(cell_config("items")).unwrap();
+    let cycles_before = manager.pic.cycle_balance(info.id);
+
+    let cells = manager.scale(info.id, scaling(ScalingStrategy::Vertical)).unwrap();
+    assert_eq!(cells, vec![info.id]);
+    assert_eq!(manager.list_cells().len(), 1);
+    assert_eq!(manager.registered_cells(), vec![info.id]);
+
+    let scaled = manager.cell_info(info.id).unwrap();
+    assert_eq!(scaled.status, CellStatus::Active);
+    assert!(scaled.metrics.memory_usage > 0);
+    assert!(scaled.metrics.last_updated >= info.created_at);
+    assert!(manager.pic.cycle_balance(info.id) > cycles_before);
+}
+
+#[test]
+fn scaling_requires_an_admin_and_a_known_cell() {
+    let manager = Manager::new();
+    let info = manager.create_cell(cell_config("items")).unwrap();
+
+    let (result,): (Result<Vec<candid::Principal>, CellError>,) =
+        manager.update(user(), "scale_cell", (info.id, scaling(ScalingStrategy::Vertical)));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    assert!(matches!(manager.scale(user(), scaling(ScalingStrategy::Vertical)), Err(CellError::NotFound(_))));
+    assert!(matches!(
+        manager.scale(info.id, scaling(ScalingStrategy::Horizontal)),
+        Err(CellError::NotImplemented(_))
+    ));
+}
@@ -0,0 +1,14 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn version_names_the_build() {
+    let manager = Manager::new();
+    let (version,): (CanisterVersion,) = manager.query(user(), "version", ());
+
+    assert_eq!(version.name, "cell_manager");
+    assert!(!version.build_version.is_empty());
+    assert!(version.build_version.starts_with(env!("CARGO_PKG_VERSION")));
+    assert_eq!(version.schema_version, None);
+}
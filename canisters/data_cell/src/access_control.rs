@@ -1,17 +1,57 @@
 //! Access control and permission management for Data Cells
+//!
+//! Read and write access is granted by the configured `AccessLevel` lists;
+//! admins and controllers pass every check. `Role` levels resolve against
+//! the cell's role registry, maintained with `grant_role`/`revoke_role`,
+//! plus the roles held at the configured role authority (the cell manager),
+//! which are fetched by update calls and cached for `ROLE_CACHE_TTL_NANOS`.
 
-use candid::Principal;
-use std::collections::HashSet;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, StableCell, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::settings::Settings;
+use crate::storage::{impl_storable, memory, Memory};
+
+/// How long roles fetched from the role authority are trusted
+const ROLE_CACHE_TTL_NANOS: u64 = 5 * 60 * 1_000_000_000;
+
+thread_local! {
+    /// Permissions from the init configuration or `set_permissions`; `None`
+    /// for cells installed before permissions were persisted, which only
+    /// admit controllers until permissions are set
+    static PERMISSIONS: RefCell<StableCell<Option<PermissionConfig>, Memory>> = RefCell::new(
+        StableCell::init(memory(MemoryId::new(21)), None)
+            .expect("Failed to initialize permissions")
+    );
+
+    /// Roles granted to each principal
+    static ROLES: RefCell<StableBTreeMap<Principal, GrantedRoles, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(22)))
+    );
+
+    /// Roles fetched from the role authority, with the time they were fetched
+    static DELEGATED_ROLES: RefCell<HashMap<Principal, (Vec<String>, u64)>> = RefCell::new(HashMap::new());
+}
 
 /// Permission configuration
-#[derive(Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct PermissionConfig {
-    pub read_permissions: Vec<AccessLevel>,
-    pub write_permissions: Vec<AccessLevel>,
-    pub admin_principals: HashSet<Principal>,
+    pub read: Vec<AccessLevel>,
+    pub write: Vec<AccessLevel>,
+    pub admin: Vec<Principal>,
 }
 
-#[derive(Clone, Debug)]
+impl_storable!(PermissionConfig);
+
+/// Roles granted to one principal in the local registry
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct GrantedRoles(Vec<String>);
+
+impl_storable!(GrantedRoles);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum AccessLevel {
     Public,
     Authenticated,
@@ -25,36 +65,143 @@ impl AccessControl {
     /// Initialize access control with configuration
     pub fn init(config: &PermissionConfig) {
         ic_cdk::println!("Initializing access control");
-        // TODO: Store permission configuration in stable memory
+        Self::set_permissions(config.clone());
+    }
+
+    /// Replace the persisted permissions
+    pub fn set_permissions(config: PermissionConfig) {
+        PERMISSIONS.with(|permissions| {
+            permissions.borrow_mut().set(Some(config))
+                .expect("Failed to persist permissions");
+        });
+    }
+
+    fn permissions() -> Option<PermissionConfig> {
+        PERMISSIONS.with(|permissions| permissions.borrow().get().clone())
     }
 
     /// Check if principal has read permission
+    ///
+    /// Without persisted permissions only admins, i.e. controllers, may read.
     pub fn can_read(caller: Principal) -> bool {
-        // TODO: Implement read permission checking
-        // - Check against configured read permissions
-        // - Validate principal identity
-        // - Apply role-based access control
-
-        true // Placeholder - allow all for now
+        Self::is_admin(caller)
+            || Self::permissions().map_or(false, |config| Self::grants(&config.read, caller))
     }
 
     /// Check if principal has write permission
+    ///
+    /// Without persisted permissions only admins, i.e. controllers, may write.
     pub fn can_write(caller: Principal) -> bool {
-        // TODO: Implement write permission checking
-        // - Check against configured write permissions
-        // - Validate principal identity
-        // - Apply role-based access control
-
-        true // Placeholder - allow all for now
+        Self::is_admin(caller)
+            || Self::permissions().map_or(false, |config| Self::grants(&config.write, caller))
     }
 
-    /// Check if principal has admin permission
+    /// Check if principal has admin permission: a configured admin or a controller
     pub fn is_admin(caller: Principal) -> bool {
-        // TODO: Implement admin permission checking
-        // - Check against admin principals list
-        // - Validate principal identity
+        ic_cdk::api::is_controller(&caller)
+            || Self::permissions().map_or(false, |config| config.admin.contains(&caller))
+    }
+
+    /// Whether any of `levels` admits `caller`
+    fn grants(levels: &[AccessLevel], caller: Principal) -> bool {
+        let mut roles = None;
+        levels.iter().any(|level| match level {
+            AccessLevel::Public => true,
+            AccessLevel::Authenticated => caller != Principal::anonymous(),
+            AccessLevel::Principal(principal) => *principal == caller,
+            AccessLevel::Role(role) => roles.get_or_insert_with(|| Self::roles_of(caller)).contains(role),
+        })
+    }
+
+    /// Roles granted to a principal, locally or by the role authority
+    ///
+    /// Authority roles count only while their cached copy is fresh.
+    pub fn roles_of(principal: Principal) -> Vec<String> {
+        let mut granted = Self::local_roles(principal);
+
+        let now = ic_cdk::api::time();
+        DELEGATED_ROLES.with(|cache| {
+            if let Some((roles, fetched_at)) = cache.borrow().get(&principal) {
+                if now.saturating_sub(*fetched_at) < ROLE_CACHE_TTL_NANOS {
+                    granted.extend(roles.iter().cloned());
+                }
+            }
+        });
+        granted
+    }
+
+    /// Roles granted to a principal in this cell's own registry
+    fn local_roles(principal: Principal) -> Vec<String> {
+        ROLES.with(|roles| roles.borrow().get(&principal)).map(|granted| granted.0).unwrap_or_default()
+    }
+
+    /// Fetch a principal's roles from the role authority unless a fresh copy is cached
+    ///
+    /// Queries can't make the call, so they see the roles fetched by the
+    /// caller's latest update call. On failure the cached copy is left to
+    /// expire.
+    pub async fn refresh_delegated_roles(principal: Principal) -> Result<(), String> {
+        let authority = match Settings::get().role_authority {
+            Some(authority) => authority,
+            None => return Ok(()),
+        };
+
+        let now = ic_cdk::api::time();
+        let fresh = DELEGATED_ROLES.with(|cache| {
+            cache.borrow().get(&principal)
+                .map_or(false, |(_, fetched_at)| now.saturating_sub(*fetched_at) < ROLE_CACHE_TTL_NANOS)
+        });
+        if fresh {
+            return Ok(());
+        }
+
+        let (roles,): (Vec<String>,) = ic_cdk::call(authority, "roles_of", (principal,))
+            .await
+            .map_err(|(code, msg)| format!("roles_of on {} failed: {:?} - {}", authority, code, msg))?;
+
+        DELEGATED_ROLES.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache.retain(|_, (_, fetched_at)| now.saturating_sub(*fetched_at) < ROLE_CACHE_TTL_NANOS);
+            cache.insert(principal, (roles, now));
+        });
+        Ok(())
+    }
+
+    /// Grant a role locally; returns false if the principal already had it locally
+    ///
+    /// Only the local registry is read and written, so roles held at the role
+    /// authority are never copied into the cell.
+    pub fn grant_role(principal: Principal, role: String) -> bool {
+        let mut granted = Self::local_roles(principal);
+        if granted.contains(&role) {
+            return false;
+        }
+
+        granted.push(role);
+        ROLES.with(|roles| roles.borrow_mut().insert(principal, GrantedRoles(granted)));
+        true
+    }
+
+    /// Revoke a locally granted role; returns false if the principal didn't have it locally
+    ///
+    /// Roles held at the role authority must be revoked there.
+    pub fn revoke_role(principal: Principal, role: &str) -> bool {
+        let mut granted = Self::local_roles(principal);
+        let before = granted.len();
+        granted.retain(|r| r != role);
+        if granted.len() == before {
+            return false;
+        }
 
-        false // Placeholder - no admins for now
+        ROLES.with(|roles| {
+            let mut roles = roles.borrow_mut();
+            if granted.is_empty() {
+                roles.remove(&principal);
+            } else {
+                roles.insert(principal, GrantedRoles(granted));
+            }
+        });
+        true
     }
 
     /// Add new permission rule
@@ -0,0 +1,155 @@
+//! Synthetic workload measuring the cost of a cell's storage operations
+//!
+//! The whole run happens inside one update call: synthetic records are
+//! inserted, read back, updated and deleted before the call returns, so no
+//! other message ever sees them. Time doesn't advance within a message, so
+//! costs are counted in instructions and throughput is estimated from them.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use crate::CellError;
+use crate::metrics::TrackedOperation;
+use crate::schema::{FieldDefinition, FieldType};
+use crate::storage::Storage;
+
+/// Largest `record_count` accepted, keeping a run well inside the instruction limit
+pub const MAX_BENCHMARK_RECORDS: u32 = 1_000;
+
+/// Prefix of synthetic record IDs
+const RECORD_ID_PREFIX: &str = "__benchmark_";
+
+/// Cycles charged per 10 instructions on a 13-node subnet
+const CYCLES_PER_TEN_INSTRUCTIONS: u64 = 4;
+
+/// Nominal instructions a replica executes per second, for throughput estimates
+const INSTRUCTIONS_PER_SECOND: u64 = 2_000_000_000;
+
+/// Result of `self_benchmark`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BenchmarkReport {
+    pub record_count: u32,
+    /// Costs of inserts, reads by ID (`Query`), updates and deletes
+    pub operations: Vec<OperationBenchmark>,
+    pub total_instructions: u64,
+}
+
+/// Measured cost of one operation type
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OperationBenchmark {
+    pub operation: TrackedOperation,
+    pub instructions_per_op: u64,
+    pub cycles_per_op: u64,
+    /// Estimated from `instructions_per_op` at `INSTRUCTIONS_PER_SECOND`
+    pub ops_per_second: u64,
+}
+
+pub struct Benchmark;
+
+impl Benchmark {
+    /// Insert, read, update and delete `record_count` synthetic records, measuring each phase
+    ///
+    /// Records are stored without validation, since synthetic values can't
+    /// satisfy arbitrary rules; indexes, hashes and blob references are
+    /// maintained as for real writes.
+    pub fn run(record_count: u32) -> Result<BenchmarkReport, CellError> {
+        if record_count == 0 || record_count > MAX_BENCHMARK_RECORDS {
+            return Err(CellError::ValidationError(
+                format!("record_count must be between 1 and {}", MAX_BENCHMARK_RECORDS)
+            ));
+        }
+
+        let schema = Storage::get_schema();
+        let record_ids: Vec<String> = (0..record_count)
+            .map(|i| format!("{}{}", RECORD_ID_PREFIX, i))
+            .collect();
+        if let Some(existing) = record_ids.iter().find(|record_id| Storage::has_record(record_id)) {
+            return Err(CellError::PreconditionFailed(
+                format!("Record {} already exists; benchmark records would overwrite it", existing)
+            ));
+        }
+
+        let records: Vec<Value> = (0..record_count as u64)
+            .map(|i| synthetic_record(&schema.fields, i))
+            .collect();
+        let updated: Vec<Value> = (0..record_count as u64)
+            .map(|i| synthetic_record(&schema.fields, i + record_count as u64))
+            .collect();
+
+        let started = ic_cdk::api::performance_counter(0);
+        let mut operations = Vec::new();
+
+        let phase = ic_cdk::api::performance_counter(0);
+        for (record_id, record) in record_ids.iter().zip(&records) {
+            Storage::put_json_record(record_id, record, None)
+                .map_err(CellError::StorageError)?;
+        }
+        operations.push(measure(TrackedOperation::Insert, record_count, phase));
+
+        let phase = ic_cdk::api::performance_counter(0);
+        for record_id in &record_ids {
+            if Storage::get_json_record(record_id).is_none() {
+                return Err(CellError::StorageError(format!("Benchmark record {} was not stored", record_id)));
+            }
+        }
+        operations.push(measure(TrackedOperation::Query, record_count, phase));
+
+        let phase = ic_cdk::api::performance_counter(0);
+        for ((record_id, previous), record) in record_ids.iter().zip(&records).zip(&updated) {
+            Storage::put_json_record(record_id, record, Some(previous))
+                .map_err(CellError::StorageError)?;
+        }
+        operations.push(measure(TrackedOperation::Update, record_count, phase));
+
+        let phase = ic_cdk::api::performance_counter(0);
+        for record_id in &record_ids {
+            Storage::remove_json_record(record_id);
+        }
+        operations.push(measure(TrackedOperation::Delete, record_count, phase));
+
+        Ok(BenchmarkReport {
+            record_count,
+            operations,
+            total_instructions: ic_cdk::api::performance_counter(0).saturating_sub(started),
+        })
+    }
+}
+
+/// Cost per operation of a phase that started at instruction count `started`
+fn measure(operation: TrackedOperation, count: u32, started: u64) -> OperationBenchmark {
+    let instructions_per_op = (ic_cdk::api::performance_counter(0).saturating_sub(started) / count as u64).max(1);
+
+    OperationBenchmark {
+        operation,
+        instructions_per_op,
+        cycles_per_op: (instructions_per_op * CYCLES_PER_TEN_INSTRUCTIONS / 10).max(1),
+        ops_per_second: INSTRUCTIONS_PER_SECOND / instructions_per_op,
+    }
+}
+
+/// Record with a value for every field of the schema except blobs, varied by `seed`
+fn synthetic_record(fields: &HashMap<String, FieldDefinition>, seed: u64) -> Value {
+    let record: Map<String, Value> = fields.iter()
+        .filter_map(|(name, definition)| {
+            synthetic_value(&definition.field_type, seed).map(|value| (name.clone(), value))
+        })
+        .collect();
+    Value::Object(record)
+}
+
+fn synthetic_value(field_type: &FieldType, seed: u64) -> Option<Value> {
+    match field_type {
+        FieldType::Text => Some(Value::String(format!("benchmark {}", seed))),
+        FieldType::Number => Some(Value::from(seed)),
+        FieldType::Boolean => Some(Value::Bool(seed % 2 == 0)),
+        FieldType::Timestamp => Some(Value::from(ic_cdk::api::time() + seed)),
+        FieldType::Principal => Some(Value::String(candid::Principal::management_canister().to_text())),
+        // Blob fields must reference uploaded blobs
+        FieldType::Blob => None,
+        FieldType::Array(element_type) => Some(Value::Array(
+            synthetic_value(element_type, seed).into_iter().collect()
+        )),
+        FieldType::Object(fields) => Some(synthetic_record(fields, seed)),
+    }
+}
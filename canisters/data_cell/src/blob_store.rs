@@ -0,0 +1,385 @@
+//! Large-object store for `Blob` field payloads, kept apart from records
+//!
+//! Blobs are uploaded with `put_blob` and a record's `Blob` field then holds
+//! the returned blob ID instead of the bytes, so record reads and scans don't
+//! carry the payload. Each blob belongs to at most one record: it is claimed
+//! when a record referencing it is stored, and deleted once that record is
+//! deleted or stops referencing it. Blobs never claimed are swept after
+//! `ORPHAN_GRACE_NANOS`.
+//!
+//! Blobs too large for one message are uploaded in chunks: `begin_upload`,
+//! any number of `put_chunk` calls in any order, then `finish_upload`, which
+//! assembles the chunks into a blob. Uploads idle for `UPLOAD_TIMEOUT_NANOS`
+//! are discarded.
+//!
+//! Blob payloads are not replicated; replicas keep the references only.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::schema::{FieldDefinition, FieldType, SchemaDefinition, ValidationRule};
+use crate::storage::{impl_storable, memory, Memory};
+use crate::CellError;
+
+/// Time an uploaded blob may stay unreferenced before it is swept
+const ORPHAN_GRACE_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Time a chunked upload may go without a new chunk before it is discarded
+const UPLOAD_TIMEOUT_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Largest blob a chunked upload may assemble
+pub const MAX_UPLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Separator between the upload ID and offset parts of a chunk key
+const CHUNK_KEY_SEPARATOR: char = '\0';
+
+thread_local! {
+    static BLOBS: RefCell<StableBTreeMap<String, Vec<u8>, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(15)))
+    );
+
+    static BLOB_INFO: RefCell<StableBTreeMap<String, BlobInfo, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(16)))
+    );
+
+    static UPLOADS: RefCell<StableBTreeMap<String, BlobUpload, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(17)))
+    );
+
+    /// Chunks of in-progress uploads, keyed by `upload_id \0 offset` with zero-padded offsets
+    static UPLOAD_CHUNKS: RefCell<StableBTreeMap<String, Vec<u8>, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(18)))
+    );
+}
+
+/// Size and ownership of a stored blob
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BlobInfo {
+    pub size: u64,
+    pub created_at: u64,
+    /// Record referencing the blob; `None` until a record claims it
+    pub record_id: Option<String>,
+}
+
+impl_storable!(BlobInfo);
+
+/// A chunked upload in progress
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct BlobUpload {
+    owner: Principal,
+    /// Limit on the assembled size, from the target field's `MaxSize` and `MAX_UPLOAD_BYTES`
+    max_size: u64,
+    /// End of the furthest chunk received so far
+    size: u64,
+    updated_at: u64,
+}
+
+impl_storable!(BlobUpload);
+
+/// A blob ID found in a record, with the limit its field places on it
+struct BlobReference {
+    path: String,
+    blob_id: String,
+    max_size: Option<u64>,
+}
+
+pub struct BlobStore;
+
+impl BlobStore {
+    /// Store a blob payload, returning its ID
+    pub fn put(bytes: Vec<u8>) -> String {
+        let now = ic_cdk::api::time();
+        let base = format!("blob_{}", now);
+        let mut blob_id = base.clone();
+        let mut suffix = 0u32;
+
+        while BLOB_INFO.with(|info| info.borrow().contains_key(&blob_id)) {
+            suffix += 1;
+            blob_id = format!("{}_{}", base, suffix);
+        }
+
+        let info = BlobInfo {
+            size: bytes.len() as u64,
+            created_at: now,
+            record_id: None,
+        };
+        BLOBS.with(|blobs| blobs.borrow_mut().insert(blob_id.clone(), bytes));
+        BLOB_INFO.with(|blob_info| blob_info.borrow_mut().insert(blob_id.clone(), info));
+
+        blob_id
+    }
+
+    /// Start a chunked upload for `owner`, returning its ID
+    ///
+    /// With `max_size` the assembled blob may not exceed it, on top of `MAX_UPLOAD_BYTES`.
+    pub fn begin_upload(owner: Principal, max_size: Option<u64>) -> String {
+        let now = ic_cdk::api::time();
+        let base = format!("upload_{}", now);
+        let mut upload_id = base.clone();
+        let mut suffix = 0u32;
+
+        while UPLOADS.with(|uploads| uploads.borrow().contains_key(&upload_id)) {
+            suffix += 1;
+            upload_id = format!("{}_{}", base, suffix);
+        }
+
+        let upload = BlobUpload {
+            owner,
+            max_size: max_size.map_or(MAX_UPLOAD_BYTES, |max_size| max_size.min(MAX_UPLOAD_BYTES)),
+            size: 0,
+            updated_at: now,
+        };
+        UPLOADS.with(|uploads| uploads.borrow_mut().insert(upload_id.clone(), upload));
+
+        upload_id
+    }
+
+    /// Store the chunk of an upload starting at byte `offset`, replacing any chunk sent there before
+    pub fn put_chunk(owner: Principal, upload_id: &str, offset: u64, bytes: Vec<u8>) -> Result<(), CellError> {
+        let mut upload = Self::owned_upload(owner, upload_id)?;
+
+        let end = offset.saturating_add(bytes.len() as u64);
+        if end > upload.max_size {
+            return Err(CellError::ValidationError(format!(
+                "Chunk ending at byte {} exceeds the upload's maximum size {}", end, upload.max_size
+            )));
+        }
+
+        UPLOAD_CHUNKS.with(|chunks| chunks.borrow_mut().insert(chunk_key(upload_id, offset), bytes));
+
+        upload.size = upload.size.max(end);
+        upload.updated_at = ic_cdk::api::time();
+        UPLOADS.with(|uploads| uploads.borrow_mut().insert(upload_id.to_string(), upload));
+        Ok(())
+    }
+
+    /// Assemble an upload's chunks into a blob, returning the blob ID
+    ///
+    /// Fails, keeping the upload, if the chunks leave a gap or overlap.
+    pub fn finish_upload(owner: Principal, upload_id: &str) -> Result<String, CellError> {
+        let upload = Self::owned_upload(owner, upload_id)?;
+        let prefix = chunk_key_prefix(upload_id);
+
+        let mut bytes = Vec::with_capacity(upload.size as usize);
+        let mut chunk_keys = Vec::new();
+        UPLOAD_CHUNKS.with(|chunks| {
+            for (key, chunk) in chunks.borrow().range(prefix.clone()..) {
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+
+                let offset: u64 = key[prefix.len()..].parse().unwrap_or(u64::MAX);
+                if offset != bytes.len() as u64 {
+                    return Err(CellError::ValidationError(format!(
+                        "Upload {} has a gap or overlap at byte {}", upload_id, bytes.len()
+                    )));
+                }
+
+                bytes.extend_from_slice(&chunk);
+                chunk_keys.push(key);
+            }
+            Ok(())
+        })?;
+
+        Self::discard_upload(upload_id, chunk_keys);
+        Ok(Self::put(bytes))
+    }
+
+    /// Drop uploads that have received no chunk for `UPLOAD_TIMEOUT_NANOS`
+    pub fn sweep_uploads(now: u64) -> u64 {
+        let abandoned: Vec<String> = UPLOADS.with(|uploads| {
+            uploads.borrow().iter()
+                .filter(|(_, upload)| now.saturating_sub(upload.updated_at) > UPLOAD_TIMEOUT_NANOS)
+                .map(|(upload_id, _)| upload_id)
+                .collect()
+        });
+
+        for upload_id in &abandoned {
+            let prefix = chunk_key_prefix(upload_id);
+            let chunk_keys = UPLOAD_CHUNKS.with(|chunks| {
+                chunks.borrow().range(prefix.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&prefix))
+                    .map(|(key, _)| key)
+                    .collect()
+            });
+            Self::discard_upload(upload_id, chunk_keys);
+        }
+        abandoned.len() as u64
+    }
+
+    fn owned_upload(owner: Principal, upload_id: &str) -> Result<BlobUpload, CellError> {
+        let upload = UPLOADS.with(|uploads| uploads.borrow().get(&upload_id.to_string()))
+            .ok_or_else(|| CellError::NotFound(upload_id.to_string()))?;
+
+        if upload.owner != owner {
+            return Err(CellError::PermissionDenied);
+        }
+        Ok(upload)
+    }
+
+    fn discard_upload(upload_id: &str, chunk_keys: Vec<String>) {
+        UPLOAD_CHUNKS.with(|chunks| {
+            let mut chunks = chunks.borrow_mut();
+            for key in chunk_keys {
+                chunks.remove(&key);
+            }
+        });
+        UPLOADS.with(|uploads| uploads.borrow_mut().remove(&upload_id.to_string()));
+    }
+
+    /// `MaxSize` rule of a top-level `Blob` field (or array of blobs), if it has one
+    pub fn field_max_size(schema: &SchemaDefinition, field: &str) -> Result<Option<u64>, CellError> {
+        let definition = schema.fields.get(field)
+            .filter(|definition| match &definition.field_type {
+                FieldType::Blob => true,
+                FieldType::Array(item_type) => matches!(item_type.as_ref(), FieldType::Blob),
+                _ => false,
+            })
+            .ok_or_else(|| CellError::ValidationError(format!("{} is not a Blob field", field)))?;
+
+        Ok(definition.validation_rules.iter().find_map(|rule| match rule {
+            ValidationRule::MaxSize(max_size) => Some(*max_size),
+            _ => None,
+        }))
+    }
+
+    /// Payload of a blob
+    pub fn get(blob_id: &str) -> Option<Vec<u8>> {
+        BLOBS.with(|blobs| blobs.borrow().get(&blob_id.to_string()))
+    }
+
+    /// Size and owner of a blob
+    pub fn info(blob_id: &str) -> Option<BlobInfo> {
+        BLOB_INFO.with(|info| info.borrow().get(&blob_id.to_string()))
+    }
+
+    /// Check that every blob `record` references exists, fits its field's
+    /// `MaxSize` and isn't owned by another record
+    pub fn check_references(schema: &SchemaDefinition, record_id: &str, record: &Value) -> Result<(), String> {
+        for reference in Self::references(schema, record) {
+            let info = Self::info(&reference.blob_id)
+                .ok_or_else(|| format!("{}: unknown blob {}", reference.path, reference.blob_id))?;
+
+            if let Some(max_size) = reference.max_size {
+                if info.size > max_size {
+                    return Err(format!(
+                        "{}: blob of {} bytes exceeds maximum size {}", reference.path, info.size, max_size
+                    ));
+                }
+            }
+
+            if info.record_id.as_deref().map_or(false, |owner| owner != record_id) {
+                return Err(format!(
+                    "{}: blob {} belongs to another record", reference.path, reference.blob_id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Claim the blobs `record` references and delete those only `previous` referenced
+    pub fn update_references(schema: &SchemaDefinition, record_id: &str, record: Option<&Value>, previous: Option<&Value>) {
+        let current: Vec<String> = record
+            .map(|record| Self::references(schema, record).into_iter().map(|r| r.blob_id).collect())
+            .unwrap_or_default();
+
+        for blob_id in &current {
+            BLOB_INFO.with(|blob_info| {
+                let mut blob_info = blob_info.borrow_mut();
+                if let Some(mut info) = blob_info.get(blob_id) {
+                    if info.record_id.is_none() {
+                        info.record_id = Some(record_id.to_string());
+                        blob_info.insert(blob_id.clone(), info);
+                    }
+                }
+            });
+        }
+
+        if let Some(previous) = previous {
+            for reference in Self::references(schema, previous) {
+                let owned = Self::info(&reference.blob_id)
+                    .map_or(false, |info| info.record_id.as_deref() == Some(record_id));
+                if owned && !current.contains(&reference.blob_id) {
+                    Self::remove(&reference.blob_id);
+                }
+            }
+        }
+    }
+
+    /// Delete up to `limit` blobs never claimed within `ORPHAN_GRACE_NANOS` of upload
+    pub fn sweep_orphans(now: u64, limit: usize) -> u64 {
+        let orphans: Vec<String> = BLOB_INFO.with(|info| {
+            info.borrow().iter()
+                .filter(|(_, info)| info.record_id.is_none()
+                    && now.saturating_sub(info.created_at) > ORPHAN_GRACE_NANOS)
+                .take(limit)
+                .map(|(blob_id, _)| blob_id)
+                .collect()
+        });
+
+        for blob_id in &orphans {
+            Self::remove(blob_id);
+        }
+        orphans.len() as u64
+    }
+
+    fn remove(blob_id: &str) {
+        BLOBS.with(|blobs| blobs.borrow_mut().remove(&blob_id.to_string()));
+        BLOB_INFO.with(|info| info.borrow_mut().remove(&blob_id.to_string()));
+    }
+
+    /// Blob IDs held in the `Blob` fields of a record
+    ///
+    /// Blob fields may also hold bytes inline as an array; those aren't references.
+    fn references(schema: &SchemaDefinition, record: &Value) -> Vec<BlobReference> {
+        let mut references = Vec::new();
+        collect_references(&schema.fields, record, "", &mut references);
+        references
+    }
+}
+
+fn collect_references(fields: &HashMap<String, FieldDefinition>, value: &Value, path: &str, out: &mut Vec<BlobReference>) {
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => return,
+    };
+
+    for (name, field) in fields {
+        if let Some(value) = obj.get(name) {
+            let field_path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+            let max_size = field.validation_rules.iter().find_map(|rule| match rule {
+                ValidationRule::MaxSize(max_size) => Some(*max_size),
+                _ => None,
+            });
+            collect_value_references(&field.field_type, value, &field_path, max_size, out);
+        }
+    }
+}
+
+fn collect_value_references(field_type: &FieldType, value: &Value, path: &str, max_size: Option<u64>, out: &mut Vec<BlobReference>) {
+    match (field_type, value) {
+        (FieldType::Blob, Value::String(blob_id)) => out.push(BlobReference {
+            path: path.to_string(),
+            blob_id: blob_id.clone(),
+            max_size,
+        }),
+        (FieldType::Array(item_type), Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_value_references(item_type, item, &format!("{}[{}]", path, i), max_size, out);
+            }
+        },
+        (FieldType::Object(fields), value) => collect_references(fields, value, path, out),
+        _ => {},
+    }
+}
+
+fn chunk_key_prefix(upload_id: &str) -> String {
+    format!("{}{}", upload_id, CHUNK_KEY_SEPARATOR)
+}
+
+fn chunk_key(upload_id: &str, offset: u64) -> String {
+    format!("{}{:020}", chunk_key_prefix(upload_id), offset)
+}
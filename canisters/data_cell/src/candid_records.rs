@@ -0,0 +1,232 @@
+//! Candid encoding of records, guided by the cell's schema
+//!
+//! Each record travels as a Candid message with a single `record` argument
+//! whose type is derived from the schema: required fields map to their type
+//! `T`, optional fields to `opt T`. Keys not in the schema are dropped when
+//! encoding and ignored when decoding.
+
+use candid::types::{Field, Label, Type, TypeEnv, TypeInner};
+use candid::{IDLArgs, IDLField, IDLValue, Principal};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::blob_store::BlobStore;
+use crate::schema::{FieldDefinition, FieldType, SchemaDefinition};
+use crate::CellError;
+
+pub struct CandidRecords;
+
+impl CandidRecords {
+    /// Candid type of a record under `schema`
+    pub fn record_type(schema: &SchemaDefinition) -> Type {
+        object_type(&schema.fields)
+    }
+
+    /// Decode a Candid-encoded record into its JSON form
+    pub fn decode(schema: &SchemaDefinition, bytes: &[u8]) -> Result<Value, CellError> {
+        let args = IDLArgs::from_bytes(bytes)
+            .map_err(|e| CellError::ValidationError(format!("Invalid Candid record: {}", e)))?;
+
+        match args.args.as_slice() {
+            [record] => decode_object(&schema.fields, record, "record"),
+            _ => Err(CellError::ValidationError("Candid record must be a single argument".to_string())),
+        }
+    }
+
+    /// Encode a JSON record as a Candid message of `record_type(schema)`
+    pub fn encode(schema: &SchemaDefinition, record: &Value) -> Result<Vec<u8>, CellError> {
+        let value = encode_object(&schema.fields, record, "record")?;
+        IDLArgs::new(&[value])
+            .to_bytes_with_types(&TypeEnv::new(), &[Self::record_type(schema)])
+            .map_err(|e| CellError::StorageError(format!("Failed to encode record as Candid: {}", e)))
+    }
+}
+
+/// Schema fields ordered by Candid label hash, the order record types and values use
+fn sorted_fields(fields: &HashMap<String, FieldDefinition>) -> Vec<(&String, &FieldDefinition)> {
+    let mut sorted: Vec<_> = fields.iter().collect();
+    sorted.sort_by_key(|(name, _)| candid::idl_hash(name));
+    sorted
+}
+
+fn object_type(fields: &HashMap<String, FieldDefinition>) -> Type {
+    let fields = sorted_fields(fields).into_iter()
+        .map(|(name, field)| {
+            let ty = field_type(&field.field_type);
+            Field {
+                id: Rc::new(Label::Named(name.clone())),
+                ty: if field.required { ty } else { TypeInner::Opt(ty).into() },
+            }
+        })
+        .collect();
+    TypeInner::Record(fields).into()
+}
+
+fn field_type(field_type: &FieldType) -> Type {
+    match field_type {
+        FieldType::Text => TypeInner::Text.into(),
+        FieldType::Number => TypeInner::Float64.into(),
+        FieldType::Boolean => TypeInner::Bool.into(),
+        FieldType::Timestamp => TypeInner::Nat64.into(),
+        FieldType::Principal => TypeInner::Principal.into(),
+        FieldType::Blob => TypeInner::Vec(TypeInner::Nat8.into()).into(),
+        FieldType::Array(item_type) => TypeInner::Vec(self::field_type(item_type)).into(),
+        FieldType::Object(fields) => object_type(fields),
+    }
+}
+
+fn encode_object(fields: &HashMap<String, FieldDefinition>, value: &Value, path: &str) -> Result<IDLValue, CellError> {
+    let obj = value.as_object()
+        .ok_or_else(|| type_error(path, "object"))?;
+
+    let mut encoded = Vec::with_capacity(fields.len());
+    for (name, field) in sorted_fields(fields) {
+        let field_path = format!("{}.{}", path, name);
+        let present = obj.get(name).filter(|value| !value.is_null());
+
+        let val = match (present, field.required) {
+            (Some(value), true) => encode_value(&field.field_type, value, &field_path)?,
+            (Some(value), false) => IDLValue::Opt(Box::new(encode_value(&field.field_type, value, &field_path)?)),
+            (None, false) => IDLValue::None,
+            (None, true) => return Err(CellError::SchemaViolation(format!("{}: required field missing", field_path))),
+        };
+        encoded.push(IDLField { id: Label::Named(name.clone()), val });
+    }
+
+    Ok(IDLValue::Record(encoded))
+}
+
+fn encode_value(field_type: &FieldType, value: &Value, path: &str) -> Result<IDLValue, CellError> {
+    match field_type {
+        FieldType::Text => value.as_str()
+            .map(|s| IDLValue::Text(s.to_string()))
+            .ok_or_else(|| type_error(path, "string")),
+        FieldType::Number => value.as_f64()
+            .map(IDLValue::Float64)
+            .ok_or_else(|| type_error(path, "number")),
+        FieldType::Boolean => value.as_bool()
+            .map(IDLValue::Bool)
+            .ok_or_else(|| type_error(path, "boolean")),
+        FieldType::Timestamp => value.as_u64()
+            .map(IDLValue::Nat64)
+            .ok_or_else(|| type_error(path, "timestamp")),
+        FieldType::Principal => value.as_str()
+            .and_then(|text| Principal::from_text(text).ok())
+            .map(IDLValue::Principal)
+            .ok_or_else(|| type_error(path, "principal text")),
+        FieldType::Blob => value.as_str()
+            .and_then(BlobStore::get)
+            .or_else(|| value.as_array().and_then(|items| items.iter()
+                .map(|item| item.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<u8>>>()))
+            .map(IDLValue::Blob)
+            .ok_or_else(|| type_error(path, "array of bytes")),
+        FieldType::Array(item_type) => {
+            let items = value.as_array().ok_or_else(|| type_error(path, "array"))?;
+            items.iter()
+                .enumerate()
+                .map(|(i, item)| encode_value(item_type, item, &format!("{}[{}]", path, i)))
+                .collect::<Result<Vec<_>, _>>()
+                .map(IDLValue::Vec)
+        },
+        FieldType::Object(fields) => encode_object(fields, value, path),
+    }
+}
+
+fn decode_object(fields: &HashMap<String, FieldDefinition>, value: &IDLValue, path: &str) -> Result<Value, CellError> {
+    let candid_fields = match value {
+        IDLValue::Record(candid_fields) => candid_fields,
+        _ => return Err(type_error(path, "record")),
+    };
+
+    let mut obj = serde_json::Map::new();
+    for (name, field) in fields {
+        let field_path = format!("{}.{}", path, name);
+        let hash = candid::idl_hash(name);
+        let present = candid_fields.iter()
+            .find(|candid_field| candid_field.id.get_id() == hash)
+            .map(|candid_field| match &candid_field.val {
+                IDLValue::Opt(inner) => inner.as_ref(),
+                other => other,
+            })
+            .filter(|val| !matches!(val, IDLValue::None | IDLValue::Null));
+
+        match present {
+            Some(val) => {
+                obj.insert(name.clone(), decode_value(&field.field_type, val, &field_path)?);
+            },
+            None if field.required && field.default_value.is_none() => {
+                return Err(CellError::SchemaViolation(format!("{}: required field missing", field_path)));
+            },
+            None => {},
+        }
+    }
+
+    Ok(Value::Object(obj))
+}
+
+fn decode_value(field_type: &FieldType, value: &IDLValue, path: &str) -> Result<Value, CellError> {
+    match (field_type, value) {
+        (FieldType::Text, IDLValue::Text(s)) => Ok(Value::String(s.clone())),
+        (FieldType::Number, value) => decode_number(value)
+            .and_then(json_number)
+            .ok_or_else(|| type_error(path, "number")),
+        (FieldType::Boolean, IDLValue::Bool(b)) => Ok(Value::Bool(*b)),
+        (FieldType::Timestamp, IDLValue::Nat64(n)) => Ok(Value::from(*n)),
+        (FieldType::Principal, IDLValue::Principal(principal)) => Ok(Value::String(principal.to_text())),
+        (FieldType::Blob, IDLValue::Blob(bytes)) => Ok(Value::from(bytes.clone())),
+        (FieldType::Blob, IDLValue::Vec(items)) => items.iter()
+            .map(|item| match item {
+                IDLValue::Nat8(byte) => Some(Value::from(*byte)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Array)
+            .ok_or_else(|| type_error(path, "blob")),
+        (FieldType::Array(item_type), IDLValue::Vec(items)) => items.iter()
+            .enumerate()
+            .map(|(i, item)| decode_value(item_type, item, &format!("{}[{}]", path, i)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        (FieldType::Object(fields), value) => decode_object(fields, value, path),
+        (FieldType::Text, _) => Err(type_error(path, "text")),
+        (FieldType::Boolean, _) => Err(type_error(path, "bool")),
+        (FieldType::Timestamp, _) => Err(type_error(path, "nat64")),
+        (FieldType::Principal, _) => Err(type_error(path, "principal")),
+        (FieldType::Blob, _) => Err(type_error(path, "blob")),
+        (FieldType::Array(_), _) => Err(type_error(path, "vec")),
+    }
+}
+
+/// Any Candid number as `f64`; clients may send integers for `Number` fields
+fn decode_number(value: &IDLValue) -> Option<f64> {
+    match value {
+        IDLValue::Float64(n) => Some(*n),
+        IDLValue::Float32(n) => Some(*n as f64),
+        IDLValue::Int8(n) => Some(*n as f64),
+        IDLValue::Int16(n) => Some(*n as f64),
+        IDLValue::Int32(n) => Some(*n as f64),
+        IDLValue::Int64(n) => Some(*n as f64),
+        IDLValue::Nat8(n) => Some(*n as f64),
+        IDLValue::Nat16(n) => Some(*n as f64),
+        IDLValue::Nat32(n) => Some(*n as f64),
+        IDLValue::Nat64(n) => Some(*n as f64),
+        IDLValue::Int(n) => n.to_string().replace('_', "").parse().ok(),
+        IDLValue::Nat(n) => n.to_string().replace('_', "").parse().ok(),
+        IDLValue::Number(n) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Whole numbers become JSON integers, matching records inserted as JSON
+pub(crate) fn json_number(n: f64) -> Option<Value> {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Some(Value::from(n as i64))
+    } else {
+        serde_json::Number::from_f64(n).map(Value::Number)
+    }
+}
+
+fn type_error(path: &str, expected: &str) -> CellError {
+    CellError::ValidationError(format!("{}: expected {}", path, expected))
+}
@@ -0,0 +1,346 @@
+//! Change feed notifying subscriber canisters of record mutations
+//!
+//! Events that can't be delivered, including those for disabled subscribers,
+//! are kept as dead letters. The sweep timer retries them with exponential
+//! backoff while their subscriber is active; admins can list them and
+//! redeliver one at any time.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use crate::storage::{impl_storable, memory, Memory, Storage};
+use crate::invalidation::Invalidation;
+use crate::replication::Replication;
+
+/// Subscribers keyed by `callback:method`
+type SubscriberStorage = StableBTreeMap<String, Subscriber, Memory>;
+
+/// Undeliverable events keyed by dead letter ID, which sorts by failure time
+type DeadLetterStorage = StableBTreeMap<String, DeadLetter, Memory>;
+
+/// Consecutive delivery failures after which a subscriber is disabled
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// Dead letters kept before the oldest are dropped
+const MAX_DEAD_LETTERS: u64 = 10_000;
+
+/// Automatic redelivery attempts per dead letter; `redeliver` still works afterwards
+const MAX_AUTOMATIC_RETRIES: u32 = 8;
+
+/// Delay before the first automatic retry, doubled after each failed retry
+const RETRY_BASE_DELAY_NANOS: u64 = 60 * 1_000_000_000;
+
+/// Dead letters retried per sweep
+const RETRY_BATCH_SIZE: usize = 50;
+
+thread_local! {
+    static SUBSCRIBERS: RefCell<SubscriberStorage> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(8)))
+    );
+
+    static DEAD_LETTERS: RefCell<DeadLetterStorage> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(23)))
+    );
+
+    /// Disambiguates dead letter IDs created in the same round
+    static DEAD_LETTER_SEQUENCE: Cell<u64> = Cell::new(0);
+}
+
+/// Canister method registered to receive change events
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Subscriber {
+    pub callback: Principal,
+    pub method: String,
+    pub consecutive_failures: u32,
+    pub active: bool,
+}
+
+impl_storable!(Subscriber);
+
+/// Mutation delivered to subscribers
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub op: ChangeOperation,
+    pub record_id: String,
+    /// Record after the change, or the removed record for deletes
+    pub record: Option<serde_json::Value>,
+    pub timestamp: u64,
+    /// The record's version after the change, increasing with every change
+    /// the cell makes, so replicas can drop events older than what they hold
+    #[serde(default)]
+    pub version: u64,
+    /// When the record expires, for inserts and updates of records with a TTL
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+/// A change event that couldn't be delivered to a subscriber
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeadLetter {
+    pub id: String,
+    pub callback: Principal,
+    pub method: String,
+    pub event: ChangeEvent,
+    /// Why the latest delivery attempt failed
+    pub reason: String,
+    /// Delivery attempts made so far, including the original one
+    pub attempts: u32,
+    pub failed_at: u64,
+    /// When the timer retries next; `None` once automatic retries are exhausted
+    pub next_retry_at: Option<u64>,
+}
+
+impl_storable!(DeadLetter);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+pub struct ChangeFeed;
+
+impl ChangeFeed {
+    /// Register a callback method, re-enabling it if previously disabled
+    pub fn subscribe(callback: Principal, method: String) {
+        let subscriber = Subscriber {
+            callback,
+            method: method.clone(),
+            consecutive_failures: 0,
+            active: true,
+        };
+
+        SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow_mut().insert(subscriber_key(&callback, &method), subscriber);
+        });
+    }
+
+    /// Remove a callback method and its dead letters, returning whether it was registered
+    pub fn unsubscribe(callback: Principal, method: &str) -> bool {
+        let removed = SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow_mut().remove(&subscriber_key(&callback, method)).is_some()
+        });
+
+        DEAD_LETTERS.with(|letters| {
+            let mut letters = letters.borrow_mut();
+            let ids: Vec<String> = letters.iter()
+                .filter(|(_, letter)| letter.callback == callback && letter.method == method)
+                .map(|(id, _)| id)
+                .collect();
+            for id in ids {
+                letters.remove(&id);
+            }
+        });
+        removed
+    }
+
+    /// Version a change to a record and deliver it to every active subscriber
+    ///
+    /// Publish after setting the record's expiry, which the event carries.
+    /// Replicas don't issue versions of their own; their events carry the
+    /// version last copied from the primary.
+    pub fn publish(op: ChangeOperation, record_id: &str, record: Option<serde_json::Value>) {
+        let version = if Replication::is_replica() {
+            Storage::record_version(record_id).unwrap_or(0)
+        } else {
+            Storage::next_record_version(record_id, op == ChangeOperation::Delete)
+        };
+        let expires_at = match op {
+            ChangeOperation::Delete => None,
+            _ => Storage::expiry(record_id),
+        };
+
+        Self::forward(ChangeEvent {
+            op,
+            record_id: record_id.to_string(),
+            record,
+            timestamp: ic_cdk::api::time(),
+            version,
+            expires_at,
+        });
+    }
+
+    /// Deliver an event to every active subscriber
+    ///
+    /// Calls run after the current message commits; a subscriber is disabled
+    /// after `MAX_CONSECUTIVE_FAILURES` failed deliveries in a row. Failed
+    /// deliveries, and events for disabled subscribers, become dead letters.
+    /// Subscribed aggregators are sent an invalidation notice as well.
+    pub fn forward(event: ChangeEvent) {
+        Invalidation::notify(&event.op, &event.record_id, event.record.as_ref());
+
+        let (subscribers, disabled): (Vec<Subscriber>, Vec<Subscriber>) = SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow().iter()
+                .map(|(_, subscriber)| subscriber)
+                .partition(|subscriber| subscriber.active)
+        });
+
+        if subscribers.is_empty() && disabled.is_empty() {
+            return;
+        }
+
+        for subscriber in disabled {
+            Self::add_dead_letter(&subscriber, event.clone(), "Subscriber disabled".to_string());
+        }
+
+        for subscriber in subscribers {
+            let event = event.clone();
+            ic_cdk::spawn(async move {
+                let result = Self::deliver(&subscriber.callback, &subscriber.method, &event).await;
+                Self::record_delivery(&subscriber, result.is_ok());
+                if let Err(reason) = result {
+                    Self::add_dead_letter(&subscriber, event, reason);
+                }
+            });
+        }
+    }
+
+    async fn deliver(callback: &Principal, method: &str, event: &ChangeEvent) -> Result<(), String> {
+        let result: Result<(), _> = ic_cdk::call(*callback, method, (event.clone(),)).await;
+        result.map_err(|(code, msg)| format!("{:?}: {}", code, msg))
+    }
+
+    /// Store an undeliverable event, dropping the oldest dead letter when full
+    fn add_dead_letter(subscriber: &Subscriber, event: ChangeEvent, reason: String) {
+        let now = ic_cdk::api::time();
+        let sequence = DEAD_LETTER_SEQUENCE.with(|sequence| {
+            let next = sequence.get();
+            sequence.set(next.wrapping_add(1));
+            next
+        });
+        let id = format!("dead_{:020}_{}", now, sequence);
+
+        let letter = DeadLetter {
+            id: id.clone(),
+            callback: subscriber.callback,
+            method: subscriber.method.clone(),
+            event,
+            reason,
+            attempts: 1,
+            failed_at: now,
+            next_retry_at: Some(now + RETRY_BASE_DELAY_NANOS),
+        };
+
+        DEAD_LETTERS.with(|letters| {
+            let mut letters = letters.borrow_mut();
+            letters.insert(id, letter);
+
+            while letters.len() > MAX_DEAD_LETTERS {
+                let oldest = match letters.iter().next() {
+                    Some((oldest, _)) => oldest,
+                    None => break,
+                };
+                ic_cdk::println!("Dead letter store full; dropping {}", oldest);
+                letters.remove(&oldest);
+            }
+        });
+    }
+
+    /// All dead letters, oldest first
+    pub fn dead_letters() -> Vec<DeadLetter> {
+        DEAD_LETTERS.with(|letters| {
+            letters.borrow().iter().map(|(_, letter)| letter).collect()
+        })
+    }
+
+    /// Retry a dead letter now, whether or not it is due or its subscriber active
+    ///
+    /// The dead letter is removed on success and updated with the new failure
+    /// otherwise.
+    pub async fn redeliver(id: &str) -> Result<(), String> {
+        let letter = DEAD_LETTERS.with(|letters| letters.borrow().get(&id.to_string()))
+            .ok_or_else(|| format!("No dead letter {}", id))?;
+
+        Self::retry(letter).await
+    }
+
+    /// Start retries of the dead letters that are due and whose subscriber is active
+    pub fn retry_due(now: u64) {
+        let due: Vec<DeadLetter> = DEAD_LETTERS.with(|letters| {
+            letters.borrow().iter()
+                .map(|(_, letter)| letter)
+                .filter(|letter| letter.next_retry_at.map_or(false, |at| at <= now))
+                .filter(|letter| Self::is_active(&letter.callback, &letter.method))
+                .take(RETRY_BATCH_SIZE)
+                .collect()
+        });
+
+        for mut letter in due {
+            // Hold the letter back while the retry is in flight
+            letter.next_retry_at = None;
+            DEAD_LETTERS.with(|letters| letters.borrow_mut().insert(letter.id.clone(), letter.clone()));
+
+            ic_cdk::spawn(async move {
+                let _ = Self::retry(letter).await;
+            });
+        }
+    }
+
+    async fn retry(mut letter: DeadLetter) -> Result<(), String> {
+        let result = Self::deliver(&letter.callback, &letter.method, &letter.event).await;
+        let subscriber = SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow().get(&subscriber_key(&letter.callback, &letter.method))
+        });
+        if let Some(subscriber) = &subscriber {
+            Self::record_delivery(subscriber, result.is_ok());
+        }
+
+        DEAD_LETTERS.with(|letters| {
+            let mut letters = letters.borrow_mut();
+            match &result {
+                Ok(()) => {
+                    letters.remove(&letter.id);
+                },
+                Err(reason) => {
+                    let now = ic_cdk::api::time();
+                    letter.reason = reason.clone();
+                    letter.attempts += 1;
+                    letter.failed_at = now;
+                    letter.next_retry_at = if letter.attempts <= MAX_AUTOMATIC_RETRIES {
+                        let backoff = RETRY_BASE_DELAY_NANOS.saturating_mul(1u64 << (letter.attempts - 1).min(16));
+                        Some(now.saturating_add(backoff))
+                    } else {
+                        None
+                    };
+                    letters.insert(letter.id.clone(), letter);
+                },
+            }
+        });
+        result
+    }
+
+    fn is_active(callback: &Principal, method: &str) -> bool {
+        SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow().get(&subscriber_key(callback, method))
+                .map_or(false, |subscriber| subscriber.active)
+        })
+    }
+
+    /// Update a subscriber's failure count after a delivery attempt
+    fn record_delivery(subscriber: &Subscriber, delivered: bool) {
+        let key = subscriber_key(&subscriber.callback, &subscriber.method);
+
+        SUBSCRIBERS.with(|subscribers| {
+            let mut subscribers_ref = subscribers.borrow_mut();
+            if let Some(mut current) = subscribers_ref.get(&key) {
+                if delivered {
+                    current.consecutive_failures = 0;
+                } else {
+                    current.consecutive_failures += 1;
+                    if current.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                        ic_cdk::println!("Disabling subscriber {} after {} failed deliveries",
+                                        key, current.consecutive_failures);
+                        current.active = false;
+                    }
+                }
+                subscribers_ref.insert(key, current);
+            }
+        });
+    }
+}
+
+fn subscriber_key(callback: &Principal, method: &str) -> String {
+    format!("{}:{}", callback, method)
+}
@@ -0,0 +1,98 @@
+//! Optional LZ4 compression of stored record blobs
+
+use std::borrow::Cow;
+
+/// Leading byte of a compressed blob. Uncompressed blobs are plain JSON, which
+/// never starts with this byte, so records written before compression was
+/// enabled still read back unchanged.
+const COMPRESSED_TAG: u8 = 0x01;
+
+/// Blobs smaller than this are stored as-is; LZ4 framing outweighs any saving
+const MIN_COMPRESSIBLE_BYTES: usize = 64;
+
+pub struct Compression;
+
+impl Compression {
+    /// Encode a record for storage, compressing only when it actually saves space
+    pub fn encode(data: Vec<u8>, enabled: bool) -> Vec<u8> {
+        if !enabled || data.len() < MIN_COMPRESSIBLE_BYTES {
+            return data;
+        }
+
+        let compressed = lz4_flex::compress_prepend_size(&data);
+        if compressed.len() + 1 >= data.len() {
+            return data;
+        }
+
+        let mut blob = Vec::with_capacity(compressed.len() + 1);
+        blob.push(COMPRESSED_TAG);
+        blob.extend_from_slice(&compressed);
+        blob
+    }
+
+    /// Recover the record bytes from a stored blob, `None` if it is corrupt
+    pub fn decode(blob: &[u8]) -> Option<Cow<'_, [u8]>> {
+        match blob.split_first() {
+            Some((&COMPRESSED_TAG, compressed)) => {
+                lz4_flex::decompress_size_prepended(compressed).ok().map(Cow::Owned)
+            },
+            _ => Some(Cow::Borrowed(blob)),
+        }
+    }
+
+    /// Size of the record a blob decodes to, without decompressing it
+    pub fn decoded_len(blob: &[u8]) -> usize {
+        match blob.split_first() {
+            Some((&COMPRESSED_TAG, compressed)) if compressed.len() >= 4 => {
+                u32::from_le_bytes([compressed[0], compressed[1], compressed[2], compressed[3]]) as usize
+            },
+            _ => blob.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repetitive_record() -> Vec<u8> {
+        serde_json::json!({"name": "widget", "description": "abc ".repeat(64)}).to_string().into_bytes()
+    }
+
+    #[test]
+    fn compressed_blobs_round_trip() {
+        let record = repetitive_record();
+        let blob = Compression::encode(record.clone(), true);
+
+        assert_eq!(blob[0], COMPRESSED_TAG);
+        assert!(blob.len() < record.len());
+        assert_eq!(Compression::decoded_len(&blob), record.len());
+        assert_eq!(Compression::decode(&blob).unwrap().as_ref(), record.as_slice());
+    }
+
+    #[test]
+    fn legacy_uncompressed_blobs_still_read() {
+        let record = repetitive_record();
+        let blob = Compression::encode(record.clone(), false);
+
+        assert_eq!(blob, record);
+        assert_eq!(Compression::decoded_len(&blob), record.len());
+        assert!(matches!(Compression::decode(&blob), Some(Cow::Borrowed(bytes)) if bytes == record.as_slice()));
+    }
+
+    #[test]
+    fn blobs_are_left_alone_when_compression_would_not_help() {
+        let small = br#"{"name":"a"}"#.to_vec();
+        assert_eq!(Compression::encode(small.clone(), true), small);
+
+        let random: Vec<u8> = (0..256u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        assert_eq!(Compression::encode(random.clone(), true), random);
+    }
+
+    #[test]
+    fn corrupt_compressed_blobs_decode_to_none() {
+        let mut blob = Compression::encode(repetitive_record(), true);
+        blob.truncate(blob.len() / 2);
+        assert!(Compression::decode(&blob).is_none());
+    }
+}
@@ -0,0 +1,116 @@
+//! CSV parsing and rendering for bulk import and export
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How CSV columns map onto record fields
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ColumnMapping {
+    /// Column name to record field; unlisted columns are ignored. Without a
+    /// header row, columns are named by their 0-based position ("0", "1", ...).
+    pub fields: Vec<(String, String)>,
+    /// Column holding the record ID; IDs follow the cell's `id_strategy` otherwise
+    pub id_column: Option<String>,
+    pub has_header: bool,
+}
+
+pub struct Csv;
+
+impl Csv {
+    /// Split CSV text into rows of fields
+    ///
+    /// Fields may be quoted, with `""` for a literal quote, and quoted fields
+    /// may contain commas and line breaks. Rows end with `\n` or `\r\n`; blank
+    /// lines are skipped.
+    pub fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut field_started = false;
+        let mut chars = text.chars().peekable();
+        let mut line = 1;
+
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    },
+                    '"' => in_quotes = false,
+                    '\n' => {
+                        line += 1;
+                        field.push(c);
+                    },
+                    _ => field.push(c),
+                }
+                continue;
+            }
+
+            match c {
+                '"' if !field_started => {
+                    in_quotes = true;
+                    field_started = true;
+                },
+                '"' => return Err(format!("Line {}: unexpected quote inside unquoted field", line)),
+                ',' => {
+                    row.push(std::mem::take(&mut field));
+                    field_started = false;
+                },
+                '\r' if chars.peek() == Some(&'\n') => {},
+                '\n' => {
+                    line += 1;
+                    Self::end_row(&mut rows, &mut row, &mut field, field_started);
+                    field_started = false;
+                },
+                _ => {
+                    field.push(c);
+                    field_started = true;
+                },
+            }
+        }
+
+        if in_quotes {
+            return Err(format!("Line {}: unterminated quoted field", line));
+        }
+        Self::end_row(&mut rows, &mut row, &mut field, field_started);
+
+        Ok(rows)
+    }
+
+    fn end_row(rows: &mut Vec<Vec<String>>, row: &mut Vec<String>, field: &mut String, field_started: bool) {
+        if row.is_empty() && !field_started && field.is_empty() {
+            return;
+        }
+        row.push(std::mem::take(field));
+        rows.push(std::mem::take(row));
+    }
+
+    /// Append one row, quoting fields that contain commas, quotes or line breaks
+    pub fn write_row(out: &mut String, fields: &[String]) {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+                out.push('"');
+                out.push_str(&field.replace('"', "\"\""));
+                out.push('"');
+            } else {
+                out.push_str(field);
+            }
+        }
+        out.push_str("\r\n");
+    }
+
+    /// CSV text of a field value: strings as-is, null as empty, other values as JSON
+    pub fn format_value(value: Option<&Value>) -> String {
+        match value {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! `Custom` validation rules delegated to an external validator canister
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::schema::SchemaDefinition;
+use crate::settings::Settings;
+use crate::validation::{CustomCheck, ValidationError, ValidationMode, Validator};
+
+/// How long a validator verdict is reused for the same rule and value
+const VERDICT_TTL_NANOS: u64 = 30 * 1_000_000_000;
+
+/// Upper bound on cached verdicts before the cache is cleared
+const MAX_CACHED_VERDICTS: usize = 10_000;
+
+thread_local! {
+    static VERDICTS: RefCell<HashMap<(String, String), CachedVerdict>> = RefCell::new(HashMap::new());
+}
+
+/// Reply of the validator canister's `validate_custom(rule, value_json)` method
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CustomValidationVerdict {
+    pub valid: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct CachedVerdict {
+    verdict: CustomValidationVerdict,
+    expires_at: u64,
+}
+
+pub struct CustomValidation;
+
+impl CustomValidation {
+    /// Evaluate the `Custom` rules that apply to `data`
+    ///
+    /// Each rule is sent to the configured validator canister as its name and
+    /// the field value encoded as JSON. Verdicts are cached briefly. When the
+    /// validator is missing or unreachable the record is rejected, unless the
+    /// cell is configured to fail open.
+    pub async fn validate(schema: &SchemaDefinition, data: &serde_json::Value, mode: ValidationMode) -> Result<(), Vec<ValidationError>> {
+        let checks = Validator::custom_checks(schema, data);
+        if checks.is_empty() {
+            return Ok(());
+        }
+
+        let settings = Settings::get();
+        let mut errors = Vec::new();
+
+        for check in checks {
+            if mode == ValidationMode::FailFast && !errors.is_empty() {
+                break;
+            }
+
+            let verdict = match settings.custom_validator {
+                Some(validator) => Self::verdict(validator, &check).await,
+                None => Err("no custom validator configured".to_string()),
+            };
+
+            match verdict {
+                Ok(verdict) if verdict.valid => {},
+                Ok(verdict) => errors.push(ValidationError::ValidationFailed(format!(
+                    "{}: rejected by custom rule '{}'{}",
+                    check.path,
+                    check.name,
+                    verdict.message.map(|m| format!(": {}", m)).unwrap_or_default(),
+                ))),
+                Err(_) if settings.custom_validator_fail_open => {},
+                Err(reason) => errors.push(ValidationError::ValidationFailed(format!(
+                    "{}: custom rule '{}' could not be checked: {}",
+                    check.path, check.name, reason,
+                ))),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Cached or freshly fetched verdict for one check; call failures are never cached
+    async fn verdict(validator: Principal, check: &CustomCheck) -> Result<CustomValidationVerdict, String> {
+        let key = (check.name.clone(), check.value.to_string());
+        let now = ic_cdk::api::time();
+
+        let cached = VERDICTS.with(|verdicts| {
+            verdicts.borrow().get(&key)
+                .filter(|cached| cached.expires_at > now)
+                .map(|cached| cached.verdict.clone())
+        });
+        if let Some(verdict) = cached {
+            return Ok(verdict);
+        }
+
+        let (verdict,): (CustomValidationVerdict,) =
+            ic_cdk::call(validator, "validate_custom", (key.0.clone(), key.1.clone()))
+                .await
+                .map_err(|(code, msg)| format!("validator call failed: {:?} - {}", code, msg))?;
+
+        VERDICTS.with(|verdicts| {
+            let mut verdicts = verdicts.borrow_mut();
+            if verdicts.len() >= MAX_CACHED_VERDICTS {
+                verdicts.retain(|_, cached| cached.expires_at > now);
+                if verdicts.len() >= MAX_CACHED_VERDICTS {
+                    verdicts.clear();
+                }
+            }
+            verdicts.insert(key, CachedVerdict {
+                verdict: verdict.clone(),
+                expires_at: ic_cdk::api::time() + VERDICT_TTL_NANOS,
+            });
+        });
+
+        Ok(verdict)
+    }
+}
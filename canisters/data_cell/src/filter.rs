@@ -0,0 +1,765 @@
+//! Filter evaluation for Data Cell queries
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use crate::schema::{FieldType, SchemaDefinition};
+use crate::{CellError, ComparisonOperator, FilterCondition, FilterExpr, QueryFilter, SortOrder};
+
+/// Maximum accepted length of a `Matches` pattern
+pub(crate) const MAX_PATTERN_LENGTH: usize = 256;
+
+/// Maximum compiled size of a `Matches` pattern, guarding against pathological regexes
+pub(crate) const MAX_REGEX_SIZE: usize = 64 * 1024;
+
+/// Filter condition prepared once per query and evaluated against many records
+pub struct CompiledCondition<'a> {
+    condition: &'a FilterCondition,
+    value_set: Option<HashSet<String>>,
+    folded_operand: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl CompiledCondition<'_> {
+    /// String operand of the condition, lowercased for case-insensitive matching
+    fn string_operand(&self) -> Option<&str> {
+        self.folded_operand.as_deref().or_else(|| self.condition.value.as_str())
+    }
+
+    /// Normalize a record's string value the same way as the operand
+    fn fold<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        if self.condition.case_insensitive {
+            Cow::Owned(s.to_lowercase())
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+}
+
+/// Filter expression tree with every leaf condition compiled
+pub enum CompiledExpr<'a> {
+    And(Vec<CompiledExpr<'a>>),
+    Or(Vec<CompiledExpr<'a>>),
+    Condition(CompiledCondition<'a>),
+}
+
+pub struct FilterEvaluator;
+
+impl FilterEvaluator {
+    /// Prepare a query filter for evaluation
+    ///
+    /// The flat `conditions` list is treated as an implicit AND alongside the
+    /// optional `expression` tree. Conditions whose operator doesn't apply to
+    /// the field's schema type are rejected.
+    pub fn compile<'a>(filter: &'a QueryFilter, schema: &SchemaDefinition) -> Result<CompiledExpr<'a>, CellError> {
+        let mut clauses = filter.conditions.iter()
+            .map(|condition| Self::compile_condition(condition, schema).map(CompiledExpr::Condition))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(expression) = &filter.expression {
+            clauses.push(Self::compile_expr(expression, schema)?);
+        }
+
+        Ok(CompiledExpr::And(clauses))
+    }
+
+    fn compile_expr<'a>(expr: &'a FilterExpr, schema: &SchemaDefinition) -> Result<CompiledExpr<'a>, CellError> {
+        match expr {
+            FilterExpr::And(children) => Ok(CompiledExpr::And(
+                children.iter().map(|child| Self::compile_expr(child, schema)).collect::<Result<_, _>>()?
+            )),
+            FilterExpr::Or(children) => Ok(CompiledExpr::Or(
+                children.iter().map(|child| Self::compile_expr(child, schema)).collect::<Result<_, _>>()?
+            )),
+            FilterExpr::Condition(condition) => Ok(CompiledExpr::Condition(Self::compile_condition(condition, schema)?)),
+        }
+    }
+
+    fn compile_condition<'a>(condition: &'a FilterCondition, schema: &SchemaDefinition) -> Result<CompiledCondition<'a>, CellError> {
+        if let Some(field_def) = schema.fields.get(&condition.field) {
+            Self::check_operator_type(condition, &field_def.field_type)?;
+        }
+
+        let value_set = match condition.operator {
+            ComparisonOperator::In | ComparisonOperator::NotIn => {
+                let values = condition.value.as_array().ok_or_else(|| CellError::ValidationError(
+                    format!("Operator {:?} on field '{}' requires an array value", condition.operator, condition.field)
+                ))?;
+
+                Some(values.iter().map(Self::membership_key).collect())
+            },
+            _ => None,
+        };
+
+        let folded_operand = if condition.case_insensitive {
+            condition.value.as_str().map(str::to_lowercase)
+        } else {
+            None
+        };
+
+        let regex = match condition.operator {
+            ComparisonOperator::Matches => Some(Self::compile_regex(condition)?),
+            _ => None,
+        };
+
+        Ok(CompiledCondition { condition, value_set, folded_operand, regex })
+    }
+
+    /// Check that a condition's operator and operand suit the field's schema type
+    ///
+    /// - `GreaterThan`/`LessThan` order numbers and timestamps numerically and
+    ///   text lexicographically, and need an operand of the same kind
+    /// - `Contains` is a substring test on text and an element test on arrays
+    /// - `StartsWith`/`EndsWith`/`Matches` apply to text and principals only
+    /// - equality, membership and null checks apply to every type
+    fn check_operator_type(condition: &FilterCondition, field_type: &FieldType) -> Result<(), CellError> {
+        let compatible = match condition.operator {
+            ComparisonOperator::GreaterThan | ComparisonOperator::LessThan => match field_type {
+                FieldType::Number | FieldType::Timestamp => condition.value.is_number(),
+                FieldType::Text => condition.value.is_string(),
+                _ => false,
+            },
+            ComparisonOperator::Contains => match field_type {
+                FieldType::Text => condition.value.is_string(),
+                FieldType::Array(_) => true,
+                _ => false,
+            },
+            ComparisonOperator::StartsWith | ComparisonOperator::EndsWith | ComparisonOperator::Matches => {
+                matches!(field_type, FieldType::Text | FieldType::Principal) && condition.value.is_string()
+            },
+            ComparisonOperator::Equals | ComparisonOperator::NotEquals
+            | ComparisonOperator::In | ComparisonOperator::NotIn
+            | ComparisonOperator::IsNull | ComparisonOperator::IsNotNull => true,
+        };
+
+        if compatible {
+            Ok(())
+        } else {
+            Err(CellError::ValidationError(format!(
+                "Operator {:?} with operand {} is not supported on field '{}' of type {:?}",
+                condition.operator, condition.value, condition.field, field_type
+            )))
+        }
+    }
+
+    /// Compile a `Matches` pattern once per query
+    fn compile_regex(condition: &FilterCondition) -> Result<Regex, CellError> {
+        let pattern = condition.value.as_str().ok_or_else(|| CellError::ValidationError(
+            format!("Operator Matches on field '{}' requires a string pattern", condition.field)
+        ))?;
+
+        if pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(CellError::ValidationError(
+                format!("Pattern too long, maximum length: {}", MAX_PATTERN_LENGTH)
+            ));
+        }
+
+        RegexBuilder::new(pattern)
+            .case_insensitive(condition.case_insensitive)
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|e| CellError::ValidationError(format!("Invalid pattern '{}': {}", pattern, e)))
+    }
+
+    /// Check if a record satisfies a compiled filter expression
+    ///
+    /// An empty `And` matches every record; an empty `Or` matches none.
+    pub fn matches(record: &Value, expr: &CompiledExpr) -> Result<bool, CellError> {
+        match expr {
+            CompiledExpr::And(children) => {
+                for child in children {
+                    if !Self::matches(record, child)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+            CompiledExpr::Or(children) => {
+                for child in children {
+                    if Self::matches(record, child)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+            CompiledExpr::Condition(compiled) => Self::matches_condition(record, compiled),
+        }
+    }
+
+    /// Check if a record satisfies a single compiled condition
+    fn matches_condition(record: &Value, compiled: &CompiledCondition) -> Result<bool, CellError> {
+        let condition = compiled.condition;
+        let field_value = record.get(&condition.field);
+
+        let matched = match (&condition.operator, field_value) {
+            (ComparisonOperator::IsNull, value) => value.map_or(true, Value::is_null),
+            (ComparisonOperator::IsNotNull, value) => value.map_or(false, |v| !v.is_null()),
+            (ComparisonOperator::NotEquals, None) => true,
+            (ComparisonOperator::NotIn, None) => true,
+            (_, None) => false,
+            (ComparisonOperator::Equals, Some(value)) => {
+                match (value.as_str(), compiled.string_operand()) {
+                    (Some(s), Some(operand)) => compiled.fold(s) == operand,
+                    _ => values_equal(value, &condition.value),
+                }
+            },
+            (ComparisonOperator::NotEquals, Some(value)) => !values_equal(value, &condition.value),
+            (ComparisonOperator::GreaterThan, Some(value)) => {
+                Self::compare_ordered(condition, value)? == Some(Ordering::Greater)
+            },
+            (ComparisonOperator::LessThan, Some(value)) => {
+                Self::compare_ordered(condition, value)? == Some(Ordering::Less)
+            },
+            (ComparisonOperator::Contains, Some(Value::String(s))) => {
+                compiled.string_operand().map_or(false, |needle| compiled.fold(s).contains(needle))
+            },
+            (ComparisonOperator::Contains, Some(Value::Array(items))) => items.contains(&condition.value),
+            (ComparisonOperator::Contains, Some(_)) => false,
+            (ComparisonOperator::StartsWith, Some(value)) => {
+                match (value.as_str(), compiled.string_operand()) {
+                    (Some(s), Some(prefix)) => compiled.fold(s).starts_with(prefix),
+                    _ => false,
+                }
+            },
+            (ComparisonOperator::EndsWith, Some(value)) => {
+                match (value.as_str(), compiled.string_operand()) {
+                    (Some(s), Some(suffix)) => compiled.fold(s).ends_with(suffix),
+                    _ => false,
+                }
+            },
+            (ComparisonOperator::Matches, Some(value)) => {
+                match (value.as_str(), &compiled.regex) {
+                    (Some(s), Some(regex)) => regex.is_match(s),
+                    _ => false,
+                }
+            },
+            (ComparisonOperator::In, Some(value)) => Self::is_member(compiled, value),
+            (ComparisonOperator::NotIn, Some(value)) => !Self::is_member(compiled, value),
+        };
+
+        Ok(matched)
+    }
+
+    /// Compare a record value against a `GreaterThan`/`LessThan` operand
+    ///
+    /// A numeric operand only compares against numbers; any other non-null
+    /// field value is rejected rather than silently not matching.
+    fn compare_ordered(condition: &FilterCondition, value: &Value) -> Result<Option<Ordering>, CellError> {
+        match (value, &condition.value) {
+            (Value::Null, _) => Ok(None),
+            (Value::Number(_), Value::Number(_)) => Ok(Self::compare_values(value, &condition.value)),
+            (_, Value::Number(_)) => Err(CellError::ValidationError(format!(
+                "Operator {:?} compares numbers, but field '{}' holds {}",
+                condition.operator, condition.field, value
+            ))),
+            _ => Ok(Self::compare_values(value, &condition.value)),
+        }
+    }
+
+    fn is_member(compiled: &CompiledCondition, value: &Value) -> bool {
+        compiled.value_set.as_ref()
+            .map_or(false, |set| set.contains(&Self::membership_key(value)))
+    }
+
+    /// Hash key for set membership, normalizing numbers so `1` and `1.0` match
+    ///
+    /// Integers, and floats with an integral value, are keyed exactly, so
+    /// distinct `u64` IDs above 2^53 don't collide through `f64`.
+    fn membership_key(value: &Value) -> String {
+        match value {
+            Value::Number(n) => match integer(n).or_else(|| n.as_f64().and_then(integral_float)) {
+                Some(i) => i.to_string(),
+                None => n.as_f64().map(|f| f.to_string()).unwrap_or_else(|| n.to_string()),
+            },
+            other => other.to_string(),
+        }
+    }
+
+    /// Sort `(record_id, record)` pairs into query order
+    ///
+    /// Records are ordered by `sort_field` (records missing it last) and then by
+    /// record ID, or by record ID alone when no sort field is given.
+    pub fn sort_records(records: &mut [(String, Value)], sort_field: Option<&str>, sort_order: &SortOrder) {
+        records.sort_by(|(a_id, a), (b_id, b)| {
+            let a_key = sort_field.and_then(|field| a.get(field));
+            let b_key = sort_field.and_then(|field| b.get(field));
+            Self::compare_positions(a_key, a_id, b_key, b_id, sort_order)
+        });
+    }
+
+    /// Compare two records' positions in query order
+    pub fn compare_positions(
+        a_key: Option<&Value>,
+        a_id: &str,
+        b_key: Option<&Value>,
+        b_id: &str,
+        sort_order: &SortOrder,
+    ) -> Ordering {
+        let ordering = match (a_key, b_key) {
+            (Some(x), Some(y)) => Self::compare_sort_values(x, y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+
+        let ordering = match sort_order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        };
+
+        ordering.then_with(|| a_id.cmp(b_id))
+    }
+
+    /// Total order over sort values, matching the index's `encode_sort_key`
+    ///
+    /// Values rank by type first (null < bool < number < string < other) and
+    /// then by value; arrays and objects compare by their JSON text.
+    pub fn compare_sort_values(a: &Value, b: &Value) -> Ordering {
+        Self::type_rank(a).cmp(&Self::type_rank(b)).then_with(|| {
+            Self::compare_values(a, b).unwrap_or_else(|| a.to_string().cmp(&b.to_string()))
+        })
+    }
+
+    fn type_rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) | Value::Object(_) => 4,
+        }
+    }
+
+    /// Compare two JSON values of the same kind, returning `None` when incomparable
+    pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => compare_numbers(x, y),
+            (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+            (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+/// Integer value of a JSON number, if it has one
+fn integer(n: &Number) -> Option<i128> {
+    n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from))
+}
+
+/// Compare two JSON numbers exactly
+///
+/// Integers compare as integers, so large `u64` values don't lose precision
+/// through `f64`. An integer against a float compares by value, falling back
+/// to integer comparison when both round to the same `f64` (where the float
+/// is necessarily integral). `None` only for NaN.
+fn compare_numbers(x: &Number, y: &Number) -> Option<Ordering> {
+    match (integer(x), integer(y)) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        (Some(a), None) => compare_integer_float(a, y.as_f64()?),
+        (None, Some(b)) => compare_integer_float(b, x.as_f64()?).map(Ordering::reverse),
+        (None, None) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+    }
+}
+
+/// JSON equality, except numbers compare by value so `2.0` equals `2`
+pub(crate) fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => compare_numbers(x, y) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+/// Exact integer value of an integral float within `i128` range
+fn integral_float(f: f64) -> Option<i128> {
+    (f.fract() == 0.0 && f.abs() < i128::MAX as f64).then(|| f as i128)
+}
+
+fn compare_integer_float(a: i128, f: f64) -> Option<Ordering> {
+    match (a as f64).partial_cmp(&f)? {
+        Ordering::Equal => Some(a.cmp(&(f as i128))),
+        ordering => Some(ordering),
+    }
+}
+
+/// Resume point for cursor-based pagination: the last record returned
+#[derive(Serialize, Deserialize)]
+pub struct QueryCursor {
+    pub sort_value: Option<Value>,
+    pub record_id: String,
+}
+
+impl QueryCursor {
+    /// Encode as an opaque cursor string
+    pub fn encode(&self) -> String {
+        serde_json::to_vec(self).map(hex_encode).unwrap_or_default()
+    }
+
+    /// Decode a cursor string produced by `encode`
+    pub fn decode(cursor: &str) -> Result<Self, CellError> {
+        hex_decode(cursor)
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| CellError::ValidationError("Invalid pagination cursor".to_string()))
+    }
+}
+
+fn hex_encode(bytes: Vec<u8>) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FieldDefinition;
+    use serde_json::json;
+
+    fn field(field_type: FieldType) -> FieldDefinition {
+        FieldDefinition {
+            field_type,
+            required: false,
+            default_value: None,
+            validation_rules: Vec::new(),
+            coerce: None,
+            auto_timestamp: None,
+        }
+    }
+
+    fn schema() -> SchemaDefinition {
+        SchemaDefinition {
+            version: 1,
+            name: "items".to_string(),
+            fields: [
+                ("name", FieldType::Text),
+                ("status", FieldType::Text),
+                ("score", FieldType::Number),
+                ("id", FieldType::Number),
+                ("tags", FieldType::Array(Box::new(FieldType::Text))),
+            ].into_iter().map(|(name, field_type)| (name.to_string(), field(field_type))).collect(),
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    fn condition(field: &str, operator: ComparisonOperator, value: Value) -> FilterCondition {
+        FilterCondition { field: field.to_string(), operator, value, case_insensitive: false }
+    }
+
+    fn filter(conditions: Vec<FilterCondition>) -> QueryFilter {
+        QueryFilter {
+            conditions,
+            expression: None,
+            sort_by: None,
+            sort_order: SortOrder::Ascending,
+            since: None,
+            trace_id: None,
+        }
+    }
+
+    /// Names of the records matching `filter`, in input order
+    fn matching(records: &[Value], filter: &QueryFilter) -> Result<Vec<String>, CellError> {
+        let expr = FilterEvaluator::compile(filter, &schema())?;
+        let mut names = Vec::new();
+        for record in records {
+            if FilterEvaluator::matches(record, &expr)? {
+                names.push(record["name"].as_str().unwrap_or_default().to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    #[test]
+    fn in_and_not_in_match_string_membership() {
+        let records = [
+            json!({"name": "a", "status": "open"}),
+            json!({"name": "b", "status": "closed"}),
+            json!({"name": "c", "status": "draft"}),
+            json!({"name": "d"}),
+        ];
+
+        let set = json!(["open", "draft"]);
+        assert_eq!(matching(&records, &filter(vec![condition("status", ComparisonOperator::In, set.clone())])).unwrap(), ["a", "c"]);
+        assert_eq!(matching(&records, &filter(vec![condition("status", ComparisonOperator::NotIn, set)])).unwrap(), ["b", "d"]);
+    }
+
+    #[test]
+    fn in_and_not_in_match_numeric_membership() {
+        let records = [
+            json!({"name": "a", "score": 1}),
+            json!({"name": "b", "score": 2.0}),
+            json!({"name": "c", "score": 2.5}),
+            json!({"name": "d", "score": "1"}),
+        ];
+
+        let set = json!([1.0, 2, 2.5]);
+        assert_eq!(matching(&records, &filter(vec![condition("score", ComparisonOperator::In, set.clone())])).unwrap(), ["a", "b", "c"]);
+        assert_eq!(matching(&records, &filter(vec![condition("score", ComparisonOperator::NotIn, set)])).unwrap(), ["d"]);
+    }
+
+    #[test]
+    fn in_keys_large_integers_exactly() {
+        let records = [
+            json!({"name": "a", "id": 9_007_199_254_740_992_u64}),
+            json!({"name": "b", "id": 9_007_199_254_740_993_u64}),
+            json!({"name": "c", "id": u64::MAX}),
+        ];
+
+        let set = json!([9_007_199_254_740_993_u64, u64::MAX]);
+        assert_eq!(matching(&records, &filter(vec![condition("id", ComparisonOperator::In, set)])).unwrap(), ["b", "c"]);
+    }
+
+    #[test]
+    fn in_rejects_non_array_values() {
+        let result = matching(&[], &filter(vec![condition("status", ComparisonOperator::In, json!("open"))]));
+        assert!(matches!(result, Err(CellError::ValidationError(_))));
+    }
+
+    #[test]
+    fn expressions_nest_or_and_and() {
+        let records = [
+            json!({"name": "a", "status": "open", "score": 1}),
+            json!({"name": "b", "status": "open", "score": 2}),
+            json!({"name": "c", "status": "closed", "score": 3}),
+            json!({"name": "d", "status": "closed", "score": 1}),
+        ];
+
+        // (status = open AND score = 1) OR score = 3
+        let expression = FilterExpr::Or(vec![
+            FilterExpr::And(vec![
+                FilterExpr::Condition(condition("status", ComparisonOperator::Equals, json!("open"))),
+                FilterExpr::Condition(condition("score", ComparisonOperator::Equals, json!(1))),
+            ]),
+            FilterExpr::Condition(condition("score", ComparisonOperator::Equals, json!(3))),
+        ]);
+        let query = QueryFilter { expression: Some(expression), ..filter(Vec::new()) };
+        assert_eq!(matching(&records, &query).unwrap(), ["a", "c"]);
+
+        // Flat conditions are ANDed with the expression
+        let query = QueryFilter {
+            conditions: vec![condition("status", ComparisonOperator::Equals, json!("closed"))],
+            ..query
+        };
+        assert_eq!(matching(&records, &query).unwrap(), ["c"]);
+    }
+
+    #[test]
+    fn empty_or_matches_nothing_and_empty_and_matches_everything() {
+        let records = [json!({"name": "a"})];
+
+        let query = QueryFilter { expression: Some(FilterExpr::Or(Vec::new())), ..filter(Vec::new()) };
+        assert!(matching(&records, &query).unwrap().is_empty());
+
+        let query = QueryFilter { expression: Some(FilterExpr::And(Vec::new())), ..filter(Vec::new()) };
+        assert_eq!(matching(&records, &query).unwrap(), ["a"]);
+    }
+
+    #[test]
+    fn case_insensitive_flag_folds_string_comparisons_only() {
+        let records = [
+            json!({"name": "Apple Pie", "score": 1}),
+            json!({"name": "apple tart", "score": 2}),
+            json!({"name": "Banana", "score": 3}),
+        ];
+        let insensitive = |mut condition: FilterCondition| {
+            condition.case_insensitive = true;
+            condition
+        };
+
+        let equals = condition("name", ComparisonOperator::Equals, json!("APPLE PIE"));
+        assert!(matching(&records, &filter(vec![equals.clone()])).unwrap().is_empty());
+        assert_eq!(matching(&records, &filter(vec![insensitive(equals)])).unwrap(), ["Apple Pie"]);
+
+        let starts_with = condition("name", ComparisonOperator::StartsWith, json!("apple"));
+        assert_eq!(matching(&records, &filter(vec![starts_with.clone()])).unwrap(), ["apple tart"]);
+        assert_eq!(matching(&records, &filter(vec![insensitive(starts_with)])).unwrap(), ["Apple Pie", "apple tart"]);
+
+        let contains = condition("name", ComparisonOperator::Contains, json!("NAN"));
+        assert!(matching(&records, &filter(vec![contains.clone()])).unwrap().is_empty());
+        assert_eq!(matching(&records, &filter(vec![insensitive(contains)])).unwrap(), ["Banana"]);
+
+        let number = condition("score", ComparisonOperator::Equals, json!(2));
+        assert_eq!(matching(&records, &filter(vec![insensitive(number)])).unwrap(), ["apple tart"]);
+    }
+
+    #[test]
+    fn ends_with_matches_suffixes() {
+        let records = [
+            json!({"name": "report.pdf"}),
+            json!({"name": "photo.PNG"}),
+            json!({"name": "notes.txt"}),
+        ];
+
+        let ends_with = condition("name", ComparisonOperator::EndsWith, json!(".pdf"));
+        assert_eq!(matching(&records, &filter(vec![ends_with])).unwrap(), ["report.pdf"]);
+
+        let ends_with = FilterCondition { case_insensitive: true, ..condition("name", ComparisonOperator::EndsWith, json!(".png")) };
+        assert_eq!(matching(&records, &filter(vec![ends_with])).unwrap(), ["photo.PNG"]);
+    }
+
+    #[test]
+    fn matches_applies_a_regex() {
+        let records = [
+            json!({"name": "order-17"}),
+            json!({"name": "order-x"}),
+            json!({"name": "invoice-3"}),
+        ];
+
+        let pattern = condition("name", ComparisonOperator::Matches, json!("^order-[0-9]+$"));
+        assert_eq!(matching(&records, &filter(vec![pattern])).unwrap(), ["order-17"]);
+    }
+
+    #[test]
+    fn matches_rejects_invalid_and_oversized_patterns() {
+        let invalid = condition("name", ComparisonOperator::Matches, json!("order-(["));
+        assert!(matches!(matching(&[], &filter(vec![invalid])), Err(CellError::ValidationError(_))));
+
+        let too_long = condition("name", ComparisonOperator::Matches, json!("a".repeat(MAX_PATTERN_LENGTH + 1)));
+        assert!(matches!(matching(&[], &filter(vec![too_long])), Err(CellError::ValidationError(_))));
+
+        let too_large = condition("name", ComparisonOperator::Matches, json!("(\\w{100}){100}"));
+        assert!(matches!(matching(&[], &filter(vec![too_large])), Err(CellError::ValidationError(_))));
+    }
+
+    #[test]
+    fn null_checks_distinguish_absent_null_and_present_fields() {
+        let records = [
+            json!({"name": "absent"}),
+            json!({"name": "null", "status": null}),
+            json!({"name": "present", "status": "open"}),
+            json!({"name": "empty", "status": ""}),
+        ];
+
+        let is_null = condition("status", ComparisonOperator::IsNull, Value::Null);
+        assert_eq!(matching(&records, &filter(vec![is_null])).unwrap(), ["absent", "null"]);
+
+        let is_not_null = condition("status", ComparisonOperator::IsNotNull, Value::Null);
+        assert_eq!(matching(&records, &filter(vec![is_not_null])).unwrap(), ["present", "empty"]);
+    }
+
+    #[test]
+    fn range_operators_compare_floats_and_integers_by_value() {
+        let records = [
+            json!({"name": "a", "score": 1}),
+            json!({"name": "b", "score": 1.5}),
+            json!({"name": "c", "score": 2}),
+            json!({"name": "d", "score": -0.5}),
+        ];
+
+        let above = condition("score", ComparisonOperator::GreaterThan, json!(1.0));
+        assert_eq!(matching(&records, &filter(vec![above])).unwrap(), ["b", "c"]);
+        let below = condition("score", ComparisonOperator::LessThan, json!(2));
+        assert_eq!(matching(&records, &filter(vec![below])).unwrap(), ["a", "b", "d"]);
+        let between = condition("score", ComparisonOperator::LessThan, json!(1.25));
+        assert_eq!(matching(&records, &filter(vec![between])).unwrap(), ["a", "d"]);
+    }
+
+    #[test]
+    fn range_operators_compare_large_integers_exactly() {
+        let records = [
+            json!({"name": "a", "id": 9_007_199_254_740_992_u64}),
+            json!({"name": "b", "id": 9_007_199_254_740_993_u64}),
+            json!({"name": "c", "id": u64::MAX}),
+            json!({"name": "d", "id": -1}),
+        ];
+
+        // 2^53 + 1 rounds to 2^53 as an f64, but compares above it
+        let above = condition("id", ComparisonOperator::GreaterThan, json!(9_007_199_254_740_992_u64));
+        assert_eq!(matching(&records, &filter(vec![above])).unwrap(), ["b", "c"]);
+        let below = condition("id", ComparisonOperator::LessThan, json!(u64::MAX));
+        assert_eq!(matching(&records, &filter(vec![below])).unwrap(), ["a", "b", "d"]);
+
+        // The integer equal to 2^53 as a float is ordered against its neighbours exactly
+        let float_above = condition("id", ComparisonOperator::GreaterThan, json!(9_007_199_254_740_992.0_f64));
+        assert_eq!(matching(&records, &filter(vec![float_above])).unwrap(), ["b", "c"]);
+    }
+
+    #[test]
+    fn sort_orders_mixed_types_by_type_then_value() {
+        let mut records: Vec<(String, Value)> = [
+            json!("b"), json!(2), json!(null), json!([1]), json!(true), json!("a"), json!(1.5), json!(false),
+        ].into_iter().enumerate().map(|(i, score)| (i.to_string(), json!({"score": score}))).collect();
+
+        FilterEvaluator::sort_records(&mut records, Some("score"), &SortOrder::Ascending);
+        let scores: Vec<Value> = records.iter().map(|(_, record)| record["score"].clone()).collect();
+        assert_eq!(scores, [
+            json!(null), json!(false), json!(true), json!(1.5), json!(2), json!("a"), json!("b"), json!([1]),
+        ]);
+    }
+
+    #[test]
+    fn range_operators_reject_non_numeric_values() {
+        let text_value = [json!({"name": "a", "score": "high"})];
+        let above = condition("score", ComparisonOperator::GreaterThan, json!(1));
+        assert!(matches!(matching(&text_value, &filter(vec![above.clone()])), Err(CellError::ValidationError(_))));
+
+        // Absent and null values just don't match
+        let missing = [json!({"name": "a"}), json!({"name": "b", "score": null})];
+        assert!(matching(&missing, &filter(vec![above])).unwrap().is_empty());
+
+        let text_operand = condition("score", ComparisonOperator::GreaterThan, json!("1"));
+        assert!(matches!(matching(&[], &filter(vec![text_operand])), Err(CellError::ValidationError(_))));
+    }
+
+    fn compiles(condition: FilterCondition) -> Result<(), CellError> {
+        FilterEvaluator::compile(&filter(vec![condition]), &schema()).map(|_| ())
+    }
+
+    #[test]
+    fn operators_incompatible_with_the_field_type_are_rejected() {
+        let incompatible = [
+            condition("name", ComparisonOperator::GreaterThan, json!(3)),
+            condition("score", ComparisonOperator::LessThan, json!("3")),
+            condition("tags", ComparisonOperator::GreaterThan, json!("a")),
+            condition("score", ComparisonOperator::Contains, json!(3)),
+            condition("name", ComparisonOperator::Contains, json!(3)),
+            condition("score", ComparisonOperator::StartsWith, json!("1")),
+            condition("tags", ComparisonOperator::EndsWith, json!("a")),
+            condition("score", ComparisonOperator::Matches, json!("^1")),
+        ];
+        for condition in incompatible {
+            let described = format!("{:?} on {}", condition.operator, condition.field);
+            assert!(matches!(compiles(condition), Err(CellError::ValidationError(_))), "{} was accepted", described);
+        }
+    }
+
+    #[test]
+    fn operators_compatible_with_the_field_type_are_accepted() {
+        let compatible = [
+            condition("name", ComparisonOperator::GreaterThan, json!("m")),
+            condition("score", ComparisonOperator::LessThan, json!(3.5)),
+            condition("name", ComparisonOperator::Contains, json!("x")),
+            condition("tags", ComparisonOperator::Contains, json!("a")),
+            condition("name", ComparisonOperator::StartsWith, json!("a")),
+            condition("score", ComparisonOperator::Equals, json!("anything")),
+            condition("tags", ComparisonOperator::IsNull, Value::Null),
+            // Fields outside the schema aren't type-checked
+            condition("extra", ComparisonOperator::GreaterThan, json!(1)),
+        ];
+        for condition in compatible {
+            let described = format!("{:?} on {}", condition.operator, condition.field);
+            assert!(compiles(condition).is_ok(), "{} was rejected", described);
+        }
+    }
+
+    #[test]
+    fn nested_expressions_are_type_checked() {
+        let expression = FilterExpr::Or(vec![
+            FilterExpr::Condition(condition("name", ComparisonOperator::Equals, json!("a"))),
+            FilterExpr::And(vec![FilterExpr::Condition(condition("score", ComparisonOperator::Contains, json!(1)))]),
+        ]);
+        let nested = QueryFilter { expression: Some(expression), ..filter(vec![]) };
+        assert!(matches!(FilterEvaluator::compile(&nested, &schema()), Err(CellError::ValidationError(_))));
+    }
+}
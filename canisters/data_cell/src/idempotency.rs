@@ -0,0 +1,125 @@
+//! Idempotency keys deduplicating retried writes
+//!
+//! A client retrying an update whose reply was lost passes the same key
+//! again and gets the first call's result instead of a second write. Keys
+//! are scoped to the caller and forgotten after `IDEMPOTENCY_TTL_NANOS`.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::storage::{impl_storable, memory, Memory};
+use crate::{CellError, UpsertResult};
+
+/// How long a completed call's result is kept for replays
+const IDEMPOTENCY_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// How long a call may hold its key before a retry may take it over,
+/// e.g. after the call trapped mid-way
+const PENDING_TIMEOUT_NANOS: u64 = 5 * 60 * 1_000_000_000;
+
+/// Longest accepted idempotency key
+const MAX_KEY_LENGTH: usize = 128;
+
+/// Separator between the caller and key parts of an entry key
+const KEY_SEPARATOR: char = '\0';
+
+thread_local! {
+    /// Entries keyed by `caller \0 idempotency_key`
+    static ENTRIES: RefCell<StableBTreeMap<String, IdempotencyEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(24)))
+    );
+}
+
+/// Result of a completed idempotent call
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum IdempotentResult {
+    Inserted(String),
+    Upserted(UpsertResult),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct IdempotencyEntry {
+    /// `None` while the first call is still running
+    result: Option<IdempotentResult>,
+    recorded_at: u64,
+}
+
+impl_storable!(IdempotencyEntry);
+
+pub struct Idempotency;
+
+impl Idempotency {
+    /// Claim `key` for a call, or return the result of the call that completed with it
+    ///
+    /// Fails while another call holding the key is still running.
+    pub fn begin(caller: Principal, key: &str) -> Result<Option<IdempotentResult>, CellError> {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(CellError::ValidationError(
+                format!("Idempotency keys must be 1 to {} bytes", MAX_KEY_LENGTH)
+            ));
+        }
+
+        let now = ic_cdk::api::time();
+        let entry_key = entry_key(caller, key);
+
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            match entries.get(&entry_key) {
+                Some(IdempotencyEntry { result: Some(result), recorded_at })
+                    if now.saturating_sub(recorded_at) < IDEMPOTENCY_TTL_NANOS => Ok(Some(result)),
+                Some(IdempotencyEntry { result: None, recorded_at })
+                    if now.saturating_sub(recorded_at) < PENDING_TIMEOUT_NANOS => Err(CellError::PreconditionFailed(
+                        format!("A call with idempotency key {} is still in progress", key)
+                    )),
+                _ => {
+                    entries.insert(entry_key, IdempotencyEntry { result: None, recorded_at: now });
+                    Ok(None)
+                },
+            }
+        })
+    }
+
+    /// Record the outcome of a call that claimed `key`
+    ///
+    /// Failed calls release the key so a retry runs again.
+    pub fn finish(caller: Principal, key: &str, result: Option<IdempotentResult>) {
+        let entry_key = entry_key(caller, key);
+
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            match result {
+                Some(result) => {
+                    entries.insert(entry_key, IdempotencyEntry { result: Some(result), recorded_at: ic_cdk::api::time() });
+                },
+                None => {
+                    entries.remove(&entry_key);
+                },
+            }
+        });
+    }
+
+    /// Forget expired results and abandoned claims, returning how many were removed
+    pub fn sweep(now: u64, limit: usize) -> usize {
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            let expired: Vec<String> = entries.iter()
+                .filter(|(_, entry)| {
+                    let ttl = if entry.result.is_some() { IDEMPOTENCY_TTL_NANOS } else { PENDING_TIMEOUT_NANOS };
+                    now.saturating_sub(entry.recorded_at) >= ttl
+                })
+                .map(|(key, _)| key)
+                .take(limit)
+                .collect();
+
+            for key in &expired {
+                entries.remove(key);
+            }
+            expired.len()
+        })
+    }
+}
+
+fn entry_key(caller: Principal, key: &str) -> String {
+    format!("{}{}{}", caller, KEY_SEPARATOR, key)
+}
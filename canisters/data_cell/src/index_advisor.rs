@@ -0,0 +1,138 @@
+//! Index recommendations from the fields queries filter and sort on
+//!
+//! Queries executed as update calls count, per field, how often it is used
+//! in an index-servable condition or as the sort key, and how many of those
+//! queries fell back to a full scan. Query calls can't persist the counts.
+
+use candid::CandidType;
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use crate::planner::QueryPlanner;
+use crate::storage::{impl_storable, memory, Memory, Storage};
+use crate::{ComparisonOperator, FilterCondition, FilterExpr, QueryFilter};
+
+/// Fields tracked at most; uses of further fields are ignored
+const MAX_TRACKED_FIELDS: u64 = 256;
+
+/// Filter and sort uses a field needs before it is suggested
+const MIN_USES_FOR_SUGGESTION: u64 = 10;
+
+thread_local! {
+    static FIELD_USAGE: RefCell<StableBTreeMap<String, FieldUsage, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(26)))
+    );
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+struct FieldUsage {
+    filters: u64,
+    sorts: u64,
+    /// Filtering queries on the field that ran as full scans
+    full_scans: u64,
+}
+
+impl_storable!(FieldUsage);
+
+/// Unindexed field worth an index, see `suggest_indexes`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IndexSuggestion {
+    pub field: String,
+    pub filter_count: u64,
+    pub sort_count: u64,
+    pub full_scan_count: u64,
+    /// Cycles the counted full scans would have saved at the current record count
+    pub estimated_cycles_saved: u64,
+}
+
+pub struct IndexAdvisor;
+
+impl IndexAdvisor {
+    /// Count the fields a query filtered and sorted on
+    pub fn record_query(filter: &QueryFilter, full_scan: bool) {
+        let mut filtered = BTreeSet::new();
+        for condition in &filter.conditions {
+            collect_condition(condition, &mut filtered);
+        }
+        if let Some(expression) = &filter.expression {
+            collect_expression(expression, &mut filtered);
+        }
+
+        FIELD_USAGE.with(|usage| {
+            let mut usage = usage.borrow_mut();
+            let mut bump = |field: &str, update: &dyn Fn(&mut FieldUsage)| {
+                let existing = usage.get(&field.to_string());
+                if existing.is_none() && usage.len() >= MAX_TRACKED_FIELDS {
+                    return;
+                }
+                let mut counts = existing.unwrap_or_default();
+                update(&mut counts);
+                usage.insert(field.to_string(), counts);
+            };
+
+            for field in &filtered {
+                bump(field, &|counts| {
+                    counts.filters += 1;
+                    if full_scan {
+                        counts.full_scans += 1;
+                    }
+                });
+            }
+            if let Some(field) = &filter.sort_by {
+                bump(field, &|counts| counts.sorts += 1);
+            }
+        });
+    }
+
+    /// Frequently used fields without a single-field index, most beneficial first
+    pub fn suggestions() -> Vec<IndexSuggestion> {
+        let full_scan_cycles = QueryPlanner::full_scan_cycles();
+
+        let mut suggestions: Vec<IndexSuggestion> = FIELD_USAGE.with(|usage| {
+            usage.borrow().iter()
+                .filter(|(field, counts)| {
+                    counts.filters + counts.sorts >= MIN_USES_FOR_SUGGESTION && !Storage::has_field_index(field)
+                })
+                .map(|(field, counts)| IndexSuggestion {
+                    field,
+                    filter_count: counts.filters,
+                    sort_count: counts.sorts,
+                    full_scan_count: counts.full_scans,
+                    estimated_cycles_saved: counts.full_scans.saturating_mul(full_scan_cycles),
+                })
+                .collect()
+        });
+
+        suggestions.sort_by(|a, b| {
+            b.estimated_cycles_saved.cmp(&a.estimated_cycles_saved)
+                .then_with(|| (b.filter_count + b.sort_count).cmp(&(a.filter_count + a.sort_count)))
+                .then_with(|| a.field.cmp(&b.field))
+        });
+        suggestions
+    }
+}
+
+fn collect_expression<'a>(expression: &'a FilterExpr, fields: &mut BTreeSet<&'a str>) {
+    match expression {
+        FilterExpr::And(children) | FilterExpr::Or(children) => {
+            for child in children {
+                collect_expression(child, fields);
+            }
+        },
+        FilterExpr::Condition(condition) => collect_condition(condition, fields),
+    }
+}
+
+/// Add the field of a condition an index could serve
+fn collect_condition<'a>(condition: &'a FilterCondition, fields: &mut BTreeSet<&'a str>) {
+    match condition.operator {
+        ComparisonOperator::Equals
+        | ComparisonOperator::GreaterThan
+        | ComparisonOperator::LessThan
+        | ComparisonOperator::StartsWith => {
+            fields.insert(&condition.field);
+        },
+        _ => {},
+    }
+}
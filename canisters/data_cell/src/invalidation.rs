@@ -0,0 +1,82 @@
+//! Cache invalidation notices sent to aggregators after every write
+//!
+//! Unlike change events, notices carry no record data, only the operation and
+//! the fields the write touched, so aggregators can drop cached query results
+//! read from this cell. Delivery is best effort: a failed notice is only
+//! logged, and the aggregator's cache falls back to TTL expiry.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::change_feed::ChangeOperation;
+use crate::storage::{memory, Memory};
+
+/// Aggregator method receiving `MutationEvent`s
+const NOTIFY_METHOD: &str = "notify_mutation";
+
+thread_local! {
+    /// Subscribed aggregators and when they subscribed
+    static AGGREGATORS: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(25)))
+    );
+}
+
+/// Notice of a write, sent to subscribed aggregators
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MutationEvent {
+    pub op: ChangeOperation,
+    pub record_id: String,
+    /// Top-level fields of the written record, hinting which cached queries are affected
+    pub fields: Vec<String>,
+    pub timestamp: u64,
+}
+
+pub struct Invalidation;
+
+impl Invalidation {
+    /// Start sending notices to an aggregator
+    pub fn subscribe(aggregator: Principal) {
+        AGGREGATORS.with(|aggregators| {
+            aggregators.borrow_mut().insert(aggregator, ic_cdk::api::time());
+        });
+    }
+
+    /// Stop sending notices to an aggregator, returning whether it was subscribed
+    pub fn unsubscribe(aggregator: Principal) -> bool {
+        AGGREGATORS.with(|aggregators| aggregators.borrow_mut().remove(&aggregator).is_some())
+    }
+
+    /// Notify every subscribed aggregator of a write
+    ///
+    /// Calls run after the current message commits.
+    pub fn notify(op: &ChangeOperation, record_id: &str, record: Option<&serde_json::Value>) {
+        let aggregators: Vec<Principal> = AGGREGATORS.with(|aggregators| {
+            aggregators.borrow().iter().map(|(aggregator, _)| aggregator).collect()
+        });
+
+        if aggregators.is_empty() {
+            return;
+        }
+
+        let event = MutationEvent {
+            op: op.clone(),
+            record_id: record_id.to_string(),
+            fields: record
+                .and_then(|record| record.as_object())
+                .map(|fields| fields.keys().cloned().collect())
+                .unwrap_or_default(),
+            timestamp: ic_cdk::api::time(),
+        };
+
+        for aggregator in aggregators {
+            let event = event.clone();
+            ic_cdk::spawn(async move {
+                let result: Result<(), _> = ic_cdk::call(aggregator, NOTIFY_METHOD, (event,)).await;
+                if let Err((code, msg)) = result {
+                    ic_cdk::println!("Failed to notify aggregator {} of a write: {:?} - {}", aggregator, code, msg);
+                }
+            });
+        }
+    }
+}
@@ -12,89 +12,1748 @@ mod schema;
 mod storage;
 mod validation;
 mod access_control;
+mod filter;
+mod planner;
+mod settings;
+mod change_feed;
+mod rate_limit;
+mod custom_validation;
+mod record_ids;
+mod compression;
+mod query_cache;
+mod replication;
+mod csv;
+mod blob_store;
+mod candid_records;
+mod proto_records;
+mod metrics;
+mod idempotency;
+mod invalidation;
+mod benchmark;
+mod index_advisor;
 
 use schema::*;
 use storage::*;
 use validation::*;
 use access_control::*;
+use filter::*;
+use planner::*;
+use settings::*;
+use change_feed::*;
+use rate_limit::*;
+use custom_validation::*;
+use record_ids::*;
+use metrics::*;
+use idempotency::*;
+use invalidation::*;
+use benchmark::*;
+use index_advisor::*;
+use query_cache::*;
+use replication::*;
+use csv::*;
+use blob_store::*;
+use candid_records::*;
+use proto_records::*;
 
 /// Initialize Data Cell with schema and configuration
 #[init]
 fn init(config: CellInitConfig) {
     ic_cdk::println!("Initializing Data Cell: {}", config.name);
 
-    // TODO: Initialize storage, schema, and access control
+    if let Err(error) = Validator::check_rules(&config.schema) {
+        trap(&format!("Invalid schema: {}", error));
+    }
     Storage::init(&config.schema);
+    Settings::init(&config);
     AccessControl::init(&config.permissions);
+
+    schedule_expiry_sweep();
+    schedule_index_builds();
+    Replication::start();
+}
+
+/// Interval between sweeps removing expired records
+const EXPIRY_SWEEP_INTERVAL_SECONDS: u64 = 60;
+
+/// Maximum number of expired records removed per sweep
+const EXPIRY_SWEEP_BATCH_SIZE: usize = 500;
+
+/// Start the timer that purges expired records and their index entries
+///
+/// The same sweep drops idle rate limit buckets, unreferenced blobs and
+/// abandoned blob uploads and expired idempotency keys, and retries due
+/// change-feed dead letters.
+fn schedule_expiry_sweep() {
+    ic_cdk_timers::set_timer_interval(
+        std::time::Duration::from_secs(EXPIRY_SWEEP_INTERVAL_SECONDS),
+        || {
+            let now = api::time();
+            let purged = Storage::purge_expired(now, EXPIRY_SWEEP_BATCH_SIZE);
+            if purged > 0 {
+                ic_cdk::println!("Purged {} expired records", purged);
+            }
+            RateLimiter::prune_idle(now);
+
+            let swept = BlobStore::sweep_orphans(now, EXPIRY_SWEEP_BATCH_SIZE);
+            if swept > 0 {
+                ic_cdk::println!("Swept {} unreferenced blobs", swept);
+            }
+            let abandoned = BlobStore::sweep_uploads(now);
+            if abandoned > 0 {
+                ic_cdk::println!("Discarded {} abandoned blob uploads", abandoned);
+            }
+            Idempotency::sweep(now, EXPIRY_SWEEP_BATCH_SIZE);
+            ChangeFeed::retry_due(now);
+        },
+    );
+}
+
+/// Interval between batches of lazy index builds
+const INDEX_BUILD_INTERVAL_SECONDS: u64 = 5;
+
+/// Records added to each lazy index being built per batch
+const INDEX_BUILD_BATCH_SIZE: usize = 500;
+
+/// Start the timer that advances lazy index builds one batch at a time
+fn schedule_index_builds() {
+    ic_cdk_timers::set_timer_interval(
+        std::time::Duration::from_secs(INDEX_BUILD_INTERVAL_SECONDS),
+        || Storage::continue_index_builds(INDEX_BUILD_BATCH_SIZE),
+    );
+}
+
+/// Insert new record with validation
+///
+/// `expires_at` (nanoseconds since epoch) overrides the cell's default TTL.
+/// Expired records are hidden from reads immediately and purged by a timer.
+/// The returned ID follows the cell's `id_strategy`. An `Absent` precondition
+/// only has an effect with `FromField` IDs, since other IDs are always new.
+///
+/// A repeated `idempotency_key` from the same caller returns the first
+/// successful call's ID instead of inserting again.
+#[update]
+async fn insert(
+    data: serde_json::Value,
+    expires_at: Option<u64>,
+    precondition: Option<Precondition>,
+    idempotency_key: Option<String>,
+) -> Result<String, CellError> {
+    let key = match idempotency_key {
+        Some(key) => key,
+        None => return insert_record(data, expires_at, precondition).await,
+    };
+    let caller = caller();
+
+    match Idempotency::begin(caller, &key)? {
+        Some(IdempotentResult::Inserted(record_id)) => return Ok(record_id),
+        Some(_) => return Err(idempotency_key_reused(&key)),
+        None => {},
+    }
+
+    let result = insert_record(data, expires_at, precondition).await;
+    Idempotency::finish(caller, &key, result.as_ref().ok().map(|id| IdempotentResult::Inserted(id.clone())));
+    result
+}
+
+async fn insert_record(mut data: serde_json::Value, expires_at: Option<u64>, precondition: Option<Precondition>) -> Result<String, CellError> {
+    let caller = caller();
+    let _timer = Metrics::track(TrackedOperation::Insert);
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let schema = Storage::get_schema();
+    schema.apply_defaults(&mut data);
+    schema.apply_timestamps(&mut data, None, api::time());
+    Validator::coerce_data(&schema, &mut data, Settings::get().coerce_types);
+    validate_for_write(&schema, &data).await
+        .map_err(validation_failure)?;
+
+    if let Some(precondition) = &precondition {
+        let existing = RecordIds::target_id(&data)?
+            .and_then(|record_id| Storage::get_json_record(&record_id));
+        check_precondition(precondition, existing.as_ref())?;
+    }
+
+    let record_id = RecordIds::generate(&data).await?;
+    Storage::put_json_record(&record_id, &data, None)
+        .map_err(CellError::StorageError)?;
+
+    if let Some(expires_at) = expires_at.or_else(default_expiry) {
+        Storage::set_expiry(&record_id, expires_at);
+    }
+
+    AccessControl::audit_access(caller, Operation::Write, record_id.clone());
+    ChangeFeed::publish(ChangeOperation::Insert, &record_id, Some(data));
+    Ok(record_id)
+}
+
+/// Upload a blob payload, returning the ID to store in a `Blob` field
+///
+/// The blob is deleted if no record references it within an hour, and
+/// along with the record that does once that record is deleted.
+#[update]
+fn put_blob(bytes: Vec<u8>) -> Result<String, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    Ok(BlobStore::put(bytes))
+}
+
+/// Start a chunked upload of a blob too large for one message
+///
+/// With `field`, the assembled blob is limited by that `Blob` field's
+/// `MaxSize` rule. Send the bytes with `put_blob_chunk` and complete the
+/// upload with `finish_blob_upload`; uploads idle for an hour are discarded.
+#[update]
+fn begin_blob_upload(field: Option<String>) -> Result<String, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let max_size = match field {
+        Some(field) => BlobStore::field_max_size(&Storage::get_schema(), &field)?,
+        None => None,
+    };
+    Ok(BlobStore::begin_upload(caller, max_size))
+}
+
+/// Send the bytes of an upload starting at `offset`; chunks may arrive in any order
+///
+/// Each chunk is a write: it needs write access and counts against the rate limit.
+#[update]
+fn put_blob_chunk(upload_id: String, offset: u64, bytes: Vec<u8>) -> Result<(), CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    BlobStore::put_chunk(caller, &upload_id, offset, bytes)
+}
+
+/// Assemble an upload's chunks into a blob, returning the ID to store in a `Blob` field
+#[update]
+fn finish_blob_upload(upload_id: String) -> Result<String, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    BlobStore::finish_upload(caller, &upload_id)
+}
+
+/// Payload of an uploaded blob
+#[query]
+fn get_blob(blob_id: String) -> Result<Vec<u8>, CellError> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    BlobStore::get(&blob_id).ok_or(CellError::NotFound(blob_id))
+}
+
+/// Check a write precondition against the currently stored record, if any
+fn check_precondition(precondition: &Precondition, existing: Option<&serde_json::Value>) -> Result<(), CellError> {
+    match (precondition, existing) {
+        (Precondition::Absent, None) => Ok(()),
+        (Precondition::Absent, Some(_)) => Err(CellError::PreconditionFailed(
+            "Record already exists".to_string()
+        )),
+        (Precondition::FieldEquals { field, value }, Some(record)) => {
+            let matches = record.get(field).map_or(false, |current| {
+                FilterEvaluator::compare_values(current, value)
+                    .map_or(current == value, |ordering| ordering == std::cmp::Ordering::Equal)
+            });
+            if matches {
+                Ok(())
+            } else {
+                Err(CellError::PreconditionFailed(format!("Field '{}' does not equal {}", field, value)))
+            }
+        },
+        (Precondition::FieldEquals { field, .. }, None) => Err(CellError::PreconditionFailed(
+            format!("No existing record to compare field '{}'", field)
+        )),
+    }
+}
+
+/// Expiry for a new record under the cell's default TTL, if one is configured
+fn default_expiry() -> Option<u64> {
+    Settings::get().default_ttl_seconds
+        .map(|ttl| api::time().saturating_add(ttl.saturating_mul(1_000_000_000)))
+}
+
+/// Insert a record under `record_id`, or replace it if one already exists
+///
+/// Replacing validates and reindexes like `update` but takes `data` as the
+/// whole record; `OnCreate` timestamps and any expiry are kept. New records
+/// get the cell's default TTL.
+///
+/// A repeated `idempotency_key` from the same caller returns the first
+/// successful call's result instead of writing again.
+#[update]
+async fn upsert(record_id: String, data: serde_json::Value, idempotency_key: Option<String>) -> Result<UpsertResult, CellError> {
+    let key = match idempotency_key {
+        Some(key) => key,
+        None => return upsert_record(record_id, data).await,
+    };
+    let caller = caller();
+
+    match Idempotency::begin(caller, &key)? {
+        Some(IdempotentResult::Upserted(result)) => return Ok(result),
+        Some(_) => return Err(idempotency_key_reused(&key)),
+        None => {},
+    }
+
+    let result = upsert_record(record_id, data).await;
+    Idempotency::finish(caller, &key, result.as_ref().ok().map(|result| IdempotentResult::Upserted(result.clone())));
+    result
+}
+
+fn idempotency_key_reused(key: &str) -> CellError {
+    CellError::ValidationError(format!("Idempotency key {} was already used for a different operation", key))
+}
+
+async fn upsert_record(record_id: String, mut data: serde_json::Value) -> Result<UpsertResult, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    if !data.is_object() {
+        return Err(CellError::ValidationError("Expected object".to_string()));
+    }
+
+    Storage::discard_if_expired(&record_id, api::time());
+    let previous = Storage::get_json_record(&record_id);
+
+    let schema = Storage::get_schema();
+    schema.apply_defaults(&mut data);
+    schema.apply_timestamps(&mut data, previous.as_ref(), api::time());
+    Validator::coerce_data(&schema, &mut data, Settings::get().coerce_types);
+    RecordIds::check_key_unchanged(&record_id, &data)?;
+    validate_for_write(&schema, &data).await
+        .map_err(validation_failure)?;
+
+    if Storage::get_json_record(&record_id) != previous {
+        return Err(CellError::StorageError(
+            format!("Record {} was modified during validation; retry the upsert", record_id)
+        ));
+    }
+
+    Storage::put_json_record(&record_id, &data, previous.as_ref())
+        .map_err(CellError::StorageError)?;
+
+    let (result, op) = match previous {
+        Some(_) => (UpsertResult::Updated, ChangeOperation::Update),
+        None => {
+            if let Some(expires_at) = default_expiry() {
+                Storage::set_expiry(&record_id, expires_at);
+            }
+            (UpsertResult::Inserted, ChangeOperation::Insert)
+        },
+    };
+
+    AccessControl::audit_access(caller, Operation::Write, record_id.clone());
+    ChangeFeed::publish(op, &record_id, Some(data));
+    Ok(result)
+}
+
+/// Validate data against the schema without storing it
+///
+/// Schema defaults, auto-timestamps and type coercion are applied first, as on
+/// insert, and every violation is reported rather than only the first.
+/// `Custom` rules need the validator canister and are only checked on writes.
+#[query]
+fn validate(mut data: serde_json::Value) -> Result<(), Vec<ValidationError>> {
+    let caller = caller();
+
+    if !AccessControl::can_write(caller) {
+        trap("Permission denied");
+    }
+
+    let schema = Storage::get_schema();
+    schema.apply_defaults(&mut data);
+    schema.apply_timestamps(&mut data, None, api::time());
+    Validator::coerce_data(&schema, &mut data, Settings::get().coerce_types);
+
+    Validator::validate_data(&schema, &data, ValidationMode::CollectAll)
+}
+
+/// Validation mode for writes, fail-fast when the cell is configured for it
+fn write_validation_mode() -> ValidationMode {
+    if Settings::get().fail_fast_validation {
+        ValidationMode::FailFast
+    } else {
+        ValidationMode::CollectAll
+    }
+}
+
+/// Run schema validation and then any `Custom` rules against the validator canister
+async fn validate_for_write(schema: &SchemaDefinition, data: &serde_json::Value) -> Result<(), Vec<ValidationError>> {
+    let mode = write_validation_mode();
+    Validator::validate_data(schema, data, mode)?;
+    CustomValidation::validate(schema, data, mode).await
+}
+
+/// Fetch the caller's roles from the role authority, if one is configured
+///
+/// A failed fetch only means authority roles aren't counted for this call.
+async fn load_delegated_roles(caller: Principal) {
+    if let Err(error) = AccessControl::refresh_delegated_roles(caller).await {
+        ic_cdk::println!("Failed to fetch roles of {}: {}", caller, error);
+    }
+}
+
+/// Fetch the caller's roles from the role authority and return all of its roles
+///
+/// Queries only see authority roles fetched by an earlier update call, so
+/// clients relying on them call this first.
+#[update]
+async fn refresh_roles() -> Vec<String> {
+    let caller = caller();
+    load_delegated_roles(caller).await;
+    AccessControl::roles_of(caller)
+}
+
+/// Reject the anonymous principal from writes unless the cell allows anonymous writes
+fn ensure_authenticated(caller: Principal) -> Result<(), CellError> {
+    if caller == Principal::anonymous() && !Settings::get().allow_anonymous_writes {
+        return Err(CellError::PermissionDenied);
+    }
+    Ok(())
+}
+
+/// Combine validation errors into a single `CellError`
+fn validation_failure(errors: Vec<ValidationError>) -> CellError {
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    CellError::ValidationError(messages.join("; "))
+}
+
+/// Maximum number of record IDs accepted by a single `get_many` call
+const MAX_BATCH_FETCH: usize = 100;
+
+/// Fetch a single record by ID
+#[query]
+fn get(record_id: String) -> Option<serde_json::Value> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    Storage::get_json_record(&record_id)
+}
+
+/// Fetch a record together with its content ETag
+///
+/// The ETag changes exactly when the record's content does, so clients can
+/// cache by it and compare it with `record_etags` instead of refetching.
+#[query]
+fn get_with_etag(record_id: String) -> Option<RecordWithEtag> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    let record = Storage::get_json_record(&record_id)?;
+    let etag = Storage::record_hash(&record_id)?;
+    Some(RecordWithEtag { record, etag })
+}
+
+/// Content ETags of records, positionally; `None` marks an ID with no stored record
+#[query]
+fn record_etags(record_ids: Vec<String>) -> Vec<Option<String>> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    if record_ids.len() > MAX_BATCH_FETCH {
+        trap(&format!("record_etags accepts at most {} record IDs", MAX_BATCH_FETCH));
+    }
+
+    record_ids.iter()
+        .map(|record_id| Storage::record_hash(record_id))
+        .collect()
+}
+
+/// Candid type of the records accepted and returned by the `_candid` endpoints
+#[query]
+fn candid_record_type() -> String {
+    CandidRecords::record_type(&Storage::get_schema()).to_string()
+}
+
+/// `insert` taking the record as a Candid message of `candid_record_type()`
+#[update]
+async fn insert_candid(record: Vec<u8>, expires_at: Option<u64>, precondition: Option<Precondition>) -> Result<String, CellError> {
+    let data = CandidRecords::decode(&Storage::get_schema(), &record)?;
+    insert_record(data, expires_at, precondition).await
+}
+
+/// `get` returning the record as a Candid message of `candid_record_type()`
+#[query]
+fn get_candid(record_id: String) -> Result<Option<Vec<u8>>, CellError> {
+    get(record_id)
+        .map(|record| CandidRecords::encode(&Storage::get_schema(), &record))
+        .transpose()
+}
+
+/// `query` returning each record as a Candid message of `candid_record_type()`
+#[query]
+fn query_candid(filter: QueryFilter, pagination: Pagination) -> Result<CandidQueryResult, CellError> {
+    let result = query(filter, pagination)?;
+    let schema = Storage::get_schema();
+
+    Ok(CandidQueryResult {
+        records: result.records.iter()
+            .map(|record| CandidRecords::encode(&schema, record))
+            .collect::<Result<_, _>>()?,
+        total_count: result.total_count,
+        has_more: result.has_more,
+        next_cursor: result.next_cursor,
+    })
+}
+
+/// `.proto` file for the `_proto` endpoints; see `proto_records` for field numbering
+#[query]
+fn proto_schema() -> Result<String, CellError> {
+    ProtoRecords::proto_file(&Storage::get_schema())
+}
+
+/// `insert` taking the record as a protobuf `Record` message
+#[update]
+async fn insert_proto(record: Vec<u8>, expires_at: Option<u64>, precondition: Option<Precondition>) -> Result<String, CellError> {
+    let data = ProtoRecords::decode(&Storage::get_schema(), &record)?;
+    insert_record(data, expires_at, precondition).await
+}
+
+/// `query` returning each record as a protobuf `Record` message
+#[query]
+fn query_proto(filter: QueryFilter, pagination: Pagination) -> Result<ProtoQueryResult, CellError> {
+    let result = query(filter, pagination)?;
+    let schema = Storage::get_schema();
+
+    Ok(ProtoQueryResult {
+        records: result.records.iter()
+            .map(|record| ProtoRecords::encode(&schema, record))
+            .collect::<Result<_, _>>()?,
+        total_count: result.total_count,
+        has_more: result.has_more,
+        next_cursor: result.next_cursor,
+    })
+}
+
+/// Fetch multiple records by ID in a single call
+///
+/// Results are returned positionally: `None` marks an ID with no stored record.
+#[query]
+fn get_many(record_ids: Vec<String>) -> Vec<Option<serde_json::Value>> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    if record_ids.len() > MAX_BATCH_FETCH {
+        trap(&format!("get_many accepts at most {} record IDs", MAX_BATCH_FETCH));
+    }
+
+    record_ids.iter()
+        .map(|record_id| Storage::get_json_record(record_id))
+        .collect()
+}
+
+/// Query records with filtering and pagination
+///
+/// Results cached by `query_cached` are served without re-executing.
+#[query]
+fn query(filter: QueryFilter, pagination: Pagination) -> Result<QueryResult, CellError> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    audit_traced_query(caller, &filter);
+
+    if let Some(result) = QueryCache::key(&filter, &pagination).and_then(|key| QueryCache::get(&key)) {
+        return Ok(result);
+    }
+
+    execute_query(&filter, &pagination)
+}
+
+/// Like `query`, but run as an update so the result is kept in the cell's query cache
+///
+/// Repeated `query` and `query_cached` calls with the same filter and
+/// pagination are then answered from the cache until any record changes or
+/// the entry expires.
+#[update]
+fn query_cached(filter: QueryFilter, pagination: Pagination) -> Result<QueryResult, CellError> {
+    let caller = caller();
+    let _timer = Metrics::track(TrackedOperation::Query);
+
+    if !AccessControl::can_read(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    audit_traced_query(caller, &filter);
+
+    let key = QueryCache::key(&filter, &pagination);
+    if let Some(result) = key.as_deref().and_then(QueryCache::get) {
+        return Ok(result);
+    }
+
+    let (result, records_expire_at) = execute_query_until(&filter, &pagination)?;
+    if let Some(key) = key {
+        QueryCache::put(key, result.clone(), records_expire_at);
+    }
+    Ok(result)
+}
+
+/// Log a query carrying an aggregator trace ID, so the cell's side can be matched to the trace
+fn audit_traced_query(caller: Principal, filter: &QueryFilter) {
+    if let Some(trace_id) = &filter.trace_id {
+        AccessControl::audit_access(caller, Operation::Read, format!("query [{}]", trace_id));
+    }
+}
+
+/// Filter, sort and page records
+fn execute_query(filter: &QueryFilter, pagination: &Pagination) -> Result<QueryResult, CellError> {
+    execute_query_until(filter, pagination).map(|(result, _)| result)
+}
+
+/// Like `execute_query`, also returning the earliest expiry among the matching records
+///
+/// The result is only valid until then, since expired records drop out of it.
+fn execute_query_until(filter: &QueryFilter, pagination: &Pagination) -> Result<(QueryResult, Option<u64>), CellError> {
+    let schema = Storage::get_schema();
+    let expr = FilterEvaluator::compile(filter, &schema)?;
+
+    let candidate_ids = QueryPlanner::candidate_ids(filter);
+    IndexAdvisor::record_query(filter, candidate_ids.is_none());
+
+    let candidates = match candidate_ids {
+        Some(record_ids) => record_ids.into_iter()
+            .filter_map(|record_id| Storage::get_json_record(&record_id).map(|record| (record_id, record)))
+            .collect(),
+        None => Storage::list_json_records(),
+    };
+
+    // Only records changed after `since`, by the schema's `OnUpdate` timestamp
+    let modified_after = match filter.since {
+        Some(since) => match schema.updated_at_field() {
+            Some(field) => Some((field.to_string(), since)),
+            None => return Err(CellError::ValidationError(
+                "since requires an OnUpdate auto-timestamp field in the schema".to_string()
+            )),
+        },
+        None => None,
+    };
+
+    let mut records = Vec::new();
+    for (record_id, record) in candidates {
+        if let Some((field, since)) = &modified_after {
+            let updated_at = record.get(field).and_then(|value| value.as_u64());
+            if updated_at.map_or(true, |updated_at| updated_at <= *since) {
+                continue;
+            }
+        }
+        if FilterEvaluator::matches(&record, &expr)? {
+            records.push((record_id, record));
+        }
+    }
+
+    let sort_field = filter.sort_by.as_deref();
+    FilterEvaluator::sort_records(&mut records, sort_field, &filter.sort_order);
+
+    let total_count = records.len() as u64;
+    let records_expire_at = records.iter().filter_map(|(record_id, _)| Storage::expiry(record_id)).min();
+
+    // Resume strictly after the cursor position, if one was given
+    let start = match &pagination.cursor {
+        Some(cursor) => {
+            let cursor = QueryCursor::decode(cursor)?;
+            records.partition_point(|(record_id, record)| {
+                FilterEvaluator::compare_positions(
+                    sort_field.and_then(|field| record.get(field)),
+                    record_id,
+                    cursor.sort_value.as_ref(),
+                    &cursor.record_id,
+                    &filter.sort_order,
+                ) != std::cmp::Ordering::Greater
+            })
+        },
+        None => 0,
+    };
+
+    let page_start = start.saturating_add(pagination.offset as usize).min(records.len());
+    let page_end = page_start.saturating_add(pagination.limit as usize).min(records.len());
+    let has_more = page_end < records.len();
+
+    let next_cursor = if has_more && page_end > page_start {
+        let (record_id, record) = &records[page_end - 1];
+        Some(QueryCursor {
+            sort_value: sort_field.and_then(|field| record.get(field)).cloned(),
+            record_id: record_id.clone(),
+        }.encode())
+    } else {
+        None
+    };
+
+    let records = records.drain(page_start..page_end)
+        .map(|(_, record)| record)
+        .collect();
+
+    let result = QueryResult {
+        records,
+        total_count,
+        has_more,
+        next_cursor,
+        not_modified: filter.since.is_some() && total_count == 0,
+    };
+    Ok((result, records_expire_at))
+}
+
+/// Estimate the cost of a query without executing it
+#[query]
+fn estimate_query(filter: QueryFilter, pagination: Pagination) -> QueryCostEstimate {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    QueryPlanner::estimate(&filter, &pagination)
+}
+
+/// Get cardinality statistics for every index
+#[query]
+fn index_stats() -> Vec<IndexStat> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    Storage::index_stats()
+}
+
+/// Bytes each top-level field contributes to stored records, largest first
+///
+/// Sizes are uncompressed JSON bytes of the field name and value.
+#[query]
+fn field_storage_breakdown() -> Vec<(String, u64)> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    Storage::field_storage_breakdown()
+}
+
+/// List distinct values of a field, sorted by their JSON representation
+///
+/// Served from the field index once it is ready and no reindex is in
+/// progress, otherwise by scanning records.
+#[query]
+fn distinct(field: String, pagination: Pagination) -> Vec<serde_json::Value> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    let mut values = std::collections::BTreeMap::new();
+
+    // Indexes are incomplete while a rebuild is in progress
+    if Storage::has_ready_field_index(&field) && !Storage::is_reindexing() {
+        for value in Storage::indexed_values(&field) {
+            values.insert(value.to_string(), value);
+        }
+    } else {
+        for (_, record) in Storage::list_json_records() {
+            if let Some(value) = record.get(&field) {
+                values.insert(value.to_string(), value.clone());
+            }
+        }
+    }
+
+    values.into_values()
+        .skip(pagination.offset as usize)
+        .take(pagination.limit as usize)
+        .collect()
+}
+
+/// Maximum number of buckets returned by `count_by`
+const MAX_COUNT_BUCKETS: usize = 1_000;
+
+/// Count records grouped by each distinct value of a field (GROUP BY COUNT)
+///
+/// Buckets are sorted by count descending and capped at `MAX_COUNT_BUCKETS`.
+#[query]
+fn count_by(field: String) -> Vec<(serde_json::Value, u64)> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    let mut counts: std::collections::BTreeMap<String, (serde_json::Value, u64)> = std::collections::BTreeMap::new();
+
+    // Indexes are incomplete while a rebuild is in progress
+    if Storage::has_ready_field_index(&field) && !Storage::is_reindexing() {
+        for (value, count) in Storage::indexed_value_counts(&field) {
+            counts.entry(value.to_string()).or_insert((value, 0)).1 += count;
+        }
+    } else {
+        for (_, record) in Storage::list_json_records() {
+            if let Some(value) = record.get(&field) {
+                counts.entry(value.to_string()).or_insert((value.clone(), 0)).1 += 1;
+            }
+        }
+    }
+
+    let mut buckets: Vec<_> = counts.into_values().collect();
+    buckets.sort_by(|a, b| b.1.cmp(&a.1));
+    buckets.truncate(MAX_COUNT_BUCKETS);
+    buckets
+}
+
+/// Update existing record
+///
+/// With a precondition the update is applied only if the stored record
+/// satisfies it, failing with `PreconditionFailed` otherwise. Fails if the
+/// record changes while `Custom` rules are being checked.
+#[update]
+async fn update(record_id: String, updates: serde_json::Value, precondition: Option<Precondition>) -> Result<(), CellError> {
+    let caller = caller();
+    let _timer = Metrics::track(TrackedOperation::Update);
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let updates = match updates {
+        serde_json::Value::Object(fields) => fields,
+        _ => return Err(CellError::ValidationError("Expected object".to_string())),
+    };
+
+    let previous = Storage::get_json_record(&record_id)
+        .ok_or_else(|| CellError::NotFound(record_id.clone()))?;
+
+    if let Some(precondition) = &precondition {
+        check_precondition(precondition, Some(&previous))?;
+    }
+
+    let mut record = previous.clone();
+    if let serde_json::Value::Object(fields) = &mut record {
+        fields.extend(updates);
+    }
+
+    RecordIds::check_key_unchanged(&record_id, &record)?;
+
+    let schema = Storage::get_schema();
+    schema.apply_timestamps(&mut record, Some(&previous), api::time());
+    Validator::coerce_data(&schema, &mut record, Settings::get().coerce_types);
+    validate_for_write(&schema, &record).await
+        .map_err(validation_failure)?;
+
+    if Storage::get_json_record(&record_id).as_ref() != Some(&previous) {
+        return Err(CellError::StorageError(
+            format!("Record {} was modified during validation; retry the update", record_id)
+        ));
+    }
+
+    Storage::put_json_record(&record_id, &record, Some(&previous))
+        .map_err(CellError::StorageError)?;
+
+    AccessControl::audit_access(caller, Operation::Write, record_id.clone());
+    ChangeFeed::publish(ChangeOperation::Update, &record_id, Some(record));
+    Ok(())
+}
+
+/// Atomically add `delta` to an integer field and return its new value
+///
+/// A missing or null field counts as 0. The field must be a `Number` and the
+/// result must pass the schema, including `Range` rules.
+#[update]
+async fn increment_field(record_id: String, field: String, delta: i64) -> Result<i64, CellError> {
+    let schema = Storage::get_schema();
+    if let Some(field_def) = schema.fields.get(&field) {
+        if !matches!(field_def.field_type, FieldType::Number) {
+            return Err(CellError::ValidationError(format!("Field '{}' is not a number", field)));
+        }
+    }
+
+    let value = mutate_field(record_id, &field, |current| {
+        let current = match current {
+            None | Some(serde_json::Value::Null) => 0,
+            Some(value) => value.as_i64().ok_or_else(|| CellError::ValidationError(
+                format!("Field '{}' does not hold an integer", field)
+            ))?,
+        };
+        current.checked_add(delta)
+            .map(serde_json::Value::from)
+            .ok_or_else(|| CellError::ValidationError(format!("Incrementing field '{}' overflows", field)))
+    }).await?;
+
+    Ok(value.as_i64().unwrap_or_default())
+}
+
+/// Atomically append values to an array field and return the updated array
+///
+/// A missing or null field counts as empty. Elements are validated against
+/// the array's item type and the result against its `MaxItems` rule.
+#[update]
+async fn append_to_array(record_id: String, field: String, values: Vec<serde_json::Value>) -> Result<Vec<serde_json::Value>, CellError> {
+    check_array_field(&field)?;
+
+    let value = mutate_field(record_id, &field, |current| {
+        let mut items = current_array(&field, current)?;
+        items.extend(values);
+        Ok(serde_json::Value::Array(items))
+    }).await?;
+
+    Ok(into_array(value))
+}
+
+/// Atomically remove every occurrence of the given values from an array field
+/// and return the updated array
+#[update]
+async fn remove_from_array(record_id: String, field: String, values: Vec<serde_json::Value>) -> Result<Vec<serde_json::Value>, CellError> {
+    check_array_field(&field)?;
+
+    let value = mutate_field(record_id, &field, |current| {
+        let mut items = current_array(&field, current)?;
+        items.retain(|item| !values.contains(item));
+        Ok(serde_json::Value::Array(items))
+    }).await?;
+
+    Ok(into_array(value))
+}
+
+/// Fail unless the schema declares `field` as an array
+fn check_array_field(field: &str) -> Result<(), CellError> {
+    match Storage::get_schema().fields.get(field).map(|field_def| &field_def.field_type) {
+        Some(FieldType::Array(_)) | None => Ok(()),
+        Some(_) => Err(CellError::ValidationError(format!("Field '{}' is not an array", field))),
+    }
+}
+
+/// Items of an array field's current value, treating missing or null as empty
+fn current_array(field: &str, current: Option<&serde_json::Value>) -> Result<Vec<serde_json::Value>, CellError> {
+    match current {
+        None | Some(serde_json::Value::Null) => Ok(Vec::new()),
+        Some(serde_json::Value::Array(items)) => Ok(items.clone()),
+        Some(_) => Err(CellError::ValidationError(format!("Field '{}' does not hold an array", field))),
+    }
+}
+
+fn into_array(value: serde_json::Value) -> Vec<serde_json::Value> {
+    match value {
+        serde_json::Value::Array(items) => items,
+        _ => Vec::new(),
+    }
+}
+
+/// Replace one field of a stored record with `f(current value)`
+///
+/// The caller's delegated roles are loaded first; after that, concurrent
+/// calls can't interleave between the read and the write, so no update is
+/// lost. The new record is checked against the schema, `Custom` rules
+/// included; if checking those lets another call change the record first,
+/// this one fails and must be retried. Returns the field's new value.
+async fn mutate_field(
+    record_id: String,
+    field: &str,
+    f: impl FnOnce(Option<&serde_json::Value>) -> Result<serde_json::Value, CellError>,
+) -> Result<serde_json::Value, CellError> {
+    let caller = caller();
+    let _timer = Metrics::track(TrackedOperation::Update);
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let previous = Storage::get_json_record(&record_id)
+        .ok_or_else(|| CellError::NotFound(record_id.clone()))?;
+
+    let value = f(previous.get(field))?;
+
+    let mut record = previous.clone();
+    match &mut record {
+        serde_json::Value::Object(fields) => {
+            fields.insert(field.to_string(), value.clone());
+        },
+        _ => return Err(CellError::StorageError(format!("Record {} is not an object", record_id))),
+    }
+
+    RecordIds::check_key_unchanged(&record_id, &record)?;
+
+    let schema = Storage::get_schema();
+    schema.apply_timestamps(&mut record, Some(&previous), api::time());
+    validate_for_write(&schema, &record).await
+        .map_err(validation_failure)?;
+
+    if Storage::get_json_record(&record_id).as_ref() != Some(&previous) {
+        return Err(CellError::StorageError(
+            format!("Record {} was modified during validation; retry the change to '{}'", record_id, field)
+        ));
+    }
+
+    Storage::put_json_record(&record_id, &record, Some(&previous))
+        .map_err(CellError::StorageError)?;
+
+    AccessControl::audit_access(caller, Operation::Write, record_id.clone());
+    ChangeFeed::publish(ChangeOperation::Update, &record_id, Some(record));
+    Ok(value)
 }
 
-/// Insert new record with validation
+/// Delete record
+#[update]
+async fn delete(record_id: String) -> Result<(), CellError> {
+    let caller = caller();
+    let _timer = Metrics::track(TrackedOperation::Delete);
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let record = Storage::remove_json_record(&record_id)
+        .ok_or_else(|| CellError::NotFound(record_id.clone()))?;
+
+    AccessControl::audit_access(caller, Operation::Delete, record_id.clone());
+    ChangeFeed::publish(ChangeOperation::Delete, &record_id, Some(record));
+    Ok(())
+}
+
+/// Subscribe a canister method to receive a `ChangeEvent` for every mutation
+///
+/// Events carry full records, so the callback canister needs read access
+/// just like the caller.
 #[update]
-fn insert(data: serde_json::Value) -> Result<String, CellError> {
+fn subscribe(callback: Principal, method: String) -> Result<(), CellError> {
     let caller = caller();
 
-    // TODO: Implement record insertion
-    // - Validate caller permissions
-    // - Validate data against schema
-    // - Store in stable memory
-    // - Return record ID
+    if !AccessControl::can_read(caller) || !AccessControl::can_read(callback) {
+        return Err(CellError::PermissionDenied);
+    }
 
-    Err(CellError::NotImplemented("Insert operation pending implementation".to_string()))
+    ChangeFeed::subscribe(callback, method);
+    Ok(())
 }
 
-/// Query records with filtering and pagination
+/// Subscribe the calling aggregator to cache invalidation notices for every write
+#[update]
+fn subscribe_invalidations() -> Result<(), CellError> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    Invalidation::subscribe(caller);
+    Ok(())
+}
+
+/// Stop sending cache invalidation notices to the calling aggregator
+#[update]
+fn unsubscribe_invalidations() -> Result<(), CellError> {
+    let caller = caller();
+
+    if Invalidation::unsubscribe(caller) {
+        Ok(())
+    } else {
+        Err(CellError::NotFound(caller.to_string()))
+    }
+}
+
+/// Change events that couldn't be delivered to subscribers (admin only)
 #[query]
-fn query(filter: QueryFilter, pagination: Pagination) -> QueryResult {
+fn dead_letters() -> Vec<DeadLetter> {
+    if !AccessControl::is_admin(caller()) {
+        trap("Permission denied");
+    }
+
+    ChangeFeed::dead_letters()
+}
+
+/// Retry delivering a dead letter now (admin only)
+#[update]
+async fn redeliver(id: String) -> Result<(), CellError> {
     let caller = caller();
 
-    // TODO: Implement query operation
-    // - Validate read permissions
-    // - Apply filters
-    // - Return paginated results
+    if !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    AccessControl::audit_access(caller, Operation::Admin, id.clone());
+    ChangeFeed::redeliver(&id).await.map_err(CellError::StorageError)
+}
+
+/// Apply a change event from the primary this replica follows
+#[update]
+fn apply_change(event: ChangeEvent) {
+    if Replication::primary() != Some(caller()) {
+        trap("Permission denied");
+    }
 
-    QueryResult {
-        records: Vec::new(),
-        total_count: 0,
-        has_more: false,
+    if let Err(error) = Replication::apply_change(event) {
+        trap(&error);
     }
 }
 
-/// Update existing record
+/// Replication progress, meaningful only on replica cells
+#[query]
+fn replica_status() -> ReplicaStatus {
+    Replication::status()
+}
+
+/// Remove a change feed subscription (the subscriber itself or an admin)
 #[update]
-fn update(record_id: String, updates: serde_json::Value) -> Result<(), CellError> {
+fn unsubscribe(callback: Principal, method: String) -> Result<(), CellError> {
     let caller = caller();
 
-    // TODO: Implement record update
-    // - Validate permissions
-    // - Validate updates against schema
-    // - Apply updates atomically
+    if caller != callback && !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if ChangeFeed::unsubscribe(callback, &method) {
+        Ok(())
+    } else {
+        Err(CellError::NotFound(format!("{}:{}", callback, method)))
+    }
+}
 
-    Err(CellError::NotImplemented("Update operation pending implementation".to_string()))
+/// Get the schema this cell validates records against
+#[query]
+fn get_schema() -> SchemaDefinition {
+    Storage::get_schema()
 }
 
-/// Delete record
+/// Get the cell's schema as a JSON Schema document, for external validators and codegen
+#[query]
+fn json_schema() -> String {
+    Storage::get_schema().to_json_schema().to_string()
+}
+
+/// Maximum number of records returned by a single `export_chunk` call
+const MAX_EXPORT_CHUNK_SIZE: u32 = 1_000;
+
+/// Export records in key order for backup or migration
+///
+/// Pass `None` to start and then each chunk's `next_cursor` until it is `None`.
+/// The first chunk also carries the schema. Records are JSON text, and those
+/// due to expire are listed with their expiry so an import can restore it.
+#[query]
+fn export_chunk(cursor: Option<String>, chunk_size: u32) -> ExportChunk {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    let chunk_size = chunk_size.clamp(1, MAX_EXPORT_CHUNK_SIZE) as usize;
+    let batch = Storage::records_after(cursor.as_deref(), chunk_size);
+
+    let next_cursor = if batch.len() == chunk_size {
+        batch.last().map(|(record_id, _)| record_id.clone())
+    } else {
+        None
+    };
+
+    let now = api::time();
+    let records: Vec<(String, String)> = batch.into_iter()
+        .filter(|(record_id, _)| !Storage::is_expired(record_id, now))
+        .filter_map(|(record_id, data)| String::from_utf8(data).ok().map(|record| (record_id, record)))
+        .collect();
+    let expirations = records.iter()
+        .filter_map(|(record_id, _)| Storage::expiry(record_id).map(|expires_at| (record_id.clone(), expires_at)))
+        .collect();
+    let versions = records.iter()
+        .filter_map(|(record_id, _)| Storage::record_version(record_id).map(|version| (record_id.clone(), version)))
+        .collect();
+
+    ExportChunk {
+        schema: if cursor.is_none() { Some(Storage::get_schema()) } else { None },
+        records,
+        expirations,
+        versions,
+        next_cursor,
+    }
+}
+
+/// Export records as newline-delimited JSON, paging like `export_chunk`
+///
+/// Each record is one `{"id": ..., "record": ...}` line, so chunks can be
+/// appended straight to a file. The first chunk starts with a header line
+/// holding the schema and the stored record count, which may include
+/// expired records not yet swept.
+#[query]
+fn export_ndjson(cursor: Option<String>, chunk_size: u32) -> NdjsonChunk {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        trap("Permission denied");
+    }
+
+    let chunk_size = chunk_size.clamp(1, MAX_EXPORT_CHUNK_SIZE) as usize;
+    let batch = Storage::records_after(cursor.as_deref(), chunk_size);
+
+    let next_cursor = if batch.len() == chunk_size {
+        batch.last().map(|(record_id, _)| record_id.clone())
+    } else {
+        None
+    };
+
+    let mut data = String::new();
+    if cursor.is_none() {
+        let header = serde_json::json!({
+            "schema": Storage::get_schema(),
+            "record_count": Storage::record_count(),
+        });
+        data.push_str(&header.to_string());
+        data.push('\n');
+    }
+
+    // Stored records are compact JSON, so they can be spliced in without re-encoding
+    let now = api::time();
+    for (record_id, record) in batch {
+        if Storage::is_expired(&record_id, now) {
+            continue;
+        }
+        if let Ok(record) = std::str::from_utf8(&record) {
+            data.push_str("{\"id\":");
+            data.push_str(&serde_json::Value::String(record_id).to_string());
+            data.push_str(",\"record\":");
+            data.push_str(record);
+            data.push_str("}\n");
+        }
+    }
+
+    NdjsonChunk { data, next_cursor }
+}
+
+/// Restore records, given as JSON text, with their original IDs (admin only)
+///
+/// With `validate` false, records from a trusted export of the same schema skip
+/// per-record validation; indexes are maintained either way. Records that
+/// already exist are replaced. Records listed in `expirations`, as in an
+/// `ExportChunk`, expire at the given time; the rest don't expire. Numeric
+/// IDs advance the `Monotonic` sequence so later inserts don't reuse them.
+/// Each imported record is published to the change feed like a regular
+/// insert or update.
+#[update]
+async fn import_chunk(records: Vec<(String, String)>, validate: bool, expirations: Option<Vec<(String, u64)>>) -> Result<ImportReport, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    if !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if records.len() > MAX_EXPORT_CHUNK_SIZE as usize {
+        return Err(CellError::ValidationError(
+            format!("import_chunk accepts at most {} records", MAX_EXPORT_CHUNK_SIZE)
+        ));
+    }
+
+    let schema = Storage::get_schema();
+    let mut report = ImportReport {
+        imported: 0,
+        rejected: 0,
+        errors: Vec::new(),
+    };
+
+    let expirations: std::collections::HashMap<String, u64> = expirations.unwrap_or_default().into_iter().collect();
+    let coerce_types = Settings::get().coerce_types;
+    for (record_id, record) in records {
+        let mut record: serde_json::Value = match serde_json::from_str(&record) {
+            Ok(record) => record,
+            Err(e) => {
+                report.rejected += 1;
+                report.errors.push((record_id, format!("Invalid JSON: {}", e)));
+                continue;
+            },
+        };
+
+        if validate {
+            Validator::coerce_data(&schema, &mut record, coerce_types);
+            if let Err(errors) = validate_for_write(&schema, &record).await {
+                report.rejected += 1;
+                report.errors.push((record_id, errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")));
+                continue;
+            }
+        }
+
+        let previous = Storage::get_json_record(&record_id);
+        match Storage::put_json_record(&record_id, &record, previous.as_ref()) {
+            Ok(()) => {
+                match expirations.get(&record_id) {
+                    Some(expires_at) => Storage::set_expiry(&record_id, *expires_at),
+                    None => Storage::clear_expiry(&record_id),
+                }
+                RecordIds::advance_past(&record_id);
+                report.imported += 1;
+                let op = if previous.is_some() { ChangeOperation::Update } else { ChangeOperation::Insert };
+                ChangeFeed::publish(op, &record_id, Some(record));
+            },
+            Err(e) => {
+                report.rejected += 1;
+                report.errors.push((record_id, e));
+            },
+        }
+    }
+
+    AccessControl::audit_access(caller, Operation::Admin, "import".to_string());
+    Ok(report)
+}
+
+/// Import CSV rows as new records, one row per record
+///
+/// Values are coerced to their field types and validated like `insert`;
+/// empty cells leave the field unset. Rejected rows are reported by their
+/// 1-based position among the data rows and don't stop the import.
+#[update]
+async fn import_csv(csv: String, mapping: ColumnMapping) -> Result<ImportReport, CellError> {
+    let caller = caller();
+
+    Replication::ensure_writable()?;
+
+    load_delegated_roles(caller).await;
+
+    if !AccessControl::can_write(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    ensure_authenticated(caller)?;
+
+    RateLimiter::check_write(caller)?;
+
+    let mut rows = Csv::parse(&csv).map_err(CellError::ValidationError)?;
+    let columns: Vec<String> = if mapping.has_header {
+        if rows.is_empty() {
+            return Err(CellError::ValidationError("CSV has no header row".to_string()));
+        }
+        rows.remove(0).into_iter().map(|column| column.trim().to_string()).collect()
+    } else {
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        (0..width).map(|i| i.to_string()).collect()
+    };
+
+    if rows.len() > MAX_EXPORT_CHUNK_SIZE as usize {
+        return Err(CellError::ValidationError(
+            format!("import_csv accepts at most {} rows", MAX_EXPORT_CHUNK_SIZE)
+        ));
+    }
+
+    let column_index = |column: &str| columns.iter().position(|name| name == column)
+        .ok_or_else(|| CellError::ValidationError(format!("CSV has no column '{}'", column)));
+    let field_columns = mapping.fields.iter()
+        .map(|(column, field)| column_index(column).map(|index| (index, field.clone())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let id_index = mapping.id_column.as_deref().map(column_index).transpose()?;
+
+    let schema = Storage::get_schema();
+    let mut report = ImportReport {
+        imported: 0,
+        rejected: 0,
+        errors: Vec::new(),
+    };
+
+    for (i, row) in rows.iter().enumerate() {
+        match import_csv_row(&schema, row, &field_columns, id_index).await {
+            Ok(record_id) => {
+                report.imported += 1;
+                AccessControl::audit_access(caller, Operation::Write, record_id);
+            },
+            Err(reason) => {
+                report.rejected += 1;
+                report.errors.push((format!("row {}", i + 1), reason));
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Build, validate and store the record for one CSV row, returning its ID
+async fn import_csv_row(
+    schema: &SchemaDefinition,
+    row: &[String],
+    field_columns: &[(usize, String)],
+    id_index: Option<usize>,
+) -> Result<String, String> {
+    let mut data = serde_json::Value::Object(field_columns.iter()
+        .filter_map(|(index, field)| {
+            row.get(*index)
+                .filter(|value| !value.is_empty())
+                .map(|value| (field.clone(), serde_json::Value::String(value.clone())))
+        })
+        .collect());
+
+    schema.apply_defaults(&mut data);
+    schema.apply_timestamps(&mut data, None, api::time());
+    Validator::coerce_data(schema, &mut data, true);
+    validate_for_write(schema, &data).await
+        .map_err(|errors| errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))?;
+
+    let record_id = match id_index {
+        Some(index) => {
+            let record_id = row.get(index)
+                .filter(|record_id| !record_id.is_empty())
+                .ok_or("Missing record ID")?
+                .clone();
+            RecordIds::claim(&record_id, &data).map_err(|e| format!("{:?}", e))?;
+            record_id
+        },
+        None => RecordIds::generate(&data).await.map_err(|e| format!("{:?}", e))?,
+    };
+
+    Storage::put_json_record(&record_id, &data, None)?;
+    if let Some(expires_at) = default_expiry() {
+        Storage::set_expiry(&record_id, expires_at);
+    }
+
+    ChangeFeed::publish(ChangeOperation::Insert, &record_id, Some(data));
+    Ok(record_id)
+}
+
+/// Render the records matching a filter as CSV with a header row
+///
+/// Columns are the schema's fields in name order, followed by any other
+/// top-level keys found in the records. Nested values are written as JSON.
+/// At most `MAX_EXPORT_CHUNK_SIZE` records are rendered per call; page with
+/// `pagination` for more.
+#[query]
+fn export_csv(filter: QueryFilter, pagination: Pagination) -> Result<String, CellError> {
+    let caller = caller();
+
+    if !AccessControl::can_read(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let pagination = Pagination {
+        limit: pagination.limit.min(MAX_EXPORT_CHUNK_SIZE as u64),
+        ..pagination
+    };
+    let result = execute_query(&filter, &pagination)?;
+
+    let schema = Storage::get_schema();
+    let mut columns: Vec<String> = schema.fields.keys().cloned().collect();
+    columns.sort();
+    let extra_columns: std::collections::BTreeSet<String> = result.records.iter()
+        .filter_map(|record| record.as_object())
+        .flat_map(|record| record.keys())
+        .filter(|key| !schema.fields.contains_key(*key))
+        .cloned()
+        .collect();
+    columns.extend(extra_columns);
+
+    let mut csv = String::new();
+    Csv::write_row(&mut csv, &columns);
+    for record in &result.records {
+        let row: Vec<String> = columns.iter()
+            .map(|column| Csv::format_value(record.get(column)))
+            .collect();
+        Csv::write_row(&mut csv, &row);
+    }
+
+    Ok(csv)
+}
+
+/// Grant a role matched by `Role` access levels (admin only)
+#[update]
+fn grant_role(principal: Principal, role: String) -> Result<(), CellError> {
+    let caller = caller();
+
+    if !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if AccessControl::grant_role(principal, role.clone()) {
+        AccessControl::audit_access(caller, Operation::Admin, format!("role {} for {}", role, principal));
+    }
+    Ok(())
+}
+
+/// Replace the cell's read, write and admin permissions (admin only)
+///
+/// Cells upgraded from before permissions were persisted admit only their
+/// controllers until this is called.
+#[update]
+fn set_permissions(permissions: PermissionConfig) -> Result<(), CellError> {
+    let caller = caller();
+
+    if !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    AccessControl::set_permissions(permissions);
+    AccessControl::audit_access(caller, Operation::Admin, "permissions".to_string());
+    Ok(())
+}
+
+/// Revoke a role granted with `grant_role` (admin only)
+#[update]
+fn revoke_role(principal: Principal, role: String) -> Result<(), CellError> {
+    let caller = caller();
+
+    if !AccessControl::is_admin(caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if !AccessControl::revoke_role(principal, &role) {
+        return Err(CellError::NotFound(format!("{} does not have role {}", principal, role)));
+    }
+
+    AccessControl::audit_access(caller, Operation::Admin, format!("role {} for {}", role, principal));
+    Ok(())
+}
+
+/// Number of records reindexed per `reindex` call
+const REINDEX_BATCH_SIZE: usize = 500;
+
+/// Rebuild all indexes from stored records (controllers only)
+///
+/// Each call processes one batch; call repeatedly until `complete` is true.
+#[update]
+fn reindex() -> Result<ReindexReport, CellError> {
+    let caller = caller();
+
+    if !api::is_controller(&caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let progress = Storage::reindex_batch(REINDEX_BATCH_SIZE);
+    AccessControl::audit_access(caller, Operation::Admin, "indexes".to_string());
+
+    Ok(ReindexReport {
+        records_reindexed: progress.records_reindexed,
+        complete: !progress.in_progress,
+    })
+}
+
+/// Add an index to the schema (controllers only)
+///
+/// A regular index is built from every stored record within this call. A
+/// `lazy` index is built in the background, in batches, by the index build
+/// timer; queries scan until it is ready.
 #[update]
-fn delete(record_id: String) -> Result<(), CellError> {
+fn add_index(index: IndexDefinition) -> Result<(), CellError> {
     let caller = caller();
 
-    // TODO: Implement record deletion
-    // - Validate permissions
-    // - Remove from storage
-    // - Update indexes
+    if !api::is_controller(&caller) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    let name = index.name.clone();
+    Storage::add_index(index).map_err(CellError::SchemaViolation)?;
+    AccessControl::audit_access(caller, Operation::Admin, format!("index {}", name));
+    Ok(())
+}
+
+/// Lazy indexes still pending or being built, with their progress
+#[query]
+fn index_builds() -> Vec<IndexBuild> {
+    Storage::index_builds()
+}
+
+/// Cycle balance below which the cell reports itself in an error state
+const MIN_HEALTHY_CYCLES: u128 = 10_000_000_000;
+
+/// Installed wasm build, for deployment tooling
+#[query]
+fn version() -> CanisterVersion {
+    CanisterVersion {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        build_version: build_version(),
+        schema_version: Some(Storage::get_schema().version),
+    }
+}
+
+/// Crate version, with the commit baked in when built with `CELLDB_GIT_COMMIT` set
+fn build_version() -> String {
+    match option_env!("CELLDB_GIT_COMMIT") {
+        Some(commit) => format!("{}+{}", env!("CARGO_PKG_VERSION"), commit),
+        None => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// Cheap liveness check used by the aggregator when registering and monitoring cells
+#[query]
+fn health() -> CellHealth {
+    let status = if api::canister_balance128() < MIN_HEALTHY_CYCLES {
+        HealthStatus::Error("Cycle balance too low".to_string())
+    } else {
+        match Replication::status() {
+            ReplicaStatus { primary: Some(_), synced: false, last_error, .. } => HealthStatus::Degraded(
+                last_error.unwrap_or_else(|| "Replica not yet synced".to_string())
+            ),
+            _ => HealthStatus::Healthy,
+        }
+    };
+
+    CellHealth {
+        status,
+        schema_version: Storage::get_schema().version,
+        record_count: Storage::record_count(),
+        capabilities: cell_capabilities(),
+    }
+}
+
+/// Features this cell actually supports
+///
+/// The aggregator rejects registrations claiming capabilities not listed here.
+#[query]
+fn capabilities() -> Vec<CellCapability> {
+    cell_capabilities()
+}
 
-    Err(CellError::NotImplemented("Delete operation pending implementation".to_string()))
+/// Indexing is only advertised once the cell has an index defined
+fn cell_capabilities() -> Vec<CellCapability> {
+    let mut capabilities = vec![CellCapability::StreamingSupport, CellCapability::BatchOperations];
+    if !Storage::index_definitions().is_empty() {
+        capabilities.push(CellCapability::AdvancedIndexing);
+    }
+    capabilities
 }
 
 /// Get cell statistics and health metrics
+///
+/// `query_count` counts queries made through `query_cached`; plain `query`
+/// calls can't persist a counter.
 #[query]
 fn get_metrics() -> CellMetrics {
-    // TODO: Implement metrics collection
+    let sizes = Storage::blob_sizes();
     CellMetrics {
-        record_count: 0,
-        memory_usage: 0,
-        query_count: 0,
+        record_count: Storage::record_count(),
+        memory_usage: Storage::memory_usage(),
+        query_count: Metrics::count(TrackedOperation::Query),
         last_updated: api::time(),
+        uncompressed_bytes: sizes.raw_bytes,
+        stored_bytes: sizes.stored_bytes,
+        compression_ratio: if sizes.stored_bytes == 0 {
+            1.0
+        } else {
+            sizes.raw_bytes as f64 / sizes.stored_bytes as f64
+        },
+    }
+}
+
+/// Per-operation instruction counts with min/avg/max and a histogram
+///
+/// Costs are instructions executed, not wall-clock latency, which a canister
+/// can't observe within a message. Plain `query` calls can't persist
+/// samples, so `Query` stats only cover reads made through `query_cached`.
+#[query]
+fn get_detailed_metrics() -> Vec<OperationMetrics> {
+    Metrics::summary()
+}
+
+/// Unindexed fields that queries often filter or sort on, most beneficial index first
+///
+/// Based on queries executed as update calls, such as `query_cached`.
+#[query]
+fn suggest_indexes() -> Vec<IndexSuggestion> {
+    IndexAdvisor::suggestions()
+}
+
+/// Measure the cost of inserts, reads, updates and deletes with synthetic records (admin only)
+///
+/// Only available on cells initialized with `benchmark_enabled`. The
+/// synthetic records are removed before the call returns.
+#[update]
+fn self_benchmark(record_count: u32) -> Result<BenchmarkReport, CellError> {
+    if !AccessControl::is_admin(caller()) {
+        return Err(CellError::PermissionDenied);
+    }
+
+    if !Settings::get().benchmark_enabled {
+        return Err(CellError::PreconditionFailed("Benchmarking is not enabled on this cell".to_string()));
     }
+
+    Benchmark::run(record_count)
 }
 
 #[pre_upgrade]
@@ -105,6 +1764,9 @@ fn pre_upgrade() {
 #[post_upgrade]
 fn post_upgrade() {
     Storage::post_upgrade();
+    schedule_expiry_sweep();
+    schedule_index_builds();
+    Replication::start();
 }
 
 /// Cell initialization configuration
@@ -113,24 +1775,74 @@ pub struct CellInitConfig {
     pub name: String,
     pub schema: SchemaDefinition,
     pub permissions: PermissionConfig,
+    /// Default lifetime of inserted records; `None` keeps records indefinitely
+    pub default_ttl_seconds: Option<u64>,
+    /// Inserts, updates and deletes allowed per caller per minute; admins are exempt
+    pub write_rate_limit_per_minute: Option<u32>,
+    /// Stop insert and update validation at the first error instead of reporting all
+    pub fail_fast_validation: Option<bool>,
+    /// Canister implementing `validate_custom(rule, value_json)` for `Custom` rules
+    pub custom_validator: Option<Principal>,
+    /// Accept writes when the custom validator is unreachable; rejects by default
+    pub custom_validator_fail_open: Option<bool>,
+    /// Convert string values such as `"42"` or `"true"` to number and boolean fields
+    /// on write; fields can override this with their `coerce` flag
+    pub coerce_types: Option<bool>,
+    /// How inserted records get their IDs; defaults to insertion-time based IDs
+    pub id_strategy: Option<IdStrategy>,
+    /// LZ4-compress stored records; existing uncompressed records stay readable
+    pub compress_records: Option<bool>,
+    /// Run as a read-only replica following this primary cell's change feed
+    pub replica_of: Option<Principal>,
+    /// Accept writes from the anonymous principal; rejected by default
+    pub allow_anonymous_writes: Option<bool>,
+    /// Resolve `Role` access levels through this canister's `roles_of` as well
+    pub role_authority: Option<Principal>,
+    /// Allow admins to run `self_benchmark`; disabled by default
+    pub benchmark_enabled: Option<bool>,
 }
 
 /// Query filter
+///
+/// `conditions` are combined with AND; `expression` adds an arbitrary
+/// AND/OR tree on top of them.
 #[derive(CandidType, Serialize, Deserialize)]
 pub struct QueryFilter {
     pub conditions: Vec<FilterCondition>,
+    pub expression: Option<FilterExpr>,
     pub sort_by: Option<String>,
     pub sort_order: SortOrder,
+    /// Only match records changed after this time (nanoseconds since epoch)
+    ///
+    /// Uses the schema's `OnUpdate` auto-timestamp field, so polling clients
+    /// can fetch deltas instead of re-running full queries.
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// Aggregator trace this query belongs to, logged with the cell's audit entry
+    #[serde(default)]
+    pub trace_id: Option<String>,
 }
 
-#[derive(CandidType, Serialize, Deserialize)]
+/// Boolean filter expression tree
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Condition(FilterCondition),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct FilterCondition {
     pub field: String,
     pub operator: ComparisonOperator,
     pub value: serde_json::Value,
+    /// Compare string values ignoring case for `Equals`, `Contains`, `StartsWith`,
+    /// `EndsWith` and `Matches`
+    #[serde(default)]
+    pub case_insensitive: bool,
 }
 
-#[derive(CandidType, Serialize, Deserialize)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum ComparisonOperator {
     Equals,
     NotEquals,
@@ -138,6 +1850,18 @@ pub enum ComparisonOperator {
     LessThan,
     Contains,
     StartsWith,
+    EndsWith,
+    /// Field value matches the regular expression given as the condition value
+    Matches,
+    /// Field value is a member of the array given as the condition value
+    In,
+    /// Field value is not a member of the array given as the condition value
+    NotIn,
+    /// Field is absent or JSON null; fields with a schema default are filled on insert
+    /// and never match
+    IsNull,
+    /// Field is present and not JSON null; the condition value is ignored
+    IsNotNull,
 }
 
 #[derive(CandidType, Serialize, Deserialize)]
@@ -146,17 +1870,153 @@ pub enum SortOrder {
     Descending,
 }
 
+/// Offset or cursor based pagination
+///
+/// When `cursor` is set the page starts right after the record it encodes and
+/// `offset` skips further from there. Cursors stay valid across concurrent
+/// inserts and deletes.
 #[derive(CandidType, Serialize, Deserialize)]
 pub struct Pagination {
     pub offset: u64,
     pub limit: u64,
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
-#[derive(CandidType, Serialize, Deserialize)]
+#[derive(CandidType, Serialize, Deserialize, Clone)]
 pub struct QueryResult {
     pub records: Vec<serde_json::Value>,
     pub total_count: u64,
     pub has_more: bool,
+    /// Opaque cursor for fetching the next page, present when `has_more` is true
+    pub next_cursor: Option<String>,
+    /// Set when `since` was given and no matching record changed after it
+    #[serde(default)]
+    pub not_modified: bool,
+}
+
+/// Planned access path and rough cost of a query
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct QueryCostEstimate {
+    /// Index (or indexed field) the planner would read, `None` for a full scan
+    pub index_used: Option<String>,
+    pub estimated_records_scanned: u64,
+    pub estimated_records_returned: u64,
+    pub estimated_cycles: u64,
+}
+
+/// Chunk of a full-cell export
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct ExportChunk {
+    /// Schema of the cell, present only in the first chunk
+    pub schema: Option<SchemaDefinition>,
+    /// Record ID and JSON text of each record
+    pub records: Vec<(String, String)>,
+    /// Expiry time (nanoseconds) of each record in `records` that has one
+    pub expirations: Vec<(String, u64)>,
+    /// Change version of each record in `records` that has one, for replica backfill
+    pub versions: Vec<(String, u64)>,
+    pub next_cursor: Option<String>,
+}
+
+/// A record and its content ETag
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct RecordWithEtag {
+    pub record: serde_json::Value,
+    pub etag: String,
+}
+
+/// `QueryResult` with Candid-encoded records
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct CandidQueryResult {
+    pub records: Vec<Vec<u8>>,
+    pub total_count: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+/// `QueryResult` with protobuf-encoded records
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct ProtoQueryResult {
+    pub records: Vec<Vec<u8>>,
+    pub total_count: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+/// Chunk of an NDJSON export
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct NdjsonChunk {
+    /// Complete lines, each ending in a newline
+    pub data: String,
+    pub next_cursor: Option<String>,
+}
+
+/// Condition the stored record must meet for a write to proceed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum Precondition {
+    /// No record with the target ID exists (insert-if-absent)
+    Absent,
+    /// The stored record's `field` equals `value`, numbers compared by value
+    FieldEquals { field: String, value: serde_json::Value },
+}
+
+/// Which path an `upsert` took
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum UpsertResult {
+    Inserted,
+    Updated,
+}
+
+/// Outcome of an `import_chunk` call
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: u64,
+    /// Record ID and reason for each rejected record
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct ReindexReport {
+    pub records_reindexed: u64,
+    pub complete: bool,
+}
+
+/// Build and schema version reported by `version`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    /// Version of the cell's schema
+    pub schema_version: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct CellHealth {
+    pub status: HealthStatus,
+    pub schema_version: u32,
+    pub record_count: u64,
+    pub capabilities: Vec<CellCapability>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum HealthStatus {
+    Healthy,
+    /// Serving, but e.g. a replica still catching up with its primary
+    Degraded(String),
+    /// Should not receive queries
+    Error(String),
+}
+
+/// Cell features, named as in the aggregator's registry
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellCapability {
+    FullTextSearch,
+    GeospatialQueries,
+    AdvancedIndexing,
+    StreamingSupport,
+    BatchOperations,
 }
 
 #[derive(CandidType, Serialize, Deserialize)]
@@ -165,6 +2025,12 @@ pub struct CellMetrics {
     pub memory_usage: u64,
     pub query_count: u64,
     pub last_updated: u64,
+    /// Size of stored records as JSON
+    pub uncompressed_bytes: u64,
+    /// Size of stored records in stable memory
+    pub stored_bytes: u64,
+    /// `uncompressed_bytes / stored_bytes`; 1.0 when nothing is compressed
+    pub compression_ratio: f64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug)]
@@ -173,6 +2039,9 @@ pub enum CellError {
     PermissionDenied,
     NotFound(String),
     SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
     NotImplemented(String),
 }
 
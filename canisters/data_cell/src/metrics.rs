@@ -0,0 +1,185 @@
+//! Per-operation cost tracking for `get_detailed_metrics`
+//!
+//! Each tracked operation records the instructions its final message
+//! executed, the cell-side cost that dominates its latency. Samples fold into
+//! min/avg/max and a fixed-bucket histogram, so storage stays bounded no
+//! matter how many operations run. Plain `#[query]` calls can't persist state,
+//! so queries are only measured through `query_cached`.
+
+use candid::CandidType;
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::storage::{impl_storable, memory, Memory};
+
+/// Upper bounds (inclusive) of the histogram buckets, in instructions; a final
+/// bucket catches everything above the last bound
+const BUCKET_BOUNDS: [u64; 6] = [
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    10_000_000_000,
+];
+
+thread_local! {
+    static OPERATION_STATS: RefCell<StableBTreeMap<String, OperationStats, Memory>> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(20)))
+    );
+}
+
+/// Operations whose cost is tracked
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TrackedOperation {
+    Insert,
+    Query,
+    Update,
+    Delete,
+}
+
+impl TrackedOperation {
+    const ALL: [TrackedOperation; 4] = [Self::Insert, Self::Query, Self::Update, Self::Delete];
+
+    fn key(self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Accumulated samples of one operation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+struct OperationStats {
+    count: u64,
+    total: u64,
+    min: u64,
+    max: u64,
+    /// Sample count per bucket: one per `BUCKET_BOUNDS` entry plus the overflow bucket
+    buckets: Vec<u64>,
+}
+
+impl_storable!(OperationStats);
+
+/// Cost summary of one operation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OperationMetrics {
+    pub operation: TrackedOperation,
+    pub count: u64,
+    pub min_instructions: u64,
+    pub avg_instructions: u64,
+    pub max_instructions: u64,
+    /// `(upper bound, samples)` per bucket; the last bound is `u64::MAX`
+    pub histogram: Vec<(u64, u64)>,
+}
+
+/// Records an operation's cost when dropped, covering every return path
+pub struct OperationTimer {
+    operation: TrackedOperation,
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        Metrics::record(self.operation, ic_cdk::api::performance_counter(0));
+    }
+}
+
+pub struct Metrics;
+
+impl Metrics {
+    /// Start measuring an operation; the sample is taken when the timer drops
+    pub fn track(operation: TrackedOperation) -> OperationTimer {
+        OperationTimer { operation }
+    }
+
+    /// Fold one sample into an operation's stats
+    pub fn record(operation: TrackedOperation, instructions: u64) {
+        OPERATION_STATS.with(|stats| {
+            let mut stats = stats.borrow_mut();
+            let key = operation.key();
+            let mut entry = stats.get(&key).unwrap_or_default();
+
+            entry.min = if entry.count == 0 { instructions } else { entry.min.min(instructions) };
+            entry.max = entry.max.max(instructions);
+            entry.count += 1;
+            entry.total = entry.total.saturating_add(instructions);
+
+            entry.buckets.resize(BUCKET_BOUNDS.len() + 1, 0);
+            let bucket = BUCKET_BOUNDS.iter()
+                .position(|bound| instructions <= *bound)
+                .unwrap_or(BUCKET_BOUNDS.len());
+            entry.buckets[bucket] += 1;
+
+            stats.insert(key, entry);
+        });
+    }
+
+    /// Number of samples recorded for an operation
+    pub fn count(operation: TrackedOperation) -> u64 {
+        OPERATION_STATS.with(|stats| stats.borrow().get(&operation.key()).map_or(0, |entry| entry.count))
+    }
+
+    /// Cost summary of every tracked operation, including ones not yet run
+    pub fn summary() -> Vec<OperationMetrics> {
+        OPERATION_STATS.with(|stats| {
+            let stats = stats.borrow();
+            TrackedOperation::ALL.iter().map(|operation| {
+                let entry = stats.get(&operation.key()).unwrap_or_default();
+                let bounds = BUCKET_BOUNDS.iter().copied().chain(std::iter::once(u64::MAX));
+
+                OperationMetrics {
+                    operation: *operation,
+                    count: entry.count,
+                    min_instructions: entry.min,
+                    avg_instructions: if entry.count == 0 { 0 } else { entry.total / entry.count },
+                    max_instructions: entry.max,
+                    histogram: bounds
+                        .enumerate()
+                        .map(|(i, bound)| (bound, entry.buckets.get(i).copied().unwrap_or(0)))
+                        .collect(),
+                }
+            }).collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics_of(operation: TrackedOperation) -> OperationMetrics {
+        Metrics::summary().into_iter().find(|metrics| metrics.operation == operation).unwrap()
+    }
+
+    #[test]
+    fn samples_fold_into_min_avg_max_and_buckets() {
+        for instructions in [50_000, 400_000, 2_000_000, 20_000_000_000] {
+            Metrics::record(TrackedOperation::Insert, instructions);
+        }
+
+        let insert = metrics_of(TrackedOperation::Insert);
+        assert_eq!(insert.count, 4);
+        assert_eq!(insert.min_instructions, 50_000);
+        assert_eq!(insert.max_instructions, 20_000_000_000);
+        assert_eq!(insert.avg_instructions, 20_002_450_000 / 4);
+        assert_eq!(insert.histogram, [
+            (100_000, 1),
+            (1_000_000, 1),
+            (10_000_000, 1),
+            (100_000_000, 0),
+            (1_000_000_000, 0),
+            (10_000_000_000, 0),
+            (u64::MAX, 1),
+        ]);
+    }
+
+    #[test]
+    fn operations_not_yet_run_report_zeroes() {
+        Metrics::record(TrackedOperation::Delete, 10);
+
+        let summary = Metrics::summary();
+        assert_eq!(summary.len(), TrackedOperation::ALL.len());
+        let update = metrics_of(TrackedOperation::Update);
+        assert_eq!((update.count, update.min_instructions, update.avg_instructions, update.max_instructions), (0, 0, 0, 0));
+        assert!(update.histogram.iter().all(|(_, samples)| *samples == 0));
+        assert_eq!(metrics_of(TrackedOperation::Delete).histogram[0], (100_000, 1));
+    }
+}
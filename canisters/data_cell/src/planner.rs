@@ -0,0 +1,179 @@
+//! Index selection for Data Cell queries
+
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::schema::IndexDefinition;
+use crate::storage::Storage;
+use crate::{ComparisonOperator, FilterCondition, FilterExpr, Pagination, QueryCostEstimate, QueryFilter};
+
+/// Fixed cost of running a query, independent of how many records it touches
+const QUERY_BASE_CYCLES: u64 = 1_000_000;
+
+/// Rough cost of reading one index entry
+const CYCLES_PER_INDEX_ENTRY: u64 = 20_000;
+
+/// Rough cost of loading, decoding and filtering one record
+const CYCLES_PER_RECORD_SCANNED: u64 = 200_000;
+
+/// How a query reads its candidate records
+enum AccessPath<'a> {
+    Equality { index: IndexDefinition, values: Vec<&'a Value> },
+    Prefix { field: &'a str, prefix: &'a str },
+    Range { field: &'a str, start: Option<&'a Value>, end: Option<&'a Value> },
+    FullScan,
+}
+
+pub struct QueryPlanner;
+
+impl QueryPlanner {
+    /// Choose candidate record IDs from an index, or `None` when a full scan is needed
+    ///
+    /// Candidates are a superset of the matching records; the filter is still
+    /// evaluated against each of them. Only conditions that are AND-ed at the top
+    /// level of the filter are considered. Lazy indexes serve queries once
+    /// their build is complete.
+    pub fn candidate_ids(filter: &QueryFilter) -> Option<Vec<String>> {
+        match Self::access_path(filter) {
+            AccessPath::FullScan => None,
+            path => {
+                let mut ids = Self::scan(&path);
+                ids.sort();
+                Some(ids)
+            },
+        }
+    }
+
+    /// Estimate the work a query would do without loading any records
+    ///
+    /// Index paths are estimated from the cardinality statistics kept alongside
+    /// each index, so no index entries are read either.
+    pub fn estimate(filter: &QueryFilter, pagination: &Pagination) -> QueryCostEstimate {
+        let path = Self::access_path(filter);
+        let records_scanned = Self::estimated_matches(&path);
+
+        let index_used = match &path {
+            AccessPath::Equality { index, .. } => Some(index.name.clone()),
+            AccessPath::Prefix { field, .. } | AccessPath::Range { field, .. } => Some(field.to_string()),
+            AccessPath::FullScan => None,
+        };
+        let index_entries_scanned = if index_used.is_some() { records_scanned } else { 0 };
+
+        QueryCostEstimate {
+            index_used,
+            estimated_records_scanned: records_scanned,
+            estimated_records_returned: records_scanned.saturating_sub(pagination.offset).min(pagination.limit),
+            estimated_cycles: QUERY_BASE_CYCLES
+                + index_entries_scanned * CYCLES_PER_INDEX_ENTRY
+                + records_scanned * CYCLES_PER_RECORD_SCANNED,
+        }
+    }
+
+    /// Rough cost of a full scan over every record, beyond the fixed query cost
+    pub fn full_scan_cycles() -> u64 {
+        Storage::get_stats().record_count * CYCLES_PER_RECORD_SCANNED
+    }
+
+    /// Pick the most selective index access for a filter
+    ///
+    /// Every index fully constrained by equality conditions, and every prefix or
+    /// range condition on an indexed field, is a candidate; the one with the
+    /// fewest estimated matching records wins.
+    fn access_path(filter: &QueryFilter) -> AccessPath<'_> {
+        // Indexes are incomplete while a rebuild is in progress
+        if Storage::is_reindexing() {
+            return AccessPath::FullScan;
+        }
+
+        let conditions = Self::top_level_conditions(filter);
+        if conditions.is_empty() {
+            return AccessPath::FullScan;
+        }
+
+        let equalities: HashMap<&str, &Value> = conditions.iter()
+            .filter(|c| matches!(c.operator, ComparisonOperator::Equals) && !c.case_insensitive)
+            .map(|c| (c.field.as_str(), &c.value))
+            .collect();
+
+        let mut candidates = Vec::new();
+
+        for index in Storage::index_definitions() {
+            if index.fields.is_empty() || !Storage::is_index_ready(&index.name) {
+                continue;
+            }
+
+            let values: Option<Vec<&Value>> = index.fields.iter()
+                .map(|field| equalities.get(field.as_str()).copied())
+                .collect();
+
+            if let Some(values) = values {
+                candidates.push(AccessPath::Equality { index, values });
+            }
+        }
+
+        for condition in conditions {
+            if !Storage::has_ready_field_index(&condition.field) {
+                continue;
+            }
+
+            let field = condition.field.as_str();
+            match (&condition.operator, &condition.value) {
+                (ComparisonOperator::StartsWith, Value::String(prefix)) if !condition.case_insensitive => {
+                    candidates.push(AccessPath::Prefix { field, prefix });
+                },
+                (ComparisonOperator::GreaterThan, value) => {
+                    candidates.push(AccessPath::Range { field, start: Some(value), end: None });
+                },
+                (ComparisonOperator::LessThan, value) => {
+                    candidates.push(AccessPath::Range { field, start: None, end: Some(value) });
+                },
+                _ => {},
+            }
+        }
+
+        candidates.into_iter()
+            .min_by_key(Self::estimated_matches)
+            .unwrap_or(AccessPath::FullScan)
+    }
+
+    /// Records an index access path would read, from index cardinality statistics
+    fn estimated_matches(path: &AccessPath) -> u64 {
+        match path {
+            AccessPath::Equality { index, values } => Storage::count_index_matches(&index.fields, values),
+            AccessPath::Prefix { field, prefix } => Storage::count_prefix_matches(field, prefix),
+            AccessPath::Range { field, start, end } => Storage::count_range_matches(field, *start, *end),
+            AccessPath::FullScan => Storage::get_stats().record_count,
+        }
+    }
+
+    /// Record IDs read through an index access path
+    fn scan(path: &AccessPath) -> Vec<String> {
+        match path {
+            AccessPath::Equality { index, values } if index.fields.len() == 1 => {
+                Storage::query_by_index(&index.fields[0], values[0])
+            },
+            AccessPath::Equality { index, values } => Storage::query_by_composite_index(&index.fields, values),
+            AccessPath::Prefix { field, prefix } => Storage::prefix_query(field, prefix),
+            AccessPath::Range { field, start, end } => Storage::range_query(field, *start, *end),
+            AccessPath::FullScan => Vec::new(),
+        }
+    }
+
+    /// Conditions that every matching record must satisfy
+    fn top_level_conditions(filter: &QueryFilter) -> Vec<&FilterCondition> {
+        let mut conditions: Vec<&FilterCondition> = filter.conditions.iter().collect();
+
+        match &filter.expression {
+            Some(FilterExpr::Condition(condition)) => conditions.push(condition),
+            Some(FilterExpr::And(children)) => {
+                for child in children {
+                    if let FilterExpr::Condition(condition) = child {
+                        conditions.push(condition);
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        conditions
+    }
+}
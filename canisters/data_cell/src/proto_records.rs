@@ -0,0 +1,595 @@
+//! Protocol Buffers encoding of records, guided by the cell's schema
+//!
+//! Records travel as proto3 messages described by `ProtoRecords::proto_file`.
+//! Field mapping:
+//!
+//! | Schema type   | Protobuf type                          |
+//! |---------------|----------------------------------------|
+//! | `Text`        | `string`                               |
+//! | `Number`      | `double`                               |
+//! | `Boolean`     | `bool`                                 |
+//! | `Timestamp`   | `uint64`                               |
+//! | `Principal`   | `string` (principal text)              |
+//! | `Blob`        | `bytes`                                |
+//! | `Array(T)`    | `repeated T` (scalars packed)          |
+//! | `Object`      | nested message                         |
+//!
+//! Arrays of arrays have no protobuf counterpart and are rejected. As in
+//! protobuf, an empty optional array decodes as absent.
+//!
+//! # Field numbering
+//!
+//! A field's number is derived from its name alone: the 32-bit FNV-1a hash
+//! of the name, folded into the valid range `1..=2^29-1` with the reserved
+//! block 19000–19999 skipped. It does not depend on field order, on the
+//! other fields, or on the schema version, so:
+//!
+//! - adding a field never renumbers existing ones, and messages written
+//!   before the field existed decode with it absent;
+//! - removing a field retires its number; an old client still sending it
+//!   has the field skipped as unknown;
+//! - renaming a field is, on the wire, removing one field and adding another.
+//!
+//! Two names hashing to the same number within one message are reported as
+//! an error by every protobuf endpoint; renaming either field resolves it.
+//!
+//! Scalar fields are declared `optional` so encoders keep explicit zero
+//! values, letting the cell tell `false` or `0` apart from a missing field.
+//! Required fields are enforced by the cell on decode, as proto3 has no
+//! `required`.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::blob_store::BlobStore;
+use crate::candid_records::json_number;
+use crate::schema::{FieldDefinition, FieldType, SchemaDefinition};
+use crate::CellError;
+
+/// Largest field number protobuf allows
+const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+/// Field numbers reserved by the protobuf implementation
+const RESERVED_FIELD_NUMBERS: std::ops::RangeInclusive<u32> = 19000..=19999;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+pub struct ProtoRecords;
+
+impl ProtoRecords {
+    /// Field number for a field name, stable across schema versions
+    pub fn field_number(name: &str) -> u32 {
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in name.bytes() {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+
+        let reserved = RESERVED_FIELD_NUMBERS.end() - RESERVED_FIELD_NUMBERS.start() + 1;
+        let number = hash % (MAX_FIELD_NUMBER - reserved) + 1;
+        if number >= *RESERVED_FIELD_NUMBERS.start() {
+            number + reserved
+        } else {
+            number
+        }
+    }
+
+    /// `.proto` file describing records under `schema`; the record message is `Record`
+    pub fn proto_file(schema: &SchemaDefinition) -> Result<String, CellError> {
+        let mut out = format!(
+            "// Generated from schema version {}\nsyntax = \"proto3\";\n\npackage celldb;\n\n",
+            schema.version
+        );
+        write_message(&mut out, "Record", &schema.fields, 0, "record")?;
+        Ok(out)
+    }
+
+    /// Decode a protobuf-encoded `Record` into its JSON form
+    pub fn decode(schema: &SchemaDefinition, bytes: &[u8]) -> Result<Value, CellError> {
+        decode_message(&schema.fields, bytes, "record")
+    }
+
+    /// Encode a JSON record as a protobuf `Record`
+    pub fn encode(schema: &SchemaDefinition, record: &Value) -> Result<Vec<u8>, CellError> {
+        let mut out = Vec::new();
+        encode_message(&schema.fields, record, "record", &mut out)?;
+        Ok(out)
+    }
+}
+
+/// Schema fields with their numbers, in field-number order
+fn numbered_fields<'a>(
+    fields: &'a HashMap<String, FieldDefinition>,
+    path: &str,
+) -> Result<Vec<(u32, &'a String, &'a FieldDefinition)>, CellError> {
+    let mut numbered: Vec<_> = fields.iter()
+        .map(|(name, field)| (ProtoRecords::field_number(name), name, field))
+        .collect();
+    numbered.sort_by_key(|(number, _, _)| *number);
+
+    if let Some(pair) = numbered.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(CellError::SchemaViolation(format!(
+            "{}: fields '{}' and '{}' share protobuf field number {}",
+            path, pair[0].1, pair[1].1, pair[0].0
+        )));
+    }
+
+    Ok(numbered)
+}
+
+/// Scalar types that proto3 packs when repeated
+fn is_packable(field_type: &FieldType) -> bool {
+    matches!(field_type, FieldType::Number | FieldType::Boolean | FieldType::Timestamp)
+}
+
+fn message_name(field_name: &str) -> String {
+    field_name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn write_message(
+    out: &mut String,
+    name: &str,
+    fields: &HashMap<String, FieldDefinition>,
+    depth: usize,
+    path: &str,
+) -> Result<(), CellError> {
+    let indent = "  ".repeat(depth);
+    out.push_str(&format!("{}message {} {{\n", indent, name));
+
+    for (number, field_name, field) in numbered_fields(fields, path)? {
+        let field_path = format!("{}.{}", path, field_name);
+        let (repeated, element_type) = match &field.field_type {
+            FieldType::Array(item_type) => (true, item_type.as_ref()),
+            other => (false, other),
+        };
+
+        let type_name = match element_type {
+            FieldType::Text | FieldType::Principal => "string".to_string(),
+            FieldType::Number => "double".to_string(),
+            FieldType::Boolean => "bool".to_string(),
+            FieldType::Timestamp => "uint64".to_string(),
+            FieldType::Blob => "bytes".to_string(),
+            FieldType::Object(nested) => {
+                let nested_name = message_name(field_name);
+                write_message(out, &nested_name, nested, depth + 1, &field_path)?;
+                nested_name
+            },
+            FieldType::Array(_) => return Err(unsupported_nested_array(&field_path)),
+        };
+
+        let label = if repeated {
+            "repeated "
+        } else if matches!(element_type, FieldType::Object(_)) {
+            ""
+        } else {
+            "optional "
+        };
+        let note = if field.required { " // required" } else { "" };
+        out.push_str(&format!("{}  {}{} {} = {};{}\n", indent, label, type_name, field_name, number, note));
+    }
+
+    out.push_str(&format!("{}}}\n", indent));
+    Ok(())
+}
+
+fn encode_message(
+    fields: &HashMap<String, FieldDefinition>,
+    value: &Value,
+    path: &str,
+    out: &mut Vec<u8>,
+) -> Result<(), CellError> {
+    let obj = value.as_object()
+        .ok_or_else(|| type_error(path, "object"))?;
+
+    for (number, name, field) in numbered_fields(fields, path)? {
+        let field_path = format!("{}.{}", path, name);
+        match obj.get(name).filter(|value| !value.is_null()) {
+            Some(value) => encode_field(number, &field.field_type, value, &field_path, out)?,
+            None if field.required => {
+                return Err(CellError::SchemaViolation(format!("{}: required field missing", field_path)));
+            },
+            None => {},
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_field(number: u32, field_type: &FieldType, value: &Value, path: &str, out: &mut Vec<u8>) -> Result<(), CellError> {
+    let item_type = match field_type {
+        FieldType::Array(item_type) => item_type.as_ref(),
+        _ => return encode_single(number, field_type, value, path, out),
+    };
+
+    if matches!(item_type, FieldType::Array(_)) {
+        return Err(unsupported_nested_array(path));
+    }
+
+    let items = value.as_array().ok_or_else(|| type_error(path, "array"))?;
+    if is_packable(item_type) {
+        let mut packed = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            encode_scalar(item_type, item, &format!("{}[{}]", path, i), &mut packed)?;
+        }
+        write_tag(out, number, WIRE_LEN);
+        write_len_delimited(out, &packed);
+    } else {
+        for (i, item) in items.iter().enumerate() {
+            encode_single(number, item_type, item, &format!("{}[{}]", path, i), out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode one non-repeated value with its tag
+fn encode_single(number: u32, field_type: &FieldType, value: &Value, path: &str, out: &mut Vec<u8>) -> Result<(), CellError> {
+    match field_type {
+        FieldType::Number => write_tag(out, number, WIRE_FIXED64),
+        FieldType::Boolean | FieldType::Timestamp => write_tag(out, number, WIRE_VARINT),
+        _ => write_tag(out, number, WIRE_LEN),
+    }
+
+    match field_type {
+        FieldType::Text => {
+            let text = value.as_str().ok_or_else(|| type_error(path, "string"))?;
+            write_len_delimited(out, text.as_bytes());
+        },
+        FieldType::Principal => {
+            let text = value.as_str()
+                .filter(|text| candid::Principal::from_text(text).is_ok())
+                .ok_or_else(|| type_error(path, "principal text"))?;
+            write_len_delimited(out, text.as_bytes());
+        },
+        FieldType::Blob => {
+            let bytes = value.as_str()
+                .and_then(BlobStore::get)
+                .or_else(|| value.as_array().and_then(|items| items.iter()
+                    .map(|item| item.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                    .collect::<Option<Vec<u8>>>()))
+                .ok_or_else(|| type_error(path, "array of bytes"))?;
+            write_len_delimited(out, &bytes);
+        },
+        FieldType::Object(fields) => {
+            let mut nested = Vec::new();
+            encode_message(fields, value, path, &mut nested)?;
+            write_len_delimited(out, &nested);
+        },
+        FieldType::Number | FieldType::Boolean | FieldType::Timestamp => encode_scalar(field_type, value, path, out)?,
+        FieldType::Array(_) => return Err(unsupported_nested_array(path)),
+    }
+
+    Ok(())
+}
+
+/// Encode a packable scalar without a tag
+fn encode_scalar(field_type: &FieldType, value: &Value, path: &str, out: &mut Vec<u8>) -> Result<(), CellError> {
+    match field_type {
+        FieldType::Number => {
+            let n = value.as_f64().ok_or_else(|| type_error(path, "number"))?;
+            out.extend_from_slice(&n.to_le_bytes());
+        },
+        FieldType::Boolean => {
+            let b = value.as_bool().ok_or_else(|| type_error(path, "boolean"))?;
+            write_varint(out, b as u64);
+        },
+        FieldType::Timestamp => {
+            let n = value.as_u64().ok_or_else(|| type_error(path, "timestamp"))?;
+            write_varint(out, n);
+        },
+        _ => return Err(type_error(path, "scalar")),
+    }
+    Ok(())
+}
+
+fn write_tag(out: &mut Vec<u8>, number: u32, wire_type: u8) {
+    write_varint(out, ((number as u64) << 3) | wire_type as u64);
+}
+
+fn write_len_delimited(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// One field occurrence as read off the wire
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64([u8; 8]),
+    Fixed32([u8; 4]),
+    Bytes(&'a [u8]),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    path: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], path: &'a str) -> Self {
+        Self { bytes, pos: 0, path }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn varint(&mut self) -> Result<u64, CellError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.pos).ok_or_else(|| self.truncated())?;
+            self.pos += 1;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(CellError::ValidationError(format!("{}: malformed varint", self.path)))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CellError> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| self.truncated())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn field(&mut self) -> Result<(u32, WireValue<'a>), CellError> {
+        let key = self.varint()?;
+        let number = u32::try_from(key >> 3)
+            .map_err(|_| CellError::ValidationError(format!("{}: invalid field number", self.path)))?;
+
+        let value = match (key & 0x7) as u8 {
+            WIRE_VARINT => WireValue::Varint(self.varint()?),
+            WIRE_FIXED64 => WireValue::Fixed64(self.take(8)?.try_into().unwrap_or_default()),
+            WIRE_FIXED32 => WireValue::Fixed32(self.take(4)?.try_into().unwrap_or_default()),
+            WIRE_LEN => {
+                let len = self.varint()? as usize;
+                WireValue::Bytes(self.take(len)?)
+            },
+            wire_type => return Err(CellError::ValidationError(format!(
+                "{}: unsupported wire type {} for field {}", self.path, wire_type, number
+            ))),
+        };
+
+        Ok((number, value))
+    }
+
+    fn truncated(&self) -> CellError {
+        CellError::ValidationError(format!("{}: truncated protobuf message", self.path))
+    }
+}
+
+fn decode_message(fields: &HashMap<String, FieldDefinition>, bytes: &[u8], path: &str) -> Result<Value, CellError> {
+    let numbered = numbered_fields(fields, path)?;
+
+    // Unknown field numbers are skipped so messages from other schema versions still decode
+    let mut occurrences: HashMap<u32, Vec<WireValue>> = HashMap::new();
+    let mut reader = Reader::new(bytes, path);
+    while !reader.is_done() {
+        let (number, value) = reader.field()?;
+        occurrences.entry(number).or_default().push(value);
+    }
+
+    let mut obj = serde_json::Map::new();
+    for (number, name, field) in numbered {
+        let field_path = format!("{}.{}", path, name);
+        let values = occurrences.remove(&number).unwrap_or_default();
+
+        let decoded = match &field.field_type {
+            FieldType::Array(item_type) => Some(decode_repeated(item_type, &values, &field_path)?),
+            field_type => match values.last() {
+                // The last occurrence of a singular field wins, as in protobuf
+                Some(value) => Some(decode_single(field_type, value, &field_path)?),
+                None => None,
+            },
+        };
+
+        match decoded {
+            Some(Value::Array(items)) if items.is_empty() && !field.required => {},
+            Some(value) => {
+                obj.insert(name.clone(), value);
+            },
+            None if field.required && field.default_value.is_none() => {
+                return Err(CellError::SchemaViolation(format!("{}: required field missing", field_path)));
+            },
+            None => {},
+        }
+    }
+
+    Ok(Value::Object(obj))
+}
+
+/// Decode every occurrence of a repeated field, packed or not
+fn decode_repeated(item_type: &FieldType, values: &[WireValue], path: &str) -> Result<Value, CellError> {
+    if matches!(item_type, FieldType::Array(_)) {
+        return Err(unsupported_nested_array(path));
+    }
+
+    let mut items = Vec::new();
+    for value in values {
+        match value {
+            WireValue::Bytes(packed) if is_packable(item_type) => {
+                let mut reader = Reader::new(packed, path);
+                while !reader.is_done() {
+                    let element = match item_type {
+                        FieldType::Number => WireValue::Fixed64(reader.take(8)?.try_into().unwrap_or_default()),
+                        _ => WireValue::Varint(reader.varint()?),
+                    };
+                    items.push(decode_single(item_type, &element, &format!("{}[{}]", path, items.len()))?);
+                }
+            },
+            value => items.push(decode_single(item_type, value, &format!("{}[{}]", path, items.len()))?),
+        }
+    }
+
+    Ok(Value::Array(items))
+}
+
+fn decode_single(field_type: &FieldType, value: &WireValue, path: &str) -> Result<Value, CellError> {
+    match (field_type, value) {
+        (FieldType::Text, WireValue::Bytes(bytes)) => std::str::from_utf8(bytes)
+            .map(|text| Value::String(text.to_string()))
+            .map_err(|_| type_error(path, "UTF-8 string")),
+        (FieldType::Principal, WireValue::Bytes(bytes)) => std::str::from_utf8(bytes).ok()
+            .filter(|text| candid::Principal::from_text(text).is_ok())
+            .map(|text| Value::String(text.to_string()))
+            .ok_or_else(|| type_error(path, "principal text")),
+        (FieldType::Blob, WireValue::Bytes(bytes)) => Ok(Value::from(bytes.to_vec())),
+        (FieldType::Object(fields), WireValue::Bytes(bytes)) => decode_message(fields, bytes, path),
+        (FieldType::Number, WireValue::Fixed64(bytes)) => json_number(f64::from_le_bytes(*bytes))
+            .ok_or_else(|| type_error(path, "finite number")),
+        (FieldType::Number, WireValue::Fixed32(bytes)) => json_number(f32::from_le_bytes(*bytes) as f64)
+            .ok_or_else(|| type_error(path, "finite number")),
+        (FieldType::Boolean, WireValue::Varint(n)) => Ok(Value::Bool(*n != 0)),
+        (FieldType::Timestamp, WireValue::Varint(n)) => Ok(Value::from(*n)),
+        (FieldType::Text, _) => Err(type_error(path, "string")),
+        (FieldType::Principal, _) => Err(type_error(path, "string")),
+        (FieldType::Blob, _) => Err(type_error(path, "bytes")),
+        (FieldType::Object(_), _) => Err(type_error(path, "message")),
+        (FieldType::Number, _) => Err(type_error(path, "double")),
+        (FieldType::Boolean, _) => Err(type_error(path, "bool")),
+        (FieldType::Timestamp, _) => Err(type_error(path, "uint64")),
+        (FieldType::Array(_), _) => Err(unsupported_nested_array(path)),
+    }
+}
+
+fn unsupported_nested_array(path: &str) -> CellError {
+    CellError::ValidationError(format!("{}: arrays of arrays cannot be represented in protobuf", path))
+}
+
+fn type_error(path: &str, expected: &str) -> CellError {
+    CellError::ValidationError(format!("{}: expected {}", path, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(field_type: FieldType) -> FieldDefinition {
+        FieldDefinition {
+            field_type,
+            required: false,
+            default_value: None,
+            validation_rules: Vec::new(),
+            coerce: None,
+            auto_timestamp: None,
+        }
+    }
+
+    fn schema(version: u32, fields: Vec<(&str, FieldDefinition)>) -> SchemaDefinition {
+        SchemaDefinition {
+            version,
+            name: "orders".to_string(),
+            fields: fields.into_iter().map(|(name, field)| (name.to_string(), field)).collect(),
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    /// An order with a required customer, packed and unpacked arrays and a nested address
+    fn order_schema() -> SchemaDefinition {
+        let address: HashMap<String, FieldDefinition> = [
+            ("city".to_string(), FieldDefinition { required: true, ..field(FieldType::Text) }),
+            ("zip".to_string(), field(FieldType::Number)),
+        ].into_iter().collect();
+
+        schema(1, vec![
+            ("customer", FieldDefinition { required: true, ..field(FieldType::Text) }),
+            ("total", field(FieldType::Number)),
+            ("paid", field(FieldType::Boolean)),
+            ("placed_at", field(FieldType::Timestamp)),
+            ("quantities", field(FieldType::Array(Box::new(FieldType::Number)))),
+            ("notes", field(FieldType::Array(Box::new(FieldType::Text)))),
+            ("address", field(FieldType::Object(address))),
+        ])
+    }
+
+    fn round_trip(schema: &SchemaDefinition, record: &Value) -> Value {
+        let bytes = ProtoRecords::encode(schema, record).unwrap();
+        ProtoRecords::decode(schema, &bytes).unwrap()
+    }
+
+    #[test]
+    fn records_round_trip_to_their_json_form() {
+        let record = json!({
+            "customer": "ada",
+            "total": 12.5,
+            "paid": true,
+            "placed_at": 1_700_000_000_000_000_000u64,
+            "quantities": [1, 2.5, 3],
+            "notes": ["fragile", "gift"],
+            "address": {"city": "Nairobi", "zip": 100},
+        });
+
+        assert_eq!(round_trip(&order_schema(), &record), record);
+    }
+
+    #[test]
+    fn explicit_zero_values_survive_and_absent_fields_stay_absent() {
+        let record = json!({"customer": "bob", "total": 0, "paid": false, "quantities": []});
+
+        assert_eq!(round_trip(&order_schema(), &record), json!({"customer": "bob", "total": 0, "paid": false}));
+    }
+
+    #[test]
+    fn messages_decode_across_schema_versions() {
+        let v1 = schema(1, vec![("customer", FieldDefinition { required: true, ..field(FieldType::Text) })]);
+        let v2 = schema(2, vec![
+            ("customer", FieldDefinition { required: true, ..field(FieldType::Text) }),
+            ("total", field(FieldType::Number)),
+        ]);
+
+        // A field added in v2 is absent from v1 messages
+        let old = ProtoRecords::encode(&v1, &json!({"customer": "ada"})).unwrap();
+        assert_eq!(ProtoRecords::decode(&v2, &old).unwrap(), json!({"customer": "ada"}));
+
+        // and skipped as unknown by v1 readers
+        let new = ProtoRecords::encode(&v2, &json!({"customer": "ada", "total": 4})).unwrap();
+        assert_eq!(ProtoRecords::decode(&v1, &new).unwrap(), json!({"customer": "ada"}));
+    }
+
+    #[test]
+    fn field_numbers_depend_only_on_the_name() {
+        let number = ProtoRecords::field_number("customer");
+        assert_eq!(ProtoRecords::field_number("customer"), number);
+        assert!((1..=MAX_FIELD_NUMBER).contains(&number));
+
+        for i in 0..10_000 {
+            let number = ProtoRecords::field_number(&format!("field_{}", i));
+            assert!((1..=MAX_FIELD_NUMBER).contains(&number));
+            assert!(!RESERVED_FIELD_NUMBERS.contains(&number));
+        }
+
+        let file = ProtoRecords::proto_file(&order_schema()).unwrap();
+        assert!(file.contains(&format!("customer = {};", number)), "{}", file);
+    }
+
+    #[test]
+    fn missing_required_fields_are_rejected() {
+        let bytes = ProtoRecords::encode(&schema(1, vec![("total", field(FieldType::Number))]), &json!({"total": 1})).unwrap();
+
+        assert!(matches!(ProtoRecords::decode(&order_schema(), &bytes), Err(CellError::SchemaViolation(_))));
+        assert!(matches!(ProtoRecords::decode(&order_schema(), &[0xff]), Err(CellError::ValidationError(_))));
+    }
+}
@@ -0,0 +1,97 @@
+//! Small in-heap cache of query results, cleared on every write
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use crate::{Pagination, QueryFilter, QueryResult};
+
+/// Maximum number of cached results; the least recently used is evicted beyond this
+const MAX_CACHED_QUERIES: usize = 64;
+
+/// How long a cached result may be served
+const QUERY_CACHE_TTL_NANOS: u64 = 30 * 1_000_000_000;
+
+thread_local! {
+    static ENTRIES: RefCell<HashMap<String, CachedQuery>> = RefCell::new(HashMap::new());
+
+    /// Monotonic counter ordering entries by last use
+    static USE_CLOCK: Cell<u64> = Cell::new(0);
+}
+
+struct CachedQuery {
+    result: QueryResult,
+    expires_at: u64,
+    last_used: u64,
+}
+
+pub struct QueryCache;
+
+impl QueryCache {
+    /// Cache key for a filter and page; `None` if they can't be serialized
+    ///
+    /// The filter's `trace_id` is left out, since it doesn't change the result.
+    pub fn key(filter: &QueryFilter, pagination: &Pagination) -> Option<String> {
+        let mut key = serde_json::to_value((filter, pagination)).ok()?;
+        if let Some(filter) = key.get_mut(0).and_then(serde_json::Value::as_object_mut) {
+            filter.remove("trace_id");
+        }
+        Some(key.to_string())
+    }
+
+    /// Cached result for `key`, if present and fresh
+    pub fn get(key: &str) -> Option<QueryResult> {
+        let now = ic_cdk::api::time();
+        let tick = Self::tick();
+
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            match entries.get_mut(key) {
+                Some(entry) if entry.expires_at > now => {
+                    entry.last_used = tick;
+                    Some(entry.result.clone())
+                },
+                Some(_) => {
+                    entries.remove(key);
+                    None
+                },
+                None => None,
+            }
+        })
+    }
+
+    /// Store a result, evicting the least recently used entry when full
+    ///
+    /// The entry is served until the TTL runs out or `records_expire_at`,
+    /// the earliest expiry among the records behind the result, whichever
+    /// comes first. Entries written during a query call are discarded with
+    /// the rest of that call's state, so only update calls populate the cache.
+    pub fn put(key: String, result: QueryResult, records_expire_at: Option<u64>) {
+        let expires_at = (ic_cdk::api::time() + QUERY_CACHE_TTL_NANOS).min(records_expire_at.unwrap_or(u64::MAX));
+        let tick = Self::tick();
+
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            if !entries.contains_key(&key) && entries.len() >= MAX_CACHED_QUERIES {
+                let oldest = entries.iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+            entries.insert(key, CachedQuery { result, expires_at, last_used: tick });
+        });
+    }
+
+    /// Drop every cached result; called whenever a record is written or removed
+    pub fn invalidate() {
+        ENTRIES.with(|entries| entries.borrow_mut().clear());
+    }
+
+    fn tick() -> u64 {
+        USE_CLOCK.with(|clock| {
+            let value = clock.get() + 1;
+            clock.set(value);
+            value
+        })
+    }
+}
@@ -0,0 +1,100 @@
+//! Per-caller token bucket rate limiting for Data Cell writes
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::access_control::AccessControl;
+use crate::settings::Settings;
+use crate::storage::{impl_storable, memory, Memory};
+use crate::CellError;
+
+/// Tokens are tracked in millionths so partial refills aren't lost
+const MICROS_PER_TOKEN: u128 = 1_000_000;
+
+/// Window over which a full bucket refills
+const REFILL_WINDOW_NANOS: u128 = 60 * 1_000_000_000;
+
+type BucketStorage = StableBTreeMap<Principal, TokenBucket, Memory>;
+
+thread_local! {
+    static BUCKETS: RefCell<BucketStorage> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(11)))
+    );
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct TokenBucket {
+    /// Available tokens, in millionths of a token
+    micro_tokens: u64,
+    last_refill: u64,
+}
+
+impl_storable!(TokenBucket);
+
+pub struct RateLimiter;
+
+impl RateLimiter {
+    /// Take one write token for `caller`, failing with `RateLimited` when none remain
+    ///
+    /// Each caller's bucket holds `write_rate_limit_per_minute` tokens and
+    /// refills continuously over a minute. Admins and cells without a limit
+    /// are never throttled.
+    pub fn check_write(caller: Principal) -> Result<(), CellError> {
+        let limit = match Settings::get().write_rate_limit_per_minute {
+            Some(limit) => limit as u128,
+            None => return Ok(()),
+        };
+
+        if AccessControl::is_admin(caller) {
+            return Ok(());
+        }
+
+        let now = ic_cdk::api::time();
+        let capacity = limit * MICROS_PER_TOKEN;
+
+        BUCKETS.with(|buckets| {
+            let mut buckets_ref = buckets.borrow_mut();
+            let mut bucket = buckets_ref.get(&caller).unwrap_or(TokenBucket {
+                micro_tokens: capacity as u64,
+                last_refill: now,
+            });
+
+            let elapsed = now.saturating_sub(bucket.last_refill) as u128;
+            let refill = elapsed * capacity / REFILL_WINDOW_NANOS;
+            bucket.micro_tokens = (bucket.micro_tokens as u128 + refill).min(capacity) as u64;
+            bucket.last_refill = now;
+
+            let allowed = bucket.micro_tokens as u128 >= MICROS_PER_TOKEN;
+            if allowed {
+                bucket.micro_tokens -= MICROS_PER_TOKEN as u64;
+            }
+            buckets_ref.insert(caller, bucket);
+
+            if allowed {
+                Ok(())
+            } else {
+                Err(CellError::RateLimited)
+            }
+        })
+    }
+
+    /// Drop buckets idle long enough to have refilled completely
+    ///
+    /// A full bucket behaves exactly like a missing one, so this frees memory
+    /// without loosening any limit.
+    pub fn prune_idle(now: u64) -> u64 {
+        BUCKETS.with(|buckets| {
+            let mut buckets_ref = buckets.borrow_mut();
+            let idle: Vec<Principal> = buckets_ref.iter()
+                .filter(|(_, bucket)| now.saturating_sub(bucket.last_refill) as u128 >= REFILL_WINDOW_NANOS)
+                .map(|(caller, _)| caller)
+                .collect();
+
+            for caller in &idle {
+                buckets_ref.remove(caller);
+            }
+            idle.len() as u64
+        })
+    }
+}
@@ -0,0 +1,187 @@
+//! Record ID generation strategies for Data Cells
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableCell, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use crate::settings::Settings;
+use crate::storage::{memory, Memory, Storage};
+use crate::CellError;
+
+thread_local! {
+    /// Last ID issued by the `Monotonic` strategy
+    static LAST_SEQUENCE: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(memory(MemoryId::new(12)), 0)
+            .expect("Failed to initialize record ID sequence")
+    );
+}
+
+/// How `insert` assigns record IDs
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum IdStrategy {
+    /// Random version 4 UUID
+    Uuid,
+    /// Zero-padded sequence number, so IDs sort in insertion order
+    Monotonic,
+    /// Value of the named field, which must be present and unique
+    FromField(String),
+}
+
+pub struct RecordIds;
+
+impl RecordIds {
+    /// Generate an ID for a record about to be inserted
+    ///
+    /// Without a configured strategy IDs are derived from the insertion time.
+    /// `FromField` fails with `SchemaViolation` when the key field is missing,
+    /// not a string or number, or already used by another unexpired record.
+    pub async fn generate(data: &Value) -> Result<String, CellError> {
+        match Settings::get().id_strategy {
+            None => Ok(Self::timestamp_id()),
+            Some(IdStrategy::Monotonic) => Self::next_sequence_id(),
+            Some(IdStrategy::Uuid) => Self::uuid_id().await,
+            Some(IdStrategy::FromField(field)) => {
+                let record_id = Self::natural_key(&field, data)?;
+                if Storage::has_live_record(&record_id) {
+                    return Err(CellError::SchemaViolation(
+                        format!("Duplicate value for key field '{}': {}", field, record_id)
+                    ));
+                }
+                Ok(record_id)
+            },
+        }
+    }
+
+    /// Accept a caller-supplied ID for a new record, as `generate` would have issued one
+    ///
+    /// The ID must not be used by another unexpired record, and under
+    /// `FromField` it must equal the record's key field. The `Monotonic`
+    /// sequence is advanced past it, so the ID is never issued again; an ID
+    /// of `u64::MAX` is refused there, since it would exhaust the sequence.
+    pub fn claim(record_id: &str, data: &Value) -> Result<(), CellError> {
+        match Settings::get().id_strategy {
+            Some(IdStrategy::FromField(field)) => {
+                if Self::natural_key(&field, data)? != record_id {
+                    return Err(CellError::SchemaViolation(
+                        format!("Record ID {} does not match key field '{}'", record_id, field)
+                    ));
+                }
+            },
+            Some(IdStrategy::Monotonic) if record_id.parse::<u64>() == Ok(u64::MAX) => {
+                return Err(CellError::SchemaViolation(
+                    format!("Record ID {} would exhaust the record ID sequence", record_id)
+                ));
+            },
+            _ => {},
+        }
+        if Storage::has_live_record(record_id) {
+            return Err(CellError::SchemaViolation(format!("Record {} already exists", record_id)));
+        }
+
+        Self::advance_past(record_id);
+        Ok(())
+    }
+
+    /// ID an insert of `data` would use when it is known in advance (`FromField` only)
+    pub fn target_id(data: &Value) -> Result<Option<String>, CellError> {
+        match Settings::get().id_strategy {
+            Some(IdStrategy::FromField(field)) => Self::natural_key(&field, data).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reject updates that would change the value of a `FromField` key
+    pub fn check_key_unchanged(record_id: &str, record: &Value) -> Result<(), CellError> {
+        if let Some(IdStrategy::FromField(field)) = Settings::get().id_strategy {
+            if Self::natural_key(&field, record)? != record_id {
+                return Err(CellError::SchemaViolation(
+                    format!("Key field '{}' cannot be changed", field)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record ID taken from a key field's string or number value
+    fn natural_key(field: &str, data: &Value) -> Result<String, CellError> {
+        match data.get(field) {
+            Some(Value::String(key)) if !key.is_empty() => Ok(key.clone()),
+            Some(Value::Number(key)) => Ok(key.to_string()),
+            Some(_) => Err(CellError::SchemaViolation(
+                format!("Key field '{}' must be a non-empty string or a number", field)
+            )),
+            None => Err(CellError::SchemaViolation(
+                format!("Missing key field '{}'", field)
+            )),
+        }
+    }
+
+    /// Time-based ID, suffixed if another record was inserted in the same round
+    fn timestamp_id() -> String {
+        let base = format!("record_{}", ic_cdk::api::time());
+        let mut record_id = base.clone();
+        let mut suffix = 0u32;
+
+        while Storage::has_record(&record_id) {
+            suffix += 1;
+            record_id = format!("{}_{}", base, suffix);
+        }
+
+        record_id
+    }
+
+    /// Advance the `Monotonic` sequence past a record ID restored from elsewhere
+    ///
+    /// IDs that aren't sequence numbers are ignored.
+    pub fn advance_past(record_id: &str) {
+        let sequence = match record_id.parse::<u64>() {
+            Ok(sequence) => sequence,
+            Err(_) => return,
+        };
+
+        LAST_SEQUENCE.with(|last| {
+            let mut last = last.borrow_mut();
+            if sequence > *last.get() {
+                last.set(sequence).expect("Failed to persist record ID sequence");
+            }
+        });
+    }
+
+    /// Next sequence ID, skipping any already taken by imported or cloned records
+    ///
+    /// Fails with `StorageError` once the sequence has reached `u64::MAX`.
+    fn next_sequence_id() -> Result<String, CellError> {
+        LAST_SEQUENCE.with(|last| {
+            let mut last = last.borrow_mut();
+            let mut sequence = *last.get();
+            let record_id = loop {
+                sequence = sequence.checked_add(1)
+                    .ok_or_else(|| CellError::StorageError("Record ID sequence is exhausted".to_string()))?;
+                let record_id = format!("{:020}", sequence);
+                if !Storage::has_record(&record_id) {
+                    break record_id;
+                }
+            };
+            last.set(sequence).expect("Failed to persist record ID sequence");
+            Ok(record_id)
+        })
+    }
+
+    /// Version 4 UUID from the management canister's randomness
+    async fn uuid_id() -> Result<String, CellError> {
+        let (bytes,): (Vec<u8>,) = ic_cdk::call(Principal::management_canister(), "raw_rand", ())
+            .await
+            .map_err(|(code, msg)| CellError::StorageError(
+                format!("Failed to obtain randomness: {:?} - {}", code, msg)
+            ))?;
+
+        let mut uuid = [0u8; 16];
+        uuid.copy_from_slice(&bytes[..16]);
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+
+        let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+    }
+}
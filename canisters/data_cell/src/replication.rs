@@ -0,0 +1,176 @@
+//! Read replica mode: following another cell through its change feed
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::change_feed::{ChangeEvent, ChangeFeed, ChangeOperation};
+use crate::settings::Settings;
+use crate::storage::Storage;
+use crate::{CellError, ExportChunk};
+
+/// Records requested per `export_chunk` call while backfilling
+const BACKFILL_CHUNK_SIZE: u32 = 500;
+
+/// Delay before retrying a failed subscription or backfill
+const SYNC_RETRY_DELAY_SECONDS: u64 = 60;
+
+/// Primary method the replica subscribes with
+const APPLY_CHANGE_METHOD: &str = "apply_change";
+
+thread_local! {
+    static STATUS: RefCell<ReplicaStatus> = RefCell::new(ReplicaStatus::default());
+}
+
+/// Replication progress of a replica cell
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReplicaStatus {
+    /// Cell this replica follows; `None` on a primary
+    pub primary: Option<Principal>,
+    /// Subscribed and done copying the primary's existing records
+    pub synced: bool,
+    pub records_backfilled: u64,
+    pub changes_applied: u64,
+    /// Timestamp of the last change event applied
+    pub last_change_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+pub struct Replication;
+
+impl Replication {
+    /// Primary cell followed by this cell, if it is a replica
+    pub fn primary() -> Option<Principal> {
+        Settings::get().replica_of
+    }
+
+    pub fn is_replica() -> bool {
+        Self::primary().is_some()
+    }
+
+    /// Reject writes on replicas, whose records only change through the primary
+    pub fn ensure_writable() -> Result<(), CellError> {
+        if Self::is_replica() {
+            Err(CellError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start following the primary, if configured; called from init and post_upgrade
+    ///
+    /// The replica subscribes to the primary's change feed before copying its
+    /// existing records, so no change made during the copy is missed.
+    pub fn start() {
+        if let Some(primary) = Self::primary() {
+            STATUS.with(|status| status.borrow_mut().primary = Some(primary));
+            Self::schedule_sync(Duration::ZERO);
+        }
+    }
+
+    fn schedule_sync(delay: Duration) {
+        ic_cdk_timers::set_timer(delay, || {
+            ic_cdk::spawn(async {
+                if let Err(error) = Self::sync().await {
+                    ic_cdk::println!("Replica sync failed, retrying: {}", error);
+                    STATUS.with(|status| status.borrow_mut().last_error = Some(error));
+                    Self::schedule_sync(Duration::from_secs(SYNC_RETRY_DELAY_SECONDS));
+                }
+            });
+        });
+    }
+
+    /// Subscribe to the primary and copy over all of its records
+    async fn sync() -> Result<(), String> {
+        let primary = Self::primary().ok_or("Cell is not a replica")?;
+
+        let (subscribed,): (Result<(), CellError>,) =
+            ic_cdk::call(primary, "subscribe", (ic_cdk::id(), APPLY_CHANGE_METHOD.to_string()))
+                .await
+                .map_err(|(code, msg)| format!("subscribe failed: {:?} - {}", code, msg))?;
+        subscribed.map_err(|e| format!("primary rejected subscription: {:?}", e))?;
+
+        let mut cursor: Option<String> = None;
+        loop {
+            let (chunk,): (ExportChunk,) =
+                ic_cdk::call(primary, "export_chunk", (cursor.clone(), BACKFILL_CHUNK_SIZE))
+                    .await
+                    .map_err(|(code, msg)| format!("export_chunk failed: {:?} - {}", code, msg))?;
+
+            let versions: HashMap<&String, u64> = chunk.versions.iter().map(|(id, version)| (id, *version)).collect();
+            let expirations: HashMap<&String, u64> = chunk.expirations.iter().map(|(id, at)| (id, *at)).collect();
+
+            for (record_id, record) in &chunk.records {
+                // The feed already delivered this record's state as of the chunk or later
+                let version = versions.get(record_id).copied().unwrap_or(0);
+                if Storage::holds_version(record_id, version) {
+                    continue;
+                }
+
+                let record: serde_json::Value = serde_json::from_str(record)
+                    .map_err(|e| format!("Invalid record {} from primary: {}", record_id, e))?;
+                let previous = Storage::get_json_record(record_id);
+                Storage::put_json_record(record_id, &record, previous.as_ref())?;
+                match expirations.get(record_id) {
+                    Some(expires_at) => Storage::set_expiry(record_id, *expires_at),
+                    None => Storage::clear_expiry(record_id),
+                }
+                Storage::set_record_version(record_id, version);
+            }
+            STATUS.with(|status| status.borrow_mut().records_backfilled += chunk.records.len() as u64);
+
+            match chunk.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        STATUS.with(|status| {
+            let mut status = status.borrow_mut();
+            status.synced = true;
+            status.last_error = None;
+        });
+        Ok(())
+    }
+
+    /// Apply a change event delivered by the primary, forwarding it to this cell's own subscribers
+    ///
+    /// Events no newer than the version already held, such as a redelivered
+    /// dead letter, are acknowledged but skipped.
+    pub fn apply_change(event: ChangeEvent) -> Result<(), String> {
+        if Storage::holds_version(&event.record_id, event.version) {
+            return Ok(());
+        }
+
+        let previous = Storage::get_json_record(&event.record_id);
+
+        match (&event.op, &event.record) {
+            (ChangeOperation::Insert | ChangeOperation::Update, Some(record)) => {
+                Storage::put_json_record(&event.record_id, record, previous.as_ref())?;
+                match event.expires_at {
+                    Some(expires_at) => Storage::set_expiry(&event.record_id, expires_at),
+                    None => Storage::clear_expiry(&event.record_id),
+                }
+            },
+            (ChangeOperation::Delete, _) => {
+                Storage::remove_json_record(&event.record_id);
+            },
+            (_, None) => return Err(format!("Change event for {} carries no record", event.record_id)),
+        }
+        Storage::set_record_version(&event.record_id, event.version);
+
+        STATUS.with(|status| {
+            let mut status = status.borrow_mut();
+            status.changes_applied += 1;
+            status.last_change_at = Some(event.timestamp);
+        });
+
+        ChangeFeed::forward(event);
+        Ok(())
+    }
+
+    pub fn status() -> ReplicaStatus {
+        STATUS.with(|status| status.borrow().clone())
+    }
+}
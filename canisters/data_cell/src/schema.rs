@@ -3,9 +3,10 @@
 use candid::{CandidType};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use crate::storage::impl_storable;
 
 /// Schema definition for a Data Cell
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SchemaDefinition {
     pub version: u32,
     pub name: String,
@@ -14,12 +15,29 @@ pub struct SchemaDefinition {
     pub constraints: Vec<ConstraintDefinition>,
 }
 
+impl_storable!(SchemaDefinition);
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct FieldDefinition {
     pub field_type: FieldType,
     pub required: bool,
     pub default_value: Option<serde_json::Value>,
     pub validation_rules: Vec<ValidationRule>,
+    /// Convert string values to this field's type before validating; overrides the cell setting
+    #[serde(default)]
+    pub coerce: Option<bool>,
+    /// Fill this field with the cell's clock; client-supplied values are ignored
+    #[serde(default)]
+    pub auto_timestamp: Option<AutoTimestamp>,
+}
+
+/// When an auto-timestamp field is set
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AutoTimestamp {
+    /// Set on insert and kept unchanged by updates (`created_at`)
+    OnCreate,
+    /// Set on insert and on every update (`updated_at`)
+    OnUpdate,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -39,8 +57,13 @@ pub struct IndexDefinition {
     pub name: String,
     pub fields: Vec<String>,
     pub unique: bool,
+    /// Build the index in the background once a query could use it, when added with `add_index`
+    #[serde(default)]
+    pub lazy: Option<bool>,
 }
 
+impl_storable!(IndexDefinition);
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum ConstraintDefinition {
     Unique(Vec<String>),
@@ -58,6 +81,14 @@ pub enum ValidationRule {
     Pattern(String),
     Range(i64, i64),
     Custom(String),
+    Email,
+    Url,
+    Uuid,
+    OneOf(Vec<serde_json::Value>),
+    /// Largest `Blob` in bytes, stored inline or uploaded with `put_blob`
+    MaxSize(u64),
+    /// Most items an `Array` may hold
+    MaxItems(u32),
 }
 
 impl SchemaDefinition {
@@ -70,6 +101,61 @@ impl SchemaDefinition {
         Ok(())
     }
 
+    /// Fill absent or null fields with their schema default values
+    pub fn apply_defaults(&self, data: &mut serde_json::Value) {
+        if let serde_json::Value::Object(obj) = data {
+            for (field_name, field_def) in &self.fields {
+                if let Some(default_value) = &field_def.default_value {
+                    let missing = obj.get(field_name).map_or(true, |v| v.is_null());
+                    if missing {
+                        obj.insert(field_name.clone(), default_value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Overwrite auto-timestamp fields with `now` (nanoseconds since epoch)
+    ///
+    /// `previous` is the stored record on update, whose `OnCreate` values are
+    /// carried over; pass `None` on insert.
+    pub fn apply_timestamps(&self, data: &mut serde_json::Value, previous: Option<&serde_json::Value>, now: u64) {
+        if let serde_json::Value::Object(obj) = data {
+            for (field_name, field_def) in &self.fields {
+                let value = match (field_def.auto_timestamp, previous) {
+                    (None, _) => continue,
+                    (Some(AutoTimestamp::OnCreate), Some(previous)) => match previous.get(field_name) {
+                        Some(created) => created.clone(),
+                        None => serde_json::Value::from(now),
+                    },
+                    (Some(_), _) => serde_json::Value::from(now),
+                };
+                obj.insert(field_name.clone(), value);
+            }
+        }
+    }
+
+    /// Name of the `OnUpdate` auto-timestamp field, which records when each record last changed
+    pub fn updated_at_field(&self) -> Option<&str> {
+        self.fields.iter()
+            .find(|(_, field_def)| field_def.auto_timestamp == Some(AutoTimestamp::OnUpdate))
+            .map(|(field_name, _)| field_name.as_str())
+    }
+
+    /// Render this schema as a JSON Schema (draft 2020-12) document
+    ///
+    /// `Custom` rules can't be expressed and are listed under the
+    /// `x-celldb-custom` extension keyword instead.
+    pub fn to_json_schema(&self) -> serde_json::Value {
+        let mut document = object_json_schema(&self.fields);
+        if let serde_json::Value::Object(obj) = &mut document {
+            obj.insert("$schema".to_string(), "https://json-schema.org/draft/2020-12/schema".into());
+            obj.insert("title".to_string(), self.name.clone().into());
+            obj.insert("x-celldb-schema-version".to_string(), self.version.into());
+        }
+        document
+    }
+
     /// Check if schema can be upgraded to new version
     pub fn can_upgrade_to(&self, new_schema: &SchemaDefinition) -> Result<(), String> {
         // TODO: Implement schema compatibility check
@@ -90,4 +176,86 @@ impl SchemaDefinition {
             .map(|s| s.as_str())
             .collect()
     }
-}
\ No newline at end of file
+}
+
+/// JSON Schema of an object with the given fields, sorted by name
+fn object_json_schema(fields: &HashMap<String, FieldDefinition>) -> serde_json::Value {
+    let mut names: Vec<&String> = fields.keys().collect();
+    names.sort();
+
+    let properties: serde_json::Map<String, serde_json::Value> = names.iter()
+        .map(|name| (name.to_string(), field_json_schema(&fields[*name])))
+        .collect();
+    let required: Vec<&String> = names.into_iter()
+        .filter(|name| fields[*name].required)
+        .collect();
+
+    serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// JSON Schema of a single field, with its validation rules
+fn field_json_schema(field: &FieldDefinition) -> serde_json::Value {
+    let mut schema = type_json_schema(&field.field_type);
+    let obj = match &mut schema {
+        serde_json::Value::Object(obj) => obj,
+        _ => return schema,
+    };
+
+    let mut custom = Vec::new();
+    for rule in &field.validation_rules {
+        match rule {
+            ValidationRule::MinLength(min) => { obj.insert("minLength".to_string(), (*min).into()); },
+            ValidationRule::MaxLength(max) => { obj.insert("maxLength".to_string(), (*max).into()); },
+            ValidationRule::Pattern(pattern) => { obj.insert("pattern".to_string(), pattern.clone().into()); },
+            ValidationRule::Range(min, max) => {
+                obj.insert("minimum".to_string(), (*min).into());
+                obj.insert("maximum".to_string(), (*max).into());
+            },
+            ValidationRule::Email => { obj.insert("format".to_string(), "email".into()); },
+            ValidationRule::Url => { obj.insert("format".to_string(), "uri".into()); },
+            ValidationRule::Uuid => { obj.insert("format".to_string(), "uuid".into()); },
+            ValidationRule::OneOf(values) => { obj.insert("enum".to_string(), values.clone().into()); },
+            ValidationRule::MaxSize(max) => {
+                obj.insert("description".to_string(), format!("Binary data of at most {} bytes", max).into());
+            },
+            ValidationRule::MaxItems(max) => { obj.insert("maxItems".to_string(), (*max).into()); },
+            ValidationRule::Custom(name) => custom.push(serde_json::Value::from(name.clone())),
+        }
+    }
+
+    if !custom.is_empty() {
+        obj.insert("x-celldb-custom".to_string(), custom.into());
+    }
+    if let Some(default_value) = &field.default_value {
+        obj.insert("default".to_string(), default_value.clone());
+    }
+    if field.auto_timestamp.is_some() {
+        obj.insert("readOnly".to_string(), true.into());
+    }
+
+    schema
+}
+
+/// JSON Schema type of a field type; types the cell doesn't check stay unconstrained
+fn type_json_schema(field_type: &FieldType) -> serde_json::Value {
+    match field_type {
+        FieldType::Text => serde_json::json!({ "type": "string" }),
+        FieldType::Number => serde_json::json!({ "type": "number" }),
+        FieldType::Boolean => serde_json::json!({ "type": "boolean" }),
+        FieldType::Timestamp => serde_json::json!({
+            "type": "integer",
+            "description": "Nanoseconds since the Unix epoch",
+        }),
+        FieldType::Principal => serde_json::json!({ "description": "Principal" }),
+        FieldType::Blob => serde_json::json!({ "description": "Binary data" }),
+        FieldType::Array(item_type) => serde_json::json!({
+            "type": "array",
+            "items": type_json_schema(item_type),
+        }),
+        FieldType::Object(fields) => object_json_schema(fields),
+    }
+}
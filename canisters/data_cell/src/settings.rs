@@ -0,0 +1,84 @@
+//! Persistent runtime settings for Data Cells
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableCell, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::record_ids::IdStrategy;
+use crate::storage::{impl_storable, memory, Memory};
+use crate::CellInitConfig;
+
+thread_local! {
+    static SETTINGS: RefCell<StableCell<CellSettings, Memory>> = RefCell::new(
+        StableCell::init(
+            memory(MemoryId::new(5)),
+            CellSettings::default(),
+        ).expect("Failed to initialize cell settings")
+    );
+}
+
+/// Cell-level behaviour configured at initialization
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CellSettings {
+    /// Lifetime applied to inserted records that don't specify `expires_at`
+    pub default_ttl_seconds: Option<u64>,
+    /// Writes allowed per caller per minute; `None` disables rate limiting
+    pub write_rate_limit_per_minute: Option<u32>,
+    /// Report only the first validation error on insert and update
+    pub fail_fast_validation: bool,
+    /// Canister evaluating `Custom` validation rules
+    pub custom_validator: Option<Principal>,
+    /// Accept records when the custom validator can't be reached instead of rejecting them
+    pub custom_validator_fail_open: bool,
+    /// Coerce string values to number and boolean fields on write
+    pub coerce_types: bool,
+    /// Record ID strategy for inserts; `None` uses insertion-time based IDs
+    pub id_strategy: Option<IdStrategy>,
+    /// Compress newly written record blobs
+    pub compress_records: bool,
+    /// Primary cell this cell replicates; writes are rejected when set
+    pub replica_of: Option<Principal>,
+    /// Accept writes from the anonymous principal
+    pub allow_anonymous_writes: bool,
+    /// Canister answering `roles_of` for `Role` access levels, e.g. the cell manager
+    pub role_authority: Option<Principal>,
+    /// Allow admins to run `self_benchmark`
+    pub benchmark_enabled: bool,
+}
+
+impl_storable!(CellSettings);
+
+pub struct Settings;
+
+impl Settings {
+    /// Persist settings from the init configuration
+    pub fn init(config: &CellInitConfig) {
+        Self::set(CellSettings {
+            default_ttl_seconds: config.default_ttl_seconds,
+            write_rate_limit_per_minute: config.write_rate_limit_per_minute,
+            fail_fast_validation: config.fail_fast_validation.unwrap_or(false),
+            custom_validator: config.custom_validator,
+            custom_validator_fail_open: config.custom_validator_fail_open.unwrap_or(false),
+            coerce_types: config.coerce_types.unwrap_or(false),
+            id_strategy: config.id_strategy.clone(),
+            compress_records: config.compress_records.unwrap_or(false),
+            replica_of: config.replica_of,
+            allow_anonymous_writes: config.allow_anonymous_writes.unwrap_or(false),
+            role_authority: config.role_authority,
+            benchmark_enabled: config.benchmark_enabled.unwrap_or(false),
+        });
+    }
+
+    /// Get the current settings
+    pub fn get() -> CellSettings {
+        SETTINGS.with(|settings| settings.borrow().get().clone())
+    }
+
+    /// Replace the current settings
+    pub fn set(settings: CellSettings) {
+        SETTINGS.with(|cell| {
+            cell.borrow_mut().set(settings)
+                .expect("Failed to persist cell settings");
+        });
+    }
+}
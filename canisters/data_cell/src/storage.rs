@@ -1,15 +1,90 @@
 //! Stable memory storage implementation for Data Cells
 
+use candid::CandidType;
 use ic_stable_structures::{
-    StableBTreeMap, StableVec, DefaultMemoryImpl, RestrictedMemory,
-    memory_manager::{MemoryManager, MemoryId}
+    StableBTreeMap, StableCell, DefaultMemoryImpl,
+    memory_manager::{MemoryManager, MemoryId, VirtualMemory}
 };
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
-use crate::schema::SchemaDefinition;
+use std::ops::Bound;
+use crate::blob_store::BlobStore;
+use crate::change_feed::{ChangeFeed, ChangeOperation};
+use crate::compression::Compression;
+use crate::query_cache::QueryCache;
+use crate::replication::Replication;
+use crate::schema::{IndexDefinition, SchemaDefinition};
+use crate::settings::Settings;
 
-type Memory = RestrictedMemory<DefaultMemoryImpl>;
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
 type RecordStorage = StableBTreeMap<String, Vec<u8>, Memory>;
-type IndexStorage = StableBTreeMap<String, Vec<String>, Memory>;
+
+/// Record ID to expiry timestamp (nanoseconds)
+type ExpiryStorage = StableBTreeMap<String, u64, Memory>;
+
+/// Expiry queue keyed by zero-padded `expires_at \0 record_id`, ordered by expiry
+type ExpiryQueue = StableBTreeMap<String, String, Memory>;
+
+/// Ordered index keyed by `field \0 sort_key \0 record_id`, mapping to the record ID.
+/// Keeping values in the key lets equality, prefix and range lookups run as
+/// range scans over the BTreeMap instead of full scans.
+type IndexStorage = StableBTreeMap<String, String, Memory>;
+type IndexCatalog = StableBTreeMap<String, IndexDefinition, Memory>;
+
+/// Records per indexed value, keyed by `field \0 sort_key` like index keys without the record ID
+type IndexValueCounts = StableBTreeMap<String, u64, Memory>;
+
+/// Entry and distinct-value totals per index key space
+type IndexCardinalities = StableBTreeMap<String, IndexCardinality, Memory>;
+
+/// Serialized bytes held by each top-level field name, across all records
+type FieldSizes = StableBTreeMap<String, u64, Memory>;
+
+/// Record ID to the hex SHA-256 of the record's JSON, its content ETag
+type RecordHashes = StableBTreeMap<String, String, Memory>;
+
+/// Lazy indexes not yet complete, keyed by index name
+type IndexBuilds = StableBTreeMap<String, IndexBuild, Memory>;
+
+/// Record ID to the version of its latest change, a primary's change sequence
+/// number. Replicas keep a deleted record's entry as a tombstone, so stale
+/// writes for it are still recognised.
+type RecordVersions = StableBTreeMap<String, u64, Memory>;
+
+/// Implement `Storable` for types kept in stable structures, encoded as CBOR
+///
+/// CBOR is self-describing, so `#[serde(default)]` fields added later decode
+/// from values written before they existed.
+macro_rules! impl_storable {
+    ($($ty:ty),+ $(,)?) => {$(
+        impl ic_stable_structures::Storable for $ty {
+            fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(self, &mut bytes)
+                    .expect(concat!("Failed to encode ", stringify!($ty)));
+                std::borrow::Cow::Owned(bytes)
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                ciborium::de::from_reader(bytes.as_ref())
+                    .expect(concat!("Failed to decode ", stringify!($ty)))
+            }
+
+            const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+        }
+    )+};
+}
+pub(crate) use impl_storable;
+
+impl_storable!(ReindexProgress, IndexBuild, BlobSizes, IndexCardinality);
+
+/// Separator between the field, sort key and record ID parts of an index key
+const INDEX_KEY_SEPARATOR: char = '\0';
+
+/// Separator between field names, and between encoded values, of a composite index key
+const COMPOSITE_SEPARATOR: char = '|';
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -26,6 +101,96 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
         )
     );
+
+    static INDEX_DEFINITIONS: RefCell<IndexCatalog> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+        )
+    );
+
+    static REINDEX_PROGRESS: RefCell<StableCell<ReindexProgress, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
+            ReindexProgress::default(),
+        ).expect("Failed to initialize reindex progress")
+    );
+
+    static SCHEMA: RefCell<StableCell<SchemaDefinition, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+            SchemaDefinition::default(),
+        ).expect("Failed to initialize schema storage")
+    );
+
+    static EXPIRATIONS: RefCell<ExpiryStorage> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+        )
+    );
+
+    static EXPIRY_QUEUE: RefCell<ExpiryQueue> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+        )
+    );
+
+    static INDEX_VALUE_COUNTS: RefCell<IndexValueCounts> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+        )
+    );
+
+    static INDEX_CARDINALITIES: RefCell<IndexCardinalities> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+        )
+    );
+
+    static BLOB_SIZES: RefCell<StableCell<BlobSizes, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+            BlobSizes::default(),
+        ).expect("Failed to initialize blob size totals")
+    );
+
+    static FIELD_SIZES: RefCell<FieldSizes> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        )
+    );
+
+    static RECORD_HASHES: RefCell<RecordHashes> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+        )
+    );
+
+    static INDEX_BUILDS: RefCell<IndexBuilds> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+        )
+    );
+
+    static RECORD_VERSIONS: RefCell<RecordVersions> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+        )
+    );
+
+    /// Last change sequence number issued
+    static CHANGE_SEQUENCE: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29))),
+            0,
+        ).expect("Failed to initialize change sequence")
+    );
+}
+
+/// Get a virtual memory region from the cell's memory manager
+///
+/// Every stable structure in the cell must claim a distinct `MemoryId`.
+pub fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
 }
 
 pub struct Storage;
@@ -34,55 +199,876 @@ impl Storage {
     /// Initialize storage with schema
     pub fn init(schema: &SchemaDefinition) {
         ic_cdk::println!("Initializing storage for schema: {}", schema.name);
-        // TODO: Initialize indexes based on schema
+
+        Self::set_schema(schema.clone());
+
+        INDEX_DEFINITIONS.with(|definitions| {
+            let mut definitions_ref = definitions.borrow_mut();
+            for index in &schema.indexes {
+                definitions_ref.insert(index.name.clone(), index.clone());
+            }
+        });
     }
 
-    /// Store a record
-    pub fn store_record(record_id: String, data: Vec<u8>) -> Result<(), String> {
-        RECORDS.with(|records| {
-            records.borrow_mut().insert(record_id, data);
-            Ok(())
+    /// Get the persisted schema
+    pub fn get_schema() -> SchemaDefinition {
+        SCHEMA.with(|schema| schema.borrow().get().clone())
+    }
+
+    /// Persist the schema
+    pub fn set_schema(schema: SchemaDefinition) {
+        SCHEMA.with(|cell| {
+            cell.borrow_mut().set(schema)
+                .expect("Failed to persist schema");
+        });
+    }
+
+    /// List all registered index definitions
+    pub fn index_definitions() -> Vec<IndexDefinition> {
+        INDEX_DEFINITIONS.with(|definitions| {
+            definitions.borrow().iter().map(|(_, index)| index).collect()
+        })
+    }
+
+    /// Check if a single-field index is registered for a field
+    pub fn has_field_index(field_name: &str) -> bool {
+        Self::index_definitions().iter()
+            .any(|index| index.fields.len() == 1 && index.fields[0] == field_name)
+    }
+
+    /// Check if a complete single-field index is registered for a field
+    pub fn has_ready_field_index(field_name: &str) -> bool {
+        Self::index_definitions().iter()
+            .any(|index| index.fields.len() == 1 && index.fields[0] == field_name && Self::is_index_ready(&index.name))
+    }
+
+    /// Register an index after initialization
+    ///
+    /// A regular index is built from every stored record before returning. A
+    /// lazy one is left pending for `continue_index_builds`; writes keep its
+    /// entries current either way.
+    pub fn add_index(index: IndexDefinition) -> Result<(), String> {
+        if index.fields.is_empty() {
+            return Err("An index needs at least one field".to_string());
+        }
+        if INDEX_DEFINITIONS.with(|definitions| definitions.borrow().contains_key(&index.name)) {
+            return Err(format!("Index {} already exists", index.name));
+        }
+
+        let mut schema = Self::get_schema();
+        if let Some(unknown) = index.fields.iter().find(|field| !schema.fields.contains_key(*field)) {
+            return Err(format!("Unknown field {}", unknown));
+        }
+        schema.indexes.push(index.clone());
+        Self::set_schema(schema);
+
+        INDEX_DEFINITIONS.with(|definitions| {
+            definitions.borrow_mut().insert(index.name.clone(), index.clone());
+        });
+
+        let mut build = IndexBuild {
+            index_name: index.name.clone(),
+            started_at: None,
+            last_record_id: None,
+            records_indexed: 0,
+        };
+        if index.lazy.unwrap_or(false) {
+            INDEX_BUILDS.with(|builds| builds.borrow_mut().insert(index.name.clone(), build));
+        } else {
+            Self::build_index_batch(&index, &mut build, usize::MAX);
+        }
+        Ok(())
+    }
+
+    /// Check if an index is complete, i.e. not a lazy index still pending or building
+    pub fn is_index_ready(index_name: &str) -> bool {
+        INDEX_BUILDS.with(|builds| !builds.borrow().contains_key(&index_name.to_string()))
+    }
+
+    /// Lazy indexes that are pending or being built
+    pub fn index_builds() -> Vec<IndexBuild> {
+        INDEX_BUILDS.with(|builds| {
+            builds.borrow().iter().map(|(_, build)| build).collect()
         })
     }
 
+    /// Index one batch of records for every lazy index still being built
+    ///
+    /// Pending builds start with their first batch. Progress is checkpointed
+    /// after each batch; an index becomes ready, and is used by the planner,
+    /// once its build has passed the last record.
+    pub fn continue_index_builds(batch_size: usize) {
+        for mut build in Self::index_builds() {
+            if build.started_at.is_none() {
+                ic_cdk::println!("Starting build of lazy index {}", build.index_name);
+                build.started_at = Some(ic_cdk::api::time());
+            }
+
+            let index = INDEX_DEFINITIONS.with(|definitions| definitions.borrow().get(&build.index_name));
+            let complete = match &index {
+                Some(index) => Self::build_index_batch(index, &mut build, batch_size),
+                None => true,
+            };
+
+            INDEX_BUILDS.with(|builds| {
+                let mut builds_ref = builds.borrow_mut();
+                if complete {
+                    ic_cdk::println!("Lazy index {} is ready after {} records", build.index_name, build.records_indexed);
+                    builds_ref.remove(&build.index_name);
+                } else {
+                    builds_ref.insert(build.index_name.clone(), build);
+                }
+            });
+        }
+    }
+
+    /// Add the records after `build`'s position to one index, returning whether none remain
+    fn build_index_batch(index: &IndexDefinition, build: &mut IndexBuild, batch_size: usize) -> bool {
+        let key_space = match index_key_space(index) {
+            Some(key_space) => key_space,
+            None => return true,
+        };
+
+        let batch = Self::records_after(build.last_record_id.as_deref(), batch_size);
+
+        for (record_id, data) in &batch {
+            if let Ok(record) = serde_json::from_slice::<Value>(data) {
+                if let Some(sort_key) = index_sort_key(index, &record) {
+                    Self::add_index_entry(&key_space, &sort_key, record_id);
+                }
+                build.records_indexed += 1;
+            }
+        }
+
+        if let Some((last, _)) = batch.last() {
+            build.last_record_id = Some(last.clone());
+        }
+        batch.len() < batch_size
+    }
+
+    /// Add a record to every index whose fields it holds
+    pub fn index_record(record_id: &str, record: &Value) {
+        for (key_space, sort_key) in Self::record_index_entries(record) {
+            Self::add_index_entry(&key_space, &sort_key, record_id);
+        }
+    }
+
+    /// Remove a record from every index it was added to by `index_record`
+    pub fn unindex_record(record_id: &str, record: &Value) {
+        for (key_space, sort_key) in Self::record_index_entries(record) {
+            Self::remove_index_entry(&key_space, &sort_key, record_id);
+        }
+    }
+
+    /// `(key_space, sort_key)` pairs a record occupies across all registered indexes
+    fn record_index_entries(record: &Value) -> Vec<(String, String)> {
+        Self::index_definitions().iter()
+            .filter_map(|index| Some((index_key_space(index)?, index_sort_key(index, record)?)))
+            .collect()
+    }
+
+    /// Insert an index entry, keeping cardinality statistics in step
+    fn add_index_entry(key_space: &str, sort_key: &str, record_id: &str) {
+        let index_key = format!("{}{}{}{}{}", key_space, INDEX_KEY_SEPARATOR, sort_key, INDEX_KEY_SEPARATOR, record_id);
+
+        let is_new = INDEXES.with(|indexes| {
+            indexes.borrow_mut().insert(index_key, record_id.to_string()).is_none()
+        });
+
+        if is_new {
+            Self::adjust_cardinality(key_space, sort_key, 1);
+        }
+    }
+
+    /// Remove an index entry, keeping cardinality statistics in step
+    fn remove_index_entry(key_space: &str, sort_key: &str, record_id: &str) {
+        let index_key = format!("{}{}{}{}{}", key_space, INDEX_KEY_SEPARATOR, sort_key, INDEX_KEY_SEPARATOR, record_id);
+
+        let existed = INDEXES.with(|indexes| {
+            indexes.borrow_mut().remove(&index_key).is_some()
+        });
+
+        if existed {
+            Self::adjust_cardinality(key_space, sort_key, -1);
+        }
+    }
+
+    /// Apply a change of one entry to the per-value and per-index counts
+    fn adjust_cardinality(key_space: &str, sort_key: &str, delta: i64) {
+        let value_key = format!("{}{}{}", key_space, INDEX_KEY_SEPARATOR, sort_key);
+
+        let (before, after) = INDEX_VALUE_COUNTS.with(|counts| {
+            let mut counts_ref = counts.borrow_mut();
+            let before = counts_ref.get(&value_key).unwrap_or(0);
+            let after = before.saturating_add_signed(delta);
+
+            if after == 0 {
+                counts_ref.remove(&value_key);
+            } else {
+                counts_ref.insert(value_key, after);
+            }
+            (before, after)
+        });
+
+        INDEX_CARDINALITIES.with(|cardinalities| {
+            let mut cardinalities_ref = cardinalities.borrow_mut();
+            let mut cardinality = cardinalities_ref.get(&key_space.to_string()).unwrap_or_default();
+
+            cardinality.entries = cardinality.entries.saturating_add_signed(delta);
+            match (before, after) {
+                (0, after) if after > 0 => cardinality.distinct_values += 1,
+                (before, 0) if before > 0 => cardinality.distinct_values = cardinality.distinct_values.saturating_sub(1),
+                _ => {},
+            }
+
+            cardinalities_ref.insert(key_space.to_string(), cardinality);
+        });
+    }
+
+    /// Cardinality statistics for every registered index
+    pub fn index_stats() -> Vec<IndexStat> {
+        Self::index_definitions().into_iter()
+            .filter_map(|index| {
+                let key_space = index_key_space(&index)?;
+                let cardinality = INDEX_CARDINALITIES.with(|cardinalities| {
+                    cardinalities.borrow().get(&key_space).unwrap_or_default()
+                });
+
+                Some(IndexStat {
+                    name: index.name,
+                    fields: index.fields,
+                    entries: cardinality.entries,
+                    distinct_values: cardinality.distinct_values,
+                })
+            })
+            .collect()
+    }
+
+    /// Number of records indexed under exact values of every field of an index
+    ///
+    /// `values` must be given in the same order as `fields`.
+    pub fn count_index_matches(fields: &[String], values: &[&Value]) -> u64 {
+        let value_key = format!("{}{}{}", fields.join(&COMPOSITE_SEPARATOR.to_string()), INDEX_KEY_SEPARATOR, composite_sort_key(values));
+        INDEX_VALUE_COUNTS.with(|counts| counts.borrow().get(&value_key).unwrap_or(0))
+    }
+
+    /// Number of records whose indexed string value starts with `prefix`
+    pub fn count_prefix_matches(field_name: &str, prefix: &str) -> u64 {
+        let key_prefix = value_prefix(field_name, &Value::String(prefix.to_string()));
+
+        INDEX_VALUE_COUNTS.with(|counts| {
+            counts.borrow().range(key_prefix.clone()..)
+                .take_while(|(key, _)| key.starts_with(&key_prefix))
+                .map(|(_, count)| count)
+                .sum()
+        })
+    }
+
+    /// Number of records whose indexed value lies between `start` and `end`, both inclusive
+    ///
+    /// Walks one counter per distinct value rather than one entry per record.
+    pub fn count_range_matches(field_name: &str, start: Option<&Value>, end: Option<&Value>) -> u64 {
+        let field_prefix = field_prefix(field_name);
+        let lower = match start {
+            Some(value) => value_prefix(field_name, value),
+            None => field_prefix.clone(),
+        };
+        let upper = end.map(|value| value_prefix(field_name, value));
+
+        INDEX_VALUE_COUNTS.with(|counts| {
+            counts.borrow().range(lower..)
+                .take_while(|(key, _)| {
+                    key.starts_with(&field_prefix) && upper.as_ref().map_or(true, |upper| key <= upper)
+                })
+                .map(|(_, count)| count)
+                .sum()
+        })
+    }
+
+    /// Store a record, compressing it when the cell is configured to
+    pub fn store_record(record_id: String, data: Vec<u8>) -> Result<(), String> {
+        let raw_len = data.len() as u64;
+        Self::adjust_field_sizes(&data, true);
+        let hash = content_hash(&data);
+        RECORD_HASHES.with(|hashes| hashes.borrow_mut().insert(record_id.clone(), hash));
+        let blob = Compression::encode(data, Settings::get().compress_records);
+        let stored_len = blob.len() as u64;
+
+        let replaced = RECORDS.with(|records| {
+            records.borrow_mut().insert(record_id, blob)
+        });
+
+        if let Some(replaced_data) = replaced.as_deref().and_then(Compression::decode) {
+            Self::adjust_field_sizes(&replaced_data, false);
+        }
+        Self::adjust_blob_sizes(replaced.as_deref(), Some((raw_len, stored_len)));
+        QueryCache::invalidate();
+        Ok(())
+    }
+
+    /// Check whether a record is stored, expired or not, without decoding it
+    pub fn has_record(record_id: &str) -> bool {
+        RECORDS.with(|records| records.borrow().contains_key(record_id))
+    }
+
+    /// Check whether an unexpired record is stored, without decoding it
+    ///
+    /// Agrees with `get_json_record` on which records exist.
+    pub fn has_live_record(record_id: &str) -> bool {
+        Self::has_record(record_id) && !Self::is_expired(record_id, ic_cdk::api::time())
+    }
+
     /// Retrieve a record
     pub fn get_record(record_id: &str) -> Option<Vec<u8>> {
         RECORDS.with(|records| {
             records.borrow().get(record_id)
-        })
+        }).and_then(|blob| Compression::decode(&blob).map(|data| data.into_owned()))
+    }
+
+    /// Content ETag of a record: the hex SHA-256 of its stored JSON
+    ///
+    /// Records are stored as JSON with sorted keys, so equal records have
+    /// equal ETags. Records written before ETags were kept get theirs computed
+    /// on demand.
+    pub fn record_hash(record_id: &str) -> Option<String> {
+        if Self::is_expired(record_id, ic_cdk::api::time()) {
+            return None;
+        }
+
+        RECORD_HASHES.with(|hashes| hashes.borrow().get(&record_id.to_string()))
+            .or_else(|| Self::get_record(record_id).map(|data| content_hash(&data)))
+    }
+
+    /// Retrieve a record decoded as JSON, treating expired records as absent
+    pub fn get_json_record(record_id: &str) -> Option<serde_json::Value> {
+        if Self::is_expired(record_id, ic_cdk::api::time()) {
+            return None;
+        }
+
+        Self::get_record(record_id)
+            .and_then(|data| serde_json::from_slice(&data).ok())
     }
 
     /// Delete a record
     pub fn delete_record(record_id: &str) -> Option<Vec<u8>> {
-        RECORDS.with(|records| {
+        let removed = RECORDS.with(|records| {
             records.borrow_mut().remove(record_id)
+        })?;
+
+        let data = Compression::decode(&removed).map(|data| data.into_owned());
+        if let Some(data) = &data {
+            Self::adjust_field_sizes(data, false);
+        }
+        RECORD_HASHES.with(|hashes| hashes.borrow_mut().remove(record_id));
+        Self::adjust_blob_sizes(Some(&removed), None);
+        QueryCache::invalidate();
+        data
+    }
+
+    /// Add a stored record's fields to the per-field byte totals, or take a removed one's away
+    ///
+    /// A field's bytes are its name plus its serialized value, as they appear
+    /// in the stored JSON before compression.
+    fn adjust_field_sizes(data: &[u8], added: bool) {
+        let fields = match serde_json::from_slice::<Value>(data) {
+            Ok(Value::Object(fields)) => fields,
+            _ => return,
+        };
+
+        FIELD_SIZES.with(|sizes| {
+            let mut sizes = sizes.borrow_mut();
+
+            for (name, value) in fields {
+                let bytes = (name.len() + value.to_string().len()) as u64;
+                let current = sizes.get(&name).unwrap_or(0);
+                let total = if added { current + bytes } else { current.saturating_sub(bytes) };
+
+                if total == 0 {
+                    sizes.remove(&name);
+                } else {
+                    sizes.insert(name, total);
+                }
+            }
+        });
+    }
+
+    /// Bytes held by each top-level field across all records, largest first
+    ///
+    /// Totals are kept as records are written and deleted, so records stored
+    /// before the cell started tracking them are not counted.
+    pub fn field_storage_breakdown() -> Vec<(String, u64)> {
+        let mut breakdown: Vec<(String, u64)> = FIELD_SIZES.with(|sizes| sizes.borrow().iter().collect());
+        breakdown.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        breakdown
+    }
+
+    /// Move the running size totals from a replaced blob to a new one
+    fn adjust_blob_sizes(removed: Option<&[u8]>, added: Option<(u64, u64)>) {
+        BLOB_SIZES.with(|sizes| {
+            let mut sizes = sizes.borrow_mut();
+            let mut totals = sizes.get().clone();
+
+            if let Some(blob) = removed {
+                totals.raw_bytes = totals.raw_bytes.saturating_sub(Compression::decoded_len(blob) as u64);
+                totals.stored_bytes = totals.stored_bytes.saturating_sub(blob.len() as u64);
+            }
+            if let Some((raw_len, stored_len)) = added {
+                totals.raw_bytes += raw_len;
+                totals.stored_bytes += stored_len;
+            }
+
+            sizes.set(totals).expect("Failed to persist blob size totals");
+        });
+    }
+
+    /// Number of stored records, including expired ones not yet swept
+    pub fn record_count() -> u64 {
+        RECORDS.with(|records| records.borrow().len())
+    }
+
+    /// Bytes of stable and heap memory the cell occupies
+    pub fn memory_usage() -> u64 {
+        const WASM_PAGE_BYTES: u64 = 64 * 1024;
+
+        let stable_pages = ic_cdk::api::stable::stable_size();
+        #[cfg(target_arch = "wasm32")]
+        let heap_pages = core::arch::wasm32::memory_size(0) as u64;
+        #[cfg(not(target_arch = "wasm32"))]
+        let heap_pages = 0;
+
+        (stable_pages + heap_pages) * WASM_PAGE_BYTES
+    }
+
+    /// Total record bytes before and after compression
+    pub fn blob_sizes() -> BlobSizes {
+        BLOB_SIZES.with(|sizes| sizes.borrow().get().clone())
+    }
+
+    /// Store a JSON record, moving its index entries from `previous` if given
+    ///
+    /// Blobs the record references are claimed for it, and blobs the record
+    /// it replaces referenced but it no longer does are deleted. Without a
+    /// `previous` record, an expired record not yet swept is discarded first,
+    /// so the new one inherits none of its index or expiry entries, and a
+    /// live record stored under the same ID is unindexed before it is
+    /// overwritten.
+    pub fn put_json_record(record_id: &str, record: &Value, previous: Option<&Value>) -> Result<(), String> {
+        let data = serde_json::to_vec(record).map_err(|e| e.to_string())?;
+
+        let schema = Self::get_schema();
+        if !Replication::is_replica() {
+            BlobStore::check_references(&schema, record_id, record)?;
+        }
+
+        let replaced = match previous {
+            Some(previous) => {
+                Self::unindex_record(record_id, previous);
+                None
+            },
+            None => {
+                Self::discard_if_expired(record_id, ic_cdk::api::time());
+                let replaced = Self::get_record(record_id).and_then(|data| serde_json::from_slice::<Value>(&data).ok());
+                if let Some(replaced) = &replaced {
+                    Self::unindex_record(record_id, replaced);
+                }
+                replaced
+            },
+        };
+        Self::store_record(record_id.to_string(), data)?;
+        Self::index_record(record_id, record);
+        BlobStore::update_references(&schema, record_id, Some(record), previous.or(replaced.as_ref()));
+
+        Ok(())
+    }
+
+    /// Delete a JSON record together with its index and expiry entries
+    pub fn remove_json_record(record_id: &str) -> Option<Value> {
+        let record = Self::get_json_record(record_id)?;
+        Self::purge_record(record_id, &record);
+        Some(record)
+    }
+
+    fn purge_record(record_id: &str, record: &Value) {
+        Self::delete_record(record_id);
+        Self::unindex_record(record_id, record);
+        Self::clear_expiry(record_id);
+        BlobStore::update_references(&Self::get_schema(), record_id, None, Some(record));
+    }
+
+    /// Version of a record's latest change, if one was recorded
+    pub fn record_version(record_id: &str) -> Option<u64> {
+        RECORD_VERSIONS.with(|versions| versions.borrow().get(&record_id.to_string()))
+    }
+
+    /// Issue the next change sequence number as a record's version
+    ///
+    /// A deleted record's version is dropped; the sequence alone keeps later
+    /// versions of a reused ID higher.
+    pub fn next_record_version(record_id: &str, deleted: bool) -> u64 {
+        let version = CHANGE_SEQUENCE.with(|sequence| {
+            let mut sequence = sequence.borrow_mut();
+            let version = *sequence.get() + 1;
+            sequence.set(version).expect("Failed to persist change sequence");
+            version
+        });
+
+        RECORD_VERSIONS.with(|versions| {
+            let mut versions = versions.borrow_mut();
+            if deleted {
+                versions.remove(&record_id.to_string());
+            } else {
+                versions.insert(record_id.to_string(), version);
+            }
+        });
+        version
+    }
+
+    /// Record the version of a change copied from a primary, deletes included
+    ///
+    /// Version 0, from records written before versions were kept, isn't stored.
+    pub fn set_record_version(record_id: &str, version: u64) {
+        if version > 0 {
+            RECORD_VERSIONS.with(|versions| versions.borrow_mut().insert(record_id.to_string(), version));
+        }
+    }
+
+    /// Whether a replica already holds `record_id` at `version` or later, or its deletion
+    pub fn holds_version(record_id: &str, version: u64) -> bool {
+        Self::record_version(record_id).map_or(false, |held| held >= version)
+    }
+
+    /// Set the time (nanoseconds) after which a record expires
+    pub fn set_expiry(record_id: &str, expires_at: u64) {
+        Self::clear_expiry(record_id);
+
+        EXPIRATIONS.with(|expirations| {
+            expirations.borrow_mut().insert(record_id.to_string(), expires_at);
+        });
+        EXPIRY_QUEUE.with(|queue| {
+            queue.borrow_mut().insert(expiry_queue_key(expires_at, record_id), record_id.to_string());
+        });
+    }
+
+    /// Remove any expiry set for a record
+    pub fn clear_expiry(record_id: &str) {
+        if let Some(expires_at) = EXPIRATIONS.with(|expirations| expirations.borrow_mut().remove(record_id)) {
+            EXPIRY_QUEUE.with(|queue| {
+                queue.borrow_mut().remove(&expiry_queue_key(expires_at, record_id));
+            });
+        }
+    }
+
+    /// Time (nanoseconds) after which a record expires, if one is set
+    pub fn expiry(record_id: &str) -> Option<u64> {
+        EXPIRATIONS.with(|expirations| expirations.borrow().get(&record_id.to_string()))
+    }
+
+    /// Check if a record has expired at time `now`
+    pub fn is_expired(record_id: &str, now: u64) -> bool {
+        EXPIRATIONS.with(|expirations| {
+            expirations.borrow().get(record_id).map_or(false, |expires_at| expires_at <= now)
         })
     }
 
-    /// Update index for a field
-    pub fn update_index(field_name: String, field_value: String, record_id: String) {
-        let index_key = format!("{}:{}", field_name, field_value);
+    /// Purge a record that has expired but not yet been swept, returning whether one was
+    pub fn discard_if_expired(record_id: &str, now: u64) -> bool {
+        if !Self::is_expired(record_id, now) {
+            return false;
+        }
 
-        INDEXES.with(|indexes| {
-            let mut indexes_ref = indexes.borrow_mut();
-            let mut record_ids = indexes_ref.get(&index_key).unwrap_or_default();
+        Self::expire_record(record_id);
+        true
+    }
 
-            if !record_ids.contains(&record_id) {
-                record_ids.push(record_id);
-                indexes_ref.insert(index_key, record_ids);
+    /// Delete up to `limit` records that expired at or before `now`, returning how many were purged
+    pub fn purge_expired(now: u64, limit: usize) -> u64 {
+        let due: Vec<String> = EXPIRY_QUEUE.with(|queue| {
+            queue.borrow().iter()
+                .take_while(|(key, _)| {
+                    key.split_once(INDEX_KEY_SEPARATOR)
+                        .and_then(|(expires_at, _)| expires_at.parse::<u64>().ok())
+                        .map_or(false, |expires_at| expires_at <= now)
+                })
+                .take(limit)
+                .map(|(_, record_id)| record_id)
+                .collect()
+        });
+
+        let mut purged = 0;
+        for record_id in due {
+            Self::expire_record(&record_id);
+            purged += 1;
+        }
+
+        purged
+    }
+
+    /// Remove an expired record, publishing its deletion like any other
+    ///
+    /// Replicas expire records locally but leave publishing to the primary,
+    /// whose versioned Delete they forward once it arrives.
+    fn expire_record(record_id: &str) {
+        let record = Self::get_record(record_id).and_then(|data| serde_json::from_slice::<Value>(&data).ok());
+        match &record {
+            Some(record) => Self::purge_record(record_id, record),
+            None => {
+                Self::delete_record(record_id);
+                Self::clear_expiry(record_id);
+            },
+        }
+        if !Replication::is_replica() {
+            ChangeFeed::publish(ChangeOperation::Delete, record_id, record);
+        }
+    }
+
+    /// List all stored records
+    pub fn list_records() -> Vec<(String, Vec<u8>)> {
+        RECORDS.with(|records| {
+            records.borrow().iter().filter_map(decode_entry).collect()
+        })
+    }
+
+    /// List up to `limit` records in key order, starting after `after` if given
+    pub fn records_after(after: Option<&str>, limit: usize) -> Vec<(String, Vec<u8>)> {
+        RECORDS.with(|records| {
+            let records_ref = records.borrow();
+            match after {
+                Some(after) => records_ref
+                    .range((Bound::Excluded(after.to_string()), Bound::Unbounded))
+                    .take(limit)
+                    .filter_map(decode_entry)
+                    .collect(),
+                None => records_ref.iter().take(limit).filter_map(decode_entry).collect(),
             }
+        })
+    }
+
+    /// List all stored records decoded as JSON, skipping expired and undecodable entries
+    pub fn list_json_records() -> Vec<(String, serde_json::Value)> {
+        let now = ic_cdk::api::time();
+
+        Self::list_records().into_iter()
+            .filter(|(record_id, _)| !Self::is_expired(record_id, now))
+            .filter_map(|(record_id, data)| {
+                serde_json::from_slice(&data).ok().map(|record| (record_id, record))
+            })
+            .collect()
+    }
+
+    /// Add a record to the index for a field value
+    pub fn update_index(field_name: &str, field_value: &Value, record_id: &str) {
+        Self::add_index_entry(field_name, &encode_sort_key(field_value), record_id);
+    }
+
+    /// Remove a record from the index for a field value
+    pub fn remove_from_index(field_name: &str, field_value: &Value, record_id: &str) {
+        Self::remove_index_entry(field_name, &encode_sort_key(field_value), record_id);
+    }
+
+    /// Query records by exact index value
+    pub fn query_by_index(field_name: &str, field_value: &Value) -> Vec<String> {
+        let prefix = format!("{}{}", value_prefix(field_name, field_value), INDEX_KEY_SEPARATOR);
+        Self::scan_index_prefix(&prefix)
+    }
+
+    /// Query records by exact values of every field of a composite index
+    ///
+    /// `values` must be given in the same order as `fields`.
+    pub fn query_by_composite_index(fields: &[String], values: &[&Value]) -> Vec<String> {
+        let prefix = format!("{}{}{}{}", fields.join(&COMPOSITE_SEPARATOR.to_string()), INDEX_KEY_SEPARATOR, composite_sort_key(values), INDEX_KEY_SEPARATOR);
+        Self::scan_index_prefix(&prefix)
+    }
+
+    /// Query records whose indexed string value starts with `prefix`
+    pub fn prefix_query(field_name: &str, prefix: &str) -> Vec<String> {
+        let key_prefix = value_prefix(field_name, &Value::String(prefix.to_string()));
+        Self::scan_index_prefix(&key_prefix)
+    }
+
+    /// Query records whose indexed value lies between `start` and `end`, both inclusive
+    ///
+    /// Values order by type first (null < bool < number < string < other), then by
+    /// value within a type, so an open-ended bound spans neighbouring types as well.
+    /// Only the matching key range of the index is visited.
+    pub fn range_query(field_name: &str, start: Option<&Value>, end: Option<&Value>) -> Vec<String> {
+        let field_prefix = field_prefix(field_name);
+        let lower = match start {
+            Some(value) => value_prefix(field_name, value),
+            None => field_prefix.clone(),
+        };
+        let upper = end.map(|value| {
+            format!("{}{}{}", value_prefix(field_name, value), INDEX_KEY_SEPARATOR, char::MAX)
         });
+
+        INDEXES.with(|indexes| {
+            indexes.borrow().range(lower..)
+                .take_while(|(key, _)| {
+                    key.starts_with(&field_prefix) && upper.as_ref().map_or(true, |upper| key < upper)
+                })
+                .map(|(_, record_id)| record_id)
+                .collect()
+        })
     }
 
-    /// Query records by index
-    pub fn query_by_index(field_name: &str, field_value: &str) -> Vec<String> {
-        let index_key = format!("{}:{}", field_name, field_value);
+    /// Collect record IDs for every index key starting with `prefix`
+    fn scan_index_prefix(prefix: &str) -> Vec<String> {
+        INDEXES.with(|indexes| {
+            indexes.borrow().range(prefix.to_string()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .map(|(_, record_id)| record_id)
+                .collect()
+        })
+    }
+
+    /// List the distinct values present in the index for a field
+    pub fn indexed_values(field_name: &str) -> Vec<Value> {
+        Self::indexed_value_counts(field_name).into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    /// Count the records holding each indexed value of a field, in index order
+    pub fn indexed_value_counts(field_name: &str) -> Vec<(Value, u64)> {
+        let prefix = field_prefix(field_name);
 
         INDEXES.with(|indexes| {
-            indexes.borrow().get(&index_key).unwrap_or_default()
+            let mut counts: Vec<(String, u64)> = Vec::new();
+
+            for (key, _) in indexes.borrow().range(prefix.clone()..) {
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+
+                let sort_key = match key[prefix.len()..].rsplit_once(INDEX_KEY_SEPARATOR) {
+                    Some((sort_key, _)) => sort_key.to_string(),
+                    None => continue,
+                };
+
+                match counts.last_mut() {
+                    Some((last, count)) if *last == sort_key => *count += 1,
+                    _ => counts.push((sort_key, 1)),
+                }
+            }
+
+            counts.into_iter()
+                .filter_map(|(sort_key, count)| decode_sort_key(&sort_key).map(|value| (value, count)))
+                .collect()
         })
     }
 
+    /// Rebuild all indexes from stored records, one bounded batch per call
+    ///
+    /// A run first clears the existing index entries, then rebuilds them,
+    /// each phase touching at most `batch_size` entries or records per call.
+    /// Progress is checkpointed in stable memory after each batch, so a run
+    /// interrupted by a trap or the instruction limit resumes from the last
+    /// committed batch on the next call.
+    pub fn reindex_batch(batch_size: usize) -> ReindexProgress {
+        let mut progress = REINDEX_PROGRESS.with(|cell| cell.borrow().get().clone());
+
+        if !progress.in_progress {
+            progress = ReindexProgress {
+                in_progress: true,
+                clearing: true,
+                last_record_id: None,
+                records_reindexed: 0,
+            };
+        }
+
+        if progress.clearing {
+            progress.clearing = Self::clear_indexes_batch(batch_size);
+        } else {
+            let batch = Self::records_after(progress.last_record_id.as_deref(), batch_size);
+
+            for (record_id, data) in &batch {
+                if let Ok(record) = serde_json::from_slice::<Value>(data) {
+                    Self::index_record(record_id, &record);
+                    progress.records_reindexed += 1;
+                }
+            }
+
+            if let Some((last, _)) = batch.last() {
+                progress.last_record_id = Some(last.clone());
+            }
+            if batch.len() < batch_size {
+                progress.in_progress = false;
+
+                // Every index, lazy ones included, is now complete
+                INDEX_BUILDS.with(|builds| {
+                    let mut builds_ref = builds.borrow_mut();
+                    let names: Vec<String> = builds_ref.iter().map(|(name, _)| name).collect();
+                    for name in names {
+                        builds_ref.remove(&name);
+                    }
+                });
+            }
+        }
+
+        REINDEX_PROGRESS.with(|cell| {
+            cell.borrow_mut().set(progress.clone())
+                .expect("Failed to checkpoint reindex progress");
+        });
+
+        progress
+    }
+
+    /// Check if a reindex is partway through, leaving indexes incomplete
+    pub fn is_reindexing() -> bool {
+        REINDEX_PROGRESS.with(|cell| cell.borrow().get().in_progress)
+    }
+
+    /// Remove up to `limit` index entries, returning whether any may remain
+    ///
+    /// Entries are removed through `remove_index_entry`, so the cardinality
+    /// statistics follow them down and writes between batches see counts that
+    /// match the entries still present; the rebuild then counts every entry it
+    /// writes. Entries are always taken from the start of the map, so no
+    /// cursor is needed to resume.
+    fn clear_indexes_batch(limit: usize) -> bool {
+        let entries: Vec<(String, String)> = INDEXES.with(|indexes| {
+            indexes.borrow().iter().take(limit).collect()
+        });
+
+        for (index_key, record_id) in &entries {
+            // Keys are `<key space>\0<sort key>\0<record ID>`
+            let parts = index_key.split_once(INDEX_KEY_SEPARATOR).and_then(|(key_space, rest)| {
+                let sort_key = rest.strip_suffix(record_id.as_str())?.strip_suffix(INDEX_KEY_SEPARATOR)?;
+                Some((key_space, sort_key))
+            });
+
+            match parts {
+                Some((key_space, sort_key)) => Self::remove_index_entry(key_space, sort_key, record_id),
+                None => {
+                    INDEXES.with(|indexes| indexes.borrow_mut().remove(index_key));
+                },
+            }
+        }
+
+        if entries.len() == limit {
+            return true;
+        }
+
+        // With no entries left, any remaining statistics are drift from before
+        // the run; dropping them in this same call leaves nothing for a write
+        // to interleave with
+        INDEX_VALUE_COUNTS.with(|counts| {
+            let mut counts_ref = counts.borrow_mut();
+            let keys: Vec<String> = counts_ref.iter().map(|(key, _)| key).collect();
+            for key in keys {
+                counts_ref.remove(&key);
+            }
+        });
+        INDEX_CARDINALITIES.with(|cardinalities| {
+            let mut cardinalities_ref = cardinalities.borrow_mut();
+            let keys: Vec<String> = cardinalities_ref.iter().map(|(key, _)| key).collect();
+            for key in keys {
+                cardinalities_ref.remove(&key);
+            }
+        });
+
+        false
+    }
+
     /// Get storage statistics
     pub fn get_stats() -> StorageStats {
         let record_count = RECORDS.with(|records| records.borrow().len());
@@ -91,7 +1077,7 @@ impl Storage {
         StorageStats {
             record_count,
             index_count,
-            memory_usage: 0, // TODO: Calculate actual memory usage
+            memory_usage: Self::memory_usage(),
         }
     }
 
@@ -104,8 +1090,270 @@ impl Storage {
     }
 }
 
+/// Checkpointed progress of an index rebuild
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReindexProgress {
+    pub in_progress: bool,
+    /// Set while the run is still removing the old index entries
+    #[serde(default)]
+    pub clearing: bool,
+    pub last_record_id: Option<String>,
+    pub records_reindexed: u64,
+}
+
+/// Checkpointed progress of a lazy index build
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IndexBuild {
+    pub index_name: String,
+    /// When the first query that could use the index started the build; `None` while pending
+    pub started_at: Option<u64>,
+    pub last_record_id: Option<String>,
+    pub records_indexed: u64,
+}
+
+/// Running totals of record sizes, for reporting the compression ratio
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BlobSizes {
+    /// Bytes of JSON before compression
+    pub raw_bytes: u64,
+    /// Bytes actually held in stable memory
+    pub stored_bytes: u64,
+}
+
+/// Running totals for one index key space
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+struct IndexCardinality {
+    entries: u64,
+    distinct_values: u64,
+}
+
+/// Cardinality statistics of an index
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IndexStat {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// Number of indexed records
+    pub entries: u64,
+    /// Number of distinct indexed values (or value tuples for composite indexes)
+    pub distinct_values: u64,
+}
+
+/// Decode a stored `(record_id, blob)` entry, dropping it if corrupt
+fn decode_entry((record_id, blob): (String, Vec<u8>)) -> Option<(String, Vec<u8>)> {
+    Compression::decode(&blob).map(|data| (record_id, data.into_owned()))
+}
+
 pub struct StorageStats {
     pub record_count: u64,
     pub index_count: u64,
     pub memory_usage: u64,
-}
\ No newline at end of file
+}
+
+/// Hex SHA-256 of a record's JSON bytes
+fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Expiry queue key, zero-padded so keys sort by expiry time
+fn expiry_queue_key(expires_at: u64, record_id: &str) -> String {
+    format!("{:020}{}{}", expires_at, INDEX_KEY_SEPARATOR, record_id)
+}
+
+/// Key space of an index: the field name, or field names joined for composite indexes
+fn index_key_space(index: &IndexDefinition) -> Option<String> {
+    if index.fields.is_empty() {
+        return None;
+    }
+    Some(index.fields.join(&COMPOSITE_SEPARATOR.to_string()))
+}
+
+/// Sort key of a record within an index, or `None` if the record lacks an indexed field
+fn index_sort_key(index: &IndexDefinition, record: &Value) -> Option<String> {
+    let encoded = index.fields.iter()
+        .map(|field| record.get(field).map(encode_sort_key))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(encoded.join(&COMPOSITE_SEPARATOR.to_string()))
+}
+
+/// Sort key of a value tuple within a composite index
+fn composite_sort_key(values: &[&Value]) -> String {
+    values.iter()
+        .map(|value| encode_sort_key(value))
+        .collect::<Vec<_>>()
+        .join(&COMPOSITE_SEPARATOR.to_string())
+}
+
+/// Index key prefix covering every value of a field
+fn field_prefix(field_name: &str) -> String {
+    format!("{}{}", field_name, INDEX_KEY_SEPARATOR)
+}
+
+/// Index key prefix covering one value of a field
+fn value_prefix(field_name: &str, value: &Value) -> String {
+    format!("{}{}", field_prefix(field_name), encode_sort_key(value))
+}
+
+/// Encode a JSON value so that string ordering of keys matches value ordering
+///
+/// A leading type tag groups values by type; numbers are encoded as fixed-width
+/// hex of their order-preserving `f64` bit pattern.
+fn encode_sort_key(value: &Value) -> String {
+    match value {
+        Value::Null => "0".to_string(),
+        Value::Bool(b) => format!("1{}", if *b { 1 } else { 0 }),
+        Value::Number(n) => {
+            let f = n.as_f64().unwrap_or(0.0);
+            let bits = if f == 0.0 { 0.0f64.to_bits() } else { f.to_bits() };
+            let ordered = if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+            format!("2{:016x}", ordered)
+        },
+        Value::String(s) => format!("3{}", s),
+        other => format!("4{}", other),
+    }
+}
+
+/// Reverse `encode_sort_key`, restoring integral numbers as integers
+fn decode_sort_key(sort_key: &str) -> Option<Value> {
+    let (tag, rest) = sort_key.split_at(1.min(sort_key.len()));
+
+    match tag {
+        "0" => Some(Value::Null),
+        "1" => Some(Value::Bool(rest == "1")),
+        "2" => {
+            let ordered = u64::from_str_radix(rest, 16).ok()?;
+            let bits = if ordered >> 63 == 1 { ordered & !(1 << 63) } else { !ordered };
+            let f = f64::from_bits(bits);
+
+            if f.fract() == 0.0 && f.abs() < (1u64 << 53) as f64 {
+                Some(Value::from(f as i64))
+            } else {
+                serde_json::Number::from_f64(f).map(Value::Number)
+            }
+        },
+        "3" => Some(Value::String(rest.to_string())),
+        "4" => serde_json::from_str(rest).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldDefinition, FieldType};
+    use serde_json::json;
+
+    fn setup_category_index() {
+        let field = FieldDefinition {
+            field_type: FieldType::Text,
+            required: false,
+            default_value: None,
+            validation_rules: Vec::new(),
+            coerce: None,
+            auto_timestamp: None,
+        };
+        Storage::set_schema(SchemaDefinition {
+            version: 1,
+            name: "items".to_string(),
+            fields: [("category".to_string(), field)].into_iter().collect(),
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        });
+        Storage::add_index(IndexDefinition {
+            name: "by_category".to_string(),
+            fields: vec!["category".to_string()],
+            unique: false,
+            lazy: None,
+        }).unwrap();
+    }
+
+    fn store(record_id: &str, record: Value) {
+        Storage::store_record(record_id.to_string(), serde_json::to_vec(&record).unwrap()).unwrap();
+        Storage::index_record(record_id, &record);
+    }
+
+    fn category(value: &str) -> Vec<String> {
+        Storage::query_by_index("category", &json!(value))
+    }
+
+    #[test]
+    fn reindex_repairs_a_corrupted_index_in_checkpointed_batches() {
+        setup_category_index();
+        store("r1", json!({"category": "tools"}));
+        store("r2", json!({"category": "books"}));
+        store("r3", json!({"category": "tools"}));
+        store("r4", json!({"category": "garden"}));
+        store("r5", json!({"category": "tools"}));
+
+        // Drop a real entry and add a stale one, as a bug or partial import would
+        Storage::remove_from_index("category", &json!("tools"), "r1");
+        Storage::update_index("category", &json!("tools"), "r2");
+        Storage::update_index("category", &json!("toys"), "r9");
+        assert_eq!(category("tools"), ["r2", "r3", "r5"]);
+
+        let mut calls = 0;
+        let progress = loop {
+            calls += 1;
+            let progress = Storage::reindex_batch(2);
+            // Index scans are bypassed while the rebuild is incomplete
+            assert_eq!(Storage::is_reindexing(), progress.in_progress);
+            if !progress.in_progress {
+                break progress;
+            }
+        };
+
+        assert_eq!(progress.records_reindexed, 5);
+        assert!(calls > 3, "reindex finished in {} batches of 2", calls);
+
+        assert_eq!(category("tools"), ["r1", "r3", "r5"]);
+        assert_eq!(category("books"), ["r2"]);
+        assert!(category("toys").is_empty());
+
+        let stats = Storage::index_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].entries, 5);
+        assert_eq!(stats[0].distinct_values, 3);
+    }
+
+    #[test]
+    fn reindex_resumes_from_its_checkpoint() {
+        setup_category_index();
+        for i in 0..5 {
+            store(&format!("r{}", i), json!({"category": "tools"}));
+        }
+
+        // Clear the old entries, then index the first two records
+        while Storage::reindex_batch(2).clearing {}
+        let progress = Storage::reindex_batch(2);
+        assert_eq!(progress.last_record_id.as_deref(), Some("r1"));
+        assert!(progress.in_progress);
+
+        // A later call picks up after the checkpoint rather than starting over
+        let progress = Storage::reindex_batch(2);
+        assert_eq!(progress.last_record_id.as_deref(), Some("r3"));
+        assert_eq!(progress.records_reindexed, 4);
+    }
+
+    #[test]
+    fn writes_between_reindex_batches_keep_index_stats_exact() {
+        setup_category_index();
+        for i in 0..6 {
+            store(&format!("r{}", i), json!({"category": if i % 2 == 0 { "tools" } else { "books" }}));
+        }
+
+        // Write while the old entries are partly cleared, and again mid-rebuild
+        let progress = Storage::reindex_batch(2);
+        assert!(progress.clearing);
+        store("r6", json!({"category": "garden"}));
+        Storage::remove_from_index("category", &json!("tools"), "r0");
+
+        while Storage::reindex_batch(2).clearing {}
+        store("r7", json!({"category": "garden"}));
+        while Storage::reindex_batch(2).in_progress {}
+
+        let stats = Storage::index_stats();
+        assert_eq!(stats[0].entries, 8);
+        assert_eq!(stats[0].distinct_values, 3);
+        assert_eq!(Storage::count_index_matches(&["category".to_string()], &[&json!("garden")]), 2);
+    }
+}
@@ -1,75 +1,238 @@
 //! Data validation logic for Data Cells
 
-use crate::schema::{SchemaDefinition, FieldType, ValidationRule};
-use serde_json::Value;
+use candid::CandidType;
+use crate::filter::{values_equal, MAX_PATTERN_LENGTH, MAX_REGEX_SIZE};
+use crate::schema::{SchemaDefinition, FieldDefinition, FieldType, ValidationRule};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// `Pattern` rules compiled so far, so each is compiled once rather than per record
+    static PATTERNS: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// Whether validation stops at the first problem or reports all of them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationMode {
+    FailFast,
+    CollectAll,
+}
 
 pub struct Validator;
 
 impl Validator {
     /// Validate data against schema
-    pub fn validate_data(schema: &SchemaDefinition, data: &Value) -> Result<(), ValidationError> {
-        // TODO: Implement comprehensive validation
-        // - Type checking
-        // - Required field validation
-        // - Custom validation rules
-        // - Constraint checking
+    ///
+    /// Fields are checked in name order, descending into nested objects and
+    /// arrays, so errors come back in a stable order with paths such as
+    /// `address.city` or `tags[2]`. In `FailFast` mode at most one error is
+    /// returned.
+    pub fn validate_data(schema: &SchemaDefinition, data: &Value, mode: ValidationMode) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
 
         match data {
-            Value::Object(obj) => {
-                for (field_name, field_def) in &schema.fields {
-                    if field_def.required && !obj.contains_key(field_name) {
-                        return Err(ValidationError::MissingRequiredField(field_name.clone()));
-                    }
+            Value::Object(obj) => Self::validate_object("", &schema.fields, obj, mode, &mut errors),
+            _ => errors.push(ValidationError::InvalidDataFormat("Expected object".to_string())),
+        }
 
-                    if let Some(field_value) = obj.get(field_name) {
-                        Self::validate_field(field_value, &field_def.field_type, &field_def.validation_rules)?;
-                    }
-                }
-                Ok(())
-            },
-            _ => Err(ValidationError::InvalidDataFormat("Expected object".to_string()))
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Convert string values to their field's number or boolean type in place
+    ///
+    /// Applies to fields whose `coerce` flag is set, or that leave it unset
+    /// when `coerce_by_default` is true. Values that can't be converted are
+    /// left as they are so validation reports a `TypeMismatch`.
+    pub fn coerce_data(schema: &SchemaDefinition, data: &mut Value, coerce_by_default: bool) {
+        if let Value::Object(obj) = data {
+            Self::coerce_object(&schema.fields, obj, coerce_by_default);
         }
     }
 
-    /// Validate individual field
-    fn validate_field(value: &Value, field_type: &FieldType, rules: &[ValidationRule]) -> Result<(), ValidationError> {
-        // TODO: Implement field-level validation
-        match field_type {
-            FieldType::Text => {
-                if !value.is_string() {
-                    return Err(ValidationError::TypeMismatch("Expected string".to_string()));
+    fn coerce_object(fields: &HashMap<String, FieldDefinition>, obj: &mut Map<String, Value>, coerce_by_default: bool) {
+        for (field_name, field_def) in fields {
+            if let Some(value) = obj.get_mut(field_name) {
+                let enabled = field_def.coerce.unwrap_or(coerce_by_default);
+                Self::coerce_value(value, &field_def.field_type, enabled, coerce_by_default);
+            }
+        }
+    }
+
+    fn coerce_value(value: &mut Value, field_type: &FieldType, enabled: bool, coerce_by_default: bool) {
+        match (field_type, &mut *value) {
+            (FieldType::Number, Value::String(s)) if enabled => {
+                if let Some(number) = coerce_number(s) {
+                    *value = Value::Number(number);
                 }
             },
-            FieldType::Number => {
-                if !value.is_number() {
-                    return Err(ValidationError::TypeMismatch("Expected number".to_string()));
+            (FieldType::Boolean, Value::String(s)) if enabled => {
+                match s.trim().to_ascii_lowercase().as_str() {
+                    "true" => *value = Value::Bool(true),
+                    "false" => *value = Value::Bool(false),
+                    _ => {},
                 }
             },
-            FieldType::Boolean => {
-                if !value.is_boolean() {
-                    return Err(ValidationError::TypeMismatch("Expected boolean".to_string()));
+            (FieldType::Array(item_type), Value::Array(items)) => {
+                for item in items {
+                    Self::coerce_value(item, item_type, enabled, coerce_by_default);
+                }
+            },
+            (FieldType::Object(fields), Value::Object(obj)) => {
+                Self::coerce_object(fields, obj, coerce_by_default);
+            },
+            _ => {},
+        }
+    }
+
+    /// List the `Custom` rule applications `data` needs, for checking by the validator canister
+    ///
+    /// Only fields present in `data` are included, descending into nested
+    /// objects with the same paths used in validation errors.
+    pub fn custom_checks(schema: &SchemaDefinition, data: &Value) -> Vec<CustomCheck> {
+        let mut checks = Vec::new();
+        if let Value::Object(obj) = data {
+            Self::collect_custom_checks("", &schema.fields, obj, &mut checks);
+        }
+        checks
+    }
+
+    fn collect_custom_checks(
+        path: &str,
+        fields: &HashMap<String, FieldDefinition>,
+        obj: &Map<String, Value>,
+        checks: &mut Vec<CustomCheck>,
+    ) {
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (field_name, field_def) in fields {
+            let value = match obj.get(field_name) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let field_path = if path.is_empty() {
+                field_name.clone()
+            } else {
+                format!("{}.{}", path, field_name)
+            };
+
+            for rule in &field_def.validation_rules {
+                if let ValidationRule::Custom(name) = rule {
+                    checks.push(CustomCheck {
+                        path: field_path.clone(),
+                        name: name.clone(),
+                        value: value.clone(),
+                    });
                 }
+            }
+
+            if let (FieldType::Object(nested), Value::Object(nested_obj)) = (&field_def.field_type, value) {
+                Self::collect_custom_checks(&field_path, nested, nested_obj, checks);
+            }
+        }
+    }
+
+    /// Validate the fields of an object, appending any problems to `errors`
+    fn validate_object(
+        path: &str,
+        fields: &HashMap<String, FieldDefinition>,
+        obj: &Map<String, Value>,
+        mode: ValidationMode,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (field_name, field_def) in fields {
+            if mode == ValidationMode::FailFast && !errors.is_empty() {
+                return;
+            }
+
+            let field_path = if path.is_empty() {
+                field_name.clone()
+            } else {
+                format!("{}.{}", path, field_name)
+            };
+
+            match obj.get(field_name) {
+                Some(field_value) => Self::validate_field(
+                    &field_path, field_value, &field_def.field_type, &field_def.validation_rules, mode, errors,
+                ),
+                None if field_def.required => {
+                    errors.push(ValidationError::MissingRequiredField(field_path));
+                },
+                None => {},
+            }
+        }
+    }
+
+    /// Validate individual field, appending any problems to `errors`
+    fn validate_field(
+        path: &str,
+        value: &Value,
+        field_type: &FieldType,
+        rules: &[ValidationRule],
+        mode: ValidationMode,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let expected = match (field_type, value) {
+            (FieldType::Text, value) if !value.is_string() => Some("string"),
+            (FieldType::Number, value) if !value.is_number() => Some("number"),
+            (FieldType::Boolean, value) if !value.is_boolean() => Some("boolean"),
+            (FieldType::Array(_), value) if !value.is_array() => Some("array"),
+            (FieldType::Object(_), value) if !value.is_object() => Some("object"),
+            _ => None,
+        };
+
+        if let Some(expected) = expected {
+            errors.push(ValidationError::TypeMismatch(format!("{}: expected {}", path, expected)));
+            return;
+        }
+
+        match (field_type, value) {
+            (FieldType::Array(item_type), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    if mode == ValidationMode::FailFast && !errors.is_empty() {
+                        return;
+                    }
+                    Self::validate_field(&format!("{}[{}]", path, i), item, item_type, &[], mode, errors);
+                }
+            },
+            (FieldType::Object(fields), Value::Object(obj)) => {
+                Self::validate_object(path, fields, obj, mode, errors);
             },
-            _ => {} // TODO: Implement other types
+            _ => {},
         }
 
         // Apply validation rules
         for rule in rules {
-            Self::apply_validation_rule(value, rule)?;
+            if mode == ValidationMode::FailFast && !errors.is_empty() {
+                return;
+            }
+            if let Err(error) = Self::apply_validation_rule(path, value, field_type, rule) {
+                errors.push(error);
+            }
         }
-
-        Ok(())
     }
 
     /// Apply validation rule to value
-    fn apply_validation_rule(value: &Value, rule: &ValidationRule) -> Result<(), ValidationError> {
-        // TODO: Implement validation rules
+    ///
+    /// `Custom` rules are checked by the validator canister, see `custom_checks`.
+    fn apply_validation_rule(path: &str, value: &Value, field_type: &FieldType, rule: &ValidationRule) -> Result<(), ValidationError> {
         match rule {
             ValidationRule::MinLength(min_len) => {
                 if let Value::String(s) = value {
                     if s.len() < *min_len as usize {
                         return Err(ValidationError::ValidationFailed(
-                            format!("String too short, minimum length: {}", min_len)
+                            format!("{}: string too short, minimum length: {}", path, min_len)
                         ));
                     }
                 }
@@ -78,18 +241,238 @@ impl Validator {
                 if let Value::String(s) = value {
                     if s.len() > *max_len as usize {
                         return Err(ValidationError::ValidationFailed(
-                            format!("String too long, maximum length: {}", max_len)
+                            format!("{}: string too long, maximum length: {}", path, max_len)
                         ));
                     }
                 }
             },
-            _ => {} // TODO: Implement other rules
+            ValidationRule::Email => Self::check_format(path, value, "email address", is_email)?,
+            ValidationRule::Url => Self::check_format(path, value, "URL", is_url)?,
+            ValidationRule::Uuid => Self::check_format(path, value, "UUID", is_uuid)?,
+            ValidationRule::OneOf(allowed) => {
+                if !allowed.iter().any(|candidate| values_equal(candidate, value)) {
+                    let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+                    return Err(ValidationError::ValidationFailed(
+                        format!("{}: {} is not one of [{}]", path, value, allowed.join(", "))
+                    ));
+                }
+            },
+            ValidationRule::MaxSize(max_size) => {
+                // Uploaded blobs are referenced by ID and checked when the record is stored
+                let inline_blobs: Vec<(String, &Vec<Value>)> = match (field_type, value) {
+                    (FieldType::Blob, Value::Array(bytes)) => vec![(path.to_string(), bytes)],
+                    (FieldType::Array(item_type), Value::Array(items)) if matches!(item_type.as_ref(), FieldType::Blob) => {
+                        items.iter().enumerate()
+                            .filter_map(|(i, item)| Some((format!("{}[{}]", path, i), item.as_array()?)))
+                            .collect()
+                    },
+                    _ => Vec::new(),
+                };
+                for (blob_path, bytes) in inline_blobs {
+                    if bytes.len() as u64 > *max_size {
+                        return Err(ValidationError::ValidationFailed(
+                            format!("{}: blob of {} bytes exceeds maximum size {}", blob_path, bytes.len(), max_size)
+                        ));
+                    }
+                }
+            },
+            ValidationRule::MaxItems(max_items) => {
+                if let (FieldType::Array(_), Value::Array(items)) = (field_type, value) {
+                    if items.len() > *max_items as usize {
+                        return Err(ValidationError::ValidationFailed(
+                            format!("{}: {} items exceed the maximum of {}", path, items.len(), max_items)
+                        ));
+                    }
+                }
+            },
+            ValidationRule::Pattern(pattern) => {
+                if let Value::String(s) = value {
+                    let matched = Self::with_pattern(pattern, |regex| regex.is_match(s))
+                        .map_err(ValidationError::ValidationFailed)?;
+                    if !matched {
+                        return Err(ValidationError::ValidationFailed(
+                            format!("{}: {:?} does not match pattern {}", path, s, pattern)
+                        ));
+                    }
+                }
+            },
+            ValidationRule::Range(min, max) => {
+                if let Value::Number(n) = value {
+                    if !in_range(n, *min, *max) {
+                        return Err(ValidationError::ValidationFailed(
+                            format!("{}: {} is outside the range {} to {}", path, n, min, max)
+                        ));
+                    }
+                }
+            },
+            ValidationRule::Custom(_) => {},
         }
         Ok(())
     }
+
+    /// Check that every `Pattern` rule compiles and every `Range` is non-empty
+    ///
+    /// Run when a schema is loaded, so bad rules are rejected up front rather
+    /// than failing each record written.
+    pub fn check_rules(schema: &SchemaDefinition) -> Result<(), String> {
+        Self::check_field_rules("", &schema.fields)
+    }
+
+    fn check_field_rules(path: &str, fields: &HashMap<String, FieldDefinition>) -> Result<(), String> {
+        for (field_name, field_def) in fields {
+            let field_path = if path.is_empty() {
+                field_name.clone()
+            } else {
+                format!("{}.{}", path, field_name)
+            };
+
+            for rule in &field_def.validation_rules {
+                match rule {
+                    ValidationRule::Pattern(pattern) => {
+                        Self::with_pattern(pattern, |_| ()).map_err(|e| format!("{}: {}", field_path, e))?;
+                    },
+                    ValidationRule::Range(min, max) if min > max => {
+                        return Err(format!("{}: range minimum {} is above its maximum {}", field_path, min, max));
+                    },
+                    _ => {},
+                }
+            }
+
+            let mut field_type = &field_def.field_type;
+            while let FieldType::Array(item_type) = field_type {
+                field_type = item_type;
+            }
+            if let FieldType::Object(nested) = field_type {
+                Self::check_field_rules(&field_path, nested)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `f` with the compiled regex for `pattern`, compiling and caching it on first use
+    fn with_pattern<T>(pattern: &str, f: impl FnOnce(&Regex) -> T) -> Result<T, String> {
+        PATTERNS.with(|patterns| {
+            let mut patterns = patterns.borrow_mut();
+            if !patterns.contains_key(pattern) {
+                if pattern.len() > MAX_PATTERN_LENGTH {
+                    return Err(format!("Pattern too long, maximum length: {}", MAX_PATTERN_LENGTH));
+                }
+                let regex = RegexBuilder::new(pattern)
+                    .size_limit(MAX_REGEX_SIZE)
+                    .build()
+                    .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+                patterns.insert(pattern.to_string(), regex);
+            }
+            Ok(f(&patterns[pattern]))
+        })
+    }
+
+    /// Reject string values that don't satisfy `is_valid`; other values are left to type checking
+    fn check_format(path: &str, value: &Value, format: &str, is_valid: fn(&str) -> bool) -> Result<(), ValidationError> {
+        match value {
+            Value::String(s) if !is_valid(s) => Err(ValidationError::ValidationFailed(
+                format!("{}: {:?} is not a valid {}", path, s, format)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse a string as an integer when possible, otherwise as a finite float
+fn coerce_number(s: &str) -> Option<serde_json::Number> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i64>() {
+        return Some(n.into());
+    }
+    if let Ok(n) = s.parse::<u64>() {
+        return Some(n.into());
+    }
+    s.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+}
+
+/// Whether a number lies within `min..=max`, comparing integers exactly
+fn in_range(n: &serde_json::Number, min: i64, max: i64) -> bool {
+    match (n.as_i64(), n.as_u64()) {
+        (Some(i), _) => min <= i && i <= max,
+        // Above `i64::MAX`, so above any maximum
+        (None, Some(_)) => false,
+        (None, None) => n.as_f64().map_or(false, |f| min as f64 <= f && f <= max as f64),
+    }
+}
+
+/// `local@domain` with a dotted domain of non-empty labels; not full RFC 5322
+fn is_email(s: &str) -> bool {
+    let (local, domain) = match s.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    let local_ok = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c));
+
+    local_ok && domain.contains('.') && is_hostname(domain)
+}
+
+/// Absolute `http`/`https` URL with a hostname (or `localhost`) and optional port
+fn is_url(s: &str) -> bool {
+    let rest = match s.strip_prefix("https://").or_else(|| s.strip_prefix("http://")) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    if rest.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next().unwrap_or("");
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            if port.is_empty() || !port.chars().all(|c| c.is_ascii_digit()) || port.parse::<u16>().is_err() {
+                return false;
+            }
+            host
+        },
+        None => authority,
+    };
+
+    host == "localhost" || (host.contains('.') && is_hostname(host))
 }
 
-#[derive(Debug, Clone)]
+/// Canonical 8-4-4-4-12 hex form, any version, either case
+fn is_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+/// Dot-separated labels of letters, digits and inner hyphens
+fn is_hostname(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 253
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// A `Custom` rule to be evaluated against one field value
+#[derive(Clone, Debug)]
+pub struct CustomCheck {
+    pub path: String,
+    pub name: String,
+    pub value: Value,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub enum ValidationError {
     MissingRequiredField(String),
     TypeMismatch(String),
@@ -113,4 +496,289 @@ impl std::fmt::Display for ValidationError {
                 write!(f, "Constraint violation: {}", msg),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(field_type: FieldType) -> FieldDefinition {
+        FieldDefinition {
+            field_type,
+            required: false,
+            default_value: None,
+            validation_rules: Vec::new(),
+            coerce: None,
+            auto_timestamp: None,
+        }
+    }
+
+    fn schema(fields: Vec<(&str, FieldDefinition)>) -> SchemaDefinition {
+        SchemaDefinition {
+            version: 1,
+            name: "people".to_string(),
+            fields: fields.into_iter().map(|(name, field)| (name.to_string(), field)).collect(),
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    /// A person with a required name, tags and a nested address
+    fn person_schema() -> SchemaDefinition {
+        let address: HashMap<String, FieldDefinition> = [
+            ("city".to_string(), FieldDefinition { required: true, ..field(FieldType::Text) }),
+            ("zip".to_string(), field(FieldType::Number)),
+        ].into_iter().collect();
+
+        schema(vec![
+            ("name", FieldDefinition {
+                required: true,
+                validation_rules: vec![ValidationRule::MinLength(3)],
+                ..field(FieldType::Text)
+            }),
+            ("tags", field(FieldType::Array(Box::new(FieldType::Text)))),
+            ("address", field(FieldType::Object(address))),
+        ])
+    }
+
+    fn messages(errors: &[ValidationError]) -> Vec<String> {
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn max_items_limits_arrays_and_max_size_limits_blobs() {
+        let schema = schema(vec![
+            ("tags", FieldDefinition {
+                validation_rules: vec![ValidationRule::MaxItems(2), ValidationRule::MaxSize(1)],
+                ..field(FieldType::Array(Box::new(FieldType::Text)))
+            }),
+            ("photo", FieldDefinition {
+                validation_rules: vec![ValidationRule::MaxSize(2), ValidationRule::MaxItems(1)],
+                ..field(FieldType::Blob)
+            }),
+        ]);
+
+        let within = json!({"tags": ["a", "b"], "photo": [1, 2]});
+        assert!(Validator::validate_data(&schema, &within, ValidationMode::CollectAll).is_ok());
+
+        let beyond = json!({"tags": ["a", "b", "c"], "photo": [1, 2, 3]});
+        let errors = Validator::validate_data(&schema, &beyond, ValidationMode::CollectAll).unwrap_err();
+        assert_eq!(messages(&errors), [
+            "Validation failed: photo: blob of 3 bytes exceeds maximum size 2",
+            "Validation failed: tags: 3 items exceed the maximum of 2",
+        ]);
+    }
+
+    #[test]
+    fn one_of_compares_large_integers_exactly() {
+        let schema = schema(vec![
+            ("id", FieldDefinition {
+                validation_rules: vec![ValidationRule::OneOf(vec![json!(9_007_199_254_740_993u64), json!(2)])],
+                ..field(FieldType::Number)
+            }),
+        ]);
+
+        for allowed in [json!({"id": 9_007_199_254_740_993u64}), json!({"id": 2.0})] {
+            assert!(Validator::validate_data(&schema, &allowed, ValidationMode::CollectAll).is_ok());
+        }
+        // Equal to the allowed value once both are rounded to f64
+        let rounded = json!({"id": 9_007_199_254_740_992u64});
+        assert!(Validator::validate_data(&schema, &rounded, ValidationMode::CollectAll).is_err());
+    }
+
+    #[test]
+    fn collects_every_violation_with_nested_paths() {
+        let data = json!({
+            "name": "Al",
+            "tags": ["ok", 7],
+            "address": { "zip": "10115" },
+        });
+
+        let errors = Validator::validate_data(&person_schema(), &data, ValidationMode::CollectAll).unwrap_err();
+        assert_eq!(messages(&errors), [
+            "Missing required field: address.city",
+            "Type mismatch: address.zip: expected number",
+            "Validation failed: name: string too short, minimum length: 3",
+            "Type mismatch: tags[1]: expected string",
+        ]);
+    }
+
+    #[test]
+    fn fail_fast_stops_at_the_first_violation() {
+        let data = json!({
+            "name": "Al",
+            "tags": ["ok", 7],
+            "address": { "zip": "10115" },
+        });
+
+        let errors = Validator::validate_data(&person_schema(), &data, ValidationMode::FailFast).unwrap_err();
+        assert_eq!(messages(&errors), ["Missing required field: address.city"]);
+    }
+
+    #[test]
+    fn valid_data_passes_in_both_modes() {
+        let data = json!({
+            "name": "Ada",
+            "tags": ["math"],
+            "address": { "city": "London", "zip": 1815 },
+        });
+
+        for mode in [ValidationMode::CollectAll, ValidationMode::FailFast] {
+            assert!(Validator::validate_data(&person_schema(), &data, mode).is_ok());
+        }
+    }
+
+    #[test]
+    fn non_objects_are_rejected() {
+        let errors = Validator::validate_data(&person_schema(), &json!([1, 2]), ValidationMode::CollectAll).unwrap_err();
+        assert!(matches!(errors.as_slice(), [ValidationError::InvalidDataFormat(_)]));
+    }
+
+    fn format_errors(rule: ValidationRule, value: Value) -> Vec<String> {
+        let schema = schema(vec![("value", FieldDefinition { validation_rules: vec![rule], ..field(FieldType::Text) })]);
+        match Validator::validate_data(&schema, &json!({ "value": value }), ValidationMode::CollectAll) {
+            Ok(()) => Vec::new(),
+            Err(errors) => messages(&errors),
+        }
+    }
+
+    #[test]
+    fn email_format() {
+        for valid in ["ada@example.com", "first.last+tag@mail.example.org", "o'neil@example.co"] {
+            assert!(is_email(valid), "{} was rejected", valid);
+        }
+        for invalid in ["", "ada", "ada@", "@example.com", "ada@example", ".ada@example.com",
+                        "a..b@example.com", "ada@exa mple.com", "ada@-example.com", "ada@@example.com"] {
+            assert!(!is_email(invalid), "{} was accepted", invalid);
+        }
+
+        assert_eq!(format_errors(ValidationRule::Email, json!("ada")),
+                   ["Validation failed: value: \"ada\" is not a valid email address"]);
+        assert!(format_errors(ValidationRule::Email, json!("ada@example.com")).is_empty());
+    }
+
+    #[test]
+    fn url_format() {
+        for valid in ["https://example.com", "http://localhost:8080/path?q=1#top", "https://a.b.example.org/"] {
+            assert!(is_url(valid), "{} was rejected", valid);
+        }
+        for invalid in ["", "example.com", "ftp://example.com", "https://", "https://example",
+                        "https://example.com:99999", "https://example.com:", "https://exa mple.com"] {
+            assert!(!is_url(invalid), "{} was accepted", invalid);
+        }
+
+        assert_eq!(format_errors(ValidationRule::Url, json!("example.com")),
+                   ["Validation failed: value: \"example.com\" is not a valid URL"]);
+    }
+
+    #[test]
+    fn uuid_format() {
+        for valid in ["123e4567-e89b-12d3-a456-426614174000", "123E4567-E89B-12D3-A456-426614174000"] {
+            assert!(is_uuid(valid), "{} was rejected", valid);
+        }
+        for invalid in ["", "123e4567e89b12d3a456426614174000", "123e4567-e89b-12d3-a456-42661417400",
+                        "123e4567-e89b-12d3-a456-4266141740000", "g23e4567-e89b-12d3-a456-426614174000"] {
+            assert!(!is_uuid(invalid), "{} was accepted", invalid);
+        }
+
+        assert_eq!(format_errors(ValidationRule::Uuid, json!("not-a-uuid")),
+                   ["Validation failed: value: \"not-a-uuid\" is not a valid UUID"]);
+    }
+
+    #[test]
+    fn formats_leave_non_strings_to_type_checking() {
+        assert_eq!(format_errors(ValidationRule::Email, json!(42)), ["Type mismatch: value: expected string"]);
+    }
+
+    fn one_of_errors(field_type: FieldType, allowed: Vec<Value>, value: Value) -> Vec<String> {
+        let rules = vec![ValidationRule::OneOf(allowed)];
+        let schema = schema(vec![("value", FieldDefinition { validation_rules: rules, ..field(field_type) })]);
+        match Validator::validate_data(&schema, &json!({ "value": value }), ValidationMode::CollectAll) {
+            Ok(()) => Vec::new(),
+            Err(errors) => messages(&errors),
+        }
+    }
+
+    #[test]
+    fn one_of_restricts_strings_to_the_allowed_set() {
+        let allowed = vec![json!("draft"), json!("published")];
+
+        assert!(one_of_errors(FieldType::Text, allowed.clone(), json!("draft")).is_empty());
+        assert_eq!(one_of_errors(FieldType::Text, allowed.clone(), json!("archived")),
+                   ["Validation failed: value: \"archived\" is not one of [\"draft\", \"published\"]"]);
+        // Matching is exact
+        assert_eq!(one_of_errors(FieldType::Text, allowed, json!("Draft")).len(), 1);
+    }
+
+    #[test]
+    fn one_of_compares_numbers_by_value() {
+        let allowed = vec![json!(1), json!(2.5)];
+
+        assert!(one_of_errors(FieldType::Number, allowed.clone(), json!(1.0)).is_empty());
+        assert!(one_of_errors(FieldType::Number, allowed.clone(), json!(2.5)).is_empty());
+        assert_eq!(one_of_errors(FieldType::Number, allowed, json!(3)),
+                   ["Validation failed: value: 3 is not one of [1, 2.5]"]);
+    }
+
+    fn coercion_schema(score_coerce: Option<bool>) -> SchemaDefinition {
+        schema(vec![
+            ("score", FieldDefinition { coerce: score_coerce, ..field(FieldType::Number) }),
+            ("active", field(FieldType::Boolean)),
+            ("label", field(FieldType::Text)),
+            ("readings", field(FieldType::Array(Box::new(FieldType::Number)))),
+        ])
+    }
+
+    #[test]
+    fn coercion_converts_strings_to_numbers_and_booleans() {
+        let schema = coercion_schema(None);
+        let mut data = json!({
+            "score": " 42 ",
+            "active": "TRUE",
+            "label": "7",
+            "readings": ["1.5", "-2", 3],
+        });
+
+        Validator::coerce_data(&schema, &mut data, true);
+        assert_eq!(data, json!({
+            "score": 42,
+            "active": true,
+            "label": "7",
+            "readings": [1.5, -2, 3],
+        }));
+        assert!(Validator::validate_data(&schema, &data, ValidationMode::CollectAll).is_ok());
+    }
+
+    #[test]
+    fn failed_coercion_leaves_a_type_mismatch() {
+        let schema = coercion_schema(None);
+        let mut data = json!({ "score": "forty-two", "active": "yes" });
+
+        Validator::coerce_data(&schema, &mut data, true);
+        assert_eq!(data, json!({ "score": "forty-two", "active": "yes" }));
+
+        let errors = Validator::validate_data(&schema, &data, ValidationMode::CollectAll).unwrap_err();
+        assert_eq!(messages(&errors), [
+            "Type mismatch: active: expected boolean",
+            "Type mismatch: score: expected number",
+        ]);
+    }
+
+    #[test]
+    fn field_setting_overrides_the_cell_default() {
+        let mut data = json!({ "score": "42", "active": "true" });
+        Validator::coerce_data(&coercion_schema(Some(false)), &mut data, true);
+        assert_eq!(data, json!({ "score": "42", "active": true }));
+
+        let mut data = json!({ "score": "42", "active": "true" });
+        Validator::coerce_data(&coercion_schema(Some(true)), &mut data, false);
+        assert_eq!(data, json!({ "score": 42, "active": "true" }));
+    }
+
+    #[test]
+    fn coercion_rejects_non_finite_numbers() {
+        assert!(coerce_number("NaN").is_none());
+        assert!(coerce_number("inf").is_none());
+        assert_eq!(coerce_number("18446744073709551615"), Some(u64::MAX.into()));
+    }
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+use serde_json::json;
+
+/// A cell anyone may write to, with anonymous writes allowed or not
+fn public_cell(allow_anonymous_writes: Option<bool>) -> Cell {
+    let mut public = config(item_schema(vec![]));
+    public.permissions.write = vec![AccessLevel::Public];
+    public.allow_anonymous_writes = allow_anonymous_writes;
+    Cell::new(public)
+}
+
+fn anonymous_insert(cell: &Cell) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) = cell.update(
+        Principal::anonymous(), "insert", (item("anon", "a", 1).to_string(), None::<u64>, None::<Precondition>, None::<String>),
+    );
+    result
+}
+
+#[test]
+fn anonymous_callers_are_denied_every_write() {
+    let cell = public_cell(None);
+    let record_id = cell.insert(item("alpha", "a", 1));
+
+    assert_eq!(anonymous_insert(&cell), Err(CellError::PermissionDenied));
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        Principal::anonymous(), "update", (record_id.clone(), json!({"score": 2}).to_string(), None::<Precondition>),
+    );
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    let (result,): (Result<(), CellError>,) = cell.update(Principal::anonymous(), "delete", (record_id.clone(),));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    let (result,): (Result<String, CellError>,) = cell.update(Principal::anonymous(), "put_blob", (vec![1u8, 2, 3],));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    let (result,): (Result<i64, CellError>,) = cell.update(
+        Principal::anonymous(), "increment_field", (record_id.clone(), "score".to_string(), 1i64),
+    );
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    assert_eq!(cell.get(&record_id).unwrap()["score"], json!(1));
+    assert_eq!(cell.health().record_count, 1);
+}
+
+#[test]
+fn anonymous_reads_are_unaffected() {
+    let cell = public_cell(None);
+    let record_id = cell.insert(item("alpha", "a", 1));
+
+    let (record,): (Option<String>,) = cell.query(Principal::anonymous(), "get", (record_id,));
+    assert_eq!(parse(&record.unwrap())["name"], json!("alpha"));
+}
+
+#[test]
+fn cells_configured_for_anonymous_writes_accept_them() {
+    let cell = public_cell(Some(true));
+
+    let record_id = anonymous_insert(&cell).unwrap();
+    assert_eq!(cell.get(&record_id).unwrap()["name"], json!("anon"));
+}
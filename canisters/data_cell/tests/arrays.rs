@@ -0,0 +1,71 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+
+fn tagged_cell() -> Cell {
+    let mut tags = field(FieldType::Array(Box::new(FieldType::Text)));
+    tags.validation_rules.push(ValidationRule::MaxItems(3));
+    Cell::new(config(schema(vec![
+        ("name", required(FieldType::Text)),
+        ("tags", tags),
+        ("score", field(FieldType::Number)),
+    ], vec![])))
+}
+
+fn mutate(cell: &Cell, method: &str, record_id: &str, field: &str, values: Vec<Value>) -> Result<Vec<Value>, CellError> {
+    let values: Vec<String> = values.iter().map(Value::to_string).collect();
+    let (result,): (Result<Vec<String>, CellError>,) =
+        cell.update(user(), method, (record_id.to_string(), field.to_string(), values));
+    result.map(|items| items.iter().map(|item| parse(item)).collect())
+}
+
+fn append(cell: &Cell, record_id: &str, values: Vec<Value>) -> Result<Vec<Value>, CellError> {
+    mutate(cell, "append_to_array", record_id, "tags", values)
+}
+
+fn remove(cell: &Cell, record_id: &str, values: Vec<Value>) -> Result<Vec<Value>, CellError> {
+    mutate(cell, "remove_from_array", record_id, "tags", values)
+}
+
+#[test]
+fn appending_extends_the_stored_array() {
+    let cell = tagged_cell();
+    let record_id = cell.insert(json!({"name": "post"}));
+
+    assert_eq!(append(&cell, &record_id, vec![json!("rust")]).unwrap(), [json!("rust")]);
+    assert_eq!(append(&cell, &record_id, vec![json!("ic"), json!("db")]).unwrap(), [json!("rust"), json!("ic"), json!("db")]);
+    assert_eq!(cell.get(&record_id).unwrap()["tags"], json!(["rust", "ic", "db"]));
+}
+
+#[test]
+fn removing_drops_every_occurrence() {
+    let cell = tagged_cell();
+    let record_id = cell.insert(json!({"name": "post", "tags": ["a", "b", "a"]}));
+
+    assert_eq!(remove(&cell, &record_id, vec![json!("a"), json!("missing")]).unwrap(), [json!("b")]);
+    assert_eq!(cell.get(&record_id).unwrap()["tags"], json!(["b"]));
+    assert_eq!(remove(&cell, &record_id, vec![json!("b")]).unwrap(), Vec::<Value>::new());
+}
+
+#[test]
+fn appends_past_max_items_or_of_the_wrong_type_are_rejected() {
+    let cell = tagged_cell();
+    let record_id = cell.insert(json!({"name": "post", "tags": ["a", "b"]}));
+
+    assert!(matches!(append(&cell, &record_id, vec![json!("c"), json!("d")]), Err(CellError::ValidationError(_))));
+    assert!(matches!(append(&cell, &record_id, vec![json!(3)]), Err(CellError::ValidationError(_))));
+    assert_eq!(cell.get(&record_id).unwrap()["tags"], json!(["a", "b"]));
+
+    assert_eq!(append(&cell, &record_id, vec![json!("c")]).unwrap().len(), 3);
+}
+
+#[test]
+fn array_operations_need_an_array_field_on_an_existing_record() {
+    let cell = tagged_cell();
+    let record_id = cell.insert(json!({"name": "post"}));
+
+    let on_number = mutate(&cell, "append_to_array", &record_id, "score", vec![json!(1)]);
+    assert!(matches!(on_number, Err(CellError::ValidationError(_))));
+    assert!(matches!(append(&cell, "missing", vec![json!("a")]), Err(CellError::NotFound(_))));
+}
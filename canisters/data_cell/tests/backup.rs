@@ -0,0 +1,134 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+
+/// Every record of a cell as `(id, record)` pairs, read with `export_chunk`
+fn export_all(cell: &Cell, chunk_size: u32) -> (Vec<ExportChunk>, Vec<(String, Value)>) {
+    let mut chunks = Vec::new();
+    let mut cursor = None;
+    loop {
+        let (chunk,): (ExportChunk,) = cell.query(user(), "export_chunk", (cursor.clone(), chunk_size));
+        cursor = chunk.next_cursor.clone();
+        chunks.push(chunk);
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    let records = chunks.iter()
+        .flat_map(|chunk| chunk.records.iter().map(|(record_id, record)| (record_id.clone(), parse(record))))
+        .collect();
+    (chunks, records)
+}
+
+fn populated_cell() -> Cell {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    for i in 0..25 {
+        cell.insert(item(&format!("item {:02}", i), if i % 2 == 0 { "even" } else { "odd" }, i));
+    }
+    cell
+}
+
+#[test]
+fn export_then_import_round_trips_a_cell() {
+    let source = populated_cell();
+    let (chunks, records) = export_all(&source, 10);
+
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks[0].schema.is_some());
+    assert!(chunks[1..].iter().all(|chunk| chunk.schema.is_none()));
+    assert_eq!(records.len(), 25);
+    let mut ids: Vec<&String> = records.iter().map(|(record_id, _)| record_id).collect();
+    let exported_order = ids.clone();
+    ids.sort();
+    assert_eq!(ids, exported_order, "records are not exported in key order");
+
+    let target = source.sibling(config(chunks[0].schema.clone().unwrap()));
+    let report = target.import(records.clone(), true);
+    assert_eq!((report.imported, report.rejected), (25, 0));
+
+    for (record_id, record) in &records {
+        assert_eq!(target.get(record_id).as_ref(), Some(record));
+    }
+}
+
+#[test]
+fn trusted_import_of_an_export_gives_query_parity() {
+    let source = populated_cell();
+    let (_, records) = export_all(&source, 1_000);
+
+    let target = source.sibling(config(item_schema(vec![index("by_category", &["category"])])));
+    let (denied,): (Result<ImportReport, CellError>,) = target.update(
+        user(), "import_chunk", (Vec::<(String, String)>::new(), false, None::<Vec<(String, u64)>>),
+    );
+    assert_eq!(denied.unwrap_err(), CellError::PermissionDenied);
+
+    let report = target.import(records, false);
+    assert_eq!((report.imported, report.rejected), (25, 0));
+
+    let even = filter(vec![condition("category", ComparisonOperator::Equals, json!("even"))]);
+    let top = filter(vec![condition("score", ComparisonOperator::GreaterThan, json!(20))]);
+    for query in [even.clone(), top, filter(vec![])] {
+        assert_eq!(target.names(query.clone()), source.names(query));
+    }
+
+    // Indexes were rebuilt for the imported records
+    assert_eq!(target.estimate(even.clone()).index_used.as_deref(), Some("by_category"));
+    assert_eq!(target.estimate(even).estimated_records_scanned, 13);
+}
+
+#[test]
+fn imported_records_are_published_to_the_change_feed() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let mut replica_config = config(item_schema(vec![]));
+    replica_config.replica_of = Some(primary.id);
+    let replica = primary.sibling(replica_config);
+    replica.settle();
+
+    let records = vec![
+        ("restored-1".to_string(), item("one", "tools", 1)),
+        ("restored-2".to_string(), item("two", "books", 2)),
+    ];
+    primary.import(records.clone(), false);
+    replica.settle();
+
+    for (record_id, record) in &records {
+        assert_eq!(replica.get(record_id).as_ref(), Some(record));
+    }
+}
+
+#[test]
+fn import_restores_expiry_times_from_an_export() {
+    let source = Cell::new(config(item_schema(vec![])));
+    let expiring = source.insert_expiring(item("session", "tools", 1), source.now() + 30 * 1_000_000_000);
+    let kept = source.insert(item("profile", "tools", 2));
+
+    let (chunks, _) = export_all(&source, 1_000);
+    assert_eq!(chunks[0].expirations.len(), 1);
+    assert_eq!(chunks[0].expirations[0].0, expiring);
+
+    let target = source.sibling(config(item_schema(vec![])));
+    let report = target.import_export(&chunks[0], true);
+    assert_eq!((report.imported, report.rejected), (2, 0));
+    assert!(target.get(&expiring).is_some());
+
+    target.pic.advance_time(std::time::Duration::from_secs(40));
+    assert_eq!(target.get(&expiring), None);
+    assert!(target.get(&kept).is_some());
+}
+
+#[test]
+fn monotonic_ids_continue_after_imported_ones() {
+    let monotonic = || CellInitConfig { id_strategy: Some(IdStrategy::Monotonic), ..config(item_schema(vec![])) };
+    let source = Cell::new(monotonic());
+    let imported: Vec<String> = (0..3).map(|i| source.insert(item(&format!("item {}", i), "tools", i))).collect();
+    let (_, records) = export_all(&source, 1_000);
+
+    let target = source.sibling(monotonic());
+    target.import(records, true);
+
+    let next = target.insert(item("fresh", "tools", 9));
+    assert!(!imported.contains(&next), "reissued imported ID {}", next);
+    assert_eq!(target.health().record_count, 4);
+}
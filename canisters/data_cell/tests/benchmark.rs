@@ -0,0 +1,73 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+
+fn benchmarking_cell() -> Cell {
+    Cell::new(CellInitConfig { benchmark_enabled: Some(true), ..config(item_schema(vec![])) })
+}
+
+fn benchmark(cell: &Cell, sender: Principal, record_count: u32) -> Result<BenchmarkReport, CellError> {
+    let (result,): (Result<BenchmarkReport, CellError>,) = cell.update(sender, "self_benchmark", (record_count,));
+    result
+}
+
+#[test]
+fn the_report_has_sane_numbers_for_every_operation() {
+    let cell = benchmarking_cell();
+    let report = benchmark(&cell, controller(), 100).unwrap();
+
+    assert_eq!(report.record_count, 100);
+    let operations: Vec<TrackedOperation> = report.operations.iter().map(|op| op.operation).collect();
+    assert_eq!(operations, [TrackedOperation::Insert, TrackedOperation::Query, TrackedOperation::Update, TrackedOperation::Delete]);
+
+    for op in &report.operations {
+        assert!(op.instructions_per_op > 0, "{:?}", op);
+        assert!(op.cycles_per_op > 0, "{:?}", op);
+        assert!(op.ops_per_second > 0, "{:?}", op);
+        assert!(op.instructions_per_op * 100 <= report.total_instructions, "{:?}", op);
+    }
+
+    // Writes maintain indexes and hashes, so they cost more than reads by ID
+    let cost = |operation: TrackedOperation| report.operations.iter()
+        .find(|op| op.operation == operation).unwrap().instructions_per_op;
+    assert!(cost(TrackedOperation::Insert) > cost(TrackedOperation::Query));
+}
+
+#[test]
+fn synthetic_records_are_cleaned_up() {
+    let cell = benchmarking_cell();
+    let kept = cell.insert(item("kept", "a", 1));
+
+    benchmark(&cell, controller(), 50).unwrap();
+
+    assert_eq!(cell.health().record_count, 1);
+    assert_eq!(cell.names(filter(vec![])), vec!["kept"]);
+    assert!(cell.get(&kept).is_some());
+
+    // A second run finds no leftovers in its way
+    benchmark(&cell, controller(), 50).unwrap();
+}
+
+#[test]
+fn record_counts_are_bounded() {
+    let cell = benchmarking_cell();
+
+    assert!(matches!(benchmark(&cell, controller(), 0), Err(CellError::ValidationError(_))));
+    assert!(matches!(benchmark(&cell, controller(), 1_001), Err(CellError::ValidationError(_))));
+}
+
+#[test]
+fn benchmarks_are_disabled_by_default() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    assert!(matches!(benchmark(&cell, controller(), 10), Err(CellError::PreconditionFailed(_))));
+}
+
+#[test]
+fn only_admins_run_benchmarks() {
+    let cell = benchmarking_cell();
+
+    assert!(matches!(benchmark(&cell, user(), 10), Err(CellError::PermissionDenied)));
+    assert_eq!(cell.health().record_count, 0);
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// Longer than the orphan grace period and upload timeout plus one expiry sweep interval
+const ORPHAN_SWEEP_SECS: u64 = 60 * 60 + 61;
+
+fn photo_cell(max_size: Option<u64>) -> Cell {
+    let mut photo = field(FieldType::Blob);
+    photo.validation_rules.extend(max_size.map(ValidationRule::MaxSize));
+    Cell::new(config(schema(vec![("name", required(FieldType::Text)), ("photo", photo)], vec![])))
+}
+
+fn put_blob(cell: &Cell, bytes: Vec<u8>) -> String {
+    let (result,): (Result<String, CellError>,) = cell.update(user(), "put_blob", (bytes,));
+    result.expect("put_blob failed")
+}
+
+fn get_blob(cell: &Cell, blob_id: &str) -> Result<Vec<u8>, CellError> {
+    let (result,): (Result<Vec<u8>, CellError>,) = cell.query(user(), "get_blob", (blob_id.to_string(),));
+    result
+}
+
+#[test]
+fn blobs_round_trip_and_records_hold_only_the_reference() {
+    let cell = photo_cell(None);
+    let bytes: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+
+    let blob_id = put_blob(&cell, bytes.clone());
+    let record_id = cell.insert(json!({"name": "alpha", "photo": blob_id}));
+
+    assert_eq!(get_blob(&cell, &blob_id).unwrap(), bytes);
+    assert_eq!(cell.get(&record_id).unwrap()["photo"], json!(blob_id));
+    assert!(matches!(get_blob(&cell, "blob_missing"), Err(CellError::NotFound(_))));
+}
+
+#[test]
+fn records_must_reference_existing_blobs_within_max_size() {
+    let cell = photo_cell(Some(100));
+
+    let unknown = cell.try_insert(json!({"name": "alpha", "photo": "blob_missing"}));
+    assert!(matches!(unknown, Err(CellError::StorageError(_))));
+
+    let oversized = put_blob(&cell, vec![0; 101]);
+    let too_large = cell.try_insert(json!({"name": "alpha", "photo": oversized}));
+    assert!(matches!(too_large, Err(CellError::StorageError(_))));
+
+    let fitting = put_blob(&cell, vec![0; 100]);
+    cell.insert(json!({"name": "alpha", "photo": fitting}));
+    let claimed = cell.try_insert(json!({"name": "beta", "photo": fitting}));
+    assert!(matches!(claimed, Err(CellError::StorageError(_))));
+}
+
+#[test]
+fn deleting_or_replacing_a_record_deletes_its_blobs() {
+    let cell = photo_cell(None);
+    let first = put_blob(&cell, vec![1; 10]);
+    let record_id = cell.insert(json!({"name": "alpha", "photo": first}));
+
+    let second = put_blob(&cell, vec![2; 10]);
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (record_id.clone(), json!({"photo": second}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    assert!(matches!(get_blob(&cell, &first), Err(CellError::NotFound(_))));
+    assert_eq!(get_blob(&cell, &second).unwrap(), vec![2; 10]);
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (record_id,));
+    result.unwrap();
+    assert!(matches!(get_blob(&cell, &second), Err(CellError::NotFound(_))));
+}
+
+#[test]
+fn unreferenced_blobs_are_swept_after_the_grace_period() {
+    let cell = photo_cell(None);
+    let orphan = put_blob(&cell, vec![1; 10]);
+    let claimed = put_blob(&cell, vec![2; 10]);
+    cell.insert(json!({"name": "alpha", "photo": claimed}));
+
+    // Sweeps within the grace period leave the orphan for a record to claim
+    cell.advance_secs(120);
+    assert!(get_blob(&cell, &orphan).is_ok());
+
+    cell.advance_secs(ORPHAN_SWEEP_SECS);
+    assert!(matches!(get_blob(&cell, &orphan), Err(CellError::NotFound(_))));
+    assert_eq!(get_blob(&cell, &claimed).unwrap(), vec![2; 10]);
+}
+
+fn begin_upload(cell: &Cell, field: Option<&str>) -> String {
+    let (result,): (Result<String, CellError>,) =
+        cell.update(user(), "begin_blob_upload", (field.map(str::to_string),));
+    result.expect("begin_blob_upload failed")
+}
+
+fn put_chunk(cell: &Cell, upload_id: &str, offset: u64, bytes: &[u8]) -> Result<(), CellError> {
+    let (result,): (Result<(), CellError>,) =
+        cell.update(user(), "put_blob_chunk", (upload_id.to_string(), offset, bytes.to_vec()));
+    result
+}
+
+fn finish_upload(cell: &Cell, upload_id: &str) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) =
+        cell.update(user(), "finish_blob_upload", (upload_id.to_string(),));
+    result
+}
+
+#[test]
+fn a_multi_chunk_upload_reads_back_intact() {
+    let cell = photo_cell(None);
+    let bytes: Vec<u8> = (0..2_500_000u32).map(|i| (i % 251) as u8).collect();
+    let upload_id = begin_upload(&cell, None);
+
+    // Chunks may arrive in any order
+    let chunks: Vec<(u64, &[u8])> = bytes.chunks(1_000_000)
+        .enumerate()
+        .map(|(i, chunk)| (i as u64 * 1_000_000, chunk))
+        .collect();
+    for (offset, chunk) in chunks.iter().rev() {
+        put_chunk(&cell, &upload_id, *offset, chunk).unwrap();
+    }
+
+    let blob_id = finish_upload(&cell, &upload_id).unwrap();
+    assert_eq!(get_blob(&cell, &blob_id).unwrap(), bytes);
+    assert!(matches!(finish_upload(&cell, &upload_id), Err(CellError::NotFound(_))));
+}
+
+#[test]
+fn uploads_are_checked_for_size_gaps_and_ownership() {
+    let cell = photo_cell(Some(100));
+    let upload_id = begin_upload(&cell, Some("photo"));
+
+    assert!(matches!(put_chunk(&cell, &upload_id, 60, &[0; 41]), Err(CellError::ValidationError(_))));
+
+    put_chunk(&cell, &upload_id, 0, &[1; 50]).unwrap();
+    put_chunk(&cell, &upload_id, 60, &[2; 40]).unwrap();
+    assert!(matches!(finish_upload(&cell, &upload_id), Err(CellError::ValidationError(_))));
+
+    let (result,): (Result<(), CellError>,) =
+        cell.update(other_user(), "put_blob_chunk", (upload_id.clone(), 50u64, vec![3u8; 10]));
+    assert!(matches!(result, Err(CellError::PermissionDenied)));
+
+    put_chunk(&cell, &upload_id, 50, &[3; 10]).unwrap();
+    let blob_id = finish_upload(&cell, &upload_id).unwrap();
+    assert_eq!(get_blob(&cell, &blob_id).unwrap().len(), 100);
+}
+
+#[test]
+fn abandoned_uploads_are_discarded() {
+    let cell = photo_cell(None);
+    let upload_id = begin_upload(&cell, None);
+    put_chunk(&cell, &upload_id, 0, &[1; 10]).unwrap();
+
+    cell.advance_secs(ORPHAN_SWEEP_SECS);
+    assert!(matches!(put_chunk(&cell, &upload_id, 10, &[2; 10]), Err(CellError::NotFound(_))));
+    assert!(matches!(finish_upload(&cell, &upload_id), Err(CellError::NotFound(_))));
+}
+
+#[test]
+fn uploads_stop_once_the_uploader_loses_write_access() {
+    let cell = photo_cell(None);
+    let upload_id = begin_upload(&cell, None);
+    put_chunk(&cell, &upload_id, 0, &[1; 10]).unwrap();
+
+    let read_only = PermissionConfig { read: vec![AccessLevel::Public], write: vec![], admin: vec![] };
+    let (result,): (Result<(), CellError>,) = cell.update(controller(), "set_permissions", (read_only,));
+    result.unwrap();
+
+    assert_eq!(put_chunk(&cell, &upload_id, 10, &[2; 10]), Err(CellError::PermissionDenied));
+    assert_eq!(finish_upload(&cell, &upload_id), Err(CellError::PermissionDenied));
+}
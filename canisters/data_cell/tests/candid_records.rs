@@ -0,0 +1,88 @@
+mod common;
+
+use common::*;
+use candid::{CandidType, Deserialize};
+use serde_json::json;
+
+/// A record of `item_schema()` as a typed client sees it
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+struct Item {
+    name: String,
+    category: Option<String>,
+    score: Option<f64>,
+    tags: Option<Vec<String>>,
+}
+
+fn insert_candid(cell: &Cell, bytes: Vec<u8>) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) =
+        cell.update(user(), "insert_candid", (bytes, None::<u64>, None::<Precondition>));
+    result
+}
+
+fn get_candid(cell: &Cell, record_id: &str) -> Option<Item> {
+    let (result,): (Result<Option<Vec<u8>>, CellError>,) = cell.query(user(), "get_candid", (record_id.to_string(),));
+    result.expect("get_candid failed")
+        .map(|bytes| candid::decode_one(&bytes).expect("not a Candid Item"))
+}
+
+#[test]
+fn typed_records_round_trip() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let record = Item {
+        name: "alpha".to_string(),
+        category: None,
+        score: Some(2.5),
+        tags: Some(vec!["x".to_string(), "y".to_string()]),
+    };
+
+    let id = insert_candid(&cell, candid::encode_one(&record).unwrap()).unwrap();
+    assert_eq!(get_candid(&cell, &id), Some(record));
+    assert_eq!(get_candid(&cell, "missing"), None);
+
+    let (record_type,): (String,) = cell.query(user(), "candid_record_type", ());
+    assert!(record_type.contains("name : text"), "{}", record_type);
+    assert!(record_type.contains("score : opt float64"), "{}", record_type);
+}
+
+#[test]
+fn typed_and_json_paths_see_the_same_records() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let typed = Item { name: "typed".to_string(), category: Some("a".to_string()), score: Some(3.0), tags: None };
+    let typed_id = insert_candid(&cell, candid::encode_one(&typed).unwrap()).unwrap();
+    assert_eq!(cell.get(&typed_id).unwrap(), item("typed", "a", 3));
+
+    let json_id = cell.insert(item("plain", "b", 7));
+    assert_eq!(
+        get_candid(&cell, &json_id),
+        Some(Item { name: "plain".to_string(), category: Some("b".to_string()), score: Some(7.0), tags: None }),
+    );
+
+    let by_category = filter(vec![condition("category", ComparisonOperator::Equals, json!("a"))]);
+    let (result,): (Result<CandidQueryResult, CellError>,) = cell.query(user(), "query_candid", (by_category.clone(), page(10)));
+    let result = result.unwrap();
+    let json_result = cell.run_query(by_category, page(10)).unwrap();
+    assert_eq!(result.total_count, json_result.total_count);
+    let records: Vec<Item> = result.records.iter().map(|bytes| candid::decode_one(bytes).unwrap()).collect();
+    assert_eq!(records, [typed]);
+}
+
+#[test]
+fn candid_records_are_validated_like_json_ones() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    #[derive(CandidType)]
+    struct Nameless {
+        score: f64,
+    }
+    assert!(matches!(insert_candid(&cell, candid::encode_one(Nameless { score: 1.0 }).unwrap()), Err(CellError::SchemaViolation(_))));
+
+    #[derive(CandidType)]
+    struct MistypedName {
+        name: u32,
+    }
+    assert!(matches!(insert_candid(&cell, candid::encode_one(MistypedName { name: 1 }).unwrap()), Err(CellError::ValidationError(_))));
+
+    assert!(matches!(insert_candid(&cell, b"not candid".to_vec()), Err(CellError::ValidationError(_))));
+    assert_eq!(cell.health().record_count, 0);
+}
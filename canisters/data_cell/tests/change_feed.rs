@@ -0,0 +1,158 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn subscribe(cell: &Cell, sender: candid::Principal, subscriber: &Subscriber, method: &str) -> Result<(), CellError> {
+    let (result,): (Result<(), CellError>,) = cell.update(sender, "subscribe", (subscriber.id, method.to_string()));
+    result
+}
+
+#[test]
+fn subscriber_receives_insert_update_and_delete_events() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let subscriber = Subscriber::install(&cell);
+    subscribe(&cell, user(), &subscriber, "on_change").unwrap();
+
+    let id = cell.insert(item("alpha", "a", 1));
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 1);
+    let event = subscriber.last_event();
+    assert_eq!(event.op, ChangeOperation::Insert);
+    assert_eq!(event.record_id, id);
+    assert_eq!(parse(event.record.as_deref().unwrap())["name"], json!("alpha"));
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (id.clone(), item("alpha", "b", 2).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 2);
+    let event = subscriber.last_event();
+    assert_eq!(event.op, ChangeOperation::Update);
+    assert_eq!(parse(event.record.as_deref().unwrap())["category"], json!("b"));
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (id.clone(),));
+    result.unwrap();
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 3);
+    let event = subscriber.last_event();
+    assert_eq!(event.op, ChangeOperation::Delete);
+    assert_eq!(event.record_id, id);
+    assert_eq!(parse(event.record.as_deref().unwrap())["category"], json!("b"));
+}
+
+#[test]
+fn unsubscribed_callbacks_receive_nothing_further() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let subscriber = Subscriber::install(&cell);
+    subscribe(&cell, user(), &subscriber, "on_change").unwrap();
+
+    cell.insert(item("alpha", "a", 1));
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 1);
+
+    let (result,): (Result<(), CellError>,) =
+        cell.update(other_user(), "unsubscribe", (subscriber.id, "on_change".to_string()));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    let (result,): (Result<(), CellError>,) =
+        cell.update(controller(), "unsubscribe", (subscriber.id, "on_change".to_string()));
+    result.unwrap();
+
+    cell.insert(item("beta", "a", 2));
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 1);
+}
+
+#[test]
+fn subscribe_requires_read_access_for_the_callback() {
+    let mut restricted = config(item_schema(vec![]));
+    restricted.permissions.read = vec![AccessLevel::Principal(user())];
+    let cell = Cell::new(restricted);
+    let subscriber = Subscriber::install(&cell);
+
+    assert_eq!(subscribe(&cell, other_user(), &subscriber, "on_change"), Err(CellError::PermissionDenied));
+    assert_eq!(subscribe(&cell, user(), &subscriber, "on_change"), Err(CellError::PermissionDenied));
+
+    cell.insert(item("alpha", "a", 1));
+    cell.settle();
+    assert_eq!(subscriber.event_count(), 0);
+}
+
+fn dead_letters(cell: &Cell) -> Vec<DeadLetter> {
+    let (letters,): (Vec<DeadLetter>,) = cell.query(controller(), "dead_letters", ());
+    letters
+}
+
+fn redeliver(cell: &Cell, sender: candid::Principal, id: &str) -> Result<(), CellError> {
+    let (result,): (Result<(), CellError>,) = cell.update(sender, "redeliver", (id.to_string(),));
+    result
+}
+
+#[test]
+fn failed_deliveries_land_in_the_dead_letter_store() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let subscriber = Subscriber::install(&cell);
+    subscribe(&cell, user(), &subscriber, "fail").unwrap();
+
+    let id = cell.insert(item("alpha", "a", 1));
+    cell.settle();
+
+    let letters = dead_letters(&cell);
+    assert_eq!(letters.len(), 1);
+    let letter = &letters[0];
+    assert_eq!((letter.callback, letter.method.as_str()), (subscriber.id, "fail"));
+    assert_eq!((letter.event.op.clone(), letter.event.record_id.as_str()), (ChangeOperation::Insert, id.as_str()));
+    assert!(!letter.reason.is_empty());
+    assert_eq!(letter.attempts, 1);
+    assert!(letter.next_retry_at.unwrap() > letter.failed_at);
+
+    // A redelivery that fails again is counted and kept
+    assert!(matches!(redeliver(&cell, controller(), &letter.id), Err(CellError::StorageError(_))));
+    assert_eq!(dead_letters(&cell)[0].attempts, 2);
+}
+
+#[test]
+fn dead_letters_can_be_redelivered_once_the_subscriber_recovers() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let subscriber = Subscriber::install(&cell);
+    subscribe(&cell, user(), &subscriber, "on_change").unwrap();
+
+    cell.pic.stop_canister(subscriber.id, Some(controller())).expect("stop failed");
+    let id = cell.insert(item("alpha", "a", 1));
+    cell.settle();
+    let letters = dead_letters(&cell);
+    assert_eq!(letters.len(), 1);
+
+    cell.pic.start_canister(subscriber.id, Some(controller())).expect("start failed");
+    assert_eq!(redeliver(&cell, user(), &letters[0].id), Err(CellError::PermissionDenied));
+    redeliver(&cell, controller(), &letters[0].id).unwrap();
+
+    assert_eq!(subscriber.event_count(), 1);
+    assert_eq!(subscriber.last_event().record_id, id);
+    assert!(dead_letters(&cell).is_empty());
+    assert!(matches!(redeliver(&cell, controller(), &letters[0].id), Err(CellError::StorageError(_))));
+}
+
+#[test]
+fn dead_letters_are_retried_automatically_with_backoff() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let subscriber = Subscriber::install(&cell);
+    subscribe(&cell, user(), &subscriber, "on_change").unwrap();
+
+    cell.pic.stop_canister(subscriber.id, Some(controller())).expect("stop failed");
+    cell.insert(item("alpha", "a", 1));
+    cell.settle();
+
+    // The first retry, a minute later, fails too and doubles the delay
+    cell.advance_secs(121);
+    let letter = dead_letters(&cell).pop().expect("the event is still undelivered");
+    assert_eq!(letter.attempts, 2);
+    assert_eq!(letter.next_retry_at.unwrap() - letter.failed_at, 2 * 60 * 1_000_000_000);
+
+    cell.pic.start_canister(subscriber.id, Some(controller())).expect("start failed");
+    cell.advance_secs(181);
+    assert_eq!(subscriber.event_count(), 1);
+    assert!(dead_letters(&cell).is_empty());
+}
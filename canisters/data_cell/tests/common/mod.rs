@@ -0,0 +1,831 @@
+//! PocketIC harness for Data Cell integration tests
+//!
+//! Needs the cell wasm built for `wasm32-unknown-unknown` (or `DATA_CELL_WASM`
+//! pointing at it) and a PocketIC server binary in `POCKET_IC_BIN`.
+//! Record JSON travels as `text`, matching `data_cell.did`.
+
+#![allow(dead_code)]
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Deserialize, Principal};
+use pocket_ic::{query_candid_as, update_candid_as, PocketIc};
+use serde_json::{json, Value};
+use std::rc::Rc;
+
+pub const CYCLES: u128 = 2_000_000_000_000;
+
+pub fn controller() -> Principal {
+    Principal::self_authenticating(b"controller")
+}
+
+pub fn user() -> Principal {
+    Principal::self_authenticating(b"user")
+}
+
+pub fn other_user() -> Principal {
+    Principal::self_authenticating(b"other user")
+}
+
+pub fn cell_wasm() -> Vec<u8> {
+    let path = std::env::var("DATA_CELL_WASM").unwrap_or_else(|_| {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/wasm32-unknown-unknown/release/celldb.wasm").to_string()
+    });
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Could not read data cell wasm at {}: {}", path, e))
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellInitConfig {
+    pub name: String,
+    pub schema: SchemaDefinition,
+    pub permissions: PermissionConfig,
+    pub default_ttl_seconds: Option<u64>,
+    pub write_rate_limit_per_minute: Option<u32>,
+    pub fail_fast_validation: Option<bool>,
+    pub custom_validator: Option<Principal>,
+    pub custom_validator_fail_open: Option<bool>,
+    pub coerce_types: Option<bool>,
+    pub id_strategy: Option<IdStrategy>,
+    pub compress_records: Option<bool>,
+    pub replica_of: Option<Principal>,
+    pub allow_anonymous_writes: Option<bool>,
+    pub role_authority: Option<Principal>,
+    pub benchmark_enabled: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum IdStrategy {
+    Uuid,
+    Monotonic,
+    FromField(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SchemaDefinition {
+    pub version: u32,
+    pub name: String,
+    pub fields: Vec<(String, FieldDefinition)>,
+    pub indexes: Vec<IndexDefinition>,
+    pub constraints: Vec<ConstraintDefinition>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldDefinition {
+    pub field_type: FieldType,
+    pub required: bool,
+    pub default_value: Option<String>,
+    pub validation_rules: Vec<ValidationRule>,
+    pub coerce: Option<bool>,
+    pub auto_timestamp: Option<AutoTimestamp>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AutoTimestamp {
+    OnCreate,
+    OnUpdate,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Timestamp,
+    Principal,
+    Blob,
+    Array(Box<FieldType>),
+    Object(Vec<(String, FieldDefinition)>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub unique: bool,
+    pub lazy: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ConstraintDefinition {
+    Unique(Vec<String>),
+    ForeignKey { fields: Vec<String>, references: String },
+    Check(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ValidationRule {
+    MinLength(u32),
+    MaxLength(u32),
+    Pattern(String),
+    Range(i64, i64),
+    Custom(String),
+    Email,
+    Url,
+    Uuid,
+    OneOf(Vec<String>),
+    MaxSize(u64),
+    MaxItems(u32),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PermissionConfig {
+    pub read: Vec<AccessLevel>,
+    pub write: Vec<AccessLevel>,
+    pub admin: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum AccessLevel {
+    Public,
+    Authenticated,
+    Principal(Principal),
+    Role(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct QueryFilter {
+    pub conditions: Vec<FilterCondition>,
+    pub expression: Option<FilterExpr>,
+    pub sort_by: Option<String>,
+    pub sort_order: SortOrder,
+    pub since: Option<u64>,
+    pub trace_id: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Condition(FilterCondition),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FilterCondition {
+    pub field: String,
+    pub operator: ComparisonOperator,
+    pub value: String,
+    pub case_insensitive: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ComparisonOperator {
+    Equals,
+    NotEquals,
+    GreaterThan,
+    LessThan,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Matches,
+    In,
+    NotIn,
+    IsNull,
+    IsNotNull,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Pagination {
+    pub offset: u64,
+    pub limit: u64,
+    pub cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QueryResult {
+    pub records: Vec<String>,
+    pub total_count: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+    pub not_modified: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    MissingRequiredField(String),
+    TypeMismatch(String),
+    ValidationFailed(String),
+    InvalidDataFormat(String),
+    ConstraintViolation(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellError {
+    ValidationError(String),
+    PermissionDenied,
+    NotFound(String),
+    SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
+    NotImplemented(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Precondition {
+    Absent,
+    FieldEquals { field: String, value: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum UpsertResult {
+    Inserted,
+    Updated,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellMetrics {
+    pub record_count: u64,
+    pub memory_usage: u64,
+    pub query_count: u64,
+    pub uncompressed_bytes: u64,
+    pub stored_bytes: u64,
+    pub compression_ratio: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QueryCostEstimate {
+    pub index_used: Option<String>,
+    pub estimated_records_scanned: u64,
+    pub estimated_records_returned: u64,
+    pub estimated_cycles: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexStat {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub entries: u64,
+    pub distinct_values: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ExportChunk {
+    pub schema: Option<SchemaDefinition>,
+    pub records: Vec<(String, String)>,
+    pub expirations: Vec<(String, u64)>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CandidQueryResult {
+    pub records: Vec<Vec<u8>>,
+    pub total_count: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NdjsonChunk {
+    pub data: String,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ColumnMapping {
+    pub fields: Vec<(String, String)>,
+    pub id_column: Option<String>,
+    pub has_header: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: u64,
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded(String),
+    Error(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellCapability {
+    FullTextSearch,
+    GeospatialQueries,
+    AdvancedIndexing,
+    StreamingSupport,
+    BatchOperations,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    pub schema_version: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellHealth {
+    pub status: HealthStatus,
+    pub schema_version: u32,
+    pub record_count: u64,
+    pub capabilities: Vec<CellCapability>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RecordWithEtag {
+    pub record: String,
+    pub etag: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReindexReport {
+    pub records_reindexed: u64,
+    pub complete: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IndexBuild {
+    pub index_name: String,
+    pub started_at: Option<u64>,
+    pub last_record_id: Option<String>,
+    pub records_indexed: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TrackedOperation {
+    Insert,
+    Query,
+    Update,
+    Delete,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct OperationMetrics {
+    pub operation: TrackedOperation,
+    pub count: u64,
+    pub min_instructions: u64,
+    pub avg_instructions: u64,
+    pub max_instructions: u64,
+    pub histogram: Vec<(u64, u64)>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BenchmarkReport {
+    pub record_count: u32,
+    pub operations: Vec<OperationBenchmark>,
+    pub total_instructions: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct OperationBenchmark {
+    pub operation: TrackedOperation,
+    pub instructions_per_op: u64,
+    pub cycles_per_op: u64,
+    pub ops_per_second: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IndexSuggestion {
+    pub field: String,
+    pub filter_count: u64,
+    pub sort_count: u64,
+    pub full_scan_count: u64,
+    pub estimated_cycles_saved: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReplicaStatus {
+    pub primary: Option<Principal>,
+    pub synced: bool,
+    pub records_backfilled: u64,
+    pub changes_applied: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub op: ChangeOperation,
+    pub record_id: String,
+    pub record: Option<String>,
+    pub timestamp: u64,
+    pub version: u64,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DeadLetter {
+    pub id: String,
+    pub callback: Principal,
+    pub method: String,
+    pub event: ChangeEvent,
+    pub reason: String,
+    pub attempts: u32,
+    pub failed_at: u64,
+    pub next_retry_at: Option<u64>,
+}
+
+pub fn field(field_type: FieldType) -> FieldDefinition {
+    FieldDefinition {
+        field_type,
+        required: false,
+        default_value: None,
+        validation_rules: Vec::new(),
+        coerce: None,
+        auto_timestamp: None,
+    }
+}
+
+pub fn required(field_type: FieldType) -> FieldDefinition {
+    FieldDefinition { required: true, ..field(field_type) }
+}
+
+pub fn index(name: &str, fields: &[&str]) -> IndexDefinition {
+    IndexDefinition {
+        name: name.to_string(),
+        fields: fields.iter().map(|field| field.to_string()).collect(),
+        unique: false,
+        lazy: None,
+    }
+}
+
+pub fn schema(fields: Vec<(&str, FieldDefinition)>, indexes: Vec<IndexDefinition>) -> SchemaDefinition {
+    SchemaDefinition {
+        version: 1,
+        name: "items".to_string(),
+        fields: fields.into_iter().map(|(name, field)| (name.to_string(), field)).collect(),
+        indexes,
+        constraints: Vec::new(),
+    }
+}
+
+/// Items with a required `name`, a `category`, a numeric `score` and text `tags`
+pub fn item_schema(indexes: Vec<IndexDefinition>) -> SchemaDefinition {
+    schema(vec![
+        ("name", required(FieldType::Text)),
+        ("category", field(FieldType::Text)),
+        ("score", field(FieldType::Number)),
+        ("tags", field(FieldType::Array(Box::new(FieldType::Text)))),
+    ], indexes)
+}
+
+/// Anyone may read, any authenticated caller may write, the controller administers
+pub fn config(schema: SchemaDefinition) -> CellInitConfig {
+    CellInitConfig {
+        name: "test_cell".to_string(),
+        schema,
+        permissions: PermissionConfig {
+            read: vec![AccessLevel::Public],
+            write: vec![AccessLevel::Authenticated],
+            admin: vec![controller()],
+        },
+        default_ttl_seconds: None,
+        write_rate_limit_per_minute: None,
+        fail_fast_validation: None,
+        custom_validator: None,
+        custom_validator_fail_open: None,
+        coerce_types: None,
+        id_strategy: None,
+        compress_records: None,
+        replica_of: None,
+        allow_anonymous_writes: None,
+        role_authority: None,
+        benchmark_enabled: None,
+    }
+}
+
+pub fn condition(field: &str, operator: ComparisonOperator, value: Value) -> FilterCondition {
+    FilterCondition {
+        field: field.to_string(),
+        operator,
+        value: value.to_string(),
+        case_insensitive: false,
+    }
+}
+
+pub fn filter(conditions: Vec<FilterCondition>) -> QueryFilter {
+    QueryFilter { conditions, ..QueryFilter::default() }
+}
+
+pub fn page(limit: u64) -> Pagination {
+    Pagination { offset: 0, limit, cursor: None }
+}
+
+pub fn parse(record: &str) -> Value {
+    serde_json::from_str(record).expect("record is not JSON")
+}
+
+/// A data cell installed in a PocketIC instance, possibly shared with other cells
+pub struct Cell {
+    pub pic: Rc<PocketIc>,
+    pub id: Principal,
+}
+
+impl Cell {
+    pub fn new(config: CellInitConfig) -> Self {
+        let pic = Rc::new(PocketIc::new());
+        let id = Self::install_in(&pic, config);
+        Self { pic, id }
+    }
+
+    /// Install another cell into the same PocketIC instance
+    pub fn sibling(&self, config: CellInitConfig) -> Cell {
+        let id = Self::install_in(&self.pic, config);
+        Cell { pic: self.pic.clone(), id }
+    }
+
+    /// Let timers and spawned calls run
+    pub fn settle(&self) {
+        for _ in 0..10 {
+            self.pic.tick();
+        }
+    }
+
+    /// Install another cell into an existing PocketIC instance
+    pub fn install_in(pic: &PocketIc, config: CellInitConfig) -> Principal {
+        let id = pic.create_canister_with_settings(Some(controller()), None);
+        pic.add_cycles(id, CYCLES);
+        pic.install_canister(id, cell_wasm(), candid::encode_one(config).unwrap(), Some(controller()));
+        id
+    }
+
+    /// Reinstall the same wasm through the upgrade hooks
+    pub fn upgrade(&self) {
+        self.pic.upgrade_canister(self.id, cell_wasm(), candid::encode_args(()).unwrap(), Some(controller()))
+            .expect("upgrade failed");
+    }
+
+    /// Current PocketIC time in nanoseconds since the epoch
+    pub fn now(&self) -> u64 {
+        self.pic.get_time()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("PocketIC time is before the epoch")
+            .as_nanos() as u64
+    }
+
+    /// Move the clock forward and let due timers run
+    pub fn advance_secs(&self, secs: u64) {
+        self.pic.advance_time(std::time::Duration::from_secs(secs));
+        self.settle();
+    }
+
+    pub fn health(&self) -> CellHealth {
+        let (health,): (CellHealth,) = self.query(user(), "health", ());
+        health
+    }
+
+    pub fn update<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        update_candid_as(&self.pic, self.id, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    pub fn query<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        query_candid_as(&self.pic, self.id, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    pub fn try_insert(&self, record: Value) -> Result<String, CellError> {
+        let (result,): (Result<String, CellError>,) = self.update(
+            user(), "insert", (record.to_string(), None::<u64>, None::<Precondition>, None::<String>),
+        );
+        result
+    }
+
+    pub fn insert_expiring(&self, record: Value, expires_at: u64) -> String {
+        let (result,): (Result<String, CellError>,) = self.update(
+            user(), "insert", (record.to_string(), Some(expires_at), None::<Precondition>, None::<String>),
+        );
+        result.expect("insert failed")
+    }
+
+    pub fn insert(&self, record: Value) -> String {
+        self.try_insert(record).expect("insert failed")
+    }
+
+    pub fn get(&self, record_id: &str) -> Option<Value> {
+        let (record,): (Option<String>,) = self.query(user(), "get", (record_id.to_string(),));
+        record.as_deref().map(parse)
+    }
+
+    /// `(entries, distinct_values)` of an index
+    pub fn index_stat(&self, name: &str) -> (u64, u64) {
+        let (stats,): (Vec<IndexStat>,) = self.query(user(), "index_stats", ());
+        let stat = stats.into_iter().find(|stat| stat.name == name)
+            .unwrap_or_else(|| panic!("no stats for index {}", name));
+        (stat.entries, stat.distinct_values)
+    }
+
+    /// Validate a record without storing it, as `user`
+    pub fn validate(&self, record: Value) -> Result<(), Vec<ValidationError>> {
+        let (result,): (Result<(), Vec<ValidationError>>,) = self.query(user(), "validate", (record.to_string(),));
+        result
+    }
+
+    pub fn estimate(&self, filter: QueryFilter) -> QueryCostEstimate {
+        let (estimate,): (QueryCostEstimate,) = self.query(user(), "estimate_query", (filter, page(100)));
+        estimate
+    }
+
+    pub fn run_query(&self, filter: QueryFilter, pagination: Pagination) -> Result<QueryResult, CellError> {
+        let (result,): (Result<QueryResult, CellError>,) = self.query(user(), "query", (filter, pagination));
+        result
+    }
+
+    /// Values of `field` in the records matching `filter`, in result order
+    pub fn query_field(&self, filter: QueryFilter, field: &str) -> Vec<Value> {
+        self.run_query(filter, page(1_000))
+            .expect("query failed")
+            .records
+            .iter()
+            .map(|record| parse(record).get(field).cloned().unwrap_or(Value::Null))
+            .collect()
+    }
+
+    /// Names of the records matching `filter`, sorted
+    pub fn names(&self, filter: QueryFilter) -> Vec<String> {
+        let mut names: Vec<String> = self.query_field(filter, "name")
+            .into_iter()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Restore records under the given IDs as the controller, in chunks of at most 1,000
+    pub fn import(&self, records: Vec<(String, Value)>, validate: bool) -> ImportReport {
+        let mut total = ImportReport { imported: 0, rejected: 0, errors: Vec::new() };
+        for chunk in records.chunks(1_000) {
+            let chunk: Vec<(String, String)> = chunk.iter()
+                .map(|(record_id, record)| (record_id.clone(), record.to_string()))
+                .collect();
+            let (report,): (Result<ImportReport, CellError>,) =
+                self.update(controller(), "import_chunk", (chunk, validate, None::<Vec<(String, u64)>>));
+            let report = report.expect("import_chunk failed");
+            total.imported += report.imported;
+            total.rejected += report.rejected;
+            total.errors.extend(report.errors);
+        }
+        total
+    }
+
+    /// Restore an `export_chunk` page as the controller, expiry times included
+    pub fn import_export(&self, chunk: &ExportChunk, validate: bool) -> ImportReport {
+        let (report,): (Result<ImportReport, CellError>,) = self.update(
+            controller(), "import_chunk", (chunk.records.clone(), validate, Some(chunk.expirations.clone())),
+        );
+        report.expect("import_chunk failed")
+    }
+
+    pub fn operation_metrics(&self, operation: TrackedOperation) -> OperationMetrics {
+        let (metrics,): (Vec<OperationMetrics>,) = self.query(controller(), "get_detailed_metrics", ());
+        metrics.into_iter()
+            .find(|metrics| metrics.operation == operation)
+            .expect("operation is not tracked")
+    }
+
+    /// Insert each record, returning their IDs in order
+    pub fn insert_items(&self, items: Vec<Value>) -> Vec<String> {
+        items.into_iter().map(|item| self.insert(item)).collect()
+    }
+}
+
+pub fn item(name: &str, category: &str, score: i64) -> Value {
+    json!({ "name": name, "category": category, "score": score })
+}
+
+/// Mock subscriber: `on_change` counts events and keeps the latest one,
+/// `fail` traps on every delivery
+const SUBSCRIBER_WAT: &str = r#"
+(module
+  (import "ic0" "msg_arg_data_size" (func $arg_size (result i32)))
+  (import "ic0" "msg_arg_data_copy" (func $arg_copy (param i32 i32 i32)))
+  (import "ic0" "msg_reply_data_append" (func $reply_append (param i32 i32)))
+  (import "ic0" "msg_reply" (func $reply))
+  (memory 1)
+  ;; Candid header for a single nat64 followed by the event count
+  (data (i32.const 0) "DIDL\00\01\78")
+  ;; Empty Candid reply
+  (data (i32.const 32) "DIDL\00\00")
+  (func $on_change
+    (i64.store (i32.const 7) (i64.add (i64.load (i32.const 7)) (i64.const 1)))
+    (i32.store (i32.const 16) (call $arg_size))
+    (call $arg_copy (i32.const 64) (i32.const 0) (call $arg_size))
+    (call $reply_append (i32.const 32) (i32.const 6))
+    (call $reply))
+  (func $fail
+    unreachable)
+  (func $event_count
+    (call $reply_append (i32.const 0) (i32.const 15))
+    (call $reply))
+  ;; The latest event's argument bytes are a valid `(ChangeEvent)` reply
+  (func $last_event
+    (call $reply_append (i32.const 64) (i32.load (i32.const 16)))
+    (call $reply))
+  (export "canister_update on_change" (func $on_change))
+  (export "canister_update fail" (func $fail))
+  (export "canister_query event_count" (func $event_count))
+  (export "canister_query last_event" (func $last_event))
+)
+"#;
+
+/// A mock subscriber canister installed next to a cell
+pub struct Subscriber {
+    pub pic: Rc<PocketIc>,
+    pub id: Principal,
+}
+
+impl Subscriber {
+    pub fn install(cell: &Cell) -> Self {
+        let id = cell.pic.create_canister_with_settings(Some(controller()), None);
+        cell.pic.add_cycles(id, CYCLES);
+        let wasm = wat::parse_str(SUBSCRIBER_WAT).expect("mock subscriber is not valid WAT");
+        cell.pic.install_canister(id, wasm, vec![], Some(controller()));
+        Self { pic: cell.pic.clone(), id }
+    }
+
+    pub fn event_count(&self) -> u64 {
+        let (count,): (u64,) = query_candid_as(&self.pic, self.id, user(), "event_count", ())
+            .expect("event_count failed");
+        count
+    }
+
+    pub fn last_event(&self) -> ChangeEvent {
+        let (event,): (ChangeEvent,) = query_candid_as(&self.pic, self.id, user(), "last_event", ())
+            .expect("no event delivered yet");
+        event
+    }
+}
+
+/// Mock custom validator: `validate_custom` replies with the verdict last
+/// given to `set_verdict` and counts its calls
+const VALIDATOR_WAT: &str = r#"
+(module
+  (import "ic0" "msg_arg_data_size" (func $arg_size (result i32)))
+  (import "ic0" "msg_arg_data_copy" (func $arg_copy (param i32 i32 i32)))
+  (import "ic0" "msg_reply_data_append" (func $reply_append (param i32 i32)))
+  (import "ic0" "msg_reply" (func $reply))
+  (memory 1)
+  ;; Candid header for a single nat64 followed by the call count
+  (data (i32.const 0) "DIDL\00\01\78")
+  ;; Empty Candid reply
+  (data (i32.const 32) "DIDL\00\00")
+  ;; The stored argument bytes are a valid `(CustomValidationVerdict)` reply
+  (func $set_verdict
+    (i32.store (i32.const 16) (call $arg_size))
+    (call $arg_copy (i32.const 64) (i32.const 0) (call $arg_size))
+    (call $reply_append (i32.const 32) (i32.const 6))
+    (call $reply))
+  (func $validate_custom
+    (i64.store (i32.const 7) (i64.add (i64.load (i32.const 7)) (i64.const 1)))
+    (call $reply_append (i32.const 64) (i32.load (i32.const 16)))
+    (call $reply))
+  (func $call_count
+    (call $reply_append (i32.const 0) (i32.const 15))
+    (call $reply))
+  (export "canister_update set_verdict" (func $set_verdict))
+  (export "canister_update validate_custom" (func $validate_custom))
+  (export "canister_query call_count" (func $call_count))
+)
+"#;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CustomValidationVerdict {
+    pub valid: bool,
+    pub message: Option<String>,
+}
+
+/// A mock validator canister for `Custom` rules
+pub struct MockValidator {
+    pub pic: Rc<PocketIc>,
+    pub id: Principal,
+}
+
+impl MockValidator {
+    /// Install a validator that approves everything until told otherwise
+    pub fn install(pic: &Rc<PocketIc>) -> Self {
+        let id = pic.create_canister_with_settings(Some(controller()), None);
+        pic.add_cycles(id, CYCLES);
+        let wasm = wat::parse_str(VALIDATOR_WAT).expect("mock validator is not valid WAT");
+        pic.install_canister(id, wasm, vec![], Some(controller()));
+        let validator = Self { pic: pic.clone(), id };
+        validator.set_verdict(true, None);
+        validator
+    }
+
+    pub fn set_verdict(&self, valid: bool, message: Option<&str>) {
+        let verdict = CustomValidationVerdict { valid, message: message.map(str::to_string) };
+        let () = update_candid_as(&self.pic, self.id, controller(), "set_verdict", (verdict,))
+            .expect("set_verdict failed");
+    }
+
+    pub fn call_count(&self) -> u64 {
+        let (count,): (u64,) = query_candid_as(&self.pic, self.id, user(), "call_count", ())
+            .expect("call_count failed");
+        count
+    }
+}
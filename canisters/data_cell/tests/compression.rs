@@ -0,0 +1,42 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn metrics(cell: &Cell) -> CellMetrics {
+    let (metrics,): (CellMetrics,) = cell.query(user(), "get_metrics", ());
+    metrics
+}
+
+fn verbose_item(name: &str) -> serde_json::Value {
+    json!({"name": name, "category": "repetitive ".repeat(40), "score": 1})
+}
+
+#[test]
+fn compressed_records_round_trip_and_report_their_ratio() {
+    let cell = Cell::new(CellInitConfig { compress_records: Some(true), ..config(item_schema(vec![])) });
+    let id = cell.insert(verbose_item("alpha"));
+    let small = cell.insert(item("beta", "a", 2));
+
+    assert_eq!(cell.get(&id).unwrap(), verbose_item("alpha"));
+    assert_eq!(cell.get(&small).unwrap(), item("beta", "a", 2));
+    assert_eq!(
+        cell.query_field(filter(vec![condition("name", ComparisonOperator::Equals, json!("alpha"))]), "score"),
+        vec![json!(1)],
+    );
+
+    let metrics = metrics(&cell);
+    assert!(metrics.stored_bytes < metrics.uncompressed_bytes);
+    assert!(metrics.compression_ratio > 1.0);
+}
+
+#[test]
+fn uncompressed_cells_store_plain_json() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let id = cell.insert(verbose_item("alpha"));
+
+    assert_eq!(cell.get(&id).unwrap(), verbose_item("alpha"));
+    let metrics = metrics(&cell);
+    assert_eq!(metrics.stored_bytes, metrics.uncompressed_bytes);
+    assert_eq!(metrics.compression_ratio, 1.0);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use pocket_ic::WasmResult;
+use serde_json::json;
+
+fn counter_cell() -> Cell {
+    let mut stock = field(FieldType::Number);
+    stock.validation_rules.push(ValidationRule::Range(0, 100));
+    Cell::new(config(schema(vec![
+        ("name", required(FieldType::Text)),
+        ("likes", field(FieldType::Number)),
+        ("stock", stock),
+    ], vec![])))
+}
+
+fn increment(cell: &Cell, record_id: &str, field: &str, delta: i64) -> Result<i64, CellError> {
+    let (result,): (Result<i64, CellError>,) =
+        cell.update(user(), "increment_field", (record_id.to_string(), field.to_string(), delta));
+    result
+}
+
+#[test]
+fn concurrent_increments_are_all_applied() {
+    let cell = counter_cell();
+    let record_id = cell.insert(json!({"name": "post"}));
+
+    // Submitted together, so every call reads the counter within the same rounds
+    let messages: Vec<_> = (1..=20)
+        .map(|delta: i64| cell.pic.submit_call(
+            cell.id, user(), "increment_field",
+            candid::encode_args((record_id.clone(), "likes".to_string(), delta)).unwrap(),
+        ).expect("submit failed"))
+        .collect();
+
+    let mut returned: Vec<i64> = messages.into_iter()
+        .map(|message| match cell.pic.await_call(message).expect("increment failed") {
+            WasmResult::Reply(bytes) => candid::decode_one::<Result<i64, CellError>>(&bytes).unwrap().unwrap(),
+            WasmResult::Reject(message) => panic!("increment rejected: {}", message),
+        })
+        .collect();
+
+    assert_eq!(cell.get(&record_id).unwrap()["likes"], json!(210));
+    // Each call saw the value left by the one before it
+    returned.sort();
+    assert_eq!(returned.len(), 20);
+    assert_eq!(returned.last(), Some(&210));
+    assert!(returned.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn increments_outside_the_range_are_rejected() {
+    let cell = counter_cell();
+    let record_id = cell.insert(json!({"name": "widget", "stock": 95}));
+
+    assert_eq!(increment(&cell, &record_id, "stock", 5), Ok(100));
+    assert!(matches!(increment(&cell, &record_id, "stock", 1), Err(CellError::ValidationError(_))));
+    assert_eq!(increment(&cell, &record_id, "stock", -100), Ok(0));
+    assert!(matches!(increment(&cell, &record_id, "stock", -1), Err(CellError::ValidationError(_))));
+    assert_eq!(cell.get(&record_id).unwrap()["stock"], json!(0));
+}
+
+#[test]
+fn increments_need_an_integer_field_on_an_existing_record() {
+    let cell = counter_cell();
+    let record_id = cell.insert(json!({"name": "post"}));
+
+    assert_eq!(increment(&cell, &record_id, "likes", -3), Ok(-3));
+    assert!(matches!(increment(&cell, &record_id, "name", 1), Err(CellError::ValidationError(_))));
+    assert!(matches!(increment(&cell, "missing", "likes", 1), Err(CellError::NotFound(_))));
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (record_id.clone(), json!({"likes": 1.5}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    assert!(matches!(increment(&cell, &record_id, "likes", 1), Err(CellError::ValidationError(_))));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const ITEMS_CSV: &str = "id,name,category,score\r\n\
+a1,\"Smith, Jane\",\"said \"\"hi\"\"\",42\r\n\
+a2,\"multi\nline\",b,7.5\r\n\
+a3,,c,1\r\n\
+a4,plain,d,lots\r\n";
+
+fn mapping(columns: &[&str], id_column: Option<&str>, has_header: bool) -> ColumnMapping {
+    ColumnMapping {
+        fields: columns.iter()
+            .map(|column| (column.to_string(), column.to_string()))
+            .collect(),
+        id_column: id_column.map(str::to_string),
+        has_header,
+    }
+}
+
+fn import_csv(cell: &Cell, csv: &str, mapping: ColumnMapping) -> ImportReport {
+    let (result,): (Result<ImportReport, CellError>,) = cell.update(user(), "import_csv", (csv.to_string(), mapping));
+    result.expect("import_csv failed")
+}
+
+fn export_csv(cell: &Cell) -> String {
+    let (result,): (Result<String, CellError>,) = cell.query(user(), "export_csv", (filter(vec![]), page(100)));
+    result.expect("export_csv failed")
+}
+
+#[test]
+fn quoted_fields_and_numbers_are_imported() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let report = import_csv(&cell, ITEMS_CSV, mapping(&["name", "category", "score"], Some("id"), true));
+    assert_eq!((report.imported, report.rejected), (2, 2));
+    let rejected: Vec<&str> = report.errors.iter().map(|(row, _)| row.as_str()).collect();
+    assert_eq!(rejected, ["row 3", "row 4"]);
+
+    assert_eq!(cell.get("a1").unwrap(), json!({"name": "Smith, Jane", "category": "said \"hi\"", "score": 42}));
+    assert_eq!(cell.get("a2").unwrap(), json!({"name": "multi\nline", "category": "b", "score": 7.5}));
+    assert!(cell.get("a3").is_none());
+}
+
+#[test]
+fn exported_csv_round_trips_through_import() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    import_csv(&cell, ITEMS_CSV, mapping(&["name", "category", "score"], Some("id"), true));
+
+    let csv = export_csv(&cell);
+    assert_eq!(csv, "category,name,score,tags\r\n\
+\"said \"\"hi\"\"\",\"Smith, Jane\",42,\r\n\
+b,\"multi\nline\",7.5,\r\n");
+
+    let copy = Cell::new(config(item_schema(vec![])));
+    let report = import_csv(&copy, &csv, mapping(&["name", "category", "score"], None, true));
+    assert_eq!((report.imported, report.rejected), (2, 0));
+    assert_eq!(export_csv(&copy), csv);
+}
+
+#[test]
+fn headerless_csv_maps_columns_by_position() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let mapping = ColumnMapping {
+        fields: vec![("0".to_string(), "name".to_string()), ("2".to_string(), "score".to_string())],
+        id_column: Some("1".to_string()),
+        has_header: false,
+    };
+
+    let report = import_csv(&cell, "alpha,x1,3\nbeta,x2,-4\n", mapping);
+    assert_eq!(report.imported, 2);
+    assert_eq!(cell.get("x2").unwrap(), json!({"name": "beta", "score": -4}));
+}
+
+#[test]
+fn malformed_csv_and_unknown_columns_are_rejected() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let (result,): (Result<ImportReport, CellError>,) =
+        cell.update(user(), "import_csv", ("name\n\"open".to_string(), mapping(&["name"], None, true)));
+    assert!(matches!(result, Err(CellError::ValidationError(_))));
+
+    let (result,): (Result<ImportReport, CellError>,) =
+        cell.update(user(), "import_csv", ("name\nalpha".to_string(), mapping(&["title"], None, true)));
+    assert!(matches!(result, Err(CellError::ValidationError(_))));
+    assert_eq!(cell.health().record_count, 0);
+}
+
+#[test]
+fn id_column_follows_the_record_id_strategy() {
+    let keyed = Cell::new(CellInitConfig {
+        id_strategy: Some(IdStrategy::FromField("name".to_string())),
+        ..config(item_schema(vec![]))
+    });
+    let report = import_csv(&keyed, "id,name\nalpha,alpha\nother,beta\n", mapping(&["name"], Some("id"), true));
+    assert_eq!((report.imported, report.rejected), (1, 1));
+    assert_eq!(report.errors[0].0, "row 2");
+    assert!(keyed.get("alpha").is_some());
+    assert!(keyed.try_insert(json!({"name": "alpha"})).is_err());
+
+    let sequenced = Cell::new(CellInitConfig { id_strategy: Some(IdStrategy::Monotonic), ..config(item_schema(vec![])) });
+    let report = import_csv(&sequenced, "id,name\n00000000000000000007,seven\n", mapping(&["name"], Some("id"), true));
+    assert_eq!(report.imported, 1);
+    assert_eq!(sequenced.insert(json!({"name": "next"})), "00000000000000000008");
+
+    // An ID already in use is rejected like any other collision
+    let report = import_csv(&sequenced, "id,name\n00000000000000000008,again\n", mapping(&["name"], Some("id"), true));
+    assert_eq!(report.rejected, 1);
+
+    // As is one that would use up the sequence
+    let report = import_csv(&sequenced, &format!("id,name\n{},last\n", u64::MAX), mapping(&["name"], Some("id"), true));
+    assert_eq!(report.rejected, 1);
+    assert_eq!(sequenced.insert(json!({"name": "after"})), "00000000000000000009");
+}
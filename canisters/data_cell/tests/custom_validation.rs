@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use pocket_ic::PocketIc;
+use serde_json::json;
+use std::rc::Rc;
+
+/// Items whose `comment` must pass the validator's `clean` rule
+fn checked_cell(configure: impl FnOnce(&mut CellInitConfig)) -> (Cell, MockValidator) {
+    let pic = Rc::new(PocketIc::new());
+    let validator = MockValidator::install(&pic);
+
+    let mut config = config(schema(vec![
+        ("name", required(FieldType::Text)),
+        ("comment", FieldDefinition {
+            validation_rules: vec![ValidationRule::Custom("clean".to_string())],
+            ..field(FieldType::Text)
+        }),
+    ], vec![]));
+    config.custom_validator = Some(validator.id);
+    configure(&mut config);
+
+    let id = Cell::install_in(&pic, config);
+    (Cell { pic, id }, validator)
+}
+
+#[test]
+fn validator_approves_and_denies_records() {
+    let (cell, validator) = checked_cell(|_| {});
+
+    cell.insert(json!({"name": "a", "comment": "lovely"}));
+
+    validator.set_verdict(false, Some("contains a banned word"));
+    let error = cell.try_insert(json!({"name": "b", "comment": "dreadful"})).unwrap_err();
+    assert!(matches!(
+        &error,
+        CellError::ValidationError(message)
+            if message.contains("comment: rejected by custom rule 'clean': contains a banned word")
+    ), "{:?}", error);
+
+    // Records without the field need no custom check
+    cell.insert(json!({"name": "c"}));
+    assert_eq!(validator.call_count(), 2);
+}
+
+#[test]
+fn verdicts_are_cached_briefly() {
+    let (cell, validator) = checked_cell(|_| {});
+
+    cell.insert(json!({"name": "a", "comment": "same"}));
+    cell.insert(json!({"name": "b", "comment": "same"}));
+    assert_eq!(validator.call_count(), 1);
+
+    cell.insert(json!({"name": "c", "comment": "different"}));
+    assert_eq!(validator.call_count(), 2);
+
+    cell.pic.advance_time(std::time::Duration::from_secs(31));
+    cell.insert(json!({"name": "d", "comment": "same"}));
+    assert_eq!(validator.call_count(), 3);
+}
+
+#[test]
+fn unreachable_validator_fails_closed_unless_configured_open() {
+    let (closed, validator) = checked_cell(|_| {});
+    closed.pic.stop_canister(validator.id, Some(controller())).expect("stop failed");
+
+    let error = closed.try_insert(json!({"name": "a", "comment": "lovely"})).unwrap_err();
+    assert!(matches!(&error, CellError::ValidationError(message) if message.contains("could not be checked")),
+            "{:?}", error);
+
+    let (open, validator) = checked_cell(|config| config.custom_validator_fail_open = Some(true));
+    open.pic.stop_canister(validator.id, Some(controller())).expect("stop failed");
+    open.insert(json!({"name": "a", "comment": "lovely"}));
+}
+
+#[test]
+fn field_mutations_run_custom_rules() {
+    let (cell, validator) = checked_cell(|config| {
+        config.schema.fields.push(("visits".to_string(), field(FieldType::Number)));
+    });
+    let record_id = cell.insert(json!({"name": "a", "comment": "lovely"}));
+
+    // Past the verdict cache, so the validator is asked again
+    cell.pic.advance_time(std::time::Duration::from_secs(31));
+    validator.set_verdict(false, Some("no longer allowed"));
+    let (result,): (Result<i64, CellError>,) =
+        cell.update(user(), "increment_field", (record_id.clone(), "visits".to_string(), 1i64));
+    assert!(matches!(
+        &result,
+        Err(CellError::ValidationError(message)) if message.contains("rejected by custom rule 'clean'")
+    ), "{:?}", result);
+    assert_eq!(cell.get(&record_id).unwrap().get("visits"), None);
+    assert_eq!(validator.call_count(), 2);
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// 40 items: 30 in category "a", 6 in "b" and 4 in "c", scored by position
+fn skewed_cell() -> Cell {
+    let cell = Cell::new(config(item_schema(vec![
+        index("by_category", &["category"]),
+        index("by_name", &["name"]),
+        index("by_score", &["score"]),
+    ])));
+
+    let records = (0..40)
+        .map(|i| {
+            let category = match i {
+                0..=29 => "a",
+                30..=35 => "b",
+                _ => "c",
+            };
+            (format!("item_{:02}", i), item(&format!("item_{:02}", i), category, i))
+        })
+        .collect();
+    cell.import(records, true);
+    cell
+}
+
+fn actual_matches(cell: &Cell, filter: QueryFilter) -> u64 {
+    cell.run_query(filter, page(1_000)).expect("query failed").total_count
+}
+
+#[test]
+fn equality_estimates_match_actual_scans() {
+    let cell = skewed_cell();
+
+    for (category, expected) in [("a", 30), ("b", 6), ("c", 4), ("missing", 0)] {
+        let filter = filter(vec![condition("category", ComparisonOperator::Equals, json!(category))]);
+        let estimate = cell.estimate(filter.clone());
+        assert_eq!(estimate.index_used.as_deref(), Some("by_category"));
+        assert_eq!(estimate.estimated_records_scanned, expected);
+        assert_eq!(actual_matches(&cell, filter), expected);
+    }
+}
+
+#[test]
+fn prefix_and_range_estimates_bound_actual_scans() {
+    let cell = skewed_cell();
+
+    let prefix = filter(vec![condition("name", ComparisonOperator::StartsWith, json!("item_1"))]);
+    let estimate = cell.estimate(prefix.clone());
+    assert_eq!(estimate.index_used.as_deref(), Some("name"));
+    assert_eq!(estimate.estimated_records_scanned, 10);
+    assert_eq!(actual_matches(&cell, prefix), 10);
+
+    // Range bounds are scanned inclusively, so the boundary value is counted too
+    let range = filter(vec![condition("score", ComparisonOperator::GreaterThan, json!(9))]);
+    let estimate = cell.estimate(range.clone());
+    assert_eq!(estimate.index_used.as_deref(), Some("score"));
+    assert_eq!(actual_matches(&cell, range), 30);
+    assert_eq!(estimate.estimated_records_scanned, 31);
+}
+
+#[test]
+fn unindexed_filters_estimate_a_full_scan() {
+    let cell = skewed_cell();
+
+    let contains = filter(vec![condition("name", ComparisonOperator::Contains, json!("_3"))]);
+    let estimate = cell.estimate(contains.clone());
+    assert_eq!(estimate.index_used, None);
+    assert_eq!(estimate.estimated_records_scanned, 40);
+    assert_eq!(actual_matches(&cell, contains), 10);
+
+    let indexed = cell.estimate(filter(vec![condition("category", ComparisonOperator::Equals, json!("c"))]));
+    assert!(indexed.estimated_cycles < estimate.estimated_cycles);
+}
+
+#[test]
+fn estimated_returns_respect_pagination() {
+    let cell = skewed_cell();
+    let filter = filter(vec![condition("category", ComparisonOperator::Equals, json!("a"))]);
+
+    let (estimate,): (QueryCostEstimate,) = cell.query(
+        user(), "estimate_query", (filter.clone(), Pagination { offset: 25, ..page(10) }),
+    );
+    assert_eq!(estimate.estimated_records_scanned, 30);
+    assert_eq!(estimate.estimated_records_returned, 5);
+
+    let (estimate,): (QueryCostEstimate,) = cell.query(user(), "estimate_query", (filter, page(10)));
+    assert_eq!(estimate.estimated_records_returned, 10);
+}
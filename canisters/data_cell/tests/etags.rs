@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn with_etag(cell: &Cell, record_id: &str) -> Option<RecordWithEtag> {
+    let (record,): (Option<RecordWithEtag>,) = cell.query(user(), "get_with_etag", (record_id.to_string(),));
+    record
+}
+
+fn etags(cell: &Cell, record_ids: &[&str]) -> Vec<Option<String>> {
+    let record_ids: Vec<String> = record_ids.iter().map(|id| id.to_string()).collect();
+    let (etags,): (Vec<Option<String>>,) = cell.query(user(), "record_etags", (record_ids,));
+    etags
+}
+
+#[test]
+fn etags_are_stable_across_reads_and_change_on_update() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let record_id = cell.insert(item("alpha", "a", 1));
+
+    let first = with_etag(&cell, &record_id).unwrap();
+    assert_eq!(parse(&first.record)["name"], json!("alpha"));
+    assert_eq!(with_etag(&cell, &record_id).unwrap().etag, first.etag);
+    assert_eq!(etags(&cell, &[&record_id]), [Some(first.etag.clone())]);
+
+    cell.upgrade();
+    assert_eq!(with_etag(&cell, &record_id).unwrap().etag, first.etag);
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (record_id.clone(), json!({"score": 2}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    let updated = with_etag(&cell, &record_id).unwrap();
+    assert_ne!(updated.etag, first.etag);
+    assert_eq!(parse(&updated.record)["score"], json!(2));
+}
+
+#[test]
+fn records_with_equal_content_share_an_etag() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let first = cell.insert(item("alpha", "a", 1));
+    let second = cell.insert(item("alpha", "a", 1));
+    let other = cell.insert(item("beta", "a", 1));
+
+    let tags = etags(&cell, &[&first, &second, &other, "missing"]);
+    assert_eq!(tags[0], tags[1]);
+    assert_ne!(tags[0], tags[2]);
+    assert_eq!(tags[3], None);
+}
+
+#[test]
+fn deleted_records_have_no_etag() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let record_id = cell.insert(item("alpha", "a", 1));
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (record_id.clone(),));
+    result.unwrap();
+    assert!(with_etag(&cell, &record_id).is_none());
+    assert_eq!(etags(&cell, &[&record_id]), [None]);
+}
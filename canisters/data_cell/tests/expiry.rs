@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const SECOND: u64 = 1_000_000_000;
+
+/// Longer than the cell's expiry sweep interval
+const SWEEP_SECS: u64 = 61;
+
+#[test]
+fn expired_records_leave_queries_at_once_and_storage_at_the_next_sweep() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    let expiring = cell.insert_expiring(item("session", "tools", 1), cell.now() + 5 * SECOND);
+    let kept = cell.insert(item("profile", "tools", 2));
+
+    let tools = || filter(vec![condition("category", ComparisonOperator::Equals, json!("tools"))]);
+    assert_eq!(cell.names(tools()), ["profile", "session"]);
+
+    // Past the expiry but before any sweep, reads already skip the record
+    cell.pic.advance_time(std::time::Duration::from_secs(10));
+    assert_eq!(cell.names(tools()), ["profile"]);
+    assert_eq!(cell.names(filter(vec![])), ["profile"]);
+    assert_eq!(cell.get(&expiring), None);
+    assert!(cell.get(&kept).is_some());
+    assert_eq!(cell.health().record_count, 2);
+
+    cell.advance_secs(SWEEP_SECS);
+    assert_eq!(cell.health().record_count, 1);
+    assert_eq!(cell.estimate(tools()).estimated_records_scanned, 1);
+}
+
+#[test]
+fn default_ttl_applies_to_records_inserted_without_an_expiry() {
+    let mut ttl_config = config(item_schema(vec![]));
+    ttl_config.default_ttl_seconds = Some(30);
+    let cell = Cell::new(ttl_config);
+    let record_id = cell.insert(item("cached", "tools", 1));
+
+    cell.pic.advance_time(std::time::Duration::from_secs(20));
+    assert!(cell.get(&record_id).is_some());
+
+    cell.advance_secs(SWEEP_SECS);
+    assert_eq!(cell.get(&record_id), None);
+    assert_eq!(cell.health().record_count, 0);
+}
+
+#[test]
+fn writing_over_an_unswept_expired_record_does_not_inherit_its_expiry() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let record_id = cell.insert_expiring(item("old", "tools", 1), cell.now() + 5 * SECOND);
+    cell.pic.advance_time(std::time::Duration::from_secs(10));
+
+    let report = cell.import(vec![(record_id.clone(), item("new", "tools", 2))], true);
+    assert_eq!(report.imported, 1);
+    assert_eq!(cell.get(&record_id).unwrap()["name"], json!("new"));
+
+    cell.advance_secs(SWEEP_SECS);
+    assert_eq!(cell.get(&record_id).unwrap()["name"], json!("new"));
+    assert_eq!(cell.health().record_count, 1);
+}
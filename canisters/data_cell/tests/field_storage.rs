@@ -0,0 +1,53 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+
+fn photo_schema() -> SchemaDefinition {
+    schema(vec![
+        ("name", required(FieldType::Text)),
+        ("photo", field(FieldType::Blob)),
+    ], vec![])
+}
+
+fn breakdown(cell: &Cell) -> Vec<(String, u64)> {
+    let (breakdown,): (Vec<(String, u64)>,) = cell.query(user(), "field_storage_breakdown", ());
+    breakdown
+}
+
+/// Bytes a field adds to the breakdown: its name plus its JSON value
+fn field_bytes(name: &str, value: &Value) -> u64 {
+    (name.len() + value.to_string().len()) as u64
+}
+
+#[test]
+fn a_large_blob_field_is_the_biggest_consumer() {
+    let cell = Cell::new(config(photo_schema()));
+    let photo = json!(vec![255u8; 1_000]);
+    for name in ["alpha", "beta"] {
+        cell.insert(json!({"name": name, "photo": photo}));
+    }
+
+    let breakdown = breakdown(&cell);
+    assert_eq!(breakdown, [
+        ("photo".to_string(), 2 * field_bytes("photo", &photo)),
+        ("name".to_string(), field_bytes("name", &json!("alpha")) + field_bytes("name", &json!("beta"))),
+    ]);
+}
+
+#[test]
+fn totals_follow_updates_and_deletes() {
+    let cell = Cell::new(config(photo_schema()));
+    let id = cell.insert(json!({"name": "alpha", "photo": vec![1u8; 100]}));
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (id.clone(), json!({"photo": [1, 2]}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    assert_eq!(breakdown(&cell)[0], ("name".to_string(), field_bytes("name", &json!("alpha"))));
+    assert_eq!(breakdown(&cell)[1], ("photo".to_string(), field_bytes("photo", &json!([1, 2]))));
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (id,));
+    result.unwrap();
+    assert!(breakdown(&cell).is_empty());
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn a_fresh_cell_reports_healthy_with_its_schema_and_record_count() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    cell.insert(item("alpha", "a", 1));
+    cell.insert(item("beta", "b", 2));
+
+    let health = cell.health();
+    assert!(matches!(health.status, HealthStatus::Healthy));
+    assert_eq!(health.schema_version, 1);
+    assert_eq!(health.record_count, 2);
+    assert!(health.capabilities.contains(&CellCapability::BatchOperations));
+}
+
+#[test]
+fn a_replica_that_cannot_reach_its_primary_reports_degraded() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    primary.pic.stop_canister(primary.id, Some(controller())).expect("stop failed");
+
+    let replica = primary.sibling(CellInitConfig { replica_of: Some(primary.id), ..config(item_schema(vec![])) });
+    replica.settle();
+
+    match replica.health().status {
+        HealthStatus::Degraded(reason) => assert!(reason.contains("subscribe"), "unexpected reason: {}", reason),
+        status => panic!("expected a degraded replica, got {:?}", status),
+    }
+}
+
+#[test]
+fn capabilities_reflect_the_features_a_cell_has_enabled() {
+    let plain = Cell::new(config(item_schema(vec![])));
+    let (capabilities,): (Vec<CellCapability>,) = plain.query(user(), "capabilities", ());
+    assert_eq!(capabilities, vec![CellCapability::StreamingSupport, CellCapability::BatchOperations]);
+
+    let indexed = plain.sibling(config(item_schema(vec![index("by_category", &["category"])])));
+    let (capabilities,): (Vec<CellCapability>,) = indexed.query(user(), "capabilities", ());
+    assert!(capabilities.contains(&CellCapability::AdvancedIndexing));
+    assert!(!capabilities.contains(&CellCapability::GeospatialQueries));
+    assert!(!capabilities.contains(&CellCapability::FullTextSearch));
+    assert_eq!(indexed.health().capabilities, capabilities);
+}
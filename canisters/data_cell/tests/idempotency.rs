@@ -0,0 +1,85 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+use serde_json::{json, Value};
+
+fn insert_with_key(cell: &Cell, sender: Principal, record: Value, key: &str) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) = cell.update(
+        sender, "insert", (record.to_string(), None::<u64>, None::<Precondition>, Some(key.to_string())),
+    );
+    result
+}
+
+fn upsert_with_key(cell: &Cell, record_id: &str, record: Value, key: &str) -> Result<UpsertResult, CellError> {
+    let (result,): (Result<UpsertResult, CellError>,) =
+        cell.update(user(), "upsert", (record_id.to_string(), record.to_string(), Some(key.to_string())));
+    result
+}
+
+#[test]
+fn a_repeated_insert_with_the_same_key_does_not_duplicate() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let first = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-1").unwrap();
+    let retried = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-1").unwrap();
+    assert_eq!(retried, first);
+    assert_eq!(cell.health().record_count, 1);
+
+    let other = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-2").unwrap();
+    assert_ne!(other, first);
+    assert_eq!(cell.health().record_count, 2);
+}
+
+#[test]
+fn keys_are_scoped_to_the_caller() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let mine = insert_with_key(&cell, user(), item("alpha", "a", 1), "shared").unwrap();
+    let theirs = insert_with_key(&cell, other_user(), item("beta", "a", 2), "shared").unwrap();
+    assert_ne!(mine, theirs);
+    assert_eq!(cell.health().record_count, 2);
+}
+
+#[test]
+fn failed_calls_release_their_key() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let invalid = insert_with_key(&cell, user(), json!({"category": "a"}), "retry-1");
+    assert!(invalid.is_err());
+    let record_id = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-1").unwrap();
+    assert_eq!(cell.get(&record_id).unwrap()["name"], json!("alpha"));
+}
+
+#[test]
+fn repeated_upserts_replay_the_first_result() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    assert_eq!(upsert_with_key(&cell, "alpha", item("alpha", "a", 1), "up-1"), Ok(UpsertResult::Inserted));
+    assert_eq!(upsert_with_key(&cell, "alpha", item("alpha", "a", 2), "up-1"), Ok(UpsertResult::Inserted));
+    assert_eq!(cell.get("alpha").unwrap()["score"], json!(1));
+
+    // A key used for an insert can't be replayed as an upsert
+    insert_with_key(&cell, user(), item("beta", "a", 1), "ins-1").unwrap();
+    assert!(matches!(upsert_with_key(&cell, "beta", item("beta", "a", 2), "ins-1"), Err(CellError::ValidationError(_))));
+}
+
+#[test]
+fn keys_expire_after_a_day() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let first = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-1").unwrap();
+
+    cell.advance_secs(24 * 60 * 60 + 61);
+    let later = insert_with_key(&cell, user(), item("alpha", "a", 1), "retry-1").unwrap();
+    assert_ne!(later, first);
+    assert_eq!(cell.health().record_count, 2);
+}
+
+#[test]
+fn oversized_keys_are_rejected() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    let result = insert_with_key(&cell, user(), item("alpha", "a", 1), &"k".repeat(129));
+    assert!(matches!(result, Err(CellError::ValidationError(_))));
+    assert!(matches!(insert_with_key(&cell, user(), item("alpha", "a", 1), ""), Err(CellError::ValidationError(_))));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn suggestions(cell: &Cell) -> Vec<IndexSuggestion> {
+    let (suggestions,): (Vec<IndexSuggestion>,) = cell.query(controller(), "suggest_indexes", ());
+    suggestions
+}
+
+fn suggested_fields(cell: &Cell) -> Vec<String> {
+    suggestions(cell).into_iter().map(|suggestion| suggestion.field).collect()
+}
+
+/// Run a query as an update call, which is what the advisor counts
+fn query_cached(cell: &Cell, filter: QueryFilter) {
+    let (result,): (Result<QueryResult, CellError>,) = cell.update(user(), "query_cached", (filter, page(10)));
+    result.expect("query_cached failed");
+}
+
+/// Filter on `field` `times` times, with a new value each time so no result is served from the cache
+fn hammer(cell: &Cell, field: &str, times: u64) {
+    for i in 0..times {
+        query_cached(cell, filter(vec![condition(field, ComparisonOperator::Equals, json!(format!("value_{}", i)))]));
+    }
+}
+
+fn stocked_cell(indexes: Vec<IndexDefinition>) -> Cell {
+    let cell = Cell::new(config(item_schema(indexes)));
+    cell.insert_items((0..20).map(|i| item(&format!("item_{}", i), "a", i)).collect());
+    cell
+}
+
+#[test]
+fn a_frequently_filtered_unindexed_field_is_suggested() {
+    let cell = stocked_cell(vec![]);
+    hammer(&cell, "category", 25);
+
+    let suggestions = suggestions(&cell);
+    assert_eq!(suggestions.len(), 1);
+    let suggestion = &suggestions[0];
+    assert_eq!(suggestion.field, "category");
+    assert_eq!(suggestion.filter_count, 25);
+    assert_eq!(suggestion.full_scan_count, 25);
+    assert!(suggestion.estimated_cycles_saved > 0);
+}
+
+#[test]
+fn rarely_used_fields_are_not_suggested() {
+    let cell = stocked_cell(vec![]);
+    hammer(&cell, "category", 9);
+
+    assert!(suggestions(&cell).is_empty());
+
+    hammer(&cell, "category", 1);
+    assert_eq!(suggested_fields(&cell), vec!["category"]);
+}
+
+#[test]
+fn indexed_fields_are_not_suggested() {
+    let cell = stocked_cell(vec![index("by_category", &["category"])]);
+    hammer(&cell, "category", 25);
+    hammer(&cell, "name", 25);
+
+    assert_eq!(suggested_fields(&cell), vec!["name"]);
+}
+
+#[test]
+fn adding_the_index_retires_the_suggestion() {
+    let cell = stocked_cell(vec![]);
+    hammer(&cell, "category", 25);
+    assert_eq!(suggested_fields(&cell), vec!["category"]);
+
+    let (added,): (Result<(), CellError>,) = cell.update(controller(), "add_index", (index("by_category", &["category"]),));
+    added.unwrap();
+
+    assert!(suggestions(&cell).is_empty());
+}
+
+#[test]
+fn sort_keys_count_towards_a_suggestion() {
+    let cell = stocked_cell(vec![]);
+    for i in 0..10 {
+        // Contains can't use an index, so only the sort key is counted
+        query_cached(&cell, QueryFilter {
+            sort_by: Some("score".to_string()),
+            ..filter(vec![condition("name", ComparisonOperator::Contains, json!(format!("item_{}", i)))])
+        });
+    }
+
+    let suggestions = suggestions(&cell);
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].field, "score");
+    assert_eq!((suggestions[0].filter_count, suggestions[0].sort_count), (0, 10));
+}
+
+#[test]
+fn fields_scanned_most_are_suggested_first() {
+    let cell = stocked_cell(vec![]);
+    hammer(&cell, "name", 12);
+    hammer(&cell, "category", 30);
+
+    assert_eq!(suggested_fields(&cell), vec!["category", "name"]);
+}
+
+#[test]
+fn usage_counts_survive_upgrades() {
+    let cell = stocked_cell(vec![]);
+    hammer(&cell, "category", 25);
+
+    cell.upgrade();
+
+    assert_eq!(suggestions(&cell)[0].filter_count, 25);
+}
+
+#[test]
+fn query_calls_are_not_counted() {
+    let cell = stocked_cell(vec![]);
+    for i in 0..25 {
+        cell.run_query(filter(vec![condition("category", ComparisonOperator::Equals, json!(format!("value_{}", i)))]), page(10)).unwrap();
+    }
+
+    assert!(suggestions(&cell).is_empty());
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn category_and_score(category: &str, score: i64) -> QueryFilter {
+    filter(vec![
+        condition("category", ComparisonOperator::Equals, json!(category)),
+        condition("score", ComparisonOperator::Equals, json!(score)),
+    ])
+}
+
+#[test]
+fn composite_index_serves_equality_on_both_fields() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category_score", &["category", "score"])])));
+    let ids = cell.insert_items(vec![
+        item("a", "tools", 1),
+        item("b", "tools", 2),
+        item("c", "books", 1),
+        item("d", "tools", 1),
+    ]);
+
+    let estimate = cell.estimate(category_and_score("tools", 1));
+    assert_eq!(estimate.index_used.as_deref(), Some("by_category_score"));
+    assert_eq!(estimate.estimated_records_scanned, 2);
+    assert_eq!(cell.names(category_and_score("tools", 1)), ["a", "d"]);
+
+    // Constraining only one of the fields can't use the composite index
+    let category_only = filter(vec![condition("category", ComparisonOperator::Equals, json!("tools"))]);
+    assert_eq!(cell.estimate(category_only).index_used, None);
+
+    // Updates and deletes keep the composite entries in step
+    let (updated,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (ids[0].clone(), json!({"score": 2}).to_string(), None::<Precondition>),
+    );
+    updated.unwrap();
+    let (deleted,): (Result<(), CellError>,) = cell.update(user(), "delete", (ids[3].clone(),));
+    deleted.unwrap();
+
+    assert!(cell.names(category_and_score("tools", 1)).is_empty());
+    assert_eq!(cell.names(category_and_score("tools", 2)), ["a", "b"]);
+    assert_eq!(cell.estimate(category_and_score("tools", 2)).estimated_records_scanned, 2);
+}
+
+#[test]
+fn reindex_runs_in_batches_for_controllers_only() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    let records = (0..1_200)
+        .map(|i| (format!("r{:04}", i), item(&format!("item {}", i), if i % 3 == 0 { "tools" } else { "books" }, i)))
+        .collect();
+    cell.import(records, false);
+
+    let (denied,): (Result<ReindexReport, CellError>,) = cell.update(user(), "reindex", ());
+    assert_eq!(denied.unwrap_err(), CellError::PermissionDenied);
+
+    let mut calls = 0;
+    let report = loop {
+        calls += 1;
+        let (report,): (Result<ReindexReport, CellError>,) = cell.update(controller(), "reindex", ());
+        let report = report.unwrap();
+        if report.complete {
+            break report;
+        }
+    };
+    assert_eq!(report.records_reindexed, 1_200);
+    assert!(calls > 3, "reindex finished in {} calls", calls);
+
+    let tools = filter(vec![condition("category", ComparisonOperator::Equals, json!("tools"))]);
+    assert_eq!(cell.estimate(tools.clone()).index_used.as_deref(), Some("by_category"));
+    assert_eq!(cell.run_query(tools, page(1)).unwrap().total_count, 400);
+}
+
+#[test]
+fn distinct_and_count_by_scan_while_a_reindex_is_in_progress() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    let records = (0..1_200)
+        .map(|i| (format!("r{:04}", i), item(&format!("item {}", i), if i % 3 == 0 { "tools" } else { "books" }, i)))
+        .collect();
+    cell.import(records, false);
+
+    let (report,): (Result<ReindexReport, CellError>,) = cell.update(controller(), "reindex", ());
+    assert!(!report.unwrap().complete);
+
+    let (values,): (Vec<String>,) = cell.query(user(), "distinct", ("category".to_string(), page(100)));
+    assert_eq!(values, ["\"books\"", "\"tools\""]);
+    let (buckets,): (Vec<(String, u64)>,) = cell.query(user(), "count_by", ("category".to_string(),));
+    assert_eq!(buckets, [("\"books\"".to_string(), 800), ("\"tools\"".to_string(), 400)]);
+}
+
+#[test]
+fn index_stats_stay_accurate_across_mutations() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    assert_eq!(cell.index_stat("by_category"), (0, 0));
+
+    let ids = cell.insert_items(vec![
+        item("a", "tools", 1),
+        item("b", "tools", 2),
+        item("c", "books", 3),
+        item("d", "games", 4),
+    ]);
+    assert_eq!(cell.index_stat("by_category"), (4, 3));
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (ids[3].clone(), item("d", "books", 4).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    assert_eq!(cell.index_stat("by_category"), (4, 2));
+
+    for id in &ids[..2] {
+        let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (id.clone(),));
+        result.unwrap();
+    }
+    assert_eq!(cell.index_stat("by_category"), (2, 1));
+    let books = filter(vec![condition("category", ComparisonOperator::Equals, json!("books"))]);
+    assert_eq!(cell.estimate(books).estimated_records_scanned, 2);
+}
+
+#[test]
+fn planner_picks_the_most_selective_index_on_skewed_data() {
+    let cell = Cell::new(config(item_schema(vec![
+        index("by_category", &["category"]),
+        index("by_score", &["score"]),
+    ])));
+    // 30 "common", 6 "uncommon" and 4 "rare" items; 8 items per score
+    let records = (0..40)
+        .map(|i| {
+            let category = match i {
+                0..=29 => "common",
+                30..=35 => "uncommon",
+                _ => "rare",
+            };
+            (format!("r{:02}", i), item(&format!("item {}", i), category, i % 5))
+        })
+        .collect();
+    cell.import(records, true);
+
+    assert_eq!(cell.index_stat("by_category"), (40, 3));
+    assert_eq!(cell.index_stat("by_score"), (40, 5));
+
+    let both = |category: &str| filter(vec![
+        condition("category", ComparisonOperator::Equals, json!(category)),
+        condition("score", ComparisonOperator::Equals, json!(1)),
+    ]);
+
+    let estimate = cell.estimate(both("common"));
+    assert_eq!(estimate.index_used.as_deref(), Some("by_score"));
+    assert_eq!(estimate.estimated_records_scanned, 8);
+    assert_eq!(cell.run_query(both("common"), page(100)).unwrap().total_count, 6);
+
+    let estimate = cell.estimate(both("uncommon"));
+    assert_eq!(estimate.index_used.as_deref(), Some("by_category"));
+    assert_eq!(estimate.estimated_records_scanned, 6);
+    assert_eq!(cell.run_query(both("uncommon"), page(100)).unwrap().total_count, 1);
+}
+
+#[test]
+fn configured_admins_who_are_not_controllers_cannot_change_indexes() {
+    let mut admin_config = config(item_schema(vec![]));
+    admin_config.permissions.admin.push(user());
+    let cell = Cell::new(admin_config);
+
+    // The admin list still covers ordinary admin endpoints
+    let (granted,): (Result<(), CellError>,) = cell.update(user(), "grant_role", (other_user(), "editor".to_string()));
+    granted.unwrap();
+
+    let (denied,): (Result<ReindexReport, CellError>,) = cell.update(user(), "reindex", ());
+    assert_eq!(denied.unwrap_err(), CellError::PermissionDenied);
+    let (denied,): (Result<(), CellError>,) = cell.update(user(), "add_index", (index("by_category", &["category"]),));
+    assert_eq!(denied, Err(CellError::PermissionDenied));
+
+    let (added,): (Result<(), CellError>,) = cell.update(controller(), "add_index", (index("by_category", &["category"]),));
+    added.unwrap();
+}
+
+#[test]
+fn numeric_equality_matches_across_integer_and_float_forms_with_and_without_an_index() {
+    for indexes in [vec![], vec![index("by_score", &["score"])]] {
+        let indexed = !indexes.is_empty();
+        let cell = Cell::new(config(item_schema(indexes)));
+        cell.insert_items(vec![
+            item("a", "tools", 2),
+            json!({"name": "b", "category": "tools", "score": 2.5}),
+            item("c", "tools", 3),
+        ]);
+
+        let equals = filter(vec![condition("score", ComparisonOperator::Equals, json!(2.0))]);
+        assert_eq!(cell.estimate(equals.clone()).index_used.is_some(), indexed);
+        assert_eq!(cell.names(equals), ["a"]);
+
+        let not_equals = filter(vec![condition("score", ComparisonOperator::NotEquals, json!(2.0))]);
+        assert_eq!(cell.names(not_equals), ["b", "c"]);
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+
+fn json_schema(cell: &Cell) -> Value {
+    let (document,): (String,) = cell.query(user(), "json_schema", ());
+    serde_json::from_str(&document).expect("json_schema is not JSON")
+}
+
+#[test]
+fn required_fields_and_types_are_exported() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let document = json_schema(&cell);
+
+    assert_eq!(document["$schema"], json!("https://json-schema.org/draft/2020-12/schema"));
+    assert_eq!(document["title"], json!("items"));
+    assert_eq!(document["x-celldb-schema-version"], json!(1));
+    assert_eq!(document["type"], json!("object"));
+    assert_eq!(document["required"], json!(["name"]));
+    assert_eq!(document["properties"]["name"], json!({"type": "string"}));
+    assert_eq!(document["properties"]["score"], json!({"type": "number"}));
+    assert_eq!(document["properties"]["tags"], json!({"type": "array", "items": {"type": "string"}}));
+}
+
+#[test]
+fn validation_rules_map_to_json_schema_keywords() {
+    let cell = Cell::new(config(schema(vec![
+        ("code", FieldDefinition {
+            validation_rules: vec![
+                ValidationRule::MinLength(2),
+                ValidationRule::MaxLength(8),
+                ValidationRule::Pattern("^[A-Z]+$".to_string()),
+            ],
+            ..required(FieldType::Text)
+        }),
+        ("rating", FieldDefinition { validation_rules: vec![ValidationRule::Range(1, 5)], ..field(FieldType::Number) }),
+        ("contact", FieldDefinition { validation_rules: vec![ValidationRule::Email], ..required(FieldType::Text) }),
+        ("checked", FieldDefinition { validation_rules: vec![ValidationRule::Custom("audit".to_string())], ..field(FieldType::Text) }),
+    ], vec![])));
+    let document = json_schema(&cell);
+
+    assert_eq!(document["required"], json!(["code", "contact"]));
+    assert_eq!(
+        document["properties"]["code"],
+        json!({"type": "string", "minLength": 2, "maxLength": 8, "pattern": "^[A-Z]+$"}),
+    );
+    assert_eq!(document["properties"]["rating"], json!({"type": "number", "minimum": 1, "maximum": 5}));
+    assert_eq!(document["properties"]["contact"]["format"], json!("email"));
+    assert_eq!(document["properties"]["checked"]["x-celldb-custom"], json!(["audit"]));
+}
+
+#[test]
+fn only_max_items_maps_to_max_items() {
+    let cell = Cell::new(config(schema(vec![
+        ("tags", FieldDefinition {
+            validation_rules: vec![ValidationRule::MaxItems(3)],
+            ..field(FieldType::Array(Box::new(FieldType::Text)))
+        }),
+        ("photo", FieldDefinition { validation_rules: vec![ValidationRule::MaxSize(100)], ..field(FieldType::Blob) }),
+    ], vec![])));
+    let document = json_schema(&cell);
+
+    assert_eq!(document["properties"]["tags"]["maxItems"], json!(3));
+    assert_eq!(document["properties"]["photo"].get("maxItems"), None);
+    assert_eq!(document["properties"]["photo"]["description"], json!("Binary data of at most 100 bytes"));
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// Seconds between build batches, each indexing up to 500 records
+const BUILD_INTERVAL_SECS: u64 = 5;
+
+/// 1,200 items over 12 categories, so a build takes three batches
+fn large_cell() -> Cell {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let records = (0..1_200)
+        .map(|i| (format!("item_{:04}", i), item(&format!("item_{:04}", i), &format!("cat_{}", i % 12), i)))
+        .collect();
+    cell.import(records, true);
+    cell
+}
+
+fn lazy_index() -> IndexDefinition {
+    IndexDefinition { lazy: Some(true), ..index("by_category", &["category"]) }
+}
+
+fn add_index(cell: &Cell, index: IndexDefinition) {
+    let (result,): (Result<(), CellError>,) = cell.update(controller(), "add_index", (index,));
+    result.expect("add_index failed");
+}
+
+fn builds(cell: &Cell) -> Vec<IndexBuild> {
+    let (builds,): (Vec<IndexBuild>,) = cell.query(user(), "index_builds", ());
+    builds
+}
+
+fn in_category(category: &str) -> QueryFilter {
+    filter(vec![condition("category", ComparisonOperator::Equals, json!(category))])
+}
+
+/// Run a query as an update call, returning the matching names and the instructions it took
+fn query_cached(cell: &Cell, category: &str) -> (Vec<String>, u64) {
+    let metrics = cell.operation_metrics(TrackedOperation::Query);
+    let before = metrics.avg_instructions * metrics.count;
+
+    let (result,): (Result<QueryResult, CellError>,) =
+        cell.update(user(), "query_cached", (in_category(category), page(1_000)));
+    let mut names: Vec<String> = result.expect("query_cached failed").records.iter()
+        .filter_map(|record| parse(record)["name"].as_str().map(str::to_string))
+        .collect();
+    names.sort();
+
+    let metrics = cell.operation_metrics(TrackedOperation::Query);
+    (names, (metrics.avg_instructions * metrics.count).saturating_sub(before))
+}
+
+fn expected_names(category: usize) -> Vec<String> {
+    (0..1_200).filter(|i| i % 12 == category).map(|i| format!("item_{:04}", i)).collect()
+}
+
+#[test]
+fn adding_a_lazy_index_leaves_it_pending_until_the_next_build_batch() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+
+    let pending = builds(&cell);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].index_name, "by_category");
+    assert!(pending[0].started_at.is_none());
+    assert_eq!(pending[0].records_indexed, 0);
+    assert_eq!(cell.estimate(in_category("cat_0")).index_used, None);
+
+    cell.advance_secs(BUILD_INTERVAL_SECS);
+    let progress = &builds(&cell)[0];
+    assert!(progress.started_at.is_some());
+    assert_eq!(progress.records_indexed, 500);
+}
+
+#[test]
+fn queries_during_the_build_are_correct_and_faster_afterwards() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+
+    let (first, scan_cost) = query_cached(&cell, "cat_0");
+    assert_eq!(first, expected_names(0));
+
+    cell.advance_secs(BUILD_INTERVAL_SECS);
+    let progress = &builds(&cell)[0];
+    assert_eq!(progress.records_indexed, 500);
+    assert_eq!(cell.estimate(in_category("cat_1")).index_used, None);
+
+    // Half-built, the index isn't used, so the scan still finds every match
+    let (during, _) = query_cached(&cell, "cat_1");
+    assert_eq!(during, expected_names(1));
+
+    cell.advance_secs(2 * BUILD_INTERVAL_SECS);
+    assert!(builds(&cell).is_empty());
+    assert_eq!(cell.estimate(in_category("cat_2")).index_used.as_deref(), Some("by_category"));
+
+    let (after, index_cost) = query_cached(&cell, "cat_2");
+    assert_eq!(after, expected_names(2));
+    assert!(index_cost * 3 < scan_cost, "indexed query cost {} instructions against {} for the scan", index_cost, scan_cost);
+}
+
+#[test]
+fn writes_during_the_build_are_indexed() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+    cell.advance_secs(BUILD_INTERVAL_SECS);
+
+    // Writes keep the index current on either side of the build's position
+    let early = cell.insert(item("early", "cat_new", 1));
+    cell.import(vec![("zzz_late".to_string(), item("late", "cat_new", 2))], true);
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", ("item_0000".to_string(), json!({"category": "cat_new"}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (early,));
+    result.unwrap();
+
+    cell.advance_secs(2 * BUILD_INTERVAL_SECS);
+    assert!(builds(&cell).is_empty());
+
+    let (names, _) = query_cached(&cell, "cat_new");
+    assert_eq!(names, vec!["item_0000", "late"]);
+    let (names, _) = query_cached(&cell, "cat_0");
+    assert_eq!(names, expected_names(0)[1..].to_vec());
+}
+
+#[test]
+fn build_progress_survives_upgrades() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+    cell.advance_secs(BUILD_INTERVAL_SECS);
+
+    cell.upgrade();
+    assert_eq!(builds(&cell)[0].records_indexed, 500);
+
+    cell.advance_secs(2 * BUILD_INTERVAL_SECS);
+    assert!(builds(&cell).is_empty());
+    let (names, _) = query_cached(&cell, "cat_3");
+    assert_eq!(names, expected_names(3));
+}
+
+#[test]
+fn query_calls_use_the_index_once_the_timer_has_built_it() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+    assert_eq!(cell.run_query(in_category("cat_0"), page(1_000)).unwrap().total_count, 100);
+
+    // No update call ever queries the cell, yet the build completes
+    cell.advance_secs(3 * BUILD_INTERVAL_SECS);
+    assert!(builds(&cell).is_empty());
+    assert_eq!(cell.estimate(in_category("cat_1")).index_used.as_deref(), Some("by_category"));
+    assert_eq!(cell.run_query(in_category("cat_1"), page(1_000)).unwrap().total_count, 100);
+}
+
+#[test]
+fn distinct_and_count_by_scan_until_the_index_is_ready() {
+    let cell = large_cell();
+    add_index(&cell, lazy_index());
+
+    let (values,): (Vec<String>,) = cell.query(user(), "distinct", ("category".to_string(), page(100)));
+    assert_eq!(values.len(), 12);
+    let (buckets,): (Vec<(String, u64)>,) = cell.query(user(), "count_by", ("category".to_string(),));
+    assert_eq!(buckets.len(), 12);
+    assert!(buckets.iter().all(|(_, count)| *count == 100));
+}
+
+#[test]
+fn regular_indexes_are_ready_immediately() {
+    let cell = large_cell();
+    add_index(&cell, index("by_category", &["category"]));
+
+    assert!(builds(&cell).is_empty());
+    assert_eq!(cell.estimate(in_category("cat_0")).index_used.as_deref(), Some("by_category"));
+}
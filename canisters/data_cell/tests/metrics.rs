@@ -0,0 +1,51 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn latencies_are_recorded_for_each_operation() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let ids = cell.insert_items((0..3).map(|i| item(&format!("item_{}", i), "a", i)).collect());
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (ids[0].clone(), json!({"score": 10}).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (ids[1].clone(),));
+    result.unwrap();
+
+    for (operation, count) in [(TrackedOperation::Insert, 3), (TrackedOperation::Update, 1), (TrackedOperation::Delete, 1)] {
+        let metrics = cell.operation_metrics(operation);
+        assert_eq!(metrics.count, count, "{:?}", operation);
+        assert!(metrics.min_instructions > 0);
+        assert!(metrics.min_instructions <= metrics.avg_instructions && metrics.avg_instructions <= metrics.max_instructions);
+        assert_eq!(metrics.histogram.iter().map(|(_, samples)| samples).sum::<u64>(), count);
+    }
+
+    // Samples live in stable memory
+    cell.upgrade();
+    assert_eq!(cell.operation_metrics(TrackedOperation::Insert).count, 3);
+}
+
+#[test]
+fn rejected_operations_are_measured_too() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    assert!(cell.try_insert(json!({"category": "a"})).is_err());
+
+    // Errors are replies rather than traps, so the sample is kept
+    assert_eq!(cell.operation_metrics(TrackedOperation::Insert).count, 1);
+}
+
+#[test]
+fn cell_metrics_report_records_memory_and_queries() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    cell.insert_items((0..3).map(|i| item(&format!("item_{}", i), "a", i)).collect());
+    let (result,): (Result<QueryResult, CellError>,) = cell.update(user(), "query_cached", (filter(vec![]), page(10)));
+    result.unwrap();
+
+    let (metrics,): (CellMetrics,) = cell.query(user(), "get_metrics", ());
+    assert_eq!(metrics.record_count, 3);
+    assert_eq!(metrics.query_count, 1);
+    assert!(metrics.memory_usage > 0);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// Concatenated `export_ndjson` chunks and how many chunks there were
+fn export_ndjson(cell: &Cell, chunk_size: u32) -> (String, usize) {
+    let mut data = String::new();
+    let mut chunks = 0;
+    let mut cursor = None;
+    loop {
+        let (chunk,): (NdjsonChunk,) = cell.query(user(), "export_ndjson", (cursor.clone(), chunk_size));
+        assert!(chunk.data.is_empty() || chunk.data.ends_with('\n'), "chunk ends mid-line");
+        data.push_str(&chunk.data);
+        chunks += 1;
+        cursor = chunk.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    (data, chunks)
+}
+
+fn lines(data: &str) -> Vec<Value> {
+    data.lines().map(|line| serde_json::from_str(line).expect("line is not JSON")).collect()
+}
+
+#[test]
+fn concatenated_chunks_are_ndjson_covering_every_record() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let ids: BTreeSet<String> = (0..25).map(|i| cell.insert(item(&format!("item_{:02}", i), "a", i))).collect();
+
+    let (data, chunks) = export_ndjson(&cell, 10);
+    assert_eq!(chunks, 3);
+    let lines = lines(&data);
+    assert_eq!(lines.len(), 26);
+
+    let header = &lines[0];
+    assert_eq!(header["record_count"], json!(25));
+    assert_eq!(header["schema"]["name"], json!("items"));
+
+    let exported: BTreeSet<String> = lines[1..].iter().map(|line| line["id"].as_str().unwrap().to_string()).collect();
+    assert_eq!(exported, ids);
+    let first = lines[1..].iter().find(|line| line["record"]["name"] == json!("item_00")).unwrap();
+    assert_eq!(first["record"], item("item_00", "a", 0));
+}
+
+#[test]
+fn expired_records_are_left_out_of_the_export() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let kept = cell.insert(item("kept", "a", 1));
+    cell.insert_expiring(item("gone", "a", 2), cell.now() + 5_000_000_000);
+    cell.pic.advance_time(std::time::Duration::from_secs(10));
+
+    let (data, _) = export_ndjson(&cell, 100);
+    let records: Vec<Value> = lines(&data).into_iter().skip(1).collect();
+    assert_eq!(records, [json!({"id": kept, "record": item("kept", "a", 1)})]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn keyed_cell() -> Cell {
+    Cell::new(CellInitConfig {
+        id_strategy: Some(IdStrategy::FromField("name".to_string())),
+        ..config(item_schema(vec![]))
+    })
+}
+
+fn insert_if(cell: &Cell, record: serde_json::Value, precondition: Precondition) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) =
+        cell.update(user(), "insert", (record.to_string(), None::<u64>, Some(precondition), None::<String>));
+    result
+}
+
+fn update_if(cell: &Cell, id: &str, updates: serde_json::Value, precondition: Precondition) -> Result<(), CellError> {
+    let (result,): (Result<(), CellError>,) =
+        cell.update(user(), "update", (id.to_string(), updates.to_string(), Some(precondition)));
+    result
+}
+
+fn field_equals(field: &str, value: serde_json::Value) -> Precondition {
+    Precondition::FieldEquals { field: field.to_string(), value: value.to_string() }
+}
+
+#[test]
+fn insert_if_absent_succeeds_only_for_new_keys() {
+    let cell = keyed_cell();
+
+    assert_eq!(insert_if(&cell, item("alpha", "a", 1), Precondition::Absent), Ok("alpha".to_string()));
+    assert!(matches!(
+        insert_if(&cell, item("alpha", "b", 2), Precondition::Absent),
+        Err(CellError::PreconditionFailed(_))
+    ));
+    assert_eq!(cell.get("alpha").unwrap()["category"], json!("a"));
+}
+
+#[test]
+fn update_if_field_equals_applies_only_on_a_match() {
+    let cell = keyed_cell();
+    cell.insert(item("alpha", "a", 1));
+
+    assert!(matches!(
+        update_if(&cell, "alpha", json!({"score": 2}), field_equals("category", json!("b"))),
+        Err(CellError::PreconditionFailed(_))
+    ));
+    assert!(matches!(
+        update_if(&cell, "alpha", json!({"score": 2}), field_equals("tags", json!(["x"]))),
+        Err(CellError::PreconditionFailed(_))
+    ));
+    assert_eq!(cell.get("alpha").unwrap()["score"], json!(1));
+
+    update_if(&cell, "alpha", json!({"score": 2}), field_equals("category", json!("a"))).unwrap();
+    // Numbers compare by value
+    update_if(&cell, "alpha", json!({"category": "b"}), field_equals("score", json!(2.0))).unwrap();
+    let record = cell.get("alpha").unwrap();
+    assert_eq!(record["score"], json!(2));
+    assert_eq!(record["category"], json!("b"));
+}
+
+#[test]
+fn preconditions_fail_when_their_target_state_is_wrong() {
+    let cell = keyed_cell();
+    cell.insert(item("alpha", "a", 1));
+
+    assert!(matches!(
+        update_if(&cell, "alpha", json!({"score": 2}), Precondition::Absent),
+        Err(CellError::PreconditionFailed(_))
+    ));
+    assert!(matches!(
+        insert_if(&cell, item("beta", "a", 1), field_equals("category", json!("a"))),
+        Err(CellError::PreconditionFailed(_))
+    ));
+    assert_eq!(cell.get("beta"), None);
+    assert_eq!(cell.get("alpha").unwrap()["score"], json!(1));
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn null_checks_see_defaults_applied_on_insert() {
+    let mut status = field(FieldType::Text);
+    status.default_value = Some(json!("new").to_string());
+    let cell = Cell::new(config(schema(vec![
+        ("name", required(FieldType::Text)),
+        ("status", status),
+        ("note", field(FieldType::Text)),
+    ], vec![])));
+
+    cell.insert(json!({"name": "defaulted"}));
+    cell.insert(json!({"name": "noted", "note": "hello"}));
+
+    let is_null = |field: &str| filter(vec![condition(field, ComparisonOperator::IsNull, json!(null))]);
+    let is_not_null = |field: &str| filter(vec![condition(field, ComparisonOperator::IsNotNull, json!(null))]);
+
+    assert!(cell.names(is_null("status")).is_empty());
+    assert_eq!(cell.names(is_not_null("status")), ["defaulted", "noted"]);
+    assert_eq!(cell.names(is_null("note")), ["defaulted"]);
+    assert_eq!(cell.names(is_not_null("note")), ["noted"]);
+}
+
+fn categorized_cell(indexes: Vec<IndexDefinition>) -> Cell {
+    let cell = Cell::new(config(item_schema(indexes)));
+    cell.insert_items(vec![
+        item("a", "tools", 1),
+        item("b", "books", 2),
+        item("c", "tools", 3),
+        item("d", "garden", 4),
+        item("e", "tools", 5),
+        item("f", "books", 6),
+        json!({"name": "g"}),
+    ]);
+    cell
+}
+
+fn distinct(cell: &Cell, field: &str, pagination: Pagination) -> Vec<serde_json::Value> {
+    let (values,): (Vec<String>,) = cell.query(user(), "distinct", (field.to_string(), pagination));
+    values.iter().map(|value| parse(value)).collect()
+}
+
+#[test]
+fn distinct_returns_each_value_once_with_and_without_an_index() {
+    let expected = vec![json!("books"), json!("garden"), json!("tools")];
+
+    let scanned = categorized_cell(vec![]);
+    assert_eq!(distinct(&scanned, "category", page(100)), expected);
+
+    let indexed = categorized_cell(vec![index("by_category", &["category"])]);
+    assert_eq!(distinct(&indexed, "category", page(100)), expected);
+
+    let second_page = Pagination { offset: 1, limit: 1, cursor: None };
+    assert_eq!(distinct(&indexed, "category", second_page), vec![json!("garden")]);
+}
+
+fn count_by(cell: &Cell, field: &str) -> Vec<(serde_json::Value, u64)> {
+    let (buckets,): (Vec<(String, u64)>,) = cell.query(user(), "count_by", (field.to_string(),));
+    buckets.iter().map(|(value, count)| (parse(value), *count)).collect()
+}
+
+#[test]
+fn count_by_groups_a_categorical_field_by_descending_count() {
+    let expected = vec![(json!("tools"), 3), (json!("books"), 2), (json!("garden"), 1)];
+
+    let scanned = categorized_cell(vec![]);
+    assert_eq!(count_by(&scanned, "category"), expected);
+
+    let indexed = categorized_cell(vec![index("by_category", &["category"])]);
+    assert_eq!(count_by(&indexed, "category"), expected);
+}
+
+#[test]
+fn cursor_paging_visits_every_record_once_under_concurrent_inserts() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let originals: Vec<String> = (0..25).map(|i| format!("item {:02}", i)).collect();
+    for (i, name) in originals.iter().enumerate() {
+        cell.insert(item(name, "tools", i as i64 * 10));
+    }
+
+    let by_score = QueryFilter { sort_by: Some("score".to_string()), ..filter(vec![]) };
+    let mut seen: Vec<String> = Vec::new();
+    let mut cursor = None;
+    let mut round = 0;
+
+    loop {
+        let result = cell.run_query(by_score.clone(), Pagination { offset: 0, limit: 10, cursor: cursor.clone() }).unwrap();
+        seen.extend(result.records.iter().map(|record| parse(record)["name"].as_str().unwrap().to_string()));
+
+        // Records land both before the cursor and ahead of it between pages
+        round += 1;
+        cell.insert(item(&format!("early {}", round), "tools", -round));
+        cell.insert(item(&format!("late {}", round), "tools", 1_000 + round));
+
+        match result.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    let mut unique = seen.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), seen.len(), "a record was returned twice: {:?}", seen);
+
+    for name in &originals {
+        assert!(seen.contains(name), "{} was skipped", name);
+    }
+    assert!(!seen.iter().any(|name| name.starts_with("early")), "records before the cursor were returned");
+}
+
+#[test]
+fn records_with_equal_sort_keys_page_in_record_id_order() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    // Imported out of ID order, all with the same score
+    let records = (0..30)
+        .map(|i| (i * 7) % 30)
+        .map(|i| (format!("id_{:02}", i), item(&format!("item_{:02}", i), "a", 5)))
+        .collect();
+    cell.import(records, true);
+
+    for sort_order in [SortOrder::Ascending, SortOrder::Descending] {
+        let by_score = QueryFilter { sort_by: Some("score".to_string()), sort_order, ..filter(vec![]) };
+        let pages = || -> Vec<Vec<String>> {
+            (0..5)
+                .map(|n| Pagination { offset: n * 7, ..page(7) })
+                .map(|pagination| cell.run_query(by_score.clone(), pagination).unwrap().records.iter()
+                    .map(|record| parse(record)["name"].as_str().unwrap().to_string())
+                    .collect())
+                .collect()
+        };
+
+        let first = pages();
+        assert_eq!(pages(), first);
+        let names: Vec<String> = first.concat();
+        let expected: Vec<String> = (0..30).map(|i| format!("item_{:02}", i)).collect();
+        assert_eq!(names, expected);
+    }
+}
+
+#[test]
+fn queries_type_check_operators_against_the_stored_schema() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    cell.insert(item("alpha", "a", 1));
+
+    let contains_number = filter(vec![condition("score", ComparisonOperator::Contains, json!(1))]);
+    assert!(matches!(cell.run_query(contains_number, page(10)), Err(CellError::ValidationError(_))));
+
+    let text_above_number = filter(vec![condition("name", ComparisonOperator::GreaterThan, json!(1))]);
+    assert!(matches!(cell.run_query(text_above_number, page(10)), Err(CellError::ValidationError(_))));
+
+    let text_above_text = filter(vec![condition("name", ComparisonOperator::GreaterThan, json!("a"))]);
+    assert_eq!(cell.names(text_above_text), ["alpha"]);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// 200 items, so an unindexed scan costs far more than a cache lookup
+fn busy_cell() -> Cell {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let records = (0..200)
+        .map(|i| (format!("item_{:03}", i), item(&format!("item_{:03}", i), "a", i)))
+        .collect();
+    cell.import(records, true);
+    cell
+}
+
+fn contains_filter() -> QueryFilter {
+    filter(vec![condition("name", ComparisonOperator::Contains, json!("_1"))])
+}
+
+fn query_cached(cell: &Cell) -> QueryResult {
+    let (result,): (Result<QueryResult, CellError>,) =
+        cell.update(user(), "query_cached", (contains_filter(), page(1_000)));
+    result.expect("query_cached failed")
+}
+
+/// Instructions spent on cached queries so far, to within rounding of the average
+fn query_instructions(cell: &Cell) -> u64 {
+    let metrics = cell.operation_metrics(TrackedOperation::Query);
+    metrics.avg_instructions * metrics.count
+}
+
+#[test]
+fn repeated_queries_are_served_from_the_cache() {
+    let cell = busy_cell();
+
+    let first = query_cached(&cell);
+    let miss = query_instructions(&cell);
+    let second = query_cached(&cell);
+    let hit = query_instructions(&cell).saturating_sub(miss);
+
+    assert_eq!(second.records, first.records);
+    assert_eq!(second.total_count, 100);
+    assert!(hit * 10 < miss, "cache hit cost {} instructions against {} for the scan", hit, miss);
+
+    // Plain queries are answered from the same cache
+    assert_eq!(cell.run_query(contains_filter(), page(1_000)).unwrap().records, first.records);
+}
+
+#[test]
+fn a_write_invalidates_cached_results() {
+    let cell = busy_cell();
+    query_cached(&cell);
+    query_cached(&cell);
+    let before_write = query_instructions(&cell);
+
+    cell.insert(item("item_1000", "a", 1_000));
+    assert_eq!(cell.run_query(contains_filter(), page(1_000)).unwrap().total_count, 101);
+
+    let after_write = query_cached(&cell);
+    let rescan = query_instructions(&cell).saturating_sub(before_write);
+    assert_eq!(after_write.total_count, 101);
+    assert!(rescan * 2 > before_write, "the query after a write was not re-executed");
+}
+
+#[test]
+fn traced_queries_share_cache_entries_with_untraced_ones() {
+    let cell = busy_cell();
+    let first = query_cached(&cell);
+    let miss = query_instructions(&cell);
+
+    let traced = QueryFilter { trace_id: Some("trace_1".to_string()), ..contains_filter() };
+    let (result,): (Result<QueryResult, CellError>,) = cell.update(user(), "query_cached", (traced, page(1_000)));
+    let hit = query_instructions(&cell).saturating_sub(miss);
+
+    assert_eq!(result.unwrap().records, first.records);
+    assert!(hit * 10 < miss, "a trace ID split the cache: {} instructions against {}", hit, miss);
+}
+
+#[test]
+fn cached_results_expire_with_their_earliest_record() {
+    let cell = busy_cell();
+    cell.insert_expiring(item("item_1000", "a", 1_000), cell.now() + 5_000_000_000);
+    assert_eq!(query_cached(&cell).total_count, 101);
+
+    // Well inside the cache TTL and before the expiry sweep runs
+    cell.advance_secs(10);
+    assert_eq!(cell.run_query(contains_filter(), page(1_000)).unwrap().total_count, 100);
+    assert_eq!(query_cached(&cell).total_count, 100);
+}
@@ -0,0 +1,51 @@
+//! Range scans over an ordered index should cost the same however many
+//! records lie outside the scanned range. Costs come from the cell's own
+//! instruction counts for `query_cached`.
+
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const LARGE: i64 = 10_000;
+const SMALL: i64 = 100;
+const MATCHES: i64 = 50;
+
+/// A cell holding `count` items scored `0..count`, with `score` indexed if asked
+fn scored_cell(count: i64, indexed: bool) -> Cell {
+    let indexes = if indexed { vec![index("by_score", &["score"])] } else { vec![] };
+    let cell = Cell::new(config(item_schema(indexes)));
+    let records = (0..count)
+        .map(|i| (format!("r{:05}", i), item(&format!("item {}", i), "bench", i)))
+        .collect();
+    let report = cell.import(records, false);
+    assert_eq!(report.imported, count as u64);
+    cell
+}
+
+/// Instructions `query_cached` spent returning the top `MATCHES` scores
+fn top_scores_cost(cell: &Cell, count: i64) -> u64 {
+    let top = filter(vec![condition("score", ComparisonOperator::GreaterThan, json!(count - MATCHES - 1))]);
+    let (result,): (Result<QueryResult, CellError>,) = cell.update(user(), "query_cached", (top, page(1_000)));
+    assert_eq!(result.unwrap().total_count, MATCHES as u64);
+
+    let metrics = cell.operation_metrics(TrackedOperation::Query);
+    assert_eq!(metrics.count, 1);
+    metrics.max_instructions
+}
+
+#[test]
+fn range_scan_over_ten_thousand_records_touches_only_the_matching_range() {
+    let large = top_scores_cost(&scored_cell(LARGE, true), LARGE);
+    let small = top_scores_cost(&scored_cell(SMALL, true), SMALL);
+    let full_scan = top_scores_cost(&scored_cell(LARGE, false), LARGE);
+
+    println!("range over {} records: {} instructions", LARGE, large);
+    println!("range over {} records: {} instructions", SMALL, small);
+    println!("full scan over {} records: {} instructions", LARGE, full_scan);
+
+    // 100x more records outside the range barely changes the cost (one BTree
+    // descent is logarithmic), while scanning them all costs far more
+    assert!(large < small * 2, "range scan grew with the record count: {} vs {}", large, small);
+    assert!(large * 20 < full_scan, "range scan was not much cheaper than a full scan: {} vs {}", large, full_scan);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+
+fn insert_as(cell: &Cell, sender: Principal, name: &str) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) = cell.update(
+        sender, "insert", (item(name, "a", 1).to_string(), None::<u64>, None::<Precondition>, None::<String>),
+    );
+    result
+}
+
+fn limited_cell(per_minute: u32) -> Cell {
+    let mut config = config(item_schema(vec![]));
+    config.write_rate_limit_per_minute = Some(per_minute);
+    Cell::new(config)
+}
+
+#[test]
+fn callers_over_the_limit_are_throttled_until_their_bucket_refills() {
+    let cell = limited_cell(3);
+
+    for i in 0..3 {
+        insert_as(&cell, user(), &format!("item {}", i)).unwrap();
+    }
+    assert_eq!(insert_as(&cell, user(), "throttled"), Err(CellError::RateLimited));
+
+    // Buckets are per caller
+    insert_as(&cell, other_user(), "other").unwrap();
+
+    // One token refills every 20 seconds at 3 per minute
+    cell.pic.advance_time(std::time::Duration::from_secs(21));
+    insert_as(&cell, user(), "refilled").unwrap();
+    assert_eq!(insert_as(&cell, user(), "throttled again"), Err(CellError::RateLimited));
+}
+
+#[test]
+fn updates_and_deletes_draw_from_the_same_bucket() {
+    let cell = limited_cell(2);
+    let id = insert_as(&cell, user(), "item").unwrap();
+
+    let (result,): (Result<(), CellError>,) = cell.update(
+        user(), "update", (id.clone(), item("item", "b", 2).to_string(), None::<Precondition>),
+    );
+    result.unwrap();
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (id,));
+    assert_eq!(result, Err(CellError::RateLimited));
+}
+
+#[test]
+fn admins_bypass_the_limit() {
+    let cell = limited_cell(1);
+
+    for i in 0..5 {
+        insert_as(&cell, controller(), &format!("item {}", i)).unwrap();
+    }
+}
+
+#[test]
+fn limits_survive_an_upgrade() {
+    let cell = limited_cell(1);
+    insert_as(&cell, user(), "item").unwrap();
+
+    cell.upgrade();
+    assert_eq!(insert_as(&cell, user(), "throttled"), Err(CellError::RateLimited));
+}
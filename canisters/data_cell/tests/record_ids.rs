@@ -0,0 +1,113 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use std::collections::HashSet;
+
+fn cell_with(strategy: IdStrategy) -> Cell {
+    Cell::new(CellInitConfig { id_strategy: Some(strategy), ..config(item_schema(vec![])) })
+}
+
+fn is_v4_uuid(id: &str) -> bool {
+    let groups: Vec<&str> = id.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups.iter().all(|group| group.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()))
+        && groups[2].starts_with('4')
+        && matches!(groups[3].chars().next(), Some('8' | '9' | 'a' | 'b'))
+}
+
+#[test]
+fn uuid_strategy_issues_distinct_v4_uuids() {
+    let cell = cell_with(IdStrategy::Uuid);
+
+    let ids: Vec<String> = (0..5).map(|i| cell.insert(item(&format!("item_{}", i), "a", i))).collect();
+    for id in &ids {
+        assert!(is_v4_uuid(id), "{} is not a v4 UUID", id);
+    }
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+    assert_eq!(cell.get(&ids[3]).unwrap()["name"], json!("item_3"));
+}
+
+#[test]
+fn monotonic_strategy_issues_sortable_sequence_numbers_across_upgrades() {
+    let cell = cell_with(IdStrategy::Monotonic);
+
+    let first = cell.insert(item("alpha", "a", 1));
+    let second = cell.insert(item("beta", "a", 2));
+    assert_eq!(first, format!("{:020}", 1));
+    assert_eq!(second, format!("{:020}", 2));
+
+    // Deleting the latest record does not hand its number out again
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "delete", (second.clone(),));
+    result.unwrap();
+    cell.upgrade();
+
+    let third = cell.insert(item("gamma", "a", 3));
+    assert_eq!(third, format!("{:020}", 3));
+    assert!(first < third);
+}
+
+#[test]
+fn from_field_strategy_uses_the_key_field_value() {
+    let cell = cell_with(IdStrategy::FromField("name".to_string()));
+
+    let id = cell.insert(item("alpha", "a", 1));
+    assert_eq!(id, "alpha");
+    assert_eq!(cell.get("alpha").unwrap()["score"], json!(1));
+
+    let numeric = cell_with(IdStrategy::FromField("score".to_string()));
+    assert_eq!(numeric.insert(item("beta", "b", 42)), "42");
+}
+
+#[test]
+fn from_field_strategy_rejects_missing_and_duplicate_keys() {
+    let cell = cell_with(IdStrategy::FromField("category".to_string()));
+
+    assert!(matches!(cell.try_insert(json!({"name": "alpha"})), Err(CellError::SchemaViolation(_))));
+    assert!(matches!(cell.try_insert(item("alpha", "", 1)), Err(CellError::SchemaViolation(_))));
+
+    cell.insert(item("alpha", "a", 1));
+    assert!(matches!(cell.try_insert(item("beta", "a", 2)), Err(CellError::SchemaViolation(_))));
+    assert_eq!(cell.get("a").unwrap()["name"], json!("alpha"));
+    assert_eq!(cell.health().record_count, 1);
+}
+
+#[test]
+fn from_field_keys_of_expired_records_can_be_reused() {
+    let cell = cell_with(IdStrategy::FromField("name".to_string()));
+    cell.insert_expiring(item("session", "a", 1), cell.now() + 5_000_000_000);
+    assert!(matches!(cell.try_insert(item("session", "b", 2)), Err(CellError::SchemaViolation(_))));
+
+    // Expired but not yet swept: the key is free again
+    cell.pic.advance_time(std::time::Duration::from_secs(10));
+    assert_eq!(cell.insert(item("session", "b", 2)), "session");
+    assert_eq!(cell.get("session").unwrap()["category"], json!("b"));
+}
+
+#[test]
+fn monotonic_strategy_skips_ids_taken_by_imported_records() {
+    let cell = Cell::new(CellInitConfig {
+        id_strategy: Some(IdStrategy::Monotonic),
+        ..config(item_schema(vec![index("by_category", &["category"])]))
+    });
+    cell.insert(item("alpha", "a", 1));
+    cell.import(vec![(format!("{:020}", 3), item("restored", "b", 3))], true);
+
+    let ids: Vec<String> = (0..3).map(|i| cell.insert(item(&format!("new_{}", i), "c", i))).collect();
+    assert!(!ids.contains(&format!("{:020}", 3)));
+    assert_eq!(cell.get(&format!("{:020}", 3)).unwrap()["name"], json!("restored"));
+
+    // Nothing was overwritten, so every record keeps exactly one index entry
+    assert_eq!(cell.health().record_count, 5);
+    assert_eq!(cell.index_stat("by_category"), (5, 3));
+}
+
+#[test]
+fn monotonic_strategy_fails_cleanly_once_the_sequence_is_exhausted() {
+    let cell = cell_with(IdStrategy::Monotonic);
+
+    // A restored record holding the last sequence number leaves none to issue
+    cell.import(vec![(u64::MAX.to_string(), item("restored", "a", 1))], true);
+    assert!(matches!(cell.try_insert(item("next", "a", 2)), Err(CellError::StorageError(_))));
+    assert_eq!(cell.health().record_count, 1);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn get_many_returns_records_positionally_with_none_for_missing_ids() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let first = cell.insert(item("alpha", "a", 1));
+    let second = cell.insert(item("beta", "b", 2));
+
+    let ids = vec![second.clone(), "missing".to_string(), first.clone(), second];
+    let (records,): (Vec<Option<String>>,) = cell.query(user(), "get_many", (ids,));
+
+    let names: Vec<Option<serde_json::Value>> = records.iter()
+        .map(|record| record.as_deref().map(|record| parse(record)["name"].clone()))
+        .collect();
+    assert_eq!(names, vec![Some(json!("beta")), None, Some(json!("alpha")), Some(json!("beta"))]);
+}
+
+#[test]
+fn get_many_rejects_more_than_a_hundred_ids() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let ids: Vec<String> = (0..101).map(|i| i.to_string()).collect();
+
+    let result: Result<(Vec<Option<String>>,), _> =
+        pocket_ic::query_candid_as(&cell.pic, cell.id, user(), "get_many", (ids,));
+    assert!(result.is_err());
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn replica_of(primary: &Cell) -> Cell {
+    let replica = primary.sibling(CellInitConfig { replica_of: Some(primary.id), ..config(item_schema(vec![])) });
+    replica.settle();
+    replica
+}
+
+fn status(replica: &Cell) -> ReplicaStatus {
+    let (status,): (ReplicaStatus,) = replica.query(user(), "replica_status", ());
+    status
+}
+
+#[test]
+fn writes_to_the_primary_propagate_to_the_replica() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let existing = primary.insert(item("alpha", "a", 1));
+
+    let replica = replica_of(&primary);
+    assert_eq!(replica.get(&existing), Some(item("alpha", "a", 1)));
+    let backfilled = status(&replica);
+    assert_eq!(backfilled.primary, Some(primary.id));
+    assert!(backfilled.synced);
+    assert_eq!(backfilled.records_backfilled, 1);
+
+    let added = primary.insert(item("beta", "b", 2));
+    let (result,): (Result<(), CellError>,) =
+        primary.update(user(), "update", (existing.clone(), json!({"score": 10}).to_string(), None::<Precondition>));
+    result.unwrap();
+    primary.settle();
+
+    assert_eq!(replica.get(&added), Some(item("beta", "b", 2)));
+    assert_eq!(replica.get(&existing).unwrap()["score"], json!(10));
+
+    let (result,): (Result<(), CellError>,) = primary.update(user(), "delete", (added.clone(),));
+    result.unwrap();
+    primary.settle();
+
+    assert_eq!(replica.get(&added), None);
+    assert_eq!(status(&replica).changes_applied, 3);
+}
+
+#[test]
+fn replicas_reject_writes() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let existing = primary.insert(item("alpha", "a", 1));
+    let replica = replica_of(&primary);
+
+    assert_eq!(replica.try_insert(item("beta", "b", 2)), Err(CellError::PermissionDenied));
+    let (result,): (Result<(), CellError>,) =
+        replica.update(user(), "update", (existing.clone(), json!({"score": 10}).to_string(), None::<Precondition>));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+    let (result,): (Result<(), CellError>,) = replica.update(user(), "delete", (existing.clone(),));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+
+    assert_eq!(replica.get(&existing), Some(item("alpha", "a", 1)));
+    assert_eq!(primary.health().record_count, 1);
+}
+
+#[test]
+fn only_the_primary_may_apply_changes() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let replica = replica_of(&primary);
+
+    let event = ChangeEvent {
+        op: ChangeOperation::Insert,
+        record_id: "forged".to_string(),
+        record: Some(item("forged", "a", 1).to_string()),
+        timestamp: 0,
+        version: 0,
+        expires_at: None,
+    };
+    let result = pocket_ic::update_candid_as::<_, ()>(&replica.pic, replica.id, user(), "apply_change", (event,));
+    assert!(result.is_err());
+    assert_eq!(replica.get("forged"), None);
+}
+
+/// Deliver an event to the replica as if the primary sent it
+fn deliver(primary: &Cell, replica: &Cell, op: ChangeOperation, record_id: &str, record: Option<serde_json::Value>, version: u64) {
+    let event = ChangeEvent {
+        op,
+        record_id: record_id.to_string(),
+        record: record.map(|record| record.to_string()),
+        timestamp: 0,
+        version,
+        expires_at: None,
+    };
+    pocket_ic::update_candid_as::<_, ()>(&replica.pic, replica.id, primary.id, "apply_change", (event,))
+        .expect("apply_change failed");
+}
+
+#[test]
+fn stale_change_events_are_skipped() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let replica = replica_of(&primary);
+
+    // Versions 1 (insert) and 2 (update)
+    let record_id = primary.insert(item("alpha", "a", 1));
+    let (result,): (Result<(), CellError>,) =
+        primary.update(user(), "update", (record_id.clone(), json!({"score": 10}).to_string(), None::<Precondition>));
+    result.unwrap();
+    primary.settle();
+    assert_eq!(replica.get(&record_id).unwrap()["score"], json!(10));
+
+    // A redelivered insert doesn't roll the record back
+    deliver(&primary, &replica, ChangeOperation::Insert, &record_id, Some(item("alpha", "a", 1)), 1);
+    assert_eq!(replica.get(&record_id).unwrap()["score"], json!(10));
+
+    // Nor does a stale update bring a deleted record back
+    let (result,): (Result<(), CellError>,) = primary.update(user(), "delete", (record_id.clone(),));
+    result.unwrap();
+    primary.settle();
+    deliver(&primary, &replica, ChangeOperation::Update, &record_id, Some(item("alpha", "a", 10)), 2);
+    assert_eq!(replica.get(&record_id), None);
+    assert_eq!(status(&replica).changes_applied, 3);
+}
+
+#[test]
+fn expiring_records_expire_on_the_replica_too() {
+    let primary = Cell::new(config(item_schema(vec![])));
+    let replica = replica_of(&primary);
+
+    let record_id = primary.insert_expiring(item("session", "tools", 1), primary.now() + 5 * 1_000_000_000);
+    primary.settle();
+    assert!(replica.get(&record_id).is_some());
+
+    // The replica stops serving it at once, and the primary's sweep deletes it everywhere
+    primary.pic.advance_time(std::time::Duration::from_secs(10));
+    assert_eq!(replica.get(&record_id), None);
+    primary.advance_secs(61);
+    assert_eq!(primary.health().record_count, 0);
+    assert_eq!(replica.health().record_count, 0);
+    assert_eq!(status(&replica).changes_applied, 2);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+use serde_json::json;
+
+/// A cell only `editor`s may write and `viewer`s or `editor`s may read
+fn role_gated_cell() -> Cell {
+    let mut gated = config(item_schema(vec![]));
+    gated.permissions.read = vec![AccessLevel::Role("viewer".to_string()), AccessLevel::Role("editor".to_string())];
+    gated.permissions.write = vec![AccessLevel::Role("editor".to_string())];
+    Cell::new(gated)
+}
+
+fn set_role(cell: &Cell, method: &str, principal: Principal, role: &str) -> Result<(), CellError> {
+    let (result,): (Result<(), CellError>,) = cell.update(controller(), method, (principal, role.to_string()));
+    result
+}
+
+fn insert_as(cell: &Cell, sender: Principal, record: serde_json::Value) -> Result<String, CellError> {
+    let (result,): (Result<String, CellError>,) = cell.update(
+        sender, "insert", (record.to_string(), None::<u64>, None::<Precondition>, None::<String>),
+    );
+    result
+}
+
+fn read_as(cell: &Cell, sender: Principal, record_id: &str) -> Result<Option<String>, pocket_ic::CallError> {
+    pocket_ic::query_candid_as::<_, (Option<String>,)>(&cell.pic, cell.id, sender, "get", (record_id.to_string(),))
+        .map(|(record,)| record)
+}
+
+#[test]
+fn a_granted_role_gives_access_until_it_is_revoked() {
+    let cell = role_gated_cell();
+    assert_eq!(insert_as(&cell, user(), item("alpha", "a", 1)), Err(CellError::PermissionDenied));
+
+    set_role(&cell, "grant_role", user(), "editor").unwrap();
+    let record_id = insert_as(&cell, user(), item("alpha", "a", 1)).unwrap();
+    assert!(read_as(&cell, user(), &record_id).unwrap().is_some());
+
+    set_role(&cell, "revoke_role", user(), "editor").unwrap();
+    assert_eq!(insert_as(&cell, user(), item("beta", "a", 2)), Err(CellError::PermissionDenied));
+    assert!(read_as(&cell, user(), &record_id).is_err());
+}
+
+#[test]
+fn roles_match_only_the_access_levels_naming_them() {
+    let cell = role_gated_cell();
+    set_role(&cell, "grant_role", user(), "editor").unwrap();
+    let record_id = insert_as(&cell, user(), item("alpha", "a", 1)).unwrap();
+
+    set_role(&cell, "grant_role", other_user(), "viewer").unwrap();
+    let record = read_as(&cell, other_user(), &record_id).unwrap().unwrap();
+    assert_eq!(parse(&record)["name"], json!("alpha"));
+    assert_eq!(insert_as(&cell, other_user(), item("beta", "a", 2)), Err(CellError::PermissionDenied));
+}
+
+#[test]
+fn granted_roles_survive_upgrades() {
+    let cell = role_gated_cell();
+    set_role(&cell, "grant_role", user(), "editor").unwrap();
+
+    cell.upgrade();
+    assert!(insert_as(&cell, user(), item("alpha", "a", 1)).is_ok());
+}
+
+#[test]
+fn only_admins_grant_and_revoke_roles() {
+    let cell = role_gated_cell();
+
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "grant_role", (user(), "editor".to_string()));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+    let (result,): (Result<(), CellError>,) = cell.update(user(), "revoke_role", (other_user(), "viewer".to_string()));
+    assert_eq!(result, Err(CellError::PermissionDenied));
+    assert_eq!(insert_as(&cell, user(), item("alpha", "a", 1)), Err(CellError::PermissionDenied));
+}
+
+#[test]
+fn admins_can_replace_the_permissions() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let record_id = insert_as(&cell, user(), item("alpha", "a", 1)).unwrap();
+
+    let viewers_only = PermissionConfig {
+        read: vec![AccessLevel::Role("viewer".to_string())],
+        write: vec![],
+        admin: vec![],
+    };
+    let (denied,): (Result<(), CellError>,) = cell.update(user(), "set_permissions", (viewers_only.clone(),));
+    assert_eq!(denied, Err(CellError::PermissionDenied));
+
+    let (result,): (Result<(), CellError>,) = cell.update(controller(), "set_permissions", (viewers_only,));
+    result.unwrap();
+    assert_eq!(insert_as(&cell, user(), item("beta", "a", 2)), Err(CellError::PermissionDenied));
+    assert!(read_as(&cell, user(), &record_id).is_err());
+
+    set_role(&cell, "grant_role", user(), "viewer").unwrap();
+    assert!(read_as(&cell, user(), &record_id).unwrap().is_some());
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn timestamped_cell() -> Cell {
+    Cell::new(config(schema(vec![
+        ("name", required(FieldType::Text)),
+        ("created_at", FieldDefinition { auto_timestamp: Some(AutoTimestamp::OnCreate), ..field(FieldType::Timestamp) }),
+        ("updated_at", FieldDefinition { auto_timestamp: Some(AutoTimestamp::OnUpdate), ..field(FieldType::Timestamp) }),
+    ], vec![])))
+}
+
+fn timestamps(cell: &Cell, id: &str) -> (u64, u64) {
+    let record = cell.get(id).expect("record missing");
+    (record["created_at"].as_u64().unwrap(), record["updated_at"].as_u64().unwrap())
+}
+
+fn update(cell: &Cell, id: &str, record: serde_json::Value) {
+    let (result,): (Result<(), CellError>,) =
+        cell.update(user(), "update", (id.to_string(), record.to_string(), None::<Precondition>));
+    result.expect("update failed");
+}
+
+#[test]
+fn created_at_is_stable_across_updates_while_updated_at_changes() {
+    let cell = timestamped_cell();
+    let before = cell.now();
+    let id = cell.insert(json!({"name": "a"}));
+
+    let (created, updated) = timestamps(&cell, &id);
+    assert!(created >= before && created <= cell.now());
+    assert_eq!(created, updated);
+
+    cell.pic.advance_time(std::time::Duration::from_secs(5));
+    update(&cell, &id, json!({"name": "b"}));
+
+    let (created_after, updated_after) = timestamps(&cell, &id);
+    assert_eq!(created_after, created);
+    assert!(updated_after >= updated + 5_000_000_000);
+}
+
+#[test]
+fn client_supplied_timestamps_are_ignored() {
+    let cell = timestamped_cell();
+    let id = cell.insert(json!({"name": "a", "created_at": 1, "updated_at": 1}));
+
+    let (created, updated) = timestamps(&cell, &id);
+    assert!(created > 1 && updated > 1);
+
+    update(&cell, &id, json!({"name": "b", "created_at": 2, "updated_at": 2}));
+    let (created_after, updated_after) = timestamps(&cell, &id);
+    assert_eq!(created_after, created);
+    assert!(updated_after >= updated && updated_after != 2);
+}
+
+fn changed_since(since: u64) -> QueryFilter {
+    QueryFilter { since: Some(since), ..filter(vec![]) }
+}
+
+#[test]
+fn a_recent_since_returns_only_records_changed_after_it() {
+    let cell = timestamped_cell();
+    let kept = cell.insert(json!({"name": "a"}));
+    let edited = cell.insert(json!({"name": "b"}));
+
+    cell.pic.advance_time(std::time::Duration::from_secs(5));
+    let since = cell.now();
+    let unchanged = cell.run_query(changed_since(since), page(10)).unwrap();
+    assert!(unchanged.records.is_empty());
+    assert!(unchanged.not_modified);
+
+    cell.pic.advance_time(std::time::Duration::from_secs(5));
+    update(&cell, &edited, json!({"name": "b2"}));
+    cell.insert(json!({"name": "c"}));
+
+    assert_eq!(cell.names(changed_since(since)), ["b2", "c"]);
+    assert!(!cell.run_query(changed_since(since), page(10)).unwrap().not_modified);
+    assert!(cell.get(&kept).is_some());
+    assert_eq!(cell.names(filter(vec![])), ["a", "b2", "c"]);
+}
+
+#[test]
+fn since_requires_an_updated_at_field() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    cell.insert(item("alpha", "a", 1));
+
+    let result = cell.run_query(changed_since(0), page(10));
+    assert!(matches!(result, Err(CellError::ValidationError(_))));
+}
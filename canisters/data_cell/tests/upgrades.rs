@@ -0,0 +1,28 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn schema_persists_across_upgrade() {
+    let schema = item_schema(vec![index("by_category", &["category"])]);
+    let cell = Cell::new(config(schema.clone()));
+    cell.insert(item("a", "tools", 1));
+
+    cell.upgrade();
+
+    let (persisted,): (SchemaDefinition,) = cell.query(user(), "get_schema", ());
+    let mut fields = persisted.fields.clone();
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected_fields = schema.fields.clone();
+    expected_fields.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(fields, expected_fields);
+    assert_eq!(persisted.indexes, schema.indexes);
+    assert_eq!(persisted.version, schema.version);
+
+    // The restored schema is still enforced and its index still serves queries
+    assert!(matches!(cell.try_insert(json!({"category": "tools"})), Err(CellError::ValidationError(_))));
+    let tools = filter(vec![condition("category", ComparisonOperator::Equals, json!("tools"))]);
+    assert_eq!(cell.estimate(tools.clone()).index_used.as_deref(), Some("by_category"));
+    assert_eq!(cell.names(tools), ["a"]);
+}
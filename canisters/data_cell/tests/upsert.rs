@@ -0,0 +1,51 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn upsert(cell: &Cell, record_id: &str, record: serde_json::Value) -> Result<UpsertResult, CellError> {
+    let (result,): (Result<UpsertResult, CellError>,) =
+        cell.update(user(), "upsert", (record_id.to_string(), record.to_string(), None::<String>));
+    result
+}
+
+fn names_in(cell: &Cell, category: &str) -> Vec<serde_json::Value> {
+    cell.query_field(filter(vec![condition("category", ComparisonOperator::Equals, json!(category))]), "name")
+}
+
+#[test]
+fn upsert_inserts_absent_records_under_the_given_id() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+
+    assert_eq!(upsert(&cell, "alpha", item("alpha", "a", 1)), Ok(UpsertResult::Inserted));
+    assert_eq!(cell.get("alpha").unwrap()["score"], json!(1));
+    assert_eq!(names_in(&cell, "a"), vec![json!("alpha")]);
+    assert_eq!(cell.health().record_count, 1);
+}
+
+#[test]
+fn upsert_replaces_present_records_and_reindexes_them() {
+    let cell = Cell::new(config(item_schema(vec![index("by_category", &["category"])])));
+    upsert(&cell, "alpha", item("alpha", "a", 1)).unwrap();
+
+    assert_eq!(upsert(&cell, "alpha", json!({"name": "alpha", "category": "b"})), Ok(UpsertResult::Updated));
+    let record = cell.get("alpha").unwrap();
+    assert_eq!(record["category"], json!("b"));
+    assert_eq!(record.get("score"), None);
+
+    assert!(names_in(&cell, "a").is_empty());
+    assert_eq!(names_in(&cell, "b"), vec![json!("alpha")]);
+    assert_eq!(cell.health().record_count, 1);
+}
+
+#[test]
+fn upsert_validates_both_paths() {
+    let cell = Cell::new(config(item_schema(vec![])));
+
+    assert!(matches!(upsert(&cell, "alpha", json!({"category": "a"})), Err(CellError::ValidationError(_))));
+    assert_eq!(cell.get("alpha"), None);
+
+    upsert(&cell, "alpha", item("alpha", "a", 1)).unwrap();
+    assert!(matches!(upsert(&cell, "alpha", json!({"category": "b"})), Err(CellError::ValidationError(_))));
+    assert_eq!(cell.get("alpha").unwrap()["category"], json!("a"));
+}
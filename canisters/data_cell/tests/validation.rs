@@ -0,0 +1,150 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// People with a required `name` of at least 3 characters, an `email`, a
+/// `status` from a fixed set and a numeric `age`
+fn people_schema() -> SchemaDefinition {
+    schema(vec![
+        ("name", FieldDefinition { validation_rules: vec![ValidationRule::MinLength(3)], ..required(FieldType::Text) }),
+        ("email", FieldDefinition { validation_rules: vec![ValidationRule::Email], ..field(FieldType::Text) }),
+        ("status", FieldDefinition {
+            validation_rules: vec![ValidationRule::OneOf(vec![json!("active").to_string(), json!("retired").to_string()])],
+            ..field(FieldType::Text)
+        }),
+        ("age", field(FieldType::Number)),
+    ], vec![])
+}
+
+#[test]
+fn validate_reports_every_violation_at_once() {
+    let cell = Cell::new(config(people_schema()));
+
+    let errors = cell.validate(json!({"email": "nope", "status": "lapsed", "age": "old"})).unwrap_err();
+    assert_eq!(errors.len(), 4, "{:?}", errors);
+    assert!(matches!(&errors[0], ValidationError::TypeMismatch(msg) if msg.starts_with("age")));
+    assert!(matches!(&errors[1], ValidationError::ValidationFailed(msg) if msg.starts_with("email")));
+    assert_eq!(errors[2], ValidationError::MissingRequiredField("name".to_string()));
+    assert!(matches!(&errors[3], ValidationError::ValidationFailed(msg) if msg.starts_with("status")));
+}
+
+#[test]
+fn validate_stores_nothing() {
+    let cell = Cell::new(config(people_schema()));
+
+    cell.validate(json!({"name": "Ada", "email": "ada@example.com", "status": "active", "age": 36})).unwrap();
+    assert!(cell.validate(json!({"name": "Al"})).is_err());
+    assert_eq!(cell.health().record_count, 0);
+}
+
+#[test]
+fn validate_requires_write_access() {
+    let mut restricted = config(people_schema());
+    restricted.permissions.write = vec![AccessLevel::Principal(user())];
+    let cell = Cell::new(restricted);
+
+    cell.validate(json!({"name": "Ada"})).unwrap();
+    let denied: Result<(Result<(), Vec<ValidationError>>,), _> = pocket_ic::query_candid_as(
+        &cell.pic, cell.id, other_user(), "validate", (json!({"name": "Ada"}).to_string(),),
+    );
+    assert!(denied.is_err());
+}
+
+#[test]
+fn insert_reports_all_violations_in_one_error() {
+    let cell = Cell::new(config(people_schema()));
+
+    let error = cell.try_insert(json!({"email": "nope", "age": "old"})).unwrap_err();
+    let message = match error {
+        CellError::ValidationError(message) => message,
+        other => panic!("unexpected error: {:?}", other),
+    };
+    assert_eq!(message.split("; ").count(), 3, "{}", message);
+    assert!(message.contains("age") && message.contains("email") && message.contains("name"));
+}
+
+#[test]
+fn fail_fast_cells_report_only_the_first_violation() {
+    let mut fail_fast = config(people_schema());
+    fail_fast.fail_fast_validation = Some(true);
+    let cell = Cell::new(fail_fast);
+
+    let error = cell.try_insert(json!({"email": "nope", "age": "old"})).unwrap_err();
+    assert!(matches!(&error, CellError::ValidationError(message) if !message.contains("; ")), "{:?}", error);
+
+    // The validate endpoint always collects everything
+    assert_eq!(cell.validate(json!({"email": "nope", "age": "old"})).unwrap_err().len(), 3);
+}
+
+#[test]
+fn one_of_rejects_values_outside_the_set() {
+    let cell = Cell::new(config(people_schema()));
+
+    cell.insert(json!({"name": "Ada", "status": "retired"}));
+    let error = cell.try_insert(json!({"name": "Bob", "status": "lapsed"})).unwrap_err();
+    assert!(
+        matches!(&error, CellError::ValidationError(message) if message.contains("[\"active\", \"retired\"]")),
+        "{:?}", error,
+    );
+}
+
+#[test]
+fn coercing_cells_store_converted_values() {
+    let mut coercing = config(item_schema(vec![]));
+    coercing.coerce_types = Some(true);
+    let cell = Cell::new(coercing);
+
+    let id = cell.insert(json!({"name": "a", "score": "42"}));
+    assert_eq!(cell.get(&id).unwrap()["score"], json!(42));
+
+    let error = cell.try_insert(json!({"name": "b", "score": "lots"})).unwrap_err();
+    assert!(matches!(&error, CellError::ValidationError(message) if message.contains("Type mismatch: score")),
+            "{:?}", error);
+
+    // Without coercion the string is a type mismatch
+    let strict = cell.sibling(config(item_schema(vec![])));
+    assert!(strict.try_insert(json!({"name": "a", "score": "42"})).is_err());
+}
+
+/// Products with an upper-case `sku` and a `rating` from 1 to 5
+fn product_schema(sku_pattern: &str, rating: (i64, i64)) -> SchemaDefinition {
+    schema(vec![
+        ("sku", FieldDefinition {
+            validation_rules: vec![ValidationRule::Pattern(sku_pattern.to_string())],
+            ..required(FieldType::Text)
+        }),
+        ("rating", FieldDefinition {
+            validation_rules: vec![ValidationRule::Range(rating.0, rating.1)],
+            ..field(FieldType::Number)
+        }),
+    ], vec![])
+}
+
+#[test]
+fn pattern_and_range_rules_are_enforced() {
+    let cell = Cell::new(config(product_schema("^[A-Z]+-[0-9]+$", (1, 5))));
+
+    cell.validate(json!({"sku": "AB-12", "rating": 5})).unwrap();
+    cell.validate(json!({"sku": "AB-12", "rating": 1.5})).unwrap();
+
+    let errors = cell.validate(json!({"sku": "ab-12", "rating": 6})).unwrap_err();
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(matches!(&errors[0], ValidationError::ValidationFailed(msg) if msg.starts_with("rating")));
+    assert!(matches!(&errors[1], ValidationError::ValidationFailed(msg) if msg.starts_with("sku")));
+
+    assert!(cell.validate(json!({"sku": "AB-12", "rating": 0.5})).is_err());
+    assert!(cell.validate(json!({"sku": "AB-12", "rating": u64::MAX})).is_err());
+}
+
+#[test]
+fn schemas_with_invalid_rules_are_rejected_at_install() {
+    let cell = Cell::new(config(product_schema("^[A-Z]+$", (1, 5))));
+
+    for invalid in [product_schema("^[A-Z+$", (1, 5)), product_schema("^[A-Z]+$", (5, 1))] {
+        let installed = cell.pic.reinstall_canister(
+            cell.id, cell_wasm(), candid::encode_one(config(invalid)).unwrap(), Some(controller()),
+        );
+        assert!(installed.is_err());
+    }
+}
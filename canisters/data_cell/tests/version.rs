@@ -0,0 +1,13 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn version_names_the_build_and_schema() {
+    let cell = Cell::new(config(item_schema(vec![])));
+    let (version,): (CanisterVersion,) = cell.query(user(), "version", ());
+
+    assert_eq!(version.name, "celldb");
+    assert!(version.build_version.starts_with(env!("CARGO_PKG_VERSION")));
+    assert_eq!(version.schema_version, Some(1));
+}
@@ -0,0 +1,85 @@
+//! Machine-readable description of the cells and queries the aggregator serves
+
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::coordination::Coordination;
+use crate::{CellDescription, QueryType, ServiceDescription};
+
+thread_local! {
+    /// Description built after the last registry change; `None` until first needed
+    static DESCRIPTION: RefCell<Option<ServiceDescription>> = RefCell::new(None);
+
+    /// JSON Schema of each cell, fetched from its `json_schema` endpoint on registration
+    static CELL_SCHEMAS: RefCell<HashMap<Principal, String>> = RefCell::new(HashMap::new());
+}
+
+pub struct Catalog;
+
+impl Catalog {
+    /// Fetch a newly registered cell's schema and rebuild the description
+    ///
+    /// A cell that can't describe its schema is still listed, without one.
+    pub async fn refresh_cell(cell_id: Principal) {
+        match ic_cdk::call::<_, (String,)>(cell_id, "json_schema", ()).await {
+            Ok((schema,)) => {
+                CELL_SCHEMAS.with(|schemas| schemas.borrow_mut().insert(cell_id, schema));
+            },
+            Err((code, msg)) => {
+                ic_cdk::println!("Failed to fetch schema of cell {}: {:?} - {}", cell_id, code, msg);
+                CELL_SCHEMAS.with(|schemas| schemas.borrow_mut().remove(&cell_id));
+            },
+        }
+        Self::rebuild();
+    }
+
+    /// Refetch every registered cell's schema, e.g. after an upgrade emptied the cache
+    pub async fn refresh_all() {
+        for registration in Coordination::registrations() {
+            Self::refresh_cell(registration.cell_id).await;
+        }
+    }
+
+    /// Rebuild the description after cells left the registry
+    pub fn rebuild() {
+        let description = Self::build();
+        DESCRIPTION.with(|cached| *cached.borrow_mut() = Some(description));
+    }
+
+    /// Cached description, built on the spot if the cache is empty
+    pub fn describe() -> ServiceDescription {
+        DESCRIPTION.with(|cached| cached.borrow().clone())
+            .unwrap_or_else(Self::build)
+    }
+
+    fn build() -> ServiceDescription {
+        let registrations = Coordination::registrations();
+
+        CELL_SCHEMAS.with(|schemas| {
+            let mut schemas = schemas.borrow_mut();
+            schemas.retain(|cell_id, _| registrations.iter().any(|registration| registration.cell_id == *cell_id));
+
+            ServiceDescription {
+                cells: registrations.into_iter()
+                    .map(|registration| CellDescription {
+                        json_schema: schemas.get(&registration.cell_id).cloned(),
+                        cell_id: registration.cell_id,
+                        name: registration.name,
+                        group: registration.group,
+                        schema_version: registration.schema_version,
+                        capabilities: registration.capabilities,
+                        replica_of: registration.replica_of,
+                    })
+                    .collect(),
+                query_types: vec![
+                    QueryType::SingleCell,
+                    QueryType::CrossCell,
+                    QueryType::Aggregation,
+                    QueryType::Join,
+                    QueryType::Search,
+                ],
+                generated_at: ic_cdk::api::time(),
+            }
+        })
+    }
+}
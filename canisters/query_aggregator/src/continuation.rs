@@ -0,0 +1,123 @@
+//! Continuation state for paging large batch query results
+
+use candid::Principal;
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use std::cell::{Cell, RefCell};
+use crate::BatchQueryResult;
+use crate::coordination::{impl_storable, memory, Memory};
+
+/// Records returned per page when the query doesn't set `page_size`
+pub const DEFAULT_PAGE_SIZE: u64 = 500;
+
+/// Lifetime of an unused continuation token
+const CONTINUATION_TTL_NANOS: u64 = 10 * 60 * 1_000_000_000;
+
+type ContinuationStorage = StableBTreeMap<String, ContinuationState, Memory>;
+
+thread_local! {
+    static NEXT_TOKEN: Cell<u64> = Cell::new(0);
+
+    static CONTINUATIONS: RefCell<ContinuationStorage> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(8)))
+    );
+}
+
+/// Merged, deduplicated and sorted records not yet returned to the caller
+#[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct ContinuationState {
+    pub owner: Principal,
+    pub result: BatchQueryResult,
+    pub page_size: u64,
+    pub expires_at: u64,
+}
+
+impl_storable!(ContinuationState);
+
+pub struct Continuations;
+
+impl Continuations {
+    /// Split off the first page of a merged result, keeping the rest for `resume`
+    ///
+    /// Records are merged and sorted once, so later pages continue the same
+    /// global order without duplicates or gaps.
+    pub fn first_page(owner: Principal, mut result: BatchQueryResult, page_size: u64) -> BatchQueryResult {
+        Self::purge_expired();
+
+        let page_size = page_size.max(1);
+        if result.records.len() as u64 <= page_size {
+            result.continuation_token = None;
+            return result;
+        }
+
+        let remaining = result.records.split_off(page_size as usize);
+        let token = Self::generate_token(&result.query_id);
+
+        let mut state = result.clone();
+        state.records = remaining;
+        state.continuation_token = None;
+
+        CONTINUATIONS.with(|continuations| {
+            continuations.borrow_mut().insert(token.clone(), ContinuationState {
+                owner,
+                result: state,
+                page_size,
+                expires_at: ic_cdk::api::time() + CONTINUATION_TTL_NANOS,
+            });
+        });
+
+        result.continuation_token = Some(token);
+        result
+    }
+
+    /// Return the next page for a continuation token issued to `caller`
+    pub fn resume(caller: Principal, token: &str) -> Result<BatchQueryResult, Box<dyn std::error::Error>> {
+        let state = CONTINUATIONS.with(|continuations| {
+            continuations.borrow_mut().remove(&token.to_string())
+        }).ok_or("Continuation token not found or expired")?;
+
+        if state.expires_at < ic_cdk::api::time() {
+            return Err("Continuation token not found or expired".into());
+        }
+
+        if state.owner != caller {
+            return Err("Continuation token was issued to a different caller".into());
+        }
+
+        Ok(Self::first_page(caller, state.result, state.page_size))
+    }
+
+    /// Drop continuation state whose token has expired
+    fn purge_expired() {
+        let now = ic_cdk::api::time();
+
+        CONTINUATIONS.with(|continuations| {
+            let mut continuations_ref = continuations.borrow_mut();
+            let expired: Vec<String> = continuations_ref.iter()
+                .filter(|(_, state)| state.expires_at < now)
+                .map(|(token, _)| token)
+                .collect();
+
+            for token in expired {
+                continuations_ref.remove(&token);
+            }
+        });
+    }
+
+    /// Generate a token unique even among calls within the same round
+    fn generate_token(query_id: &str) -> String {
+        let sequence = NEXT_TOKEN.with(|next| {
+            let value = next.get();
+            next.set(value + 1);
+            value
+        });
+        format!("{}_{}_{}", query_id, ic_cdk::api::time(), sequence)
+    }
+
+    pub fn pre_upgrade() {
+        // Stable structures handle persistence automatically
+    }
+
+    pub fn post_upgrade() {
+        // Stable structures handle restoration automatically
+    }
+}
@@ -1,16 +1,94 @@
 //! Multi-cell coordination and intelligent query distribution
 
-use candid::Principal;
-use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, RestrictedMemory, memory_manager::{MemoryManager, MemoryId}};
-use std::cell::RefCell;
-use std::collections::{HashMap, BTreeSet};
-use crate::{BatchQuery, BatchQueryResult, CellRegistration, CellExecutionStats};
+use candid::{CandidType, Principal};
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use ic_cdk::api::call::RejectionCode;
+use ic_stable_structures::{StableBTreeMap, StableCell, DefaultMemoryImpl, memory_manager::{MemoryManager, MemoryId, VirtualMemory}};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, BTreeMap, BTreeSet};
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use crate::traces::Traces;
+use crate::{BatchQuery, BatchQueryResult, CellCapability, CellRegistration, CellExecutionStats, ConsistencyLevel, FilterOperator, Pagination, QueryError, QueryPriority};
+
+/// Records requested from each cell when a batch query sets no `max_results`
+const DEFAULT_CELL_RESULT_LIMIT: u64 = 1_000;
+
+/// Maximum distinct values requested from each cell when merging across cells
+const MAX_DISTINCT_VALUES_PER_CELL: u64 = 1_000;
+
+/// Consecutive failed calls after which a cell's circuit opens
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Time an open circuit rejects calls before letting a probe through
+const CIRCUIT_COOLDOWN_NANOS: u64 = 30 * 1_000_000_000;
+
+/// Coordinated queries allowed to run at once; further queries wait in the queue
+const MAX_CONCURRENT_QUERIES: u32 = 8;
+
+/// Queries allowed to wait for a slot before new ones are rejected
+const MAX_QUEUED_QUERIES: usize = 64;
+
+/// Cycles charged for each inter-canister call attempt
+const CELL_CALL_BASE_CYCLES: u64 = 260_000;
+
+/// Cycles charged per byte of a cell's reply
+const CELL_CALL_BYTE_CYCLES: u64 = 1_000;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// Implement `Storable` for types kept in stable structures, encoded as CBOR
+///
+/// CBOR is self-describing, so `#[serde(default)]` fields added later decode
+/// from values written before they existed.
+macro_rules! impl_storable {
+    ($($ty:ty),+ $(,)?) => {$(
+        impl ic_stable_structures::Storable for $ty {
+            fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(self, &mut bytes)
+                    .expect(concat!("Failed to encode ", stringify!($ty)));
+                std::borrow::Cow::Owned(bytes)
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                ciborium::de::from_reader(bytes.as_ref())
+                    .expect(concat!("Failed to decode ", stringify!($ty)))
+            }
+
+            const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+        }
+    )+};
+}
+pub(crate) use impl_storable;
 
-type Memory = RestrictedMemory<DefaultMemoryImpl>;
 type CellRegistry = StableBTreeMap<Principal, CellRegistration, Memory>;
 type AuthorizedManagers = StableBTreeMap<Principal, bool, Memory>;
 
 thread_local! {
+    /// Rotates reads of each primary across it and its replicas
+    static READ_ROTATION: RefCell<HashMap<Principal, usize>> = RefCell::new(HashMap::new());
+
+    /// Circuit breakers are deliberately kept on the heap: an upgrade gives
+    /// every cell a fresh chance.
+    static CIRCUIT_BREAKERS: RefCell<HashMap<Principal, CircuitBreaker>> = RefCell::new(HashMap::new());
+
+    /// Coordinated queries currently holding a slot
+    static RUNNING_QUERIES: Cell<u32> = Cell::new(0);
+
+    /// Queries waiting for a slot, keyed by priority rank then arrival order
+    static QUERY_QUEUE: RefCell<BTreeMap<(u8, u64), oneshot::Sender<()>>> = RefCell::new(BTreeMap::new());
+
+    static NEXT_QUEUE_TICKET: Cell<u64> = Cell::new(0);
+
+    /// Queries turned away because the queue was full, kept across upgrades
+    static REJECTED_QUERIES: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))),
+            0,
+        ).expect("Failed to initialize rejected query counter")
+    );
+
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
 
@@ -25,21 +103,47 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
         )
     );
+
+    static RETRY_POLICY: RefCell<StableCell<RetryPolicy, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))),
+            RetryPolicy::default(),
+        ).expect("Failed to initialize retry policy")
+    );
+}
+
+/// Get a virtual memory region from the aggregator's memory manager
+///
+/// Every stable structure in the aggregator must claim a distinct `MemoryId`.
+pub fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
 }
 
 pub struct Coordination;
 
 impl Coordination {
-    /// Initialize coordination layer with registered cells
-    pub fn init(cells: &[CellRegistration]) {
+    /// Initialize coordination layer with registered cells, authorized managers and the cell call retry policy
+    pub fn init(cells: &[CellRegistration], managers: &[Principal], retry_policy: Option<RetryPolicy>) {
         ic_cdk::println!("Initializing coordination layer with {} cells", cells.len());
 
+        RETRY_POLICY.with(|cell| {
+            cell.borrow_mut().set(retry_policy.unwrap_or_default())
+                .expect("Failed to persist retry policy");
+        });
+
         REGISTERED_CELLS.with(|registry| {
             let mut registry_ref = registry.borrow_mut();
             for cell in cells {
                 registry_ref.insert(cell.cell_id, cell.clone());
             }
         });
+
+        AUTHORIZED_MANAGERS.with(|authorized| {
+            let mut authorized_ref = authorized.borrow_mut();
+            for manager in managers {
+                authorized_ref.insert(*manager, true);
+            }
+        });
     }
 
     /// Validate caller has access to specified cells
@@ -65,24 +169,111 @@ impl Coordination {
         true // Placeholder - implement actual permission validation
     }
 
+    /// Every registration in the registry
+    pub fn registrations() -> Vec<CellRegistration> {
+        REGISTERED_CELLS.with(|registry| {
+            registry.borrow().iter().map(|(_, registration)| registration).collect()
+        })
+    }
+
+    /// Whether a cell, primary or replica, is in the registry
+    pub fn is_registered(cell_id: Principal) -> bool {
+        REGISTERED_CELLS.with(|registry| registry.borrow().contains_key(&cell_id))
+    }
+
+    /// Registered primary cells having every one of `required`
+    ///
+    /// Replicas are left out; reads of their primary are routed to them anyway.
+    pub fn cells_with_capabilities(required: &[CellCapability]) -> Vec<Principal> {
+        REGISTERED_CELLS.with(|registry| {
+            registry.borrow().iter()
+                .filter(|(_, cell)| cell.replica_of.is_none())
+                .filter(|(_, cell)| required.iter().all(|capability| cell.capabilities.contains(capability)))
+                .map(|(cell_id, _)| cell_id)
+                .collect()
+        })
+    }
+
+    /// Cells registered in `group`, optionally including replicas
+    pub fn cells_in_group(group: &str, include_replicas: bool) -> Vec<Principal> {
+        REGISTERED_CELLS.with(|registry| {
+            registry.borrow().iter()
+                .filter(|(_, cell)| cell.group.as_deref() == Some(group))
+                .filter(|(_, cell)| include_replicas || cell.replica_of.is_none())
+                .map(|(cell_id, _)| cell_id)
+                .collect()
+        })
+    }
+
+    /// Distinct group names in the registry, sorted
+    pub fn list_groups() -> Vec<String> {
+        REGISTERED_CELLS.with(|registry| {
+            registry.borrow().iter()
+                .filter_map(|(_, cell)| cell.group)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        })
+    }
+
+    /// Registered schema version of every cell
+    pub fn schema_versions() -> HashMap<Principal, u32> {
+        REGISTERED_CELLS.with(|registry| {
+            registry.borrow().iter()
+                .map(|(cell_id, cell)| (cell_id, cell.schema_version))
+                .collect()
+        })
+    }
+
+    /// Require every registered cell in `cell_ids` to be on the same schema version
+    pub fn check_schema_versions(cell_ids: &[Principal]) -> Result<(), QueryError> {
+        let versions: BTreeMap<u32, Vec<Principal>> = REGISTERED_CELLS.with(|registry| {
+            let registry = registry.borrow();
+            let mut versions = BTreeMap::<u32, Vec<Principal>>::new();
+            for cell_id in cell_ids {
+                if let Some(cell) = registry.get(cell_id) {
+                    versions.entry(cell.schema_version).or_default().push(*cell_id);
+                }
+            }
+            versions
+        });
+
+        if versions.len() <= 1 {
+            return Ok(());
+        }
+
+        let summary = versions.iter()
+            .map(|(version, cells)| format!(
+                "v{}: {}", version, cells.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+            ))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(QueryError::SchemaMismatch(format!("Target cells are on different schema versions ({})", summary)))
+    }
+
     /// Execute coordinated query across multiple cells
-    pub async fn execute_coordinated_query(caller: Principal, query: BatchQuery) -> Result<BatchQueryResult, Box<dyn std::error::Error>> {
+    ///
+    /// Planning and every cell call are recorded as spans of `trace_id`.
+    pub async fn execute_coordinated_query(caller: Principal, query: BatchQuery, trace_id: &str) -> Result<BatchQueryResult, Box<dyn std::error::Error>> {
         ic_cdk::println!("Executing coordinated query across {} cells", query.target_cells.len());
 
+        let _slot = Self::acquire_query_slot(query.options.priority.clone().unwrap_or_default()).await?;
+
         let query_id = Self::generate_query_id();
         let start_time = ic_cdk::api::time();
 
         // Analyze query for optimal execution strategy
         let execution_plan = Self::create_execution_plan(&query).await?;
-        ic_cdk::println!("Created execution plan: {:?}", execution_plan.strategy);
+        ic_cdk::println!("[{}] Created execution plan: {:?}", trace_id, execution_plan.strategy);
+        Traces::record(trace_id, "plan", None, start_time, 0, None);
 
         // Execute query with intelligent coordination
         let results = match execution_plan.strategy {
             ExecutionStrategy::Parallel => {
-                Self::execute_parallel_query(&query, &execution_plan).await?
+                Self::execute_parallel_query(&query, &execution_plan, trace_id).await?
             },
             ExecutionStrategy::Sequential => {
-                Self::execute_sequential_query(&query, &execution_plan).await?
+                Self::execute_sequential_query(&query, &execution_plan, trace_id).await?
             },
             ExecutionStrategy::Streaming => {
                 Self::execute_streaming_query(&query, &execution_plan).await?
@@ -97,9 +288,80 @@ impl Coordination {
             records: results.records,
             total_count: results.total_count,
             cell_statistics: results.cell_stats,
+            cell_errors: results.cell_errors,
+            continuation_token: None,
+            trace_id: Some(trace_id.to_string()),
         })
     }
 
+    /// Wait for one of the `MAX_CONCURRENT_QUERIES` slots
+    ///
+    /// When the aggregator is saturated the query is queued; queued queries
+    /// are admitted highest priority first, in arrival order within a
+    /// priority. Fails with `ResourceExhausted` when the queue is full.
+    async fn acquire_query_slot(priority: QueryPriority) -> Result<QuerySlot, QueryError> {
+        let queue_empty = QUERY_QUEUE.with(|queue| queue.borrow().is_empty());
+        if queue_empty && Self::in_flight_queries() < MAX_CONCURRENT_QUERIES {
+            RUNNING_QUERIES.with(|running| running.set(running.get() + 1));
+            return Ok(QuerySlot);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        QUERY_QUEUE.with(|queue| {
+            let mut queue = queue.borrow_mut();
+            if queue.len() >= MAX_QUEUED_QUERIES {
+                REJECTED_QUERIES.with(|rejected| {
+                    let mut rejected = rejected.borrow_mut();
+                    let count = *rejected.get() + 1;
+                    rejected.set(count).expect("Failed to persist rejected query counter");
+                });
+                return Err(QueryError::ResourceExhausted);
+            }
+            let ticket = NEXT_QUEUE_TICKET.with(|next| next.replace(next.get() + 1));
+            queue.insert((priority.rank(), ticket), sender);
+            Ok(())
+        })?;
+
+        // The releasing query hands its slot over, so the running count is unchanged
+        receiver.await.map_err(|_| QueryError::ResourceExhausted)?;
+        Ok(QuerySlot)
+    }
+
+    /// Hand a finished query's slot to the next queued query, or free it
+    fn release_query_slot() {
+        loop {
+            let next = QUERY_QUEUE.with(|queue| queue.borrow_mut().pop_first());
+            match next {
+                // A waiter whose call has gone away can't take the slot; try the next one
+                Some((_, sender)) => if sender.send(()).is_ok() {
+                    return;
+                },
+                None => {
+                    RUNNING_QUERIES.with(|running| running.set(running.get().saturating_sub(1)));
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Queries waiting for a slot
+    pub fn queue_depth() -> u32 {
+        QUERY_QUEUE.with(|queue| queue.borrow().len() as u32)
+    }
+
+    /// Queries holding a slot
+    ///
+    /// Not persisted: an upgrade only happens once the canister has stopped,
+    /// with no query in flight.
+    pub fn in_flight_queries() -> u32 {
+        RUNNING_QUERIES.with(Cell::get)
+    }
+
+    /// Queries rejected because the queue was full, since installation
+    pub fn rejected_queries() -> u64 {
+        REJECTED_QUERIES.with(|rejected| *rejected.borrow().get())
+    }
+
     /// Create optimal execution plan based on query characteristics
     async fn create_execution_plan(query: &BatchQuery) -> Result<ExecutionPlan, Box<dyn std::error::Error>> {
         // Analyze query complexity and cell characteristics
@@ -115,6 +377,8 @@ impl Coordination {
         };
 
         Ok(ExecutionPlan {
+            deadline: query.options.timeout_ms
+                .map(|timeout_ms| ic_cdk::api::time().saturating_add(timeout_ms.saturating_mul(1_000_000))),
             strategy,
             estimated_duration: Self::estimate_execution_time(cell_count, estimated_complexity),
             resource_requirements: Self::calculate_resource_needs(&strategy, cell_count),
@@ -122,79 +386,259 @@ impl Coordination {
     }
 
     /// Execute query in parallel across multiple cells
-    async fn execute_parallel_query(query: &BatchQuery, plan: &ExecutionPlan) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
+    async fn execute_parallel_query(query: &BatchQuery, plan: &ExecutionPlan, trace_id: &str) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
         ic_cdk::println!("Executing parallel query across {} cells", query.target_cells.len());
 
-        let mut cell_futures = Vec::new();
-        let mut cell_stats = HashMap::new();
+        let filter = Self::cell_filter(query, Some(trace_id));
+        let pagination = Self::cell_pagination(query);
 
-        // Launch parallel queries with intelligent load balancing
-        for cell_id in &query.target_cells {
-            let cell_start_time = ic_cdk::api::time();
+        // Launch every cell call before awaiting any of them
+        let cell_futures = query.target_cells.iter().map(|cell_id| {
+            let target = Self::read_target(*cell_id, &query.options.consistency_level);
+            Self::query_cell(target, filter.clone(), pagination.clone(), plan.deadline)
+        });
+        Self::check_cycle_budget(query, query.target_cells.len() as u64 * CELL_CALL_BASE_CYCLES)?;
+        let outcomes = futures::future::join_all(cell_futures).await;
 
-            // TODO: Make actual inter-canister call to cell
-            // let result = ic_cdk::call::<(String, HashMap<String, serde_json::Value>), (Vec<serde_json::Value>,)>
-            //     (*cell_id, "query", (query.query_sql.clone(), query.parameters.clone())).await?;
+        let consumed = outcomes.iter()
+            .map(|outcome| outcome.as_ref().map_or(CELL_CALL_BASE_CYCLES, |(_, stats)| stats.cycles_consumed))
+            .sum();
+        Self::check_cycle_budget(query, consumed)?;
 
-            // Placeholder for actual cell communication
-            let mock_records = vec![
-                serde_json::json!({"cell_id": cell_id.to_string(), "data": "mock_data"})
-            ];
+        Self::collect_results(query, query.target_cells.iter().copied().zip(outcomes))
+    }
 
-            let execution_time = (ic_cdk::api::time() - cell_start_time) / 1_000_000;
+    /// Execute query sequentially for complex operations
+    async fn execute_sequential_query(query: &BatchQuery, plan: &ExecutionPlan, trace_id: &str) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
+        ic_cdk::println!("Executing sequential query across {} cells", query.target_cells.len());
 
-            cell_stats.insert(*cell_id, CellExecutionStats {
-                response_time_ms: execution_time,
-                records_returned: mock_records.len() as u64,
-                cycles_consumed: 1_000_000, // TODO: Calculate actual cycles
-                cache_hit: false, // TODO: Implement cache tracking
-            });
+        let filter = Self::cell_filter(query, Some(trace_id));
+        let pagination = Self::cell_pagination(query);
 
-            cell_futures.extend(mock_records);
+        let mut outcomes = Vec::new();
+        let mut consumed = 0;
+        for cell_id in &query.target_cells {
+            // Stop before a call that would take the query over its budget
+            Self::check_cycle_budget(query, consumed + CELL_CALL_BASE_CYCLES)?;
+
+            let target = Self::read_target(*cell_id, &query.options.consistency_level);
+            let outcome = Self::query_cell(target, filter.clone(), pagination.clone(), plan.deadline).await;
+            consumed += outcome.as_ref().map_or(CELL_CALL_BASE_CYCLES, |(_, stats)| stats.cycles_consumed);
+            outcomes.push((*cell_id, outcome));
         }
+        Self::check_cycle_budget(query, consumed)?;
 
-        Ok(CoordinatedResults {
-            records: cell_futures,
-            total_count: cell_futures.len() as u64,
-            cell_stats,
-        })
+        Self::collect_results(query, outcomes)
     }
 
-    /// Execute query sequentially for complex operations
-    async fn execute_sequential_query(query: &BatchQuery, plan: &ExecutionPlan) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
-        ic_cdk::println!("Executing sequential query across {} cells", query.target_cells.len());
+    /// Fail with `ResourceExhausted` if `cycles` exceeds the query's `max_cycles`
+    fn check_cycle_budget(query: &BatchQuery, cycles: u64) -> Result<(), QueryError> {
+        match query.options.max_cycles {
+            Some(budget) if cycles > budget => {
+                ic_cdk::println!("Query over its cycle budget: {} > {}", cycles, budget);
+                Err(QueryError::ResourceExhausted)
+            },
+            _ => Ok(()),
+        }
+    }
 
-        let mut all_records = Vec::new();
-        let mut cell_stats = HashMap::new();
+    /// Estimated cycles spent on a cell call: a base fee per attempt plus the reply size
+    fn call_cycles(records: &[serde_json::Value], retries: u32) -> u64 {
+        let reply_bytes: u64 = records.iter()
+            .map(|record| record.to_string().len() as u64)
+            .sum();
+        (retries as u64 + 1) * CELL_CALL_BASE_CYCLES + reply_bytes * CELL_CALL_BYTE_CYCLES
+    }
 
-        // Execute queries in optimal sequence
-        for cell_id in &query.target_cells {
-            let cell_start_time = ic_cdk::api::time();
+    /// Canister to read `cell_id` from: the cell itself or one of its registered replicas
+    ///
+    /// Reads rotate across the primary and replicas whose circuits aren't
+    /// open. `Strong` reads always go to the primary, since replicas lag it.
+    fn read_target(cell_id: Principal, consistency: &ConsistencyLevel) -> Principal {
+        if matches!(consistency, ConsistencyLevel::Strong) {
+            return cell_id;
+        }
+
+        let mut candidates = vec![cell_id];
+        REGISTERED_CELLS.with(|registry| {
+            candidates.extend(registry.borrow().iter()
+                .filter(|(_, registration)| registration.replica_of == Some(cell_id))
+                .map(|(replica_id, _)| replica_id));
+        });
+        candidates.retain(|candidate| *candidate == cell_id || Self::circuit_allows(candidate));
+
+        if candidates.len() == 1 {
+            return cell_id;
+        }
 
-            // TODO: Implement actual sequential execution with result dependency handling
-            let mock_records = vec![
-                serde_json::json!({"cell_id": cell_id.to_string(), "sequence": all_records.len()})
-            ];
+        let turn = READ_ROTATION.with(|rotation| {
+            let mut rotation = rotation.borrow_mut();
+            let turn = rotation.entry(cell_id).or_insert(0);
+            let current = *turn;
+            *turn = turn.wrapping_add(1);
+            current
+        });
+        candidates[turn % candidates.len()]
+    }
 
-            let execution_time = (ic_cdk::api::time() - cell_start_time) / 1_000_000;
+    /// Query one cell, timing the call and recording a span when the filter carries a trace ID
+    async fn query_cell(
+        cell_id: Principal,
+        filter: CellQueryFilter,
+        pagination: Pagination,
+        deadline: Option<u64>,
+    ) -> Result<(Vec<serde_json::Value>, CellExecutionStats), String> {
+        Self::query_cell_page(cell_id, filter, pagination, deadline).await
+            .map(|(result, stats)| (result.records, stats))
+    }
 
-            cell_stats.insert(*cell_id, CellExecutionStats {
-                response_time_ms: execution_time,
-                records_returned: mock_records.len() as u64,
-                cycles_consumed: 800_000, // Sequential is more efficient
-                cache_hit: false,
+    /// Query one cell, keeping the cursor it returns for the next page
+    async fn query_cell_page(
+        cell_id: Principal,
+        filter: CellQueryFilter,
+        pagination: Pagination,
+        deadline: Option<u64>,
+    ) -> Result<(CellQueryResult, CellExecutionStats), String> {
+        let cell_start_time = ic_cdk::api::time();
+        let trace_id = filter.trace_id.clone();
+
+        let reply = Self::call_cell_until(cell_id, "query", (filter, pagination), deadline).await
+            .map_err(|e| e.to_string())
+            .and_then(|outcome: CellCallOutcome<(Result<CellQueryResult, CellQueryError>,)>| {
+                let retries = outcome.retries;
+                outcome.reply.0
+                    .map(|result| (result, retries))
+                    .map_err(|e| format!("Cell {} rejected query: {:?}", cell_id, e))
             });
 
-            all_records.extend(mock_records);
+        if let Some(trace_id) = &trace_id {
+            let records = reply.as_ref().map_or(0, |(result, _)| result.records.len() as u64);
+            Traces::record(trace_id, "cell_query", Some(cell_id), cell_start_time, records, reply.as_ref().err().cloned());
+        }
+
+        let (result, retries) = reply?;
+
+        let stats = CellExecutionStats {
+            response_time_ms: (ic_cdk::api::time() - cell_start_time) / 1_000_000,
+            records_returned: result.records.len() as u64,
+            cycles_consumed: Self::call_cycles(&result.records, retries),
+            cache_hit: false, // TODO: Implement cache tracking
+            retries,
+        };
+
+        Ok((result, stats))
+    }
+
+    /// Fetch a page of a cell's records for a stream, reading from the cell itself
+    ///
+    /// The page resumes after `cursor`, the previous page's `next_cursor`, so
+    /// records written or deleted between pages don't shift it; `offset` only
+    /// applies without a cursor. `sort` is the field to sort by and whether to
+    /// sort descending.
+    pub async fn stream_page(
+        cell_id: Principal,
+        cursor: Option<String>,
+        offset: u64,
+        limit: u64,
+        sort: Option<(&str, bool)>,
+    ) -> Result<StreamPage, String> {
+        let filter = CellQueryFilter {
+            conditions: Vec::new(),
+            trace_id: None,
+            sort_by: sort.map(|(field, _)| field.to_string()),
+            sort_order: match sort {
+                Some((_, true)) => CellSortOrder::Descending,
+                _ => CellSortOrder::Ascending,
+            },
+        };
+        let offset = if cursor.is_some() { 0 } else { offset };
+        let pagination = Pagination { offset, limit, cursor };
+
+        Self::query_cell_page(cell_id, filter, pagination, None).await
+            .map(|(result, _)| StreamPage { records: result.records, next_cursor: result.next_cursor })
+    }
+
+    /// Merge per-cell outcomes, recording failed cells in `cell_errors`
+    ///
+    /// Under `Strong` consistency any failed cell fails the whole query; otherwise
+    /// the records of the cells that answered are returned.
+    fn collect_results(
+        query: &BatchQuery,
+        outcomes: impl IntoIterator<Item = (Principal, Result<(Vec<serde_json::Value>, CellExecutionStats), String>)>,
+    ) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
+        let mut records = Vec::new();
+        let mut cell_stats = HashMap::new();
+        let mut cell_errors = HashMap::new();
+
+        for (cell_id, outcome) in outcomes {
+            match outcome {
+                Ok((cell_records, stats)) => {
+                    records.extend(cell_records);
+                    cell_stats.insert(cell_id, stats);
+                },
+                Err(error) => {
+                    ic_cdk::println!("Cell {} failed during coordinated query: {}", cell_id, error);
+                    cell_errors.insert(cell_id, error);
+                },
+            }
+        }
+
+        if matches!(query.options.consistency_level, ConsistencyLevel::Strong) && !cell_errors.is_empty() {
+            let failures: Vec<String> = cell_errors.iter()
+                .map(|(cell_id, error)| format!("{}: {}", cell_id, error))
+                .collect();
+            return Err(format!("Strongly consistent query failed on {} cells: {}",
+                               failures.len(), failures.join("; ")).into());
         }
 
         Ok(CoordinatedResults {
-            records: all_records,
-            total_count: all_records.len() as u64,
+            total_count: records.len() as u64,
+            records,
             cell_stats,
+            cell_errors,
         })
     }
 
+    /// Translate batch query parameters into equality conditions understood by
+    /// cells, followed by the query's string filters
+    fn cell_filter(query: &BatchQuery, trace_id: Option<&str>) -> CellQueryFilter {
+        let equalities = query.parameters.iter()
+            .map(|(field, value)| CellFilterCondition {
+                field: field.clone(),
+                operator: CellComparisonOperator::Equals,
+                value: value.clone(),
+                case_insensitive: false,
+            });
+        let filters = query.filters.iter().flatten()
+            .map(|filter| CellFilterCondition {
+                field: filter.field.clone(),
+                operator: match filter.operator {
+                    FilterOperator::Equals => CellComparisonOperator::Equals,
+                    FilterOperator::StartsWith => CellComparisonOperator::StartsWith,
+                    FilterOperator::EndsWith => CellComparisonOperator::EndsWith,
+                    FilterOperator::Matches => CellComparisonOperator::Matches,
+                },
+                value: serde_json::Value::String(filter.value.clone()),
+                case_insensitive: filter.case_insensitive.unwrap_or(false),
+            });
+
+        CellQueryFilter {
+            trace_id: trace_id.map(str::to_string),
+            conditions: equalities.chain(filters).collect(),
+            sort_by: None,
+            sort_order: CellSortOrder::Ascending,
+        }
+    }
+
+    /// Page requested from each cell, bounded by the query's `max_results`
+    fn cell_pagination(query: &BatchQuery) -> Pagination {
+        Pagination {
+            offset: 0,
+            limit: query.options.max_results.unwrap_or(DEFAULT_CELL_RESULT_LIMIT),
+            cursor: None,
+        }
+    }
+
     /// Execute query with streaming coordination
     async fn execute_streaming_query(query: &BatchQuery, plan: &ExecutionPlan) -> Result<CoordinatedResults, Box<dyn std::error::Error>> {
         ic_cdk::println!("Executing streaming query across {} cells", query.target_cells.len());
@@ -208,6 +652,170 @@ impl Coordination {
             records: vec![serde_json::json!({"streaming": "placeholder"})],
             total_count: 1,
             cell_stats: HashMap::new(),
+            cell_errors: HashMap::new(),
+        })
+    }
+
+    /// Collect distinct values of a field from each cell and merge them
+    pub async fn execute_distinct(field: &str, cell_ids: &[Principal], pagination: Pagination) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+        ic_cdk::println!("Collecting distinct values of '{}' across {} cells", field, cell_ids.len());
+
+        let cell_pagination = Pagination {
+            offset: 0,
+            limit: MAX_DISTINCT_VALUES_PER_CELL,
+            cursor: None,
+        };
+
+        let mut merged = BTreeMap::new();
+        for cell_id in cell_ids {
+            let (values,): (Vec<serde_json::Value>,) = Self::call_cell(
+                *cell_id,
+                "distinct",
+                (field.to_string(), cell_pagination.clone()),
+            ).await?;
+
+            for value in values {
+                merged.insert(value.to_string(), value);
+            }
+        }
+
+        Ok(merged.into_values()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit as usize)
+            .collect())
+    }
+
+    /// Call a cell method through its circuit breaker, retrying transient failures
+    pub async fn call_cell<T, R>(cell_id: Principal, method: &str, args: T) -> Result<R, Box<dyn std::error::Error>>
+    where
+        T: ArgumentEncoder + Clone,
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        Self::call_cell_until(cell_id, method, args, None).await
+            .map(|outcome| outcome.reply)
+    }
+
+    /// Call a cell method, retrying transient failures with exponential backoff until `deadline`
+    ///
+    /// Calls to a cell whose circuit is open fail immediately with
+    /// `QueryError::CellUnavailable`. Rejections raised by the cell's own code
+    /// (`CanisterReject`) show the cell is reachable and don't count as failures.
+    /// Only `SysTransient` rejections are retried, and never past `deadline`
+    /// (nanoseconds since epoch).
+    pub async fn call_cell_until<T, R>(
+        cell_id: Principal,
+        method: &str,
+        args: T,
+        deadline: Option<u64>,
+    ) -> Result<CellCallOutcome<R>, Box<dyn std::error::Error>>
+    where
+        T: ArgumentEncoder + Clone,
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        let policy = RETRY_POLICY.with(|cell| cell.borrow().get().clone());
+        let mut retries = 0;
+
+        loop {
+            Self::check_circuit(cell_id)?;
+
+            let result = ic_cdk::call(cell_id, method, args.clone()).await;
+            Self::record_call_outcome(cell_id, match &result {
+                Ok(_) => true,
+                Err((code, _)) => *code == RejectionCode::CanisterReject,
+            });
+
+            let (code, msg) = match result {
+                Ok(reply) => return Ok(CellCallOutcome { reply, retries }),
+                Err(error) => error,
+            };
+
+            let delay_ms = policy.delay_ms(retries);
+            let retry_at = ic_cdk::api::time().saturating_add(delay_ms * 1_000_000);
+
+            if !policy.should_retry(code, retries, retry_at, deadline) {
+                return Err(format!("Cell {} failed {} after {} retries: {:?} {}",
+                                   cell_id, method, retries, code, msg).into());
+            }
+
+            ic_cdk::println!("Retrying {} on cell {} in {}ms after {:?}", method, cell_id, delay_ms, code);
+            sleep(std::time::Duration::from_millis(delay_ms)).await;
+            retries += 1;
+        }
+    }
+
+    /// Reject calls to a cell whose circuit is open, half-opening it once the cooldown has passed
+    fn check_circuit(cell_id: Principal) -> Result<(), QueryError> {
+        let now = ic_cdk::api::time();
+
+        CIRCUIT_BREAKERS.with(|breakers| {
+            let mut breakers_ref = breakers.borrow_mut();
+            let breaker = match breakers_ref.get_mut(&cell_id) {
+                Some(breaker) => breaker,
+                None => return Ok(()),
+            };
+
+            match breaker.state {
+                CircuitState::Open if now.saturating_sub(breaker.opened_at) >= CIRCUIT_COOLDOWN_NANOS => {
+                    ic_cdk::println!("Half-opening circuit for cell {}", cell_id);
+                    breaker.state = CircuitState::HalfOpen;
+                    Ok(())
+                },
+                CircuitState::Open => Err(QueryError::CellUnavailable(cell_id)),
+                CircuitState::Closed | CircuitState::HalfOpen => Ok(()),
+            }
+        })
+    }
+
+    /// Whether a call to the cell would currently be let through, without changing its circuit
+    fn circuit_allows(cell_id: &Principal) -> bool {
+        let now = ic_cdk::api::time();
+
+        CIRCUIT_BREAKERS.with(|breakers| {
+            breakers.borrow().get(cell_id).map_or(true, |breaker| {
+                breaker.state != CircuitState::Open
+                    || now.saturating_sub(breaker.opened_at) >= CIRCUIT_COOLDOWN_NANOS
+            })
+        })
+    }
+
+    /// Update a cell's circuit after a call
+    ///
+    /// A success closes the circuit. A failed half-open probe reopens it, as do
+    /// `CIRCUIT_FAILURE_THRESHOLD` consecutive failures.
+    fn record_call_outcome(cell_id: Principal, healthy: bool) {
+        CIRCUIT_BREAKERS.with(|breakers| {
+            let mut breakers_ref = breakers.borrow_mut();
+
+            if healthy {
+                breakers_ref.remove(&cell_id);
+                return;
+            }
+
+            let breaker = breakers_ref.entry(cell_id).or_default();
+            breaker.consecutive_failures += 1;
+
+            let trips = breaker.state == CircuitState::HalfOpen
+                || breaker.consecutive_failures >= CIRCUIT_FAILURE_THRESHOLD;
+            if trips && breaker.state != CircuitState::Open {
+                ic_cdk::println!("Opening circuit for cell {} after {} consecutive failures",
+                                cell_id, breaker.consecutive_failures);
+                breaker.state = CircuitState::Open;
+                breaker.opened_at = ic_cdk::api::time();
+            }
+        })
+    }
+
+    /// Circuit state of every cell whose circuit isn't closed
+    pub fn get_circuit_statuses() -> Vec<CellCircuitStatus> {
+        CIRCUIT_BREAKERS.with(|breakers| {
+            breakers.borrow().iter()
+                .filter(|(_, breaker)| breaker.state != CircuitState::Closed)
+                .map(|(cell_id, breaker)| CellCircuitStatus {
+                    cell_id: *cell_id,
+                    state: breaker.state.clone(),
+                    consecutive_failures: breaker.consecutive_failures,
+                })
+                .collect()
         })
     }
 
@@ -216,7 +824,7 @@ impl Coordination {
         ic_cdk::println!("Registering cell: {} ({})", registration.name, registration.cell_id);
 
         // Validate cell accessibility
-        Self::validate_cell_connectivity(&registration.cell_id).await?;
+        Self::validate_cell_connectivity(&registration).await?;
 
         // Store registration
         REGISTERED_CELLS.with(|registry| {
@@ -226,17 +834,63 @@ impl Coordination {
         Ok(())
     }
 
-    /// Validate cell connectivity and capabilities
-    async fn validate_cell_connectivity(cell_id: &Principal) -> Result<(), Box<dyn std::error::Error>> {
-        // TODO: Implement cell health check
-        // - Verify canister is running
-        // - Test basic query functionality
-        // - Validate schema compatibility
+    /// Remove a cell and any replicas of it from the registry, returning whether it was registered
+    pub fn unregister_cell(cell_id: Principal) -> bool {
+        let removed = REGISTERED_CELLS.with(|registry| {
+            let mut registry_ref = registry.borrow_mut();
+            let replicas: Vec<Principal> = registry_ref.iter()
+                .filter(|(_, registration)| registration.replica_of == Some(cell_id))
+                .map(|(replica_id, _)| replica_id)
+                .collect();
+            for replica_id in &replicas {
+                registry_ref.remove(replica_id);
+            }
+            registry_ref.remove(&cell_id).is_some()
+        });
+
+        CIRCUIT_BREAKERS.with(|breakers| breakers.borrow_mut().remove(&cell_id));
+        READ_ROTATION.with(|rotation| rotation.borrow_mut().remove(&cell_id));
+        removed
+    }
 
+    /// Check that a cell answers its health check, is not in an error state,
+    /// runs the schema version it is being registered with and supports every
+    /// capability the registration declares
+    async fn validate_cell_connectivity(registration: &CellRegistration) -> Result<(), Box<dyn std::error::Error>> {
+        let cell_id = registration.cell_id;
         ic_cdk::println!("Validating connectivity to cell: {}", cell_id);
+
+        let health = Self::cell_health(cell_id).await?;
+
+        if let CellHealthStatus::Error(reason) = health.status {
+            return Err(format!("Cell {} reports an error state: {}", cell_id, reason).into());
+        }
+
+        if health.schema_version != registration.schema_version {
+            return Err(format!(
+                "Cell {} runs schema version {}, registration claims {}",
+                cell_id, health.schema_version, registration.schema_version
+            ).into());
+        }
+
+        let unsupported: Vec<&CellCapability> = registration.capabilities.iter()
+            .filter(|capability| !health.capabilities.contains(capability))
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(format!("Cell {} does not support declared capabilities {:?}", cell_id, unsupported).into());
+        }
+
         Ok(())
     }
 
+    /// Call a cell's `health` endpoint
+    async fn cell_health(cell_id: Principal) -> Result<CellHealth, Box<dyn std::error::Error>> {
+        let (health,): (CellHealth,) = ic_cdk::call(cell_id, "health", ())
+            .await
+            .map_err(|(code, msg)| format!("Cell {} is unreachable: {:?} - {}", cell_id, code, msg))?;
+        Ok(health)
+    }
+
     /// Check if caller is authorized manager
     pub async fn is_authorized_manager(caller: Principal) -> bool {
         AUTHORIZED_MANAGERS.with(|managers| {
@@ -270,15 +924,11 @@ impl Coordination {
             }
         }
 
-        match complexity_score {
-            0..=2 => ComplexityLevel::Low,
-            3..=5 => ComplexityLevel::Medium,
-            _ => ComplexityLevel::High,
-        }
+        ComplexityLevel::from_score(complexity_score)
     }
 
     /// Estimate execution time based on complexity and cell count
-    fn estimate_execution_time(cell_count: usize, complexity: ComplexityLevel) -> u64 {
+    pub fn estimate_execution_time(cell_count: usize, complexity: ComplexityLevel) -> u64 {
         let base_time = match complexity {
             ComplexityLevel::Low => 100,
             ComplexityLevel::Medium => 300,
@@ -289,7 +939,7 @@ impl Coordination {
     }
 
     /// Calculate resource requirements for execution plan
-    fn calculate_resource_needs(strategy: &ExecutionStrategy, cell_count: usize) -> ResourceRequirements {
+    pub fn calculate_resource_needs(strategy: &ExecutionStrategy, cell_count: usize) -> ResourceRequirements {
         ResourceRequirements {
             estimated_cycles: match strategy {
                 ExecutionStrategy::Parallel => cell_count as u64 * 2_000_000,
@@ -328,9 +978,22 @@ pub enum ComplexityLevel {
     High,
 }
 
+impl ComplexityLevel {
+    /// Bucket a weighted complexity score
+    pub fn from_score(score: u32) -> Self {
+        match score {
+            0..=2 => ComplexityLevel::Low,
+            3..=5 => ComplexityLevel::Medium,
+            _ => ComplexityLevel::High,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExecutionPlan {
     pub strategy: ExecutionStrategy,
+    /// Time by which cell calls, including retries, must finish
+    pub deadline: Option<u64>,
     pub estimated_duration: u64,
     pub resource_requirements: ResourceRequirements,
 }
@@ -346,4 +1009,241 @@ pub struct CoordinatedResults {
     pub records: Vec<serde_json::Value>,
     pub total_count: u64,
     pub cell_stats: HashMap<Principal, CellExecutionStats>,
-}
\ No newline at end of file
+    pub cell_errors: HashMap<Principal, String>,
+}
+
+/// Data Cell `QueryFilter`, limited to the parts the aggregator sends
+#[derive(CandidType, Clone, Debug)]
+struct CellQueryFilter {
+    conditions: Vec<CellFilterCondition>,
+    sort_by: Option<String>,
+    sort_order: CellSortOrder,
+    /// Correlates the cell's processing with the aggregator's trace
+    trace_id: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct CellFilterCondition {
+    field: String,
+    operator: CellComparisonOperator,
+    value: serde_json::Value,
+    case_insensitive: bool,
+}
+
+#[derive(CandidType, Clone, Debug)]
+enum CellComparisonOperator {
+    Equals,
+    StartsWith,
+    EndsWith,
+    Matches,
+}
+
+#[derive(CandidType, Clone, Debug)]
+enum CellSortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Data Cell `QueryResult`, limited to the fields the aggregator reads
+#[derive(CandidType, Deserialize, Debug)]
+struct CellQueryResult {
+    records: Vec<serde_json::Value>,
+    next_cursor: Option<String>,
+}
+
+/// One page of a cell's records read by a stream
+pub struct StreamPage {
+    pub records: Vec<serde_json::Value>,
+    /// Where the next page starts; `None` once the cell has no more records
+    pub next_cursor: Option<String>,
+}
+
+/// Data Cell `CellError`
+#[derive(CandidType, Deserialize, Debug)]
+enum CellQueryError {
+    ValidationError(String),
+    PermissionDenied,
+    NotFound(String),
+    SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
+    NotImplemented(String),
+}
+
+/// Data Cell `CellHealth`
+#[derive(CandidType, Deserialize, Debug)]
+struct CellHealth {
+    status: CellHealthStatus,
+    schema_version: u32,
+    record_count: u64,
+    capabilities: Vec<CellCapability>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum CellHealthStatus {
+    Healthy,
+    Degraded(String),
+    Error(String),
+}
+
+/// Bounded exponential backoff for transient cell call failures
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl_storable!(RetryPolicy);
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay_ms: 100,
+            max_delay_ms: 2_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (0-based), doubling each time up to `max_delay_ms`
+    fn delay_ms(&self, retry: u32) -> u64 {
+        self.base_delay_ms
+            .saturating_mul(1u64.checked_shl(retry).unwrap_or(u64::MAX))
+            .min(self.max_delay_ms)
+    }
+
+    /// Whether a call rejected with `code` after `retries` retries is tried
+    /// again at `retry_at`: only transient rejections, within the retry budget
+    /// and before the deadline
+    fn should_retry(&self, code: RejectionCode, retries: u32, retry_at: u64, deadline: Option<u64>) -> bool {
+        code == RejectionCode::SysTransient
+            && retries < self.max_retries
+            && deadline.map_or(true, |deadline| retry_at < deadline)
+    }
+}
+
+/// A coordinated query's concurrency slot, released when dropped
+struct QuerySlot;
+
+impl Drop for QuerySlot {
+    fn drop(&mut self) {
+        Coordination::release_query_slot();
+    }
+}
+
+/// Reply of a cell call together with how many retries it took
+pub struct CellCallOutcome<R> {
+    pub reply: R,
+    pub retries: u32,
+}
+
+/// Wait without blocking other messages, by resuming from a one-shot timer
+async fn sleep(duration: std::time::Duration) {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    ic_cdk_timers::set_timer(duration, move || {
+        let _ = sender.send(());
+    });
+    let _ = receiver.await;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: u64,
+}
+
+/// Circuit breaker state of a cell, reported in aggregator metrics
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CellCircuitStatus {
+    pub cell_id: Principal,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy { max_retries: 3, base_delay_ms: 100, max_delay_ms: 500 }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum_delay() {
+        let delays: Vec<u64> = (0..5).map(|retry| policy().delay_ms(retry)).collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+        assert_eq!(policy().delay_ms(u32::MAX), 500);
+    }
+
+    #[test]
+    fn only_transient_rejections_are_retried() {
+        let policy = policy();
+
+        assert!(policy.should_retry(RejectionCode::SysTransient, 0, 10, None));
+        for code in [
+            RejectionCode::CanisterReject,
+            RejectionCode::CanisterError,
+            RejectionCode::SysFatal,
+            RejectionCode::DestinationInvalid,
+        ] {
+            assert!(!policy.should_retry(code, 0, 10, None), "{:?} was retried", code);
+        }
+    }
+
+    #[test]
+    fn retries_stop_at_the_budget_and_the_deadline() {
+        let policy = policy();
+
+        assert!(policy.should_retry(RejectionCode::SysTransient, 2, 10, None));
+        assert!(!policy.should_retry(RejectionCode::SysTransient, 3, 10, None));
+
+        assert!(policy.should_retry(RejectionCode::SysTransient, 0, 10, Some(11)));
+        assert!(!policy.should_retry(RejectionCode::SysTransient, 0, 10, Some(10)));
+    }
+
+    /// The slot a queued query holds, if it has been admitted
+    fn admitted<F: std::future::Future<Output = Result<QuerySlot, QueryError>> + Unpin>(query: &mut F) -> Option<QuerySlot> {
+        futures::FutureExt::now_or_never(query).map(|slot| slot.unwrap())
+    }
+
+    #[test]
+    fn high_priority_queries_are_admitted_ahead_of_queued_ones() {
+        let mut running: Vec<QuerySlot> = (0..MAX_CONCURRENT_QUERIES)
+            .map(|_| admitted(&mut Box::pin(Coordination::acquire_query_slot(QueryPriority::Normal)))
+                .expect("a free slot was not granted at once"))
+            .collect();
+
+        let mut first_low = Box::pin(Coordination::acquire_query_slot(QueryPriority::Low));
+        let mut second_low = Box::pin(Coordination::acquire_query_slot(QueryPriority::Low));
+        let mut high = Box::pin(Coordination::acquire_query_slot(QueryPriority::High));
+        assert!(admitted(&mut first_low).is_none());
+        assert!(admitted(&mut second_low).is_none());
+        assert!(admitted(&mut high).is_none());
+        assert_eq!(Coordination::queue_depth(), 3);
+
+        // Each finished query hands its slot to the best queued one
+        drop(running.pop());
+        let high_slot = admitted(&mut high).expect("the high-priority query was not admitted");
+        assert!(admitted(&mut first_low).is_none());
+
+        drop(high_slot);
+        let low_slot = admitted(&mut first_low).expect("the earlier low-priority query was not admitted");
+        assert!(admitted(&mut second_low).is_none());
+
+        drop(low_slot);
+        assert!(admitted(&mut second_low).is_some());
+        assert_eq!(Coordination::queue_depth(), 0);
+    }
+}
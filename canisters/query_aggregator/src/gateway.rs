@@ -0,0 +1,101 @@
+//! Principal allow/deny rules checked before any query work
+//!
+//! A coarse gateway control independent of per-cell ACLs: denylisted
+//! principals are always rejected, and in allowlist-only mode every principal
+//! not on the allowlist is rejected too.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, StableCell, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use crate::QueryError;
+use crate::coordination::{impl_storable, memory, Memory};
+
+type GatewayListings = StableBTreeMap<Principal, GatewayListing, Memory>;
+
+thread_local! {
+    static LISTINGS: RefCell<GatewayListings> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(13)))
+    );
+
+    static ALLOWLIST_ONLY: RefCell<StableCell<bool, Memory>> = RefCell::new(
+        StableCell::init(
+            memory(MemoryId::new(14)),
+            false,
+        ).expect("Failed to initialize gateway mode")
+    );
+}
+
+/// Which gateway list a principal is on
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum GatewayListing {
+    Allowed,
+    Denied,
+}
+
+impl_storable!(GatewayListing);
+
+/// Current gateway configuration
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GatewayRules {
+    /// Reject every principal not on the allowlist
+    pub allowlist_only: bool,
+    pub allowlist: Vec<Principal>,
+    pub denylist: Vec<Principal>,
+}
+
+pub struct Gateway;
+
+impl Gateway {
+    /// Reject `caller` if the gateway rules don't admit it
+    pub fn check(caller: Principal) -> Result<(), QueryError> {
+        let listing = LISTINGS.with(|listings| listings.borrow().get(&caller));
+
+        match listing {
+            Some(GatewayListing::Denied) => Err(QueryError::PermissionDenied(
+                format!("{} is blocked by the gateway denylist", caller)
+            )),
+            Some(GatewayListing::Allowed) => Ok(()),
+            None if Self::allowlist_only() => Err(QueryError::PermissionDenied(
+                format!("{} is not on the gateway allowlist", caller)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Put a principal on a list, or take it off both with `None`
+    pub fn set_listing(principal: Principal, listing: Option<GatewayListing>) {
+        LISTINGS.with(|listings| {
+            let mut listings = listings.borrow_mut();
+            match listing {
+                Some(listing) => listings.insert(principal, listing),
+                None => listings.remove(&principal),
+            };
+        });
+    }
+
+    /// Switch allowlist-only mode on or off
+    pub fn set_allowlist_only(enabled: bool) {
+        ALLOWLIST_ONLY.with(|mode| {
+            mode.borrow_mut().set(enabled).expect("Failed to persist gateway mode");
+        });
+    }
+
+    fn allowlist_only() -> bool {
+        ALLOWLIST_ONLY.with(|mode| *mode.borrow().get())
+    }
+
+    /// The allowlist, denylist and mode
+    pub fn rules() -> GatewayRules {
+        LISTINGS.with(|listings| {
+            let (allowed, denied): (Vec<_>, Vec<_>) = listings.borrow().iter()
+                .partition(|(_, listing)| *listing == GatewayListing::Allowed);
+
+            GatewayRules {
+                allowlist_only: Self::allowlist_only(),
+                allowlist: allowed.into_iter().map(|(principal, _)| principal).collect(),
+                denylist: denied.into_iter().map(|(principal, _)| principal).collect(),
+            }
+        })
+    }
+}
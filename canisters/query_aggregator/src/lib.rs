@@ -13,10 +13,22 @@ use std::collections::{HashMap, BTreeMap};
 mod streaming;
 mod coordination;
 mod optimization;
+mod views;
+mod continuation;
+mod catalog;
+mod gateway;
+mod traces;
+mod warmup;
 
 use streaming::*;
 use coordination::*;
 use optimization::*;
+use views::*;
+use continuation::*;
+use catalog::Catalog;
+use gateway::*;
+use traces::*;
+use warmup::*;
 
 /// Initialize Query Aggregator with cell registry and optimization parameters
 #[init]
@@ -24,15 +36,21 @@ fn init(config: AggregatorConfig) {
     ic_cdk::println!("Initializing Query Aggregator: {}", config.name);
 
     // Initialize coordination state and optimization engine
-    Coordination::init(&config.registered_cells);
+    Coordination::init(
+        &config.registered_cells,
+        config.authorized_managers.as_deref().unwrap_or_default(),
+        config.retry_policy.clone(),
+    );
     StreamingEngine::init(&config.streaming_config);
     QueryOptimizer::init(&config.optimization_config);
+    HotQueries::schedule();
 }
 
 /// Execute streaming query across multiple Data Cells
 #[update]
 async fn execute_streaming_query(query_plan: QueryPlan) -> Result<StreamHandle, QueryError> {
     let caller = caller();
+    Gateway::check(caller)?;
 
     ic_cdk::println!("Executing streaming query from principal: {}", caller);
 
@@ -45,44 +63,419 @@ async fn execute_streaming_query(query_plan: QueryPlan) -> Result<StreamHandle,
     let optimized_plan = QueryOptimizer::optimize_plan(query_plan).await
         .map_err(|e| QueryError::OptimizationFailed(e.to_string()))?;
 
-    // Create streaming execution context
-    let stream_handle = StreamingEngine::create_stream(optimized_plan).await
-        .map_err(|e| QueryError::ExecutionFailed(e.to_string()))?;
+    // Create streaming execution context, subject to stream limits
+    let stream_handle = StreamingEngine::create_stream(caller, optimized_plan).await?;
 
     Ok(stream_handle)
 }
 
+/// Explain how a query plan would be executed, without executing it
+#[query]
+async fn explain_query(plan: QueryPlan) -> Result<QueryExplanation, QueryError> {
+    let caller = caller();
+    Gateway::check(caller)?;
+
+    if !Coordination::validate_cell_access(caller, &plan.target_cells).await {
+        return Err(QueryError::PermissionDenied("Insufficient cell access permissions".to_string()));
+    }
+
+    QueryOptimizer::explain_plan(plan).await
+        .map_err(|e| QueryError::OptimizationFailed(e.to_string()))
+}
+
 /// Execute batch query with intelligent coordination
+///
+/// `target_group` adds the primary cells of that group to `target_cells`.
+/// Group queries, and queries setting `require_same_schema`, are rejected
+/// with `SchemaMismatch` unless all targets share one schema version.
+/// An empty target set queries every registered primary cell that has the
+/// options' `required_capabilities`, but only when `allow_full_scan` is set.
+/// Queries registered with `register_hot_query` are answered from the cache
+/// while their warm result is fresh.
 #[update]
-async fn execute_batch_query(query: BatchQuery) -> Result<BatchQueryResult, QueryError> {
+async fn execute_batch_query(mut query: BatchQuery) -> Result<BatchQueryResult, QueryError> {
     let caller = caller();
+    Gateway::check(caller)?;
+
+    resolve_targets(&mut query)?;
 
     ic_cdk::println!("Executing batch query across {} cells", query.target_cells.len());
 
+    let page_size = query.options.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    // Hot queries are kept cached by the warm-up timer
+    if let Some(records) = QueryOptimizer::cached_records(&QueryOptimizer::batch_signature(&query)) {
+        let cached_result = BatchQueryResult {
+            query_id: format!("cached_{}", api::time()),
+            execution_time_ms: 0,
+            total_count: records.len() as u64,
+            records,
+            cell_statistics: HashMap::new(),
+            cell_errors: HashMap::new(),
+            continuation_token: None,
+            trace_id: None,
+        };
+        return Ok(Continuations::first_page(caller, cached_result, page_size));
+    }
+
+    let aggregated_result = run_batch_query(caller, query).await?;
+    Ok(Continuations::first_page(caller, aggregated_result, page_size))
+}
+
+/// Add a batch query's group members to its target cells, or every capable
+/// cell for an allowed full scan, and check their schema versions if asked
+fn resolve_targets(query: &mut BatchQuery) -> Result<(), QueryError> {
+    if let Some(group) = &query.target_group {
+        let members = Coordination::cells_in_group(group, false);
+        if members.is_empty() {
+            return Err(QueryError::InvalidQuery(format!("Group {} has no cells", group)));
+        }
+        for cell_id in members {
+            if !query.target_cells.contains(&cell_id) {
+                query.target_cells.push(cell_id);
+            }
+        }
+    }
+
+    if query.target_cells.is_empty() {
+        if !query.options.allow_full_scan.unwrap_or(false) {
+            return Err(QueryError::InvalidQuery(
+                "No target cells given; set allow_full_scan to query all registered cells".to_string()
+            ));
+        }
+
+        let required = query.options.required_capabilities.clone().unwrap_or_default();
+        query.target_cells = Coordination::cells_with_capabilities(&required);
+        if query.target_cells.is_empty() {
+            return Err(QueryError::InvalidQuery("No registered cell has the required capabilities".to_string()));
+        }
+    }
+
+    if query.target_group.is_some() || query.options.require_same_schema.unwrap_or(false) {
+        Coordination::check_schema_versions(&query.target_cells)?;
+    }
+
+    Ok(())
+}
+
+/// Execute a batch query with resolved targets and aggregate the results, under a new trace
+async fn run_batch_query(caller: Principal, query: BatchQuery) -> Result<BatchQueryResult, QueryError> {
+    let trace_id = Traces::new_trace_id();
+    let started_at = api::time();
+
     // Coordinate execution across multiple cells with optimal batching
-    let coordination_result = Coordination::execute_coordinated_query(caller, query).await
-        .map_err(|e| QueryError::CoordinationFailed(e.to_string()))?;
+    let outcome = Coordination::execute_coordinated_query(caller, query, &trace_id).await
+        .map_err(coordination_error);
 
     // Apply post-processing and result aggregation
-    let aggregated_result = QueryOptimizer::aggregate_results(coordination_result).await
-        .map_err(|e| QueryError::AggregationFailed(e.to_string()))?;
+    let outcome = match outcome {
+        Ok(coordination_result) => QueryOptimizer::aggregate_results(coordination_result).await
+            .map_err(|e| QueryError::AggregationFailed(e.to_string())),
+        Err(error) => Err(error),
+    };
 
+    let records = outcome.as_ref().map_or(0, |result| result.records.len() as u64);
+    Traces::record(&trace_id, "batch_query", None, started_at, records, outcome.as_ref().err().map(ToString::to_string));
+
+    let mut aggregated_result = outcome?;
+    aggregated_result.trace_id = Some(trace_id);
     Ok(aggregated_result)
 }
 
+/// Get the next page of a batch query result
+#[update]
+fn continue_batch_query(token: String) -> Result<BatchQueryResult, QueryError> {
+    let caller = caller();
+    Gateway::check(caller)?;
+
+    Continuations::resume(caller, &token)
+        .map_err(|e| QueryError::InvalidQuery(e.to_string()))
+}
+
+/// Get distinct values of a field merged and deduplicated across Data Cells
+#[update]
+async fn distinct(field: String, target_cells: Vec<Principal>, pagination: Pagination) -> Result<Vec<serde_json::Value>, QueryError> {
+    let caller = caller();
+    Gateway::check(caller)?;
+
+    if !Coordination::validate_cell_access(caller, &target_cells).await {
+        return Err(QueryError::PermissionDenied("Insufficient cell access permissions".to_string()));
+    }
+
+    Coordination::execute_distinct(&field, &target_cells, pagination).await
+        .map_err(coordination_error)
+}
+
+/// Create a materialized view kept up to date from its source cells' change feeds
+#[update]
+async fn create_materialized_view(def: ViewDefinition) -> Result<(), QueryError> {
+    let caller = caller();
+
+    ensure_authenticated(caller)?;
+
+    if !Coordination::is_authorized_manager(caller).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can create views".to_string()));
+    }
+
+    if !Coordination::validate_cell_access(caller, &def.source_cells).await {
+        return Err(QueryError::PermissionDenied("Insufficient cell access permissions".to_string()));
+    }
+
+    Views::create_view(def).await
+        .map_err(|e| QueryError::ExecutionFailed(e.to_string()))
+}
+
+/// Read rows of a materialized view whose fields equal every filter entry
+#[query]
+fn query_view(name: String, filter: HashMap<String, serde_json::Value>, pagination: Pagination) -> Result<Vec<serde_json::Value>, QueryError> {
+    Gateway::check(caller())?;
+
+    Views::query_view(&name, &filter, &pagination)
+        .map_err(|e| QueryError::InvalidQuery(e.to_string()))
+}
+
+/// Change feed callback invoked by Data Cells that materialized views read from
+#[update]
+fn on_cell_change(event: ChangeEvent) -> Result<(), QueryError> {
+    Views::apply_change(caller(), event)
+        .map_err(|e| QueryError::PermissionDenied(e.to_string()))
+}
+
+/// Invalidation notice from a registered Data Cell after a write
+///
+/// Drops cached query results the write may have changed.
+#[update]
+fn notify_mutation(event: MutationEvent) -> Result<(), QueryError> {
+    let caller = caller();
+
+    if !Coordination::is_registered(caller) {
+        return Err(QueryError::PermissionDenied(format!("{} is not a registered cell", caller)));
+    }
+
+    let invalidated = QueryOptimizer::invalidate(caller, &event.fields);
+    if invalidated > 0 {
+        ic_cdk::println!("Invalidated {} cached results after {:?} of {} in {}",
+                        invalidated, event.op, event.record_id, caller);
+    }
+    Ok(())
+}
+
+/// Drop every cached query result tagged with `tag` (authorized managers only)
+///
+/// Returns the number of results dropped.
+#[update]
+async fn invalidate_cache_tag(tag: String) -> Result<u64, QueryError> {
+    if !Coordination::is_authorized_manager(caller()).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can invalidate the cache".to_string()));
+    }
+
+    Ok(QueryOptimizer::invalidate_by_tag(&tag))
+}
+
+/// Convert a coordination failure into a `QueryError`, keeping typed errors such as `CellUnavailable`
+fn coordination_error(error: Box<dyn std::error::Error>) -> QueryError {
+    match error.downcast::<QueryError>() {
+        Ok(error) => *error,
+        Err(error) => QueryError::CoordinationFailed(error.to_string()),
+    }
+}
+
+/// Convert a streaming failure into a `QueryError`, keeping typed errors such as `PermissionDenied`
+fn streaming_error(error: Box<dyn std::error::Error>) -> QueryError {
+    match error.downcast::<QueryError>() {
+        Ok(error) => *error,
+        Err(error) => QueryError::StreamingFailed(error.to_string()),
+    }
+}
+
 /// Get next batch of streaming results
 #[update]
 async fn get_stream_batch(stream_handle: StreamHandle, batch_size: u32) -> Result<StreamBatch, QueryError> {
+    let caller = caller();
+
+    Gateway::check(caller)?;
+
     // Validate stream handle and fetch next batch
-    StreamingEngine::get_next_batch(stream_handle, batch_size).await
-        .map_err(|e| QueryError::StreamingFailed(e.to_string()))
+    StreamingEngine::get_next_batch(caller, stream_handle, batch_size).await
+        .map_err(streaming_error)
+}
+
+/// Inspect a stream's progress without pulling a batch
+#[query]
+fn get_stream_status(stream_handle: StreamHandle) -> Result<StreamStatus, QueryError> {
+    let caller = caller();
+
+    Gateway::check(caller)?;
+
+    StreamingEngine::stream_status(caller, &stream_handle)
+}
+
+/// Pause a stream, stopping all fetching from cells until it is resumed
+///
+/// With `hold`, the stream is kept alive while paused and its expiry is
+/// pushed back by the paused time on resume.
+#[update]
+fn pause_stream(stream_handle: StreamHandle, hold: Option<bool>) -> Result<(), QueryError> {
+    let caller = caller();
+
+    Gateway::check(caller)?;
+
+    StreamingEngine::set_paused(caller, &stream_handle, true, hold.unwrap_or(false))
+}
+
+/// Resume a paused stream
+#[update]
+fn resume_stream(stream_handle: StreamHandle) -> Result<(), QueryError> {
+    let caller = caller();
+
+    Gateway::check(caller)?;
+
+    StreamingEngine::set_paused(caller, &stream_handle, false, false)
 }
 
 /// Close streaming query and cleanup resources
 #[update]
 async fn close_stream(stream_handle: StreamHandle) -> Result<(), QueryError> {
-    StreamingEngine::close_stream(stream_handle).await
-        .map_err(|e| QueryError::StreamingFailed(e.to_string()))
+    let caller = caller();
+
+    Gateway::check(caller)?;
+
+    StreamingEngine::close_stream(caller, stream_handle).await
+        .map_err(streaming_error)
+}
+
+/// Drop all streams, e.g. to relieve memory pressure (controllers only)
+///
+/// Returns the number of streams cancelled.
+#[update]
+async fn cancel_all_streams() -> Result<u64, QueryError> {
+    let caller = caller();
+
+    ensure_authenticated(caller)?;
+
+    if !api::is_controller(&caller) {
+        return Err(QueryError::PermissionDenied("Only controllers can cancel all streams".to_string()));
+    }
+
+    let cancelled = StreamingEngine::cancel_all_streams();
+    ic_cdk::println!("Cancelled {} streams at the request of {}", cancelled, caller);
+    Ok(cancelled)
+}
+
+/// Put a principal on the gateway allowlist or denylist, or clear it with `None` (controllers only)
+#[update]
+fn set_gateway_listing(principal: Principal, listing: Option<GatewayListing>) -> Result<(), QueryError> {
+    if !api::is_controller(&caller()) {
+        return Err(QueryError::PermissionDenied("Only controllers can change gateway rules".to_string()));
+    }
+
+    Gateway::set_listing(principal, listing);
+    Ok(())
+}
+
+/// Admit only allowlisted principals to query entrypoints (controllers only)
+#[update]
+fn set_gateway_allowlist_only(enabled: bool) -> Result<(), QueryError> {
+    if !api::is_controller(&caller()) {
+        return Err(QueryError::PermissionDenied("Only controllers can change gateway rules".to_string()));
+    }
+
+    Gateway::set_allowlist_only(enabled);
+    Ok(())
+}
+
+/// Current gateway allowlist, denylist and mode (controllers only)
+#[query]
+fn gateway_rules() -> Result<GatewayRules, QueryError> {
+    if !api::is_controller(&caller()) {
+        return Err(QueryError::PermissionDenied("Only controllers can read gateway rules".to_string()));
+    }
+
+    Ok(Gateway::rules())
+}
+
+/// Make plans with `signature` use `strategy` instead of the optimizer's choice (controllers only)
+///
+/// `explain_query` reports the signature of a plan.
+#[update]
+fn pin_plan(signature: String, strategy: CoordinationStrategy) -> Result<(), QueryError> {
+    if !api::is_controller(&caller()) {
+        return Err(QueryError::PermissionDenied("Only controllers can pin plans".to_string()));
+    }
+
+    QueryOptimizer::pin_plan(signature, strategy);
+    Ok(())
+}
+
+/// Let the optimizer choose the strategy for `signature` again (controllers only)
+#[update]
+fn unpin_plan(signature: String) -> Result<(), QueryError> {
+    if !api::is_controller(&caller()) {
+        return Err(QueryError::PermissionDenied("Only controllers can unpin plans".to_string()));
+    }
+
+    if QueryOptimizer::unpin_plan(&signature) {
+        Ok(())
+    } else {
+        Err(QueryError::InvalidQuery(format!("No plan pinned for {}", signature)))
+    }
+}
+
+/// Pinned plan signatures and their strategies
+#[query]
+fn pinned_plans() -> Vec<(String, CoordinationStrategy)> {
+    QueryOptimizer::pinned_plans()
+}
+
+/// Register a batch query for the aggregator to keep cached (authorized managers only)
+///
+/// The query runs right away and again whenever its cached result is
+/// missing or close to expiry. Returns the hot query's ID.
+#[update]
+async fn register_hot_query(query: BatchQuery) -> Result<String, QueryError> {
+    let caller = caller();
+
+    ensure_authenticated(caller)?;
+
+    if !Coordination::is_authorized_manager(caller).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can register hot queries".to_string()));
+    }
+
+    resolve_targets(&mut query.clone())?;
+
+    Ok(HotQueries::register(caller, query))
+}
+
+/// Stop keeping a hot query cached (authorized managers only)
+#[update]
+async fn unregister_hot_query(id: String) -> Result<(), QueryError> {
+    if !Coordination::is_authorized_manager(caller()).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can unregister hot queries".to_string()));
+    }
+
+    if HotQueries::unregister(&id) {
+        Ok(())
+    } else {
+        Err(QueryError::InvalidQuery(format!("No hot query {}", id)))
+    }
+}
+
+/// Registered hot queries with their latest warm-up outcome
+#[query]
+fn list_hot_queries() -> Vec<HotQuery> {
+    HotQueries::list()
+}
+
+/// Spans recorded for a batch query's trace (controllers and managers only)
+#[query]
+async fn get_trace(trace_id: String) -> Result<Vec<TraceSpan>, QueryError> {
+    let caller = caller();
+
+    if !api::is_controller(&caller) && !Coordination::is_authorized_manager(caller).await {
+        return Err(QueryError::PermissionDenied("Only controllers and managers can read traces".to_string()));
+    }
+
+    Ok(Traces::get(&trace_id))
 }
 
 /// Register new Data Cell for aggregation
@@ -90,13 +483,72 @@ async fn close_stream(stream_handle: StreamHandle) -> Result<(), QueryError> {
 async fn register_cell(cell_info: CellRegistration) -> Result<(), QueryError> {
     let caller = caller();
 
+    ensure_authenticated(caller)?;
+
     // Validate caller has permission to register cells
     if !Coordination::is_authorized_manager(caller).await {
         return Err(QueryError::PermissionDenied("Only authorized managers can register cells".to_string()));
     }
 
+    let cell_id = cell_info.cell_id;
     Coordination::register_cell(cell_info).await
-        .map_err(|e| QueryError::RegistrationFailed(e.to_string()))
+        .map_err(|e| QueryError::RegistrationFailed(e.to_string()))?;
+
+    Catalog::refresh_cell(cell_id).await;
+
+    // Without notices, cached results from the cell only go stale until their TTL
+    let subscribed: Result<(Result<(), candid::Reserved>,), _> =
+        Coordination::call_cell(cell_id, "subscribe_invalidations", ()).await;
+    if !matches!(subscribed, Ok((Ok(()),))) {
+        ic_cdk::println!("Cell {} did not accept invalidation notices; relying on cache TTL", cell_id);
+    }
+    Ok(())
+}
+
+/// Remove a Data Cell from the registry, e.g. after it has been deleted
+///
+/// Read replicas registered for the cell are removed with it.
+#[update]
+async fn unregister_cell(cell_id: Principal) -> Result<(), QueryError> {
+    let caller = caller();
+
+    ensure_authenticated(caller)?;
+
+    if !Coordination::is_authorized_manager(caller).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can unregister cells".to_string()));
+    }
+
+    if Coordination::unregister_cell(cell_id) {
+        QueryOptimizer::invalidate_by_cell(cell_id);
+        Catalog::rebuild();
+        Ok(())
+    } else {
+        Err(QueryError::RegistrationFailed(format!("Cell {} is not registered", cell_id)))
+    }
+}
+
+/// Catalog of registered cells, their schemas and the supported query types
+///
+/// Schemas are fetched from each cell when it registers and cached; the
+/// description is rebuilt whenever the registry changes.
+#[query]
+fn describe_service() -> ServiceDescription {
+    Catalog::describe()
+}
+
+/// Refetch every registered cell's schema for `describe_service` (managers only)
+#[update]
+async fn refresh_service_description() -> Result<(), QueryError> {
+    let caller = caller();
+
+    ensure_authenticated(caller)?;
+
+    if !Coordination::is_authorized_manager(caller).await {
+        return Err(QueryError::PermissionDenied("Only authorized managers can refresh the service description".to_string()));
+    }
+
+    Catalog::refresh_all().await;
+    Ok(())
 }
 
 /// Get aggregator performance metrics and health status
@@ -107,11 +559,52 @@ fn get_aggregator_metrics() -> AggregatorMetrics {
         registered_cells: Coordination::get_registered_cell_count(),
         query_cache_hits: QueryOptimizer::get_cache_hit_rate(),
         average_query_latency: QueryOptimizer::get_average_latency(),
+        latency_percentiles: QueryOptimizer::get_latency_percentiles(),
         cycle_efficiency_score: QueryOptimizer::get_cycle_efficiency(),
+        circuit_breakers: Coordination::get_circuit_statuses(),
+        in_flight_queries: Coordination::in_flight_queries(),
+        queued_queries: Coordination::queue_depth(),
+        rejected_queries: Coordination::rejected_queries(),
         last_updated: api::time(),
     }
 }
 
+/// Names of all cell groups in the registry
+#[query]
+fn list_groups() -> Vec<String> {
+    Coordination::list_groups()
+}
+
+/// Cells registered in a group, replicas included
+#[query]
+fn cells_in_group(name: String) -> Vec<Principal> {
+    Coordination::cells_in_group(&name, true)
+}
+
+/// Registered schema version of every cell
+#[query]
+fn schema_versions() -> HashMap<Principal, u32> {
+    Coordination::schema_versions()
+}
+
+/// Installed wasm build, for deployment tooling
+#[query]
+fn version() -> CanisterVersion {
+    CanisterVersion {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        build_version: build_version(),
+        schema_version: None,
+    }
+}
+
+/// Crate version, with the commit baked in when built with `CELLDB_GIT_COMMIT` set
+fn build_version() -> String {
+    match option_env!("CELLDB_GIT_COMMIT") {
+        Some(commit) => format!("{}+{}", env!("CARGO_PKG_VERSION"), commit),
+        None => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
 /// Get query execution statistics
 #[query]
 fn get_query_stats(time_window: u64) -> QueryStats {
@@ -123,6 +616,8 @@ fn pre_upgrade() {
     Coordination::pre_upgrade();
     StreamingEngine::pre_upgrade();
     QueryOptimizer::pre_upgrade();
+    Views::pre_upgrade();
+    Continuations::pre_upgrade();
 }
 
 #[post_upgrade]
@@ -130,6 +625,17 @@ fn post_upgrade() {
     Coordination::post_upgrade();
     StreamingEngine::post_upgrade();
     QueryOptimizer::post_upgrade();
+    Views::post_upgrade();
+    Continuations::post_upgrade();
+    HotQueries::schedule();
+}
+
+/// Reject the anonymous principal from endpoints that change aggregator state
+fn ensure_authenticated(caller: Principal) -> Result<(), QueryError> {
+    if caller == Principal::anonymous() {
+        return Err(QueryError::PermissionDenied("Anonymous callers cannot modify the aggregator".to_string()));
+    }
+    Ok(())
 }
 
 /// Configuration for Query Aggregator initialization
@@ -137,6 +643,10 @@ fn post_upgrade() {
 pub struct AggregatorConfig {
     pub name: String,
     pub registered_cells: Vec<CellRegistration>,
+    /// Cell managers allowed to register cells, e.g. the cell_manager canister
+    pub authorized_managers: Option<Vec<Principal>>,
+    /// Retry policy for transient cell call failures; defaults apply when `None`
+    pub retry_policy: Option<RetryPolicy>,
     pub streaming_config: StreamingConfig,
     pub optimization_config: OptimizationConfig,
 }
@@ -149,9 +659,17 @@ pub struct CellRegistration {
     pub schema_version: u32,
     pub capabilities: Vec<CellCapability>,
     pub performance_hints: PerformanceHints,
+    /// Primary cell this cell is a read replica of; reads of the primary may be routed here
+    #[serde(default)]
+    pub replica_of: Option<Principal>,
+    /// Logical group, e.g. the shards of one dataset, queries can target by name
+    #[serde(default)]
+    pub group: Option<String>,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+impl_storable!(CellRegistration);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum CellCapability {
     FullTextSearch,
     GeospatialQueries,
@@ -196,21 +714,88 @@ pub enum CoordinationStrategy {
     PipelinedStreaming,
 }
 
+impl_storable!(CoordinationStrategy);
+
 /// Batch query for coordinated execution
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BatchQuery {
     pub query_sql: String,
     pub target_cells: Vec<Principal>,
+    /// Group whose cells are queried in addition to `target_cells`
+    #[serde(default)]
+    pub target_group: Option<String>,
     pub parameters: HashMap<String, serde_json::Value>,
+    /// String conditions forwarded to cells alongside the `parameters` equalities
+    #[serde(default)]
+    pub filters: Option<Vec<BatchFilter>>,
     pub options: BatchQueryOptions,
 }
 
+/// String condition evaluated by each target cell
+///
+/// `Matches` patterns are compiled by the cells, which reject invalid or
+/// oversized ones with a `ValidationError`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BatchFilter {
+    pub field: String,
+    pub operator: FilterOperator,
+    pub value: String,
+    #[serde(default)]
+    pub case_insensitive: Option<bool>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum FilterOperator {
+    Equals,
+    StartsWith,
+    EndsWith,
+    Matches,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BatchQueryOptions {
     pub max_results: Option<u64>,
+    /// Records per page; remaining records are fetched with `continue_batch_query`
+    pub page_size: Option<u64>,
     pub timeout_ms: Option<u64>,
     pub consistency_level: ConsistencyLevel,
     pub result_format: ResultFormat,
+    /// Permit an empty `target_cells` to query every registered cell
+    #[serde(default)]
+    pub allow_full_scan: Option<bool>,
+    /// Capabilities a cell needs to be included in a full scan
+    #[serde(default)]
+    pub required_capabilities: Option<Vec<CellCapability>>,
+    /// Reject the query unless every target cell is on the same schema version
+    #[serde(default)]
+    pub require_same_schema: Option<bool>,
+    /// Cycle budget; the query fails with `ResourceExhausted` once its cell
+    /// calls are estimated to exceed it
+    #[serde(default)]
+    pub max_cycles: Option<u64>,
+    /// Scheduling class when the aggregator is saturated; `Normal` when `None`
+    #[serde(default)]
+    pub priority: Option<QueryPriority>,
+}
+
+/// Order in which queued queries are admitted
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub enum QueryPriority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl QueryPriority {
+    /// Queue position of the class; lower ranks are admitted first
+    pub fn rank(&self) -> u8 {
+        match self {
+            QueryPriority::High => 0,
+            QueryPriority::Normal => 1,
+            QueryPriority::Low => 2,
+        }
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -227,6 +812,39 @@ pub enum ResultFormat {
     Streaming,
 }
 
+/// Offset-based pagination, mirroring the Data Cell interface
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Pagination {
+    pub offset: u64,
+    pub limit: u64,
+    pub cursor: Option<String>,
+}
+
+/// Execution plan and cost estimate reported by `explain_query`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QueryExplanation {
+    pub plan_id: String,
+    pub strategy: CoordinationStrategy,
+    pub cell_estimates: Vec<CellCostEstimate>,
+    pub estimated_total_cycles: u64,
+    pub estimated_duration_ms: u64,
+    /// Known only when the result is cached or the plan has a limit
+    pub estimated_rows: Option<u64>,
+    pub cache_hit: bool,
+    /// Whether `strategy` comes from `pin_plan` rather than the optimizer
+    #[serde(default)]
+    pub pinned: bool,
+    /// Signature to pass to `pin_plan` for plans like this one
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CellCostEstimate {
+    pub cell_id: Principal,
+    pub estimated_cycles: u64,
+}
+
 /// Handle for managing streaming queries
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct StreamHandle {
@@ -245,6 +863,19 @@ pub struct StreamBatch {
     pub estimated_remaining: Option<u64>,
 }
 
+/// Progress of a stream, as reported by `get_stream_status`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StreamStatus {
+    /// Records returned to the client so far
+    pub position: u64,
+    pub is_complete: bool,
+    /// Records fetched from cells and waiting to be returned
+    pub buffered: u64,
+    pub expires_at: u64,
+    pub error: Option<String>,
+    pub paused: bool,
+}
+
 /// Result of batch query execution
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BatchQueryResult {
@@ -253,6 +884,14 @@ pub struct BatchQueryResult {
     pub records: Vec<serde_json::Value>,
     pub total_count: u64,
     pub cell_statistics: HashMap<Principal, CellExecutionStats>,
+    /// Cells that failed and why; the other cells' records are still returned
+    /// unless the query asked for `Strong` consistency
+    pub cell_errors: HashMap<Principal, String>,
+    /// Token for the next page, or `None` on the last page
+    pub continuation_token: Option<String>,
+    /// Trace to pass to `get_trace` to see how the query was executed
+    #[serde(default)]
+    pub trace_id: Option<String>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -261,6 +900,37 @@ pub struct CellExecutionStats {
     pub records_returned: u64,
     pub cycles_consumed: u64,
     pub cache_hit: bool,
+    /// Retries needed after transient call failures
+    pub retries: u32,
+}
+
+/// Build and schema version reported by `version`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    /// Always `None`; the aggregator has no schema of its own
+    pub schema_version: Option<u32>,
+}
+
+/// Catalog returned by `describe_service`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ServiceDescription {
+    pub cells: Vec<CellDescription>,
+    pub query_types: Vec<QueryType>,
+    pub generated_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CellDescription {
+    pub cell_id: Principal,
+    pub name: String,
+    pub group: Option<String>,
+    pub schema_version: u32,
+    pub capabilities: Vec<CellCapability>,
+    pub replica_of: Option<Principal>,
+    /// The cell's schema as a JSON Schema document; `None` if it couldn't be fetched
+    pub json_schema: Option<String>,
 }
 
 /// Performance metrics for the aggregator
@@ -269,11 +939,30 @@ pub struct AggregatorMetrics {
     pub active_streams: u32,
     pub registered_cells: u32,
     pub query_cache_hits: f64,
+    /// Mean latency in milliseconds of the queries executed in the last hour
     pub average_query_latency: u64,
+    /// Latency percentiles of the queries executed in the last hour
+    pub latency_percentiles: LatencyPercentiles,
     pub cycle_efficiency_score: f64,
+    /// Cells whose circuit breaker is open or half-open
+    pub circuit_breakers: Vec<CellCircuitStatus>,
+    /// Batch queries currently executing
+    pub in_flight_queries: u32,
+    /// Batch queries waiting for a concurrency slot
+    pub queued_queries: u32,
+    /// Batch queries rejected because the queue was full
+    pub rejected_queries: u64,
     pub last_updated: u64,
 }
 
+/// Query latencies in milliseconds at the 50th, 95th and 99th percentiles
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LatencyPercentiles {
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+}
+
 /// Query execution statistics
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct QueryStats {
@@ -281,8 +970,11 @@ pub struct QueryStats {
     pub successful_queries: u64,
     pub failed_queries: u64,
     pub average_execution_time: u64,
+    pub latency_percentiles: LatencyPercentiles,
     pub cache_hit_rate: f64,
     pub most_queried_cells: Vec<(Principal, u64)>,
+    /// Cells by average response time in milliseconds, slowest first
+    pub slowest_cells: Vec<(Principal, u64)>,
 }
 
 /// Query aggregator errors
@@ -297,8 +989,18 @@ pub enum QueryError {
     RegistrationFailed(String),
     InvalidQuery(String),
     CellUnavailable(Principal),
+    /// Target cells are on incompatible schema versions
+    SchemaMismatch(String),
     TimeoutExceeded,
     ResourceExhausted,
 }
 
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
 ic_cdk::export_candid!();
\ No newline at end of file
@@ -1,32 +1,61 @@
 //! Query optimization engine with intelligent caching and cycle cost minimization
 
-use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, RestrictedMemory, memory_manager::{MemoryManager, MemoryId}};
-use std::cell::RefCell;
+use ic_stable_structures::{StableBTreeMap, StableCell, memory_manager::MemoryId};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use crate::{QueryPlan, QueryStats, CoordinationStrategy, OptimizationConfig};
-use crate::coordination::CoordinatedResults;
+use crate::{QueryPlan, QueryStats, QueryExplanation, CellCostEstimate, CoordinationStrategy, OptimizationConfig, LatencyPercentiles};
+use crate::coordination::{impl_storable, memory, Coordination, CoordinatedResults, ComplexityLevel, ExecutionStrategy, Memory};
 
-type Memory = RestrictedMemory<DefaultMemoryImpl>;
 type QueryCache = StableBTreeMap<String, CachedQueryResult, Memory>;
 type ExecutionHistory = StableBTreeMap<String, QueryExecutionRecord, Memory>;
+/// Operator-chosen strategies keyed by query signature
+type PlanPins = StableBTreeMap<String, CoordinationStrategy, Memory>;
 
 thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
-        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
-
     static QUERY_CACHE: RefCell<QueryCache> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
-        )
+        StableBTreeMap::init(memory(MemoryId::new(3)))
     );
 
     static EXECUTION_HISTORY: RefCell<ExecutionHistory> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
-        )
+        StableBTreeMap::init(memory(MemoryId::new(4)))
+    );
+
+    static OPTIMIZATION_CONFIG: RefCell<StableCell<OptimizationConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            memory(MemoryId::new(12)),
+            OptimizationConfig::default(),
+        ).expect("Failed to initialize optimization config")
     );
+
+    static PLAN_PINS: RefCell<PlanPins> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(17)))
+    );
+
+    /// Distinguishes executions recorded within the same round, which share a timestamp
+    static EXECUTION_SEQUENCE: Cell<u64> = Cell::new(0);
+
+    /// When expired execution records were last pruned
+    static LAST_HISTORY_PRUNE: Cell<u64> = Cell::new(0);
 }
 
+/// Execution records older than this are pruned unless the config says otherwise
+const DEFAULT_HISTORY_RETENTION_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Execution records kept at most unless the config says otherwise
+const DEFAULT_MAX_HISTORY_RECORDS: u64 = 10_000;
+
+/// Window of recent executions behind the latency metrics
+const LATENCY_WINDOW_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Minimum time between scans of the history for expired records
+const HISTORY_PRUNE_INTERVAL_NANOS: u64 = 60 * 1_000_000_000;
+
+/// Cells listed in each `QueryStats` ranking
+const CELL_RANKING_SIZE: usize = 10;
+
+/// Cycles hot query warm-ups may spend per round unless the config says otherwise
+pub const DEFAULT_WARMUP_CYCLE_BUDGET: u64 = 2_000_000_000;
+
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct OptimizationConfig {
     pub cache_enabled: bool,
@@ -34,6 +63,32 @@ pub struct OptimizationConfig {
     pub max_cache_entries: u64,
     pub cost_optimization_enabled: bool,
     pub adaptive_batching: bool,
+    /// Age after which execution records are pruned; `DEFAULT_HISTORY_RETENTION_SECONDS` when `None`
+    #[serde(default)]
+    pub history_retention_seconds: Option<u64>,
+    /// Execution records kept at most, oldest pruned first; `DEFAULT_MAX_HISTORY_RECORDS` when `None`
+    #[serde(default)]
+    pub max_history_records: Option<u64>,
+    /// Cycles hot query warm-ups may spend per round; `DEFAULT_WARMUP_CYCLE_BUDGET` when `None`
+    #[serde(default)]
+    pub warmup_cycle_budget: Option<u64>,
+}
+
+impl_storable!(OptimizationConfig);
+
+impl Default for OptimizationConfig {
+    fn default() -> Self {
+        Self {
+            cache_enabled: true,
+            cache_ttl_seconds: 300,
+            max_cache_entries: 1000,
+            cost_optimization_enabled: true,
+            adaptive_batching: true,
+            history_retention_seconds: None,
+            max_history_records: None,
+            warmup_cycle_budget: None,
+        }
+    }
 }
 
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -44,6 +99,27 @@ struct CachedQueryResult {
     pub expires_at: u64,
     pub hit_count: u64,
     pub estimated_cycles_saved: u64,
+    /// Cells the result was read from
+    #[serde(default)]
+    pub cells: Vec<candid::Principal>,
+    /// Record fields the query reads; empty when unknown, matching every write
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Tables or other labels the result depends on, for `invalidate_by_tag`
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl_storable!(CachedQueryResult);
+
+/// Write notice sent by a Data Cell, see `notify_mutation`
+#[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct MutationEvent {
+    pub op: crate::ChangeOperation,
+    pub record_id: String,
+    /// Top-level fields of the written record
+    pub fields: Vec<String>,
+    pub timestamp: u64,
 }
 
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -52,10 +128,15 @@ struct QueryExecutionRecord {
     pub execution_time_ms: u64,
     pub cycles_consumed: u64,
     pub cells_involved: Vec<candid::Principal>,
+    /// Response time of each cell that answered
+    #[serde(default)]
+    pub cell_latencies_ms: Vec<(candid::Principal, u64)>,
     pub success: bool,
     pub timestamp: u64,
 }
 
+impl_storable!(QueryExecutionRecord);
+
 pub struct QueryOptimizer;
 
 impl QueryOptimizer {
@@ -64,6 +145,11 @@ impl QueryOptimizer {
         ic_cdk::println!("Initializing Query Optimizer - Cache: {}, Cost Optimization: {}",
                         config.cache_enabled, config.cost_optimization_enabled);
 
+        OPTIMIZATION_CONFIG.with(|cell| {
+            cell.borrow_mut().set(config.clone())
+                .expect("Failed to persist optimization config");
+        });
+
         // TODO: Configure optimization parameters in stable memory
         // - Set up cache eviction policies
         // - Initialize cost analysis models
@@ -78,8 +164,15 @@ impl QueryOptimizer {
         let query_signature = Self::generate_query_signature(&query_plan);
         let historical_performance = Self::get_historical_performance(&query_signature);
 
-        // Apply intelligent optimizations based on analysis
-        query_plan = Self::optimize_coordination_strategy(query_plan, &historical_performance).await?;
+        // Apply intelligent optimizations based on analysis; a pinned strategy overrides the heuristic
+        query_plan = match Self::pinned_strategy(&query_signature) {
+            Some(strategy) => {
+                ic_cdk::println!("Using pinned strategy {:?} for {}", strategy, query_signature);
+                query_plan.coordination_strategy = strategy;
+                query_plan
+            },
+            None => Self::optimize_coordination_strategy(query_plan, &historical_performance).await?,
+        };
         query_plan = Self::optimize_operation_order(query_plan).await?;
         query_plan = Self::apply_caching_strategy(query_plan).await?;
 
@@ -87,6 +180,63 @@ impl QueryOptimizer {
         Ok(query_plan)
     }
 
+    /// Describe how a plan would be executed without running it
+    pub async fn explain_plan(query_plan: QueryPlan) -> Result<QueryExplanation, Box<dyn std::error::Error>> {
+        let query_signature = Self::generate_query_signature(&query_plan);
+        let historical_performance = Self::get_historical_performance(&query_signature);
+
+        let plan_id = query_plan.id.clone();
+        let operations = query_plan.operations.clone();
+        let optimized = match Self::pinned_strategy(&query_signature) {
+            Some(strategy) => QueryPlan { coordination_strategy: strategy, ..query_plan },
+            None => Self::optimize_coordination_strategy(query_plan, &historical_performance).await?,
+        };
+
+        let cell_count = optimized.target_cells.len();
+        let execution_strategy = match optimized.coordination_strategy {
+            CoordinationStrategy::Sequential => ExecutionStrategy::Sequential,
+            CoordinationStrategy::Parallel | CoordinationStrategy::AdaptiveParallel => ExecutionStrategy::Parallel,
+            CoordinationStrategy::PipelinedStreaming => ExecutionStrategy::Streaming,
+        };
+        let resources = Coordination::calculate_resource_needs(&execution_strategy, cell_count);
+        let cycles_per_cell = resources.estimated_cycles / cell_count.max(1) as u64;
+
+        let complexity = ComplexityLevel::from_score(
+            operations.iter().map(Self::estimate_operation_cost).sum()
+        );
+
+        let cached = Self::get_cached_result(&query_signature)
+            .filter(|cached| cached.expires_at > ic_cdk::api::time());
+
+        // Only a cached result or an explicit limit bounds the row count
+        let estimated_rows = match &cached {
+            Some(cached) => Some(cached.result.len() as u64),
+            None => operations.iter()
+                .filter_map(|operation| match operation {
+                    crate::QueryOperation::Limit(limit) => Some(*limit),
+                    _ => None,
+                })
+                .min(),
+        };
+
+        Ok(QueryExplanation {
+            plan_id,
+            strategy: optimized.coordination_strategy,
+            cell_estimates: optimized.target_cells.iter()
+                .map(|cell_id| CellCostEstimate {
+                    cell_id: *cell_id,
+                    estimated_cycles: cycles_per_cell,
+                })
+                .collect(),
+            estimated_total_cycles: resources.estimated_cycles,
+            estimated_duration_ms: Coordination::estimate_execution_time(cell_count, complexity),
+            estimated_rows,
+            cache_hit: cached.is_some(),
+            pinned: Self::pinned_strategy(&query_signature).is_some(),
+            signature: Some(query_signature),
+        })
+    }
+
     /// Optimize coordination strategy based on historical performance and current conditions
     async fn optimize_coordination_strategy(mut query_plan: QueryPlan, history: &Option<QueryExecutionRecord>) -> Result<QueryPlan, Box<dyn std::error::Error>> {
         // Analyze current network conditions and cell performance
@@ -170,6 +320,9 @@ impl QueryOptimizer {
             records: sorted_records,
             total_count: results.total_count,
             cell_statistics: results.cell_stats,
+            cell_errors: results.cell_errors,
+            continuation_token: None,
+            trace_id: None,
         })
     }
 
@@ -191,12 +344,13 @@ impl QueryOptimizer {
     /// Apply global sorting across aggregated results
     async fn apply_global_sorting(mut records: Vec<serde_json::Value>) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
         // TODO: Implement configurable sorting with multiple sort keys
-        // For now, sort by timestamp if available
+        // For now, sort by timestamp if available, descending. Records with
+        // equal timestamps are ordered by their serialized JSON so the order
+        // is the same on every call and pages don't shift between requests.
 
-        records.sort_by(|a, b| {
-            let timestamp_a = a.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
-            let timestamp_b = b.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
-            timestamp_b.cmp(&timestamp_a) // Descending order
+        records.sort_by_cached_key(|record| {
+            let timestamp = record.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0);
+            (std::cmp::Reverse(timestamp), serde_json::to_string(record).unwrap_or_default())
         });
 
         Ok(records)
@@ -219,20 +373,63 @@ impl QueryOptimizer {
         })
     }
 
-    /// Get average query latency
+    /// Average latency of the queries executed within the last `LATENCY_WINDOW_NANOS`
     pub fn get_average_latency() -> u64 {
+        Self::average_latency_at(ic_cdk::api::time())
+    }
+
+    /// `get_average_latency` as of `now`
+    fn average_latency_at(now: u64) -> u64 {
+        let window_start = now.saturating_sub(LATENCY_WINDOW_NANOS);
+
         EXECUTION_HISTORY.with(|history| {
-            let history_ref = history.borrow();
-            if history_ref.is_empty() {
-                return 0;
+            let (total_time, count) = history.borrow().iter()
+                .filter(|(_, record)| record.timestamp >= window_start)
+                .fold((0u64, 0u64), |(total, count), (_, record)| (total + record.execution_time_ms, count + 1));
+
+            if count == 0 {
+                0
+            } else {
+                total_time / count
             }
+        })
+    }
 
-            let total_time: u64 = history_ref.iter()
+    /// Latency percentiles of the queries executed within the last `LATENCY_WINDOW_NANOS`
+    pub fn get_latency_percentiles() -> LatencyPercentiles {
+        Self::latency_percentiles_at(ic_cdk::api::time())
+    }
+
+    /// `get_latency_percentiles` as of `now`
+    fn latency_percentiles_at(now: u64) -> LatencyPercentiles {
+        let window_start = now.saturating_sub(LATENCY_WINDOW_NANOS);
+
+        let samples = EXECUTION_HISTORY.with(|history| {
+            history.borrow().iter()
+                .filter(|(_, record)| record.timestamp >= window_start)
                 .map(|(_, record)| record.execution_time_ms)
-                .sum();
+                .collect()
+        });
+        Self::percentiles(samples)
+    }
 
-            total_time / history_ref.len() as u64
-        })
+    /// Nearest-rank p50, p95 and p99 of latency samples; all 0 without samples
+    fn percentiles(mut samples: Vec<u64>) -> LatencyPercentiles {
+        samples.sort_unstable();
+
+        let rank = |percentile: u64| -> u64 {
+            if samples.is_empty() {
+                return 0;
+            }
+            let index = ((percentile * samples.len() as u64 + 99) / 100).max(1) - 1;
+            samples[index as usize]
+        };
+
+        LatencyPercentiles {
+            p50: rank(50),
+            p95: rank(95),
+            p99: rank(99),
+        }
     }
 
     /// Get cycle efficiency score
@@ -246,9 +443,15 @@ impl QueryOptimizer {
     }
 
     /// Get execution statistics for time window
+    ///
+    /// Windows longer than the history retention only cover the retained records.
     pub fn get_execution_stats(time_window: u64) -> QueryStats {
-        let current_time = ic_cdk::api::time();
-        let window_start = current_time.saturating_sub(time_window);
+        Self::execution_stats_at(ic_cdk::api::time(), time_window)
+    }
+
+    /// `get_execution_stats` as of `now`
+    fn execution_stats_at(now: u64, time_window: u64) -> QueryStats {
+        let window_start = now.saturating_sub(time_window);
 
         EXECUTION_HISTORY.with(|history| {
             let mut total_queries = 0u64;
@@ -256,11 +459,14 @@ impl QueryOptimizer {
             let mut failed_queries = 0u64;
             let mut total_execution_time = 0u64;
             let mut cell_query_counts = HashMap::new();
+            let mut cell_latencies: HashMap<candid::Principal, (u64, u64)> = HashMap::new();
+            let mut latencies = Vec::new();
 
             for (_, record) in history.borrow().iter() {
                 if record.timestamp >= window_start {
                     total_queries += 1;
                     total_execution_time += record.execution_time_ms;
+                    latencies.push(record.execution_time_ms);
 
                     if record.success {
                         successful_queries += 1;
@@ -272,6 +478,12 @@ impl QueryOptimizer {
                     for cell_id in &record.cells_involved {
                         *cell_query_counts.entry(*cell_id).or_insert(0) += 1;
                     }
+
+                    for (cell_id, latency_ms) in &record.cell_latencies_ms {
+                        let (total, count) = cell_latencies.entry(*cell_id).or_insert((0, 0));
+                        *total += latency_ms;
+                        *count += 1;
+                    }
                 }
             }
 
@@ -281,22 +493,50 @@ impl QueryOptimizer {
                 0
             };
 
-            // Get most queried cells
-            let mut most_queried: Vec<_> = cell_query_counts.into_iter().collect();
-            most_queried.sort_by(|a, b| b.1.cmp(&a.1));
-            most_queried.truncate(10); // Top 10
+            let most_queried = Self::rank_cells(cell_query_counts.into_iter().collect());
+            let slowest = Self::rank_cells(cell_latencies.into_iter()
+                .map(|(cell_id, (total, count))| (cell_id, total / count))
+                .collect());
 
             QueryStats {
                 total_queries,
                 successful_queries,
                 failed_queries,
                 average_execution_time,
+                latency_percentiles: Self::percentiles(latencies),
                 cache_hit_rate: Self::get_cache_hit_rate(),
                 most_queried_cells: most_queried,
+                slowest_cells: slowest,
             }
         })
     }
 
+    /// Top `CELL_RANKING_SIZE` cells by descending value, ties broken by cell ID
+    fn rank_cells(mut cells: Vec<(candid::Principal, u64)>) -> Vec<(candid::Principal, u64)> {
+        cells.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        cells.truncate(CELL_RANKING_SIZE);
+        cells
+    }
+
+    /// Make every plan with `signature` use `strategy`, replacing any earlier pin
+    pub fn pin_plan(signature: String, strategy: CoordinationStrategy) {
+        PLAN_PINS.with(|pins| pins.borrow_mut().insert(signature, strategy));
+    }
+
+    /// Return plans with `signature` to the heuristic, returning whether one was pinned
+    pub fn unpin_plan(signature: &str) -> bool {
+        PLAN_PINS.with(|pins| pins.borrow_mut().remove(&signature.to_string()).is_some())
+    }
+
+    /// Every pinned signature and its strategy
+    pub fn pinned_plans() -> Vec<(String, CoordinationStrategy)> {
+        PLAN_PINS.with(|pins| pins.borrow().iter().collect())
+    }
+
+    fn pinned_strategy(signature: &str) -> Option<CoordinationStrategy> {
+        PLAN_PINS.with(|pins| pins.borrow().get(&signature.to_string()))
+    }
+
     /// Generate query signature for caching and analysis
     fn generate_query_signature(query_plan: &QueryPlan) -> String {
         // TODO: Implement sophisticated query fingerprinting
@@ -310,6 +550,132 @@ impl QueryOptimizer {
                 query_plan.operations.len())
     }
 
+    /// Drop cached results read from `cell_id` that may include any of `fields`,
+    /// returning how many were dropped
+    ///
+    /// Empty `fields` drops every result read from the cell.
+    pub fn invalidate(cell_id: candid::Principal, fields: &[String]) -> u64 {
+        Self::evict(|cached| {
+            cached.cells.contains(&cell_id)
+                && (fields.is_empty()
+                    || cached.fields.is_empty()
+                    || cached.fields.iter().any(|field| fields.contains(field)))
+        })
+    }
+
+    /// Drop every cached result read from `cell_id`, returning how many were dropped
+    pub fn invalidate_by_cell(cell_id: candid::Principal) -> u64 {
+        Self::evict(|cached| cached.cells.contains(&cell_id))
+    }
+
+    /// Drop every cached result tagged with `tag`, returning how many were dropped
+    pub fn invalidate_by_tag(tag: &str) -> u64 {
+        Self::evict(|cached| cached.tags.iter().any(|cached_tag| cached_tag == tag))
+    }
+
+    /// Remove the cached results for which `matches` holds
+    fn evict(matches: impl Fn(&CachedQueryResult) -> bool) -> u64 {
+        QUERY_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let stale: Vec<String> = cache.iter()
+                .filter(|(_, cached)| matches(cached))
+                .map(|(query_hash, _)| query_hash)
+                .collect();
+
+            for query_hash in &stale {
+                cache.remove(query_hash);
+            }
+            stale.len() as u64
+        })
+    }
+
+    /// Current optimizer configuration
+    pub fn config() -> OptimizationConfig {
+        OPTIMIZATION_CONFIG.with(|cell| cell.borrow().get().clone())
+    }
+
+    /// Cache key of a batch query whose target cells are resolved
+    ///
+    /// Only the parts that decide which records come back are included, so
+    /// the same query with a different page size or cycle budget shares an
+    /// entry.
+    pub fn batch_signature(query: &crate::BatchQuery) -> String {
+        let mut cells = query.target_cells.clone();
+        cells.sort();
+        let parameters: std::collections::BTreeMap<_, _> = query.parameters.iter().collect();
+
+        format!("batch_{}_{:?}_{}_{:?}_{:?}_{:?}_{:?}",
+                query.query_sql,
+                cells,
+                serde_json::to_string(&parameters).unwrap_or_default(),
+                query.filters,
+                query.options.max_results,
+                query.options.consistency_level,
+                query.options.result_format)
+    }
+
+    /// Records of an unexpired cached result, counting the hit
+    pub fn cached_records(query_hash: &str) -> Option<Vec<serde_json::Value>> {
+        if !Self::config().cache_enabled {
+            return None;
+        }
+
+        QUERY_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let mut cached = cache.get(&query_hash.to_string())
+                .filter(|cached| cached.expires_at > ic_cdk::api::time())?;
+
+            cached.hit_count += 1;
+            let records = cached.result.clone();
+            cache.insert(query_hash.to_string(), cached);
+            Some(records)
+        })
+    }
+
+    /// Whether a result is missing from the cache or expires within `lead_nanos`
+    pub fn cache_expiring(query_hash: &str, lead_nanos: u64) -> bool {
+        Self::get_cached_result(query_hash)
+            .map_or(true, |cached| cached.expires_at <= ic_cdk::api::time().saturating_add(lead_nanos))
+    }
+
+    /// Cache a query result for the configured TTL
+    ///
+    /// When the cache is full, the entry closest to expiry makes room.
+    pub fn cache_result(query_hash: String, records: Vec<serde_json::Value>, cells: Vec<candid::Principal>, cycles_consumed: u64) {
+        let config = Self::config();
+        if !config.cache_enabled || config.max_cache_entries == 0 {
+            return;
+        }
+
+        let now = ic_cdk::api::time();
+        let cached = CachedQueryResult {
+            query_hash: query_hash.clone(),
+            result: records,
+            cached_at: now,
+            expires_at: now.saturating_add(config.cache_ttl_seconds.saturating_mul(1_000_000_000)),
+            hit_count: 0,
+            estimated_cycles_saved: cycles_consumed,
+            cells,
+            fields: Vec::new(),
+            tags: Vec::new(),
+        };
+
+        QUERY_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache.insert(query_hash, cached);
+
+            while cache.len() > config.max_cache_entries {
+                let soonest = cache.iter()
+                    .min_by_key(|(_, cached)| cached.expires_at)
+                    .map(|(query_hash, _)| query_hash);
+                match soonest {
+                    Some(query_hash) => cache.remove(&query_hash),
+                    None => break,
+                };
+            }
+        });
+    }
+
     /// Get cached query result if available and valid
     fn get_cached_result(query_hash: &str) -> Option<CachedQueryResult> {
         QUERY_CACHE.with(|cache| {
@@ -356,18 +722,68 @@ impl QueryOptimizer {
 
     /// Record query execution for future optimization
     fn record_execution(results: &CoordinatedResults, total_cycles: u64, avg_response_time: u64) {
+        let sequence = EXECUTION_SEQUENCE.with(|sequence| sequence.replace(sequence.get() + 1));
         let record = QueryExecutionRecord {
-            query_hash: format!("exec_{}", ic_cdk::api::time()),
+            query_hash: format!("exec_{}_{}", ic_cdk::api::time(), sequence),
             execution_time_ms: avg_response_time,
             cycles_consumed: total_cycles,
-            cells_involved: results.cell_stats.keys().cloned().collect(),
-            success: true,
+            cells_involved: results.cell_stats.keys().chain(results.cell_errors.keys()).cloned().collect(),
+            cell_latencies_ms: results.cell_stats.iter()
+                .map(|(cell_id, stats)| (*cell_id, stats.response_time_ms))
+                .collect(),
+            success: results.cell_errors.is_empty(),
             timestamp: ic_cdk::api::time(),
         };
 
         EXECUTION_HISTORY.with(|history| {
             history.borrow_mut().insert(record.query_hash.clone(), record);
         });
+
+        Self::prune_history();
+    }
+
+    /// Drop execution records past the retention window, then the oldest beyond the record cap
+    ///
+    /// The full scan for expired records runs at most once per
+    /// `HISTORY_PRUNE_INTERVAL_NANOS`; the cap is enforced on every call.
+    /// Record keys start with their timestamp, so key order is age order.
+    fn prune_history() {
+        Self::prune_history_at(ic_cdk::api::time());
+    }
+
+    /// `prune_history` as of `now`
+    fn prune_history_at(now: u64) {
+        let config = OPTIMIZATION_CONFIG.with(|cell| cell.borrow().get().clone());
+
+        EXECUTION_HISTORY.with(|history| {
+            let mut history = history.borrow_mut();
+
+            if now.saturating_sub(LAST_HISTORY_PRUNE.with(Cell::get)) >= HISTORY_PRUNE_INTERVAL_NANOS {
+                LAST_HISTORY_PRUNE.with(|last| last.set(now));
+
+                let retention_nanos = config.history_retention_seconds
+                    .unwrap_or(DEFAULT_HISTORY_RETENTION_SECONDS)
+                    .saturating_mul(1_000_000_000);
+                let cutoff = now.saturating_sub(retention_nanos);
+                let expired: Vec<String> = history.iter()
+                    .filter(|(_, record)| record.timestamp < cutoff)
+                    .map(|(key, _)| key)
+                    .collect();
+                for key in expired {
+                    history.remove(&key);
+                }
+            }
+
+            let max_records = config.max_history_records.unwrap_or(DEFAULT_MAX_HISTORY_RECORDS);
+            let excess = history.len().saturating_sub(max_records);
+            let oldest: Vec<String> = history.iter()
+                .take(excess as usize)
+                .map(|(key, _)| key)
+                .collect();
+            for key in oldest {
+                history.remove(&key);
+            }
+        });
     }
 
     pub fn pre_upgrade() {
@@ -394,4 +810,219 @@ pub enum QueryOperation {
     Join(String),
     Aggregate(String),
     Limit(u64),
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    const NOW: u64 = 1_700_000_000_000_000_000;
+    const SECOND: u64 = 1_000_000_000;
+
+    fn cell(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    /// Store an execution record `age` nanoseconds before `NOW`
+    fn executed(age: u64, execution_time_ms: u64, cell_latencies_ms: Vec<(Principal, u64)>) {
+        let timestamp = NOW - age;
+        let sequence = EXECUTION_SEQUENCE.with(|sequence| sequence.replace(sequence.get() + 1));
+        let record = QueryExecutionRecord {
+            query_hash: format!("exec_{}_{}", timestamp, sequence),
+            execution_time_ms,
+            cycles_consumed: 0,
+            cells_involved: cell_latencies_ms.iter().map(|(cell_id, _)| *cell_id).collect(),
+            cell_latencies_ms,
+            success: true,
+            timestamp,
+        };
+        EXECUTION_HISTORY.with(|history| history.borrow_mut().insert(record.query_hash.clone(), record));
+    }
+
+    #[test]
+    fn slowest_cells_are_ranked_by_mean_latency() {
+        executed(SECOND, 50, vec![(cell(1), 10), (cell(2), 200), (cell(3), 40)]);
+        executed(SECOND, 50, vec![(cell(1), 30), (cell(2), 100)]);
+        executed(SECOND, 50, vec![(cell(1), 20)]);
+
+        let stats = QueryOptimizer::execution_stats_at(NOW, 60 * SECOND);
+        assert_eq!(stats.slowest_cells, [(cell(2), 150), (cell(3), 40), (cell(1), 20)]);
+        assert_eq!(stats.most_queried_cells, [(cell(1), 3), (cell(2), 2), (cell(3), 1)]);
+    }
+
+    #[test]
+    fn rankings_only_cover_the_time_window() {
+        executed(2 * 60 * SECOND, 50, vec![(cell(1), 900)]);
+        executed(SECOND, 50, vec![(cell(1), 10), (cell(2), 20)]);
+
+        let stats = QueryOptimizer::execution_stats_at(NOW, 60 * SECOND);
+        assert_eq!(stats.total_queries, 1);
+        assert_eq!(stats.slowest_cells, [(cell(2), 20), (cell(1), 10)]);
+    }
+
+    fn configure_history(history_retention_seconds: Option<u64>, max_history_records: Option<u64>) {
+        let config = OptimizationConfig { history_retention_seconds, max_history_records, ..OptimizationConfig::default() };
+        OPTIMIZATION_CONFIG.with(|cell| cell.borrow_mut().set(config)).expect("Failed to set config");
+    }
+
+    fn history_ages() -> Vec<u64> {
+        EXECUTION_HISTORY.with(|history| history.borrow().iter().map(|(_, record)| NOW - record.timestamp).collect())
+    }
+
+    #[test]
+    fn records_past_the_retention_window_are_pruned() {
+        configure_history(Some(3_600), None);
+        executed(2 * 3_600 * SECOND, 10, vec![]);
+        executed(3_601 * SECOND, 10, vec![]);
+        executed(3_599 * SECOND, 10, vec![]);
+        executed(SECOND, 10, vec![]);
+
+        QueryOptimizer::prune_history_at(NOW);
+        assert_eq!(history_ages(), [3_599 * SECOND, SECOND]);
+
+        let stats = QueryOptimizer::execution_stats_at(NOW, 24 * 3_600 * SECOND);
+        assert_eq!(stats.total_queries, 2);
+    }
+
+    #[test]
+    fn the_oldest_records_beyond_the_cap_are_pruned() {
+        configure_history(None, Some(2));
+        for age in [4, 3, 2, 1] {
+            executed(age * SECOND, 10, vec![]);
+        }
+
+        QueryOptimizer::prune_history_at(NOW);
+        assert_eq!(history_ages(), [2 * SECOND, SECOND]);
+    }
+
+    #[test]
+    fn average_latency_only_counts_the_last_hour() {
+        for _ in 0..5 {
+            executed(2 * 3_600 * SECOND, 5_000, vec![]);
+        }
+        executed(30 * 60 * SECOND, 20, vec![]);
+        executed(SECOND, 40, vec![]);
+
+        assert_eq!(QueryOptimizer::average_latency_at(NOW), 30);
+        assert_eq!(QueryOptimizer::average_latency_at(NOW + 3_600 * SECOND), 0);
+    }
+
+    fn as_tuple(latencies: &LatencyPercentiles) -> (u64, u64, u64) {
+        (latencies.p50, latencies.p95, latencies.p99)
+    }
+
+    #[test]
+    fn percentiles_of_a_uniform_distribution() {
+        let samples: Vec<u64> = (1..=1_000).rev().collect();
+
+        assert_eq!(as_tuple(&QueryOptimizer::percentiles(samples)), (500, 950, 990));
+        assert_eq!(as_tuple(&QueryOptimizer::percentiles(vec![7])), (7, 7, 7));
+        assert_eq!(as_tuple(&QueryOptimizer::percentiles(Vec::new())), (0, 0, 0));
+    }
+
+    #[test]
+    fn tail_latency_shows_in_the_windowed_percentiles() {
+        // 95 fast queries and a slow tail of 5 within the hour, plus older outliers
+        for _ in 0..95 {
+            executed(60 * SECOND, 10, vec![]);
+        }
+        for latency in [500, 600, 700, 800, 900] {
+            executed(60 * SECOND, latency, vec![]);
+        }
+        for _ in 0..50 {
+            executed(2 * 3_600 * SECOND, 10_000, vec![]);
+        }
+
+        let latencies = QueryOptimizer::latency_percentiles_at(NOW);
+        assert_eq!(latencies.p50, 10);
+        assert_eq!(latencies.p95, 10);
+        assert_eq!(latencies.p99, 800);
+        assert!(QueryOptimizer::average_latency_at(NOW) < latencies.p99);
+    }
+
+    fn globally_sorted(records: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+        use futures::FutureExt;
+        QueryOptimizer::apply_global_sorting(records)
+            .now_or_never()
+            .expect("sorting does not suspend")
+            .expect("sorting failed")
+    }
+
+    #[test]
+    fn records_with_equal_timestamps_sort_the_same_whatever_their_arrival_order() {
+        let records: Vec<serde_json::Value> = (0..20)
+            .map(|i| serde_json::json!({"name": format!("item_{:02}", i), "timestamp": if i < 15 { 100 } else { 200 }}))
+            .collect();
+        let mut reversed = records.clone();
+        reversed.reverse();
+        let mut rotated = records.clone();
+        rotated.rotate_left(7);
+
+        let sorted = globally_sorted(records);
+        assert_eq!(globally_sorted(reversed), sorted);
+        assert_eq!(globally_sorted(rotated), sorted);
+
+        let names: Vec<&str> = sorted.iter().map(|record| record["name"].as_str().unwrap()).collect();
+        assert_eq!(&names[..5], ["item_15", "item_16", "item_17", "item_18", "item_19"]);
+        assert_eq!(&names[5..8], ["item_00", "item_01", "item_02"]);
+    }
+
+    fn cached(query_hash: &str, cells: Vec<Principal>, fields: &[&str], tags: &[&str]) {
+        let entry = CachedQueryResult {
+            query_hash: query_hash.to_string(),
+            result: Vec::new(),
+            cached_at: NOW,
+            expires_at: NOW + 60 * SECOND,
+            hit_count: 0,
+            estimated_cycles_saved: 0,
+            cells,
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        };
+        QUERY_CACHE.with(|cache| cache.borrow_mut().insert(query_hash.to_string(), entry));
+    }
+
+    fn cached_hashes() -> Vec<String> {
+        QUERY_CACHE.with(|cache| cache.borrow().iter().map(|(query_hash, _)| query_hash).collect())
+    }
+
+    #[test]
+    fn invalidating_a_cell_only_evicts_results_read_from_it() {
+        cached("one", vec![cell(1)], &[], &[]);
+        cached("both", vec![cell(1), cell(2)], &[], &[]);
+        cached("two", vec![cell(2)], &[], &[]);
+        cached("three", vec![cell(3)], &[], &[]);
+
+        assert_eq!(QueryOptimizer::invalidate_by_cell(cell(1)), 2);
+        assert_eq!(cached_hashes(), ["three", "two"]);
+        assert_eq!(QueryOptimizer::invalidate_by_cell(cell(1)), 0);
+    }
+
+    #[test]
+    fn invalidating_a_tag_only_evicts_results_carrying_it() {
+        cached("orders", vec![cell(1)], &[], &["orders"]);
+        cached("joined", vec![cell(1)], &[], &["orders", "users"]);
+        cached("users", vec![cell(1)], &[], &["users"]);
+        cached("untagged", vec![cell(1)], &[], &[]);
+
+        assert_eq!(QueryOptimizer::invalidate_by_tag("orders"), 2);
+        assert_eq!(cached_hashes(), ["untagged", "users"]);
+        assert_eq!(QueryOptimizer::invalidate_by_tag("missing"), 0);
+    }
+
+    #[test]
+    fn a_write_only_evicts_results_reading_its_fields() {
+        cached("by_name", vec![cell(1)], &["name"], &[]);
+        cached("by_score", vec![cell(1)], &["score"], &[]);
+        cached("unknown_fields", vec![cell(1)], &[], &[]);
+        cached("other_cell", vec![cell(2)], &["name"], &[]);
+
+        assert_eq!(QueryOptimizer::invalidate(cell(1), &["name".to_string()]), 2);
+        assert_eq!(cached_hashes(), ["by_score", "other_cell"]);
+
+        // A write that doesn't say what it touched evicts everything from the cell
+        assert_eq!(QueryOptimizer::invalidate(cell(1), &[]), 1);
+        assert_eq!(cached_hashes(), ["other_cell"]);
+    }
+}
@@ -1,22 +1,49 @@
 //! Streaming query execution engine optimized for Internet Computer's async model
 
 use candid::Principal;
-use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, RestrictedMemory, memory_manager::{MemoryManager, MemoryId}};
+use ic_stable_structures::{StableBTreeMap, StableCell, memory_manager::MemoryId};
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use crate::{QueryPlan, StreamHandle, StreamBatch, QueryError};
+use crate::{QueryOperation, QueryPlan, StreamHandle, StreamBatch, StreamStatus, QueryError};
+use crate::coordination::{impl_storable, memory, Coordination, Memory};
 
-type Memory = RestrictedMemory<DefaultMemoryImpl>;
 type StreamStorage = StableBTreeMap<String, StreamState, Memory>;
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
-        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+/// Serialized size a batch aims for, leaving headroom under the 2 MiB message limit
+const TARGET_BATCH_BYTES: u64 = 1024 * 1024;
+
+/// Buffer fill, as a percentage of `StreamingConfig.buffer_size`, at which prefetching pauses
+const HIGH_WATER_PERCENT: u64 = 80;
+
+/// Buffer fill, as a percentage of `StreamingConfig.buffer_size`, at which paused prefetching resumes
+const LOW_WATER_PERCENT: u64 = 40;
+
+/// Weight of the newest batch in a stream's running average record size, as 1/n
+const RECORD_SIZE_SMOOTHING: u64 = 4;
+
+/// Longest lifetime a stream can be given, whatever the configured timeout
+const MAX_STREAM_TIMEOUT_SECONDS: u64 = 24 * 60 * 60;
+
+/// Records fetched per cell call when merging sorted cell results
+const SORTED_PAGE_SIZE: u64 = 100;
 
+/// Longest a paused stream is held past its expiry
+const MAX_PAUSE_HOLD_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Error state of streams that could not be resumed after an upgrade
+const STREAM_INTERRUPTED: &str = "interrupted_by_upgrade";
+
+thread_local! {
     static ACTIVE_STREAMS: RefCell<StreamStorage> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
-        )
+        StableBTreeMap::init(memory(MemoryId::new(0)))
+    );
+
+    static STREAMING_CONFIG: RefCell<StableCell<StreamingConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            memory(MemoryId::new(9)),
+            StreamingConfig::default(),
+        ).expect("Failed to initialize streaming config")
     );
 }
 
@@ -26,17 +53,89 @@ pub struct StreamingConfig {
     pub max_concurrent_streams: u32,
     pub stream_timeout_seconds: u64,
     pub buffer_size: u32,
+    /// Buffer the next batch while serving the current one, so the following
+    /// pull is answered from memory; otherwise cells are read only on demand
     pub prefetch_enabled: bool,
+    /// Open streams allowed per caller, on top of the global `max_concurrent_streams`
+    pub max_streams_per_caller: Option<u32>,
+}
+
+impl_storable!(StreamingConfig);
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            default_batch_size: 100,
+            max_concurrent_streams: 100,
+            stream_timeout_seconds: 3600,
+            buffer_size: 1000,
+            prefetch_enabled: true,
+            max_streams_per_caller: None,
+        }
+    }
 }
 
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
 struct StreamState {
     pub handle: StreamHandle,
+    pub owner: Principal,
     pub query_plan: QueryPlan,
     pub current_position: u64,
     pub buffer: Vec<serde_json::Value>,
     pub is_complete: bool,
     pub error_state: Option<String>,
+    /// Running average serialized size of returned records, 0 until the first batch
+    pub avg_record_bytes: u64,
+    /// Batch size derived from `avg_record_bytes`, used once records have been observed
+    pub adaptive_batch_size: u32,
+    /// Set when the buffer reached the high-water mark, cleared below the low-water mark
+    pub prefetch_paused: bool,
+    /// Read position in each target cell
+    #[serde(default)]
+    pub cell_cursors: Vec<CellCursor>,
+    /// Paused by the client; no records are fetched from cells while set
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub paused_at: u64,
+    /// Keep the stream alive while paused, pushing its expiry back on resume
+    #[serde(default)]
+    pub hold_while_paused: bool,
+    /// Bumped on every write, so a pull can tell the stream changed while it awaited cells
+    #[serde(default)]
+    pub revision: u64,
+    /// Records fetched from cells so far, counted against the plan's `Limit`
+    #[serde(default)]
+    pub fetched: u64,
+}
+
+impl_storable!(StreamState);
+
+impl StreamState {
+    /// Whether the stream has expired; a held paused stream expires only after `MAX_PAUSE_HOLD_NANOS`
+    fn is_expired(&self, now: u64) -> bool {
+        if self.paused && self.hold_while_paused {
+            now > self.handle.expires_at.max(self.paused_at.saturating_add(MAX_PAUSE_HOLD_NANOS))
+        } else {
+            now > self.handle.expires_at
+        }
+    }
+}
+
+/// How far a stream has read into one of its target cells
+#[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct CellCursor {
+    pub cell_id: Principal,
+    /// The cell's `next_cursor` after the last page read
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Records read by offset, for streams opened before cursors were kept
+    pub offset: u64,
+    /// The cell reported no further records
+    pub exhausted: bool,
+    /// Fetched records not yet emitted, in sort order; only used by sorted streams
+    #[serde(default)]
+    pub pending: Vec<serde_json::Value>,
 }
 
 pub struct StreamingEngine;
@@ -46,30 +145,77 @@ impl StreamingEngine {
     pub fn init(config: &StreamingConfig) {
         ic_cdk::println!("Initializing Streaming Engine with batch size: {}", config.default_batch_size);
 
-        // TODO: Set up streaming configuration in stable memory
-        // - Configure buffer sizes
-        // - Set timeout parameters
-        // - Initialize performance monitoring
+        STREAMING_CONFIG.with(|cell| {
+            cell.borrow_mut().set(config.clone())
+                .expect("Failed to persist streaming config");
+        });
+    }
+
+    /// Get the persisted streaming configuration
+    pub fn config() -> StreamingConfig {
+        STREAMING_CONFIG.with(|cell| cell.borrow().get().clone())
     }
 
     /// Create new streaming query execution
-    pub async fn create_stream(query_plan: QueryPlan) -> Result<StreamHandle, Box<dyn std::error::Error>> {
+    ///
+    /// Streams apply only `Sort` and `Limit` operations; plans with any other
+    /// operation fail with `InvalidQuery`. Fails with `ResourceExhausted` when
+    /// `max_concurrent_streams` unexpired streams are open, or the caller
+    /// already holds `max_streams_per_caller`.
+    pub async fn create_stream(owner: Principal, query_plan: QueryPlan) -> Result<StreamHandle, QueryError> {
+        let unsupported = query_plan.operations.iter()
+            .find(|operation| !matches!(operation, QueryOperation::Sort(_) | QueryOperation::Limit(_)));
+        if let Some(operation) = unsupported {
+            return Err(QueryError::InvalidQuery(format!("Streams do not support {:?} operations", operation)));
+        }
+
+        let config = Self::config();
+        Self::purge_expired_streams();
+
+        let (total_streams, owner_streams) = ACTIVE_STREAMS.with(|streams| {
+            let streams_ref = streams.borrow();
+            let owner_streams = streams_ref.iter()
+                .filter(|(_, state)| state.owner == owner)
+                .count() as u32;
+            (streams_ref.len() as u32, owner_streams)
+        });
+
+        if total_streams >= config.max_concurrent_streams {
+            return Err(QueryError::ResourceExhausted);
+        }
+
+        if config.max_streams_per_caller.map_or(false, |limit| owner_streams >= limit) {
+            return Err(QueryError::ResourceExhausted);
+        }
+
         let stream_id = Self::generate_stream_id();
         let current_time = ic_cdk::api::time();
 
         let handle = StreamHandle {
             id: stream_id.clone(),
             created_at: current_time,
-            expires_at: current_time + (3600 * 1_000_000_000), // 1 hour expiry
+            expires_at: current_time + Self::stream_timeout_seconds(&query_plan, &config) * 1_000_000_000,
         };
 
         let stream_state = StreamState {
             handle: handle.clone(),
+            owner,
             query_plan: query_plan.clone(),
             current_position: 0,
             buffer: Vec::new(),
-            is_complete: false,
+            is_complete: query_plan.target_cells.is_empty() || stream_limit(&query_plan) == Some(0),
             error_state: None,
+            avg_record_bytes: 0,
+            adaptive_batch_size: config.default_batch_size,
+            prefetch_paused: false,
+            cell_cursors: query_plan.target_cells.iter()
+                .map(|cell_id| CellCursor { cell_id: *cell_id, cursor: None, offset: 0, exhausted: false, pending: Vec::new() })
+                .collect(),
+            paused: false,
+            paused_at: 0,
+            hold_while_paused: false,
+            revision: 0,
+            fetched: 0,
         };
 
         // Store stream state
@@ -78,11 +224,20 @@ impl StreamingEngine {
         });
 
         // Initialize streaming execution with intelligent prefetching
-        Self::start_stream_execution(&handle, query_plan).await?;
+        Self::start_stream_execution(&handle, query_plan).await
+            .map_err(|e| QueryError::ExecutionFailed(e.to_string()))?;
 
         Ok(handle)
     }
 
+    /// Lifetime of a new stream: the plan's own `stream_timeout_seconds` if it
+    /// has a streaming config, otherwise the engine's, capped at `MAX_STREAM_TIMEOUT_SECONDS`
+    fn stream_timeout_seconds(query_plan: &QueryPlan, config: &StreamingConfig) -> u64 {
+        query_plan.streaming_config.as_ref()
+            .map_or(config.stream_timeout_seconds, |plan_config| plan_config.stream_timeout_seconds)
+            .clamp(1, MAX_STREAM_TIMEOUT_SECONDS)
+    }
+
     /// Start asynchronous stream execution with optimal cell coordination
     async fn start_stream_execution(handle: &StreamHandle, query_plan: QueryPlan) -> Result<(), Box<dyn std::error::Error>> {
         ic_cdk::println!("Starting stream execution for: {}", handle.id);
@@ -103,43 +258,72 @@ impl StreamingEngine {
     }
 
     /// Get next batch of results from stream
-    pub async fn get_next_batch(handle: StreamHandle, batch_size: u32) -> Result<StreamBatch, Box<dyn std::error::Error>> {
+    ///
+    /// `batch_size` is used until the stream has returned records; after that the
+    /// batch size adapts to the observed record size so each batch stays near
+    /// `TARGET_BATCH_BYTES`, within `StreamingConfig.buffer_size`.
+    ///
+    /// Once a cell call has failed the stream is in an error state: records
+    /// already fetched are still returned, and every later pull fails with the
+    /// recorded reason.
+    ///
+    /// A pull that finds the stream closed, cancelled or changed by another
+    /// call once its cell calls return fails without returning or keeping
+    /// anything, so concurrent pulls never hand out the same records.
+    ///
+    /// Only the stream's owner may pull; anyone else gets `PermissionDenied`.
+    pub async fn get_next_batch(caller: Principal, handle: StreamHandle, batch_size: u32) -> Result<StreamBatch, Box<dyn std::error::Error>> {
         let stream_state = ACTIVE_STREAMS.with(|streams| {
             streams.borrow().get(&handle.id)
         });
 
         match stream_state {
             Some(mut state) => {
+                if state.owner != caller {
+                    return Err(Box::new(QueryError::PermissionDenied("Only the stream owner can pull from it".to_string())));
+                }
+
                 // Check stream expiry
-                if ic_cdk::api::time() > handle.expires_at {
+                if state.is_expired(ic_cdk::api::time()) {
                     return Err("Stream expired".into());
                 }
 
-                // TODO: Implement intelligent batch retrieval
-                // - Fetch from buffer or execute next query segment
-                // - Handle cross-cell result coordination
-                // - Apply result streaming optimizations
+                if let Some(reason) = &state.error_state {
+                    return Err(reason.clone().into());
+                }
+
+                let config = Self::config();
+                let max_batch_size = config.buffer_size.max(1);
+                let batch_size = if state.avg_record_bytes == 0 { batch_size } else { state.adaptive_batch_size }
+                    .clamp(1, max_batch_size);
+
+                // Serve buffered records first, then fetch the rest from cells
+                let buffered = state.buffer.len().min(batch_size as usize);
+                let mut records: Vec<serde_json::Value> = state.buffer.drain(0..buffered).collect();
+                if records.len() < batch_size as usize && !state.is_complete && !state.paused {
+                    let missing = batch_size - records.len() as u32;
+                    records.extend(self::fetch_more_data(&mut state, missing).await);
+                }
+
+                Self::prefetch(&mut state, batch_size, &config).await;
 
-                let records = if state.buffer.len() >= batch_size as usize {
-                    // Return from buffer
-                    state.buffer.drain(0..batch_size as usize).collect()
-                } else {
-                    // Fetch more data from cells
-                    self::fetch_more_data(&mut state, batch_size).await?
-                };
+                if let (Some(reason), true) = (state.error_state.clone(), records.is_empty()) {
+                    Self::commit_pull(&handle.id, state)?;
+                    return Err(reason.into());
+                }
 
-                let has_more = !state.is_complete || !state.buffer.is_empty();
+                let has_more = !state.is_complete || !state.buffer.is_empty() || state.error_state.is_some();
                 let estimated_remaining = if has_more { Some(1000u64) } else { None }; // TODO: Calculate actual estimate
 
                 // Update stream state
+                Self::adapt_batch_size(&mut state, &records, max_batch_size);
                 state.current_position += records.len() as u64;
-                ACTIVE_STREAMS.with(|streams| {
-                    streams.borrow_mut().insert(handle.id.clone(), state);
-                });
+                let batch_number = (state.current_position / batch_size as u64) as u32;
+                Self::commit_pull(&handle.id, state)?;
 
                 Ok(StreamBatch {
                     stream_handle: handle,
-                    batch_number: (state.current_position / batch_size as u64) as u32,
+                    batch_number,
                     records,
                     has_more,
                     estimated_remaining,
@@ -149,13 +333,161 @@ impl StreamingEngine {
         }
     }
 
-    /// Close stream and cleanup resources
-    pub async fn close_stream(handle: StreamHandle) -> Result<(), Box<dyn std::error::Error>> {
-        ic_cdk::println!("Closing stream: {}", handle.id);
+    /// Write back the state a pull worked on, unless the stream was removed or
+    /// written by another call while the pull awaited its cells
+    ///
+    /// The stored stream is left as it is in that case: its cursors still
+    /// point before the records this pull fetched, so they are read again.
+    fn commit_pull(stream_id: &str, mut state: StreamState) -> Result<(), String> {
+        ACTIVE_STREAMS.with(|streams| {
+            let mut streams_ref = streams.borrow_mut();
+            match streams_ref.get(&stream_id.to_string()) {
+                None => Err("Stream was closed while the pull was running".to_string()),
+                Some(stored) if stored.revision != state.revision => {
+                    Err("Stream was changed by another call while the pull was running".to_string())
+                },
+                Some(_) => {
+                    state.revision += 1;
+                    streams_ref.insert(stream_id.to_string(), state);
+                    Ok(())
+                },
+            }
+        })
+    }
+
+    /// Buffer the next segment ahead of the consumer, subject to backpressure
+    ///
+    /// Prefetching pauses once the buffer reaches the high-water mark and only
+    /// resumes after the consumer drains it below the low-water mark, so a slow
+    /// consumer never grows the buffer past `StreamingConfig.buffer_size`.
+    async fn prefetch(state: &mut StreamState, batch_size: u32, config: &StreamingConfig) {
+        if !config.prefetch_enabled || state.is_complete || state.paused || state.error_state.is_some() {
+            return;
+        }
+
+        let (high_water, low_water) = water_marks(config.buffer_size);
+        let buffered = state.buffer.len() as u64;
+
+        if state.prefetch_paused {
+            if buffered > low_water {
+                return;
+            }
+            state.prefetch_paused = false;
+        }
+
+        let room = high_water.saturating_sub(buffered);
+        if room == 0 {
+            state.prefetch_paused = true;
+            return;
+        }
+
+        let records = self::fetch_more_data(state, batch_size.min(room as u32)).await;
+        state.buffer.extend(records);
+
+        if state.buffer.len() as u64 >= high_water {
+            state.prefetch_paused = true;
+        }
+    }
+
+    /// Fold a batch's record sizes into the stream's running average and resize
+    ///
+    /// Large records shrink the next batch to stay under the message limit;
+    /// small records grow it to save round trips.
+    fn adapt_batch_size(state: &mut StreamState, records: &[serde_json::Value], max_batch_size: u32) {
+        if records.is_empty() {
+            return;
+        }
+
+        let batch_bytes: u64 = records.iter()
+            .map(|record| serde_json::to_vec(record).map(|bytes| bytes.len() as u64).unwrap_or(0))
+            .sum();
+        let batch_avg = (batch_bytes / records.len() as u64).max(1);
+
+        state.avg_record_bytes = if state.avg_record_bytes == 0 {
+            batch_avg
+        } else {
+            (state.avg_record_bytes * (RECORD_SIZE_SMOOTHING - 1) + batch_avg) / RECORD_SIZE_SMOOTHING
+        };
+
+        state.adaptive_batch_size = (TARGET_BATCH_BYTES / state.avg_record_bytes.max(1))
+            .clamp(1, max_batch_size as u64) as u32;
+    }
+
+    /// Pause or resume a stream owned by `caller`
+    ///
+    /// Pausing stops all fetching from cells, prefetching included; pulls are
+    /// served from the buffer only. With `hold`, the paused stream is kept past
+    /// its expiry (for at most `MAX_PAUSE_HOLD_NANOS`), and resuming pushes the
+    /// expiry back by the time spent paused.
+    pub fn set_paused(caller: Principal, handle: &StreamHandle, paused: bool, hold: bool) -> Result<(), QueryError> {
+        let now = ic_cdk::api::time();
+        let mut state = ACTIVE_STREAMS.with(|streams| streams.borrow().get(&handle.id))
+            .filter(|state| !state.is_expired(now))
+            .ok_or_else(|| QueryError::StreamingFailed("Stream not found or expired".to_string()))?;
+
+        if state.owner != caller {
+            return Err(QueryError::PermissionDenied("Only the stream owner can pause or resume it".to_string()));
+        }
+
+        if paused && !state.paused {
+            state.paused = true;
+            state.paused_at = now;
+            state.hold_while_paused = hold;
+        } else if !paused && state.paused {
+            if state.hold_while_paused {
+                let paused_for = now.saturating_sub(state.paused_at);
+                state.handle.expires_at = state.handle.expires_at.saturating_add(paused_for);
+            }
+            state.paused = false;
+            state.hold_while_paused = false;
+        }
+        state.revision += 1;
 
         ACTIVE_STREAMS.with(|streams| {
-            streams.borrow_mut().remove(&handle.id);
+            streams.borrow_mut().insert(handle.id.clone(), state);
         });
+        Ok(())
+    }
+
+    /// Current progress of an unexpired stream owned by `caller`
+    pub fn stream_status(caller: Principal, handle: &StreamHandle) -> Result<StreamStatus, QueryError> {
+        let now = ic_cdk::api::time();
+        let state = ACTIVE_STREAMS.with(|streams| streams.borrow().get(&handle.id))
+            .filter(|state| !state.is_expired(now))
+            .ok_or_else(|| QueryError::StreamingFailed("Stream not found or expired".to_string()))?;
+
+        if state.owner != caller {
+            return Err(QueryError::PermissionDenied("Only the stream owner can inspect it".to_string()));
+        }
+
+        Ok(StreamStatus {
+            position: state.current_position,
+            is_complete: state.is_complete && state.buffer.is_empty(),
+            buffered: state.buffer.len() as u64,
+            expires_at: state.handle.expires_at,
+            error: state.error_state,
+            paused: state.paused,
+        })
+    }
+
+    /// Close a stream owned by `caller` and cleanup resources
+    ///
+    /// Closing a stream that no longer exists succeeds.
+    pub async fn close_stream(caller: Principal, handle: StreamHandle) -> Result<(), Box<dyn std::error::Error>> {
+        ic_cdk::println!("Closing stream: {}", handle.id);
+
+        ACTIVE_STREAMS.with(|streams| {
+            let mut streams_ref = streams.borrow_mut();
+            match streams_ref.get(&handle.id) {
+                Some(state) if state.owner != caller => {
+                    Err(QueryError::PermissionDenied("Only the stream owner can close it".to_string()))
+                },
+                _ => {
+                    streams_ref.remove(&handle.id);
+                    Ok(())
+                },
+            }
+        })?;
 
         // TODO: Cleanup any ongoing cell communications
         // TODO: Release allocated resources
@@ -163,13 +495,46 @@ impl StreamingEngine {
         Ok(())
     }
 
-    /// Get count of active streams
+    /// Drop every stream, returning how many were dropped
+    pub fn cancel_all_streams() -> u64 {
+        ACTIVE_STREAMS.with(|streams| {
+            let mut streams_ref = streams.borrow_mut();
+            let ids: Vec<String> = streams_ref.iter().map(|(id, _)| id).collect();
+            for id in &ids {
+                streams_ref.remove(id);
+            }
+            ids.len() as u64
+        })
+    }
+
+    /// Get count of active (unexpired) streams
     pub fn get_active_stream_count() -> u32 {
+        let now = ic_cdk::api::time();
+
         ACTIVE_STREAMS.with(|streams| {
-            streams.borrow().len() as u32
+            streams.borrow().iter()
+                .filter(|(_, state)| !state.is_expired(now))
+                .count() as u32
         })
     }
 
+    /// Drop streams past their expiry so they stop counting against limits
+    fn purge_expired_streams() {
+        let now = ic_cdk::api::time();
+
+        ACTIVE_STREAMS.with(|streams| {
+            let mut streams_ref = streams.borrow_mut();
+            let expired: Vec<String> = streams_ref.iter()
+                .filter(|(_, state)| state.is_expired(now))
+                .map(|(id, _)| id)
+                .collect();
+
+            for id in expired {
+                streams_ref.remove(&id);
+            }
+        });
+    }
+
     /// Generate unique stream identifier
     fn generate_stream_id() -> String {
         // TODO: Implement cryptographically secure stream ID generation
@@ -180,34 +545,303 @@ impl StreamingEngine {
         // Stable structures handle persistence automatically
     }
 
+    /// Re-establish streams after an upgrade
+    ///
+    /// Stream state, including each cell cursor and the buffer, lives in stable
+    /// memory, so a stream resumes with its next pull re-issuing cell queries
+    /// from where its cursors stopped. Streams without cursors can't be resumed
+    /// and are failed with `STREAM_INTERRUPTED` so clients reopen them.
     pub fn post_upgrade() {
-        // Stable structures handle restoration automatically
+        Self::purge_expired_streams();
+
+        ACTIVE_STREAMS.with(|streams| {
+            let mut streams_ref = streams.borrow_mut();
+            let interrupted: Vec<(String, StreamState)> = streams_ref.iter()
+                .filter(|(_, state)| !state.is_complete && state.error_state.is_none())
+                .filter(|(_, state)| state.cell_cursors.is_empty() && !state.query_plan.target_cells.is_empty())
+                .collect();
+
+            for (id, mut state) in interrupted {
+                state.error_state = Some(STREAM_INTERRUPTED.to_string());
+                streams_ref.insert(id, state);
+            }
+        });
     }
 }
 
-/// Fetch additional data from cells for streaming
-async fn fetch_more_data(state: &mut StreamState, batch_size: u32) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
-    // TODO: Implement intelligent data fetching
-    // - Coordinate with multiple cells
-    // - Apply query operations
-    // - Handle result transformation and filtering
+/// High- and low-water marks of a stream buffer, in records
+fn water_marks(buffer_size: u32) -> (u64, u64) {
+    let buffer_size = buffer_size.max(1) as u64;
+    let high_water = (buffer_size * HIGH_WATER_PERCENT / 100).max(1);
+    let low_water = buffer_size * LOW_WATER_PERCENT / 100;
+    (high_water, low_water)
+}
 
+/// Fetch up to `batch_size` further records from the stream's cells
+///
+/// A failed cell call stops the fetch and puts the stream into its error
+/// state; the records fetched before the failure are still returned. The
+/// stream completes once its cells are exhausted or its `Limit` is reached.
+async fn fetch_more_data(state: &mut StreamState, batch_size: u32) -> Vec<serde_json::Value> {
     ic_cdk::println!("Fetching more data for stream at position: {}", state.current_position);
 
-    // Placeholder implementation
+    let limit = stream_limit(&state.query_plan);
+    let remaining = limit.map_or(u64::MAX, |limit| limit.saturating_sub(state.fetched));
+    let batch_size = (batch_size as u64).min(remaining) as u32;
+
+    let records = match stream_sort(&state.query_plan) {
+        _ if batch_size == 0 => Vec::new(),
+        Some((field, descending)) => fetch_sorted(state, batch_size, &field, descending).await,
+        None => fetch_unsorted(state, batch_size).await,
+    };
+    state.fetched += records.len() as u64;
+
+    state.is_complete = limit.map_or(false, |limit| state.fetched >= limit)
+        || state.cell_cursors.iter().all(|cursor| cursor.exhausted && cursor.pending.is_empty());
+    records
+}
+
+/// Read cells one after another, each until it is exhausted
+async fn fetch_unsorted(state: &mut StreamState, batch_size: u32) -> Vec<serde_json::Value> {
     let mut records = Vec::new();
-    for i in 0..batch_size.min(10) {
-        records.push(serde_json::json!({
-            "id": format!("record_{}", state.current_position + i as u64),
-            "data": "placeholder_data",
-            "timestamp": ic_cdk::api::time()
-        }));
+    for cursor in state.cell_cursors.iter_mut().filter(|cursor| !cursor.exhausted) {
+        let wanted = (batch_size as u64).saturating_sub(records.len() as u64);
+        if wanted == 0 {
+            break;
+        }
+
+        match Coordination::stream_page(cursor.cell_id, cursor.cursor.clone(), cursor.offset, wanted, None).await {
+            Ok(page) => {
+                cursor.exhausted = page.next_cursor.is_none();
+                cursor.cursor = page.next_cursor;
+                records.extend(page.records);
+            },
+            Err(error) => {
+                state.error_state = Some(format!("Cell {} failed mid-stream: {}", cursor.cell_id, error));
+                break;
+            },
+        }
+    }
+    records
+}
+
+/// K-way merge of the cells' sorted results, so records come out in global order across batches
+///
+/// A record can only be emitted once every cell that may still hold records
+/// has a candidate to compare against, so the stream keeps at least one
+/// record per unfinished cell in `pending`, and up to `SORTED_PAGE_SIZE`.
+async fn fetch_sorted(state: &mut StreamState, batch_size: u32, field: &str, descending: bool) -> Vec<serde_json::Value> {
+    let mut records = Vec::new();
+
+    while records.len() < batch_size as usize {
+        for cursor in state.cell_cursors.iter_mut().filter(|cursor| cursor.pending.is_empty() && !cursor.exhausted) {
+            let sort = Some((field, descending));
+            match Coordination::stream_page(cursor.cell_id, cursor.cursor.clone(), cursor.offset, SORTED_PAGE_SIZE, sort).await {
+                Ok(page) => {
+                    cursor.exhausted = page.next_cursor.is_none();
+                    cursor.cursor = page.next_cursor;
+                    cursor.pending = page.records;
+                },
+                Err(error) => {
+                    state.error_state = Some(format!("Cell {} failed mid-stream: {}", cursor.cell_id, error));
+                    break;
+                },
+            }
+        }
+
+        if state.error_state.is_some() {
+            break;
+        }
+
+        let next = state.cell_cursors.iter_mut()
+            .filter(|cursor| !cursor.pending.is_empty())
+            .min_by(|a, b| compare_sort_keys(a.pending[0].get(field), b.pending[0].get(field), descending));
+
+        match next {
+            Some(cursor) => records.push(cursor.pending.remove(0)),
+            None => break,
+        }
+    }
+
+    records
+}
+
+/// Sort field and direction of a plan's `Sort` operation, e.g. `"price"` or `"price DESC"`
+fn stream_sort(plan: &QueryPlan) -> Option<(String, bool)> {
+    plan.operations.iter().find_map(|operation| match operation {
+        QueryOperation::Sort(spec) => {
+            let mut parts = spec.split_whitespace();
+            let field = parts.next()?.to_string();
+            let descending = parts.next().map_or(false, |direction| direction.eq_ignore_ascii_case("desc"));
+            Some((field, descending))
+        },
+        _ => None,
+    })
+}
+
+/// The smallest of a plan's `Limit` operations, if it has any
+fn stream_limit(plan: &QueryPlan) -> Option<u64> {
+    plan.operations.iter()
+        .filter_map(|operation| match operation {
+            QueryOperation::Limit(limit) => Some(*limit),
+            _ => None,
+        })
+        .min()
+}
+
+/// Order two sort keys the way data cells do: records missing the field sort after the rest
+///
+/// Present values rank by type first (null < bool < number < string < other)
+/// and then by value, matching the cells' index order.
+fn compare_sort_keys(a: Option<&serde_json::Value>, b: Option<&serde_json::Value>, descending: bool) -> Ordering {
+    let ordering = match (a, b) {
+        (Some(x), Some(y)) => compare_sort_values(x, y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+
+    if descending { ordering.reverse() } else { ordering }
+}
+
+fn compare_sort_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    use serde_json::Value;
+
+    fn type_rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) | Value::Object(_) => 4,
+        }
     }
 
-    // Simulate stream completion after some records
-    if state.current_position > 100 {
-        state.is_complete = true;
+    type_rank(a).cmp(&type_rank(b)).then_with(|| match (a, b) {
+        (Value::Number(x), Value::Number(y)) => compare_numbers(x, y),
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Null, Value::Null) => Ordering::Equal,
+        _ => a.to_string().cmp(&b.to_string()),
+    })
+}
+
+/// Compare two JSON numbers exactly, so `u64` keys above 2^53 don't collapse through `f64`
+fn compare_numbers(x: &serde_json::Number, y: &serde_json::Number) -> Ordering {
+    let integer = |n: &serde_json::Number| n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from));
+
+    match (integer(x), integer(y)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(a), None) => compare_integer_float(a, y.as_f64().unwrap_or(0.0)),
+        (None, Some(b)) => compare_integer_float(b, x.as_f64().unwrap_or(0.0)).reverse(),
+        (None, None) => x.as_f64().unwrap_or(0.0).total_cmp(&y.as_f64().unwrap_or(0.0)),
     }
+}
 
-    Ok(records)
-}
\ No newline at end of file
+fn compare_integer_float(a: i128, f: f64) -> Ordering {
+    match (a as f64).total_cmp(&f) {
+        Ordering::Equal => a.cmp(&(f as i128)),
+        ordering => ordering,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoordinationStrategy, QueryType};
+    use serde_json::json;
+
+    fn stream_state() -> StreamState {
+        StreamState {
+            handle: StreamHandle { id: "stream".to_string(), created_at: 0, expires_at: u64::MAX },
+            owner: Principal::anonymous(),
+            query_plan: QueryPlan {
+                id: "plan".to_string(),
+                query_type: QueryType::CrossCell,
+                target_cells: Vec::new(),
+                operations: Vec::new(),
+                coordination_strategy: CoordinationStrategy::Sequential,
+                streaming_config: None,
+            },
+            current_position: 0,
+            buffer: Vec::new(),
+            is_complete: false,
+            error_state: None,
+            avg_record_bytes: 0,
+            adaptive_batch_size: 100,
+            prefetch_paused: false,
+            cell_cursors: Vec::new(),
+            paused: false,
+            paused_at: 0,
+            hold_while_paused: false,
+            revision: 0,
+            fetched: 0,
+        }
+    }
+
+    /// `count` records of roughly `bytes` serialized bytes each
+    fn records(count: usize, bytes: usize) -> Vec<serde_json::Value> {
+        (0..count).map(|_| json!({ "payload": "x".repeat(bytes) })).collect()
+    }
+
+    #[test]
+    fn batch_size_adapts_to_alternating_record_sizes() {
+        let mut state = stream_state();
+        let large = records(4, 256 * 1024);
+        let small = records(50, 16);
+
+        StreamingEngine::adapt_batch_size(&mut state, &large, 1_000);
+        let after_large = state.adaptive_batch_size;
+        assert!(after_large <= 4, "large records gave batches of {}", after_large);
+        assert!(after_large as u64 * state.avg_record_bytes <= TARGET_BATCH_BYTES);
+
+        StreamingEngine::adapt_batch_size(&mut state, &small, 1_000);
+        let after_small = state.adaptive_batch_size;
+        assert!(after_small > after_large);
+
+        StreamingEngine::adapt_batch_size(&mut state, &large, 1_000);
+        assert!(state.adaptive_batch_size < after_small);
+
+        for _ in 0..40 {
+            StreamingEngine::adapt_batch_size(&mut state, &small, 1_000);
+        }
+        assert_eq!(state.adaptive_batch_size, 1_000);
+
+        StreamingEngine::adapt_batch_size(&mut state, &large, 1_000);
+        assert!(state.adaptive_batch_size < 1_000);
+    }
+
+    #[test]
+    fn batch_size_stays_within_buffer_size() {
+        let mut state = stream_state();
+
+        StreamingEngine::adapt_batch_size(&mut state, &records(10, 16), 50);
+        assert_eq!(state.adaptive_batch_size, 50);
+
+        let mut state = stream_state();
+        StreamingEngine::adapt_batch_size(&mut state, &records(1, 2 * 1024 * 1024), 50);
+        assert_eq!(state.adaptive_batch_size, 1);
+    }
+
+    #[test]
+    fn empty_batches_leave_the_batch_size_alone() {
+        let mut state = stream_state();
+
+        StreamingEngine::adapt_batch_size(&mut state, &[], 1_000);
+        assert_eq!(state.avg_record_bytes, 0);
+        assert_eq!(state.adaptive_batch_size, 100);
+    }
+
+    #[test]
+    fn sort_keys_rank_types_then_compare_numbers_exactly() {
+        let mut keys = vec![
+            json!("a"), json!(u64::MAX), json!(u64::MAX - 1), json!(null), json!(true), json!(1.5), json!({"x": 1}),
+        ];
+        keys.sort_by(|a, b| compare_sort_keys(Some(a), Some(b), false));
+        assert_eq!(keys, [
+            json!(null), json!(true), json!(1.5), json!(u64::MAX - 1), json!(u64::MAX), json!("a"), json!({"x": 1}),
+        ]);
+
+        assert_eq!(compare_sort_keys(Some(&json!(0)), None, false), Ordering::Less);
+    }
+}
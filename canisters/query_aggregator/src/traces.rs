@@ -0,0 +1,111 @@
+//! Trace spans correlating a batch query with each cell call it made
+//!
+//! Every batch query gets a trace ID, which is also sent to the cells in the
+//! query filter so their logs can be matched up. Spans are kept for the most
+//! recent queries only, `MAX_TRACE_SPANS` in total, oldest dropped first.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::ops::Bound;
+use crate::coordination::{impl_storable, memory, Memory};
+
+/// Spans kept across all traces
+const MAX_TRACE_SPANS: u64 = 10_000;
+
+/// Separator between the trace ID and sequence parts of a span key
+const KEY_SEPARATOR: char = '\0';
+
+/// Spans keyed by `trace_id\0sequence`; trace IDs sort by creation time
+type TraceSpans = StableBTreeMap<String, TraceSpan, Memory>;
+
+thread_local! {
+    static SPANS: RefCell<TraceSpans> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(15)))
+    );
+
+    /// Disambiguates trace IDs and span keys created in the same round
+    static SEQUENCE: Cell<u64> = Cell::new(0);
+}
+
+/// One step of a traced query
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TraceSpan {
+    pub trace_id: String,
+    /// Step name, e.g. `batch_query`, `plan` or `cell_query`
+    pub name: String,
+    /// Cell called, for cell spans
+    pub cell_id: Option<Principal>,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub records: u64,
+    pub error: Option<String>,
+}
+
+impl_storable!(TraceSpan);
+
+pub struct Traces;
+
+impl Traces {
+    /// Fresh trace ID, sorting after every earlier one
+    pub fn new_trace_id() -> String {
+        format!("trace_{:020}_{}", ic_cdk::api::time(), Self::next_sequence())
+    }
+
+    fn next_sequence() -> u64 {
+        SEQUENCE.with(|sequence| {
+            let next = sequence.get();
+            sequence.set(next.wrapping_add(1));
+            next
+        })
+    }
+
+    /// Record a span that started at `started_at` and ends now
+    pub fn record(
+        trace_id: &str,
+        name: &str,
+        cell_id: Option<Principal>,
+        started_at: u64,
+        records: u64,
+        error: Option<String>,
+    ) {
+        let span = TraceSpan {
+            trace_id: trace_id.to_string(),
+            name: name.to_string(),
+            cell_id,
+            started_at,
+            duration_ms: ic_cdk::api::time().saturating_sub(started_at) / 1_000_000,
+            records,
+            error,
+        };
+        let key = format!("{}{}{:020}", trace_id, KEY_SEPARATOR, Self::next_sequence());
+
+        SPANS.with(|spans| {
+            let mut spans = spans.borrow_mut();
+            spans.insert(key, span);
+
+            while spans.len() > MAX_TRACE_SPANS {
+                match spans.iter().next() {
+                    Some((oldest, _)) => spans.remove(&oldest),
+                    None => break,
+                };
+            }
+        });
+    }
+
+    /// Spans of a trace, ordered by start time
+    pub fn get(trace_id: &str) -> Vec<TraceSpan> {
+        let prefix = format!("{}{}", trace_id, KEY_SEPARATOR);
+
+        let mut trace: Vec<TraceSpan> = SPANS.with(|spans| {
+            spans.borrow()
+                .range((Bound::Included(prefix.clone()), Bound::Unbounded))
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .map(|(_, span)| span)
+                .collect()
+        });
+        trace.sort_by_key(|span| span.started_at);
+        trace
+    }
+}
@@ -0,0 +1,370 @@
+//! Materialized cross-cell views maintained incrementally from cell change feeds
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::coordination::{impl_storable, memory, Coordination, Memory};
+use crate::Pagination;
+
+/// Method on this canister that cells deliver change events to
+const CHANGE_CALLBACK_METHOD: &str = "on_cell_change";
+
+/// Records requested per `export_chunk` call while backfilling a view
+const BACKFILL_CHUNK_SIZE: u32 = 500;
+
+/// Separator between the parts of view row and source keys
+const KEY_SEPARATOR: char = '\0';
+
+type ViewCatalog = StableBTreeMap<String, ViewState, Memory>;
+/// Rows as JSON text, keyed by `view\0row_key`
+type ViewRows = StableBTreeMap<String, String, Memory>;
+/// Last seen version of each source record as JSON text, keyed by `view\0cell\0record_id`
+type ViewSources = StableBTreeMap<String, String, Memory>;
+
+thread_local! {
+    static VIEWS: RefCell<ViewCatalog> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(5)))
+    );
+
+    static VIEW_ROWS: RefCell<ViewRows> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(6)))
+    );
+
+    static VIEW_SOURCES: RefCell<ViewSources> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(7)))
+    );
+}
+
+/// Definition of a materialized view over one or more Data Cells
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ViewDefinition {
+    pub name: String,
+    pub source_cells: Vec<Principal>,
+    pub kind: ViewKind,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ViewKind {
+    /// Every source record, tagged with `_cell` and `_id`
+    Union,
+    /// Number of records per distinct value of a field
+    CountBy(String),
+    /// Sum of a numeric field per distinct value of another field
+    SumBy { field: String, group_by: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+struct ViewState {
+    pub definition: ViewDefinition,
+    pub created_at: u64,
+    pub last_refreshed: u64,
+}
+
+impl_storable!(ViewState);
+
+/// Change event as emitted by a Data Cell's change feed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub op: ChangeOperation,
+    pub record_id: String,
+    pub record: Option<serde_json::Value>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Page of a Data Cell export, without the schema carried by the first chunk
+#[derive(CandidType, Deserialize)]
+struct ExportChunk {
+    /// Record ID and JSON text of each record
+    records: Vec<(String, String)>,
+    next_cursor: Option<String>,
+}
+
+pub struct Views;
+
+impl Views {
+    /// Create a view, subscribe to its source cells and backfill existing records
+    pub async fn create_view(definition: ViewDefinition) -> Result<(), Box<dyn std::error::Error>> {
+        if definition.name.is_empty() || definition.name.contains(KEY_SEPARATOR) {
+            return Err("View name must be non-empty and must not contain NUL".into());
+        }
+
+        if definition.source_cells.is_empty() {
+            return Err("View requires at least one source cell".into());
+        }
+
+        if VIEWS.with(|views| views.borrow().contains_key(&definition.name)) {
+            return Err(format!("View already exists: {}", definition.name).into());
+        }
+
+        let now = ic_cdk::api::time();
+        let name = definition.name.clone();
+        VIEWS.with(|views| {
+            views.borrow_mut().insert(name.clone(), ViewState {
+                definition,
+                created_at: now,
+                last_refreshed: now,
+            });
+        });
+
+        Self::refresh_view(&name).await
+    }
+
+    /// Query a view's rows, keeping those whose fields equal every filter entry
+    pub fn query_view(
+        name: &str,
+        filter: &HashMap<String, serde_json::Value>,
+        pagination: &Pagination,
+    ) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+        if !VIEWS.with(|views| views.borrow().contains_key(&name.to_string())) {
+            return Err(format!("View not found: {}", name).into());
+        }
+
+        let prefix = view_prefix(name);
+        Ok(VIEW_ROWS.with(|rows| {
+            rows.borrow()
+                .range(prefix.clone()..)
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .filter_map(|(_, row)| serde_json::from_str::<serde_json::Value>(&row).ok())
+                .filter(|row| filter.iter().all(|(field, value)| row.get(field) == Some(value)))
+                .skip(pagination.offset as usize)
+                .take(pagination.limit as usize)
+                .collect()
+        }))
+    }
+
+    /// Apply a change event from a source cell to every view reading from it
+    pub fn apply_change(cell_id: Principal, event: ChangeEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let views = Self::views_for_cell(&cell_id);
+        if views.is_empty() {
+            return Err(format!("No view reads from cell {}", cell_id).into());
+        }
+
+        let record = match event.op {
+            ChangeOperation::Delete => None,
+            ChangeOperation::Insert | ChangeOperation::Update => event.record,
+        };
+
+        for state in views {
+            Self::apply_record(&state.definition, cell_id, &event.record_id, record.clone());
+            Self::touch(&state.definition.name, event.timestamp);
+        }
+
+        Ok(())
+    }
+
+    /// Re-subscribe to source cells and rebuild every view
+    ///
+    /// Events delivered while the aggregator was upgrading are lost and the
+    /// cell may have disabled the subscription, so views are rebuilt from a
+    /// full export rather than trusted as-is.
+    pub async fn reconcile() {
+        let names: Vec<String> = VIEWS.with(|views| {
+            views.borrow().iter().map(|(name, _)| name).collect()
+        });
+
+        for name in names {
+            if let Err(e) = Self::refresh_view(&name).await {
+                ic_cdk::println!("Failed to reconcile view {}: {}", name, e);
+            }
+        }
+    }
+
+    /// Subscribe to a view's source cells and rebuild its rows from their exports
+    async fn refresh_view(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let state = VIEWS.with(|views| views.borrow().get(&name.to_string()))
+            .ok_or_else(|| format!("View not found: {}", name))?;
+
+        Self::clear_view(name);
+
+        for cell_id in &state.definition.source_cells {
+            let (subscribed,): (Result<(), candid::Reserved>,) = Coordination::call_cell(
+                *cell_id,
+                "subscribe",
+                (ic_cdk::id(), CHANGE_CALLBACK_METHOD.to_string()),
+            ).await?;
+            subscribed.map_err(|_| format!("Cell {} rejected subscription", cell_id))?;
+
+            Self::backfill(&state.definition, *cell_id).await?;
+        }
+
+        Self::touch(name, ic_cdk::api::time());
+        Ok(())
+    }
+
+    /// Feed every existing record of a cell into a view
+    async fn backfill(definition: &ViewDefinition, cell_id: Principal) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let (chunk,): (ExportChunk,) = Coordination::call_cell(
+                cell_id,
+                "export_chunk",
+                (cursor.clone(), BACKFILL_CHUNK_SIZE),
+            ).await?;
+
+            for (record_id, record) in chunk.records {
+                if let Ok(record) = serde_json::from_str(&record) {
+                    Self::apply_record(definition, cell_id, &record_id, Some(record));
+                }
+            }
+
+            match chunk.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Replace a source record's contribution to a view with its new version
+    fn apply_record(definition: &ViewDefinition, cell_id: Principal, record_id: &str, record: Option<serde_json::Value>) {
+        let source_key = format!("{}{}{}{}", view_prefix(&definition.name), cell_id, KEY_SEPARATOR, record_id);
+
+        let previous = VIEW_SOURCES.with(|sources| {
+            let mut sources_ref = sources.borrow_mut();
+            match &record {
+                Some(record) => sources_ref.insert(source_key.clone(), record.to_string()),
+                None => sources_ref.remove(&source_key),
+            }
+        }).and_then(|previous| serde_json::from_str::<serde_json::Value>(&previous).ok());
+
+        match &definition.kind {
+            ViewKind::Union => VIEW_ROWS.with(|rows| {
+                let mut rows_ref = rows.borrow_mut();
+                match record {
+                    Some(mut record) => {
+                        if let Some(object) = record.as_object_mut() {
+                            object.insert("_cell".to_string(), serde_json::json!(cell_id.to_string()));
+                            object.insert("_id".to_string(), serde_json::json!(record_id));
+                        }
+                        rows_ref.insert(source_key, record.to_string());
+                    },
+                    None => {
+                        rows_ref.remove(&source_key);
+                    },
+                }
+            }),
+            ViewKind::CountBy(group_by) => {
+                if let Some(previous) = &previous {
+                    Self::adjust_group(&definition.name, previous, group_by, "count", -1.0, -1);
+                }
+                if let Some(record) = &record {
+                    Self::adjust_group(&definition.name, record, group_by, "count", 1.0, 1);
+                }
+            },
+            ViewKind::SumBy { field, group_by } => {
+                let amount = |r: &serde_json::Value| r.get(field).and_then(|v| v.as_f64()).unwrap_or(0.0);
+                if let Some(previous) = &previous {
+                    Self::adjust_group(&definition.name, previous, group_by, "sum", -amount(previous), -1);
+                }
+                if let Some(record) = &record {
+                    Self::adjust_group(&definition.name, record, group_by, "sum", amount(record), 1);
+                }
+            },
+        }
+    }
+
+    /// Add `delta` to the aggregate of the group a record falls into
+    ///
+    /// `members` tracks how many source records fall into the group so that
+    /// groups are removed once the last one leaves.
+    fn adjust_group(view: &str, record: &serde_json::Value, group_by: &str, aggregate: &str, delta: f64, members: i64) {
+        let group = record.get(group_by).cloned().unwrap_or(serde_json::Value::Null);
+        let row_key = format!("{}{}", view_prefix(view), group);
+
+        VIEW_ROWS.with(|rows| {
+            let mut rows_ref = rows.borrow_mut();
+            let row = rows_ref.get(&row_key)
+                .and_then(|row| serde_json::from_str::<serde_json::Value>(&row).ok());
+
+            let members = row.as_ref()
+                .and_then(|r| r.get("_members"))
+                .and_then(|v| v.as_i64())
+                .unwrap_or(0) + members;
+
+            if members <= 0 {
+                rows_ref.remove(&row_key);
+                return;
+            }
+
+            let current = row.as_ref()
+                .and_then(|r| r.get(aggregate))
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0);
+
+            let mut updated = serde_json::Map::new();
+            updated.insert(group_by.to_string(), group);
+            updated.insert(aggregate.to_string(), serde_json::json!(current + delta));
+            updated.insert("_members".to_string(), serde_json::json!(members));
+            rows_ref.insert(row_key, serde_json::Value::Object(updated).to_string());
+        });
+    }
+
+    /// Views that read from a cell
+    fn views_for_cell(cell_id: &Principal) -> Vec<ViewState> {
+        VIEWS.with(|views| {
+            views.borrow().iter()
+                .map(|(_, state)| state)
+                .filter(|state| state.definition.source_cells.contains(cell_id))
+                .collect()
+        })
+    }
+
+    /// Remove all rows and source records of a view
+    fn clear_view(name: &str) {
+        let prefix = view_prefix(name);
+
+        VIEW_ROWS.with(|rows| {
+            let mut rows_ref = rows.borrow_mut();
+            let keys: Vec<String> = rows_ref.range(prefix.clone()..)
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, _)| key)
+                .collect();
+            for key in keys {
+                rows_ref.remove(&key);
+            }
+        });
+
+        VIEW_SOURCES.with(|sources| {
+            let mut sources_ref = sources.borrow_mut();
+            let keys: Vec<String> = sources_ref.range(prefix.clone()..)
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, _)| key)
+                .collect();
+            for key in keys {
+                sources_ref.remove(&key);
+            }
+        });
+    }
+
+    fn touch(name: &str, timestamp: u64) {
+        VIEWS.with(|views| {
+            let mut views_ref = views.borrow_mut();
+            if let Some(mut state) = views_ref.get(&name.to_string()) {
+                state.last_refreshed = timestamp;
+                views_ref.insert(name.to_string(), state);
+            }
+        });
+    }
+
+    pub fn pre_upgrade() {
+        // Stable structures handle persistence automatically
+    }
+
+    pub fn post_upgrade() {
+        // Calls can't be made from post_upgrade; reconcile once it completes
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, || ic_cdk::spawn(Self::reconcile()));
+    }
+}
+
+fn view_prefix(name: &str) -> String {
+    format!("{}{}", name, KEY_SEPARATOR)
+}
@@ -0,0 +1,175 @@
+//! Hot queries kept warm in the query cache
+//!
+//! Operators register batch queries that dashboards run often. A timer
+//! re-executes each one whose cached result is missing, e.g. after a cell
+//! write invalidated it, or close to expiry, so clients hit a warm cache.
+//! Warm-ups of one round share the config's `warmup_cycle_budget`; queries
+//! left over when it runs out wait for the next round.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, memory_manager::MemoryId};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use crate::{BatchQuery, QueryError};
+use crate::optimization::{QueryOptimizer, DEFAULT_WARMUP_CYCLE_BUDGET};
+use crate::coordination::{impl_storable, memory, Memory};
+
+/// Seconds between warm-up rounds
+const WARMUP_INTERVAL_SECONDS: u64 = 60;
+
+/// Results expiring within this window are refreshed, so they never lapse between rounds
+const WARMUP_LEAD_NANOS: u64 = 2 * WARMUP_INTERVAL_SECONDS * 1_000_000_000;
+
+type HotQueryStorage = StableBTreeMap<String, HotQuery, Memory>;
+
+thread_local! {
+    static HOT_QUERIES: RefCell<HotQueryStorage> = RefCell::new(
+        StableBTreeMap::init(memory(MemoryId::new(16)))
+    );
+
+    /// Set while a round runs, so a slow round isn't overlapped by the next one
+    static WARMING: Cell<bool> = Cell::new(false);
+
+    /// Disambiguates hot query IDs created in the same round
+    static SEQUENCE: Cell<u64> = Cell::new(0);
+}
+
+/// Batch query the aggregator keeps cached
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HotQuery {
+    pub id: String,
+    pub query: BatchQuery,
+    pub registered_by: Principal,
+    pub registered_at: u64,
+    pub last_warmed_at: Option<u64>,
+    /// Why the latest warm-up failed, cleared by the next successful one
+    pub last_error: Option<String>,
+}
+
+impl_storable!(HotQuery);
+
+pub struct HotQueries;
+
+impl HotQueries {
+    /// Start the warm-up timer; timers don't survive upgrades, so call after each one too
+    pub fn schedule() {
+        ic_cdk_timers::set_timer_interval(
+            std::time::Duration::from_secs(WARMUP_INTERVAL_SECONDS),
+            || ic_cdk::spawn(Self::warm_due()),
+        );
+    }
+
+    /// Register a query and warm it right away, returning its ID
+    pub fn register(caller: Principal, query: BatchQuery) -> String {
+        let now = ic_cdk::api::time();
+        let sequence = SEQUENCE.with(|sequence| {
+            let next = sequence.get();
+            sequence.set(next.wrapping_add(1));
+            next
+        });
+        let id = format!("hot_{:020}_{}", now, sequence);
+
+        HOT_QUERIES.with(|queries| {
+            queries.borrow_mut().insert(id.clone(), HotQuery {
+                id: id.clone(),
+                query,
+                registered_by: caller,
+                registered_at: now,
+                last_warmed_at: None,
+                last_error: None,
+            });
+        });
+
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, || ic_cdk::spawn(Self::warm_due()));
+        id
+    }
+
+    /// Stop warming a query, returning whether it was registered
+    ///
+    /// Its cached result stays until it expires or is invalidated.
+    pub fn unregister(id: &str) -> bool {
+        HOT_QUERIES.with(|queries| queries.borrow_mut().remove(&id.to_string()).is_some())
+    }
+
+    /// Every registered hot query
+    pub fn list() -> Vec<HotQuery> {
+        HOT_QUERIES.with(|queries| {
+            queries.borrow().iter().map(|(_, query)| query).collect()
+        })
+    }
+
+    /// Execute and cache every hot query whose result is missing or about to expire
+    async fn warm_due() {
+        if WARMING.with(|warming| warming.replace(true)) {
+            return;
+        }
+
+        let config = QueryOptimizer::config();
+        if config.cache_enabled {
+            let budget = config.warmup_cycle_budget.unwrap_or(DEFAULT_WARMUP_CYCLE_BUDGET);
+            let mut spent = 0u64;
+
+            for hot in Self::list() {
+                let remaining = budget.saturating_sub(spent);
+                if remaining == 0 {
+                    ic_cdk::println!("Warm-up cycle budget of {} spent; remaining hot queries wait a round", budget);
+                    break;
+                }
+
+                if let Some((cycles, outcome)) = Self::warm(hot.query.clone(), remaining).await {
+                    spent = spent.saturating_add(cycles);
+                    Self::record_outcome(&hot.id, outcome.err().map(|error| error.to_string()));
+                }
+            }
+        }
+
+        WARMING.with(|warming| warming.set(false));
+    }
+
+    /// Refresh one query's cached result if due, returning the cycles spent
+    /// and the outcome, or `None` when it was still warm
+    ///
+    /// Results with failed cells aren't cached, since they are incomplete.
+    async fn warm(mut query: BatchQuery, budget: u64) -> Option<(u64, Result<(), QueryError>)> {
+        if let Err(error) = crate::resolve_targets(&mut query) {
+            return Some((0, Err(error)));
+        }
+
+        let signature = QueryOptimizer::batch_signature(&query);
+        if !QueryOptimizer::cache_expiring(&signature, WARMUP_LEAD_NANOS) {
+            return None;
+        }
+
+        query.options.max_cycles = Some(query.options.max_cycles.map_or(budget, |max| max.min(budget)));
+        let cells = query.target_cells.clone();
+        let result = match crate::run_batch_query(ic_cdk::id(), query).await {
+            Ok(result) => result,
+            Err(error) => return Some((0, Err(error))),
+        };
+
+        let cycles: u64 = result.cell_statistics.values()
+            .map(|stats| stats.cycles_consumed)
+            .sum();
+        if !result.cell_errors.is_empty() {
+            return Some((cycles, Err(QueryError::ExecutionFailed(format!(
+                "{} cells failed during warm-up", result.cell_errors.len()
+            )))));
+        }
+
+        QueryOptimizer::cache_result(signature, result.records, cells, cycles);
+        Some((cycles, Ok(())))
+    }
+
+    fn record_outcome(id: &str, error: Option<String>) {
+        HOT_QUERIES.with(|queries| {
+            let mut queries = queries.borrow_mut();
+            if let Some(mut hot) = queries.get(&id.to_string()) {
+                if error.is_none() {
+                    hot.last_warmed_at = Some(ic_cdk::api::time());
+                }
+                hot.last_error = error;
+                queries.insert(id.to_string(), hot);
+            }
+        });
+    }
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn distinct_merges_and_dedupes_values_across_cells() {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "a", "category": "tools"}));
+    mesh.insert(mesh.cells[0], json!({"name": "b", "category": "books"}));
+    mesh.insert(mesh.cells[1], json!({"name": "c", "category": "tools"}));
+    mesh.insert(mesh.cells[1], json!({"name": "d", "category": "garden"}));
+
+    let (result,): (Result<Vec<String>, QueryError>,) = mesh.update(
+        user(), "distinct", ("category".to_string(), mesh.cells.clone(), page(100)),
+    );
+    let mut values: Vec<serde_json::Value> = result.unwrap().iter().map(|value| parse(value)).collect();
+    values.sort_by_key(|value| value.to_string());
+    assert_eq!(values, vec![json!("books"), json!("garden"), json!("tools")]);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+
+fn denied<T: std::fmt::Debug>(result: Result<T, QueryError>) {
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))), "expected PermissionDenied, got {:?}", result);
+}
+
+#[test]
+fn anonymous_callers_cannot_modify_the_aggregator() {
+    let mesh = Mesh::new(1);
+    let anonymous = Principal::anonymous();
+    let stray = Mesh::install_cell(&mesh.pic, cell_config("stray", 1));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(anonymous, "register_cell", (registration(stray, "stray"),));
+    denied(result);
+    let (result,): (Result<(), QueryError>,) = mesh.update(anonymous, "unregister_cell", (mesh.cells[0],));
+    denied(result);
+    let (result,): (Result<(), QueryError>,) = mesh.update(anonymous, "create_materialized_view", (ViewDefinition {
+        name: "everything".to_string(),
+        source_cells: mesh.cells.clone(),
+        kind: ViewKind::Union,
+    },));
+    denied(result);
+    let (result,): (Result<String, QueryError>,) =
+        mesh.update(anonymous, "register_hot_query", (batch_query(mesh.cells.clone()),));
+    denied(result);
+    let (result,): (Result<u64, QueryError>,) = mesh.update(anonymous, "cancel_all_streams", ());
+    denied(result);
+    let (result,): (Result<(), QueryError>,) = mesh.update(anonymous, "refresh_service_description", ());
+    denied(result);
+
+    // The registered cell is still queryable
+    assert!(mesh.batch(batch_query(mesh.cells.clone())).is_ok());
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn string_filter(field: &str, operator: FilterOperator, value: &str) -> BatchFilter {
+    BatchFilter {
+        field: field.to_string(),
+        operator,
+        value: value.to_string(),
+        case_insensitive: None,
+    }
+}
+
+#[test]
+fn ends_with_and_matches_filters_are_evaluated_by_every_cell() {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "report.pdf"}));
+    mesh.insert(mesh.cells[0], json!({"name": "notes.txt"}));
+    mesh.insert(mesh.cells[1], json!({"name": "invoice.pdf"}));
+    mesh.insert(mesh.cells[1], json!({"name": "order-17"}));
+
+    let query = BatchQuery {
+        filters: Some(vec![string_filter("name", FilterOperator::EndsWith, ".pdf")]),
+        ..batch_query(mesh.cells.clone())
+    };
+    assert_eq!(names(&mesh.batch(query).unwrap()), ["invoice.pdf", "report.pdf"]);
+
+    let query = BatchQuery {
+        filters: Some(vec![string_filter("name", FilterOperator::Matches, "^(order|notes)[.-]")]),
+        ..batch_query(mesh.cells.clone())
+    };
+    assert_eq!(names(&mesh.batch(query).unwrap()), ["notes.txt", "order-17"]);
+}
+
+#[test]
+fn invalid_matches_pattern_is_reported_per_cell() {
+    let mesh = Mesh::new(1);
+    mesh.insert(mesh.cells[0], json!({"name": "report.pdf"}));
+
+    let query = BatchQuery {
+        filters: Some(vec![string_filter("name", FilterOperator::Matches, "([")]),
+        ..batch_query(mesh.cells.clone())
+    };
+    let result = mesh.batch(query).unwrap();
+    assert!(result.records.is_empty());
+    assert_eq!(result.cell_errors.len(), 1);
+}
+
+#[test]
+fn failing_cell_is_reported_while_the_others_return_data() {
+    let mesh = Mesh::new(3);
+    mesh.insert(mesh.cells[0], json!({"name": "first"}));
+    mesh.insert(mesh.cells[2], json!({"name": "third"}));
+    mesh.pic.stop_canister(mesh.cells[1], Some(controller())).expect("stop failed");
+
+    let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    assert_eq!(names(&result), ["first", "third"]);
+
+    let failed: Vec<_> = result.cell_errors.iter().map(|(cell_id, _)| *cell_id).collect();
+    assert_eq!(failed, [mesh.cells[1]]);
+    assert!(!result.cell_errors[0].1.is_empty());
+
+    let mut succeeded: Vec<_> = result.cell_statistics.iter().map(|(cell_id, _)| *cell_id).collect();
+    succeeded.sort();
+    let mut expected = vec![mesh.cells[0], mesh.cells[2]];
+    expected.sort();
+    assert_eq!(succeeded, expected);
+}
+
+#[test]
+fn strong_consistency_fails_when_any_cell_fails() {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "first"}));
+    mesh.pic.stop_canister(mesh.cells[1], Some(controller())).expect("stop failed");
+
+    let query = BatchQuery {
+        options: BatchQueryOptions { consistency_level: ConsistencyLevel::Strong, ..options() },
+        ..batch_query(mesh.cells.clone())
+    };
+    assert!(mesh.batch(query).is_err());
+}
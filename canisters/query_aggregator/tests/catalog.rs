@@ -0,0 +1,62 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+use serde_json::json;
+
+fn describe(mesh: &Mesh) -> ServiceDescription {
+    let (description,): (ServiceDescription,) = mesh.query(user(), "describe_service", ());
+    description
+}
+
+fn described(mesh: &Mesh, cell_id: Principal) -> Option<CellDescription> {
+    describe(mesh).cells.into_iter().find(|cell| cell.cell_id == cell_id)
+}
+
+#[test]
+fn a_newly_registered_cell_appears_with_its_schema() {
+    let mesh = Mesh::new(1);
+    let cell = Mesh::install_cell(&mesh.pic, cell_config("fresh", 1));
+    assert!(described(&mesh, cell).is_none());
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        group: Some("orders".to_string()),
+        ..registration(cell, "fresh")
+    },));
+    result.unwrap();
+
+    let description = described(&mesh, cell).expect("the new cell is not described");
+    assert_eq!(description.name, "fresh");
+    assert_eq!(description.group.as_deref(), Some("orders"));
+    assert_eq!(description.schema_version, 1);
+    let schema = parse(&description.json_schema.expect("no schema fetched"));
+    assert_eq!(schema["required"], json!(["name"]));
+    assert_eq!(schema["properties"]["score"], json!({"type": "number"}));
+
+    assert_eq!(describe(&mesh).query_types.len(), 5);
+    assert!(described(&mesh, mesh.cells[0]).is_some());
+}
+
+#[test]
+fn unregistered_cells_leave_the_description() {
+    let mesh = Mesh::new(2);
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "unregister_cell", (mesh.cells[1],));
+    result.unwrap();
+
+    let cells: Vec<Principal> = describe(&mesh).cells.iter().map(|cell| cell.cell_id).collect();
+    assert_eq!(cells, [mesh.cells[0]]);
+}
+
+#[test]
+fn refreshing_fetches_schemas_of_cells_registered_at_install() {
+    let mesh = Mesh::new(1);
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "refresh_service_description", ());
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "refresh_service_description", ());
+    result.unwrap();
+    let schema = described(&mesh, mesh.cells[0]).unwrap().json_schema.expect("no schema fetched");
+    assert_eq!(parse(&schema)["title"], json!("items"));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use std::time::Duration;
+
+fn circuit(mesh: &Mesh, cell_id: candid::Principal) -> Option<(CircuitState, u32)> {
+    mesh.metrics().circuit_breakers.into_iter()
+        .find(|status| status.cell_id == cell_id)
+        .map(|status| (status.state, status.consecutive_failures))
+}
+
+fn stopped_cell_mesh() -> Mesh {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "healthy"}));
+    mesh.insert(mesh.cells[1], json!({"name": "flaky"}));
+    mesh.pic.stop_canister(mesh.cells[1], Some(controller())).expect("stop failed");
+    mesh
+}
+
+#[test]
+fn consecutive_failures_open_the_circuit_and_short_circuit_calls() {
+    let mesh = stopped_cell_mesh();
+
+    for _ in 0..5 {
+        let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+        assert_eq!(names(&result), ["healthy"]);
+        assert!(result.cell_errors.iter().any(|(cell_id, _)| *cell_id == mesh.cells[1]));
+    }
+    assert_eq!(circuit(&mesh, mesh.cells[1]), Some((CircuitState::Open, 5)));
+    assert_eq!(circuit(&mesh, mesh.cells[0]), None);
+
+    // An open circuit fails without calling the cell, so the failure count stays put
+    let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    assert_eq!(names(&result), ["healthy"]);
+    assert_eq!(circuit(&mesh, mesh.cells[1]), Some((CircuitState::Open, 5)));
+}
+
+#[test]
+fn successful_probe_after_the_cooldown_closes_the_circuit() {
+    let mesh = stopped_cell_mesh();
+    for _ in 0..5 {
+        mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    }
+    assert_eq!(circuit(&mesh, mesh.cells[1]).map(|(state, _)| state), Some(CircuitState::Open));
+
+    mesh.pic.start_canister(mesh.cells[1], Some(controller())).expect("start failed");
+    mesh.pic.advance_time(Duration::from_secs(31));
+
+    let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    assert_eq!(names(&result), ["flaky", "healthy"]);
+    assert!(result.cell_errors.is_empty());
+    assert_eq!(circuit(&mesh, mesh.cells[1]), None);
+}
+
+#[test]
+fn failed_probe_reopens_the_circuit() {
+    let mesh = stopped_cell_mesh();
+    for _ in 0..5 {
+        mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    }
+
+    mesh.pic.advance_time(Duration::from_secs(31));
+    mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    assert_eq!(circuit(&mesh, mesh.cells[1]), Some((CircuitState::Open, 6)));
+}
@@ -0,0 +1,828 @@
+//! PocketIC harness for Query Aggregator integration tests
+//!
+//! Installs real Data Cells next to the aggregator, so both wasm modules must
+//! be built for `wasm32-unknown-unknown` (or pointed at with `DATA_CELL_WASM`
+//! and `QUERY_AGGREGATOR_WASM`), and `POCKET_IC_BIN` must name a PocketIC
+//! server binary. Record JSON travels as `text`, matching the `.did` files.
+
+#![allow(dead_code)]
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Deserialize, Principal};
+use pocket_ic::{query_candid_as, update_candid_as, PocketIc, PocketIcBuilder};
+use serde_json::Value;
+
+pub const CYCLES: u128 = 2_000_000_000_000;
+
+pub fn controller() -> Principal {
+    Principal::self_authenticating(b"controller")
+}
+
+pub fn user() -> Principal {
+    Principal::self_authenticating(b"user")
+}
+
+pub fn other_user() -> Principal {
+    Principal::self_authenticating(b"other user")
+}
+
+fn wasm(env_var: &str, file: &str) -> Vec<u8> {
+    let path = std::env::var(env_var).unwrap_or_else(|_| {
+        format!("{}/../../target/wasm32-unknown-unknown/release/{}", env!("CARGO_MANIFEST_DIR"), file)
+    });
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Could not read {} at {}: {}", file, path, e))
+}
+
+pub fn aggregator_wasm() -> Vec<u8> {
+    wasm("QUERY_AGGREGATOR_WASM", "query_aggregator.wasm")
+}
+
+pub fn cell_wasm() -> Vec<u8> {
+    wasm("DATA_CELL_WASM", "celldb.wasm")
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AggregatorConfig {
+    pub name: String,
+    pub registered_cells: Vec<CellRegistration>,
+    pub authorized_managers: Option<Vec<Principal>>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub streaming_config: StreamingConfig,
+    pub optimization_config: OptimizationConfig,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellRegistration {
+    pub cell_id: Principal,
+    pub name: String,
+    pub schema_version: u32,
+    pub capabilities: Vec<CellCapability>,
+    pub performance_hints: PerformanceHints,
+    pub replica_of: Option<Principal>,
+    pub group: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellCapability {
+    FullTextSearch,
+    GeospatialQueries,
+    AdvancedIndexing,
+    StreamingSupport,
+    BatchOperations,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CanisterVersion {
+    pub name: String,
+    pub build_version: String,
+    pub schema_version: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellHealth {
+    pub status: HealthStatus,
+    pub schema_version: u32,
+    pub record_count: u64,
+    pub capabilities: Vec<CellCapability>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded(String),
+    Error(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PerformanceHints {
+    pub typical_response_time_ms: u32,
+    pub max_concurrent_queries: u32,
+    pub preferred_batch_size: u32,
+    pub subnet_location: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StreamingConfig {
+    pub default_batch_size: u32,
+    pub max_concurrent_streams: u32,
+    pub stream_timeout_seconds: u64,
+    pub buffer_size: u32,
+    pub prefetch_enabled: bool,
+    pub max_streams_per_caller: Option<u32>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct OptimizationConfig {
+    pub cache_enabled: bool,
+    pub cache_ttl_seconds: u64,
+    pub max_cache_entries: u64,
+    pub cost_optimization_enabled: bool,
+    pub adaptive_batching: bool,
+    pub history_retention_seconds: Option<u64>,
+    pub max_history_records: Option<u64>,
+    pub warmup_cycle_budget: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BatchQuery {
+    pub query_sql: String,
+    pub target_cells: Vec<Principal>,
+    pub target_group: Option<String>,
+    pub parameters: Vec<(String, String)>,
+    pub filters: Option<Vec<BatchFilter>>,
+    pub options: BatchQueryOptions,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BatchFilter {
+    pub field: String,
+    pub operator: FilterOperator,
+    pub value: String,
+    pub case_insensitive: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum FilterOperator {
+    Equals,
+    StartsWith,
+    EndsWith,
+    Matches,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BatchQueryOptions {
+    pub max_results: Option<u64>,
+    pub page_size: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub consistency_level: ConsistencyLevel,
+    pub result_format: ResultFormat,
+    pub allow_full_scan: Option<bool>,
+    pub required_capabilities: Option<Vec<CellCapability>>,
+    pub require_same_schema: Option<bool>,
+    pub max_cycles: Option<u64>,
+    pub priority: Option<QueryPriority>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum QueryPriority {
+    High,
+    Normal,
+    Low,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ConsistencyLevel {
+    Strong,
+    Eventual,
+    Weak,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ResultFormat {
+    Json,
+    Binary,
+    Streaming,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Pagination {
+    pub offset: u64,
+    pub limit: u64,
+    pub cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QueryPlan {
+    pub id: String,
+    pub query_type: QueryType,
+    pub target_cells: Vec<Principal>,
+    pub operations: Vec<QueryOperation>,
+    pub coordination_strategy: CoordinationStrategy,
+    pub streaming_config: Option<StreamingConfig>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum QueryType {
+    SingleCell,
+    CrossCell,
+    Aggregation,
+    Join,
+    Search,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum QueryOperation {
+    Filter(String),
+    Sort(String),
+    Join(String),
+    Aggregate(String),
+    Limit(u64),
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum CoordinationStrategy {
+    Sequential,
+    Parallel,
+    AdaptiveParallel,
+    PipelinedStreaming,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QueryExplanation {
+    pub plan_id: String,
+    pub strategy: CoordinationStrategy,
+    pub cell_estimates: Vec<CellCostEstimate>,
+    pub estimated_total_cycles: u64,
+    pub estimated_duration_ms: u64,
+    pub estimated_rows: Option<u64>,
+    pub cache_hit: bool,
+    pub pinned: bool,
+    pub signature: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellCostEstimate {
+    pub cell_id: Principal,
+    pub estimated_cycles: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StreamHandle {
+    pub id: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StreamBatch {
+    pub stream_handle: StreamHandle,
+    pub batch_number: u32,
+    pub records: Vec<String>,
+    pub has_more: bool,
+    pub estimated_remaining: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StreamStatus {
+    pub position: u64,
+    pub is_complete: bool,
+    pub buffered: u64,
+    pub expires_at: u64,
+    pub error: Option<String>,
+    pub paused: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ServiceDescription {
+    pub cells: Vec<CellDescription>,
+    pub query_types: Vec<QueryType>,
+    pub generated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellDescription {
+    pub cell_id: Principal,
+    pub name: String,
+    pub group: Option<String>,
+    pub schema_version: u32,
+    pub capabilities: Vec<CellCapability>,
+    pub replica_of: Option<Principal>,
+    pub json_schema: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum GatewayListing {
+    Allowed,
+    Denied,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GatewayRules {
+    pub allowlist_only: bool,
+    pub allowlist: Vec<Principal>,
+    pub denylist: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TraceSpan {
+    pub trace_id: String,
+    pub name: String,
+    pub cell_id: Option<Principal>,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub records: u64,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HotQuery {
+    pub id: String,
+    pub query: BatchQuery,
+    pub registered_by: Principal,
+    pub registered_at: u64,
+    pub last_warmed_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// The parts of `AggregatorMetrics` these tests read
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AggregatorMetrics {
+    pub active_streams: u32,
+    pub registered_cells: u32,
+    pub circuit_breakers: Vec<CellCircuitStatus>,
+    pub in_flight_queries: u32,
+    pub queued_queries: u32,
+    pub rejected_queries: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellCircuitStatus {
+    pub cell_id: Principal,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ViewDefinition {
+    pub name: String,
+    pub source_cells: Vec<Principal>,
+    pub kind: ViewKind,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ViewKind {
+    Union,
+    CountBy(String),
+    SumBy { field: String, group_by: String },
+}
+
+/// A cross-cell plan that leaves the strategy to the optimizer
+pub fn query_plan(target_cells: Vec<Principal>) -> QueryPlan {
+    QueryPlan {
+        id: "plan".to_string(),
+        query_type: QueryType::CrossCell,
+        target_cells,
+        operations: vec![QueryOperation::Filter("category = 'a'".to_string())],
+        coordination_strategy: CoordinationStrategy::Sequential,
+        streaming_config: None,
+    }
+}
+
+/// A plan streaming every record of the target cells
+pub fn stream_plan(target_cells: Vec<Principal>) -> QueryPlan {
+    QueryPlan { operations: Vec::new(), ..query_plan(target_cells) }
+}
+
+pub fn page(limit: u64) -> Pagination {
+    Pagination { offset: 0, limit, cursor: None }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BatchQueryResult {
+    pub query_id: String,
+    pub execution_time_ms: u64,
+    pub records: Vec<String>,
+    pub total_count: u64,
+    pub cell_statistics: Vec<(Principal, CellExecutionStats)>,
+    pub cell_errors: Vec<(Principal, String)>,
+    pub continuation_token: Option<String>,
+    pub trace_id: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellExecutionStats {
+    pub response_time_ms: u64,
+    pub records_returned: u64,
+    pub cycles_consumed: u64,
+    pub cache_hit: bool,
+    pub retries: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum QueryError {
+    PermissionDenied(String),
+    OptimizationFailed(String),
+    ExecutionFailed(String),
+    CoordinationFailed(String),
+    AggregationFailed(String),
+    StreamingFailed(String),
+    RegistrationFailed(String),
+    InvalidQuery(String),
+    CellUnavailable(Principal),
+    SchemaMismatch(String),
+    TimeoutExceeded,
+    ResourceExhausted,
+}
+
+/// The parts of the Data Cell init argument these tests set
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CellInitConfig {
+    pub name: String,
+    pub schema: SchemaDefinition,
+    pub permissions: PermissionConfig,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SchemaDefinition {
+    pub version: u32,
+    pub name: String,
+    pub fields: Vec<(String, FieldDefinition)>,
+    pub indexes: Vec<IndexDefinition>,
+    pub constraints: Vec<ConstraintDefinition>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FieldDefinition {
+    pub field_type: FieldType,
+    pub required: bool,
+    pub default_value: Option<String>,
+    pub validation_rules: Vec<ValidationRule>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Timestamp,
+    Principal,
+    Blob,
+    Array(Box<FieldType>),
+    Object(Vec<(String, FieldDefinition)>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub unique: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ConstraintDefinition {
+    Unique(Vec<String>),
+    ForeignKey { fields: Vec<String>, references: String },
+    Check(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum ValidationRule {
+    MinLength(u32),
+    MaxLength(u32),
+    Pattern(String),
+    Range(i64, i64),
+    Custom(String),
+    Email,
+    Url,
+    Uuid,
+    OneOf(Vec<String>),
+    MaxSize(u64),
+    MaxItems(u32),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PermissionConfig {
+    pub read: Vec<AccessLevel>,
+    pub write: Vec<AccessLevel>,
+    pub admin: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum AccessLevel {
+    Public,
+    Authenticated,
+    Principal(Principal),
+    Role(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum CellError {
+    ValidationError(String),
+    PermissionDenied,
+    NotFound(String),
+    SchemaViolation(String),
+    StorageError(String),
+    RateLimited,
+    PreconditionFailed(String),
+    NotImplemented(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Precondition {
+    Absent,
+    FieldEquals { field: String, value: String },
+}
+
+/// Items with a required `name`, a `category` and a numeric `score`
+pub fn cell_config(name: &str, schema_version: u32) -> CellInitConfig {
+    let field = |field_type: FieldType, required: bool| FieldDefinition {
+        field_type,
+        required,
+        default_value: None,
+        validation_rules: Vec::new(),
+    };
+
+    CellInitConfig {
+        name: name.to_string(),
+        schema: SchemaDefinition {
+            version: schema_version,
+            name: "items".to_string(),
+            fields: vec![
+                ("name".to_string(), field(FieldType::Text, true)),
+                ("category".to_string(), field(FieldType::Text, false)),
+                ("score".to_string(), field(FieldType::Number, false)),
+            ],
+            indexes: Vec::new(),
+            constraints: Vec::new(),
+        },
+        permissions: PermissionConfig {
+            read: vec![AccessLevel::Public],
+            write: vec![AccessLevel::Authenticated],
+            admin: vec![controller()],
+        },
+    }
+}
+
+pub fn registration(cell_id: Principal, name: &str) -> CellRegistration {
+    CellRegistration {
+        cell_id,
+        name: name.to_string(),
+        schema_version: 1,
+        capabilities: vec![CellCapability::BatchOperations],
+        performance_hints: PerformanceHints {
+            typical_response_time_ms: 100,
+            max_concurrent_queries: 10,
+            preferred_batch_size: 100,
+            subnet_location: None,
+        },
+        replica_of: None,
+        group: None,
+    }
+}
+
+pub fn aggregator_config(registered_cells: Vec<CellRegistration>) -> AggregatorConfig {
+    AggregatorConfig {
+        name: "test_aggregator".to_string(),
+        registered_cells,
+        authorized_managers: Some(vec![controller()]),
+        retry_policy: None,
+        streaming_config: StreamingConfig {
+            default_batch_size: 10,
+            max_concurrent_streams: 10,
+            stream_timeout_seconds: 3_600,
+            buffer_size: 100,
+            prefetch_enabled: false,
+            max_streams_per_caller: None,
+        },
+        optimization_config: OptimizationConfig {
+            cache_enabled: false,
+            cache_ttl_seconds: 60,
+            max_cache_entries: 100,
+            cost_optimization_enabled: false,
+            adaptive_batching: false,
+            history_retention_seconds: None,
+            max_history_records: None,
+            warmup_cycle_budget: None,
+        },
+    }
+}
+
+pub fn options() -> BatchQueryOptions {
+    BatchQueryOptions {
+        max_results: None,
+        page_size: None,
+        timeout_ms: None,
+        consistency_level: ConsistencyLevel::Eventual,
+        result_format: ResultFormat::Json,
+        allow_full_scan: None,
+        required_capabilities: None,
+        require_same_schema: None,
+        max_cycles: None,
+        priority: None,
+    }
+}
+
+pub fn batch_query(target_cells: Vec<Principal>) -> BatchQuery {
+    BatchQuery {
+        query_sql: "SELECT * FROM items".to_string(),
+        target_cells,
+        target_group: None,
+        parameters: Vec::new(),
+        filters: None,
+        options: options(),
+    }
+}
+
+pub fn parse(record: &str) -> Value {
+    serde_json::from_str(record).expect("record is not JSON")
+}
+
+/// Sorted `name` fields of a result's records
+pub fn names(result: &BatchQueryResult) -> Vec<String> {
+    let mut names: Vec<String> = result.records.iter()
+        .filter_map(|record| parse(record).get("name").and_then(Value::as_str).map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Install a canister whose `health` query always answers with `health`
+///
+/// Stands in for a cell in states a real one can't easily be driven into.
+pub fn install_health_stub(pic: &PocketIc, health: &CellHealth) -> Principal {
+    let reply = candid::encode_one(health).unwrap();
+    let data: String = reply.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+    let module = format!(r#"
+(module
+  (import "ic0" "msg_reply_data_append" (func $append (param i32 i32)))
+  (import "ic0" "msg_reply" (func $reply))
+  (memory 1)
+  (data (i32.const 0) "{}")
+  (func $health
+    (call $append (i32.const 0) (i32.const {}))
+    (call $reply))
+  (export "canister_query health" (func $health)))
+"#, data, reply.len());
+
+    let stub = pic.create_canister_with_settings(Some(controller()), None);
+    pic.add_cycles(stub, CYCLES);
+    pic.install_canister(stub, wat::parse_str(module).expect("invalid stub module"), Vec::new(), Some(controller()));
+    stub
+}
+
+/// An aggregator and the Data Cells registered with it, in one PocketIC instance
+pub struct Mesh {
+    pub pic: PocketIc,
+    pub aggregator: Principal,
+    pub cells: Vec<Principal>,
+}
+
+impl Mesh {
+    /// Install `cell_count` item cells and an aggregator that has them registered
+    pub fn new(cell_count: usize) -> Self {
+        Self::with_config(cell_count, |config| config)
+    }
+
+    pub fn with_config(cell_count: usize, configure: impl FnOnce(AggregatorConfig) -> AggregatorConfig) -> Self {
+        let pic = PocketIc::new();
+        let cells: Vec<Principal> = (0..cell_count)
+            .map(|i| Self::install_cell(&pic, cell_config(&format!("cell_{}", i), 1)))
+            .collect();
+
+        let aggregator = pic.create_canister_with_settings(Some(controller()), None);
+        Self::install_aggregator(&pic, aggregator, &cells, configure);
+
+        Self { pic, aggregator, cells }
+    }
+
+    /// Like `new`, but with the cells on a second subnet, so each cell call
+    /// spans several rounds and a query stays in flight between ticks
+    pub fn across_subnets(cell_count: usize) -> Self {
+        let pic = PocketIcBuilder::new().with_application_subnet().with_application_subnet().build();
+        let subnets = pic.topology().get_app_subnets();
+
+        let cells: Vec<Principal> = (0..cell_count)
+            .map(|i| {
+                let cell_id = pic.create_canister_on_subnet(Some(controller()), None, subnets[1]);
+                Self::install_cell_into(&pic, cell_id, cell_config(&format!("cell_{}", i), 1));
+                cell_id
+            })
+            .collect();
+
+        let aggregator = pic.create_canister_on_subnet(Some(controller()), None, subnets[0]);
+        Self::install_aggregator(&pic, aggregator, &cells, |config| config);
+
+        Self { pic, aggregator, cells }
+    }
+
+    fn install_aggregator(
+        pic: &PocketIc,
+        aggregator: Principal,
+        cells: &[Principal],
+        configure: impl FnOnce(AggregatorConfig) -> AggregatorConfig,
+    ) {
+        let registrations = cells.iter().enumerate()
+            .map(|(i, cell_id)| registration(*cell_id, &format!("cell_{}", i)))
+            .collect();
+        let config = configure(aggregator_config(registrations));
+
+        pic.add_cycles(aggregator, CYCLES);
+        pic.install_canister(aggregator, aggregator_wasm(), candid::encode_one(config).unwrap(), Some(controller()));
+    }
+
+    pub fn install_cell(pic: &PocketIc, config: CellInitConfig) -> Principal {
+        let cell_id = pic.create_canister_with_settings(Some(controller()), None);
+        Self::install_cell_into(pic, cell_id, config);
+        cell_id
+    }
+
+    fn install_cell_into(pic: &PocketIc, cell_id: Principal, config: CellInitConfig) {
+        pic.add_cycles(cell_id, CYCLES);
+        pic.install_canister(cell_id, cell_wasm(), candid::encode_one(config).unwrap(), Some(controller()));
+    }
+
+    /// Upgrade the aggregator in place, running its upgrade hooks
+    pub fn upgrade(&self) {
+        self.pic.upgrade_canister(self.aggregator, aggregator_wasm(), candid::encode_args(()).unwrap(), Some(controller()))
+            .expect("upgrade failed");
+    }
+
+    pub fn update<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        update_candid_as(&self.pic, self.aggregator, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    pub fn query<A: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(&self, sender: Principal, method: &str, args: A) -> R {
+        query_candid_as(&self.pic, self.aggregator, sender, method, args)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e))
+    }
+
+    /// Insert a record into one of the cells directly
+    pub fn insert(&self, cell_id: Principal, record: Value) -> String {
+        let (result,): (Result<String, CellError>,) = update_candid_as(
+            &self.pic, cell_id, user(), "insert",
+            (record.to_string(), None::<u64>, None::<Precondition>, None::<String>),
+        ).expect("insert call failed");
+        result.expect("insert failed")
+    }
+
+    /// Let timers and spawned calls, such as change feed deliveries, run
+    pub fn settle(&self) {
+        for _ in 0..10 {
+            self.pic.tick();
+        }
+    }
+
+    pub fn delete(&self, cell_id: Principal, record_id: &str) {
+        let (result,): (Result<(), CellError>,) = update_candid_as(
+            &self.pic, cell_id, user(), "delete", (record_id.to_string(),),
+        ).expect("delete call failed");
+        result.expect("delete failed")
+    }
+
+    pub fn create_view(&self, definition: ViewDefinition) -> Result<(), QueryError> {
+        let (result,): (Result<(), QueryError>,) = self.update(controller(), "create_materialized_view", (definition,));
+        result
+    }
+
+    /// Rows of a view whose fields equal every `filter` entry
+    pub fn view_rows(&self, name: &str, filter: Vec<(&str, Value)>) -> Vec<Value> {
+        let filter: Vec<(String, String)> = filter.into_iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect();
+        let (result,): (Result<Vec<String>, QueryError>,) =
+            self.query(user(), "query_view", (name.to_string(), filter, page(100)));
+        result.expect("query_view failed").iter().map(|row| parse(row)).collect()
+    }
+
+    pub fn explain(&self, plan: QueryPlan) -> Result<QueryExplanation, QueryError> {
+        let (result,): (Result<QueryExplanation, QueryError>,) = self.query(user(), "explain_query", (plan,));
+        result
+    }
+
+    pub fn open_stream(&self, sender: Principal, plan: QueryPlan) -> Result<StreamHandle, QueryError> {
+        // Stream IDs are derived from the time, so keep streams in separate rounds apart
+        self.pic.advance_time(std::time::Duration::from_millis(1));
+        let (result,): (Result<StreamHandle, QueryError>,) = self.update(sender, "execute_streaming_query", (plan,));
+        result
+    }
+
+    pub fn pull(&self, sender: Principal, handle: &StreamHandle, batch_size: u32) -> Result<StreamBatch, QueryError> {
+        let (result,): (Result<StreamBatch, QueryError>,) =
+            self.update(sender, "get_stream_batch", (handle.clone(), batch_size));
+        result
+    }
+
+    pub fn stream_status(&self, sender: Principal, handle: &StreamHandle) -> Result<StreamStatus, QueryError> {
+        let (result,): (Result<StreamStatus, QueryError>,) = self.query(sender, "get_stream_status", (handle.clone(),));
+        result
+    }
+
+    pub fn metrics(&self) -> AggregatorMetrics {
+        let (metrics,): (AggregatorMetrics,) = self.query(user(), "get_aggregator_metrics", ());
+        metrics
+    }
+
+    pub fn batch(&self, query: BatchQuery) -> Result<BatchQueryResult, QueryError> {
+        self.batch_as(user(), query)
+    }
+
+    pub fn batch_as(&self, sender: Principal, query: BatchQuery) -> Result<BatchQueryResult, QueryError> {
+        let (result,): (Result<BatchQueryResult, QueryError>,) = self.update(sender, "execute_batch_query", (query,));
+        result
+    }
+}
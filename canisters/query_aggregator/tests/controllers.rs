@@ -0,0 +1,32 @@
+mod common;
+
+use common::*;
+
+/// A mesh where `user()` is an authorized manager but not a controller
+fn managed_mesh() -> Mesh {
+    Mesh::with_config(1, |config| AggregatorConfig {
+        authorized_managers: Some(vec![controller(), user()]),
+        ..config
+    })
+}
+
+#[test]
+fn managers_who_are_not_controllers_are_refused_controller_only_endpoints() {
+    let mesh = managed_mesh();
+
+    // Managers may still manage cells
+    let stray = Mesh::install_cell(&mesh.pic, cell_config("stray", 1));
+    let (registered,): (Result<(), QueryError>,) = mesh.update(user(), "register_cell", (registration(stray, "stray"),));
+    registered.unwrap();
+
+    let (result,): (Result<u64, QueryError>,) = mesh.update(user(), "cancel_all_streams", ());
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+    let (result,): (Result<(), QueryError>,) =
+        mesh.update(user(), "pin_plan", ("signature".to_string(), CoordinationStrategy::Sequential));
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "set_gateway_allowlist_only", (true,));
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+
+    let (result,): (Result<u64, QueryError>,) = mesh.update(controller(), "cancel_all_streams", ());
+    assert_eq!(result.unwrap(), 0);
+}
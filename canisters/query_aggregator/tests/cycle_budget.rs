@@ -0,0 +1,48 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// Base cost the aggregator charges a query for each cell call
+const CELL_CALL_BASE_CYCLES: u64 = 260_000;
+
+fn budgeted(mesh: &Mesh, query_sql: &str, max_cycles: Option<u64>) -> BatchQuery {
+    BatchQuery {
+        query_sql: query_sql.to_string(),
+        options: BatchQueryOptions { max_cycles, ..options() },
+        ..batch_query(mesh.cells.clone())
+    }
+}
+
+fn filled_mesh() -> Mesh {
+    let mesh = Mesh::new(3);
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        mesh.insert(*cell_id, json!({"name": format!("item_{}", i)}));
+    }
+    mesh
+}
+
+#[test]
+fn a_low_budget_stops_a_sequential_query_before_the_next_cell() {
+    let mesh = filled_mesh();
+    // UNION makes the query complex enough to be run one cell at a time
+    let sql = "SELECT * FROM a UNION SELECT * FROM b";
+
+    // Enough for the first cell call but not the second
+    let result = mesh.batch(budgeted(&mesh, sql, Some(CELL_CALL_BASE_CYCLES * 3 / 2)));
+    assert!(matches!(result, Err(QueryError::ResourceExhausted)), "{:?}", result);
+
+    let result = mesh.batch(budgeted(&mesh, sql, Some(CELL_CALL_BASE_CYCLES * 10))).unwrap();
+    assert_eq!(result.records.len(), 3);
+}
+
+#[test]
+fn a_parallel_fan_out_over_budget_is_refused_up_front() {
+    let mesh = filled_mesh();
+
+    let result = mesh.batch(budgeted(&mesh, "SELECT * FROM items", Some(CELL_CALL_BASE_CYCLES * 2)));
+    assert!(matches!(result, Err(QueryError::ResourceExhausted)), "{:?}", result);
+
+    let result = mesh.batch(budgeted(&mesh, "SELECT * FROM items", None)).unwrap();
+    assert_eq!(result.records.len(), 3);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn explanation_reflects_the_strategy_chosen_for_the_cell_count() {
+    let mesh = Mesh::new(4);
+
+    let strategy = |cells: usize| mesh.explain(query_plan(mesh.cells[..cells].to_vec())).unwrap().strategy;
+    assert_eq!(strategy(1), CoordinationStrategy::Sequential);
+    assert_eq!(strategy(2), CoordinationStrategy::Parallel);
+    assert_eq!(strategy(3), CoordinationStrategy::Parallel);
+    assert_eq!(strategy(4), CoordinationStrategy::PipelinedStreaming);
+}
+
+#[test]
+fn explanation_estimates_every_target_cell_without_executing() {
+    let mesh = Mesh::new(3);
+    let plan = QueryPlan {
+        operations: vec![
+            QueryOperation::Filter("category = 'a'".to_string()),
+            QueryOperation::Limit(25),
+        ],
+        ..query_plan(mesh.cells.clone())
+    };
+
+    let explanation = mesh.explain(plan).unwrap();
+    assert_eq!(explanation.plan_id, "plan");
+    let estimated: Vec<_> = explanation.cell_estimates.iter().map(|estimate| estimate.cell_id).collect();
+    assert_eq!(estimated, mesh.cells);
+    let per_cell: u64 = explanation.cell_estimates.iter().map(|estimate| estimate.estimated_cycles).sum();
+    assert!(per_cell <= explanation.estimated_total_cycles);
+    assert_eq!(explanation.estimated_rows, Some(25));
+    assert!(!explanation.cache_hit);
+}
+
+#[test]
+fn explaining_unregistered_cells_is_denied() {
+    let mesh = Mesh::new(1);
+    let stray = Mesh::install_cell(&mesh.pic, cell_config("stray", 1));
+
+    assert!(matches!(mesh.explain(query_plan(vec![stray])), Err(QueryError::PermissionDenied(_))));
+}
+
+fn signature(mesh: &Mesh, plan: &QueryPlan) -> String {
+    mesh.explain(plan.clone()).unwrap().signature.expect("plan signature")
+}
+
+fn pin(mesh: &Mesh, sender: candid::Principal, signature: String, strategy: CoordinationStrategy) -> Result<(), QueryError> {
+    let (result,): (Result<(), QueryError>,) = mesh.update(sender, "pin_plan", (signature, strategy));
+    result
+}
+
+#[test]
+fn pinned_strategy_overrides_the_optimizer() {
+    let mesh = Mesh::new(2);
+    let plan = query_plan(mesh.cells.clone());
+
+    pin(&mesh, controller(), signature(&mesh, &plan), CoordinationStrategy::Sequential).unwrap();
+
+    let explanation = mesh.explain(plan).unwrap();
+    assert_eq!(explanation.strategy, CoordinationStrategy::Sequential);
+    assert!(explanation.pinned);
+}
+
+#[test]
+fn pins_apply_whatever_the_cell_count() {
+    let mesh = Mesh::new(5);
+
+    for cells in 1..=5 {
+        let plan = query_plan(mesh.cells[..cells].to_vec());
+        pin(&mesh, controller(), signature(&mesh, &plan), CoordinationStrategy::AdaptiveParallel).unwrap();
+    }
+
+    for cells in 1..=5 {
+        let explanation = mesh.explain(query_plan(mesh.cells[..cells].to_vec())).unwrap();
+        assert_eq!(explanation.strategy, CoordinationStrategy::AdaptiveParallel, "{} cells", cells);
+        assert!(explanation.pinned);
+    }
+}
+
+#[test]
+fn a_pin_only_covers_its_own_signature() {
+    let mesh = Mesh::new(2);
+    let pinned = query_plan(mesh.cells.clone());
+    pin(&mesh, controller(), signature(&mesh, &pinned), CoordinationStrategy::Sequential).unwrap();
+
+    let other = mesh.explain(query_plan(mesh.cells[..1].to_vec())).unwrap();
+    assert!(!other.pinned);
+}
+
+#[test]
+fn unpinning_hands_the_choice_back_to_the_optimizer() {
+    let mesh = Mesh::new(2);
+    let plan = query_plan(mesh.cells.clone());
+    let signature = signature(&mesh, &plan);
+    pin(&mesh, controller(), signature.clone(), CoordinationStrategy::Sequential).unwrap();
+
+    let (listed,): (Vec<(String, CoordinationStrategy)>,) = mesh.query(user(), "pinned_plans", ());
+    assert_eq!(listed, vec![(signature.clone(), CoordinationStrategy::Sequential)]);
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "unpin_plan", (signature.clone(),));
+    result.unwrap();
+    let explanation = mesh.explain(plan).unwrap();
+    assert_eq!(explanation.strategy, CoordinationStrategy::Parallel);
+    assert!(!explanation.pinned);
+
+    let (again,): (Result<(), QueryError>,) = mesh.update(controller(), "unpin_plan", (signature,));
+    assert!(matches!(again, Err(QueryError::InvalidQuery(_))));
+}
+
+#[test]
+fn pins_survive_upgrades() {
+    let mesh = Mesh::new(2);
+    let plan = query_plan(mesh.cells.clone());
+    pin(&mesh, controller(), signature(&mesh, &plan), CoordinationStrategy::PipelinedStreaming).unwrap();
+
+    mesh.upgrade();
+
+    assert_eq!(mesh.explain(plan).unwrap().strategy, CoordinationStrategy::PipelinedStreaming);
+}
+
+#[test]
+fn only_controllers_pin_plans() {
+    let mesh = Mesh::new(1);
+    let signature = signature(&mesh, &query_plan(mesh.cells.clone()));
+
+    assert!(matches!(
+        pin(&mesh, user(), signature, CoordinationStrategy::Parallel),
+        Err(QueryError::PermissionDenied(_))
+    ));
+}
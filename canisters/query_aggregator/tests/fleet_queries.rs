@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// Three cells, the last registered without `BatchOperations`
+fn fleet() -> Mesh {
+    let mesh = Mesh::with_config(3, |mut config| {
+        config.registered_cells[2].capabilities.clear();
+        config
+    });
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        mesh.insert(*cell_id, json!({"name": format!("from_cell_{}", i)}));
+    }
+    mesh
+}
+
+fn full_scan(required_capabilities: Option<Vec<CellCapability>>) -> BatchQuery {
+    BatchQuery {
+        options: BatchQueryOptions { allow_full_scan: Some(true), required_capabilities, ..options() },
+        ..batch_query(Vec::new())
+    }
+}
+
+#[test]
+fn an_untargeted_query_hits_every_registered_capable_cell() {
+    let mesh = fleet();
+
+    let result = mesh.batch(full_scan(Some(vec![CellCapability::BatchOperations]))).unwrap();
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_1"]);
+    let mut queried: Vec<_> = result.cell_statistics.iter().map(|(cell_id, _)| *cell_id).collect();
+    queried.sort();
+    let mut capable = mesh.cells[..2].to_vec();
+    capable.sort();
+    assert_eq!(queried, capable);
+
+    let result = mesh.batch(full_scan(None)).unwrap();
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_1", "from_cell_2"]);
+}
+
+#[test]
+fn untargeted_queries_require_allow_full_scan() {
+    let mesh = fleet();
+
+    assert!(matches!(mesh.batch(batch_query(Vec::new())), Err(QueryError::InvalidQuery(_))));
+
+    let refused = BatchQuery {
+        options: BatchQueryOptions { allow_full_scan: Some(false), ..options() },
+        ..batch_query(Vec::new())
+    };
+    assert!(matches!(mesh.batch(refused), Err(QueryError::InvalidQuery(_))));
+
+    let unmatched = full_scan(Some(vec![CellCapability::GeospatialQueries]));
+    assert!(matches!(mesh.batch(unmatched), Err(QueryError::InvalidQuery(_))));
+}
+
+#[test]
+fn full_scans_leave_replicas_to_read_routing() {
+    let mesh = fleet();
+    let replica = Mesh::install_cell(&mesh.pic, cell_config("cell_0_replica", 1));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        replica_of: Some(mesh.cells[0]),
+        ..registration(replica, "cell_0_replica")
+    },));
+    result.unwrap();
+
+    let result = mesh.batch(full_scan(Some(vec![CellCapability::BatchOperations]))).unwrap();
+    assert_eq!(result.cell_statistics.len(), 2);
+    assert!(result.cell_statistics.iter().all(|(cell_id, _)| *cell_id != replica));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use candid::Principal;
+use common::*;
+use serde_json::json;
+
+fn list(mesh: &Mesh, principal: Principal, listing: Option<GatewayListing>) {
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "set_gateway_listing", (principal, listing));
+    result.expect("set_gateway_listing failed");
+}
+
+fn allowlist_only(mesh: &Mesh, enabled: bool) {
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "set_gateway_allowlist_only", (enabled,));
+    result.expect("set_gateway_allowlist_only failed");
+}
+
+fn rules(mesh: &Mesh) -> GatewayRules {
+    let (rules,): (Result<GatewayRules, QueryError>,) = mesh.query(controller(), "gateway_rules", ());
+    rules.expect("gateway_rules failed")
+}
+
+fn denied<T: std::fmt::Debug>(result: Result<T, QueryError>) {
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))), "expected PermissionDenied, got {:?}", result);
+}
+
+fn seeded_mesh() -> Mesh {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "alpha"}));
+    mesh.insert(mesh.cells[1], json!({"name": "beta"}));
+    mesh
+}
+
+#[test]
+fn denylisted_principals_are_blocked_at_every_query_entrypoint() {
+    let mesh = seeded_mesh();
+    list(&mesh, other_user(), Some(GatewayListing::Denied));
+
+    denied(mesh.batch_as(other_user(), batch_query(mesh.cells.clone())));
+    denied(mesh.open_stream(other_user(), stream_plan(mesh.cells.clone())));
+    let (result,): (Result<QueryExplanation, QueryError>,) =
+        mesh.query(other_user(), "explain_query", (query_plan(mesh.cells.clone()),));
+    denied(result);
+
+    // Everyone else is unaffected
+    assert_eq!(mesh.batch(batch_query(mesh.cells.clone())).unwrap().records.len(), 2);
+
+    list(&mesh, other_user(), None);
+    assert_eq!(mesh.batch_as(other_user(), batch_query(mesh.cells.clone())).unwrap().records.len(), 2);
+}
+
+#[test]
+fn denylisting_cuts_off_streams_already_open() {
+    let mesh = seeded_mesh();
+    let handle = mesh.open_stream(other_user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    list(&mesh, other_user(), Some(GatewayListing::Denied));
+    denied(mesh.pull(other_user(), &handle, 1));
+    denied(mesh.stream_status(other_user(), &handle));
+    let (result,): (Result<(), QueryError>,) = mesh.update(other_user(), "close_stream", (handle.clone(),));
+    denied(result);
+}
+
+#[test]
+fn allowlist_only_mode_admits_just_the_allowlist() {
+    let mesh = seeded_mesh();
+    list(&mesh, user(), Some(GatewayListing::Allowed));
+    allowlist_only(&mesh, true);
+
+    assert_eq!(mesh.batch_as(user(), batch_query(mesh.cells.clone())).unwrap().records.len(), 2);
+    denied(mesh.batch_as(other_user(), batch_query(mesh.cells.clone())));
+    denied(mesh.open_stream(other_user(), stream_plan(mesh.cells.clone())));
+
+    allowlist_only(&mesh, false);
+    assert!(mesh.batch_as(other_user(), batch_query(mesh.cells.clone())).is_ok());
+}
+
+#[test]
+fn the_denylist_wins_over_allowlist_only_mode_and_rules_survive_upgrades() {
+    let mesh = seeded_mesh();
+    list(&mesh, user(), Some(GatewayListing::Allowed));
+    list(&mesh, other_user(), Some(GatewayListing::Denied));
+    allowlist_only(&mesh, true);
+
+    mesh.upgrade();
+    let rules = rules(&mesh);
+    assert!(rules.allowlist_only);
+    assert_eq!(rules.allowlist, [user()]);
+    assert_eq!(rules.denylist, [other_user()]);
+
+    // Moving a principal to the denylist takes it off the allowlist
+    list(&mesh, user(), Some(GatewayListing::Denied));
+    denied(mesh.batch_as(user(), batch_query(mesh.cells.clone())));
+    assert!(rules(&mesh).allowlist.is_empty());
+}
+
+#[test]
+fn only_controllers_manage_the_gateway() {
+    let mesh = seeded_mesh();
+
+    let (result,): (Result<(), QueryError>,) =
+        mesh.update(user(), "set_gateway_listing", (other_user(), Some(GatewayListing::Denied)));
+    denied(result);
+    let (result,): (Result<GatewayRules, QueryError>,) = mesh.query(user(), "gateway_rules", ());
+    denied(result);
+    assert!(rules(&mesh).denylist.is_empty());
+}
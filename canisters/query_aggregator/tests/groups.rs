@@ -0,0 +1,79 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+use serde_json::json;
+
+/// Cells 0 and 1 in "orders", 2 and 3 in "users"
+fn grouped_mesh() -> Mesh {
+    let mesh = Mesh::with_config(4, |mut config| {
+        for (i, registration) in config.registered_cells.iter_mut().enumerate() {
+            registration.group = Some(if i < 2 { "orders" } else { "users" }.to_string());
+        }
+        config
+    });
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        mesh.insert(*cell_id, json!({"name": format!("from_cell_{}", i)}));
+    }
+    mesh
+}
+
+fn members(mesh: &Mesh, group: &str) -> Vec<Principal> {
+    let (mut cells,): (Vec<Principal>,) = mesh.query(user(), "cells_in_group", (group.to_string(),));
+    cells.sort();
+    cells
+}
+
+fn sorted(mut cells: Vec<Principal>) -> Vec<Principal> {
+    cells.sort();
+    cells
+}
+
+fn group_query(group: &str) -> BatchQuery {
+    BatchQuery { target_group: Some(group.to_string()), ..batch_query(Vec::new()) }
+}
+
+#[test]
+fn groups_are_listed_with_their_members() {
+    let mesh = grouped_mesh();
+
+    let (groups,): (Vec<String>,) = mesh.query(user(), "list_groups", ());
+    assert_eq!(groups, ["orders", "users"]);
+    assert_eq!(members(&mesh, "orders"), sorted(mesh.cells[..2].to_vec()));
+    assert_eq!(members(&mesh, "users"), sorted(mesh.cells[2..].to_vec()));
+    assert!(members(&mesh, "missing").is_empty());
+}
+
+#[test]
+fn querying_a_group_targets_only_its_members() {
+    let mesh = grouped_mesh();
+
+    let result = mesh.batch(group_query("orders")).unwrap();
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_1"]);
+
+    // Explicit targets are kept alongside the group's members
+    let mixed = BatchQuery { target_cells: vec![mesh.cells[3]], ..group_query("orders") };
+    assert_eq!(names(&mesh.batch(mixed).unwrap()), ["from_cell_0", "from_cell_1", "from_cell_3"]);
+
+    assert!(matches!(mesh.batch(group_query("missing")), Err(QueryError::InvalidQuery(_))));
+}
+
+#[test]
+fn replicas_are_group_members_but_not_query_targets() {
+    let mesh = grouped_mesh();
+    let replica = Mesh::install_cell(&mesh.pic, cell_config("cell_0_replica", 1));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        replica_of: Some(mesh.cells[0]),
+        group: Some("orders".to_string()),
+        ..registration(replica, "cell_0_replica")
+    },));
+    result.unwrap();
+
+    assert!(members(&mesh, "orders").contains(&replica));
+    let result = mesh.batch(BatchQuery {
+        options: BatchQueryOptions { consistency_level: ConsistencyLevel::Strong, ..options() },
+        ..group_query("orders")
+    }).unwrap();
+    assert_eq!(result.cell_statistics.len(), 2);
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_1"]);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+use serde_json::json;
+use std::time::Duration;
+
+fn warming_mesh(warmup_cycle_budget: Option<u64>) -> Mesh {
+    Mesh::with_config(0, |mut config| {
+        config.optimization_config.cache_enabled = true;
+        config.optimization_config.cache_ttl_seconds = 600;
+        config.optimization_config.warmup_cycle_budget = warmup_cycle_budget;
+        config
+    })
+}
+
+fn subscribed_cell(mesh: &Mesh, name: &str) -> Principal {
+    let cell = Mesh::install_cell(&mesh.pic, cell_config(name, 1));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (registration(cell, name),));
+    result.expect("registration failed");
+    cell
+}
+
+fn register_hot_query(mesh: &Mesh, sender: Principal, query: BatchQuery) -> Result<String, QueryError> {
+    let (result,): (Result<String, QueryError>,) = mesh.update(sender, "register_hot_query", (query,));
+    result
+}
+
+fn hot_query(mesh: &Mesh, id: &str) -> HotQuery {
+    let (hot,): (Vec<HotQuery>,) = mesh.query(controller(), "list_hot_queries", ());
+    hot.into_iter().find(|hot| hot.id == id).expect("hot query not listed")
+}
+
+fn from_cache(result: &BatchQueryResult) -> bool {
+    result.query_id.starts_with("cached_")
+}
+
+/// Let a warm-up round run
+fn next_round(mesh: &Mesh) {
+    mesh.pic.advance_time(Duration::from_secs(61));
+    mesh.settle();
+}
+
+#[test]
+fn a_hot_query_is_cached_before_any_client_runs_it() {
+    let mesh = warming_mesh(None);
+    let cell = subscribed_cell(&mesh, "dashboard");
+    mesh.insert(cell, json!({"name": "a"}));
+
+    let id = register_hot_query(&mesh, controller(), batch_query(vec![cell])).unwrap();
+    mesh.settle();
+    assert!(hot_query(&mesh, &id).last_warmed_at.is_some());
+
+    let first = mesh.batch(batch_query(vec![cell])).unwrap();
+    assert!(from_cache(&first), "the first client call should hit the warm cache");
+    assert_eq!(names(&first), vec!["a"]);
+}
+
+#[test]
+fn hot_queries_are_refreshed_before_they_expire() {
+    let mesh = warming_mesh(None);
+    let cell = subscribed_cell(&mesh, "dashboard");
+    let id = register_hot_query(&mesh, controller(), batch_query(vec![cell])).unwrap();
+    mesh.settle();
+    let first_warmed = hot_query(&mesh, &id).last_warmed_at.unwrap();
+
+    // Ten minutes of rounds outlast the first result's TTL
+    for _ in 0..10 {
+        next_round(&mesh);
+        assert!(from_cache(&mesh.batch(batch_query(vec![cell])).unwrap()));
+    }
+    assert!(hot_query(&mesh, &id).last_warmed_at.unwrap() > first_warmed);
+}
+
+#[test]
+fn warm_ups_stay_within_the_cycle_budget() {
+    // Enough for one empty cell call per round, not two
+    let mesh = warming_mesh(Some(300_000));
+    let first_cell = subscribed_cell(&mesh, "first");
+    let second_cell = subscribed_cell(&mesh, "second");
+    let first = register_hot_query(&mesh, controller(), batch_query(vec![first_cell])).unwrap();
+    mesh.settle();
+    let second = register_hot_query(&mesh, controller(), batch_query(vec![second_cell])).unwrap();
+    mesh.settle();
+
+    // Invalidate both, so the next round has two queries due
+    for cell in [first_cell, second_cell] {
+        let record_id = mesh.insert(cell, json!({"name": "a"}));
+        mesh.delete(cell, &record_id);
+    }
+    mesh.settle();
+
+    next_round(&mesh);
+    assert!(hot_query(&mesh, &first).last_error.is_none());
+    assert!(hot_query(&mesh, &second).last_error.is_some());
+    assert!(from_cache(&mesh.batch(batch_query(vec![first_cell])).unwrap()));
+
+    next_round(&mesh);
+    assert!(hot_query(&mesh, &second).last_error.is_none());
+}
+
+#[test]
+fn unregistered_hot_queries_are_no_longer_warmed() {
+    let mesh = warming_mesh(None);
+    let cell = subscribed_cell(&mesh, "dashboard");
+    let id = register_hot_query(&mesh, controller(), batch_query(vec![cell])).unwrap();
+    mesh.settle();
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "unregister_hot_query", (id.clone(),));
+    result.unwrap();
+    let (hot,): (Vec<HotQuery>,) = mesh.query(controller(), "list_hot_queries", ());
+    assert!(hot.is_empty());
+
+    mesh.insert(cell, json!({"name": "a"}));
+    mesh.settle();
+    next_round(&mesh);
+    assert!(!from_cache(&mesh.batch(batch_query(vec![cell])).unwrap()));
+}
+
+#[test]
+fn only_managers_register_hot_queries() {
+    let mesh = warming_mesh(None);
+    let cell = subscribed_cell(&mesh, "dashboard");
+
+    let denied = register_hot_query(&mesh, user(), batch_query(vec![cell]));
+    assert!(matches!(denied, Err(QueryError::PermissionDenied(_))), "{:?}", denied);
+}
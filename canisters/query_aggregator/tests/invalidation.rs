@@ -0,0 +1,148 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+use serde_json::json;
+use std::time::Duration;
+
+const CACHE_TTL_SECS: u64 = 600;
+
+fn caching_mesh() -> Mesh {
+    Mesh::with_config(1, |mut config| {
+        config.optimization_config.cache_enabled = true;
+        config.optimization_config.cache_ttl_seconds = CACHE_TTL_SECS;
+        config
+    })
+}
+
+/// Install a cell and register it through `register_cell`, which subscribes the aggregator to its writes
+fn subscribed_cell(mesh: &Mesh) -> Principal {
+    let cell = Mesh::install_cell(&mesh.pic, cell_config("subscribed", 1));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (registration(cell, "subscribed"),));
+    result.expect("registration failed");
+    cell
+}
+
+fn keep_warm(mesh: &Mesh, query: BatchQuery) {
+    let (result,): (Result<String, QueryError>,) = mesh.update(controller(), "register_hot_query", (query,));
+    result.expect("hot query registration failed");
+    mesh.settle();
+}
+
+fn from_cache(result: &BatchQueryResult) -> bool {
+    result.query_id.starts_with("cached_")
+}
+
+#[test]
+fn a_cell_write_invalidates_the_cached_query() {
+    let mesh = caching_mesh();
+    let cell = subscribed_cell(&mesh);
+    mesh.insert(cell, json!({"name": "a"}));
+    keep_warm(&mesh, batch_query(vec![cell]));
+
+    let cached = mesh.batch(batch_query(vec![cell])).unwrap();
+    assert!(from_cache(&cached));
+    assert_eq!(names(&cached), vec!["a"]);
+
+    mesh.insert(cell, json!({"name": "b"}));
+    mesh.settle();
+
+    let fresh = mesh.batch(batch_query(vec![cell])).unwrap();
+    assert!(!from_cache(&fresh), "the write should have evicted the cached result");
+    assert_eq!(names(&fresh), vec!["a", "b"]);
+}
+
+#[test]
+fn deletes_invalidate_too() {
+    let mesh = caching_mesh();
+    let cell = subscribed_cell(&mesh);
+    mesh.insert(cell, json!({"name": "a"}));
+    let doomed = mesh.insert(cell, json!({"name": "b"}));
+    keep_warm(&mesh, batch_query(vec![cell]));
+    assert!(from_cache(&mesh.batch(batch_query(vec![cell])).unwrap()));
+
+    mesh.delete(cell, &doomed);
+    mesh.settle();
+
+    assert_eq!(names(&mesh.batch(batch_query(vec![cell])).unwrap()), vec!["a"]);
+}
+
+#[test]
+fn the_warm_up_refills_an_invalidated_query() {
+    let mesh = caching_mesh();
+    let cell = subscribed_cell(&mesh);
+    keep_warm(&mesh, batch_query(vec![cell]));
+
+    mesh.insert(cell, json!({"name": "a"}));
+    mesh.settle();
+    mesh.pic.advance_time(Duration::from_secs(61));
+    mesh.settle();
+
+    let rewarmed = mesh.batch(batch_query(vec![cell])).unwrap();
+    assert!(from_cache(&rewarmed));
+    assert_eq!(names(&rewarmed), vec!["a"]);
+}
+
+#[test]
+fn writes_to_other_cells_leave_the_cache_alone() {
+    let mesh = caching_mesh();
+    let cached_cell = subscribed_cell(&mesh);
+    let other_cell = subscribed_cell(&mesh);
+    mesh.insert(cached_cell, json!({"name": "a"}));
+    keep_warm(&mesh, batch_query(vec![cached_cell]));
+
+    mesh.insert(other_cell, json!({"name": "b"}));
+    mesh.settle();
+
+    assert!(from_cache(&mesh.batch(batch_query(vec![cached_cell])).unwrap()));
+}
+
+#[test]
+fn without_a_subscription_the_cache_expires_by_ttl() {
+    // Cells registered through the init config never get `subscribe_invalidations`
+    let mesh = caching_mesh();
+    let cell = mesh.cells[0];
+    mesh.insert(cell, json!({"name": "a"}));
+    keep_warm(&mesh, batch_query(vec![cell]));
+
+    mesh.insert(cell, json!({"name": "b"}));
+    mesh.settle();
+
+    let stale = mesh.batch(batch_query(vec![cell])).unwrap();
+    assert!(from_cache(&stale));
+    assert_eq!(names(&stale), vec!["a"]);
+
+    mesh.pic.advance_time(Duration::from_secs(CACHE_TTL_SECS + 1));
+    mesh.settle();
+
+    assert_eq!(names(&mesh.batch(batch_query(vec![cell])).unwrap()), vec!["a", "b"]);
+}
+
+#[test]
+fn unregistering_a_cell_only_evicts_results_read_from_it() {
+    let mesh = caching_mesh();
+    let leaving = subscribed_cell(&mesh);
+    let staying = subscribed_cell(&mesh);
+    mesh.insert(leaving, json!({"name": "a"}));
+    mesh.insert(staying, json!({"name": "b"}));
+    keep_warm(&mesh, batch_query(vec![leaving]));
+    keep_warm(&mesh, batch_query(vec![staying]));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "unregister_cell", (leaving,));
+    result.unwrap();
+
+    let kept = mesh.batch(batch_query(vec![staying])).unwrap();
+    assert!(from_cache(&kept));
+    assert_eq!(names(&kept), vec!["b"]);
+}
+
+#[test]
+fn only_managers_invalidate_by_tag() {
+    let mesh = caching_mesh();
+
+    let (denied,): (Result<u64, QueryError>,) = mesh.update(user(), "invalidate_cache_tag", ("orders".to_string(),));
+    assert!(matches!(denied, Err(QueryError::PermissionDenied(_))), "{:?}", denied);
+
+    let (dropped,): (Result<u64, QueryError>,) = mesh.update(controller(), "invalidate_cache_tag", ("orders".to_string(),));
+    assert_eq!(dropped.unwrap(), 0);
+}
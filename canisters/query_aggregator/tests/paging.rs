@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use std::collections::BTreeSet;
+
+fn paged_query(mesh: &Mesh, page_size: u64) -> BatchQuery {
+    BatchQuery {
+        options: BatchQueryOptions { page_size: Some(page_size), ..options() },
+        ..batch_query(mesh.cells.clone())
+    }
+}
+
+fn resume(mesh: &Mesh, sender: candid::Principal, token: &str) -> Result<BatchQueryResult, QueryError> {
+    let (result,): (Result<BatchQueryResult, QueryError>,) =
+        mesh.update(sender, "continue_batch_query", (token.to_string(),));
+    result
+}
+
+fn record_names(result: &BatchQueryResult) -> Vec<String> {
+    result.records.iter()
+        .map(|record| parse(record)["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn paging_a_multi_cell_result_has_no_duplicates_or_gaps() {
+    let mesh = Mesh::new(3);
+    let mut expected = BTreeSet::new();
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        for j in 0..7 {
+            let name = format!("item_{}_{}", i, j);
+            mesh.insert(*cell_id, json!({"name": name}));
+            expected.insert(name);
+        }
+    }
+
+    let mut page = mesh.batch(paged_query(&mesh, 5)).unwrap();
+    let mut seen = Vec::new();
+    let mut page_sizes = Vec::new();
+    loop {
+        page_sizes.push(page.records.len());
+        seen.extend(record_names(&page));
+        match page.continuation_token.clone() {
+            Some(token) => page = resume(&mesh, user(), &token).unwrap(),
+            None => break,
+        }
+    }
+
+    assert_eq!(page_sizes, [5, 5, 5, 5, 1]);
+    let unique: BTreeSet<String> = seen.iter().cloned().collect();
+    assert_eq!(unique.len(), seen.len());
+    assert_eq!(unique, expected);
+}
+
+#[test]
+fn continuation_tokens_are_single_use_and_bound_to_their_caller() {
+    let mesh = Mesh::new(2);
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        for j in 0..3 {
+            mesh.insert(*cell_id, json!({"name": format!("item_{}_{}", i, j)}));
+        }
+    }
+
+    let page = mesh.batch(paged_query(&mesh, 4)).unwrap();
+    let token = page.continuation_token.expect("a second page");
+
+    assert!(matches!(resume(&mesh, other_user(), &token), Err(QueryError::InvalidQuery(_))));
+
+    let page = mesh.batch(paged_query(&mesh, 4)).unwrap();
+    let token = page.continuation_token.expect("a second page");
+    let last = resume(&mesh, user(), &token).unwrap();
+    assert_eq!(last.records.len(), 2);
+    assert!(last.continuation_token.is_none());
+    assert!(matches!(resume(&mesh, user(), &token), Err(QueryError::InvalidQuery(_))));
+}
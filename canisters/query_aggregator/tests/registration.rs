@@ -0,0 +1,95 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+
+fn register(mesh: &Mesh, registration: CellRegistration) -> Result<(), QueryError> {
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (registration,));
+    result
+}
+
+fn is_registered(mesh: &Mesh, cell_id: Principal) -> bool {
+    let (versions,): (Vec<(Principal, u32)>,) = mesh.query(user(), "schema_versions", ());
+    versions.iter().any(|(registered, _)| *registered == cell_id)
+}
+
+#[test]
+fn a_healthy_cell_registers() {
+    let mesh = Mesh::new(0);
+    let cell = Mesh::install_cell(&mesh.pic, cell_config("fresh", 1));
+
+    register(&mesh, registration(cell, "fresh")).unwrap();
+    assert!(is_registered(&mesh, cell));
+}
+
+#[test]
+fn a_cell_reporting_an_error_state_is_rejected() {
+    let mesh = Mesh::new(0);
+    let failing = install_health_stub(&mesh.pic, &CellHealth {
+        status: HealthStatus::Error("Cycle balance too low".to_string()),
+        schema_version: 1,
+        record_count: 0,
+        capabilities: vec![CellCapability::BatchOperations],
+    });
+
+    match register(&mesh, registration(failing, "failing")) {
+        Err(QueryError::RegistrationFailed(reason)) => assert!(reason.contains("Cycle balance too low"), "{}", reason),
+        other => panic!("expected the registration to fail, got {:?}", other),
+    }
+    assert!(!is_registered(&mesh, failing));
+}
+
+#[test]
+fn unreachable_cells_and_wrong_schema_versions_are_rejected() {
+    let mesh = Mesh::new(0);
+    let stopped = Mesh::install_cell(&mesh.pic, cell_config("stopped", 1));
+    mesh.pic.stop_canister(stopped, Some(controller())).expect("stop failed");
+    assert!(matches!(register(&mesh, registration(stopped, "stopped")), Err(QueryError::RegistrationFailed(_))));
+    assert!(!is_registered(&mesh, stopped));
+
+    let cell = Mesh::install_cell(&mesh.pic, cell_config("v1", 1));
+    let claims_v2 = CellRegistration { schema_version: 2, ..registration(cell, "v1") };
+    assert!(matches!(register(&mesh, claims_v2), Err(QueryError::RegistrationFailed(_))));
+    assert!(!is_registered(&mesh, cell));
+}
+
+#[test]
+fn a_cell_without_geo_support_cannot_register_as_geo_capable() {
+    let mesh = Mesh::new(0);
+    let cell = Mesh::install_cell(&mesh.pic, cell_config("plain", 1));
+
+    let geo = CellRegistration {
+        capabilities: vec![CellCapability::BatchOperations, CellCapability::GeospatialQueries],
+        ..registration(cell, "plain")
+    };
+    assert!(matches!(register(&mesh, geo), Err(QueryError::RegistrationFailed(_))));
+    assert!(!is_registered(&mesh, cell));
+
+    register(&mesh, registration(cell, "plain")).unwrap();
+    assert!(is_registered(&mesh, cell));
+}
+
+#[test]
+fn advanced_indexing_is_only_accepted_from_cells_with_an_index() {
+    let mesh = Mesh::new(0);
+    let indexing = || CellRegistration {
+        capabilities: vec![CellCapability::AdvancedIndexing],
+        ..registration(Principal::anonymous(), "cell")
+    };
+
+    let plain = Mesh::install_cell(&mesh.pic, cell_config("plain", 1));
+    assert!(matches!(
+        register(&mesh, CellRegistration { cell_id: plain, ..indexing() }),
+        Err(QueryError::RegistrationFailed(_))
+    ));
+
+    let mut indexed_config = cell_config("indexed", 1);
+    indexed_config.schema.indexes.push(IndexDefinition {
+        name: "by_category".to_string(),
+        fields: vec!["category".to_string()],
+        unique: false,
+    });
+    let indexed = Mesh::install_cell(&mesh.pic, indexed_config);
+    register(&mesh, CellRegistration { cell_id: indexed, ..indexing() }).unwrap();
+    assert!(is_registered(&mesh, indexed));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+/// A mesh whose only cell has a registered replica, returning the replica
+///
+/// The replica is a separate cell holding different records, so each result
+/// shows which canister answered.
+fn mesh_with_replica() -> (Mesh, candid::Principal) {
+    let mesh = Mesh::new(1);
+    let replica = Mesh::install_cell(&mesh.pic, cell_config("cell_0_replica", 1));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        replica_of: Some(mesh.cells[0]),
+        ..registration(replica, "cell_0_replica")
+    },));
+    result.unwrap();
+
+    mesh.insert(mesh.cells[0], json!({"name": "from_primary"}));
+    mesh.insert(replica, json!({"name": "from_replica"}));
+    (mesh, replica)
+}
+
+#[test]
+fn eventual_reads_rotate_across_the_primary_and_its_replicas() {
+    let (mesh, _) = mesh_with_replica();
+
+    let answers: Vec<Vec<String>> = (0..4)
+        .map(|_| names(&mesh.batch(batch_query(mesh.cells.clone())).unwrap()))
+        .collect();
+    let from_primary = answers.iter().filter(|names| *names == &["from_primary"]).count();
+    let from_replica = answers.iter().filter(|names| *names == &["from_replica"]).count();
+    assert_eq!((from_primary, from_replica), (2, 2));
+}
+
+#[test]
+fn strong_reads_always_go_to_the_primary() {
+    let (mesh, _) = mesh_with_replica();
+    let strong = BatchQuery {
+        options: BatchQueryOptions { consistency_level: ConsistencyLevel::Strong, ..options() },
+        ..batch_query(mesh.cells.clone())
+    };
+
+    for _ in 0..3 {
+        assert_eq!(names(&mesh.batch(strong.clone()).unwrap()), ["from_primary"]);
+    }
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn permanent_failures_are_not_retried_and_successes_report_no_retries() {
+    let mesh = Mesh::with_config(2, |mut config| {
+        config.retry_policy = Some(RetryPolicy { max_retries: 5, base_delay_ms: 10, max_delay_ms: 100 });
+        config
+    });
+    mesh.insert(mesh.cells[0], json!({"name": "healthy"}));
+    mesh.pic.stop_canister(mesh.cells[1], Some(controller())).expect("stop failed");
+
+    let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    assert_eq!(names(&result), ["healthy"]);
+
+    let (_, stats) = result.cell_statistics.iter()
+        .find(|(cell_id, _)| *cell_id == mesh.cells[0])
+        .expect("stats for the healthy cell");
+    assert_eq!(stats.retries, 0);
+
+    let (_, error) = result.cell_errors.iter()
+        .find(|(cell_id, _)| *cell_id == mesh.cells[1])
+        .expect("error for the stopped cell");
+    assert!(error.contains("after 0 retries"), "unexpected error: {}", error);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::*;
+use candid::Principal;
+use serde_json::json;
+
+/// Two version 1 cells and a registered version 2 cell, which is returned
+fn mixed_versions() -> (Mesh, Principal) {
+    let mesh = Mesh::new(2);
+    let upgraded = Mesh::install_cell(&mesh.pic, cell_config("cell_v2", 2));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        schema_version: 2,
+        ..registration(upgraded, "cell_v2")
+    },));
+    result.unwrap();
+
+    for (i, cell_id) in mesh.cells.iter().chain([&upgraded]).enumerate() {
+        mesh.insert(*cell_id, json!({"name": format!("from_cell_{}", i)}));
+    }
+    (mesh, upgraded)
+}
+
+fn same_schema(target_cells: Vec<Principal>) -> BatchQuery {
+    BatchQuery {
+        options: BatchQueryOptions { require_same_schema: Some(true), ..options() },
+        ..batch_query(target_cells)
+    }
+}
+
+#[test]
+fn registered_schema_versions_are_listed() {
+    let (mesh, upgraded) = mixed_versions();
+
+    let (mut versions,): (Vec<(Principal, u32)>,) = mesh.query(user(), "schema_versions", ());
+    versions.sort();
+    let mut expected = vec![(mesh.cells[0], 1), (mesh.cells[1], 1), (upgraded, 2)];
+    expected.sort();
+    assert_eq!(versions, expected);
+}
+
+#[test]
+fn a_mismatched_version_cell_is_rejected_when_schemas_must_match() {
+    let (mesh, upgraded) = mixed_versions();
+
+    let result = mesh.batch(same_schema(vec![mesh.cells[0], upgraded]));
+    assert!(matches!(result, Err(QueryError::SchemaMismatch(_))));
+
+    let result = mesh.batch(same_schema(mesh.cells.clone())).unwrap();
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_1"]);
+
+    // Without the requirement, mixed versions are queried as before
+    let result = mesh.batch(batch_query(vec![mesh.cells[0], upgraded])).unwrap();
+    assert_eq!(names(&result), ["from_cell_0", "from_cell_2"]);
+}
+
+#[test]
+fn group_queries_always_check_schema_versions() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.registered_cells[0].group = Some("shards".to_string());
+        config
+    });
+    let upgraded = Mesh::install_cell(&mesh.pic, cell_config("cell_v2", 2));
+    let (result,): (Result<(), QueryError>,) = mesh.update(controller(), "register_cell", (CellRegistration {
+        schema_version: 2,
+        group: Some("shards".to_string()),
+        ..registration(upgraded, "cell_v2")
+    },));
+    result.unwrap();
+
+    let result = mesh.batch(BatchQuery { target_group: Some("shards".to_string()), ..batch_query(Vec::new()) });
+    assert!(matches!(result, Err(QueryError::SchemaMismatch(_))));
+}
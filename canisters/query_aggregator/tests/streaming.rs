@@ -0,0 +1,524 @@
+mod common;
+
+use common::*;
+use pocket_ic::WasmResult;
+use serde_json::json;
+use std::collections::BTreeSet;
+
+#[test]
+fn slow_consumer_never_grows_the_buffer_past_the_high_water_mark() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.buffer_size = 10;
+        config.streaming_config.prefetch_enabled = true;
+        config
+    });
+    for i in 0..60 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_{:02}", i)}));
+    }
+
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    let mut seen = Vec::new();
+    let mut peak = 0;
+    loop {
+        let batch = mesh.pull(user(), &handle, 1).unwrap();
+        seen.extend(batch.records.iter().map(|record| parse(record)["name"].as_str().unwrap().to_string()));
+
+        let status = mesh.stream_status(user(), &handle).unwrap();
+        // High-water mark is 80% of the 10-record buffer
+        assert!(status.buffered <= 8, "buffer grew to {}", status.buffered);
+        peak = peak.max(status.buffered);
+
+        if !batch.has_more {
+            break;
+        }
+        mesh.pic.advance_time(std::time::Duration::from_secs(1));
+    }
+
+    assert_eq!(peak, 8, "prefetching never filled the buffer");
+    let unique: BTreeSet<&String> = seen.iter().collect();
+    assert_eq!(seen.len(), 60);
+    assert_eq!(unique.len(), 60);
+}
+
+#[test]
+fn streams_past_the_concurrency_limit_are_refused() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.max_concurrent_streams = 2;
+        config
+    });
+    let plan = stream_plan(mesh.cells.clone());
+
+    let first = mesh.open_stream(user(), plan.clone()).unwrap();
+    mesh.open_stream(other_user(), plan.clone()).unwrap();
+    assert!(matches!(mesh.open_stream(user(), plan.clone()), Err(QueryError::ResourceExhausted)));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "close_stream", (first,));
+    result.unwrap();
+    mesh.open_stream(user(), plan).unwrap();
+}
+
+#[test]
+fn per_caller_quota_applies_on_top_of_the_global_limit() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.max_concurrent_streams = 3;
+        config.streaming_config.max_streams_per_caller = Some(2);
+        config
+    });
+    let plan = stream_plan(mesh.cells.clone());
+
+    for _ in 0..2 {
+        mesh.open_stream(user(), plan.clone()).unwrap();
+    }
+    assert!(matches!(mesh.open_stream(user(), plan.clone()), Err(QueryError::ResourceExhausted)));
+
+    mesh.open_stream(other_user(), plan.clone()).unwrap();
+    assert!(matches!(mesh.open_stream(other_user(), plan), Err(QueryError::ResourceExhausted)));
+}
+
+#[test]
+fn expired_streams_stop_counting_against_the_limit() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.max_concurrent_streams = 2;
+        config.streaming_config.stream_timeout_seconds = 60;
+        config
+    });
+    let plan = stream_plan(mesh.cells.clone());
+
+    for _ in 0..2 {
+        mesh.open_stream(user(), plan.clone()).unwrap();
+    }
+    assert!(matches!(mesh.open_stream(user(), plan.clone()), Err(QueryError::ResourceExhausted)));
+
+    mesh.pic.advance_time(std::time::Duration::from_secs(61));
+    mesh.open_stream(user(), plan).unwrap();
+}
+
+fn batch_names(batch: &StreamBatch) -> Vec<String> {
+    batch.records.iter().map(|record| parse(record)["name"].as_str().unwrap().to_string()).collect()
+}
+
+/// `per_cell` records in each cell, named `item_<cell>_<n>`
+fn filled_mesh(cell_count: usize, per_cell: usize) -> Mesh {
+    let mesh = Mesh::new(cell_count);
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        for j in 0..per_cell {
+            mesh.insert(*cell_id, json!({"name": format!("item_{}_{}", i, j)}));
+        }
+    }
+    mesh
+}
+
+#[test]
+fn a_cell_failing_mid_stream_fails_the_next_pull() {
+    let mesh = filled_mesh(2, 3);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    assert_eq!(batch_names(&mesh.pull(user(), &handle, 2).unwrap()), ["item_0_0", "item_0_1"]);
+
+    mesh.pic.stop_canister(mesh.cells[1], Some(controller())).expect("stop failed");
+
+    // Records fetched before the failure are still delivered
+    let batch = mesh.pull(user(), &handle, 5).unwrap();
+    assert_eq!(batch_names(&batch), ["item_0_2"]);
+    assert!(batch.has_more);
+    let error = mesh.stream_status(user(), &handle).unwrap().error.expect("an error state");
+    assert!(error.contains(&mesh.cells[1].to_text()), "{}", error);
+
+    match mesh.pull(user(), &handle, 5) {
+        Err(QueryError::StreamingFailed(reason)) => assert!(reason.contains("failed mid-stream"), "{}", reason),
+        other => panic!("expected the stream to fail, got {:?}", other),
+    }
+    assert!(matches!(mesh.pull(user(), &handle, 5), Err(QueryError::StreamingFailed(_))));
+}
+
+#[test]
+fn a_stream_over_healthy_cells_runs_to_completion() {
+    let mesh = filled_mesh(2, 3);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let batch = mesh.pull(user(), &handle, 10).unwrap();
+    assert_eq!(batch.records.len(), 6);
+    assert!(!batch.has_more);
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert!(status.is_complete);
+    assert_eq!(status.error, None);
+}
+
+#[test]
+fn streams_resume_from_their_cursors_after_an_upgrade() {
+    let mesh = filled_mesh(2, 3);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    let mut seen = batch_names(&mesh.pull(user(), &handle, 2).unwrap());
+
+    mesh.upgrade();
+
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert_eq!(status.position, 2);
+    assert_eq!(status.error, None);
+
+    let batch = mesh.pull(user(), &handle, 10).unwrap();
+    assert!(!batch.has_more);
+    seen.extend(batch_names(&batch));
+    assert_eq!(seen, ["item_0_0", "item_0_1", "item_0_2", "item_1_0", "item_1_1", "item_1_2"]);
+    assert!(mesh.stream_status(user(), &handle).unwrap().is_complete);
+}
+
+#[test]
+fn status_tracks_the_position_without_consuming_records() {
+    let mesh = filled_mesh(1, 5);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert_eq!(status.position, 0);
+    assert!(!status.is_complete);
+    assert_eq!(status.expires_at, handle.expires_at);
+
+    mesh.pull(user(), &handle, 2).unwrap();
+    mesh.pull(user(), &handle, 2).unwrap();
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert_eq!(status.position, 4);
+    assert!(!status.is_complete);
+    assert_eq!(mesh.stream_status(user(), &handle).unwrap().position, 4);
+
+    let last = mesh.pull(user(), &handle, 2).unwrap();
+    assert_eq!(batch_names(&last), ["item_0_4"]);
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert_eq!(status.position, 5);
+    assert!(status.is_complete);
+}
+
+#[test]
+fn status_of_unknown_or_closed_streams_is_not_found() {
+    let mesh = filled_mesh(1, 1);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let unknown = StreamHandle { id: "stream_0".to_string(), ..handle.clone() };
+    assert!(matches!(mesh.stream_status(user(), &unknown), Err(QueryError::StreamingFailed(_))));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "close_stream", (handle.clone(),));
+    result.unwrap();
+    assert!(matches!(mesh.stream_status(user(), &handle), Err(QueryError::StreamingFailed(_))));
+}
+
+#[test]
+fn paused_streams_stop_prefetching_until_resumed() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.buffer_size = 10;
+        config.streaming_config.prefetch_enabled = true;
+        config
+    });
+    for i in 0..30 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_{:02}", i)}));
+    }
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    mesh.pull(user(), &handle, 2).unwrap();
+    assert_eq!(mesh.stream_status(user(), &handle).unwrap().buffered, 2);
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "pause_stream", (handle.clone(), None::<bool>));
+    result.unwrap();
+    assert!(mesh.stream_status(user(), &handle).unwrap().paused);
+
+    // Only what was already buffered is served while paused
+    let batch = mesh.pull(user(), &handle, 5).unwrap();
+    assert_eq!(batch_names(&batch), ["item_02", "item_03"]);
+    assert!(batch.has_more);
+    let batch = mesh.pull(user(), &handle, 5).unwrap();
+    assert!(batch.records.is_empty());
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert_eq!((status.position, status.buffered), (4, 0));
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(user(), "resume_stream", (handle.clone(),));
+    result.unwrap();
+    let batch = mesh.pull(user(), &handle, 5).unwrap();
+    assert_eq!(batch_names(&batch).first().map(String::as_str), Some("item_04"));
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    assert!(!status.paused);
+    assert!(status.buffered > 0, "prefetching did not resume");
+}
+
+#[test]
+fn only_the_owner_pauses_a_stream() {
+    let mesh = filled_mesh(1, 1);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let (result,): (Result<(), QueryError>,) = mesh.update(other_user(), "pause_stream", (handle.clone(), None::<bool>));
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+    assert!(!mesh.stream_status(user(), &handle).unwrap().paused);
+}
+
+#[test]
+fn only_the_owner_pulls_inspects_or_closes_a_stream() {
+    let mesh = filled_mesh(1, 3);
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    assert!(matches!(mesh.pull(other_user(), &handle, 3), Err(QueryError::PermissionDenied(_))));
+    assert!(matches!(mesh.stream_status(other_user(), &handle), Err(QueryError::PermissionDenied(_))));
+    let (result,): (Result<(), QueryError>,) = mesh.update(other_user(), "close_stream", (handle.clone(),));
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+
+    // The owner's stream is untouched
+    assert_eq!(mesh.stream_status(user(), &handle).unwrap().position, 0);
+    assert_eq!(mesh.pull(user(), &handle, 3).unwrap().records.len(), 3);
+}
+
+/// Three cells holding the scores 0..24 between them, each inserted out of order
+fn scored_mesh() -> Mesh {
+    let mesh = Mesh::with_config(3, |mut config| {
+        config.streaming_config.buffer_size = 5;
+        config
+    });
+    for (i, cell_id) in mesh.cells.iter().enumerate() {
+        for j in [5, 1, 7, 0, 3, 6, 2, 4] {
+            let score = j * 3 + i;
+            mesh.insert(*cell_id, json!({"name": format!("item_{:02}", score), "score": score}));
+        }
+    }
+    mesh
+}
+
+fn sorted_scores(mesh: &Mesh, sort: &str) -> Vec<u64> {
+    let plan = QueryPlan {
+        operations: vec![QueryOperation::Sort(sort.to_string())],
+        ..stream_plan(mesh.cells.clone())
+    };
+    let handle = mesh.open_stream(user(), plan).unwrap();
+
+    let mut scores = Vec::new();
+    let mut batches = 0;
+    loop {
+        let batch = mesh.pull(user(), &handle, 4).unwrap();
+        assert!(batch.records.len() <= 5);
+        scores.extend(batch.records.iter().map(|record| parse(record)["score"].as_u64().unwrap()));
+        batches += 1;
+        if !batch.has_more {
+            break;
+        }
+    }
+    assert!(batches > 1, "the stream fit in a single batch");
+    scores
+}
+
+#[test]
+fn sorted_streams_are_ordered_across_batch_boundaries() {
+    let mesh = scored_mesh();
+
+    assert_eq!(sorted_scores(&mesh, "score"), (0..24).collect::<Vec<u64>>());
+    assert_eq!(sorted_scores(&mesh, "score DESC"), (0..24).rev().collect::<Vec<u64>>());
+}
+
+#[test]
+fn limited_streams_complete_at_the_limit() {
+    let mesh = scored_mesh();
+    let plan = QueryPlan {
+        operations: vec![QueryOperation::Sort("score".to_string()), QueryOperation::Limit(5)],
+        ..stream_plan(mesh.cells.clone())
+    };
+    let handle = mesh.open_stream(user(), plan).unwrap();
+
+    let first = mesh.pull(user(), &handle, 4).unwrap();
+    assert_eq!(batch_names(&first), ["item_00", "item_01", "item_02", "item_03"]);
+    assert!(first.has_more);
+
+    let last = mesh.pull(user(), &handle, 4).unwrap();
+    assert_eq!(batch_names(&last), ["item_04"]);
+    assert!(!last.has_more);
+    assert!(mesh.stream_status(user(), &handle).unwrap().is_complete);
+}
+
+#[test]
+fn streams_reject_operations_they_cannot_apply() {
+    let mesh = filled_mesh(1, 3);
+
+    // `query_plan` filters by category
+    assert!(matches!(mesh.open_stream(user(), query_plan(mesh.cells.clone())), Err(QueryError::InvalidQuery(_))));
+    let plan = QueryPlan {
+        operations: vec![QueryOperation::Aggregate("count".to_string())],
+        ..stream_plan(mesh.cells.clone())
+    };
+    assert!(matches!(mesh.open_stream(user(), plan), Err(QueryError::InvalidQuery(_))));
+    assert_eq!(mesh.metrics().active_streams, 0);
+}
+
+#[test]
+fn cancelling_all_streams_drops_every_stream() {
+    let mesh = filled_mesh(1, 3);
+    let plan = stream_plan(mesh.cells.clone());
+    let handles: Vec<StreamHandle> = (0..3).map(|_| mesh.open_stream(user(), plan.clone()).unwrap()).collect();
+    mesh.open_stream(other_user(), plan).unwrap();
+    assert_eq!(mesh.metrics().active_streams, 4);
+
+    let (result,): (Result<u64, QueryError>,) = mesh.update(user(), "cancel_all_streams", ());
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+    assert_eq!(mesh.metrics().active_streams, 4);
+
+    let (result,): (Result<u64, QueryError>,) = mesh.update(controller(), "cancel_all_streams", ());
+    assert_eq!(result.unwrap(), 4);
+    assert_eq!(mesh.metrics().active_streams, 0);
+    assert!(matches!(mesh.pull(user(), &handles[0], 1), Err(QueryError::StreamingFailed(_))));
+}
+
+fn plan_with_ttl(mesh: &Mesh, stream_timeout_seconds: u64) -> QueryPlan {
+    QueryPlan {
+        streaming_config: Some(StreamingConfig {
+            stream_timeout_seconds,
+            ..aggregator_config(Vec::new()).streaming_config
+        }),
+        ..stream_plan(mesh.cells.clone())
+    }
+}
+
+#[test]
+fn a_per_plan_ttl_overrides_the_engine_default() {
+    let mesh = filled_mesh(1, 3);
+    let short = mesh.open_stream(user(), plan_with_ttl(&mesh, 30)).unwrap();
+    let default = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    assert_eq!(short.expires_at - short.created_at, 30_000_000_000);
+    assert_eq!(default.expires_at - default.created_at, 3_600_000_000_000);
+
+    mesh.pull(user(), &short, 1).unwrap();
+    mesh.pic.advance_time(std::time::Duration::from_secs(31));
+
+    assert!(matches!(mesh.pull(user(), &short, 1), Err(QueryError::StreamingFailed(_))));
+    assert!(matches!(mesh.stream_status(user(), &short), Err(QueryError::StreamingFailed(_))));
+    assert_eq!(mesh.pull(user(), &default, 3).unwrap().records.len(), 3);
+}
+
+#[test]
+fn stream_ttls_are_capped_at_a_day() {
+    let mesh = filled_mesh(1, 1);
+
+    let handle = mesh.open_stream(user(), plan_with_ttl(&mesh, 10 * 24 * 3_600)).unwrap();
+    assert_eq!(handle.expires_at - handle.created_at, 24 * 3_600 * 1_000_000_000);
+}
+
+fn buffered_after_first_pull(prefetch_enabled: bool) -> (u64, u64) {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.prefetch_enabled = prefetch_enabled;
+        config
+    });
+    for i in 0..20 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_{:02}", i)}));
+    }
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let batch = mesh.pull(user(), &handle, 5).unwrap();
+    assert_eq!(batch_names(&batch), ["item_00", "item_01", "item_02", "item_03", "item_04"]);
+    let status = mesh.stream_status(user(), &handle).unwrap();
+    (status.position, status.buffered)
+}
+
+#[test]
+fn prefetching_buffers_the_next_batch_only_when_enabled() {
+    assert_eq!(buffered_after_first_pull(false), (5, 0));
+    assert_eq!(buffered_after_first_pull(true), (5, 5));
+}
+
+#[test]
+fn prefetched_records_are_served_before_fetching_again() {
+    let mesh = Mesh::with_config(2, |mut config| {
+        config.streaming_config.prefetch_enabled = true;
+        config
+    });
+    for i in 0..4 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_0_{}", i)}));
+    }
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+    mesh.pull(user(), &handle, 2).unwrap();
+    assert_eq!(mesh.stream_status(user(), &handle).unwrap().buffered, 2);
+
+    // Records written after the prefetch don't displace the buffered ones
+    mesh.insert(mesh.cells[1], json!({"name": "item_1_0"}));
+    let batch = mesh.pull(user(), &handle, 10).unwrap();
+    assert_eq!(batch_names(&batch), ["item_0_2", "item_0_3", "item_1_0"]);
+    assert!(!batch.has_more);
+}
+
+#[test]
+fn deleting_records_already_read_does_not_skip_the_next_ones() {
+    let mesh = Mesh::with_config(1, |mut config| {
+        config.streaming_config.prefetch_enabled = false;
+        config
+    });
+    let ids: Vec<String> = (0..6)
+        .map(|i| mesh.insert(mesh.cells[0], json!({"name": format!("item_{}", i)})))
+        .collect();
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let first = mesh.pull(user(), &handle, 2).unwrap();
+    assert_eq!(batch_names(&first), ["item_0", "item_1"]);
+
+    // The cell's cursor resumes after the last record read, not at a shifted offset
+    mesh.delete(mesh.cells[0], &ids[0]);
+    mesh.delete(mesh.cells[0], &ids[1]);
+    let rest = mesh.pull(user(), &handle, 10).unwrap();
+    assert_eq!(batch_names(&rest), ["item_2", "item_3", "item_4", "item_5"]);
+    assert!(!rest.has_more);
+}
+
+/// Start a pull without waiting for it, so other calls can land while it awaits its cells
+macro_rules! submit_pull {
+    ($mesh:expr, $handle:expr, $batch_size:expr) => {
+        $mesh.pic.submit_call(
+            $mesh.aggregator, user(), "get_stream_batch",
+            candid::encode_args(($handle.clone(), $batch_size as u32)).unwrap(),
+        ).expect("submit failed")
+    };
+}
+
+fn pull_result(result: WasmResult) -> Result<StreamBatch, QueryError> {
+    match result {
+        WasmResult::Reply(bytes) => candid::decode_one(&bytes).unwrap(),
+        WasmResult::Reject(message) => panic!("pull rejected: {}", message),
+    }
+}
+
+#[test]
+fn cancelling_during_a_pull_keeps_the_stream_cancelled() {
+    let mesh = Mesh::across_subnets(1);
+    for i in 0..5 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_{}", i)}));
+    }
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let pull = submit_pull!(mesh, handle, 2);
+    // The pull is now waiting on the other subnet's cell
+    mesh.pic.tick();
+    let (result,): (Result<u64, QueryError>,) = mesh.update(controller(), "cancel_all_streams", ());
+    assert_eq!(result.unwrap(), 1);
+
+    assert!(matches!(pull_result(mesh.pic.await_call(pull).unwrap()), Err(QueryError::StreamingFailed(_))));
+    assert_eq!(mesh.metrics().active_streams, 0);
+    assert!(mesh.stream_status(user(), &handle).is_err());
+}
+
+#[test]
+fn concurrent_pulls_never_return_the_same_records() {
+    let mesh = Mesh::across_subnets(1);
+    for i in 0..10 {
+        mesh.insert(mesh.cells[0], json!({"name": format!("item_{}", i)}));
+    }
+    let handle = mesh.open_stream(user(), stream_plan(mesh.cells.clone())).unwrap();
+
+    let first = submit_pull!(mesh, handle, 3);
+    let second = submit_pull!(mesh, handle, 3);
+    let results = [
+        pull_result(mesh.pic.await_call(first).unwrap()),
+        pull_result(mesh.pic.await_call(second).unwrap()),
+    ];
+    let mut seen: Vec<String> = results.iter()
+        .filter_map(|result| result.as_ref().ok())
+        .flat_map(batch_names)
+        .collect();
+    assert!(!seen.is_empty());
+
+    // Drain the rest; every record comes out exactly once
+    loop {
+        let batch = mesh.pull(user(), &handle, 3).unwrap();
+        seen.extend(batch_names(&batch));
+        if !batch.has_more {
+            break;
+        }
+    }
+    let unique: BTreeSet<&String> = seen.iter().collect();
+    assert_eq!(seen.len(), 10);
+    assert_eq!(unique.len(), 10);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn trace(mesh: &Mesh, sender: candid::Principal, trace_id: &str) -> Result<Vec<TraceSpan>, QueryError> {
+    let (result,): (Result<Vec<TraceSpan>, QueryError>,) = mesh.query(sender, "get_trace", (trace_id.to_string(),));
+    result
+}
+
+#[test]
+fn a_batch_query_trace_covers_the_aggregator_and_every_cell_call() {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "alpha"}));
+    mesh.insert(mesh.cells[1], json!({"name": "beta"}));
+    mesh.insert(mesh.cells[1], json!({"name": "gamma"}));
+
+    let result = mesh.batch(batch_query(mesh.cells.clone())).unwrap();
+    let trace_id = result.trace_id.expect("batch queries are traced");
+
+    let spans = trace(&mesh, controller(), &trace_id).unwrap();
+    assert!(spans.iter().all(|span| span.trace_id == trace_id));
+    assert!(spans.windows(2).all(|pair| pair[0].started_at <= pair[1].started_at));
+
+    let batch: Vec<&TraceSpan> = spans.iter().filter(|span| span.name == "batch_query").collect();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].records, 3);
+
+    // The cell spans carry the trace ID the cells received in their filter
+    let mut cell_spans: Vec<(candid::Principal, u64)> = spans.iter()
+        .filter(|span| span.name == "cell_query")
+        .map(|span| (span.cell_id.expect("cell spans name their cell"), span.records))
+        .collect();
+    cell_spans.sort();
+    let mut expected = vec![(mesh.cells[0], 1), (mesh.cells[1], 2)];
+    expected.sort();
+    assert_eq!(cell_spans, expected);
+}
+
+#[test]
+fn each_query_gets_its_own_trace() {
+    let mesh = Mesh::new(1);
+    let first = mesh.batch(batch_query(mesh.cells.clone())).unwrap().trace_id.unwrap();
+    let second = mesh.batch(batch_query(mesh.cells.clone())).unwrap().trace_id.unwrap();
+
+    assert_ne!(first, second);
+    assert!(first < second);
+    assert!(trace(&mesh, controller(), "trace_unknown").unwrap().is_empty());
+}
+
+#[test]
+fn only_controllers_and_managers_read_traces() {
+    let mesh = Mesh::new(1);
+    let trace_id = mesh.batch(batch_query(mesh.cells.clone())).unwrap().trace_id.unwrap();
+
+    assert!(matches!(trace(&mesh, user(), &trace_id), Err(QueryError::PermissionDenied(_))));
+}
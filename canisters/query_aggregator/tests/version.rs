@@ -0,0 +1,13 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn version_names_the_build() {
+    let mesh = Mesh::new(0);
+    let (version,): (CanisterVersion,) = mesh.query(user(), "version", ());
+
+    assert_eq!(version.name, "query_aggregator");
+    assert!(version.build_version.starts_with(env!("CARGO_PKG_VERSION")));
+    assert_eq!(version.schema_version, None);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+fn count_for(mesh: &Mesh, view: &str, category: &str) -> Option<f64> {
+    mesh.view_rows(view, vec![("category", json!(category))])
+        .first()
+        .map(|row| row["count"].as_f64().unwrap())
+}
+
+#[test]
+fn cell_inserts_propagate_into_a_count_view() {
+    let mesh = Mesh::new(2);
+    mesh.insert(mesh.cells[0], json!({"name": "a1", "category": "a"}));
+
+    mesh.create_view(ViewDefinition {
+        name: "by_category".to_string(),
+        source_cells: mesh.cells.clone(),
+        kind: ViewKind::CountBy("category".to_string()),
+    }).unwrap();
+    assert_eq!(count_for(&mesh, "by_category", "a"), Some(1.0));
+
+    mesh.insert(mesh.cells[0], json!({"name": "a2", "category": "a"}));
+    let b1 = mesh.insert(mesh.cells[1], json!({"name": "b1", "category": "b"}));
+    mesh.insert(mesh.cells[1], json!({"name": "a3", "category": "a"}));
+    mesh.settle();
+    assert_eq!(count_for(&mesh, "by_category", "a"), Some(3.0));
+    assert_eq!(count_for(&mesh, "by_category", "b"), Some(1.0));
+
+    mesh.delete(mesh.cells[1], &b1);
+    mesh.settle();
+    assert_eq!(count_for(&mesh, "by_category", "b"), None);
+}
+
+#[test]
+fn union_view_tags_rows_with_their_source() {
+    let mesh = Mesh::new(2);
+    mesh.create_view(ViewDefinition {
+        name: "everything".to_string(),
+        source_cells: mesh.cells.clone(),
+        kind: ViewKind::Union,
+    }).unwrap();
+    assert!(mesh.view_rows("everything", vec![]).is_empty());
+
+    let id = mesh.insert(mesh.cells[1], json!({"name": "b1", "category": "b"}));
+    mesh.settle();
+
+    let rows = mesh.view_rows("everything", vec![("name", json!("b1"))]);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["_cell"], json!(mesh.cells[1].to_text()));
+    assert_eq!(rows[0]["_id"], json!(id));
+}
+
+#[test]
+fn only_managers_create_views_over_registered_cells() {
+    let mesh = Mesh::new(1);
+    let definition = ViewDefinition {
+        name: "by_category".to_string(),
+        source_cells: mesh.cells.clone(),
+        kind: ViewKind::CountBy("category".to_string()),
+    };
+
+    let (result,): (Result<(), QueryError>,) =
+        mesh.update(user(), "create_materialized_view", (definition.clone(),));
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+
+    let unregistered = Mesh::install_cell(&mesh.pic, cell_config("stray", 1));
+    let result = mesh.create_view(ViewDefinition { source_cells: vec![unregistered], ..definition });
+    assert!(matches!(result, Err(QueryError::PermissionDenied(_))));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn in_flight_count_covers_a_query_while_it_runs() {
+    let mesh = Mesh::across_subnets(2);
+    for cell_id in &mesh.cells {
+        mesh.insert(*cell_id, json!({"name": "item"}));
+    }
+    assert_eq!(mesh.metrics().in_flight_queries, 0);
+
+    let message = mesh.pic.submit_call(
+        mesh.aggregator, user(), "execute_batch_query",
+        candid::encode_one(batch_query(mesh.cells.clone())).unwrap(),
+    ).expect("submit failed");
+    // The query is now waiting on the other subnet's cells
+    mesh.pic.tick();
+    let metrics = mesh.metrics();
+    assert_eq!(metrics.in_flight_queries, 1);
+    assert_eq!(metrics.queued_queries, 0);
+
+    mesh.pic.await_call(message).expect("query failed");
+    let metrics = mesh.metrics();
+    assert_eq!(metrics.in_flight_queries, 0);
+    assert_eq!(metrics.rejected_queries, 0);
+}
+
+#[test]
+fn failed_queries_release_their_slot() {
+    let mesh = Mesh::new(2);
+    let over_budget = BatchQuery {
+        options: BatchQueryOptions { max_cycles: Some(1), ..options() },
+        ..batch_query(mesh.cells.clone())
+    };
+
+    assert!(matches!(mesh.batch(over_budget), Err(QueryError::ResourceExhausted)));
+    assert_eq!(mesh.metrics().in_flight_queries, 0);
+    assert!(mesh.batch(batch_query(mesh.cells.clone())).is_ok());
+}